@@ -10,11 +10,13 @@ require('dotenv').config();
 const authRoutes = require('./routes/auth');
 const searchRoutes = require('./routes/search');
 const adminRoutes = require('./routes/admin');
+const alertRoutes = require('./routes/alerts');
 const { authenticateAdmin } = require('./middleware/auth');
 const { runBackgroundTasks } = require('./services/backgroundTasks');
 const { errorHandler, notFoundHandler } = require('./middleware/errorHandler');
 const rustEngine = require('./utils/rustEngine');
 const websocketService = require('./services/websocketService');
+const { version: serverVersion } = require('./package.json');
 
 const app = express();
 const server = http.createServer(app);
@@ -65,12 +67,29 @@ app.use('/api/auth', authRoutes);
 app.use('/api/search', searchRoutes);
 app.use('/api/tasks', authenticateAdmin, taskRoutes);
 app.use('/api/admin', authenticateAdmin, adminRoutes);
+app.use('/api/alerts', authenticateAdmin, alertRoutes);
 
 // Health check
 app.get('/api/health', (req, res) => {
   res.json({ status: 'OK', timestamp: new Date().toISOString() });
 });
 
+// Build info for deployments and bug reports
+app.get('/api/version', async (req, res, next) => {
+  try {
+    const engine = await rustEngine.getVersion();
+    res.json({
+      success: true,
+      data: {
+        server: serverVersion,
+        engine
+      }
+    });
+  } catch (error) {
+    next(error);
+  }
+});
+
 // 404 handler
 app.use('*', notFoundHandler);
 
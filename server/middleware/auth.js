@@ -30,6 +30,41 @@ const authenticateAdmin = (req, res, next) => {
   }
 };
 
+// Principal of callers without a token, who only see documents without an access control list
+const ANONYMOUS_PRINCIPAL = 'anonymous';
+// Principal the engine lets see every document
+const ALL_PRINCIPALS = '*';
+
+// Works out whom a search is made for, so the engine leaves out documents whose access control
+// lists don't name them: an admin token sees everything, another token its username and groups,
+// and a request without a token is anonymous. A token that doesn't verify is still rejected.
+const identifyPrincipals = (req, res, next) => {
+  try {
+    const authHeader = req.headers.authorization;
+
+    if (!authHeader || !authHeader.startsWith('Bearer ')) {
+      req.principals = [ANONYMOUS_PRINCIPAL];
+      return next();
+    }
+
+    const decoded = jwt.verify(authHeader.substring(7), process.env.JWT_SECRET);
+    req.user = decoded;
+    if (decoded.role === 'admin') {
+      req.principals = [ALL_PRINCIPALS];
+    } else {
+      const groups = Array.isArray(decoded.groups) ? decoded.groups.filter(group => typeof group === 'string') : [];
+      const principals = [decoded.username, ...groups].filter(Boolean);
+      // A token without a username or groups sees what callers without a token see
+      req.principals = principals.length > 0 ? principals : [ANONYMOUS_PRINCIPAL];
+    }
+    next();
+  } catch (error) {
+    next(error);
+  }
+};
+
 module.exports = {
-  authenticateAdmin
+  authenticateAdmin,
+  identifyPrincipals,
+  ANONYMOUS_PRINCIPAL
 };
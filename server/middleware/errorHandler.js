@@ -17,11 +17,12 @@ const logger = winston.createLogger({
 
 // Custom error classes
 class SearchEngineError extends Error {
-  constructor(message, code = 'SEARCH_ENGINE_ERROR', statusCode = 500) {
+  constructor(message, code = 'SEARCH_ENGINE_ERROR', statusCode = 500, details = undefined) {
     super(message);
     this.name = 'SearchEngineError';
     this.code = code;
     this.statusCode = statusCode;
+    this.details = details;
   }
 }
 
@@ -121,7 +122,8 @@ const errorHandler = (err, req, res, next) => {
       error: {
         type: 'search_engine',
         code: err.code,
-        message: err.message
+        message: err.message,
+        details: err.details
       }
     });
   }
@@ -5,6 +5,7 @@ const path = require('path');
 const rustEngine = require('../utils/rustEngine');
 const resultCacheManager = require('../utils/resultCacheManager');
 const { ValidationError } = require('../middleware/errorHandler');
+const { isMetadata } = require('../utils/metadata');
 const winston = require('winston');
 
 const router = express.Router();
@@ -15,7 +16,10 @@ const logger = winston.createLogger({
   transports: [new winston.transports.Console()]
 });
 
-// Configure multer for text file uploads
+// Documents the engine converts to text, storing the text beside them
+const CONVERTED_EXTENSIONS = ['.pdf', '.docx', '.odt', '.html', '.htm'];
+
+// Configure multer for text, PDF, office document and HTML uploads
 const upload = multer({
   dest: 'searches/', // Upload directly to search directory
   limits: {
@@ -23,11 +27,12 @@ const upload = multer({
     files: 10 // Limit concurrent uploads
   },
   fileFilter: (req, file, cb) => {
-    // Only accept .txt files for direct search
-    if (file.originalname.toLowerCase().endsWith('.txt') || file.mimetype === 'text/plain') {
+    // Only accept .txt files, and documents the engine extracts the text of, for direct search
+    const extension = path.extname(file.originalname).toLowerCase();
+    if (extension === '.txt' || file.mimetype === 'text/plain' || CONVERTED_EXTENSIONS.includes(extension)) {
       cb(null, true);
     } else {
-      cb(new ValidationError('Only .txt files are supported', 'document'), false);
+      cb(new ValidationError('Only .txt, .pdf, .docx, .odt and .html files are supported', 'document'), false);
     }
   },
   storage: multer.diskStorage({
@@ -42,25 +47,94 @@ const upload = multer({
   })
 });
 
-// Upload text documents for direct search
+// JSON files whose records are indexed as documents of their own
+const JSON_EXTENSIONS = ['.json', '.jsonl', '.ndjson'];
+
+const jsonUpload = multer({
+  limits: {
+    fileSize: 10 * 1024 * 1024, // 10MB limit
+    files: 1
+  },
+  fileFilter: (req, file, cb) => {
+    if (JSON_EXTENSIONS.includes(path.extname(file.originalname).toLowerCase())) {
+      cb(null, true);
+    } else {
+      cb(new ValidationError('Only .json, .jsonl and .ndjson files are supported', 'document'), false);
+    }
+  },
+  storage: multer.diskStorage({
+    destination: 'searches/',
+    filename: (req, file, cb) => {
+      const timestamp = Date.now();
+      const { name, ext } = path.parse(file.originalname);
+      cb(null, `${name}-${timestamp}${ext}`);
+    }
+  })
+});
+
+// Parses the optional `metadata` form field, a JSON object of field names to strings or numbers
+function parseUploadMetadata(body) {
+  if (!body || body.metadata === undefined || body.metadata === '') {
+    return null;
+  }
+  let metadata;
+  try {
+    metadata = JSON.parse(body.metadata);
+  } catch (error) {
+    throw new ValidationError('Metadata must be a JSON object', 'metadata');
+  }
+  if (!isMetadata(metadata)) {
+    throw new ValidationError('Metadata must map field names to strings or numbers', 'metadata');
+  }
+  return metadata;
+}
+
+// Parses the optional `acl` form field, a JSON array of the principals that may see the document
+function parseUploadAcl(body) {
+  if (!body || body.acl === undefined || body.acl === '') {
+    return null;
+  }
+  let acl;
+  try {
+    acl = JSON.parse(body.acl);
+  } catch (e) {
+    throw new ValidationError('ACL must be a JSON array', 'acl');
+  }
+  if (!Array.isArray(acl) || acl.length === 0 || acl.some(principal => typeof principal !== 'string' || principal.trim().length === 0)) {
+    throw new ValidationError('ACL must be a non-empty array of principal names', 'acl');
+  }
+  return acl;
+}
+
+// Upload text, PDF, office or HTML documents for direct search, optionally with `metadata` and `acl` form fields
 router.post('/upload', upload.single('document'), async (req, res, next) => {
   try {
     if (!req.file) {
       throw new ValidationError('No file uploaded', 'document');
     }
+    const metadata = parseUploadMetadata(req.body);
+    const acl = parseUploadAcl(req.body);
 
     // File is already validated by multer filter and uploaded to searches/ directory
-    
+    const isConverted = CONVERTED_EXTENSIONS.includes(path.extname(req.file.path).toLowerCase());
+
     // Validate text content - ensure it's readable UTF-8
-    const fileContent = await fs.readFile(req.file.path, 'utf8');
-    if (!fileContent.trim()) {
-      // Clean up empty file
-      await fs.unlink(req.file.path);
-      throw new ValidationError('Empty text file is not supported', 'document');
+    if (!isConverted) {
+      const fileContent = await fs.readFile(req.file.path, 'utf8');
+      if (!fileContent.trim()) {
+        // Clean up empty file
+        await fs.unlink(req.file.path);
+        throw new ValidationError('Empty text file is not supported', 'document');
+      }
     }
 
-    // Run maintenance to refresh file cache (since we added a new file)
-    await rustEngine.runMaintenance('cleanup');
+    // Index the new file with its metadata and ACL, or run maintenance to pick it up; a rescan
+    // only finds text files, so converted documents are always indexed, which stores their text
+    if (metadata || acl || isConverted) {
+      await rustEngine.indexDocument(req.file.path, metadata || {}, acl || [], req.user.username);
+    } else {
+      await rustEngine.runMaintenance('cleanup', req.user.username);
+    }
 
     logger.info(`Text document uploaded successfully: ${req.file.originalname}`);
 
@@ -71,6 +145,8 @@ router.post('/upload', upload.single('document'), async (req, res, next) => {
         filename: req.file.originalname,
         searchPath: req.file.path,
         fileSize: req.file.size,
+        metadata: metadata || {},
+        acl: acl || [],
         uploadedAt: new Date().toISOString()
       }
     });
@@ -88,10 +164,78 @@ router.post('/upload', upload.single('document'), async (req, res, next) => {
   }
 });
 
+// Parses the optional `mapping` form field, a JSON object naming the fields of each record
+// that make up its document: { id, title, content: [...], metadata: [...] }
+function parseUploadMapping(body) {
+  if (!body || body.mapping === undefined || body.mapping === '') {
+    return {};
+  }
+  let mapping;
+  try {
+    mapping = JSON.parse(body.mapping);
+  } catch (e) {
+    throw new ValidationError('Mapping must be a JSON object', 'mapping');
+  }
+  if (!mapping || typeof mapping !== 'object' || Array.isArray(mapping)) {
+    throw new ValidationError('Mapping must be a JSON object', 'mapping');
+  }
+  const { id, title, content = [], metadata = [] } = mapping;
+  for (const [name, field] of [['id', id], ['title', title]]) {
+    if (field !== undefined && (typeof field !== 'string' || field.trim().length === 0)) {
+      throw new ValidationError(`mapping.${name} must be a field name`, `mapping.${name}`);
+    }
+  }
+  for (const [name, fields] of [['content', content], ['metadata', metadata]]) {
+    if (!Array.isArray(fields) || fields.some(field => typeof field !== 'string' || field.trim().length === 0)) {
+      throw new ValidationError(`mapping.${name} must be an array of field names`, `mapping.${name}`);
+    }
+  }
+  return { id, title, content, metadata };
+}
+
+// Upload a JSON or NDJSON file and index each of its records as a document, optionally with
+// `mapping` and `acl` form fields
+router.post('/upload-json', jsonUpload.single('document'), async (req, res, next) => {
+  try {
+    if (!req.file) {
+      throw new ValidationError('No file uploaded', 'document');
+    }
+    const mapping = parseUploadMapping(req.body);
+    const acl = parseUploadAcl(req.body);
+
+    const report = await rustEngine.indexJson(req.file.path, mapping, acl || [], req.user.username);
+
+    logger.info(`JSON records uploaded: ${req.file.originalname}, ${report.indexed.length} indexed`);
+
+    res.json({
+      success: true,
+      data: {
+        message: 'JSON records uploaded successfully and are now searchable',
+        filename: req.file.originalname,
+        searchPath: req.file.path,
+        fileSize: req.file.size,
+        indexed: report.indexed.length,
+        failed: report.failed || [],
+        uploadedAt: new Date().toISOString()
+      }
+    });
+
+  } catch (error) {
+    if (req.file) {
+      try {
+        await fs.unlink(req.file.path);
+      } catch (cleanupError) {
+        logger.warn('Failed to cleanup uploaded file:', cleanupError.message);
+      }
+    }
+    next(error);
+  }
+});
+
 // Delete all search files
 router.delete('/files', async (req, res, next) => {
   try {
-    const result = await rustEngine.runMaintenance('clear-all');
+    const result = await rustEngine.runMaintenance('clear-all', req.user.username);
 
     logger.info('All search files deleted successfully');
     res.json({
@@ -108,6 +252,102 @@ router.delete('/files', async (req, res, next) => {
   }
 });
 
+function validateTagRequest(body) {
+  const { document, tags } = body || {};
+  if (typeof document !== 'string' || document.trim().length === 0) {
+    throw new ValidationError('Document path or ID is required', 'document');
+  }
+  if (!Array.isArray(tags) || tags.length === 0 || tags.some(tag => typeof tag !== 'string' || tag.trim().length === 0)) {
+    throw new ValidationError('Tags must be a non-empty array of tag names', 'tags');
+  }
+  return { document, tags };
+}
+
+// Tags of a document, given as ?document=<path or ID>
+router.get('/tags', async (req, res, next) => {
+  try {
+    const { document } = req.query;
+    if (typeof document !== 'string' || document.trim().length === 0) {
+      throw new ValidationError('Document path or ID is required', 'document');
+    }
+    const tags = await rustEngine.getDocumentTags(document);
+
+    res.json({
+      success: true,
+      data: tags
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Add tags to a document; responds with all of its tags
+router.post('/tags', async (req, res, next) => {
+  try {
+    const { document, tags } = validateTagRequest(req.body);
+    const result = await rustEngine.addTags(document, tags, req.user.username);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Remove tags from a document; responds with the tags it has left
+router.delete('/tags', async (req, res, next) => {
+  try {
+    const { document, tags } = validateTagRequest(req.body);
+    const result = await rustEngine.removeTags(document, tags, req.user.username);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Points an alias at a collection, switching every search that uses it at once
+router.put('/aliases/:alias', async (req, res, next) => {
+  try {
+    const { collection } = req.body || {};
+    if (typeof collection !== 'string' || collection.trim().length === 0) {
+      throw new ValidationError('Collection must be a non-empty name', 'collection');
+    }
+    const result = await rustEngine.setAlias(req.params.alias, collection, req.user.username);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Removes an alias; searches using it fail until it is set again
+router.delete('/aliases/:alias', async (req, res, next) => {
+  try {
+    const result = await rustEngine.removeAlias(req.params.alias, req.user.username);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
 // Get cache statistics
 router.get('/cache/stats', async (req, res, next) => {
   try {
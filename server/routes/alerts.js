@@ -0,0 +1,157 @@
+const express = require('express');
+const fs = require('fs').promises;
+const os = require('os');
+const path = require('path');
+const crypto = require('crypto');
+const rustEngine = require('../utils/rustEngine');
+const { ValidationError } = require('../middleware/errorHandler');
+const winston = require('winston');
+
+const router = express.Router();
+
+const logger = winston.createLogger({
+  level: 'info',
+  format: winston.format.simple(),
+  transports: [new winston.transports.Console()]
+});
+
+// How often the stream checks the engine's alert log for new events
+const STREAM_POLL_INTERVAL = 5000;
+
+// List saved queries
+router.get('/', async (req, res, next) => {
+  try {
+    const alerts = await rustEngine.getAlerts();
+    res.json({
+      success: true,
+      data: alerts
+    });
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Save a query that raises an alert whenever a new or changed document matches
+router.post('/', async (req, res, next) => {
+  try {
+    const { query, mode, filters, webhook } = req.body;
+
+    if (!query || query.trim().length === 0) {
+      throw new ValidationError('Alert query is required', 'query');
+    }
+
+    if (mode !== undefined && !['substring', 'word', 'regex'].includes(mode)) {
+      throw new ValidationError('Mode must be "substring", "word" or "regex"', 'mode');
+    }
+
+    if (webhook !== undefined && !/^http:\/\//.test(webhook)) {
+      throw new ValidationError('Webhook must be an http:// URL', 'webhook');
+    }
+
+    const alert = await rustEngine.addAlert(query.trim(), { mode, filters, webhook, actor: req.user.username });
+    logger.info(`Saved alert ${alert.id} for query: "${alert.query}"`);
+
+    res.status(201).json({
+      success: true,
+      data: alert
+    });
+  } catch (error) {
+    next(error);
+  }
+});
+
+router.delete('/:id', async (req, res, next) => {
+  try {
+    const result = await rustEngine.removeAlert(req.params.id, req.user.username);
+    res.json({
+      success: true,
+      data: result
+    });
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Saved queries a document would match, without indexing it
+router.post('/percolate', async (req, res, next) => {
+  const { text, path: documentPath } = req.body;
+  const tempFile = path.join(os.tmpdir(), `percolate-${crypto.randomUUID()}.txt`);
+  try {
+    if (typeof text !== 'string' || text.length === 0) {
+      throw new ValidationError('Document text is required', 'text');
+    }
+
+    if (documentPath !== undefined && typeof documentPath !== 'string') {
+      throw new ValidationError('path must be a string', 'path');
+    }
+
+    await fs.writeFile(tempFile, text);
+    const matches = await rustEngine.percolate(tempFile, documentPath);
+    res.json({
+      success: true,
+      data: matches
+    });
+  } catch (error) {
+    next(error);
+  } finally {
+    await fs.unlink(tempFile).catch(() => {});
+  }
+});
+
+// Alerts raised so far (?after=<ISO timestamp>&limit=N)
+router.get('/events', async (req, res, next) => {
+  try {
+    const { after } = req.query;
+    let limit;
+    if (req.query.limit !== undefined) {
+      limit = parseInt(req.query.limit, 10);
+      if (isNaN(limit) || limit < 1) {
+        throw new ValidationError('limit must be a positive number', 'limit');
+      }
+    }
+    if (after !== undefined && isNaN(Date.parse(after))) {
+      throw new ValidationError('after must be an ISO 8601 timestamp', 'after');
+    }
+
+    const events = await rustEngine.getAlertEvents({ after, limit });
+    res.json({
+      success: true,
+      data: events
+    });
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Server-sent events: one "alert" event per alert raised after the client connects
+router.get('/stream', (req, res) => {
+  res.writeHead(200, {
+    'Content-Type': 'text/event-stream',
+    'Cache-Control': 'no-cache',
+    Connection: 'keep-alive'
+  });
+  res.write(': connected\n\n');
+
+  let after = new Date().toISOString();
+  let polling = false;
+  const poll = async () => {
+    if (polling) return;
+    polling = true;
+    try {
+      const events = await rustEngine.getAlertEvents({ after });
+      for (const event of events) {
+        res.write(`event: alert\ndata: ${JSON.stringify(event)}\n\n`);
+        after = event.triggered_at;
+      }
+    } catch (error) {
+      logger.error('Failed to poll alert events:', error.message);
+    } finally {
+      polling = false;
+    }
+  };
+
+  const timer = setInterval(poll, STREAM_POLL_INTERVAL);
+  req.on('close', () => clearInterval(timer));
+});
+
+module.exports = router;
@@ -1,6 +1,8 @@
 const express = require('express');
 const rustEngine = require('../utils/rustEngine');
 const { ValidationError } = require('../middleware/errorHandler');
+const { identifyPrincipals } = require('../middleware/auth');
+const { isMetadata, isFieldList } = require('../utils/metadata');
 const winston = require('winston');
 
 const router = express.Router();
@@ -19,14 +21,35 @@ function generateSessionId() {
 }
 
 
-// Search endpoint with caching and streaming support
-router.post('/query', async (req, res, next) => {
+// Search endpoint with caching and streaming support; results only include documents the
+// caller's token may see, see identifyPrincipals
+router.post('/query', identifyPrincipals, async (req, res, next) => {
   try {
     const { 
       query, 
       limit = 50, 
       offset = 0, 
       stream = false,
+      sort,
+      collation,
+      mode,
+      caseSensitive = false,
+      filters,
+      highlight = false,
+      timeout,
+      collapseDuplicates = false,
+      clusters,
+      summarize = false,
+      reader,
+      within,
+      saveResults,
+      collection,
+      collections,
+      maxPerFile,
+      diversify = false,
+      boosts,
+      facets,
+      fuzzy,
       sessionId: providedSessionId
     } = req.body;
 
@@ -43,11 +66,109 @@ router.post('/query', async (req, res, next) => {
       throw new ValidationError('Offset must be a non-negative number', 'offset');
     }
 
+    if (sort !== undefined && !['relevance', 'path', 'title'].includes(sort)) {
+      throw new ValidationError('Sort must be "relevance", "path" or "title"', 'sort');
+    }
+
+    if (collation !== undefined && (typeof collation !== 'string' || !/^[A-Za-z0-9-]{2,35}$/.test(collation))) {
+      throw new ValidationError('Collation must be a locale such as "de" or "sv-SE"', 'collation');
+    }
+
+    if (mode !== undefined && !['substring', 'word', 'regex'].includes(mode)) {
+      throw new ValidationError('Mode must be "substring", "word" or "regex"', 'mode');
+    }
+
+    if (clusters !== undefined && (!Number.isInteger(clusters) || clusters < 0 || clusters > 20)) {
+      throw new ValidationError('Clusters must be between 0 and 20', 'clusters');
+    }
+
+    if (collection !== undefined && (typeof collection !== 'string' || collection.trim().length === 0)) {
+      throw new ValidationError('Collection must be a non-empty name', 'collection');
+    }
+
+    if (collections !== undefined && (!Array.isArray(collections) || collections.length === 0
+      || collections.some(name => typeof name !== 'string' || name.trim().length === 0 || name.includes(',')))) {
+      throw new ValidationError('Collections must be a non-empty array of collection names', 'collections');
+    }
+
+    if (collection !== undefined && collections !== undefined) {
+      throw new ValidationError('Give either collection or collections, not both', 'collections');
+    }
+
+    if (maxPerFile !== undefined && (!Number.isInteger(maxPerFile) || maxPerFile < 1)) {
+      throw new ValidationError('maxPerFile must be a positive integer', 'maxPerFile');
+    }
+
+    if (fuzzy !== undefined && (!Number.isInteger(fuzzy) || fuzzy < 0 || fuzzy > 2)) {
+      throw new ValidationError('fuzzy must be an edit distance from 0 to 2', 'fuzzy');
+    }
+
+    if (boosts !== undefined && (typeof boosts !== 'object' || boosts === null || Array.isArray(boosts)
+      || Object.values(boosts).some(factor => typeof factor !== 'number' || !(factor > 0) || !isFinite(factor)))) {
+      throw new ValidationError('Boosts must map path prefixes to positive numbers', 'boosts');
+    }
+
+    if (reader !== undefined && (typeof reader !== 'string' || !/^[0-9a-f-]+$/i.test(reader))) {
+      throw new ValidationError('Reader must be an ID returned by POST /api/search/readers', 'reader');
+    }
+
+    if (within !== undefined && (typeof within !== 'string' || !/^[0-9a-f-]+$/i.test(within))) {
+      throw new ValidationError('within must be the result_set ID of an earlier search with saveResults', 'within');
+    }
+
+    if (saveResults !== undefined && (typeof saveResults !== 'string' || !/^\d+(ms|s|m|h|d|w)?$/.test(saveResults))) {
+      throw new ValidationError('saveResults must be a keep-alive duration such as "5m"', 'saveResults');
+    }
+
+    if (filters && filters.tags !== undefined && (!Array.isArray(filters.tags) || filters.tags.some(tag => typeof tag !== 'string'))) {
+      throw new ValidationError('filters.tags must be an array of tag names', 'filters.tags');
+    }
+
+    if (filters && filters.languages !== undefined
+      && (!Array.isArray(filters.languages) || filters.languages.some(language => typeof language !== 'string' || !/^[a-z]{2,3}$/i.test(language)))) {
+      throw new ValidationError('filters.languages must be an array of language codes such as "fr"', 'filters.languages');
+    }
+
+    if (filters && filters.metadata !== undefined && !isMetadata(filters.metadata)) {
+      throw new ValidationError('filters.metadata must map field names to strings or numbers', 'filters.metadata');
+    }
+
+    if (facets !== undefined && !isFieldList(facets)) {
+      throw new ValidationError('Facets must be an array of metadata field names', 'facets');
+    }
+
+    for (const bound of ['from', 'to', 'modifiedAfter', 'modifiedBefore']) {
+      if (filters && filters[bound] !== undefined && isNaN(Date.parse(filters[bound]))) {
+        throw new ValidationError(`filters.${bound} must be an ISO 8601 date`, `filters.${bound}`);
+      }
+    }
+
     const sessionId = providedSessionId || generateSessionId();
     const searchParams = { 
       query: query.trim(), 
       limit, 
-      offset 
+      offset,
+      sort,
+      collation,
+      mode,
+      caseSensitive,
+      filters,
+      highlight,
+      timeout,
+      collapseDuplicates,
+      clusters,
+      summarize,
+      reader,
+      within,
+      saveResults,
+      collection,
+      collections,
+      maxPerFile,
+      diversify,
+      boosts,
+      facets,
+      fuzzy,
+      principals: req.principals
     };
 
 
@@ -58,7 +179,7 @@ router.post('/query', async (req, res, next) => {
     }
 
     // Execute regular search
-    const results = await rustEngine.search(query.trim(), limit, offset);
+    const results = await rustEngine.search(query.trim(), searchParams);
     
     // Enhance with pagination metadata
     const enhancedResults = {
@@ -67,6 +188,13 @@ router.post('/query', async (req, res, next) => {
       total: results.total || 0,
       limit,
       offset,
+      timedOut: results.timed_out || false,
+      limited: results.limited || false,
+      clusters: results.clusters || [],
+      terms: results.terms || [],
+      queryTree: results.query_tree,
+      tagCounts: results.tag_counts || {},
+      facets: results.facets || {},
       pagination: {
         offset,
         limit,
@@ -120,9 +248,9 @@ async function handleStreamingSearch(res, searchParams, sessionId) {
     let processedResults = 0;
     const chunkSize = 100;
 
-    // Stream all results in chunks
+    // Stream all results in chunks; clustering a single chunk would be misleading, so it is skipped
     while (true) {
-      const chunkResults = await rustEngine.search(searchParams.query, chunkSize, chunkOffset);
+      const chunkResults = await rustEngine.search(searchParams.query, { ...searchParams, limit: chunkSize, offset: chunkOffset, clusters: 0 });
       
       if (chunkOffset === 0) {
         totalResults = chunkResults.total || 0;
@@ -137,6 +265,8 @@ async function handleStreamingSearch(res, searchParams, sessionId) {
         offset: chunkOffset,
         limit: chunkSize,
         results: results,
+        terms: chunkResults.terms || [],
+        limited: chunkResults.limited || false,
         total: totalResults,
         processed: processedResults,
         hasMore: (chunkOffset + chunkSize) < totalResults
@@ -172,10 +302,39 @@ async function handleStreamingSearch(res, searchParams, sessionId) {
   }
 }
 
-// Get search statistics
+// Natural-language search over embedded passages; needs an embedder in the engine config
+router.post('/semantic', identifyPrincipals, async (req, res, next) => {
+  try {
+    const { query, limit = 10, offset = 0, filters } = req.body;
+
+    if (!query || query.trim().length === 0) {
+      throw new ValidationError('Search query is required', 'query');
+    }
+
+    if (typeof limit !== 'number' || limit < 1 || limit > 1000) {
+      throw new ValidationError('Limit must be between 1 and 1000', 'limit');
+    }
+
+    if (typeof offset !== 'number' || offset < 0) {
+      throw new ValidationError('Offset must be a non-negative number', 'offset');
+    }
+
+    const results = await rustEngine.semanticSearch(query.trim(), { limit, offset, filters, principals: req.principals });
+
+    res.json({
+      success: true,
+      data: results
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Get search statistics (?detailed=true adds a per-document breakdown)
 router.get('/stats', async (req, res, next) => {
   try {
-    const stats = await rustEngine.getStats();
+    const stats = await rustEngine.getStats(req.query.detailed === 'true');
     
     // Add session statistics
     const sessionStats = {
@@ -194,6 +353,387 @@ router.get('/stats', async (req, res, next) => {
   }
 });
 
+// List clusters of near-duplicate documents (?maxDistance=N fingerprint bits)
+router.get('/duplicates', identifyPrincipals, async (req, res, next) => {
+  try {
+    let maxDistance;
+    if (req.query.maxDistance !== undefined) {
+      maxDistance = parseInt(req.query.maxDistance, 10);
+      if (isNaN(maxDistance) || maxDistance < 0 || maxDistance > 64) {
+        throw new ValidationError('maxDistance must be between 0 and 64', 'maxDistance');
+      }
+    }
+    const clusters = await rustEngine.getDuplicates(maxDistance, req.principals);
+
+    res.json({
+      success: true,
+      data: clusters
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Keywords shared by the most documents, for faceting (?top=N)
+router.get('/keywords', identifyPrincipals, async (req, res, next) => {
+  try {
+    let top;
+    if (req.query.top !== undefined) {
+      top = parseInt(req.query.top, 10);
+      if (isNaN(top) || top < 1 || top > 1000) {
+        throw new ValidationError('top must be between 1 and 1000', 'top');
+      }
+    }
+    const keywords = await rustEngine.getKeywords(top, req.principals);
+
+    res.json({
+      success: true,
+      data: keywords
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Completions of a partly typed query (?q=TEXT&limit=N), from indexed terms, or with
+// history=true from past queries that found results (&since=7d to only learn from recent ones)
+router.get('/suggest', identifyPrincipals, async (req, res, next) => {
+  try {
+    const prefix = req.query.q;
+    if (typeof prefix !== 'string' || !prefix.trim() || prefix.length > 200) {
+      throw new ValidationError('q must be between 1 and 200 characters', 'q');
+    }
+    let limit;
+    if (req.query.limit !== undefined) {
+      limit = parseInt(req.query.limit, 10);
+      if (isNaN(limit) || limit < 1 || limit > 100) {
+        throw new ValidationError('limit must be between 1 and 100', 'limit');
+      }
+    }
+    const fromHistory = req.query.history === 'true';
+    const { since } = req.query;
+    if (since !== undefined && (!fromHistory || typeof since !== 'string' || !/^\d+(ms|s|m|h|d|w)?$/.test(since))) {
+      throw new ValidationError('since must be a duration such as "7d", with history=true', 'since');
+    }
+    const suggestions = await rustEngine.getSuggestions(prefix, { limit, fromHistory, since, principals: req.principals });
+
+    res.json({
+      success: true,
+      data: suggestions
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Term frequency and document length histograms and the top terms of each directory, for
+// relevance tuning (?top=N terms overall and per directory)
+router.get('/corpus-stats', async (req, res, next) => {
+  try {
+    let top;
+    if (req.query.top !== undefined) {
+      top = parseInt(req.query.top, 10);
+      if (isNaN(top) || top < 1 || top > 1000) {
+        throw new ValidationError('top must be between 1 and 1000', 'top');
+      }
+    }
+    const report = await rustEngine.getCorpusStats(top);
+
+    res.json({
+      success: true,
+      data: report
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Time partitions holding indexed documents, oldest first
+router.get('/partitions', async (req, res, next) => {
+  try {
+    const partitions = await rustEngine.getPartitions();
+
+    res.json({
+      success: true,
+      data: partitions
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Documents added, updated or deleted after an index generation (?since=N&limit=N), so
+// caches can catch up incrementally; resync in the response means reload everything
+router.get('/changes', async (req, res, next) => {
+  try {
+    let since = 0;
+    if (req.query.since !== undefined) {
+      since = parseInt(req.query.since, 10);
+      if (isNaN(since) || since < 0) {
+        throw new ValidationError('since must be a non-negative generation', 'since');
+      }
+    }
+    let limit;
+    if (req.query.limit !== undefined) {
+      limit = parseInt(req.query.limit, 10);
+      if (isNaN(limit) || limit < 1 || limit > 10000) {
+        throw new ValidationError('limit must be between 1 and 10000', 'limit');
+      }
+    }
+    const feed = await rustEngine.getChanges(since, limit);
+
+    res.json({
+      success: true,
+      data: feed
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Number of documents carrying each tag, for tag facets
+router.get('/tags', async (req, res, next) => {
+  try {
+    const counts = await rustEngine.getTagCounts();
+
+    res.json({
+      success: true,
+      data: counts
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Number of documents with each value of metadata fields, given as ?fields=customer,priority
+router.get('/facets', identifyPrincipals, async (req, res, next) => {
+  try {
+    const fields = typeof req.query.fields === 'string' ? req.query.fields.split(',').map(field => field.trim()) : [];
+    if (!isFieldList(fields) || fields.length === 0) {
+      throw new ValidationError('At least one metadata field name is required', 'fields');
+    }
+    const facets = await rustEngine.getFacets(fields, req.principals);
+
+    res.json({
+      success: true,
+      data: facets
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Named search defaults from the engine config
+router.get('/collections', async (req, res, next) => {
+  try {
+    const collections = await rustEngine.getCollections();
+
+    res.json({
+      success: true,
+      data: collections
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Alias names pointing at collections, e.g. current -> docs-2024-06
+router.get('/aliases', async (req, res, next) => {
+  try {
+    const aliases = await rustEngine.getAliases();
+
+    res.json({
+      success: true,
+      data: aliases
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Point-in-time readers pin the current documents so paging isn't disturbed by indexing
+router.post('/readers', async (req, res, next) => {
+  try {
+    const { keepAlive } = req.body || {};
+    if (keepAlive !== undefined && (typeof keepAlive !== 'string' || !/^\d+(ms|s|m|h|d|w)?$/.test(keepAlive))) {
+      throw new ValidationError('keepAlive must be a duration such as "5m"', 'keepAlive');
+    }
+    const reader = await rustEngine.openReader(keepAlive);
+
+    res.status(201).json({
+      success: true,
+      data: reader
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+router.get('/readers', async (req, res, next) => {
+  try {
+    const readers = await rustEngine.getReaders();
+
+    res.json({
+      success: true,
+      data: readers
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+router.delete('/readers/:id', async (req, res, next) => {
+  try {
+    const result = await rustEngine.closeReader(req.params.id);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Scrolls return every match of a query in batches, for exports that need the whole result
+// set; GET /scrolls/:id fetches the next batch until one comes back with done
+router.post('/scrolls', identifyPrincipals, async (req, res, next) => {
+  try {
+    const { query, batchSize, keepAlive, mode, caseSensitive = false, fuzzy, filters, highlight = false, reader } = req.body || {};
+    if (typeof query !== 'string' || query.trim().length === 0) {
+      throw new ValidationError('Search query is required', 'query');
+    }
+    if (batchSize !== undefined && (!Number.isInteger(batchSize) || batchSize < 1 || batchSize > 10000)) {
+      throw new ValidationError('batchSize must be between 1 and 10000', 'batchSize');
+    }
+    if (keepAlive !== undefined && (typeof keepAlive !== 'string' || !/^\d+(ms|s|m|h|d|w)?$/.test(keepAlive))) {
+      throw new ValidationError('keepAlive must be a duration such as "5m"', 'keepAlive');
+    }
+    if (mode !== undefined && !['substring', 'word', 'regex'].includes(mode)) {
+      throw new ValidationError('Mode must be "substring", "word" or "regex"', 'mode');
+    }
+    if (fuzzy !== undefined && (!Number.isInteger(fuzzy) || fuzzy < 0 || fuzzy > 2)) {
+      throw new ValidationError('fuzzy must be an edit distance from 0 to 2', 'fuzzy');
+    }
+    if (filters && filters.tags !== undefined && (!Array.isArray(filters.tags) || filters.tags.some(tag => typeof tag !== 'string'))) {
+      throw new ValidationError('filters.tags must be an array of tag names', 'filters.tags');
+    }
+    if (filters && filters.metadata !== undefined && !isMetadata(filters.metadata)) {
+      throw new ValidationError('filters.metadata must map field names to strings or numbers', 'filters.metadata');
+    }
+    if (reader !== undefined && (typeof reader !== 'string' || !/^[0-9a-f-]+$/i.test(reader))) {
+      throw new ValidationError('Reader must be an ID returned by POST /api/search/readers', 'reader');
+    }
+    const page = await rustEngine.openScroll(query.trim(), { batchSize, keepAlive, mode, caseSensitive, fuzzy, filters, highlight, reader, principals: req.principals });
+
+    res.status(201).json({
+      success: true,
+      data: page
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+router.get('/scrolls/:id', async (req, res, next) => {
+  try {
+    const page = await rustEngine.nextScrollBatch(req.params.id);
+
+    res.json({
+      success: true,
+      data: page
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+router.delete('/scrolls/:id', async (req, res, next) => {
+  try {
+    const result = await rustEngine.closeScroll(req.params.id);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Lines of a document (?document=path-or-id&start=N&end=N), for loading more context around
+// a hit without fetching the whole file
+router.get('/lines', identifyPrincipals, async (req, res, next) => {
+  try {
+    const { document } = req.query;
+    if (typeof document !== 'string' || document.length === 0) {
+      throw new ValidationError('document is required', 'document');
+    }
+    const start = parseInt(req.query.start, 10);
+    if (isNaN(start) || start < 1) {
+      throw new ValidationError('start must be a line number of 1 or more', 'start');
+    }
+    const end = req.query.end === undefined ? start : parseInt(req.query.end, 10);
+    if (isNaN(end) || end < start || end - start >= 1000) {
+      throw new ValidationError('end must be at or after start and within 1000 lines of it', 'end');
+    }
+    const range = await rustEngine.getLines(document, start, end, req.principals);
+
+    res.json({
+      success: true,
+      data: range
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+// Result sets saved by searches with saveResults, which later searches narrow with within
+router.get('/result-sets', async (req, res, next) => {
+  try {
+    const resultSets = await rustEngine.getResultSets();
+
+    res.json({
+      success: true,
+      data: resultSets
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
+router.delete('/result-sets/:id', async (req, res, next) => {
+  try {
+    const result = await rustEngine.closeResultSet(req.params.id);
+
+    res.json({
+      success: true,
+      data: result
+    });
+
+  } catch (error) {
+    next(error);
+  }
+});
+
 // Session management endpoints
 router.get('/session/:sessionId', async (req, res, next) => {
   try {
@@ -84,7 +84,7 @@ router.post('/maintenance', async (req, res, next) => {
       throw new ValidationError('Maintenance task is required', 'task');
     }
 
-    const allowedTasks = ['cleanup', 'clear-all', 'update-stats'];
+    const allowedTasks = ['cleanup', 'clear-all', 'update-stats', 'rebuild', 'replicate'];
     if (!allowedTasks.includes(task)) {
       throw new ValidationError(`Invalid maintenance task: ${task}`, 'task');
     }
@@ -97,7 +97,7 @@ router.post('/maintenance', async (req, res, next) => {
       });
 
       // Start async execution
-      asyncSearchService.executeMaintenance(task, task_obj.id)
+      asyncSearchService.executeMaintenance(task, task_obj.id, req.user.username)
         .catch(error => {
           logger.error(`Async maintenance task ${task_obj.id} failed:`, error.message);
         });
@@ -117,7 +117,7 @@ router.post('/maintenance', async (req, res, next) => {
 
     } else {
       // Execute maintenance synchronously
-      const result = await asyncSearchService.executeMaintenance(task);
+      const result = await asyncSearchService.executeMaintenance(task, null, req.user.username);
       res.json(result);
     }
 
@@ -326,7 +326,7 @@ router.get('/:taskId/results', async (req, res, next) => {
     const rustEngine = require('../utils/rustEngine');
     
     // Execute search with requested pagination
-    const searchResults = await rustEngine.search(query, parseInt(limit), parseInt(offset));
+    const searchResults = await rustEngine.search(query, { limit: parseInt(limit), offset: parseInt(offset) });
     
     res.json({
       success: true,
@@ -0,0 +1,96 @@
+#ifndef SEARCH_ENGINE_H
+#define SEARCH_ENGINE_H
+
+/* Generated from src/ffi.rs by cbindgen; see cbindgen.toml. Build the library with `--features ffi`. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct Engine Engine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Opens the search directory at `search_path`, creating it if needed.
+ Returns NULL on failure.
+
+ # Safety
+ `search_path` must be NULL or a valid NUL-terminated string.
+ */
+Engine *engine_new(const char *search_path);
+
+/*
+ Opens the existing search directory at `search_path` without writing anything under it;
+ indexing through the handle fails. Returns NULL on failure.
+
+ # Safety
+ `search_path` must be NULL or a valid NUL-terminated string.
+ */
+Engine *engine_open_read_only(const char *search_path);
+
+/*
+ Searches for `query` and returns the response as a JSON string, or NULL on failure.
+ The response is wrapped as {"schema_version": 1, "data": {...}}.
+ `options_json` may be NULL or a JSON object with limit, offset, sort, mode,
+ filters, highlight, timeout, collapse_duplicates, clusters, summarize, reader,
+ max_per_file, boosts, facets, fuzzy and limits.
+
+ # Safety
+ `engine` must come from `engine_new` and not be freed; the strings must be
+ NULL or valid NUL-terminated strings.
+ */
+char *engine_search_json(const Engine *engine, const char *query, const char *options_json);
+
+/*
+ Indexes the text file at `path`, copying it into the search directory if needed; the
+ text of a PDF, Word, OpenDocument or HTML file is copied instead.
+ Returns 0 on success and -1 on failure.
+
+ # Safety
+ `engine` must come from `engine_new` and not be freed; `path` must be NULL
+ or a valid NUL-terminated string.
+ */
+int engine_index(const Engine *engine, const char *path);
+
+/*
+ Like `engine_index`, storing `metadata_json`, a JSON object of field names to
+ strings or numbers, with the document in place of the metadata it had.
+ Returns 0 on success and -1 on failure.
+
+ # Safety
+ `engine` must come from `engine_new` and not be freed; the strings must be
+ NULL or valid NUL-terminated strings.
+ */
+int engine_index_with_metadata(const Engine *engine, const char *path, const char *metadata_json);
+
+/*
+ Closes an engine. Passing NULL does nothing.
+
+ # Safety
+ `engine` must be NULL or come from `engine_new`, and must not be used afterwards.
+ */
+void engine_free(Engine *engine);
+
+/*
+ Releases a string returned by this library. Passing NULL does nothing.
+
+ # Safety
+ `value` must be NULL or a string returned by this library that was not freed yet.
+ */
+void engine_string_free(char *value);
+
+/*
+ The error from the last failed call on this thread, or NULL. The string is
+ owned by the library and valid until the next call on the same thread.
+ */
+const char *engine_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SEARCH_ENGINE_H */
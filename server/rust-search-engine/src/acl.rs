@@ -0,0 +1,73 @@
+// Access control lists: the principals, such as user names or "group:finance", that may see a
+// document, given with it at index time. A search made on behalf of principals only returns
+// documents without a list and those whose list names one of them. Access is denied by
+// default: a search without principals only sees documents without a list, and seeing every
+// document takes the `*` principal, which callers give for administrators only. Names compare
+// exactly. Pure data, so it builds for wasm32 as well.
+
+use crate::error::{Result, SearchEngineError};
+
+pub const MAX_ACL_PRINCIPALS: usize = 256;
+pub const MAX_PRINCIPAL_LENGTH: usize = 256;
+// Principal that may see every document; it can't be named in a document's list
+pub const ALL_PRINCIPALS: &str = "*";
+
+// Checks the size of a list given at index time and the principals in it
+pub fn validate_acl(acl: &[String]) -> Result<()> {
+    let invalid = |message: String| Err(SearchEngineError::InvalidInput(message));
+    if acl.len() > MAX_ACL_PRINCIPALS {
+        return invalid(format!("An access control list may name at most {} principals", MAX_ACL_PRINCIPALS));
+    }
+    for principal in acl {
+        let valid = !principal.trim().is_empty()
+            && principal.len() <= MAX_PRINCIPAL_LENGTH
+            && !principal.chars().any(char::is_control)
+            && principal != ALL_PRINCIPALS;
+        if !valid {
+            return invalid(format!(
+                "Invalid principal {:?}: give a non-empty name of up to {} bytes without control characters, other than {:?}", principal, MAX_PRINCIPAL_LENGTH, ALL_PRINCIPALS
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Whether a search on behalf of `principals` may see a document with `acl`
+pub fn permits(acl: &[String], principals: Option<&[String]>) -> bool {
+    let principals = principals.unwrap_or_default();
+    acl.is_empty()
+        || principals.iter().any(|principal| principal == ALL_PRINCIPALS)
+        || acl.iter().any(|allowed| principals.contains(allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn documents_without_a_list_are_public() {
+        assert!(permits(&[], None));
+        assert!(permits(&[], Some(&names(&["alice"]))));
+    }
+
+    #[test]
+    fn a_list_admits_only_the_principals_it_names() {
+        let acl = names(&["hr", "alice"]);
+        assert!(!permits(&acl, None));
+        assert!(!permits(&acl, Some(&[])));
+        assert!(!permits(&acl, Some(&names(&["bob", "group:finance"]))));
+        assert!(permits(&acl, Some(&names(&["bob", "hr"]))));
+        assert!(permits(&acl, Some(&names(&[ALL_PRINCIPALS]))));
+    }
+
+    #[test]
+    fn the_wildcard_cant_be_listed() {
+        assert!(validate_acl(&names(&["hr"])).is_ok());
+        assert!(validate_acl(&names(&[ALL_PRINCIPALS])).is_err());
+        assert!(validate_acl(&names(&[" "])).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::docstore::temp_path;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::http::HttpEndpoint;
+use crate::ids::random_uuid;
+use crate::matching::Matcher;
+use crate::normalize::normalize_query;
+use crate::options::{MatchMode, SearchFilters, SearchLimits};
+use crate::percolator::Percolator;
+use crate::SearchEngine;
+
+pub const ALERTS_FILE: &str = "alerts.json";
+pub const ALERT_LOG_FILE: &str = "alerts.log";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+// Events a slow server-mode subscriber may fall behind by before missing some
+pub(crate) const EVENT_BUFFER: usize = 256;
+
+// A standing query checked against every newly indexed or changed document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SavedQuery {
+    pub id: String,
+    pub query: String,
+    #[serde(default)]
+    pub mode: MatchMode,
+    #[serde(default)]
+    pub filters: SearchFilters,
+    // Plain-HTTP URL the event is POSTed to, in addition to the alert log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AlertMatch {
+    pub line_number: usize,
+    pub content: String,
+}
+
+// One line of the append-only alert log: a saved query matched a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AlertEvent {
+    pub alert_id: String,
+    pub query: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+    pub triggered_at: DateTime<Utc>,
+    pub total_matches: usize,
+    pub matches: Vec<AlertMatch>,
+}
+
+impl SearchEngine {
+    fn alerts_path(&self) -> std::path::PathBuf {
+        self.doc_store.index_dir().join(ALERTS_FILE)
+    }
+
+    fn alert_log_path(&self) -> std::path::PathBuf {
+        self.doc_store.index_dir().join(ALERT_LOG_FILE)
+    }
+
+    pub async fn alerts(&self) -> Result<Vec<SavedQuery>> {
+        match async_fs::read(self.alerts_path()).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).context("Failed to parse saved alerts")?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).context("Failed to read saved alerts"),
+        }
+    }
+
+    async fn save_alerts(&self, alerts: &[SavedQuery]) -> Result<()> {
+        async_fs::create_dir_all(self.doc_store.index_dir()).await
+            .context("Failed to create index directory")?;
+        let target = self.alerts_path();
+        let temp = temp_path(&target);
+        async_fs::write(&temp, serde_json::to_vec_pretty(alerts)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist saved alerts")?;
+        Ok(())
+    }
+
+    // Registers a standing query; the query and webhook URL are validated up front
+    pub async fn add_alert(&self, query: &str, mode: MatchMode, filters: SearchFilters, webhook: Option<String>) -> Result<SavedQuery> {
+        let query = normalize_query(query, mode)?;
+        self.ensure_writable()?;
+        Matcher::new(&query, mode, None, &SearchLimits::default())?;
+        if let Some(url) = &webhook {
+            HttpEndpoint::parse(url)?;
+        }
+
+        let _alerts = self.shared.alerts_lock.lock().await;
+        let mut alerts = self.alerts().await?;
+        let alert = SavedQuery {
+            id: random_uuid(),
+            query,
+            mode,
+            filters,
+            webhook,
+            created_at: Utc::now(),
+        };
+        alerts.push(alert.clone());
+        self.save_alerts(&alerts).await?;
+        Ok(alert)
+    }
+
+    // Returns false if no alert has the ID
+    pub async fn remove_alert(&self, id: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let _alerts = self.shared.alerts_lock.lock().await;
+        let mut alerts = self.alerts().await?;
+        let before = alerts.len();
+        alerts.retain(|alert| alert.id != id);
+        if alerts.len() == before {
+            return Ok(false);
+        }
+        self.save_alerts(&alerts).await?;
+        Ok(true)
+    }
+
+    // Events from the alert log, oldest first
+    pub async fn alert_events(&self, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Result<Vec<AlertEvent>> {
+        let file = match async_fs::File::open(self.alert_log_path()).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to open alert log"),
+        };
+
+        let mut events = Vec::new();
+        let mut lines = BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str::<AlertEvent>(&line) {
+                Ok(event) if since.map(|since| event.triggered_at > since).unwrap_or(true) => events.push(event),
+                Ok(_) => {}
+                Err(e) => warn!(error = %e, "Skipping unreadable alert log line"),
+            }
+        }
+
+        if let Some(limit) = limit {
+            let skip = events.len().saturating_sub(limit);
+            events.drain(..skip);
+        }
+        Ok(events)
+    }
+
+    // Receives every alert event raised through this engine or its clones from now on
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<AlertEvent> {
+        self.shared.alert_events.subscribe()
+    }
+
+    // Runs the saved queries against a newly indexed or changed document. Failures are
+    // logged rather than returned, since the document itself was indexed fine.
+    pub(crate) async fn check_alerts(&self, file_path: &Path) {
+        if self.read_only {
+            return;
+        }
+        let alerts = match self.alerts().await {
+            Ok(alerts) if alerts.is_empty() => return,
+            Ok(alerts) => alerts,
+            Err(e) => {
+                warn!(error = %e, "Failed to load saved alerts");
+                return;
+            }
+        };
+        let relative_path = self.relative_path(file_path);
+        let entry = self.catalog().await.ok()
+            .and_then(|catalog| catalog.documents.iter().find(|doc| doc.path == file_path).cloned());
+        let title = entry.as_ref().and_then(|doc| doc.attributes.title.as_deref());
+        if !alerts.iter().any(|alert| alert.filters.matches(&relative_path, title)) {
+            return;
+        }
+        let text = match self.read_document(file_path).await {
+            Ok(text) => text,
+            Err(e) => {
+                warn!(path = ?file_path, error = %e, "Failed to read document for alerts");
+                return;
+            }
+        };
+        let document_id = entry.as_ref().and_then(|doc| doc.attributes.document_id.clone());
+
+        for matched in Percolator::new(&alerts).matches(&text, Some(&relative_path), title) {
+            let Some(alert) = alerts.iter().find(|alert| alert.id == matched.alert_id) else {
+                continue;
+            };
+            let event = AlertEvent {
+                alert_id: matched.alert_id,
+                query: matched.query,
+                path: relative_path.clone(),
+                document_id: document_id.clone(),
+                triggered_at: Utc::now(),
+                total_matches: matched.total_matches,
+                matches: matched.matches,
+            };
+            info!(alert = %alert.id, path = %relative_path, total_matches = event.total_matches, "Saved query matched document");
+            self.notify(alert, &event).await;
+        }
+    }
+
+    async fn notify(&self, alert: &SavedQuery, event: &AlertEvent) {
+        if let Err(e) = self.append_alert_event(event).await {
+            warn!(alert = %alert.id, error = %e, "Failed to write alert log entry");
+        }
+        // No receivers is the usual case outside server mode
+        let _ = self.shared.alert_events.send(event.clone());
+
+        let Some(url) = &alert.webhook else {
+            return;
+        };
+        let result = async {
+            let endpoint = HttpEndpoint::parse(url)?;
+            let body = serde_json::to_vec(event)?;
+            match tokio::time::timeout(WEBHOOK_TIMEOUT, endpoint.post_json(&body, None)).await {
+                Ok(response) => response.context("Webhook request failed").map(|_| ()),
+                Err(_) => Err(SearchEngineError::Io {
+                    context: "Webhook request failed".to_string(),
+                    source: std::io::ErrorKind::TimedOut.into(),
+                }),
+            }
+        }.await;
+        if let Err(e) = result {
+            warn!(alert = %alert.id, url = %url, error = %e, "Failed to call alert webhook");
+        }
+    }
+
+    async fn append_alert_event(&self, event: &AlertEvent) -> Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        async_fs::create_dir_all(self.doc_store.index_dir()).await?;
+        let mut file = async_fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.alert_log_path())
+            .await
+            .context("Failed to open alert log")?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+// Collection aliases: a name such as `current` that stands for a collection from the config,
+// so clients can always query `current` while `docs-2024-07` is indexed next to
+// `docs-2024-06`, then move the alias over in one step. Aliases are kept in
+// `.index/aliases.json`, which is replaced with a rename, so every search sees either the old
+// target or the new one; each lookup reads the file, so a switch made by another process
+// applies to the next search.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio::fs as async_fs;
+use tracing::info;
+
+use crate::docstore::temp_path;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::options::Collection;
+use crate::SearchEngine;
+
+pub const ALIASES_FILE: &str = "aliases.json";
+
+// What moving an alias changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AliasSwitch {
+    pub alias: String,
+    pub collection: String,
+    // The collection the alias pointed at before, if it existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+}
+
+impl SearchEngine {
+    fn aliases_path(&self) -> std::path::PathBuf {
+        self.doc_store.index_dir().join(ALIASES_FILE)
+    }
+
+    // Every alias with the collection it points at
+    pub async fn aliases(&self) -> Result<BTreeMap<String, String>> {
+        match async_fs::read(self.aliases_path()).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).context("Failed to parse collection aliases")?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e).context("Failed to read collection aliases"),
+        }
+    }
+
+    async fn save_aliases(&self, aliases: &BTreeMap<String, String>) -> Result<()> {
+        async_fs::create_dir_all(self.doc_store.index_dir()).await
+            .context("Failed to create index directory")?;
+        let target = self.aliases_path();
+        let temp = temp_path(&target);
+        async_fs::write(&temp, serde_json::to_vec_pretty(aliases)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist collection aliases")?;
+        Ok(())
+    }
+
+    // Points `alias` at a collection from the config, creating the alias or moving it. An
+    // alias can't share a collection's name or point at another alias.
+    pub async fn set_alias(&self, alias: &str, collection: &str) -> Result<AliasSwitch> {
+        self.ensure_writable()?;
+        if alias.trim().is_empty() {
+            return Err(SearchEngineError::InvalidInput("alias names must not be empty".to_string()));
+        }
+        if self.collections.contains_key(alias) {
+            return Err(SearchEngineError::InvalidInput(format!("{:?} is already a collection", alias)));
+        }
+        self.collection(collection)?;
+
+        let _aliases = self.shared.aliases_lock.lock().await;
+        let mut aliases = self.aliases().await?;
+        let previous = aliases.insert(alias.to_string(), collection.to_string());
+        self.save_aliases(&aliases).await?;
+        info!(alias, collection, previous = ?previous, "Switched collection alias");
+        Ok(AliasSwitch { alias: alias.to_string(), collection: collection.to_string(), previous })
+    }
+
+    // Returns false if there is no such alias
+    pub async fn remove_alias(&self, alias: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let _aliases = self.shared.aliases_lock.lock().await;
+        let mut aliases = self.aliases().await?;
+        if aliases.remove(alias).is_none() {
+            return Ok(false);
+        }
+        self.save_aliases(&aliases).await?;
+        Ok(true)
+    }
+
+    // Search defaults of a collection named in the config or of the one an alias points at
+    pub async fn resolve_collection(&self, name: &str) -> Result<&Collection> {
+        if self.collections.contains_key(name) {
+            return self.collection(name);
+        }
+        match self.aliases().await?.get(name) {
+            Some(target) => self.collections.get(target).ok_or_else(|| {
+                SearchEngineError::InvalidInput(format!("Alias {:?} points at unknown collection {:?}", name, target))
+            }),
+            None => self.collection(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+
+    async fn with_collections(dir: &TestDir) -> SearchEngine {
+        let collection = |prefix: &str| Collection { path_prefix: Some(prefix.to_string()), ..Default::default() };
+        let collections = BTreeMap::from([
+            ("docs-1".to_string(), collection("v1/")),
+            ("docs-2".to_string(), collection("v2/")),
+        ]);
+        SearchEngine::builder(dir.path()).collections(collections).build().await.unwrap()
+    }
+
+    // Relative paths of the documents `alias` finds "release" in
+    async fn found(engine: &SearchEngine, alias: &str) -> Vec<String> {
+        let options = engine.resolve_collection(alias).await.unwrap().options();
+        let response = engine.search("release", &options).await.unwrap();
+        response.results.iter().map(|result| engine.relative_path(std::path::Path::new(&*result.path))).collect()
+    }
+
+    #[test]
+    fn aliases_move_between_collections() {
+        let dir = TestDir::new("aliases");
+        block_on(async {
+            let engine = with_collections(&dir).await;
+            index(&engine, vec![
+                DocumentInput::from_bytes("v1/notes.txt", "release one\n"),
+                DocumentInput::from_bytes("v2/notes.txt", "release two\n"),
+            ]).await;
+
+            let switch = engine.set_alias("current", "docs-1").await.unwrap();
+            assert_eq!(switch.previous, None);
+            assert_eq!(found(&engine, "current").await, ["v1/notes.txt"]);
+
+            let switch = engine.set_alias("current", "docs-2").await.unwrap();
+            assert_eq!(switch.previous.as_deref(), Some("docs-1"));
+            assert_eq!(found(&engine, "current").await, ["v2/notes.txt"]);
+            // Another process sharing the directory sees the switch on its next search
+            assert_eq!(found(&with_collections(&dir).await, "current").await, ["v2/notes.txt"]);
+
+            assert!(engine.remove_alias("current").await.unwrap());
+            assert!(!engine.remove_alias("current").await.unwrap());
+            assert!(engine.resolve_collection("current").await.is_err());
+            assert!(engine.aliases().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn aliases_must_name_a_collection_without_being_one() {
+        let dir = TestDir::new("aliases-invalid");
+        block_on(async {
+            let engine = with_collections(&dir).await;
+            let invalid = |result: Result<AliasSwitch>| matches!(result, Err(SearchEngineError::InvalidInput(_)));
+            assert!(invalid(engine.set_alias("docs-1", "docs-2").await));
+            assert!(invalid(engine.set_alias(" ", "docs-2").await));
+            assert!(invalid(engine.set_alias("current", "missing").await));
+            assert!(engine.aliases().await.unwrap().is_empty());
+
+            engine.set_alias("current", "docs-1").await.unwrap();
+            let read_only = SearchEngine::builder(dir.path()).read_only(true).build().await.unwrap();
+            assert!(matches!(read_only.set_alias("current", "docs-2").await, Err(SearchEngineError::ReadOnly(_))));
+            assert_eq!(engine.aliases().await.unwrap()["current"], "docs-1");
+        });
+    }
+}
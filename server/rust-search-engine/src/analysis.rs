@@ -0,0 +1,178 @@
+// How each document is analyzed: whether accents are folded, whether its words are stemmed and
+// in which language, whether its postings list n-grams and which stop words they leave out. The
+// engine's `fold_accents`, `stemming`, `stem_language`, `ngrams` and `stopwords` settings apply to every document unless
+// an analysis rule in `search-engine.json` overrides them for documents matching its pattern,
+// the first matching rule winning:
+//
+//   "analysis": [{"pattern": "*.log", "stemming": false, "stopwords": "none"},
+//                {"pattern": "fr/**", "stem_language": "french"}]
+//
+// A search matches each document the way it was analyzed, so "running" finds "ran" in a
+// stemmed document but only "running" in a log file the rule above covers. An `Analyzer`
+// given to the builder splits every document into terms, whatever the rules.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::analyzer::Analyzer;
+use crate::docstore::fnv1a64;
+use crate::engine::relative_path;
+use crate::error::{Result, SearchEngineError};
+use crate::roots::{pattern_matches, SearchRoot};
+use crate::stem::StemLanguage;
+use crate::stopwords::{load_stopwords, StopWordList, StopWords};
+
+// N-gram sizes postings tables can list: shorter ones match too many lines to narrow a
+// lookup, longer ones miss terms of two characters, common in Chinese and Japanese
+pub const NGRAM_SIZES: [usize; 2] = [2, 3];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisRule {
+    // Glob such as "*.log" or "logs/**"; one without a '/' is matched against the file name,
+    // otherwise against the document's relative path
+    pub pattern: String,
+    // Override the engine's settings when given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fold_accents: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stemming: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stem_language: Option<StemLanguage>,
+    // 0 turns n-grams off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ngrams: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopwords: Option<StopWordList>,
+}
+
+// The analysis of one document
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub fold_accents: bool,
+    pub stemming: bool,
+    pub stem_language: StemLanguage,
+    // Characters in the n-grams postings tables list, if they list any
+    pub ngrams: Option<usize>,
+    pub stopwords: Arc<StopWords>,
+    // Splits lines into terms, if not into runs of letters, digits and underscores
+    pub analyzer: Option<Arc<dyn Analyzer>>,
+}
+
+// Identifies an analyzer by its name, 0 for none
+pub(crate) fn analyzer_fingerprint(analyzer: Option<&dyn Analyzer>) -> u64 {
+    analyzer.map(|analyzer| fnv1a64(analyzer.name().as_bytes())).unwrap_or(0)
+}
+
+// What the analyzers are built from, kept to reread stop word files
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AnalysisConfig {
+    pub(crate) fold_accents: bool,
+    pub(crate) stemming: bool,
+    pub(crate) stem_language: StemLanguage,
+    pub(crate) ngrams: Option<usize>,
+    pub(crate) stopwords: StopWordList,
+    pub(crate) rules: Vec<AnalysisRule>,
+    pub(crate) analyzer: Option<Arc<dyn Analyzer>>,
+}
+
+impl AnalysisConfig {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.rules.iter().any(|rule| rule.pattern.is_empty()) {
+            return Err(SearchEngineError::InvalidConfig("analysis rules need a pattern".to_string()));
+        }
+        let sizes = self.ngrams.into_iter().chain(self.rules.iter().filter_map(|rule| rule.ngrams.filter(|size| *size > 0)));
+        for size in sizes {
+            if !NGRAM_SIZES.contains(&size) {
+                return Err(SearchEngineError::InvalidConfig(format!("ngrams must be 2 or 3, not {}", size)));
+            }
+        }
+        Ok(())
+    }
+}
+
+// The engine's analysis and that of each rule, with their stop words loaded
+#[derive(Debug, Default)]
+pub(crate) struct Analyzers {
+    default: Analysis,
+    rules: Vec<(String, Analysis)>,
+    // Where relative paths for the rules' patterns are taken from
+    search_path: std::path::PathBuf,
+    roots: Vec<SearchRoot>,
+}
+
+impl Analyzers {
+    pub(crate) async fn load(config: &AnalysisConfig, search_path: &Path, roots: &[SearchRoot]) -> Result<Self> {
+        let default = Analysis {
+            fold_accents: config.fold_accents,
+            stemming: config.stemming,
+            stem_language: config.stem_language,
+            ngrams: config.ngrams,
+            stopwords: Arc::new(load_stopwords(&config.stopwords).await?.stemmed_in(config.stem_language)),
+            analyzer: config.analyzer.clone(),
+        };
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in &config.rules {
+            let stem_language = rule.stem_language.unwrap_or(default.stem_language);
+            let stopwords = match &rule.stopwords {
+                Some(list) => Arc::new(load_stopwords(list).await?.stemmed_in(stem_language)),
+                None if stem_language != default.stem_language => Arc::new(default.stopwords.as_ref().clone().stemmed_in(stem_language)),
+                None => default.stopwords.clone(),
+            };
+            let fold_accents = rule.fold_accents.unwrap_or(default.fold_accents);
+            let stemming = rule.stemming.unwrap_or(default.stemming);
+            let ngrams = match rule.ngrams {
+                Some(0) => None,
+                Some(size) => Some(size),
+                None => default.ngrams,
+            };
+            let analyzer = default.analyzer.clone();
+            rules.push((rule.pattern.clone(), Analysis { fold_accents, stemming, stem_language, ngrams, stopwords, analyzer }));
+        }
+        Ok(Analyzers { default, rules, search_path: search_path.to_path_buf(), roots: roots.to_vec() })
+    }
+
+    pub(crate) fn default_analysis(&self) -> &Analysis {
+        &self.default
+    }
+
+    // Every analysis in use: the engine's first, then each rule's
+    pub(crate) fn all(&self) -> impl Iterator<Item = &Analysis> {
+        std::iter::once(&self.default).chain(self.rules.iter().map(|(_, analysis)| analysis))
+    }
+
+    // Position in `all` of the analysis for a document
+    pub(crate) fn index_for(&self, file_path: &Path) -> usize {
+        if self.rules.is_empty() {
+            return 0;
+        }
+        let relative = relative_path(&self.search_path, &self.roots, file_path).replace('\\', "/");
+        self.rules.iter()
+            .position(|(pattern, _)| pattern_matches(pattern, &relative))
+            .map(|at| at + 1)
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn for_file(&self, file_path: &Path) -> &Analysis {
+        self.all().nth(self.index_for(file_path)).unwrap_or(&self.default)
+    }
+
+    // Identifies the stop words, accent folding and analyzer in use and which documents they
+    // apply to, as postings tables and the term statistics taken from them depend on them
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let of = |analysis: &Analysis| {
+            analysis.stopwords.fingerprint() ^ analysis.fold_accents as u64 ^ analyzer_fingerprint(analysis.analyzer.as_deref()).rotate_left(1)
+        };
+        self.rules.iter().fold(of(&self.default), |hash, (pattern, analysis)| {
+            hash.rotate_left(5) ^ fnv1a64(pattern.as_bytes()) ^ of(analysis)
+        })
+    }
+}
+
+// Shared by the engine and its postings store; replaced whole when stop words are reloaded
+pub(crate) type SharedAnalyzers = Arc<RwLock<Arc<Analyzers>>>;
+
+pub(crate) fn current(analyzers: &SharedAnalyzers) -> Arc<Analyzers> {
+    analyzers.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
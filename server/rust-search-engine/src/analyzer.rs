@@ -0,0 +1,173 @@
+// Custom analyzers: how a line is split into the terms postings tables list, BM25 counts and
+// word mode matches whole. Without one the engine splits lowercased text into runs of letters,
+// digits and underscores. An analyzer sees lines as written, so it can split on case, and the
+// engine lowercases and folds the terms it returns. Query terms reach it already lowercased,
+// so a filter splitting on case has to keep the whole token as well for "getusername" to
+// find "getUserName".
+//
+// `Pipeline` puts one together from char filters, a tokenizer and token filters:
+//
+//   Pipeline::new("code", WhitespaceTokenizer)
+//       .token_filter(DelimiterFilter::new(['-', '.', '_']))
+//       .token_filter(CamelCaseFilter)
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::matching::{index_terms, is_word_char};
+
+pub trait Analyzer: fmt::Debug + Send + Sync {
+    // Identifies the analyzer; postings tables built by one with another name are rebuilt
+    fn name(&self) -> &str;
+
+    // The terms of `text`, in order
+    fn analyze(&self, text: &str) -> Vec<String>;
+}
+
+// Rewrites text before it is split, e.g. to drop markup or map characters
+pub trait CharFilter: fmt::Debug + Send + Sync {
+    fn filter<'a>(&self, text: &'a str) -> Cow<'a, str>;
+}
+
+pub trait Tokenizer: fmt::Debug + Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+// Rewrites, adds or drops tokens
+pub trait TokenFilter: fmt::Debug + Send + Sync {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String>;
+}
+
+// Char filters, then a tokenizer, then token filters, each in the order added
+#[derive(Debug)]
+pub struct Pipeline {
+    name: String,
+    char_filters: Vec<Box<dyn CharFilter>>,
+    tokenizer: Box<dyn Tokenizer>,
+    token_filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl Pipeline {
+    pub fn new(name: impl Into<String>, tokenizer: impl Tokenizer + 'static) -> Self {
+        Pipeline { name: name.into(), char_filters: Vec::new(), tokenizer: Box::new(tokenizer), token_filters: Vec::new() }
+    }
+
+    pub fn char_filter(mut self, filter: impl CharFilter + 'static) -> Self {
+        self.char_filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn token_filter(mut self, filter: impl TokenFilter + 'static) -> Self {
+        self.token_filters.push(Box::new(filter));
+        self
+    }
+}
+
+impl Analyzer for Pipeline {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn analyze(&self, text: &str) -> Vec<String> {
+        let text = self.char_filters.iter().fold(Cow::Borrowed(text), |text, filter| match filter.filter(&text) {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(filtered) => Cow::Owned(filtered),
+        });
+        let tokens = self.tokenizer.tokenize(&text);
+        self.token_filters.iter().fold(tokens, |tokens, filter| filter.filter(tokens))
+    }
+}
+
+// Runs of letters, digits and underscores, as the engine splits text without an analyzer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StandardTokenizer;
+
+impl Tokenizer for StandardTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        index_terms(text).map(str::to_string).collect()
+    }
+}
+
+// Text between whitespace, without punctuation at either end, so "e-mail," is "e-mail"
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|token| token.trim_matches(|c: char| !is_word_char(c)))
+            .filter(|token| !token.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+// Follows each token containing one of the delimiters with its parts between them, so
+// "e-mail" is also "e" and "mail"
+#[derive(Debug, Clone)]
+pub struct DelimiterFilter {
+    delimiters: Vec<char>,
+}
+
+impl DelimiterFilter {
+    pub fn new(delimiters: impl IntoIterator<Item = char>) -> Self {
+        DelimiterFilter { delimiters: delimiters.into_iter().collect() }
+    }
+}
+
+impl TokenFilter for DelimiterFilter {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String> {
+        let mut filtered = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let parts: Vec<String> = token.split(self.delimiters.as_slice())
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect();
+            filtered.push(token);
+            if parts.len() > 1 {
+                filtered.extend(parts);
+            }
+        }
+        filtered
+    }
+}
+
+// Follows each camelCase or PascalCase token with its words, so "getHTTPResponse" is also
+// "get", "HTTP" and "Response"
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CamelCaseFilter;
+
+// Words of a camelCase token: a new one starts at an uppercase letter after a lowercase letter
+// or digit, and at the last of a run of uppercase letters followed by a lowercase one
+fn camel_case_words(token: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = token.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (at, c) = chars[i];
+        let previous = chars[i - 1].1;
+        let next_lower = chars.get(i + 1).is_some_and(|(_, next)| next.is_lowercase());
+        let boundary = c.is_uppercase()
+            && ((previous.is_lowercase() || previous.is_numeric()) || (previous.is_uppercase() && next_lower));
+        if boundary {
+            words.push(&token[start..at]);
+            start = at;
+        }
+    }
+    words.push(&token[start..]);
+    words
+}
+
+impl TokenFilter for CamelCaseFilter {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String> {
+        let mut filtered = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let words: Vec<String> = camel_case_words(&token).into_iter().map(str::to_string).collect();
+            filtered.push(token);
+            if words.len() > 1 {
+                filtered.extend(words);
+            }
+        }
+        filtered
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::warn;
+
+use crate::error::{Context, Result};
+use crate::SearchEngine;
+
+pub const AUDIT_LOG_FILE: &str = "audit.log";
+
+// One line of the append-only audit log of index mutations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    // Who asked for the change: "cli", or the identity supplied by a server request
+    pub actor: String,
+    pub operation: String,
+    pub documents: Vec<String>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub since: Option<DateTime<Utc>>,
+    pub actor: Option<String>,
+    pub operation: Option<String>,
+    // Most recent N matching entries
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.map(|since| entry.timestamp >= since).unwrap_or(true)
+            && self.actor.as_ref().map(|actor| &entry.actor == actor).unwrap_or(true)
+            && self.operation.as_ref().map(|op| &entry.operation == op).unwrap_or(true)
+    }
+}
+
+impl SearchEngine {
+    // Identity recorded in the audit log for subsequent mutations
+    pub fn set_actor(&mut self, actor: &str) {
+        self.actor = actor.to_string();
+    }
+
+    pub(crate) async fn audit(&self, operation: &str, documents: Vec<String>, result: std::result::Result<(), String>) {
+        // Read-only engines change nothing, and may not write the log either
+        if self.read_only {
+            return;
+        }
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            actor: self.actor.clone(),
+            operation: operation.to_string(),
+            documents,
+            success: result.is_ok(),
+            message: result.err(),
+        };
+        if let Err(e) = self.append_audit(&entry).await {
+            warn!(error = %e, operation, "Failed to write audit log entry");
+        }
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        async_fs::create_dir_all(self.doc_store.index_dir()).await?;
+        let mut file = async_fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.doc_store.index_dir().join(AUDIT_LOG_FILE))
+            .await
+            .context("Failed to open audit log")?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+
+    pub async fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let path = self.doc_store.index_dir().join(AUDIT_LOG_FILE);
+        let file = match async_fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to open audit log"),
+        };
+
+        let mut entries = Vec::new();
+        let mut lines = BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str::<AuditEntry>(&line) {
+                Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(e) => warn!(error = %e, "Skipping unreadable audit log line"),
+            }
+        }
+
+        if let Some(limit) = filter.limit {
+            let skip = entries.len().saturating_sub(limit);
+            entries.drain(..skip);
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,357 @@
+// Synchronous wrapper around the async engine for callers without a tokio runtime.
+// Each engine owns a single-threaded runtime and blocks on it, so these methods
+// must not be called from inside another runtime.
+
+use chrono::{DateTime, Duration, Utc};
+use futures_core::Stream;
+use std::collections::BTreeMap;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::alerts::{AlertEvent, SavedQuery};
+use crate::aliases::AliasSwitch;
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::changes::ChangeFeed;
+use crate::config::Config;
+use crate::corpusstats::CorpusReport;
+use crate::dedup::DuplicateCluster;
+use crate::dictionaries::DictionaryVersion;
+use crate::doctor::DoctorReport;
+use crate::error::{Context, Result};
+use crate::keywords::KeywordCount;
+use crate::metadata::Metadata;
+use crate::partition::PartitionStats;
+use crate::reader::PointInTime;
+use crate::records::JsonMapping;
+use crate::refine::ResultSet;
+use crate::replication::{ReplicationManifest, SyncReport};
+use crate::scroll::ScrollPage;
+use crate::options::{MatchMode, SearchFilters, SearchOptions};
+use crate::percolator::PercolateMatch;
+use crate::progress::ProgressReporter;
+use crate::querylog::{QueryAnalytics, SlowQueryEntry};
+use crate::source::{DocumentInput, DocumentSource, IndexReport};
+use crate::suggest::Suggestion;
+use crate::trash::TrashEntry;
+use crate::usage::UsageStats;
+use crate::vector::SemanticResponse;
+use crate::{
+    IndexedDocument, LineRange, LineResult, MaintenanceResult, MaintenanceTask, SearchEngineBuilder, SearchResponse, Stats, Status,
+};
+
+pub struct SearchEngine {
+    inner: crate::SearchEngine,
+    runtime: Runtime,
+}
+
+impl SearchEngine {
+    pub fn new(search_path: &str) -> Result<Self> {
+        Self::open(SearchEngineBuilder::new(search_path))
+    }
+
+    pub fn open(builder: SearchEngineBuilder) -> Result<Self> {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start runtime")?;
+        let inner = runtime.block_on(builder.build())?;
+        Ok(SearchEngine { inner, runtime })
+    }
+
+    // The wrapped async engine, for APIs not mirrored here
+    pub fn get_ref(&self) -> &crate::SearchEngine {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> crate::SearchEngine {
+        self.inner
+    }
+
+    pub fn set_actor(&mut self, actor: &str) {
+        self.inner.set_actor(actor);
+    }
+
+    pub fn warm(&self) -> Result<()> {
+        self.runtime.block_on(self.inner.warm())
+    }
+
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<SearchResponse> {
+        self.runtime.block_on(self.inner.search(query, options))
+    }
+
+    pub fn search_collections(&self, query: &str, searches: &[(String, SearchOptions)]) -> Result<SearchResponse> {
+        self.runtime.block_on(self.inner.search_collections(query, searches))
+    }
+
+    pub fn search_fuzzy(&self, query: &str, max_distance: usize, options: &SearchOptions) -> Result<SearchResponse> {
+        self.runtime.block_on(self.inner.search_fuzzy(query, max_distance, options))
+    }
+
+    pub fn semantic_search(&self, query: &str, options: &SearchOptions) -> Result<SemanticResponse> {
+        self.runtime.block_on(self.inner.semantic_search(query, options))
+    }
+
+    pub fn get_line(&self, path: &str, line_number: usize, principals: Option<&[String]>) -> Result<Option<LineResult>> {
+        self.runtime.block_on(self.inner.get_line(path, line_number, principals))
+    }
+
+    pub fn get_lines(&self, path: &str, start: usize, end: usize, principals: Option<&[String]>) -> Result<Option<LineRange>> {
+        self.runtime.block_on(self.inner.get_lines(path, start, end, principals))
+    }
+
+    pub fn index_document(&self, source: &str) -> Result<IndexedDocument> {
+        self.runtime.block_on(self.inner.index_document(source))
+    }
+
+    pub fn index_document_with_ttl(&self, source: &str, ttl: Option<Duration>) -> Result<IndexedDocument> {
+        self.runtime.block_on(self.inner.index_document_with_ttl(source, ttl))
+    }
+
+    pub fn index_document_with_id(&self, source: &str, id: &str, ttl: Option<Duration>) -> Result<IndexedDocument> {
+        self.runtime.block_on(self.inner.index_document_with_id(source, id, ttl))
+    }
+
+    pub fn index_document_with_metadata(
+        &self,
+        source: &str,
+        id: Option<&str>,
+        ttl: Option<Duration>,
+        metadata: Metadata,
+    ) -> Result<IndexedDocument> {
+        self.runtime.block_on(self.inner.index_document_with_metadata(source, id, ttl, metadata))
+    }
+
+    pub fn index_document_with_acl(
+        &self,
+        source: &str,
+        id: Option<&str>,
+        ttl: Option<Duration>,
+        metadata: Option<Metadata>,
+        acl: Vec<String>,
+    ) -> Result<IndexedDocument> {
+        self.runtime.block_on(self.inner.index_document_with_acl(source, id, ttl, metadata, acl))
+    }
+
+    pub fn index_json(&self, path: &str, mapping: JsonMapping, ttl: Option<Duration>, acl: Option<Vec<String>>) -> Result<IndexReport> {
+        self.runtime.block_on(self.inner.index_json(path, mapping, ttl, acl))
+    }
+
+    pub fn index_source<S: DocumentSource>(&self, source: S) -> Result<IndexReport> {
+        self.runtime.block_on(self.inner.index_source(source))
+    }
+
+    pub fn index_stream<S>(&self, stream: S, batch_size: usize) -> Result<IndexReport>
+    where
+        S: Stream<Item = DocumentInput> + Send,
+    {
+        self.runtime.block_on(self.inner.index_stream(stream, batch_size))
+    }
+
+    pub fn index_source_with_progress<S: DocumentSource>(
+        &self,
+        source: S,
+        batch_size: usize,
+        progress: &dyn ProgressReporter,
+    ) -> Result<IndexReport> {
+        self.runtime.block_on(self.inner.index_source_with_progress(source, batch_size, progress))
+    }
+
+    pub fn delete_document(&self, path: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.delete_document(path))
+    }
+
+    pub fn trash_entries(&self) -> Result<Vec<TrashEntry>> {
+        self.runtime.block_on(self.inner.trash_entries())
+    }
+
+    pub fn restore_document(&self, path: &str) -> Result<IndexedDocument> {
+        self.runtime.block_on(self.inner.restore_document(path))
+    }
+
+    pub fn near_duplicates(&self, max_distance: u32, principals: Option<&[String]>) -> Result<Vec<DuplicateCluster>> {
+        self.runtime.block_on(self.inner.near_duplicates(max_distance, principals))
+    }
+
+    pub fn keyword_counts(&self, top: usize, principals: Option<&[String]>) -> Result<Vec<KeywordCount>> {
+        self.runtime.block_on(self.inner.keyword_counts(top, principals))
+    }
+
+    pub fn corpus_report(&self, top: usize) -> Result<CorpusReport> {
+        self.runtime.block_on(self.inner.corpus_report(top))
+    }
+
+    pub fn facet_counts(&self, fields: &[String], principals: Option<&[String]>) -> Result<BTreeMap<String, BTreeMap<String, usize>>> {
+        self.runtime.block_on(self.inner.facet_counts(fields, principals))
+    }
+
+    pub fn partitions(&self) -> Result<Vec<PartitionStats>> {
+        self.runtime.block_on(self.inner.partitions())
+    }
+
+    pub fn generation(&self) -> Result<u64> {
+        self.runtime.block_on(self.inner.generation())
+    }
+
+    pub fn changes_since(&self, since: u64, limit: Option<usize>) -> Result<ChangeFeed> {
+        self.runtime.block_on(self.inner.changes_since(since, limit))
+    }
+
+    pub fn aliases(&self) -> Result<BTreeMap<String, String>> {
+        self.runtime.block_on(self.inner.aliases())
+    }
+
+    pub fn set_alias(&self, alias: &str, collection: &str) -> Result<AliasSwitch> {
+        self.runtime.block_on(self.inner.set_alias(alias, collection))
+    }
+
+    pub fn remove_alias(&self, alias: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.remove_alias(alias))
+    }
+
+    pub fn add_tags(&self, document: &str, tags: &[String]) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.add_tags(document, tags))
+    }
+
+    pub fn remove_tags(&self, document: &str, tags: &[String]) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.remove_tags(document, tags))
+    }
+
+    pub fn document_tags(&self, document: &str) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.document_tags(document))
+    }
+
+    pub fn tag_counts(&self) -> Result<BTreeMap<String, usize>> {
+        self.runtime.block_on(self.inner.tag_counts())
+    }
+
+    pub fn open_reader(&self, keep_alive: Option<Duration>) -> Result<PointInTime> {
+        self.runtime.block_on(self.inner.open_reader(keep_alive))
+    }
+
+    pub fn close_reader(&self, id: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.close_reader(id))
+    }
+
+    pub fn readers(&self) -> Result<Vec<PointInTime>> {
+        self.runtime.block_on(self.inner.readers())
+    }
+
+    pub fn result_sets(&self) -> Result<Vec<ResultSet>> {
+        self.runtime.block_on(self.inner.result_sets())
+    }
+
+    pub fn close_result_set(&self, id: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.close_result_set(id))
+    }
+
+    pub fn open_scroll(&self, query: &str, options: &SearchOptions, batch_size: usize, keep_alive: Option<Duration>) -> Result<ScrollPage> {
+        self.runtime.block_on(self.inner.open_scroll(query, options, batch_size, keep_alive))
+    }
+
+    pub fn scroll(&self, id: &str) -> Result<ScrollPage> {
+        self.runtime.block_on(self.inner.scroll(id))
+    }
+
+    pub fn close_scroll(&self, id: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.close_scroll(id))
+    }
+
+    pub fn replication_manifest(&self) -> Result<ReplicationManifest> {
+        self.runtime.block_on(self.inner.replication_manifest())
+    }
+
+    pub fn sync_replica(&self) -> Result<SyncReport> {
+        self.runtime.block_on(self.inner.sync_replica())
+    }
+
+    pub fn get_stats(&self) -> Result<Stats> {
+        self.runtime.block_on(self.inner.get_stats())
+    }
+
+    pub fn get_detailed_stats(&self) -> Result<Stats> {
+        self.runtime.block_on(self.inner.get_detailed_stats())
+    }
+
+    pub fn get_status(&self) -> Result<Status> {
+        self.runtime.block_on(self.inner.get_status())
+    }
+
+    pub fn reload_stopwords(&self) -> Result<usize> {
+        self.runtime.block_on(self.inner.reload_stopwords())
+    }
+
+    pub fn reload_synonyms(&self) -> Result<usize> {
+        self.runtime.block_on(self.inner.reload_synonyms())
+    }
+
+    pub fn dictionary_version(&self) -> DictionaryVersion {
+        self.inner.dictionary_version()
+    }
+
+    pub fn reload_pins(&self) -> Result<usize> {
+        self.runtime.block_on(self.inner.reload_pins())
+    }
+
+    pub fn query_analytics(&self, top: usize, since: Option<DateTime<Utc>>) -> Result<QueryAnalytics> {
+        self.runtime.block_on(self.inner.query_analytics(top, since))
+    }
+
+    pub fn suggest(&self, prefix: &str, limit: usize, principals: Option<&[String]>) -> Result<Vec<Suggestion>> {
+        self.runtime.block_on(self.inner.suggest(prefix, limit, principals))
+    }
+
+    pub fn suggest_from_history(&self, prefix: &str, limit: usize, since: Option<DateTime<Utc>>) -> Result<Vec<Suggestion>> {
+        self.runtime.block_on(self.inner.suggest_from_history(prefix, limit, since))
+    }
+
+    pub fn slow_queries(&self, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Result<Vec<SlowQueryEntry>> {
+        self.runtime.block_on(self.inner.slow_queries(since, limit))
+    }
+
+    pub fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        self.runtime.block_on(self.inner.audit_log(filter))
+    }
+
+    pub fn alerts(&self) -> Result<Vec<SavedQuery>> {
+        self.runtime.block_on(self.inner.alerts())
+    }
+
+    pub fn add_alert(&self, query: &str, mode: MatchMode, filters: SearchFilters, webhook: Option<String>) -> Result<SavedQuery> {
+        self.runtime.block_on(self.inner.add_alert(query, mode, filters, webhook))
+    }
+
+    pub fn remove_alert(&self, id: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.remove_alert(id))
+    }
+
+    pub fn percolate(&self, text: &str, path: Option<&str>) -> Result<Vec<PercolateMatch>> {
+        self.runtime.block_on(self.inner.percolate(text, path))
+    }
+
+    pub fn alert_events(&self, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Result<Vec<AlertEvent>> {
+        self.runtime.block_on(self.inner.alert_events(since, limit))
+    }
+
+    pub fn run_maintenance(&self, task: &MaintenanceTask) -> Result<MaintenanceResult> {
+        self.runtime.block_on(self.inner.run_maintenance(task))
+    }
+
+    pub fn run_maintenance_with_progress(&self, task: &MaintenanceTask, progress: &dyn ProgressReporter) -> Result<MaintenanceResult> {
+        self.runtime.block_on(self.inner.run_maintenance_with_progress(task, progress))
+    }
+
+    pub fn preview_maintenance(&self, task: &MaintenanceTask) -> Result<MaintenanceResult> {
+        self.runtime.block_on(self.inner.preview_maintenance(task))
+    }
+
+    pub fn doctor(&self, config: &Config) -> Result<DoctorReport> {
+        self.runtime.block_on(self.inner.doctor(config))
+    }
+
+    pub fn prometheus_metrics(&self) -> Result<String> {
+        self.runtime.block_on(self.inner.prometheus_metrics())
+    }
+
+    pub fn export_usage_stats(&self, reset: bool) -> Result<Option<UsageStats>> {
+        self.runtime.block_on(self.inner.export_usage_stats(reset))
+    }
+}
@@ -0,0 +1,294 @@
+// BM25 relevance for matching lines. Each line is scored as its own unit, with term
+// rarity (IDF) taken from how many indexed documents contain each term and length
+// normalization against the average line length of the corpus.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::boolean::QueryNode;
+use crate::error::{Result, SearchEngineError};
+use crate::fuzzy::edit_distance;
+use crate::matching::{index_terms, Matcher};
+use crate::normalize::fold_text;
+use crate::options::MatchMode;
+use crate::stem::{stem, StemLanguage};
+use crate::synonyms::SYNONYM_WEIGHT;
+use crate::wildcard::wildcard_matches;
+
+// Tuning parameters: `k1` controls how quickly repeated terms stop adding to the score,
+// `b` how strongly long lines are penalized (0 ignores length, 1 normalizes fully)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bm25 {
+    pub k1: f32,
+    pub b: f32,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    pub fn new(k1: f32, b: f32) -> Self {
+        Bm25 { k1, b }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.k1.is_finite() || self.k1 < 0.0 {
+            return Err(SearchEngineError::InvalidConfig("bm25 k1 must be a non-negative number".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.b) {
+            return Err(SearchEngineError::InvalidConfig("bm25 b must be between 0 and 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Term statistics over a set of documents, as collected when they were indexed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CorpusStats {
+    pub documents: u64,
+    pub lines: u64,
+    // Term occurrences across all lines
+    pub tokens: u64,
+    // Number of documents containing each term
+    pub(crate) document_frequency: HashMap<String, u64>,
+}
+
+impl CorpusStats {
+    // Adds a document of `lines` lines and `tokens` term occurrences with these distinct terms
+    pub fn add_document<'a>(&mut self, lines: u64, tokens: u64, terms: impl IntoIterator<Item = &'a str>) {
+        self.documents += 1;
+        self.lines += lines;
+        self.tokens += tokens;
+        for term in terms {
+            match self.document_frequency.get_mut(term) {
+                Some(count) => *count += 1,
+                None => {
+                    self.document_frequency.insert(term.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    // Statistics of documents already in memory
+    pub fn from_texts<'a>(texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut stats = CorpusStats::default();
+        for text in texts {
+            let lower = fold_text(text, false);
+            let mut terms: Vec<&str> = index_terms(&lower).collect();
+            let tokens = terms.len() as u64;
+            terms.sort_unstable();
+            terms.dedup();
+            stats.add_document(text.lines().count() as u64, tokens, terms);
+        }
+        stats
+    }
+
+    pub fn distinct_terms(&self) -> usize {
+        self.document_frequency.len()
+    }
+
+    // Documents containing `term`, or in substring matching a term that contains it; the
+    // latter adds up the terms' counts, so it can overstate documents holding several of them
+    pub fn document_frequency(&self, term: &str, exact: bool) -> u64 {
+        if exact {
+            return self.document_frequency.get(term).copied().unwrap_or(0);
+        }
+        let total: u64 = self.document_frequency.iter()
+            .filter(|(indexed, _)| indexed.contains(term))
+            .map(|(_, count)| count)
+            .sum();
+        total.min(self.documents)
+    }
+
+    // Documents `document_frequency` counts for `term` or containing a word with the same
+    // stem in `language`, added up the same way
+    pub fn document_frequency_stemmed(&self, term: &str, exact: bool, language: StemLanguage) -> u64 {
+        let stemmed = stem(term, language);
+        let total: u64 = self.document_frequency.iter()
+            .filter(|(indexed, _)| {
+                (if exact { *indexed == term } else { indexed.contains(term) }) || stem(indexed, language) == stemmed
+            })
+            .map(|(_, count)| count)
+            .sum();
+        total.min(self.documents)
+    }
+
+    // Documents containing a term within `distance` edits of `term`, added up the same way
+    pub fn document_frequency_within(&self, term: &str, distance: usize) -> u64 {
+        let total: u64 = self.document_frequency.iter()
+            .filter(|(indexed, _)| edit_distance(indexed, term, distance).is_some())
+            .map(|(_, count)| count)
+            .sum();
+        total.min(self.documents)
+    }
+
+    // Documents containing a term that fits the wildcard `pattern`, added up the same way
+    pub fn document_frequency_matching(&self, pattern: &str) -> u64 {
+        let total: u64 = self.document_frequency.iter()
+            .filter(|(indexed, _)| wildcard_matches(pattern, indexed))
+            .map(|(_, count)| count)
+            .sum();
+        total.min(self.documents)
+    }
+
+    pub fn average_line_length(&self) -> f32 {
+        if self.lines == 0 {
+            return 0.0;
+        }
+        self.tokens as f32 / self.lines as f32
+    }
+
+    fn idf(&self, term: &str, exact: bool) -> f32 {
+        self.idf_of(self.document_frequency(term, exact))
+    }
+
+    fn idf_of(&self, frequency: u64) -> f32 {
+        let documents = self.documents as f32;
+        let frequency = frequency as f32;
+        (1.0 + (documents - frequency + 0.5) / (frequency + 0.5)).ln()
+    }
+}
+
+// Scores the lines matched by a query, with each query term's IDF worked out up front
+pub(crate) struct Scorer<'a> {
+    matcher: &'a Matcher,
+    params: Bm25,
+    average_line_length: f32,
+    // Query terms with their IDF, leaving out stop words unless there is nothing else; empty
+    // in regex mode
+    terms: Vec<(String, f32)>,
+    // Stems of the query terms, in the same order, when the matcher stems them
+    stems: Vec<String>,
+    // Fuzzy terms with the edits they allow and the IDF of the words they match
+    fuzzy: Vec<(String, usize, f32)>,
+    // Wildcard patterns with the IDF of the words they match
+    wildcards: Vec<(String, f32)>,
+    // NEAR word pairs with the distance allowed and the IDF of both words added up
+    near: Vec<(String, String, usize, f32)>,
+    // Synonyms with the IDF of the terms they stand in for
+    synonyms: Vec<(&'a QueryNode, f32)>,
+}
+
+impl<'a> Scorer<'a> {
+    pub(crate) fn new(matcher: &'a Matcher, params: Bm25, stats: &CorpusStats) -> Self {
+        // Statistics and stop words are lowercased, whatever the case a search matches in
+        let key = |term: &str| -> String {
+            if matcher.is_case_sensitive() { term.to_lowercase() } else { term.to_string() }
+        };
+        let mut terms: Vec<(String, f32)> = Vec::new();
+        if matcher.mode() != MatchMode::Regex {
+            let stopwords = matcher.stopwords();
+            let query_terms = matcher.terms_of(matcher.query());
+            let all_stopwords = query_terms.iter().all(|term| stopwords.contains(&key(term)));
+            for term in query_terms.iter().filter(|term| all_stopwords || !stopwords.contains(&key(term))) {
+                let term = term.as_ref();
+                if !terms.iter().any(|(existing, _)| existing == term) {
+                    let exact = matcher.is_exact(term);
+                    let idf = if matcher.stemming() {
+                        stats.idf_of(stats.document_frequency_stemmed(term, exact, matcher.stem_language()))
+                    } else {
+                        stats.idf(&key(term), exact)
+                    };
+                    terms.push((term.to_string(), idf));
+                }
+            }
+        }
+        let stems = if matcher.stemming() {
+            terms.iter().map(|(term, _)| stem(term, matcher.stem_language()).into_owned()).collect()
+        } else {
+            Vec::new()
+        };
+        let fuzzy = matcher.fuzzy_terms()
+            .map(|(term, distance)| (term.to_string(), distance, stats.idf_of(stats.document_frequency_within(&key(term), distance))))
+            .collect();
+        let wildcards = matcher.wildcard_terms()
+            .map(|pattern| (pattern.to_string(), stats.idf_of(stats.document_frequency_matching(&key(pattern)))))
+            .collect();
+        let near = matcher.near_terms()
+            .map(|(left, right, distance)| {
+                (left.to_string(), right.to_string(), distance, stats.idf(&key(left), true) + stats.idf(&key(right), true))
+            })
+            .collect();
+        let synonyms = matcher.synonym_terms()
+            .map(|(synonym, of)| {
+                let idf = matcher.terms_of(of).iter()
+                    .filter_map(|word| terms.iter().find(|(term, _)| term == word).map(|(_, idf)| *idf))
+                    .sum();
+                (synonym, idf)
+            })
+            .collect();
+        Scorer { matcher, params, average_line_length: stats.average_line_length(), terms, stems, fuzzy, wildcards, near, synonyms }
+    }
+
+    pub(crate) fn matcher(&self) -> &Matcher {
+        self.matcher
+    }
+
+    // Whether scoring uses corpus statistics; regex queries are scored by match count alone
+    pub(crate) fn needs_stats(matcher: &Matcher) -> bool {
+        matcher.mode() != MatchMode::Regex
+    }
+
+    // Relevance of a line the matcher accepted; `line_lower` is the lowercased `line`
+    pub(crate) fn score(&self, line: &str, line_lower: &str) -> f32 {
+        if let Some(count) = self.matcher.regex_count(line) {
+            return count as f32 * 10.0;
+        }
+        let line_terms = self.matcher.line_terms(line, line_lower);
+        let words: Vec<&str> = line_terms.iter().map(|word| word.as_ref()).collect();
+        let length = words.len() as f32;
+        let relative_length = if self.average_line_length > 0.0 { length / self.average_line_length } else { 1.0 };
+        let Bm25 { k1, b } = self.params;
+        let norm = k1 * (1.0 - b + b * relative_length);
+        let saturate = |tf: f32| if tf > 0.0 { tf * (k1 + 1.0) / (tf + norm) } else { 0.0 };
+
+        // A query without letters or digits counts as one term that is never rare
+        if self.terms.is_empty() && self.fuzzy.is_empty() && self.wildcards.is_empty() && self.near.is_empty() {
+            return saturate(line_lower.matches(self.matcher.query()).count() as f32);
+        }
+        // With stemming, a term occurs as often as words with its stem do, or as it matches
+        // as written if that is more often
+        let exact: f32 = self.terms.iter().enumerate()
+            .map(|(at, (term, idf))| {
+                let mut tf = if self.matcher.is_exact(term) {
+                    words.iter().filter(|word| **word == term).count()
+                } else {
+                    line_lower.matches(term.as_str()).count()
+                };
+                if let Some(stemmed) = self.stems.get(at) {
+                    tf = tf.max(words.iter().filter(|word| stem(word, self.matcher.stem_language()) == stemmed.as_str()).count());
+                }
+                idf * saturate(tf as f32)
+            })
+            .sum();
+        // A word n edits from a fuzzy term counts 1 / (n + 1) of an occurrence, so exact
+        // matches rank above near ones
+        let fuzzy: f32 = self.fuzzy.iter()
+            .map(|(term, distance, idf)| {
+                let tf: f32 = words.iter()
+                    .filter_map(|word| edit_distance(word, term, *distance))
+                    .map(|edits| 1.0 / (edits + 1) as f32)
+                    .sum();
+                idf * saturate(tf)
+            })
+            .sum();
+        let wildcards: f32 = self.wildcards.iter()
+            .map(|(pattern, idf)| idf * saturate(words.iter().filter(|word| wildcard_matches(pattern, word)).count() as f32))
+            .sum();
+        // Each occurrence of the left word counts 1 / n for the right word n words away, so
+        // closer pairs rank higher
+        let near: f32 = self.near.iter()
+            .map(|(left, right, distance, idf)| idf * saturate(self.matcher.near_frequency(line, line_lower, left, right, *distance)))
+            .sum();
+        // An occurrence of a synonym counts SYNONYM_WEIGHT of one of the terms it stands in
+        // for, so lines with the query's own words rank first
+        let synonyms: f32 = self.synonyms.iter()
+            .map(|(synonym, idf)| idf * saturate(SYNONYM_WEIGHT * self.matcher.leaf_ranges(line, synonym).len() as f32))
+            .sum();
+        exact + fuzzy + wildcards + near + synonyms
+    }
+}
@@ -0,0 +1,90 @@
+// The tree a boolean query is parsed into by `query`: terms, phrases, fuzzy terms, wildcards
+// and NEAR pairs combined with AND, OR and NOT, and the synonyms `synonyms` ORs terms with.
+// The tree is evaluated per document.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryNode {
+    // Lowercased text matched according to the query's match mode
+    Term(String),
+    // Lowercased words, separated by single spaces, that must appear as adjacent whole words
+    Phrase(String),
+    // A lowercased word matching whole words at most `distance` edits from it
+    Fuzzy { term: String, distance: usize },
+    // A lowercased word with `*` and `?` wildcards matching whole words that fit it
+    Wildcard(String),
+    // Two lowercased words, separated by a space, that must occur as whole words on the same
+    // line at most `distance` words apart
+    Near { terms: String, distance: usize },
+    // Lowercased text matched like a term in place of the query text `of`, which has it as a
+    // synonym; scored a little below `of` itself
+    Synonym { term: String, of: String },
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+    Not(Box<QueryNode>),
+}
+
+impl QueryNode {
+    // Distinct terms and phrases in the order they appear, either those that are not negated
+    // or those that are
+    pub fn terms(&self, negated: bool) -> Vec<&QueryNode> {
+        let mut terms = Vec::new();
+        self.collect_terms(negated, false, &mut terms);
+        terms
+    }
+
+    // Replaces the text of every term, phrase, fuzzy term, wildcard pattern and NEAR
+    pub(crate) fn map_text(&mut self, f: &dyn Fn(&str) -> String) {
+        match self {
+            QueryNode::Term(text) | QueryNode::Phrase(text) | QueryNode::Fuzzy { term: text, .. } | QueryNode::Wildcard(text)
+            | QueryNode::Near { terms: text, .. } => *text = f(text),
+            QueryNode::Synonym { term, of } => {
+                *term = f(term);
+                *of = f(of);
+            }
+            QueryNode::And(nodes) | QueryNode::Or(nodes) => nodes.iter_mut().for_each(|node| node.map_text(f)),
+            QueryNode::Not(node) => node.map_text(f),
+        }
+    }
+
+    // The text of a term, phrase, fuzzy term, wildcard pattern or the two words of a NEAR
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            QueryNode::Term(text) | QueryNode::Phrase(text) | QueryNode::Fuzzy { term: text, .. } | QueryNode::Wildcard(text)
+            | QueryNode::Near { terms: text, .. } | QueryNode::Synonym { term: text, .. } => Some(text),
+            QueryNode::And(_) | QueryNode::Or(_) | QueryNode::Not(_) => None,
+        }
+    }
+
+    fn collect_terms<'a>(&'a self, wanted: bool, negated: bool, terms: &mut Vec<&'a QueryNode>) {
+        match self {
+            QueryNode::Term(_) | QueryNode::Phrase(_) | QueryNode::Fuzzy { .. } | QueryNode::Wildcard(_) | QueryNode::Near { .. }
+            | QueryNode::Synonym { .. } => {
+                if wanted == negated && !terms.contains(&self) {
+                    terms.push(self);
+                }
+            }
+            QueryNode::And(operands) | QueryNode::Or(operands) => {
+                for operand in operands {
+                    operand.collect_terms(wanted, negated, terms);
+                }
+            }
+            QueryNode::Not(operand) => operand.collect_terms(wanted, !negated, terms),
+        }
+    }
+
+    // Whether a document containing just the terms and phrases `contains` accepts satisfies the query
+    pub fn evaluate(&self, contains: &dyn Fn(&QueryNode) -> bool) -> bool {
+        match self {
+            QueryNode::Term(_) | QueryNode::Phrase(_) | QueryNode::Fuzzy { .. } | QueryNode::Wildcard(_) | QueryNode::Near { .. }
+            | QueryNode::Synonym { .. } => {
+                contains(self)
+            }
+            QueryNode::And(operands) => operands.iter().all(|operand| operand.evaluate(contains)),
+            QueryNode::Or(operands) => operands.iter().any(|operand| operand.evaluate(contains)),
+            QueryNode::Not(operand) => !operand.evaluate(contains),
+        }
+    }
+}
@@ -0,0 +1,542 @@
+use chrono::Duration;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::fs as async_fs;
+use tracing::warn;
+
+use crate::bm25::Bm25;
+use crate::docstore::DocStore;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::quota::DiskQuota;
+use crate::replication::ReplicaSource;
+use crate::roots::{validate_roots, SearchRoot};
+use crate::engine::{restore_catalog, Shared};
+use crate::http::HttpEndpoint;
+use crate::ids::IdStrategy;
+use crate::indexlock::LOCK_FILE;
+use crate::options::{Collection, SearchLimits};
+use crate::partition::Partitioning;
+use crate::analysis::{AnalysisConfig, AnalysisRule, Analyzers};
+use crate::stem::StemLanguage;
+use crate::stopwords::StopWordList;
+use crate::synonyms::load_synonyms;
+use crate::pins::load_pins;
+use crate::storage::{LocalStorage, MemoryStorage, StorageBackend};
+use crate::analyzer::Analyzer;
+use crate::embedder::Embedder;
+use crate::vector::VectorStore;
+use crate::postings::PostingsStore;
+use crate::SearchEngine;
+
+pub const DEFAULT_EXTENSIONS: &[&str] = &["txt"];
+// Documents a search reads and matches at once unless `parallelism` says otherwise
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+// Configures and opens a `SearchEngine`. Every option has a default, so
+// `SearchEngine::builder(dir).build()` behaves like `SearchEngine::new(dir)`.
+#[derive(Debug, Clone)]
+pub struct SearchEngineBuilder {
+    directory: PathBuf,
+    extensions: Vec<String>,
+    create_directory: bool,
+    quota: Option<DiskQuota>,
+    default_ttl: Option<Duration>,
+    trash_retention: Option<Duration>,
+    query_log: bool,
+    slow_query_threshold: Option<Duration>,
+    usage_stats: bool,
+    actor: String,
+    storage: Arc<dyn StorageBackend>,
+    id_strategy: IdStrategy,
+    embedder: Option<Arc<dyn Embedder>>,
+    partitioning: Option<Partitioning>,
+    read_only: bool,
+    bm25: Bm25,
+    fold_accents: bool,
+    stemming: bool,
+    stem_language: StemLanguage,
+    ngrams: Option<usize>,
+    stopwords: StopWordList,
+    analysis_rules: Vec<AnalysisRule>,
+    analyzer: Option<Arc<dyn Analyzer>>,
+    synonyms: Option<PathBuf>,
+    pins: Option<PathBuf>,
+    limits: SearchLimits,
+    replica: Option<ReplicaSource>,
+    collections: BTreeMap<String, Collection>,
+    roots: Vec<SearchRoot>,
+    parallelism: usize,
+    postings_cache: usize,
+}
+
+impl SearchEngineBuilder {
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        SearchEngineBuilder {
+            directory: directory.as_ref().to_path_buf(),
+            extensions: DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            create_directory: true,
+            quota: None,
+            default_ttl: None,
+            trash_retention: Some(Duration::days(7)),
+            query_log: true,
+            slow_query_threshold: None,
+            usage_stats: false,
+            actor: "cli".to_string(),
+            storage: Arc::new(LocalStorage),
+            id_strategy: IdStrategy::default(),
+            embedder: None,
+            partitioning: None,
+            read_only: false,
+            bm25: Bm25::default(),
+            fold_accents: false,
+            stemming: false,
+            stem_language: StemLanguage::default(),
+            ngrams: None,
+            stopwords: StopWordList::default(),
+            analysis_rules: Vec::new(),
+            analyzer: None,
+            synonyms: None,
+            pins: None,
+            limits: SearchLimits::default(),
+            replica: None,
+            collections: BTreeMap::new(),
+            roots: Vec::new(),
+            parallelism: DEFAULT_PARALLELISM,
+            postings_cache: 0,
+        }
+    }
+
+    // File extensions (without the dot, case-insensitive) treated as documents; defaults to txt
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(|ext| ext.into().to_lowercase()).collect();
+        self
+    }
+
+    // Create the search directory if it is missing (the default) instead of failing
+    pub fn create_directory(mut self, create: bool) -> Self {
+        self.create_directory = create;
+        self
+    }
+
+    pub fn quota(mut self, quota: Option<DiskQuota>) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    // Lifetime for documents indexed without an explicit TTL, and for documents
+    // found on disk by a rescan (measured from their modification time)
+    pub fn default_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    // How long deleted documents stay in the trash; None deletes them permanently
+    pub fn trash_retention(mut self, retention: Option<Duration>) -> Self {
+        self.trash_retention = retention;
+        self
+    }
+
+    // Record searches for `query_analytics`; on by default
+    pub fn query_log(mut self, enabled: bool) -> Self {
+        self.query_log = enabled;
+        self
+    }
+
+    // Record searches taking longer than this in the slow query log, with their options and
+    // a profile of where the time went; off by default. Searches are profiled while it's on.
+    pub fn slow_query_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_query_threshold = threshold;
+        self
+    }
+
+    // Keep local aggregate usage counters; off by default
+    pub fn usage_stats(mut self, enabled: bool) -> Self {
+        self.usage_stats = enabled;
+        self
+    }
+
+    // Identity recorded in the audit log
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    // Where document content is read from and written to; defaults to files in the search directory
+    pub fn storage(mut self, storage: impl StorageBackend + 'static) -> Self {
+        self.storage = Arc::new(storage);
+        self
+    }
+
+    // Keeps document content in memory rather than in files, as `storage(MemoryStorage::new())`
+    // does; the index is still written under the search directory
+    pub fn in_memory(self) -> Self {
+        self.storage(MemoryStorage::new())
+    }
+
+    // How indexed documents get their IDs; defaults to random UUIDs
+    pub fn id_strategy(mut self, strategy: IdStrategy) -> Self {
+        self.id_strategy = strategy;
+        self
+    }
+
+    // Chunks and embeds documents at index time, enabling `semantic_search`
+    pub fn embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    // Store newly indexed documents in daily or weekly partition directories, by the date
+    // given with `DocumentInput::date` or else found in the document; off by default
+    pub fn partitioning(mut self, partitioning: Option<Partitioning>) -> Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    // BM25 `k1` and `b`; defaults to 1.2 and 0.75
+    pub fn bm25(mut self, bm25: Bm25) -> Self {
+        self.bm25 = bm25;
+        self
+    }
+
+    // Folds accents and compatibility characters in indexed text and queries, so "cafe"
+    // matches "Café". Postings tables built with the other setting are rebuilt when next searched.
+    pub fn fold_accents(mut self, enabled: bool) -> Self {
+        self.fold_accents = enabled;
+        self
+    }
+
+    // Lets query terms match other forms of the same word, such as "runs" and "ran" for
+    // "running". Postings tables built with the other setting are rebuilt when next searched.
+    pub fn stemming(mut self, enabled: bool) -> Self {
+        self.stemming = enabled;
+        self
+    }
+
+    // Language words are stemmed in with stemming on, English by default. Postings tables
+    // stemmed in another language are rebuilt when next searched.
+    pub fn stem_language(mut self, language: StemLanguage) -> Self {
+        self.stem_language = language;
+        self
+    }
+
+    // Lists the lines of every run of `size` characters (2 or 3) in postings tables, so parts
+    // of words and words in Chinese and Japanese text are looked up without checking every
+    // term. Postings tables built with another size are rebuilt when next searched.
+    pub fn ngrams(mut self, size: Option<usize>) -> Self {
+        self.ngrams = size;
+        self
+    }
+
+    // Words left out of postings tables and relevance scores; defaults to English. A file is
+    // read when the engine is built and again by `SearchEngine::reload_stopwords`.
+    pub fn stopwords(mut self, list: StopWordList) -> Self {
+        self.stopwords = list;
+        self
+    }
+
+    // Overrides stemming and stop words for documents matching a rule's pattern, the first
+    // matching rule winning; other documents keep the engine's settings
+    pub fn analysis_rules(mut self, rules: Vec<AnalysisRule>) -> Self {
+        self.analysis_rules = rules;
+        self
+    }
+
+    // Splits every document and query term into terms, for tokenization such as splitting
+    // camelCase identifiers. Postings tables built by an analyzer of another name, or none,
+    // are rebuilt when next searched.
+    pub fn analyzer(mut self, analyzer: impl Analyzer + 'static) -> Self {
+        self.analyzer = Some(Arc::new(analyzer));
+        self
+    }
+
+    // A file of synonyms to expand query terms with, such as `error => failure, fault`; read
+    // when the engine is built and again by `SearchEngine::reload_synonyms`
+    pub fn synonyms(mut self, path: Option<PathBuf>) -> Self {
+        self.synonyms = path;
+        self
+    }
+
+    // A JSON file of queries and the documents whose matches rank first for them, such as
+    // `{"annual report": ["reports/2024.txt"]}`; read when the engine is built and again by
+    // `SearchEngine::reload_pins`
+    pub fn pins(mut self, path: Option<PathBuf>) -> Self {
+        self.pins = path;
+        self
+    }
+
+    // Caps every search; `SearchOptions::limits` can tighten them for one search but not lift them
+    pub fn limits(mut self, limits: SearchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Opens the search directory without writing anything under it: the catalog and derived
+    // tables are kept in memory, nothing is logged, and every call that would change
+    // documents, tags, alerts or readers fails with ReadOnly
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    // Makes the engine a read-only replica that changes only through `sync_replica`
+    pub fn replica_of(mut self, source: Option<ReplicaSource>) -> Self {
+        self.replica = source;
+        self
+    }
+
+    // Named search defaults, looked up with `SearchEngine::collection`
+    pub fn collections(mut self, collections: BTreeMap<String, Collection>) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    // Directories searched in place alongside the search directory, each under its own name
+    pub fn roots(mut self, roots: Vec<SearchRoot>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    // How many documents a search reads and matches at once; 4 by default. Reading is what
+    // overlaps, so more helps most on slow disks and remote storage.
+    pub fn parallelism(mut self, documents: usize) -> Self {
+        self.parallelism = documents;
+        self
+    }
+
+    // How many documents' postings tables searches keep in memory, the most recently used
+    // first, rather than reading them from `.index` each time; none by default
+    pub fn postings_cache(mut self, tables: usize) -> Self {
+        self.postings_cache = tables;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(SearchEngineError::InvalidConfig(message));
+
+        if self.directory.as_os_str().is_empty() {
+            return invalid("search directory must not be empty".to_string());
+        }
+        if self.extensions.is_empty() {
+            return invalid("at least one document extension is required".to_string());
+        }
+        if let Some(ext) = self.extensions.iter().find(|ext| ext.is_empty() || ext.contains(['.', '/', '\\', '*'])) {
+            return invalid(format!("extension {:?} must be a bare name such as \"txt\"", ext));
+        }
+        if self.parallelism == 0 {
+            return invalid("parallelism must be greater than zero".to_string());
+        }
+        if self.quota.map(|quota| quota.max_bytes == 0).unwrap_or(false) {
+            return invalid("quota max_bytes must be greater than zero".to_string());
+        }
+        for (name, duration) in [("default TTL", self.default_ttl), ("trash retention", self.trash_retention)] {
+            if duration.map(|duration| duration <= Duration::zero()).unwrap_or(false) {
+                return invalid(format!("{} must be positive", name));
+            }
+        }
+        if self.embedder.as_ref().map(|embedder| embedder.dimensions() == 0).unwrap_or(false) {
+            return invalid("embedder dimensions must be greater than zero".to_string());
+        }
+        self.bm25.validate()?;
+        self.limits.validate().map_err(|e| match e {
+            SearchEngineError::InvalidInput(message) => SearchEngineError::InvalidConfig(format!("limits: {}", message)),
+            e => e,
+        })?;
+        if let Some(source) = &self.replica {
+            HttpEndpoint::parse(&source.primary)?;
+            if source.token.trim().is_empty() {
+                return invalid("replica_of needs the primary's replication token".to_string());
+            }
+        }
+        for (name, collection) in &self.collections {
+            if name.trim().is_empty() {
+                return invalid("collection names must not be empty".to_string());
+            }
+            if collection.max_per_file == Some(0) {
+                return invalid(format!("collection {:?} max_per_file must be greater than zero", name));
+            }
+            if let Some((prefix, _)) = collection.boosts.iter().find(|(_, boost)| !boost.is_finite() || **boost <= 0.0) {
+                return invalid(format!("collection {:?} boost for {:?} must be a positive number", name, prefix));
+            }
+        }
+        validate_roots(&self.roots, &self.directory)
+    }
+
+    pub async fn build(self) -> Result<SearchEngine> {
+        self.validate()?;
+        let search_path = self.directory;
+
+        match async_fs::metadata(&search_path).await {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(SearchEngineError::InvalidConfig(format!("{:?} is not a directory", search_path)));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && self.create_directory && !self.read_only => {
+                async_fs::create_dir_all(&search_path).await
+                    .context("Failed to create search directory")?;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to open search directory {:?}", search_path)),
+        }
+        // Paths under a root's name would be taken for the root's documents
+        for root in &self.roots {
+            if async_fs::metadata(search_path.join(root.name())).await.is_ok() {
+                return Err(SearchEngineError::InvalidConfig(format!(
+                    "root {:?} has the same name as an entry in the search directory", root.name()
+                )));
+            }
+        }
+
+        let analysis = AnalysisConfig {
+            fold_accents: self.fold_accents,
+            stemming: self.stemming,
+            stem_language: self.stem_language,
+            ngrams: self.ngrams,
+            stopwords: self.stopwords,
+            rules: self.analysis_rules,
+            analyzer: self.analyzer,
+        };
+        analysis.validate()?;
+        let analyzers = Arc::new(RwLock::new(Arc::new(Analyzers::load(&analysis, &search_path, &self.roots).await?)));
+        let synonyms = Arc::new(RwLock::new(Arc::new(load_synonyms(self.synonyms.as_deref()).await?)));
+        let pins = Arc::new(RwLock::new(Arc::new(load_pins(self.pins.as_deref()).await?)));
+
+        // Only the small index metadata is read here; walking the tree is deferred to first use
+        let doc_store = DocStore::new(&search_path, self.storage.clone()).read_only(self.read_only);
+        let vector_store = VectorStore::new(&search_path, doc_store.index_dir(), self.storage.clone())
+            .read_only(self.read_only);
+        let postings_store = PostingsStore::new(&search_path, doc_store.index_dir(), self.storage.clone())
+            .read_only(self.read_only)
+            .analyzers(analyzers.clone())
+            .cache_size(self.postings_cache);
+        // The revision is read first, so a catalog saved in between is reloaded, not missed
+        let revision = async_fs::read_to_string(doc_store.index_dir().join(LOCK_FILE)).await.ok()
+            .and_then(|revision| revision.trim().parse().ok());
+        let catalog = match doc_store.load_meta().await {
+            Ok(Some(meta)) => Some(restore_catalog(&search_path, &self.roots, meta)),
+            Ok(None) => None,
+            Err(e) => {
+                warn!(error = %e, "Ignoring unreadable index metadata");
+                None
+            }
+        };
+
+        Ok(SearchEngine {
+            search_path,
+            shared: Arc::new(Shared::new(catalog, revision)),
+            doc_store,
+            extensions: self.extensions,
+            quota: self.quota,
+            default_ttl: self.default_ttl,
+            query_log: self.query_log && !self.read_only,
+            slow_query_threshold: self.slow_query_threshold.filter(|_| !self.read_only),
+            trash_retention: self.trash_retention,
+            usage_stats: self.usage_stats && !self.read_only,
+            actor: self.actor,
+            storage: self.storage,
+            id_strategy: self.id_strategy,
+            embedder: self.embedder,
+            vector_store,
+            postings_store,
+            partitioning: self.partitioning,
+            read_only: self.read_only,
+            bm25: self.bm25,
+            analyzers,
+            analysis,
+            synonyms,
+            synonyms_file: self.synonyms,
+            pins,
+            pins_file: self.pins,
+            limits: self.limits,
+            replica: self.replica,
+            collections: self.collections,
+            roots: self.roots,
+            parallelism: self.parallelism,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::SearchOptions;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+
+    // Indexes, searches, reads and deletes documents, as every backend must support
+    async fn exercise(engine: SearchEngine) {
+        index(&engine, vec![
+            DocumentInput::from_bytes("notes/a.txt", "the quick brown fox\njumps over\n"),
+            DocumentInput::from_bytes("b.txt", "a lazy dog\n"),
+        ]).await;
+        let response = engine.search("fox", &SearchOptions::default()).await.unwrap();
+        assert_eq!(response.total, 1);
+        assert_eq!(engine.relative_path(Path::new(&*response.results[0].path)), "notes/a.txt");
+        let line = engine.get_line("notes/a.txt", 2, None).await.unwrap().unwrap();
+        assert_eq!(line.content, "jumps over");
+        assert_eq!(engine.catalog().await.unwrap().documents.len(), 2);
+
+        engine.delete_document("notes/a.txt").await.unwrap();
+        assert_eq!(engine.search("fox", &SearchOptions::default()).await.unwrap().total, 0);
+        assert_eq!(engine.search("dog", &SearchOptions::default()).await.unwrap().total, 1);
+    }
+
+    #[test]
+    fn engines_work_on_disk() {
+        let dir = TestDir::new("builder-disk");
+        block_on(async {
+            exercise(SearchEngine::builder(dir.path()).build().await.unwrap()).await;
+            assert!(dir.path().join("b.txt").is_file());
+        });
+    }
+
+    #[test]
+    fn engines_work_in_memory() {
+        let dir = TestDir::new("builder-memory");
+        block_on(async {
+            let storage = MemoryStorage::new();
+            exercise(SearchEngine::builder(dir.path()).storage(storage.clone()).build().await.unwrap()).await;
+            assert!(!dir.path().join("b.txt").exists());
+            // Another engine sharing the storage finds the documents
+            let reopened = SearchEngine::builder(dir.path()).storage(storage).build().await.unwrap();
+            assert_eq!(reopened.search("dog", &SearchOptions::default()).await.unwrap().total, 1);
+        });
+    }
+
+    #[test]
+    fn parallel_searches_find_what_one_at_a_time_does() {
+        let dir = TestDir::new("builder-parallelism");
+        block_on(async {
+            let documents = (0..20).map(|n| DocumentInput::from_bytes(format!("{}.txt", n), format!("line {} of many\nmany more\n", n))).collect();
+            index(&SearchEngine::builder(dir.path()).build().await.unwrap(), documents).await;
+            let options = SearchOptions { limit: 100, ..Default::default() };
+            let mut found = Vec::new();
+            for parallelism in [1, 3, 8] {
+                let engine = SearchEngine::builder(dir.path()).parallelism(parallelism).build().await.unwrap();
+                let response = engine.search("many", &options).await.unwrap();
+                found.push(response.results.iter().map(|result| (result.path.clone(), result.line_number)).collect::<Vec<_>>());
+            }
+            assert_eq!(found[0].len(), 40);
+            assert!(found.iter().all(|results| *results == found[0]));
+            assert!(SearchEngine::builder(dir.path()).parallelism(0).build().await.is_err());
+        });
+    }
+
+    #[test]
+    fn cached_postings_tables_are_not_read_again() {
+        let dir = TestDir::new("builder-cache");
+        block_on(async {
+            let engine = SearchEngine::builder(dir.path()).postings_cache(4).build().await.unwrap();
+            index(&engine, vec![DocumentInput::from_bytes("a.txt", "cached words\n")]).await;
+            assert_eq!(engine.search("cached", &SearchOptions::default()).await.unwrap().total, 1);
+            let table = engine.postings_store.postings_path(&dir.path().join("a.txt").canonicalize().unwrap());
+            std::fs::remove_file(&table).unwrap();
+            // Served from memory, so the missing table isn't rebuilt
+            assert_eq!(engine.search("cached", &SearchOptions::default()).await.unwrap().total, 1);
+            assert!(!table.exists());
+        });
+    }
+}
@@ -0,0 +1,267 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::metadata::Metadata;
+
+// Running totals kept up to date by every index/delete instead of being recomputed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexCounters {
+    pub total_documents: usize,
+    pub total_bytes: u64,
+    pub per_extension: BTreeMap<String, usize>,
+    // Set when an incremental update could not be applied cleanly; cleared by a full rescan
+    pub dirty: bool,
+    pub last_verified: DateTime<Utc>,
+}
+
+impl IndexCounters {
+    fn add(&mut self, entry: &CatalogEntry) {
+        self.total_documents += 1;
+        self.total_bytes += entry.size;
+        *self.per_extension.entry(extension_of(&entry.path)).or_insert(0) += 1;
+    }
+
+    fn subtract(&mut self, entry: &CatalogEntry) {
+        self.total_documents = self.total_documents.saturating_sub(1);
+        self.total_bytes = self.total_bytes.saturating_sub(entry.size);
+
+        let extension = extension_of(&entry.path);
+        if let Some(count) = self.per_extension.get_mut(&extension) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_extension.remove(&extension);
+            }
+        }
+    }
+}
+
+// What happened to a document, as reported by the change feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Updated,
+    Deleted,
+}
+
+// A change to the catalog not yet written to the change log
+#[derive(Debug, Clone)]
+pub(crate) struct PendingChange {
+    pub(crate) generation: u64,
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) kind: ChangeKind,
+    pub(crate) path: PathBuf,
+    pub(crate) document_id: Option<String>,
+}
+
+// The set of documents the engine searches over, loaded lazily on first use
+#[derive(Clone)]
+pub struct Catalog {
+    pub documents: Vec<CatalogEntry>,
+    pub counters: IndexCounters,
+    pub last_scanned: DateTime<Utc>,
+    // Goes up by one with every document added, updated or deleted, and is persisted with
+    // the catalog, so it never goes back
+    pub generation: u64,
+    // Changes made since the change log was last written
+    pub(crate) pending: Vec<PendingChange>,
+}
+
+#[derive(Clone)]
+pub struct CatalogEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub attributes: DocumentAttributes,
+}
+
+// Per-document settings supplied at index time rather than derived from the file,
+// so they have to survive a rescan of the search directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    // When the current content was indexed, explicitly or by the rescan that found it new or
+    // changed; unset in catalogs written before rescans recorded it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indexed_at: Option<DateTime<Utc>>,
+    // Assigned according to the engine's `IdStrategy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+    // SimHash of the content, for near-duplicate detection
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "crate::dedup::hex_fingerprint")]
+    pub fingerprint: Option<u64>,
+    // Most significant phrases of the content, best first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    // ISO 639-1 code detected from the content, or "und" if it couldn't be told; unset for
+    // documents not analyzed since detection was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // Lines holding a page break, for documents with pages such as PDFs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub page_breaks: Vec<usize>,
+    // Declared by the document, such as an HTML page's <title>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // Assigned with `add_tags`, sorted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // Application fields given at index time
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: Metadata,
+    // Principals that may see the document, given at index time; empty for everyone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acl: Vec<String>,
+}
+
+impl Catalog {
+    // Builds a catalog from a full scan, recomputing (and so verifying) every counter
+    pub fn scanned(documents: Vec<CatalogEntry>, last_scanned: DateTime<Utc>) -> Self {
+        let mut counters = IndexCounters {
+            total_documents: 0,
+            total_bytes: 0,
+            per_extension: BTreeMap::new(),
+            dirty: false,
+            last_verified: last_scanned,
+        };
+        for entry in &documents {
+            counters.add(entry);
+        }
+
+        Catalog {
+            documents,
+            counters,
+            last_scanned,
+            generation: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    // Restores a catalog from persisted metadata, trusting the stored counters
+    pub fn restored(documents: Vec<CatalogEntry>, counters: IndexCounters, last_scanned: DateTime<Utc>, generation: u64) -> Self {
+        Catalog {
+            documents,
+            counters,
+            last_scanned,
+            generation,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&CatalogEntry> {
+        self.documents.iter().find(|doc| doc.path == path)
+    }
+
+    // Adds a document, replacing any existing entry for the same path
+    pub fn insert(&mut self, entry: CatalogEntry) {
+        let kind = match self.take(&entry.path) {
+            Some(_) => ChangeKind::Updated,
+            None => ChangeKind::Added,
+        };
+        self.record(kind, &entry.path, entry.attributes.document_id.clone());
+        self.counters.add(&entry);
+        self.documents.push(entry);
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<CatalogEntry> {
+        let entry = self.take(path)?;
+        self.record(ChangeKind::Deleted, path, entry.attributes.document_id.clone());
+        Some(entry)
+    }
+
+    fn take(&mut self, path: &Path) -> Option<CatalogEntry> {
+        let position = self.documents.iter().position(|doc| doc.path == path)?;
+        let entry = self.documents.remove(position);
+        self.counters.subtract(&entry);
+        Some(entry)
+    }
+
+    // Moves to the next generation for a change to the document at `path`
+    pub(crate) fn record(&mut self, kind: ChangeKind, path: &Path, document_id: Option<String>) {
+        self.generation += 1;
+        self.pending.push(PendingChange {
+            generation: self.generation,
+            timestamp: Utc::now(),
+            kind,
+            path: path.to_path_buf(),
+            document_id,
+        });
+    }
+
+    // Continues from `generation` after a rescan, recording what differs from the documents
+    // known before it. Documents whose size and modification time are unchanged count as unchanged.
+    pub(crate) fn record_rescan(&mut self, previous: &[CatalogEntry], generation: u64) {
+        self.generation = generation;
+        let before: HashMap<&Path, &CatalogEntry> = previous.iter().map(|doc| (doc.path.as_path(), doc)).collect();
+        let mut changes = Vec::new();
+        for doc in &self.documents {
+            match before.get(doc.path.as_path()) {
+                Some(old) if old.size == doc.size && old.modified == doc.modified => {}
+                Some(_) => changes.push((ChangeKind::Updated, doc.path.clone(), doc.attributes.document_id.clone())),
+                None => changes.push((ChangeKind::Added, doc.path.clone(), doc.attributes.document_id.clone())),
+            }
+        }
+        let now: HashSet<&Path> = self.documents.iter().map(|doc| doc.path.as_path()).collect();
+        for old in previous.iter().filter(|old| !now.contains(old.path.as_path())) {
+            changes.push((ChangeKind::Deleted, old.path.clone(), old.attributes.document_id.clone()));
+        }
+        for (kind, path, document_id) in changes {
+            self.record(kind, &path, document_id);
+        }
+    }
+
+    // Documents a rescan found new or changed, compared to `previous`, count as indexed when
+    // it ran
+    pub(crate) fn stamp_rescanned(&mut self, previous: &[CatalogEntry]) {
+        let before: HashMap<&Path, &CatalogEntry> = previous.iter().map(|doc| (doc.path.as_path(), doc)).collect();
+        for doc in &mut self.documents {
+            let unchanged = before.get(doc.path.as_path())
+                .is_some_and(|old| old.size == doc.size && old.modified == doc.modified);
+            if !unchanged {
+                doc.attributes.indexed_at = Some(self.last_scanned);
+            }
+        }
+    }
+
+    // Carries index-time attributes over from a previous catalog after a rescan
+    pub fn inherit_attributes(&mut self, previous: &Catalog) {
+        let previous: HashMap<&Path, &DocumentAttributes> = previous.documents.iter()
+            .map(|doc| (doc.path.as_path(), &doc.attributes))
+            .collect();
+        for doc in &mut self.documents {
+            if let Some(attributes) = previous.get(doc.path.as_path()) {
+                doc.attributes = (*attributes).clone();
+            }
+        }
+    }
+
+    // Applies the changes `ours` made but hasn't committed on top of this catalog, which
+    // another process saved since `ours` was loaded; each is recorded again as a change here
+    pub(crate) fn rebase(&mut self, ours: &Catalog) {
+        let mut applied = HashSet::new();
+        for change in &ours.pending {
+            if !applied.insert(change.path.as_path()) {
+                continue;
+            }
+            match ours.get(&change.path) {
+                Some(entry) => self.insert(entry.clone()),
+                None => {
+                    self.remove(&change.path);
+                }
+            }
+        }
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.counters.dirty = true;
+    }
+}
+
+pub fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::warn;
+
+use crate::catalog::{ChangeKind, PendingChange};
+use crate::error::{Context, Result, SearchEngineError};
+use crate::SearchEngine;
+
+// Append-only log of document changes, one JSON line per generation
+pub const CHANGES_FILE: &str = "changes.log";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DocumentChange {
+    // The index generation this change produced
+    pub generation: u64,
+    pub timestamp: DateTime<Utc>,
+    pub kind: ChangeKind,
+    // Relative to the search directory
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+}
+
+// Changes after some generation, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ChangeFeed {
+    // The index generation now
+    pub generation: u64,
+    pub changes: Vec<DocumentChange>,
+    // More changes follow the last one returned; ask again from its generation
+    pub has_more: bool,
+    // Some changes since the requested generation are missing from the log, for instance
+    // because it was deleted, so the caller should reload everything instead
+    pub resync: bool,
+}
+
+impl SearchEngine {
+    fn changes_path(&self) -> std::path::PathBuf {
+        self.doc_store.index_dir().join(CHANGES_FILE)
+    }
+
+    fn document_change(&self, change: &PendingChange) -> DocumentChange {
+        DocumentChange {
+            generation: change.generation,
+            timestamp: change.timestamp,
+            kind: change.kind,
+            path: self.relative_path(&change.path),
+            document_id: change.document_id.clone(),
+        }
+    }
+
+    // Appends changes to the log; failures are only logged, as the changes have already happened
+    pub(crate) async fn log_changes(&self, changes: Vec<PendingChange>) {
+        if self.read_only || changes.is_empty() {
+            return;
+        }
+        if let Err(e) = self.append_changes(&changes).await {
+            warn!(error = %e, count = changes.len(), "Failed to write change log");
+        }
+    }
+
+    async fn append_changes(&self, changes: &[PendingChange]) -> Result<()> {
+        let mut lines = Vec::new();
+        for change in changes {
+            lines.extend(serde_json::to_vec(&self.document_change(change))?);
+            lines.push(b'\n');
+        }
+
+        async_fs::create_dir_all(self.doc_store.index_dir()).await?;
+        let mut file = async_fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.changes_path())
+            .await
+            .context("Failed to open change log")?;
+        file.write_all(&lines).await?;
+        Ok(())
+    }
+
+    async fn read_changes(&self, since: u64) -> Result<Vec<DocumentChange>> {
+        let file = match async_fs::File::open(self.changes_path()).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to open change log"),
+        };
+
+        let mut changes = Vec::new();
+        let mut lines = BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str::<DocumentChange>(&line) {
+                Ok(change) if change.generation > since => changes.push(change),
+                Ok(_) => {}
+                Err(e) => warn!(error = %e, "Skipping unreadable change log line"),
+            }
+        }
+        Ok(changes)
+    }
+
+    // Latest generation in the change log, for a catalog rebuilt without its metadata
+    pub(crate) async fn logged_generation(&self) -> u64 {
+        match self.read_changes(0).await {
+            Ok(changes) => changes.iter().map(|change| change.generation).max().unwrap_or(0),
+            Err(e) => {
+                warn!(error = %e, "Failed to read change log");
+                0
+            }
+        }
+    }
+
+    // The index generation, which goes up by one with every document added, updated or deleted
+    pub async fn generation(&self) -> Result<u64> {
+        Ok(self.catalog().await?.generation)
+    }
+
+    // Documents added, updated or deleted after generation `since`, at most `limit` of them
+    pub async fn changes_since(&self, since: u64, limit: Option<usize>) -> Result<ChangeFeed> {
+        let catalog = self.catalog().await?;
+        if since > catalog.generation {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "generation {} is ahead of the index, which is at {}", since, catalog.generation
+            )));
+        }
+        // Changes not yet written to the log come from the catalog itself
+        let changes: BTreeMap<u64, DocumentChange> = self.read_changes(since).await?.into_iter()
+            .chain(catalog.pending.iter().filter(|change| change.generation > since).map(|change| self.document_change(change)))
+            .filter(|change| change.generation <= catalog.generation)
+            .map(|change| (change.generation, change))
+            .collect();
+
+        let resync = changes.len() as u64 != catalog.generation - since;
+        let limit = limit.unwrap_or(usize::MAX);
+        let has_more = changes.len() > limit;
+        let changes = changes.into_values().take(limit).collect();
+        Ok(ChangeFeed { generation: catalog.generation, changes, has_more, resync })
+    }
+}
@@ -0,0 +1,78 @@
+// CRC-32 checksums on the files under `.index`, so a table or catalog damaged on disk is
+// caught when it is read instead of giving wrong results. Binary tables end with the CRC-32
+// (IEEE, computed by crc32fast) of everything before it; JSON files carry it as a "checksum"
+// field over the rest of the object, and files written before checksums existed are read
+// without one.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::{Result, SearchEngineError};
+
+pub(crate) const CHECKSUM_LEN: usize = 4;
+const CHECKSUM_FIELD: &str = "checksum";
+
+// Appends the checksum of `bytes` to them
+pub(crate) fn seal(bytes: &mut Vec<u8>) {
+    let crc = crc32fast::hash(bytes);
+    bytes.extend_from_slice(&crc.to_le_bytes());
+}
+
+// `bytes` without their checksum, if it matches; `what` names the file in the error
+pub(crate) fn unseal<'a>(bytes: &'a [u8], what: &str) -> Result<&'a [u8]> {
+    let Some(at) = bytes.len().checked_sub(CHECKSUM_LEN) else {
+        return Err(SearchEngineError::IndexCorrupted(format!("Truncated {}", what)));
+    };
+    let (payload, stored) = bytes.split_at(at);
+    if crc32fast::hash(payload).to_le_bytes() != stored {
+        return Err(SearchEngineError::IndexCorrupted(format!("Checksum mismatch in {}", what)));
+    }
+    Ok(payload)
+}
+
+// JSON for `value` with a checksum field, for a value serializing to an object
+pub(crate) fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut value = serde_json::to_value(value)?;
+    if let Value::Object(map) = &mut value {
+        let crc = crc32fast::hash(&serde_json::to_vec(map)?);
+        map.insert(CHECKSUM_FIELD.to_string(), Value::String(format!("{:08x}", crc)));
+    }
+    Ok(serde_json::to_vec(&value)?)
+}
+
+// Parses JSON written by `to_json`, checking its checksum if it has one
+pub(crate) fn from_json<T: DeserializeOwned>(bytes: &[u8], what: &str) -> Result<T> {
+    let unreadable = |e: serde_json::Error| SearchEngineError::IndexCorrupted(format!("Unreadable {}: {}", what, e));
+    let mut value: Value = serde_json::from_slice(bytes).map_err(unreadable)?;
+    if let Value::Object(map) = &mut value {
+        if let Some(stored) = map.remove(CHECKSUM_FIELD) {
+            let crc = crc32fast::hash(&serde_json::to_vec(map)?);
+            if stored.as_str() != Some(format!("{:08x}", crc).as_str()) {
+                return Err(SearchEngineError::IndexCorrupted(format!("Checksum mismatch in {}", what)));
+            }
+        }
+    }
+    serde_json::from_value(value).map_err(unreadable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damaged_files_fail_their_checksum() {
+        let mut bytes = b"123456789".to_vec();
+        seal(&mut bytes);
+        assert_eq!(bytes[9..], 0xcbf43926u32.to_le_bytes());
+        assert_eq!(unseal(&bytes, "table").unwrap(), b"123456789");
+        bytes[0] = b'0';
+        assert!(matches!(unseal(&bytes, "table"), Err(SearchEngineError::IndexCorrupted(_))));
+
+        let json = to_json(&serde_json::json!({"documents": 3})).unwrap();
+        assert_eq!(from_json::<Value>(&json, "catalog").unwrap(), serde_json::json!({"documents": 3}));
+        let damaged = String::from_utf8(json).unwrap().replace('3', "4");
+        assert!(from_json::<Value>(damaged.as_bytes(), "catalog").is_err());
+        assert!(from_json::<Value>(br#"{"documents": 3}"#, "catalog").is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+// Groups search results into topics with k-means over TF-IDF term vectors of their content.
+// Pure computation like `matching`, so it builds for wasm32 as well.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::keywords::is_stop_word;
+
+// More clusters than this would be too fine-grained to present as themes
+pub const MAX_CLUSTERS: usize = 20;
+const MAX_ITERATIONS: usize = 20;
+// Terms listed for each cluster; the first few form its label
+const CLUSTER_TERMS: usize = 5;
+const LABEL_TERMS: usize = 3;
+// Label of the cluster holding results with no informative terms
+const OTHER_LABEL: &str = "other";
+
+// A group of results about one theme
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ResultCluster {
+    // Top terms joined with ", ", e.g. "timeout, connection, retry"
+    pub label: String,
+    // Most characteristic terms, strongest first
+    pub terms: Vec<String>,
+    // IDs of the member results, in ranking order
+    pub result_ids: Vec<String>,
+}
+
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| !is_stop_word(word))
+}
+
+fn dot(vector: &[(usize, f32)], centroid: &[f32]) -> f32 {
+    vector.iter().map(|(term, weight)| weight * centroid[*term]).sum()
+}
+
+// Clusters `(id, content)` pairs, given in ranking order, into at most `k` groups.
+// Query terms occur in every result, so they are left out of the term vectors.
+pub fn cluster_results<'a>(results: impl IntoIterator<Item = (&'a str, &'a str)>, query: &str, k: usize) -> Vec<ResultCluster> {
+    let results: Vec<(&str, &str)> = results.into_iter().collect();
+    let k = k.min(MAX_CLUSTERS).min(results.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    let query_terms: HashSet<String> = terms(query).collect();
+
+    // Term frequencies per result, and how many results each term occurs in
+    let counts: Vec<HashMap<String, usize>> = results.iter()
+        .map(|(_, content)| {
+            let mut counts = HashMap::new();
+            for term in terms(content).filter(|term| !query_terms.contains(term)) {
+                *counts.entry(term).or_insert(0) += 1;
+            }
+            counts
+        })
+        .collect();
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for term in counts.iter().flat_map(HashMap::keys) {
+        *document_frequency.entry(term).or_insert(0) += 1;
+    }
+
+    // Terms in a single result can't link it to others, and terms in all of them can't
+    // tell them apart
+    let mut vocabulary: Vec<&str> = document_frequency.iter()
+        .filter(|(_, df)| **df > 1 && **df < results.len())
+        .map(|(term, _)| *term)
+        .collect();
+    vocabulary.sort_unstable();
+    let index: HashMap<&str, usize> = vocabulary.iter().enumerate().map(|(i, term)| (*term, i)).collect();
+
+    // L2-normalized sparse TF-IDF vectors
+    let vectors: Vec<Vec<(usize, f32)>> = counts.iter()
+        .map(|counts| {
+            let mut vector: Vec<(usize, f32)> = counts.iter()
+                .filter_map(|(term, count)| {
+                    let term_index = *index.get(term.as_str())?;
+                    let idf = (results.len() as f32 / document_frequency[term.as_str()] as f32).ln();
+                    Some((term_index, (1.0 + (*count as f32).ln()) * idf))
+                })
+                .collect();
+            let norm = vector.iter().map(|(_, weight)| weight * weight).sum::<f32>().sqrt();
+            for (_, weight) in vector.iter_mut() {
+                *weight /= norm;
+            }
+            vector
+        })
+        .collect();
+
+    // Seed with the best-ranked result, then repeatedly with the result least similar to
+    // every seed so far, which keeps the outcome deterministic
+    let candidates: Vec<usize> = (0..results.len()).filter(|i| !vectors[*i].is_empty()).collect();
+    if candidates.is_empty() {
+        return vec![ResultCluster {
+            label: OTHER_LABEL.to_string(),
+            terms: Vec::new(),
+            result_ids: results.iter().map(|(id, _)| id.to_string()).collect(),
+        }];
+    }
+    let dense = |i: usize| {
+        let mut centroid = vec![0.0; vocabulary.len()];
+        for (term, weight) in &vectors[i] {
+            centroid[*term] = *weight;
+        }
+        centroid
+    };
+    let mut centroids = vec![dense(candidates[0])];
+    while centroids.len() < k.min(candidates.len()) {
+        let farthest = candidates.iter()
+            .map(|i| (*i, centroids.iter().map(|centroid| dot(&vectors[*i], centroid)).fold(f32::MIN, f32::max)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, _)| i)
+            .expect("candidates is not empty");
+        centroids.push(dense(farthest));
+    }
+
+    let mut assignments = vec![usize::MAX; results.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for &i in &candidates {
+            let nearest = centroids.iter().enumerate()
+                .map(|(c, centroid)| (c, dot(&vectors[i], centroid)))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal).then(b.0.cmp(&a.0)))
+                .map(|(c, _)| c)
+                .expect("at least one centroid");
+            changed |= assignments[i] != nearest;
+            assignments[i] = nearest;
+        }
+        if !changed {
+            break;
+        }
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let mut sum = vec![0.0; vocabulary.len()];
+            for &i in candidates.iter().filter(|i| assignments[**i] == c) {
+                for (term, weight) in &vectors[i] {
+                    sum[*term] += weight;
+                }
+            }
+            let norm = sum.iter().map(|weight| weight * weight).sum::<f32>().sqrt();
+            // An emptied cluster keeps its old centroid and may pick members up again
+            if norm > 0.0 {
+                *centroid = sum.into_iter().map(|weight| weight / norm).collect();
+            }
+        }
+    }
+
+    let mut clusters: Vec<(usize, ResultCluster)> = centroids.iter().enumerate()
+        .filter_map(|(c, centroid)| {
+            let members: Vec<usize> = (0..results.len()).filter(|i| assignments[*i] == c).collect();
+            let first = *members.first()?;
+            let mut strongest: Vec<(usize, f32)> = centroid.iter().copied().enumerate()
+                .filter(|(_, weight)| *weight > 0.0)
+                .collect();
+            strongest.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+            let terms: Vec<String> = strongest.iter().take(CLUSTER_TERMS).map(|(term, _)| vocabulary[*term].to_string()).collect();
+            Some((first, ResultCluster {
+                label: terms.iter().take(LABEL_TERMS).cloned().collect::<Vec<_>>().join(", "),
+                terms,
+                result_ids: members.into_iter().map(|i| results[i].0.to_string()).collect(),
+            }))
+        })
+        .collect();
+    // Largest themes first, ties broken by their best-ranked result
+    clusters.sort_by(|a, b| b.1.result_ids.len().cmp(&a.1.result_ids.len()).then(a.0.cmp(&b.0)));
+    let mut clusters: Vec<ResultCluster> = clusters.into_iter().map(|(_, cluster)| cluster).collect();
+
+    let unassigned: Vec<String> = (0..results.len())
+        .filter(|i| assignments[*i] == usize::MAX)
+        .map(|i| results[i].0.to_string())
+        .collect();
+    if !unassigned.is_empty() {
+        clusters.push(ResultCluster {
+            label: OTHER_LABEL.to_string(),
+            terms: Vec::new(),
+            result_ids: unassigned,
+        });
+    }
+    clusters
+}
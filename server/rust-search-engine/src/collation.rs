@@ -0,0 +1,79 @@
+// Locale-aware ordering for results sorted by path or title, chosen per search with a locale
+// such as "de", "sv" or "fr-CA": "Äpfel" sorts next to "Apfel" in German but after "Zucker"
+// in Swedish, and "émile" next to "emile" rather than after every plain letter. The ordering
+// comes from the Unicode Collation Algorithm with CLDR's rules for the locale, falling back to
+// the root order for one without rules of its own. Without the `collation` feature, asking
+// for a locale fails and paths are compared byte by byte.
+
+use std::cmp::Ordering;
+
+use crate::error::{Result, SearchEngineError};
+
+#[cfg(feature = "collation")]
+pub(crate) struct Collation {
+    collator: icu_collator::Collator,
+}
+
+#[cfg(feature = "collation")]
+impl Collation {
+    pub(crate) fn new(locale: &str) -> Result<Self> {
+        let invalid = || SearchEngineError::InvalidInput(format!("collation {:?} is not a locale such as \"de\" or \"sv-SE\"", locale));
+        let parsed: icu_locid::Locale = locale.parse().map_err(|_| invalid())?;
+        let collator = icu_collator::Collator::try_new(&(&parsed).into(), icu_collator::CollatorOptions::new())
+            .map_err(|_| invalid())?;
+        Ok(Collation { collator })
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.collator.compare(a, b)
+    }
+}
+
+#[cfg(not(feature = "collation"))]
+pub(crate) struct Collation;
+
+#[cfg(not(feature = "collation"))]
+impl Collation {
+    pub(crate) fn new(_locale: &str) -> Result<Self> {
+        Err(SearchEngineError::InvalidInput("collation requires the `collation` feature".to_string()))
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+fn components(path: &str) -> std::str::Split<'_, [char; 2]> {
+    path.split(['/', '\\'])
+}
+
+// Last component of a path, which a result's title starts with
+pub(crate) fn file_name(path: &str) -> &str {
+    components(path).next_back().unwrap_or(path)
+}
+
+// Compares paths directory by directory, so a directory's documents stay together whatever
+// the locale does with separators; byte by byte without a collation
+pub(crate) fn compare_paths(a: &str, b: &str, collation: Option<&Collation>) -> Ordering {
+    let Some(collation) = collation else {
+        return a.cmp(b);
+    };
+    let (mut a, mut b) = (components(a), components(b));
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) => match collation.compare(x, y) {
+                Ordering::Equal => continue,
+                unequal => return unequal,
+            },
+            (x, y) => return x.is_some().cmp(&y.is_some()),
+        }
+    }
+}
+
+// Compares file names alone
+pub(crate) fn compare_names(a: &str, b: &str, collation: Option<&Collation>) -> Ordering {
+    match collation {
+        Some(collation) => collation.compare(a, b),
+        None => a.cmp(b),
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::AnalysisRule;
+use crate::bm25::Bm25;
+use crate::builder::{DEFAULT_EXTENSIONS, DEFAULT_PARALLELISM};
+use crate::embedder::EmbedderConfig;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::ids::IdStrategy;
+use crate::logging::LoggingConfig;
+use crate::options::{Collection, SearchLimits};
+use crate::partition::Partitioning;
+use crate::replication::ReplicaSource;
+use crate::roots::SearchRoot;
+use crate::quota::DiskQuota;
+use crate::schedule::ScheduleEntry;
+use crate::stem::StemLanguage;
+use crate::stopwords::StopWordList;
+use crate::trash::DEFAULT_TRASH_RETENTION;
+
+pub const DEFAULT_CONFIG_FILE: &str = "search-engine.json";
+pub const DEFAULT_SEARCH_DIRECTORY: &str = "index";
+const PROFILES_KEY: &str = "profiles";
+
+// Settings read from the JSON config file; every section is optional. A top-level
+// "profiles" object maps names such as "dev" or "prod" to overrides of these settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Used when neither --dir nor SEARCH_DIRECTORY is given
+    pub search_directory: Option<PathBuf>,
+    // File extensions indexed as documents, without the dot
+    pub extensions: Vec<String>,
+    // Maintenance tasks run automatically in server mode
+    pub schedule: Vec<ScheduleEntry>,
+    // Byte limit for the documents, index and trash in the search directory
+    pub quota: Option<DiskQuota>,
+    // Default document lifetime such as "30d"; expired documents are removed by `maintenance expire`
+    pub document_ttl: Option<String>,
+    pub logging: LoggingConfig,
+    // Record every search in the query log used by `analytics`
+    pub query_log: bool,
+    // Searches taking longer than this, e.g. "500ms", are written to `.index/slow.log` with
+    // their options and per-phase timings, for `slow-queries`
+    pub slow_query_threshold: Option<String>,
+    // Opt in to local aggregate usage counters, exported with `usage`
+    pub usage_stats: bool,
+    // How long deleted documents stay in .trash/ before `maintenance purge-trash` removes them;
+    // null deletes documents permanently
+    pub trash_retention: Option<String>,
+    // Run destructive maintenance (clear-all, prune, expire) without --yes
+    pub allow_destructive_maintenance: bool,
+    // How document IDs are assigned: "random-uuid", "path-uuid", "content-hash" or "provided"
+    pub id_strategy: IdStrategy,
+    // Enables semantic search; null leaves it off
+    pub embedder: Option<EmbedderConfig>,
+    // "daily" or "weekly" stores new documents in time partition directories; null leaves them where they are
+    pub partitioning: Option<Partitioning>,
+    // Makes this a read-only replica of {"primary": "http://host:7700", "token": ...}; schedule
+    // the "replicate" maintenance task to keep it in sync
+    pub replica_of: Option<ReplicaSource>,
+    // Never write under the search directory; indexing, deletes, tags, alerts, readers and
+    // maintenance that changes the index fail with READ_ONLY
+    pub read_only: bool,
+    // Directories `serve` may index documents from besides the search directory; paths
+    // outside them, or containing "..", are refused with FORBIDDEN
+    pub allowed_roots: Vec<PathBuf>,
+    // Directories searched in place as part of the corpus, e.g. [{"path": "/var/log/app", "name":
+    // "logs", "include": ["*.log"], "exclude": ["archive/**"]}]; their documents are listed
+    // under the root's name and never deleted or moved
+    pub roots: Vec<SearchRoot>,
+    // BM25 relevance tuning, e.g. {"k1": 1.2, "b": 0.75}: higher k1 lets repeated terms count
+    // for more, lower b penalizes long lines less
+    pub bm25: Bm25,
+    // Fold accents and compatibility characters, so "cafe" matches "Café" and "ﬁ" matches
+    // "fi"; changing it rebuilds each document's postings when it is next searched
+    pub fold_accents: bool,
+    // Match other forms of query words, such as "runs" and "ran" for "running", by their
+    // stems; changing it rebuilds each document's postings when it is next searched
+    pub stemming: bool,
+    // Language words are stemmed in: "english" (the default), "french", "german", "spanish",
+    // "russian" or another of the Snowball stemmers' languages
+    pub stem_language: StemLanguage,
+    // List the lines of every run of 2 or 3 characters of indexed words, e.g. "ngrams": 3, so
+    // parts of words and Chinese or Japanese text are found without checking every word
+    pub ngrams: Option<usize>,
+    // Words left out of postings and relevance scores: "english" (the default), "none", or the
+    // path of a file with one word per line; `serve` rereads the file on "reload-stopwords"
+    pub stopwords: StopWordList,
+    // Stemming and stop words for documents matching a pattern, overriding the two settings
+    // above, e.g. [{"pattern": "*.log", "stemming": false, "stopwords": "none"}]; the first
+    // matching rule wins
+    pub analysis: Vec<AnalysisRule>,
+    // Path of a synonym file, with lines such as "error => failure, fault" or "car, automobile";
+    // `serve` rereads it on "reload-synonyms"
+    pub synonyms: Option<PathBuf>,
+    // Path of a JSON file of queries and the documents ranked first for them, e.g.
+    // {"annual report": ["reports/2024.txt"]}; `serve` rereads it on "reload-pins"
+    pub pins: Option<PathBuf>,
+    // `serve` also rereads the stop word, synonym and pins files whenever they change
+    pub watch_dictionaries: bool,
+    // Caps on every search, e.g. {"max_documents": 10000, "max_matches": 50000, "max_expansions": 500,
+    // "max_regex_size": 1000000}; searches that reach one return partial results marked "limited"
+    pub limits: SearchLimits,
+    // Named search defaults, e.g. {"logs": {"path_prefix": "logs/", "mode": "word", "sort": "path",
+    // "max_per_file": 3, "boosts": {"logs/errors/": 2.0}}}, used by `search --collection logs`
+    pub collections: BTreeMap<String, Collection>,
+    // Documents a search reads and matches at once
+    pub parallelism: usize,
+    // Postings tables kept in memory for searches, the most recently used; 0 reads them from
+    // `.index` every time
+    pub postings_cache: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            search_directory: None,
+            extensions: DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            schedule: Vec::new(),
+            quota: None,
+            document_ttl: None,
+            logging: LoggingConfig::default(),
+            query_log: true,
+            slow_query_threshold: None,
+            usage_stats: false,
+            trash_retention: Some(DEFAULT_TRASH_RETENTION.to_string()),
+            allow_destructive_maintenance: false,
+            id_strategy: IdStrategy::default(),
+            embedder: None,
+            partitioning: None,
+            replica_of: None,
+            read_only: false,
+            allowed_roots: Vec::new(),
+            roots: Vec::new(),
+            bm25: Bm25::default(),
+            fold_accents: false,
+            stemming: false,
+            stem_language: StemLanguage::default(),
+            ngrams: None,
+            stopwords: StopWordList::default(),
+            analysis: Vec::new(),
+            synonyms: None,
+            pins: None,
+            watch_dictionaries: false,
+            limits: SearchLimits::default(),
+            collections: BTreeMap::new(),
+            parallelism: DEFAULT_PARALLELISM,
+            postings_cache: 0,
+        }
+    }
+}
+
+impl Config {
+    // Loads a config file, applying the named profile's overrides on top of the base settings
+    pub async fn load(path: &Path, profile: Option<&str>) -> Result<Self> {
+        let bytes = tokio::fs::read(path).await
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let mut settings: Value = serde_json::from_slice(&bytes)
+            .map_err(|e| SearchEngineError::InvalidConfig(format!("{:?}: {}", path, e)))?;
+
+        let profiles = settings.as_object_mut()
+            .and_then(|settings| settings.remove(PROFILES_KEY))
+            .unwrap_or(Value::Null);
+        if let Some(name) = profile {
+            let Some(overrides) = profiles.get(name) else {
+                return Err(SearchEngineError::InvalidConfig(format!(
+                    "Profile {:?} is not defined in config file {:?}", name, path
+                )));
+            };
+            merge(&mut settings, overrides.clone());
+        }
+
+        serde_json::from_value(settings)
+            .map_err(|e| SearchEngineError::InvalidConfig(format!("{:?}: {}", path, e)))
+    }
+
+    // Loads an explicitly requested config file, or the default one if it exists
+    pub async fn discover(explicit: Option<&Path>, profile: Option<&str>) -> Result<Self> {
+        match explicit {
+            Some(path) => Config::load(path, profile).await,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::load(Path::new(DEFAULT_CONFIG_FILE), profile).await,
+            None if profile.is_some() => Err(SearchEngineError::InvalidConfig(
+                "A config profile was requested but no config file was found".to_string(),
+            )),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+// Objects merge key by key; any other override value replaces the base value
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
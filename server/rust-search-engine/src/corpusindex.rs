@@ -0,0 +1,224 @@
+// The corpus dictionary: every term and stem in the catalog's postings tables with the
+// documents it occurs in, so a search opens only the tables of documents holding the query's
+// terms. It is merged when the catalog is committed, dropping documents removed or changed
+// and listing those added from their tables, and kept in `.index/dictionary.json`. Documents
+// it doesn't list, such as one whose table couldn't be read, are always searched.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs as async_fs;
+use tracing::warn;
+
+use crate::analysis::{self, Analyzers};
+use crate::catalog::{Catalog, CatalogEntry};
+use crate::checksum;
+use crate::docstore::temp_path;
+use crate::error::{Context, Result};
+use crate::matching::Matcher;
+use crate::postings::catalog_fingerprint;
+use crate::termindex::TermDictionary;
+use crate::SearchEngine;
+
+const DICTIONARY_FILE: &str = "dictionary.json";
+const DICTIONARY_VERSION: u32 = 1;
+
+// The version of a document whose terms are listed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ListedDocument {
+    path: PathBuf,
+    size: u64,
+    modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct CorpusDictionary {
+    version: u32,
+    // From `analysis_fingerprint`; the tables of other analysis settings list other terms
+    analysis: u64,
+    // By document number; a removed document's number goes to the next one added
+    documents: Vec<Option<ListedDocument>>,
+    terms: TermDictionary,
+    // Fingerprint of the catalog last merged
+    #[serde(skip)]
+    catalog: u64,
+    #[serde(skip)]
+    numbers: HashMap<PathBuf, usize>,
+}
+
+// Identifies what decides a table's terms and stems: the analysis fingerprint leaves out
+// whether each analysis stems and in which language
+fn analysis_fingerprint(analyzers: &Analyzers) -> u64 {
+    analyzers.all().fold(analyzers.fingerprint(), |hash, analysis| {
+        hash.rotate_left(1) ^ (analysis.stemming as u64 | analysis.stem_language.code() << 8)
+    })
+}
+
+impl CorpusDictionary {
+    fn new(analysis: u64) -> Self {
+        CorpusDictionary { version: DICTIONARY_VERSION, analysis, terms: TermDictionary::new(true), ..Default::default() }
+    }
+
+    // The number of `doc` if this version of it is listed
+    fn number(&self, doc: &CatalogEntry) -> Option<usize> {
+        let number = *self.numbers.get(&doc.path)?;
+        let listed = self.documents.get(number)?.as_ref()?;
+        (listed.size == doc.size && listed.modified == doc.modified).then_some(number)
+    }
+
+    // Keeps the documents a query can match in: those listed with its terms, and any the
+    // dictionary doesn't list or can't narrow the query down for. `matchers` are the query's,
+    // one for each analysis in `analyzers`.
+    pub(crate) fn narrow(&self, documents: &mut Vec<&CatalogEntry>, matchers: &[Matcher], analyzers: &Analyzers) {
+        let found: Vec<Option<HashSet<usize>>> = matchers.iter()
+            .map(|matcher| self.terms.documents_matching(matcher).map(HashSet::from_iter))
+            .collect();
+        if found.iter().all(Option::is_none) {
+            return;
+        }
+        documents.retain(|doc| match (&found[analyzers.index_for(&doc.path)], self.number(doc)) {
+            (Some(found), Some(number)) => found.contains(&number),
+            _ => true,
+        });
+    }
+
+    // Drops the documents no longer in `catalog` as listed and lists the rest from their
+    // tables. True if anything changed.
+    async fn merge(&mut self, engine: &SearchEngine, catalog: &Catalog) -> bool {
+        let current: HashMap<&PathBuf, &CatalogEntry> = catalog.documents.iter().map(|doc| (&doc.path, doc)).collect();
+        let mut removed = HashSet::new();
+        for (number, slot) in self.documents.iter_mut().enumerate() {
+            let Some(listed) = slot else {
+                continue;
+            };
+            if current.get(&listed.path).is_none_or(|doc| doc.size != listed.size || doc.modified != listed.modified) {
+                self.numbers.remove(&listed.path);
+                removed.insert(number);
+                *slot = None;
+            }
+        }
+        self.terms.remove_documents(&removed);
+
+        let mut free: Vec<usize> = (0..self.documents.len()).rev().filter(|number| self.documents[*number].is_none()).collect();
+        let mut added = false;
+        for doc in &catalog.documents {
+            if self.numbers.contains_key(&doc.path) {
+                continue;
+            }
+            // A read-only engine can't keep a rebuilt table, so a document without a current
+            // one stays unlisted
+            let table = if engine.read_only {
+                engine.postings_store.stored_postings(&doc.path).await
+            } else {
+                engine.postings_store.postings(&doc.path).await.map(Some)
+            };
+            let table = match table {
+                Ok(Some(table)) => table,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(path = ?doc.path, error = %e, "Failed to read postings for the term dictionary");
+                    continue;
+                }
+            };
+            let number = free.pop().unwrap_or(self.documents.len());
+            let listed = ListedDocument { path: doc.path.clone(), size: doc.size, modified: doc.modified };
+            if number == self.documents.len() {
+                self.documents.push(Some(listed));
+            } else {
+                self.documents[number] = Some(listed);
+            }
+            self.numbers.insert(doc.path.clone(), number);
+            for term in table.terms() {
+                self.terms.add_term(number, term);
+            }
+            for stem in table.stems() {
+                self.terms.add_stem(number, stem);
+            }
+            added = true;
+        }
+        added || !removed.is_empty()
+    }
+}
+
+impl SearchEngine {
+    fn dictionary_path(&self) -> PathBuf {
+        self.doc_store.index_dir().join(DICTIONARY_FILE)
+    }
+
+    // The saved dictionary, or an empty one if there is none or it can't be read
+    async fn load_dictionary(&self) -> CorpusDictionary {
+        let bytes = match async_fs::read(self.dictionary_path()).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return CorpusDictionary::default(),
+            Err(e) => {
+                warn!(error = %e, "Failed to read the term dictionary, rebuilding it");
+                return CorpusDictionary::default();
+            }
+        };
+        match checksum::from_json::<CorpusDictionary>(&bytes, "term dictionary") {
+            Ok(mut dictionary) => {
+                dictionary.numbers = dictionary.documents.iter().enumerate()
+                    .filter_map(|(number, listed)| Some((listed.as_ref()?.path.clone(), number)))
+                    .collect();
+                dictionary
+            }
+            Err(e) => {
+                warn!(error = %e, "Rebuilding unreadable term dictionary");
+                CorpusDictionary::default()
+            }
+        }
+    }
+
+    async fn save_dictionary(&self, dictionary: &CorpusDictionary) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let path = self.dictionary_path();
+        async_fs::create_dir_all(self.doc_store.index_dir()).await
+            .context("Failed to create index directory")?;
+        let temp = temp_path(&path);
+        async_fs::write(&temp, checksum::to_json(dictionary)?).await?;
+        async_fs::rename(&temp, &path).await
+            .context("Failed to persist term dictionary")?;
+        Ok(())
+    }
+
+    // Checks that the saved dictionary, if any, is intact
+    pub(crate) async fn verify_dictionary(&self) -> Result<()> {
+        match async_fs::read(self.dictionary_path()).await {
+            Ok(bytes) => checksum::from_json::<CorpusDictionary>(&bytes, "term dictionary").map(|_| ()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to read term dictionary"),
+        }
+    }
+
+    // The dictionary merged up to `catalog`, loaded from `.index` the first time and saved
+    // whenever a merge changes it. A dictionary of other analysis settings starts over.
+    pub(crate) async fn term_dictionary(&self, catalog: &Catalog) -> Arc<CorpusDictionary> {
+        let analysis = analysis_fingerprint(&analysis::current(&self.analyzers));
+        let fingerprint = catalog_fingerprint(catalog);
+        let mut slot = self.shared.term_dictionary.lock().await;
+        if let Some(dictionary) = slot.as_ref().filter(|cached| cached.analysis == analysis && cached.catalog == fingerprint) {
+            return dictionary.clone();
+        }
+        let mut dictionary = match slot.take() {
+            Some(dictionary) => dictionary,
+            None => Arc::new(self.load_dictionary().await),
+        };
+        if dictionary.version != DICTIONARY_VERSION || dictionary.analysis != analysis {
+            dictionary = Arc::new(CorpusDictionary::new(analysis));
+        }
+        let merged = Arc::make_mut(&mut dictionary);
+        if merged.merge(self, catalog).await {
+            if let Err(e) = self.save_dictionary(merged).await {
+                warn!(error = %e, "Failed to save the term dictionary");
+            }
+        }
+        merged.catalog = fingerprint;
+        *slot = Some(dictionary.clone());
+        dictionary
+    }
+
+}
@@ -0,0 +1,227 @@
+// Corpus-level statistics for tuning relevance offline: how terms spread over documents and
+// lines, how long documents are, and which terms dominate each directory. The figures come
+// from the postings tables, so terms are the ones searches look up, as the analysis settings
+// split and fold them and without stop words, and a term's frequency is the number of lines
+// it occurs on, as postings record lines rather than each occurrence. Histograms double their
+// bucket width each step: 1, 2-3, 4-7 and so on, with 0 as a bucket of its own.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tracing::{instrument, warn};
+
+use crate::error::Result;
+use crate::SearchEngine;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Bucket {
+    pub min: u64,
+    pub max: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TermFrequency {
+    pub term: String,
+    // Documents containing the term
+    pub documents: u64,
+    // Lines containing the term, across those documents
+    pub lines: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DirectoryTerms {
+    // Relative to the search directory; "." for documents at its top
+    pub directory: String,
+    pub documents: u64,
+    pub tokens: u64,
+    pub top_terms: Vec<TermFrequency>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CorpusReport {
+    pub documents: u64,
+    pub lines: u64,
+    // Term occurrences across all lines, stop words included
+    pub tokens: u64,
+    pub distinct_terms: u64,
+    // Tokens per document and per line
+    pub average_document_length: f64,
+    pub average_line_length: f64,
+    // Terms by the number of lines they occur on
+    pub term_frequencies: Vec<Bucket>,
+    // Terms by the number of documents they occur in
+    pub document_frequencies: Vec<Bucket>,
+    // Documents by their number of tokens
+    pub document_lengths: Vec<Bucket>,
+    // Terms on the most lines, most frequent first
+    pub top_terms: Vec<TermFrequency>,
+    // Directories by path, each with the terms on the most of its lines
+    pub directories: Vec<DirectoryTerms>,
+}
+
+// Bucket of a value: 0 alone, then [2^(n-1), 2^n - 1] for n = 1, 2, ...
+fn bucket_of(value: u64) -> usize {
+    (u64::BITS - value.leading_zeros()) as usize
+}
+
+fn histogram(values: impl Iterator<Item = u64>) -> Vec<Bucket> {
+    let mut counts: Vec<u64> = Vec::new();
+    for value in values {
+        let bucket = bucket_of(value);
+        if counts.len() <= bucket {
+            counts.resize(bucket + 1, 0);
+        }
+        counts[bucket] += 1;
+    }
+    let first = counts.iter().position(|count| *count > 0).unwrap_or(counts.len());
+    counts.iter().enumerate().skip(first)
+        .map(|(bucket, count)| match bucket {
+            0 => Bucket { min: 0, max: 0, count: *count },
+            _ => Bucket { min: 1 << (bucket - 1), max: u64::MAX >> (u64::BITS as usize - bucket), count: *count },
+        })
+        .collect()
+}
+
+// The `top` terms on the most lines, ties broken alphabetically
+fn top_terms(terms: &HashMap<String, (u64, u64)>, top: usize) -> Vec<TermFrequency> {
+    let mut ranked: Vec<(&String, &(u64, u64))> = terms.iter().collect();
+    ranked.sort_by(|(a, (_, a_lines)), (b, (_, b_lines))| b_lines.cmp(a_lines).then_with(|| a.cmp(b)));
+    ranked.into_iter()
+        .take(top)
+        .map(|(term, (documents, lines))| TermFrequency { term: term.clone(), documents: *documents, lines: *lines })
+        .collect()
+}
+
+#[derive(Default)]
+struct DirectoryCounts {
+    documents: u64,
+    tokens: u64,
+    terms: HashMap<String, (u64, u64)>,
+}
+
+fn add_terms<'a>(terms: &mut HashMap<String, (u64, u64)>, document: impl Iterator<Item = (&'a str, u64)>) {
+    for (term, lines) in document {
+        match terms.get_mut(term) {
+            Some((documents, total)) => {
+                *documents += 1;
+                *total += lines;
+            }
+            None => {
+                terms.insert(term.to_string(), (1, lines));
+            }
+        }
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn buckets_csv(buckets: &[Bucket], counted: &str) -> String {
+    let mut csv = format!("min,max,{}\n", counted);
+    for bucket in buckets {
+        csv.push_str(&format!("{},{},{}\n", bucket.min, bucket.max, bucket.count));
+    }
+    csv
+}
+
+impl CorpusReport {
+    // The report as CSV files named for their content: a one-row summary, the three
+    // histograms, the top terms, and the top terms of each directory one per row
+    pub fn csv_tables(&self) -> Vec<(&'static str, String)> {
+        let summary = format!(
+            "documents,lines,tokens,distinct_terms,average_document_length,average_line_length\n{},{},{},{},{},{}\n",
+            self.documents, self.lines, self.tokens, self.distinct_terms, self.average_document_length, self.average_line_length,
+        );
+        let mut top_terms = "rank,term,documents,lines\n".to_string();
+        for (rank, term) in self.top_terms.iter().enumerate() {
+            top_terms.push_str(&format!("{},{},{},{}\n", rank + 1, csv_field(&term.term), term.documents, term.lines));
+        }
+        let mut directories = "directory,directory_documents,directory_tokens,rank,term,documents,lines\n".to_string();
+        for directory in &self.directories {
+            for (rank, term) in directory.top_terms.iter().enumerate() {
+                directories.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    csv_field(&directory.directory), directory.documents, directory.tokens, rank + 1, csv_field(&term.term), term.documents, term.lines,
+                ));
+            }
+        }
+        vec![
+            ("summary.csv", summary),
+            ("term_frequencies.csv", buckets_csv(&self.term_frequencies, "terms")),
+            ("document_frequencies.csv", buckets_csv(&self.document_frequencies, "terms")),
+            ("document_lengths.csv", buckets_csv(&self.document_lengths, "documents")),
+            ("top_terms.csv", top_terms),
+            ("directory_terms.csv", directories),
+        ]
+    }
+}
+
+impl SearchEngine {
+    // Reads the postings table of every document, building any that are missing or stale;
+    // `top` terms are listed overall and for each directory
+    #[instrument(skip(self))]
+    pub async fn corpus_report(&self, top: usize) -> Result<CorpusReport> {
+        let catalog = self.catalog().await?;
+        let _tables = self.shared.index_lock.read().await;
+        let (mut documents, mut lines, mut tokens) = (0u64, 0u64, 0u64);
+        let mut lengths = Vec::with_capacity(catalog.documents.len());
+        let mut terms: HashMap<String, (u64, u64)> = HashMap::new();
+        let mut directories: BTreeMap<String, DirectoryCounts> = BTreeMap::new();
+        for doc in &catalog.documents {
+            let table = match self.postings_store.postings(&doc.path).await {
+                Ok(table) => table,
+                Err(e) => {
+                    warn!(path = ?doc.path, error = %e, "Failed to read postings for corpus statistics");
+                    continue;
+                }
+            };
+            documents += 1;
+            lines += table.line_count();
+            tokens += table.token_count();
+            lengths.push(table.token_count());
+            add_terms(&mut terms, table.term_lines());
+
+            let relative = self.relative_path(&doc.path);
+            let directory = match Path::new(&relative).parent().map(|parent| parent.to_string_lossy().replace('\\', "/")) {
+                Some(parent) if !parent.is_empty() => parent,
+                _ => ".".to_string(),
+            };
+            let counts = directories.entry(directory).or_default();
+            counts.documents += 1;
+            counts.tokens += table.token_count();
+            add_terms(&mut counts.terms, table.term_lines());
+        }
+
+        let average = |total: u64, count: u64| if count == 0 { 0.0 } else { total as f64 / count as f64 };
+        Ok(CorpusReport {
+            documents,
+            lines,
+            tokens,
+            distinct_terms: terms.len() as u64,
+            average_document_length: average(tokens, documents),
+            average_line_length: average(tokens, lines),
+            term_frequencies: histogram(terms.values().map(|(_, lines)| *lines)),
+            document_frequencies: histogram(terms.values().map(|(documents, _)| *documents)),
+            document_lengths: histogram(lengths.into_iter()),
+            top_terms: top_terms(&terms, top),
+            directories: directories.into_iter()
+                .map(|(directory, counts)| DirectoryTerms {
+                    directory,
+                    documents: counts.documents,
+                    tokens: counts.tokens,
+                    top_terms: top_terms(&counts.terms, top),
+                })
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::catalog::Catalog;
+use crate::docstore::fnv1a64;
+use crate::engine::visible_documents;
+use crate::error::Result;
+use crate::SearchEngine;
+
+// Fingerprints differing in at most this many of their 64 bits count as near-duplicates
+pub const DEFAULT_MAX_DISTANCE: u32 = 3;
+// Words per shingle; single words would make any two documents on one topic look alike
+const SHINGLE_WORDS: usize = 3;
+
+// 64-bit SimHash over word shingles: documents sharing most of their text get
+// fingerprints that differ in only a few bits
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut weights = [0i64; 64];
+    let mut add = |feature: &str| {
+        let hash = fnv1a64(feature.as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    };
+    if words.len() < SHINGLE_WORDS {
+        add(&words.join(" "));
+    }
+    for shingle in words.windows(SHINGLE_WORDS) {
+        add(&shingle.join(" "));
+    }
+    weights.iter().enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit)
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// Fingerprints are written as 16 hex digits, since JSON numbers lose precision past 2^53
+pub(crate) mod hex_fingerprint {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(fingerprint: &Option<u64>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match fingerprint {
+            Some(fingerprint) => serializer.serialize_str(&format!("{:016x}", fingerprint)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<u64>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|hex| u64::from_str_radix(&hex, 16).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+// Documents whose fingerprints are within the distance of one another, directly or
+// through other members
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DuplicateCluster {
+    // Relative paths, sorted
+    pub documents: Vec<String>,
+    // Largest distance between two linked members
+    pub max_distance: u32,
+}
+
+// Groups documents by near-duplicate cluster, returning each document's cluster number.
+// Compares every pair, which is fine for tens of thousands of documents.
+fn cluster(fingerprints: &[(PathBuf, u64)], max_distance: u32) -> (Vec<usize>, Vec<u32>) {
+    let mut parent: Vec<usize> = (0..fingerprints.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    let mut widest = vec![0; fingerprints.len()];
+    for i in 0..fingerprints.len() {
+        for j in i + 1..fingerprints.len() {
+            let distance = hamming_distance(fingerprints[i].1, fingerprints[j].1);
+            if distance <= max_distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[b] = a;
+                widest[a] = widest[a].max(widest[b]).max(distance);
+            }
+        }
+    }
+    let roots = (0..fingerprints.len()).map(|i| root(&mut parent, i)).collect();
+    (roots, widest)
+}
+
+impl SearchEngine {
+    pub(crate) async fn fingerprint(&self, file_path: &Path) -> Result<u64> {
+        Ok(simhash(&self.read_document(file_path).await?))
+    }
+
+    // Clusters of two or more near-duplicate documents among those `principals` may see.
+    // Documents picked up by a rescan have no stored fingerprint and are fingerprinted here;
+    // `optimize` stores them.
+    pub async fn near_duplicates(&self, max_distance: u32, principals: Option<&[String]>) -> Result<Vec<DuplicateCluster>> {
+        let catalog = self.catalog().await?;
+        let mut fingerprints = Vec::with_capacity(catalog.documents.len());
+        for doc in visible_documents(&catalog, principals) {
+            let fingerprint = match doc.attributes.fingerprint {
+                Some(fingerprint) => fingerprint,
+                None => match self.fingerprint(&doc.path).await {
+                    Ok(fingerprint) => fingerprint,
+                    Err(e) => {
+                        warn!(path = ?doc.path, error = %e, "Failed to fingerprint document");
+                        continue;
+                    }
+                },
+            };
+            fingerprints.push((doc.path.clone(), fingerprint));
+        }
+
+        let (roots, widest) = cluster(&fingerprints, max_distance);
+        let mut clusters: HashMap<usize, Vec<String>> = HashMap::new();
+        for (i, root) in roots.iter().enumerate() {
+            clusters.entry(*root).or_default().push(self.relative_path(&fingerprints[i].0));
+        }
+        let mut clusters: Vec<DuplicateCluster> = clusters.into_iter()
+            .filter(|(_, documents)| documents.len() > 1)
+            .map(|(root, mut documents)| {
+                documents.sort();
+                DuplicateCluster { documents, max_distance: widest[root] }
+            })
+            .collect();
+        clusters.sort_by(|a, b| b.documents.len().cmp(&a.documents.len()).then_with(|| a.documents.cmp(&b.documents)));
+        Ok(clusters)
+    }
+
+    // Maps each fingerprinted document to the first document of its near-duplicate
+    // cluster, for collapsing search results
+    pub(crate) fn duplicate_representatives(&self, catalog: &Catalog) -> HashMap<PathBuf, PathBuf> {
+        let fingerprints: Vec<(PathBuf, u64)> = catalog.documents.iter()
+            .filter_map(|doc| doc.attributes.fingerprint.map(|fingerprint| (doc.path.clone(), fingerprint)))
+            .collect();
+        let (roots, _) = cluster(&fingerprints, DEFAULT_MAX_DISTANCE);
+        roots.into_iter().enumerate()
+            .map(|(i, root)| (fingerprints[i].0.clone(), fingerprints[root].0.clone()))
+            .collect()
+    }
+}
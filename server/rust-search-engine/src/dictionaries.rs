@@ -0,0 +1,162 @@
+// Hot reloading of the stop word, synonym and pins files. Each reload reads and parses a
+// file completely, then swaps it in whole, so a search sees the old or the new list and never
+// a mix; a file that fails to parse leaves the one in use. Every swap bumps a version number.
+// A watcher rereads a file shortly after it changes, watching its directory so files replaced
+// by a rename are followed too.
+
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::PoisonError;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::error::{Result, SearchEngineError};
+use crate::stopwords::StopWordList;
+use crate::SearchEngine;
+
+// How long a file has to stay unchanged before it is reread, as editors often write in steps
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+// The version of the stop words, synonyms and pins in use
+#[derive(Debug)]
+pub(crate) struct DictionaryState {
+    version: u64,
+}
+
+impl Default for DictionaryState {
+    fn default() -> Self {
+        DictionaryState { version: 1 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DictionaryVersion {
+    // 1 for the files the engine was built with, bumped by each reload
+    pub version: u64,
+    pub stopwords: usize,
+    pub synonyms: usize,
+    // Queries with pinned results
+    pub pins: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Dictionary {
+    StopWords,
+    Synonyms,
+    Pins,
+}
+
+// Watches the engine's stop word, synonym and pins files until dropped
+pub struct DictionaryWatcher {
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl Drop for DictionaryWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl SearchEngine {
+    pub fn dictionary_version(&self) -> DictionaryVersion {
+        let state = self.shared.dictionaries.lock().unwrap_or_else(PoisonError::into_inner);
+        DictionaryVersion {
+            version: state.version,
+            stopwords: self.stopwords().len(),
+            synonyms: self.synonyms().len(),
+            pins: self.pins().len(),
+        }
+    }
+
+    // Runs `swap`, which replaces a dictionary, and bumps the version
+    pub(crate) fn swap_dictionary(&self, swap: impl FnOnce()) {
+        let mut state = self.shared.dictionaries.lock().unwrap_or_else(PoisonError::into_inner);
+        swap();
+        state.version += 1;
+    }
+
+    // The stop word, synonym and pins files the engine was built with, as absolute paths
+    fn dictionary_files(&self) -> Result<Vec<(PathBuf, Dictionary)>> {
+        let stopword_lists = std::iter::once(&self.analysis.stopwords)
+            .chain(self.analysis.rules.iter().filter_map(|rule| rule.stopwords.as_ref()));
+        let stopword_files = stopword_lists.filter_map(|list| match list {
+            StopWordList::File(path) => Some((path, Dictionary::StopWords)),
+            _ => None,
+        });
+        let synonym_file = self.synonyms_file.iter().map(|path| (path, Dictionary::Synonyms));
+        let pins_file = self.pins_file.iter().map(|path| (path, Dictionary::Pins));
+        stopword_files
+            .chain(synonym_file)
+            .chain(pins_file)
+            .map(|(path, dictionary)| Ok((std::path::absolute(path)?, dictionary)))
+            .collect()
+    }
+
+    // Starts rereading the stop word, synonym and pins files whenever they change, until the
+    // returned watcher is dropped. Fails if the engine has none of them.
+    pub fn watch_dictionaries(&self) -> Result<DictionaryWatcher> {
+        let files = self.dictionary_files()?;
+        if files.is_empty() {
+            return Err(SearchEngineError::InvalidConfig("there are no stop word, synonym or pins files to watch".to_string()));
+        }
+
+        let (changes, mut changed) = mpsc::unbounded_channel();
+        let watched = files.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            // Reading the files, as reloads do, is reported too
+            let writes = !matches!(event.kind, EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write));
+            if !writes {
+                return;
+            }
+            for (path, dictionary) in &watched {
+                if event.paths.contains(path) {
+                    let _ = changes.send(*dictionary);
+                }
+            }
+        })
+        .map_err(|e| SearchEngineError::InvalidConfig(format!("Failed to watch the stop word, synonym and pins files: {}", e)))?;
+        let directories: BTreeSet<PathBuf> = files.iter().filter_map(|(path, _)| path.parent().map(PathBuf::from)).collect();
+        for directory in directories {
+            watcher.watch(&directory, RecursiveMode::NonRecursive)
+                .map_err(|e| SearchEngineError::InvalidConfig(format!("Failed to watch {:?}: {}", directory, e)))?;
+        }
+
+        let engine = self.clone();
+        let task = tokio::spawn(async move {
+            while let Some(first) = changed.recv().await {
+                let mut dictionaries = BTreeSet::from([first]);
+                loop {
+                    match tokio::time::timeout(SETTLE_TIME, changed.recv()).await {
+                        Ok(Some(dictionary)) => {
+                            dictionaries.insert(dictionary);
+                        }
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+                for dictionary in dictionaries {
+                    let reloaded = match dictionary {
+                        Dictionary::StopWords => engine.reload_stopwords().await.map(|count| ("stop words", count)),
+                        Dictionary::Synonyms => engine.reload_synonyms().await.map(|count| ("synonyms", count)),
+                        Dictionary::Pins => engine.reload_pins().await.map(|count| ("pins", count)),
+                    };
+                    match reloaded {
+                        Ok((kind, count)) => info!(count, version = engine.dictionary_version().version, "Reloaded {}", kind),
+                        Err(e) => warn!(error = %e, "Failed to reload a changed dictionary file; keeping the one in use"),
+                    }
+                }
+            }
+        });
+        Ok(DictionaryWatcher { _watcher: watcher, task })
+    }
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{instrument, warn};
+
+use crate::error::Result;
+use crate::querylog;
+use crate::storage::StorageBackend;
+use crate::{SearchEngine, Stats};
+
+// Per-document figures for finding documents that dominate search cost
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DocumentStats {
+    pub path: String,
+    pub size: u64,
+    pub line_count: usize,
+    pub term_count: usize,
+    pub unique_terms: usize,
+    pub modified: DateTime<Utc>,
+    pub indexed_at: Option<DateTime<Utc>>,
+    // Logged searches that returned at least one line from this document
+    pub hit_count: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+struct TermCounts {
+    lines: usize,
+    terms: usize,
+    unique: usize,
+}
+
+// Terms are lowercased runs of alphanumeric characters
+async fn count_terms(storage: &dyn StorageBackend, file_path: &Path) -> Result<TermCounts> {
+    let file = storage.open(file_path).await?;
+    let mut reader = BufReader::new(file);
+
+    let mut counts = TermCounts { lines: 0, terms: 0, unique: 0 };
+    let mut seen = HashSet::new();
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer).await? == 0 {
+            break;
+        }
+        counts.lines += 1;
+
+        let line = String::from_utf8_lossy(&buffer).to_lowercase();
+        for term in line.split(|c: char| !c.is_alphanumeric()).filter(|term| !term.is_empty()) {
+            counts.terms += 1;
+            if !seen.contains(term) {
+                seen.insert(term.to_string());
+            }
+        }
+    }
+    counts.unique = seen.len();
+    Ok(counts)
+}
+
+impl SearchEngine {
+    // Reads every document, so this is proportional to the size of the index; largest documents first
+    #[instrument(skip(self))]
+    pub async fn document_stats(&self) -> Result<Vec<DocumentStats>> {
+        let mut hits: HashMap<String, usize> = HashMap::new();
+        for entry in querylog::read(&self.query_log_path(), None).await? {
+            for path in entry.hits {
+                *hits.entry(path).or_insert(0) += 1;
+            }
+        }
+
+        let mut stats = Vec::new();
+        for doc in &self.catalog().await?.documents {
+            let counts = match count_terms(self.storage.as_ref(), &doc.path).await {
+                Ok(counts) => counts,
+                Err(e) => {
+                    warn!(path = ?doc.path, error = %e, "Failed to read document for stats");
+                    continue;
+                }
+            };
+            let path = self.relative_path(&doc.path);
+            stats.push(DocumentStats {
+                hit_count: hits.get(&path).copied().unwrap_or(0),
+                path,
+                size: doc.size,
+                line_count: counts.lines,
+                term_count: counts.terms,
+                unique_terms: counts.unique,
+                modified: doc.modified,
+                indexed_at: doc.attributes.indexed_at,
+                keywords: doc.attributes.keywords.clone(),
+                language: doc.attributes.language.clone(),
+            });
+        }
+
+        stats.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        Ok(stats)
+    }
+
+    // Index-wide stats plus a per-document breakdown
+    pub async fn get_detailed_stats(&self) -> Result<Stats> {
+        let mut stats = self.get_stats().await?;
+        stats.documents = self.document_stats().await?;
+        Ok(stats)
+    }
+}
@@ -0,0 +1,403 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::checksum;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::catalog::{DocumentAttributes, IndexCounters};
+use crate::storage::{StorageBackend, StoredObject};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::fs as async_fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tracing::warn;
+
+pub const INDEX_DIR: &str = ".index";
+pub(crate) const LINES_DIR: &str = "lines";
+pub(crate) const VECTORS_DIR: &str = "vectors";
+pub(crate) const POSTINGS_DIR: &str = "postings";
+pub(crate) const META_FILE: &str = "meta.json";
+pub const INDEX_FORMAT_VERSION: u32 = 3;
+const OFFSETS_MAGIC: &[u8; 4] = b"LOFF";
+const OFFSETS_VERSION: u32 = 2;
+const HEADER_LEN: usize = 4 + 4 + 8 + 8 + 4 + 8;
+
+// A unique sibling of `target` to write before renaming over it, so concurrent
+// writers of the same file never share a temp file
+pub(crate) fn temp_path(target: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    target.with_extension(format!("{}.{}.tmp", std::process::id(), n))
+}
+
+// Identifies the version of a source file an offset table was built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified_secs: i64,
+    pub modified_nanos: u32,
+}
+
+impl FileStamp {
+    pub fn from_object(object: &StoredObject) -> Self {
+        FileStamp {
+            size: object.size,
+            modified_secs: object.modified.timestamp(),
+            modified_nanos: object.modified.timestamp_subsec_nanos(),
+        }
+    }
+}
+
+// Byte offset of the first byte of every line in a document. Line N (1-based)
+// spans offsets[N - 1] up to offsets[N] (or the end of the file for the last line).
+#[derive(Debug, Clone)]
+pub struct LineOffsets {
+    stamp: FileStamp,
+    offsets: Vec<u64>,
+}
+
+impl LineOffsets {
+    pub async fn build(storage: &dyn StorageBackend, file_path: &Path) -> Result<Self> {
+        let object = stat_document(storage, file_path).await?;
+        let file = storage.open(file_path).await?;
+        let mut reader = BufReader::with_capacity(4 * 1024 * 1024, file);
+
+        let mut offsets = Vec::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut position = 0u64;
+        let mut at_line_start = true;
+
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            for (i, byte) in buffer[..read].iter().enumerate() {
+                if at_line_start {
+                    offsets.push(position + i as u64);
+                    at_line_start = false;
+                }
+                if *byte == b'\n' {
+                    at_line_start = true;
+                }
+            }
+            position += read as u64;
+        }
+
+        Ok(LineOffsets {
+            stamp: FileStamp::from_object(&object),
+            offsets,
+        })
+    }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    // Byte range of a 1-based line, including its line terminator
+    pub fn line_range(&self, line_number: usize) -> Option<(u64, u64)> {
+        if line_number == 0 || line_number > self.offsets.len() {
+            return None;
+        }
+        let start = self.offsets[line_number - 1];
+        let end = self.offsets.get(line_number).copied().unwrap_or(self.stamp.size);
+        Some((start, end))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.offsets.len() * 8 + checksum::CHECKSUM_LEN);
+        bytes.extend_from_slice(OFFSETS_MAGIC);
+        bytes.extend_from_slice(&OFFSETS_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.size.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.modified_secs.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.modified_nanos.to_le_bytes());
+        bytes.extend_from_slice(&(self.offsets.len() as u64).to_le_bytes());
+        for offset in &self.offsets {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        checksum::seal(&mut bytes);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let bytes = checksum::unseal(bytes, "line offset table")?;
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != OFFSETS_MAGIC {
+            return Err(SearchEngineError::IndexCorrupted("Invalid line offset table".to_string()));
+        }
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != OFFSETS_VERSION {
+            return Err(SearchEngineError::IndexCorrupted(format!("Unsupported line offset table version {}", version)));
+        }
+
+        let stamp = FileStamp {
+            size: read_u64(8),
+            modified_secs: read_u64(16) as i64,
+            modified_nanos: u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+        };
+        let count = read_u64(28) as usize;
+        if bytes.len() != HEADER_LEN + count * 8 {
+            return Err(SearchEngineError::IndexCorrupted("Truncated line offset table".to_string()));
+        }
+
+        let offsets = (0..count).map(|i| read_u64(HEADER_LEN + i * 8)).collect();
+        Ok(LineOffsets { stamp, offsets })
+    }
+}
+
+// Small eagerly-loaded summary of the indexed corpus, written on every refresh
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexMeta {
+    pub format_version: u32,
+    pub last_scanned: DateTime<Utc>,
+    pub counters: IndexCounters,
+    // Catalogs saved before the change feed existed start from 0
+    #[serde(default)]
+    pub generation: u64,
+    pub documents: Vec<DocumentMeta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentMeta {
+    // Relative to the search directory
+    pub path: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    #[serde(flatten)]
+    pub attributes: DocumentAttributes,
+}
+
+// Persists per-document line offset tables under `<search_path>/.index/lines`
+#[derive(Debug, Clone)]
+pub struct DocStore {
+    root: PathBuf,
+    // Rebuilt tables are kept in memory only
+    read_only: bool,
+    index_dir: PathBuf,
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl DocStore {
+    pub fn new(root: &Path, storage: Arc<dyn StorageBackend>) -> Self {
+        DocStore {
+            root: root.to_path_buf(),
+            read_only: false,
+            index_dir: root.join(INDEX_DIR),
+            storage,
+        }
+    }
+
+    pub(crate) fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    // Keeps metadata and line offset tables under `index_dir` instead of `<root>/.index`
+    pub(crate) fn with_index_dir(mut self, index_dir: &Path) -> Self {
+        self.index_dir = index_dir.to_path_buf();
+        self
+    }
+
+    pub fn index_dir(&self) -> &Path {
+        &self.index_dir
+    }
+
+    pub fn meta_path(&self) -> PathBuf {
+        self.index_dir.join(META_FILE)
+    }
+
+    pub async fn load_meta(&self) -> Result<Option<IndexMeta>> {
+        let bytes = match async_fs::read(self.meta_path()).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read index metadata"),
+        };
+
+        // Metadata written by another format version is treated as absent and rebuilt
+        let value: serde_json::Value = checksum::from_json(&bytes, "index metadata")?;
+        if value.get("format_version").and_then(serde_json::Value::as_u64) != Some(INDEX_FORMAT_VERSION as u64) {
+            return Ok(None);
+        }
+
+        let meta = serde_json::from_value(value)
+            .map_err(|e| SearchEngineError::IndexCorrupted(format!("Unreadable index metadata: {}", e)))?;
+        Ok(Some(meta))
+    }
+
+    pub async fn save_meta(&self, meta: &IndexMeta) -> Result<()> {
+        async_fs::create_dir_all(&self.index_dir).await
+            .context("Failed to create index directory")?;
+
+        let target = self.meta_path();
+        let temp = temp_path(&target);
+        async_fs::write(&temp, checksum::to_json(meta)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist index metadata")?;
+        Ok(())
+    }
+
+    // Name of a document's derived files in `.index/lines`, `.index/vectors` and `.index/postings`
+    fn derived_file_name(&self, file_path: &Path) -> String {
+        let relative = file_path.strip_prefix(&self.root).unwrap_or(file_path);
+        format!("{:016x}.bin", fnv1a64(relative.to_string_lossy().as_bytes()))
+    }
+
+    pub(crate) fn offsets_path(&self, file_path: &Path) -> PathBuf {
+        self.index_dir.join(LINES_DIR).join(self.derived_file_name(file_path))
+    }
+
+    async fn load_offsets(&self, file_path: &Path) -> Result<Option<LineOffsets>> {
+        match async_fs::read(self.offsets_path(file_path)).await {
+            Ok(bytes) => Ok(Some(LineOffsets::decode(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read line offset table"),
+        }
+    }
+
+    async fn save_offsets(&self, file_path: &Path, offsets: &LineOffsets) -> Result<()> {
+        let target = self.offsets_path(file_path);
+        if let Some(parent) = target.parent() {
+            async_fs::create_dir_all(parent).await
+                .context("Failed to create line offset directory")?;
+        }
+
+        // Write to a temp file and rename so readers never see a partial table
+        let temp = temp_path(&target);
+        let mut file = async_fs::File::create(&temp).await?;
+        file.write_all(&offsets.encode()).await?;
+        file.flush().await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist line offset table")?;
+        Ok(())
+    }
+
+    // Returns the offset table for a document, rebuilding it if the stored
+    // one is missing, unreadable, or was built from a different file version
+    pub async fn offsets(&self, file_path: &Path) -> Result<LineOffsets> {
+        Ok(self.ensure_offsets(file_path).await?.0)
+    }
+
+    // Like `offsets`, also reporting whether the table had to be rebuilt
+    pub async fn ensure_offsets(&self, file_path: &Path) -> Result<(LineOffsets, bool)> {
+        let stamp = FileStamp::from_object(&stat_document(self.storage.as_ref(), file_path).await?);
+
+        match self.load_offsets(file_path).await {
+            Ok(Some(stored)) if stored.stamp() == stamp => return Ok((stored, false)),
+            Err(e) => warn!(path = ?file_path, error = %e, "Rebuilding unreadable line offset table"),
+            _ => {}
+        }
+
+        let offsets = LineOffsets::build(self.storage.as_ref(), file_path).await?;
+        if !self.read_only {
+            self.save_offsets(file_path, &offsets).await?;
+        }
+        Ok((offsets, true))
+    }
+
+    // Checks that a stored table exists, decodes cleanly, and matches the file on disk
+    pub async fn verify_offsets(&self, file_path: &Path) -> Result<()> {
+        let object = stat_document(self.storage.as_ref(), file_path).await?;
+        match self.load_offsets(file_path).await? {
+            Some(stored) if stored.stamp() == FileStamp::from_object(&object) => Ok(()),
+            Some(_) => Err(SearchEngineError::IndexCorrupted("Line offset table is stale".to_string())),
+            None => Err(SearchEngineError::IndexCorrupted("Line offset table is missing".to_string())),
+        }
+    }
+
+    // Offset, vector and postings tables that belong to no live document, plus leftover temp files
+    pub async fn orphans(&self, live: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let keep: std::collections::HashSet<String> = live.iter()
+            .map(|file_path| self.derived_file_name(file_path))
+            .collect();
+
+        let mut orphans = Vec::new();
+        let derived_dirs = [self.index_dir.join(LINES_DIR), self.index_dir.join(VECTORS_DIR), self.index_dir.join(POSTINGS_DIR)];
+        for dir in std::iter::once(self.index_dir.clone()).chain(derived_dirs.iter().cloned()) {
+            let mut entries = match async_fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("Failed to list index directory"),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let is_temp = path.extension().map(|ext| ext == "tmp").unwrap_or(false);
+                let is_orphan = derived_dirs.contains(&dir) && !keep.contains(&entry.file_name().to_string_lossy().into_owned());
+                if entry.file_type().await?.is_file() && (is_temp || is_orphan) {
+                    orphans.push(path);
+                }
+            }
+        }
+        Ok(orphans)
+    }
+
+    pub async fn remove_orphans(&self, live: &[PathBuf]) -> Result<usize> {
+        let orphans = self.orphans(live).await?;
+        for path in &orphans {
+            async_fs::remove_file(path).await?;
+        }
+        Ok(orphans.len())
+    }
+
+    pub async fn remove_offsets(&self, file_path: &Path) -> Result<()> {
+        match async_fs::remove_file(self.offsets_path(file_path)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to remove line offset table"),
+        }
+    }
+
+    // Fetches a single 1-based line with a seek + bounded read
+    pub async fn read_line(&self, file_path: &Path, line_number: usize) -> Result<Option<String>> {
+        let offsets = self.offsets(file_path).await?;
+        let Some((start, end)) = offsets.line_range(line_number) else {
+            return Ok(None);
+        };
+
+        let buffer = self.storage.read_range(file_path, start, end).await?;
+        Ok(Some(trim_line_ending(&buffer)))
+    }
+
+    // Fetches 1-based lines `start..=end` with one bounded read
+    pub async fn read_lines(&self, file_path: &Path, start: usize, end: usize) -> Result<Option<String>> {
+        let offsets = self.offsets(file_path).await?;
+        let (Some((from, _)), Some((_, to))) = (offsets.line_range(start), offsets.line_range(end)) else {
+            return Ok(None);
+        };
+
+        let buffer = self.storage.read_range(file_path, from, to).await?;
+        Ok(Some(trim_line_ending(&buffer)))
+    }
+}
+
+async fn stat_document(storage: &dyn StorageBackend, file_path: &Path) -> Result<StoredObject> {
+    storage.stat(file_path).await?
+        .ok_or_else(|| SearchEngineError::DocumentNotFound(file_path.to_string_lossy().to_string()))
+}
+
+pub(crate) fn trim_line_ending(bytes: &[u8]) -> String {
+    let mut end = bytes.len();
+    if end > 0 && bytes[end - 1] == b'\n' {
+        end -= 1;
+    }
+    if end > 0 && bytes[end - 1] == b'\r' {
+        end -= 1;
+    }
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+// Identifies one exact set of documents, changing whenever any of them does
+pub(crate) fn generation(documents: &[DocumentMeta]) -> Result<String> {
+    Ok(format!("{:016x}", fnv1a64(&serde_json::to_vec(documents)?)))
+}
+
+pub(crate) fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
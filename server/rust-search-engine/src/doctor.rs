@@ -0,0 +1,286 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::instrument;
+
+use crate::error::Result;
+use crate::config::Config;
+use crate::schedule::CronSchedule;
+use crate::storage::StorageBackend;
+use crate::ttl::parse_duration;
+use crate::SearchEngine;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: String,
+    pub message: String,
+    // What to run or change to resolve the finding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DoctorReport {
+    // False if any finding is an error
+    pub healthy: bool,
+    pub checked_at: DateTime<Utc>,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Default)]
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn add(&mut self, severity: Severity, check: &str, message: String, fix: Option<&str>) {
+        self.0.push(Finding {
+            severity,
+            check: check.to_string(),
+            message,
+            fix: fix.map(str::to_string),
+        });
+    }
+}
+
+// Finds the first line that is not valid UTF-8 or contains a NUL byte, which
+// search cannot read or which suggests a binary file
+async fn first_encoding_problem(storage: &dyn StorageBackend, file_path: &Path) -> Result<Option<(usize, &'static str)>> {
+    let file = storage.open(file_path).await?;
+    let mut reader = BufReader::new(file);
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer).await? == 0 {
+            return Ok(None);
+        }
+        line_number += 1;
+        if std::str::from_utf8(&buffer).is_err() {
+            return Ok(Some((line_number, "invalid UTF-8")));
+        }
+        if buffer.contains(&0) {
+            return Ok(Some((line_number, "NUL byte")));
+        }
+    }
+}
+
+async fn probe_writable(dir: &Path) -> Result<()> {
+    async_fs::create_dir_all(dir).await?;
+    let probe = dir.join("doctor-probe.tmp");
+    async_fs::write(&probe, b"").await?;
+    async_fs::remove_file(&probe).await?;
+    Ok(())
+}
+
+impl SearchEngine {
+    // Checks the search directory, index and configuration without changing the index
+    #[instrument(skip(self, config))]
+    pub async fn doctor(&self, config: &Config) -> Result<DoctorReport> {
+        let mut findings = Findings::default();
+
+        if !self.check_permissions(&mut findings).await {
+            return Ok(report(findings));
+        }
+        self.check_consistency(&mut findings).await?;
+        self.check_orphans(&mut findings).await?;
+        self.check_encoding(&mut findings).await?;
+        self.check_trash(&mut findings).await?;
+        self.check_config(config, &mut findings).await?;
+
+        Ok(report(findings))
+    }
+
+    // Returns false if the search directory is unusable and further checks are pointless
+    async fn check_permissions(&self, findings: &mut Findings) -> bool {
+        let path = self.search_path.display();
+        match async_fs::metadata(&self.search_path).await {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                findings.add(Severity::Error, "permissions", format!("{} is not a directory", path), Some("point SEARCH_DIRECTORY at a directory"));
+                return false;
+            }
+            Err(e) => {
+                findings.add(Severity::Error, "permissions", format!("{} is not accessible: {}", path, e), Some("create the directory or fix SEARCH_DIRECTORY"));
+                return false;
+            }
+        }
+
+        if let Err(e) = async_fs::read_dir(&self.search_path).await {
+            findings.add(Severity::Error, "permissions", format!("{} is not readable: {}", path, e), Some("grant read permission on the search directory"));
+            return false;
+        }
+        if self.read_only {
+            findings.add(Severity::Info, "permissions", format!("{} was opened read-only; write access was not checked", path), None);
+            return true;
+        }
+        if let Err(e) = probe_writable(&self.search_path).await {
+            findings.add(Severity::Error, "permissions", format!("{} is not writable: {}", path, e), Some("grant write permission on the search directory"));
+        }
+        if let Err(e) = probe_writable(self.doc_store.index_dir()).await {
+            findings.add(
+                Severity::Error,
+                "permissions",
+                format!("{} is not writable: {}", self.doc_store.index_dir().display(), e),
+                Some("grant write permission on the index directory"),
+            );
+        }
+        true
+    }
+
+    async fn check_consistency(&self, findings: &mut Findings) -> Result<()> {
+        let catalog = self.catalog().await?;
+        if catalog.counters.dirty {
+            findings.add(Severity::Warning, "consistency", "Index counters are flagged for re-verification".to_string(), Some("run `maintenance refresh`"));
+        }
+
+        let total_bytes: u64 = catalog.documents.iter().map(|doc| doc.size).sum();
+        if catalog.counters.total_documents != catalog.documents.len() || catalog.counters.total_bytes != total_bytes {
+            findings.add(
+                Severity::Error,
+                "consistency",
+                format!(
+                    "Counters report {} documents / {} bytes but the catalog holds {} / {}",
+                    catalog.counters.total_documents, catalog.counters.total_bytes, catalog.documents.len(), total_bytes
+                ),
+                Some("run `maintenance refresh`"),
+            );
+        }
+
+        for doc in &catalog.documents {
+            let path = self.relative_path(&doc.path);
+            let stored = match self.storage.stat(&doc.path).await {
+                Ok(Some(stored)) => stored,
+                _ => {
+                    findings.add(Severity::Error, "consistency", format!("{}: indexed but missing from disk", path), Some("run `maintenance refresh`"));
+                    continue;
+                }
+            };
+
+            if stored.size != doc.size || stored.modified != doc.modified {
+                findings.add(Severity::Warning, "stale-cache", format!("{}: changed on disk since it was indexed", path), Some("run `maintenance refresh`"));
+            } else if let Err(e) = self.doc_store.verify_offsets(&doc.path).await {
+                findings.add(Severity::Warning, "stale-cache", format!("{}: {}", path, e), Some("run `maintenance optimize`"));
+            }
+        }
+
+        let indexed: HashSet<&PathBuf> = catalog.documents.iter().map(|doc| &doc.path).collect();
+        let unindexed: Vec<String> = self.walk_documents().await?.iter()
+            .filter(|doc| !indexed.contains(&doc.path))
+            .map(|doc| self.relative_path(&doc.path))
+            .collect();
+        if !unindexed.is_empty() {
+            findings.add(
+                Severity::Warning,
+                "stale-cache",
+                format!("{} documents on disk are not indexed: {}", unindexed.len(), unindexed.join(", ")),
+                Some("run `maintenance refresh`"),
+            );
+        }
+        Ok(())
+    }
+
+    async fn check_orphans(&self, findings: &mut Findings) -> Result<()> {
+        let live: Vec<PathBuf> = self.catalog().await?.documents.iter()
+            .map(|doc| doc.path.clone())
+            .collect();
+        let orphans = self.doc_store.orphans(&live).await?;
+        if !orphans.is_empty() {
+            findings.add(
+                Severity::Warning,
+                "orphans",
+                format!("{} orphaned files in {}", orphans.len(), self.doc_store.index_dir().display()),
+                Some("run `maintenance compact`"),
+            );
+        }
+        Ok(())
+    }
+
+    async fn check_encoding(&self, findings: &mut Findings) -> Result<()> {
+        for doc in &self.catalog().await?.documents {
+            match first_encoding_problem(self.storage.as_ref(), &doc.path).await {
+                Ok(Some((line, problem))) => findings.add(
+                    Severity::Error,
+                    "encoding",
+                    format!("{}: {} on line {}; search stops reading the document there", self.relative_path(&doc.path), problem, line),
+                    Some("convert the document to UTF-8 text"),
+                ),
+                Ok(None) => {}
+                // A missing file is already reported by the consistency check
+                Err(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_trash(&self, findings: &mut Findings) -> Result<()> {
+        let due = self.purgeable_trash(false, Utc::now()).await?;
+        if !due.is_empty() && self.trash_retention.is_some() {
+            findings.add(
+                Severity::Info,
+                "trash",
+                format!("{} trashed documents are past the retention period", due.len()),
+                Some("run `maintenance purge-trash --yes` or schedule it"),
+            );
+        }
+        Ok(())
+    }
+
+    async fn check_config(&self, config: &Config, findings: &mut Findings) -> Result<()> {
+        for entry in &config.schedule {
+            if let Err(e) = CronSchedule::parse(&entry.cron) {
+                findings.add(Severity::Error, "config", format!("Schedule for {}: {:#}", entry.task.name(), e), Some("fix the cron expression in the config file"));
+            }
+        }
+        for (name, value) in [("document_ttl", &config.document_ttl), ("trash_retention", &config.trash_retention)] {
+            if let Some(Err(e)) = value.as_deref().map(parse_duration) {
+                findings.add(Severity::Error, "config", format!("{}: {:#}", name, e), Some("use a duration such as \"12h\" or \"7d\""));
+            }
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&config.logging.level) {
+            findings.add(Severity::Warning, "config", format!("logging.level {:?}: {}", config.logging.level, e), Some("use a level such as \"warn\" or \"info\""));
+        }
+
+        for root in &self.roots {
+            if !async_fs::metadata(&root.path).await.map(|metadata| metadata.is_dir()).unwrap_or(false) {
+                findings.add(Severity::Warning, "config", format!("root {:?}: {} is not a directory, so nothing is searched there", root.name(), root.path.display()), Some("mount the directory or remove the root"));
+            }
+        }
+
+        if let Some(quota) = config.quota {
+            let catalog = self.catalog().await?;
+            let used = self.stored_bytes(&catalog);
+            if quota.max_bytes == 0 {
+                findings.add(Severity::Error, "config", "quota.max_bytes is 0, so nothing can be indexed".to_string(), Some("raise quota.max_bytes or remove the quota"));
+            } else if used > quota.max_bytes {
+                findings.add(
+                    Severity::Warning,
+                    "config",
+                    format!("Documents use {} bytes, over the quota of {}", used, quota.max_bytes),
+                    Some("raise quota.max_bytes or remove documents"),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn report(findings: Findings) -> DoctorReport {
+    DoctorReport {
+        healthy: findings.0.iter().all(|finding| finding.severity != Severity::Error),
+        checked_at: Utc::now(),
+        findings: findings.0,
+    }
+}
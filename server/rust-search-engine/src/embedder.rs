@@ -0,0 +1,557 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Semaphore;
+
+use crate::docstore::fnv1a64;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::http::{HttpEndpoint, DEFAULT_TIMEOUT as DEFAULT_HTTP_TIMEOUT};
+use crate::storage::BoxFuture;
+use crate::ttl::parse_duration;
+
+pub const DEFAULT_DIMENSIONS: usize = 256;
+// Longest a command embedder may take to answer, including starting the process
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Turns text into a fixed-size vector, placing texts with similar meaning close together.
+// Chunk vectors are cached by a hash of the chunk text, so an embedder is only called
+// for text it hasn't seen since the document was last embedded with the same model.
+pub trait Embedder: fmt::Debug + Send + Sync {
+    // Identifies the model; vectors built by a different model are rebuilt
+    fn model(&self) -> &str;
+
+    fn dimensions(&self) -> usize;
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>>;
+}
+
+impl<E: Embedder + ?Sized> Embedder for Arc<E> {
+    fn model(&self) -> &str {
+        (**self).model()
+    }
+
+    fn dimensions(&self) -> usize {
+        (**self).dimensions()
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>> {
+        (**self).embed(text)
+    }
+}
+
+fn embedder_error(message: String) -> std::io::Error {
+    std::io::Error::other(message)
+}
+
+// Hashes each lowercased word (and each pair of neighbouring words) into a signed bucket.
+// Needs no model, so it matches shared vocabulary rather than meaning; a real model
+// plugged in through `Embedder` does much better on paraphrases.
+#[derive(Debug, Clone)]
+pub struct HashingEmbedder {
+    dimensions: usize,
+    model: String,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        HashingEmbedder {
+            dimensions,
+            model: format!("hashing-{}", dimensions),
+        }
+    }
+
+    fn add(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a64(feature.as_bytes());
+        let bucket = (hash % self.dimensions as u64) as usize;
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[bucket] += sign * weight;
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self::new(DEFAULT_DIMENSIONS)
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let mut vector = vec![0.0; self.dimensions];
+            let words: Vec<String> = text
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect();
+            for word in &words {
+                self.add(&mut vector, word, 1.0);
+            }
+            for pair in words.windows(2) {
+                self.add(&mut vector, &format!("{} {}", pair[0], pair[1]), 0.5);
+            }
+            Ok(vector)
+        })
+    }
+}
+
+// Calls an OpenAI-compatible embeddings endpoint over HTTP or HTTPS: POSTs {"model", "input"}
+// and reads {"data": [{"embedding": [...]}]}. Each chunk is one request on a pooled connection;
+// connecting gives up after 10 seconds and each read or write after the timeout, 60 seconds
+// unless set.
+#[derive(Clone)]
+pub struct HttpEmbedder {
+    endpoint: HttpEndpoint,
+    model: String,
+    dimensions: usize,
+    api_key: Option<String>,
+}
+
+impl fmt::Debug for HttpEmbedder {
+    // Leaves out the API key
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpEmbedder")
+            .field("url", &self.endpoint.url())
+            .field("model", &self.model)
+            .field("dimensions", &self.dimensions)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingResponse {
+    OpenAi { data: Vec<EmbeddingData> },
+    Single { embedding: Vec<f32> },
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+impl HttpEmbedder {
+    // `url` such as "https://api.example.com/v1/embeddings" or "http://localhost:8080/v1/embeddings"
+    pub fn new(url: &str, model: impl Into<String>, dimensions: usize) -> Result<Self> {
+        Ok(HttpEmbedder {
+            endpoint: HttpEndpoint::parse(url)?,
+            model: model.into(),
+            dimensions,
+            api_key: None,
+        })
+    }
+
+    // Sent as a bearer token
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    // Longest wait for each read and write of a request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.endpoint = self.endpoint.timeout(timeout);
+        self
+    }
+}
+
+impl Embedder for HttpEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let body = serde_json::to_vec(&serde_json::json!({ "model": self.model, "input": text }))?;
+            let response = self.endpoint.post_json(&body, self.api_key.as_deref()).await
+                .context("Embedding request failed")?;
+            let parsed: EmbeddingResponse = serde_json::from_slice(&response)
+                .context("Unexpected embedding API response")?;
+            match parsed {
+                EmbeddingResponse::OpenAi { mut data } if !data.is_empty() => Ok(data.swap_remove(0).embedding),
+                EmbeddingResponse::OpenAi { .. } => Err(embedder_error("Embedding API returned no embeddings".to_string()))
+                    .context("Embedding request failed"),
+                EmbeddingResponse::Single { embedding } => Ok(embedding),
+            }
+        })
+    }
+}
+
+struct ModelProcess {
+    // Kept so the process is killed when this is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+// Runs a local model as long-lived child processes: each text is written to a process's
+// stdin as a JSON string on one line and it answers with a JSON array of floats on one line.
+// Up to `processes` of them, 1 unless set, answer requests at once; they start on first use.
+// A process that fails or takes longer than the timeout is killed and a new one started for
+// the next request.
+pub struct CommandEmbedder {
+    program: String,
+    args: Vec<String>,
+    model: String,
+    dimensions: usize,
+    timeout: Duration,
+    // Processes waiting for a request
+    idle: Mutex<Vec<ModelProcess>>,
+    // One permit per process that may run
+    slots: Semaphore,
+}
+
+impl fmt::Debug for CommandEmbedder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandEmbedder")
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .field("model", &self.model)
+            .field("dimensions", &self.dimensions)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl CommandEmbedder {
+    pub fn new<I, S>(program: impl Into<String>, args: I, model: impl Into<String>, dimensions: usize) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        CommandEmbedder {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            model: model.into(),
+            dimensions,
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+            idle: Mutex::new(Vec::new()),
+            slots: Semaphore::new(1),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // How many processes may answer requests at once, at least 1
+    pub fn processes(mut self, processes: usize) -> Self {
+        self.slots = Semaphore::new(processes.max(1));
+        self
+    }
+
+    fn spawn(&self) -> Result<ModelProcess> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start embedding command {:?}", self.program))?;
+        let stdin = child.stdin.take().ok_or_else(|| embedder_error("no stdin".to_string()))?;
+        let stdout = child.stdout.take().ok_or_else(|| embedder_error("no stdout".to_string()))?;
+        Ok(ModelProcess {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        })
+    }
+
+    async fn request(process: &mut ModelProcess, text: &str) -> Result<Vec<f32>> {
+        let mut line = serde_json::to_vec(text)?;
+        line.push(b'\n');
+        process.stdin.write_all(&line).await.context("Failed to write to embedding command")?;
+        process.stdin.flush().await?;
+        let reply = process.stdout.next_line().await
+            .context("Failed to read from embedding command")?
+            .ok_or_else(|| embedder_error("Embedding command exited".to_string()))?;
+        serde_json::from_str(&reply).context("Embedding command printed something other than a JSON array")
+    }
+}
+
+impl Embedder for CommandEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let _slot = self.slots.acquire().await.map_err(|e| embedder_error(e.to_string()))?;
+            let idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner).pop();
+            let mut process = match idle {
+                Some(process) => process,
+                None => self.spawn()?,
+            };
+            // Dropping the process on failure kills it
+            let vector = tokio::time::timeout(self.timeout, Self::request(&mut process, text)).await
+                .map_err(|_| embedder_error(format!("no answer within {:?}", self.timeout)))
+                .context("Embedding command timed out")??;
+            self.idle.lock().unwrap_or_else(PoisonError::into_inner).push(process);
+            Ok(vector)
+        })
+    }
+}
+
+// Runs an ONNX sentence-embedding model in this process with ONNX Runtime, which is loaded
+// when the first model is: the library ORT_DYLIB_PATH names, or libonnxruntime from the
+// library path. Texts are tokenized with the model's Hugging Face tokenizer.json, truncated
+// to 256 tokens. The model takes input_ids and attention_mask, plus token_type_ids if it
+// declares them, and returns token embeddings, which are mean-pooled over the attention mask.
+#[cfg(feature = "onnx")]
+#[derive(Clone)]
+pub struct OnnxEmbedder {
+    model: String,
+    dimensions: usize,
+    tokenizer: Arc<tokenizers::Tokenizer>,
+    // Running the model needs it to itself
+    session: Arc<Mutex<ort::session::Session>>,
+    token_type_ids: bool,
+}
+
+#[cfg(feature = "onnx")]
+const MAX_TOKENS: usize = 256;
+
+#[cfg(feature = "onnx")]
+impl fmt::Debug for OnnxEmbedder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnnxEmbedder")
+            .field("model", &self.model)
+            .field("dimensions", &self.dimensions)
+            .finish()
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl OnnxEmbedder {
+    pub fn new(
+        model_file: impl AsRef<std::path::Path>,
+        tokenizer_file: impl AsRef<std::path::Path>,
+        model: impl Into<String>,
+        dimensions: usize,
+    ) -> Result<Self> {
+        let (model_file, tokenizer_file) = (model_file.as_ref(), tokenizer_file.as_ref());
+        let mut tokenizer = tokenizers::Tokenizer::from_file(tokenizer_file).map_err(|e| {
+            SearchEngineError::InvalidConfig(format!("Failed to load tokenizer {:?}: {}", tokenizer_file, e))
+        })?;
+        let truncation = tokenizers::TruncationParams { max_length: MAX_TOKENS, ..Default::default() };
+        tokenizer.with_truncation(Some(truncation))
+            .map_err(|e| SearchEngineError::InvalidConfig(format!("Failed to set up tokenizer {:?}: {}", tokenizer_file, e)))?;
+        let session = ort::session::Session::builder()
+            .and_then(|builder| builder.commit_from_file(model_file))
+            .map_err(|e| SearchEngineError::InvalidConfig(format!("Failed to load ONNX model {:?}: {}", model_file, e)))?;
+        let token_type_ids = session.inputs.iter().any(|input| input.name == "token_type_ids");
+        Ok(OnnxEmbedder {
+            model: model.into(),
+            dimensions,
+            tokenizer: Arc::new(tokenizer),
+            session: Arc::new(Mutex::new(session)),
+            token_type_ids,
+        })
+    }
+
+    fn run(&self, text: &str) -> std::result::Result<Vec<f32>, String> {
+        use ort::value::Tensor;
+
+        let encoding = self.tokenizer.encode(text, true).map_err(|e| e.to_string())?;
+        let as_tensor = |values: &[u32]| {
+            Tensor::from_array(([1, values.len()], values.iter().map(|&value| value as i64).collect::<Vec<i64>>()))
+        };
+        let mask = encoding.get_attention_mask();
+        let mut inputs = vec![
+            ("input_ids", as_tensor(encoding.get_ids()).map_err(|e| e.to_string())?),
+            ("attention_mask", as_tensor(mask).map_err(|e| e.to_string())?),
+        ];
+        if self.token_type_ids {
+            inputs.push(("token_type_ids", as_tensor(encoding.get_type_ids()).map_err(|e| e.to_string())?));
+        }
+
+        let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        let outputs = session.run(inputs).map_err(|e| e.to_string())?;
+        let (shape, tokens) = outputs[0].try_extract_tensor::<f32>().map_err(|e| e.to_string())?;
+        let width = shape.last().copied().unwrap_or(0) as usize;
+        let mut vector = vec![0.0; width];
+        let mut counted = 0.0f32;
+        for (token, &attended) in tokens.chunks_exact(width.max(1)).zip(mask) {
+            if attended == 0 {
+                continue;
+            }
+            counted += 1.0;
+            for (sum, value) in vector.iter_mut().zip(token) {
+                *sum += value;
+            }
+        }
+        Ok(vector.into_iter().map(|sum| sum / counted.max(1.0)).collect())
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl Embedder for OnnxEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let embedder = self.clone();
+            let text = text.to_string();
+            tokio::task::spawn_blocking(move || embedder.run(&text)).await
+                .map_err(|e| embedder_error(e.to_string()))?
+                .map_err(embedder_error)
+                .context("Embedding model failed")
+        })
+    }
+}
+
+fn default_dimensions() -> usize {
+    DEFAULT_DIMENSIONS
+}
+
+// Parses an embedder's "timeout", such as "30s"
+fn parse_timeout(timeout: Option<&str>, default: Duration) -> Result<Duration> {
+    let Some(timeout) = timeout else {
+        return Ok(default);
+    };
+    parse_duration(timeout)?.to_std()
+        .map_err(|_| SearchEngineError::InvalidConfig(format!("Invalid embedder timeout {:?}", timeout)))
+}
+
+// The "embedder" config section, e.g. {"type": "hashing", "dimensions": 256},
+// {"type": "http", "url": "https://api.example.com/v1/embeddings", "model": "...", "dimensions": 384}
+// or {"type": "onnx", "path": "model.onnx", "tokenizer": "tokenizer.json", "model": "...", "dimensions": 384}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum EmbedderConfig {
+    Hashing {
+        #[serde(default = "default_dimensions")]
+        dimensions: usize,
+    },
+    Http {
+        url: String,
+        model: String,
+        dimensions: usize,
+        // Environment variable holding the API key, so the key stays out of the config file
+        #[serde(default)]
+        api_key_env: Option<String>,
+        // Longest wait for each read and write, e.g. "30s"; 60 seconds unless set
+        #[serde(default)]
+        timeout: Option<String>,
+    },
+    // A local model process; `command` is the program followed by its arguments
+    Command {
+        command: Vec<String>,
+        model: String,
+        dimensions: usize,
+        // Longest wait for an answer, e.g. "30s"; 60 seconds unless set
+        #[serde(default)]
+        timeout: Option<String>,
+        // Processes answering at once; 1 unless set
+        #[serde(default)]
+        processes: Option<usize>,
+    },
+    // An ONNX model run in this process; needs the `onnx` feature
+    Onnx {
+        path: PathBuf,
+        tokenizer: PathBuf,
+        model: String,
+        dimensions: usize,
+    },
+}
+
+impl EmbedderConfig {
+    pub fn build(&self) -> Result<Arc<dyn Embedder>> {
+        Ok(match self {
+            EmbedderConfig::Hashing { dimensions } => Arc::new(HashingEmbedder::new(*dimensions)),
+            EmbedderConfig::Http { url, model, dimensions, api_key_env, timeout } => {
+                let mut embedder = HttpEmbedder::new(url, model.as_str(), *dimensions)?
+                    .timeout(parse_timeout(timeout.as_deref(), DEFAULT_HTTP_TIMEOUT)?);
+                if let Some(name) = api_key_env {
+                    let key = std::env::var(name).map_err(|_| SearchEngineError::InvalidConfig(format!(
+                        "environment variable {} with the embedding API key is not set", name
+                    )))?;
+                    embedder = embedder.api_key(key);
+                }
+                Arc::new(embedder)
+            }
+            EmbedderConfig::Command { command, model, dimensions, timeout, processes } => {
+                let (program, args) = command.split_first().ok_or_else(|| {
+                    SearchEngineError::InvalidConfig("embedder command must not be empty".to_string())
+                })?;
+                Arc::new(CommandEmbedder::new(program.as_str(), args.iter().cloned(), model.as_str(), *dimensions)
+                    .timeout(parse_timeout(timeout.as_deref(), DEFAULT_COMMAND_TIMEOUT)?)
+                    .processes(processes.unwrap_or(1)))
+            }
+            #[cfg(feature = "onnx")]
+            EmbedderConfig::Onnx { path, tokenizer, model, dimensions } => {
+                Arc::new(OnnxEmbedder::new(path, tokenizer, model.as_str(), *dimensions)?)
+            }
+            #[cfg(not(feature = "onnx"))]
+            EmbedderConfig::Onnx { .. } => {
+                return Err(SearchEngineError::InvalidConfig("the onnx embedder requires the `onnx` feature".to_string()));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::block_on;
+
+    #[cfg(unix)]
+    #[test]
+    fn command_processes_that_stop_answering_are_replaced() {
+        block_on(async {
+            // Answers the first text and hangs on the second
+            let script = r#"read line; echo "[1.0, 2.0]"; read line; sleep 30"#;
+            let embedder = CommandEmbedder::new("sh", ["-c", script], "test", 2).timeout(Duration::from_millis(200));
+            assert_eq!(embedder.embed("one").await.unwrap(), [1.0, 2.0]);
+            assert!(embedder.embed("two").await.is_err());
+            assert_eq!(embedder.embed("three").await.unwrap(), [1.0, 2.0]);
+        });
+    }
+
+    #[test]
+    fn http_requests_time_out_on_a_silent_server() {
+        block_on(async {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/v1/embeddings", listener.local_addr().unwrap());
+            let embedder = HttpEmbedder::new(&url, "test", 2).unwrap().timeout(Duration::from_millis(200));
+            let started = std::time::Instant::now();
+            assert!(embedder.embed("text").await.is_err());
+            assert!(started.elapsed() < Duration::from_secs(5));
+            drop(listener);
+        });
+    }
+
+    #[test]
+    fn endpoints_take_http_and_https_urls() {
+        assert!(HttpEmbedder::new("https://api.example.com/v1/embeddings", "test", 2).is_ok());
+        assert!(HttpEmbedder::new("http://localhost:8080/v1/embeddings", "test", 2).is_ok());
+        assert!(HttpEmbedder::new("ftp://example.com/", "test", 2).is_err());
+        assert!(HttpEmbedder::new("https:///v1", "test", 2).is_err());
+    }
+}
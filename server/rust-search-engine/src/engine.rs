@@ -0,0 +1,1811 @@
+use chrono::{DateTime, Duration, Utc};
+use futures_core::Stream;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
+use tracing::{debug, info, instrument, warn};
+
+use crate::alerts::{self, AlertEvent};
+use crate::acl::{permits, validate_acl};
+use crate::analysis::{self, Analysis, AnalysisConfig, Analyzers, SharedAnalyzers};
+use crate::bm25::{Bm25, CorpusStats, Scorer};
+use crate::catalog::{Catalog, CatalogEntry, DocumentAttributes};
+use crate::clustering::cluster_results;
+use crate::corpusindex::CorpusDictionary;
+use crate::dedup::simhash;
+use crate::docstats::DocumentStats;
+use crate::docstore::{temp_path, trim_line_ending, DocStore, FileStamp, DocumentMeta, IndexMeta, INDEX_DIR, INDEX_FORMAT_VERSION};
+use crate::error::{Context, Result, SearchEngineError};
+use crate::extract::{convert, converted_extensions, is_converted, page_breaks, page_of, text_path};
+use crate::ids::IdStrategy;
+use crate::keywords::{extract_keywords, KeywordCount, MAX_KEYWORDS};
+use crate::language::detect_language;
+use crate::matching::{self, Matcher, Presence};
+use crate::metadata::{count_facets, validate_metadata, Metadata};
+use crate::normalize::normalize_query;
+use crate::options::{Collection, MatchMode, SearchLimits, SearchOptions};
+use crate::query::parse_query;
+use crate::postings::PostingsStore;
+use crate::partition::{detect_date, partition_of, partition_span, PartitionStats, Partitioning};
+use crate::profile::{Phase, Profiler, QueryProfile};
+use crate::progress::{NoProgress, ProgressReporter, Tracker};
+use crate::quota::{DiskQuota, QuotaStatus};
+use crate::replication::ReplicaSource;
+use crate::roots::SearchRoot;
+use crate::querylog::{self, QueryAnalytics, QueryLogEntry, SlowQueryEntry, QUERY_LOG_FILE, SLOW_LOG_FILE};
+use crate::schedule::ScheduleStatus;
+use crate::source::{DocumentInput, DocumentSource, IndexFailure, IndexReport, StreamSource};
+use crate::dictionaries::DictionaryState;
+use crate::stem::StemLanguage;
+use crate::stopwords::StopWords;
+use crate::synonyms::{load_synonyms, Synonyms};
+use crate::pins::{self, load_pins, Pins};
+use crate::storage::{StorageBackend, StoredObject};
+use crate::summary::{summarize, SUMMARY_SENTENCES};
+use crate::trash::TRASH_DIR;
+use crate::usage::UsageEvent;
+use crate::embedder::Embedder;
+use crate::vector::VectorStore;
+use crate::{SearchEngineBuilder, SearchResponse, SearchResult};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LineResult {
+    pub path: String,
+    pub line_number: i64,
+    pub content: String,
+}
+
+// Consecutive lines of a document, `start` to `end` inclusive and 1-based
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LineRange {
+    pub path: String,
+    pub start: i64,
+    pub end: i64,
+    // Lines in the whole document, so callers know how much more there is to fetch
+    pub total_lines: usize,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct IndexedDocument {
+    pub path: String,
+    pub size: u64,
+    pub line_count: usize,
+    pub indexed_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    // Assigned by the engine's `IdStrategy`; None only for content-hash or provided
+    // IDs of documents picked up by a rescan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+    // Most significant phrases, best first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    // ISO 639-1 code, or "und" if it couldn't be told
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // Declared by the document, such as an HTML page's <title>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: Metadata,
+    // Principals that may see the document; empty for everyone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acl: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Stats {
+    pub total_documents: usize,
+    pub index_size_bytes: u64,
+    pub last_updated: DateTime<Utc>,
+    pub search_path: String,
+    pub per_extension: BTreeMap<String, usize>,
+    pub dirty: bool,
+    pub last_verified: DateTime<Utc>,
+    // Only populated by `get_detailed_stats`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub documents: Vec<DocumentStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Status {
+    pub index_exists: bool,
+    pub index_healthy: bool,
+    pub total_documents: usize,
+    pub index_size_bytes: u64,
+    pub last_updated: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaStatus>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fold_accents: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stemming: bool,
+    // Language words are stemmed in, while stemming is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stem_language: Option<StemLanguage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ngrams: Option<usize>,
+    // Number of stop words in use
+    #[serde(default)]
+    pub stopwords: usize,
+    // Number of synonym entries in use
+    #[serde(default)]
+    pub synonyms: usize,
+    // Number of queries with pinned results
+    #[serde(default)]
+    pub pins: usize,
+    // Version of the stop words, synonyms and pins in use, bumped by each reload
+    #[serde(default)]
+    pub dictionary_version: u64,
+    // Name of the analyzer splitting documents into terms, if one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analyzer: Option<String>,
+    // Only populated in server mode when maintenance is scheduled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scheduled_tasks: Vec<ScheduleStatus>,
+}
+
+
+// Cheap to clone: clones share the catalog and locks, so one engine can serve
+// concurrent tasks. Searches read a catalog snapshot and never wait for writers.
+#[derive(Clone)]
+pub struct SearchEngine {
+    pub(crate) search_path: PathBuf,
+    pub(crate) shared: Arc<Shared>,
+    pub(crate) doc_store: DocStore,
+    pub(crate) extensions: Vec<String>,
+    pub(crate) quota: Option<DiskQuota>,
+    pub(crate) default_ttl: Option<Duration>,
+    pub(crate) query_log: bool,
+    // Searches taking longer are profiled into the slow query log
+    pub(crate) slow_query_threshold: Option<Duration>,
+    pub(crate) trash_retention: Option<Duration>,
+    pub(crate) usage_stats: bool,
+    pub(crate) actor: String,
+    pub(crate) storage: Arc<dyn StorageBackend>,
+    pub(crate) id_strategy: IdStrategy,
+    // Semantic search is available only when an embedder is configured
+    pub(crate) embedder: Option<Arc<dyn Embedder>>,
+    pub(crate) vector_store: VectorStore,
+    pub(crate) postings_store: PostingsStore,
+    pub(crate) bm25: Bm25,
+    // Stemming and stop words of each document, shared with the postings store and replaced
+    // whole when stop words are reloaded
+    pub(crate) analyzers: SharedAnalyzers,
+    pub(crate) analysis: AnalysisConfig,
+    // Synonyms query terms are expanded with, replaced whole when their file is reread
+    pub(crate) synonyms: Arc<RwLock<Arc<Synonyms>>>,
+    pub(crate) synonyms_file: Option<PathBuf>,
+    // Documents ranked first for some queries, replaced whole when their file is reread
+    pub(crate) pins: Arc<RwLock<Arc<Pins>>>,
+    pub(crate) pins_file: Option<PathBuf>,
+    pub(crate) limits: SearchLimits,
+    pub(crate) partitioning: Option<Partitioning>,
+    // Nothing is written under the search directory; mutating calls fail with ReadOnly
+    pub(crate) read_only: bool,
+    // Set on read-only replicas
+    pub(crate) replica: Option<ReplicaSource>,
+    pub(crate) collections: BTreeMap<String, Collection>,
+    // Directories searched in place besides the search directory
+    pub(crate) roots: Vec<SearchRoot>,
+    // Documents a search reads and matches at once
+    pub(crate) parallelism: usize,
+}
+
+// State shared by every clone of an engine
+pub(crate) struct Shared {
+    // None until the first scan; writers replace it copy-on-write
+    catalog: RwLock<Option<Arc<Catalog>>>,
+    // Held by every operation that changes documents on disk or in the catalog, with the
+    // index lock other processes take (see `lock_writes`)
+    pub(crate) write_lock: tokio::sync::Mutex<()>,
+    // Revision of the catalog on disk the one in memory was loaded from or saved as
+    pub(crate) revision: Mutex<Option<u64>>,
+    // Held for reading while a search or line fetch uses the derived tables under `.index`;
+    // a rebuild takes it for writing only to swap the new tables in
+    pub(crate) index_lock: tokio::sync::RwLock<()>,
+    // Guards the read-modify-write of the usage counters
+    pub(crate) usage_lock: tokio::sync::Mutex<()>,
+    // Guards the read-modify-write of the saved alerts
+    pub(crate) alerts_lock: tokio::sync::Mutex<()>,
+    // Guards the read-modify-write of the collection aliases
+    pub(crate) aliases_lock: tokio::sync::Mutex<()>,
+    pub(crate) alert_events: broadcast::Sender<AlertEvent>,
+    // BM25 statistics and the fingerprint of the catalog they were aggregated for
+    pub(crate) corpus_stats: Mutex<Option<(u64, Arc<CorpusStats>)>>,
+    // Version of the stop words, synonyms and pins in use
+    pub(crate) dictionaries: Mutex<DictionaryState>,
+    // Documents of each term, merged up to the catalog it was last asked for
+    pub(crate) term_dictionary: tokio::sync::Mutex<Option<Arc<CorpusDictionary>>>,
+}
+
+impl Shared {
+    pub(crate) fn new(catalog: Option<Catalog>, revision: Option<u64>) -> Self {
+        Shared {
+            catalog: RwLock::new(catalog.map(Arc::new)),
+            write_lock: tokio::sync::Mutex::new(()),
+            revision: Mutex::new(revision),
+            index_lock: tokio::sync::RwLock::new(()),
+            usage_lock: tokio::sync::Mutex::new(()),
+            alerts_lock: tokio::sync::Mutex::new(()),
+            aliases_lock: tokio::sync::Mutex::new(()),
+            alert_events: broadcast::channel(alerts::EVENT_BUFFER).0,
+            corpus_stats: Mutex::new(None),
+            dictionaries: Mutex::new(DictionaryState::default()),
+            term_dictionary: tokio::sync::Mutex::new(None),
+        }
+    }
+}
+
+// Bytes read from the start of a document when looking for its date
+const DATE_SCAN_BYTES: u64 = 64 * 1024;
+// Most lines `get_lines` returns at once
+pub const MAX_LINE_RANGE: usize = 1000;
+
+const _: fn() = || {
+    fn assert_shareable<T: Send + Sync + Clone>() {}
+    assert_shareable::<SearchEngine>();
+};
+
+impl SearchEngine {
+    pub async fn new(search_path: &str) -> Result<Self> {
+        SearchEngineBuilder::new(search_path).build().await
+    }
+
+    pub fn builder(search_path: impl AsRef<Path>) -> SearchEngineBuilder {
+        SearchEngineBuilder::new(search_path)
+    }
+
+    // Extensions (lowercase, without the dot) of the files treated as documents
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    // Extensions of the files that can be indexed: those of documents, then those of the
+    // formats converted to text as they are indexed
+    pub fn accepted_extensions(&self) -> Vec<String> {
+        self.extensions.iter().cloned().chain(converted_extensions().map(str::to_string)).collect()
+    }
+
+    pub fn collections(&self) -> &BTreeMap<String, Collection> {
+        &self.collections
+    }
+
+    // Search defaults of a collection named in the config
+    pub fn collection(&self, name: &str) -> Result<&Collection> {
+        self.collections.get(name)
+            .ok_or_else(|| SearchEngineError::InvalidInput(format!("Unknown collection {:?}", name)))
+    }
+
+    // Eagerly loads the document list, line offset and postings tables, for long-running servers
+    pub async fn warm(&self) -> Result<()> {
+        let catalog = self.catalog().await?;
+        // Tables a read-only engine builds aren't kept, so there is nothing to load ahead
+        if !self.read_only {
+            self.warm_documents(&catalog.documents).await;
+        }
+        Ok(())
+    }
+
+    async fn warm_documents(&self, documents: &[CatalogEntry]) {
+        for doc in documents {
+            if let Err(e) = self.doc_store.offsets(&doc.path).await {
+                warn!(path = ?doc.path, error = %e, "Failed to build line offsets");
+            }
+            if let Err(e) = self.postings_store.postings(&doc.path).await {
+                warn!(path = ?doc.path, error = %e, "Failed to build postings");
+            }
+            if let Err(e) = self.embed_document(&doc.path).await {
+                warn!(path = ?doc.path, error = %e, "Failed to embed document");
+            }
+        }
+    }
+
+    // Relevance scoring parameters
+    pub fn bm25(&self) -> Bm25 {
+        self.bm25
+    }
+
+    // Whether accents are folded in indexed text and queries, unless an analysis rule says
+    // otherwise for a document
+    pub fn fold_accents(&self) -> bool {
+        self.analysis.fold_accents
+    }
+
+    // Whether query terms also match words with the same stem, unless an analysis rule says
+    // otherwise for a document
+    pub fn stemming(&self) -> bool {
+        self.analysis.stemming
+    }
+
+    pub fn stem_language(&self) -> StemLanguage {
+        self.analysis.stem_language
+    }
+
+    // The stop words in use for documents no analysis rule covers
+    pub fn stopwords(&self) -> Arc<StopWords> {
+        analysis::current(&self.analyzers).default_analysis().stopwords.clone()
+    }
+
+    // How a document is analyzed, after the analysis rules
+    pub fn analysis_for(&self, file_path: &Path) -> Analysis {
+        analysis::current(&self.analyzers).for_file(file_path).clone()
+    }
+
+    // Rereads the stop word files the engine and its analysis rules were built with and
+    // returns the number of default stop words. Postings tables built with old lists are
+    // rebuilt when next searched.
+    pub async fn reload_stopwords(&self) -> Result<usize> {
+        let analyzers = Analyzers::load(&self.analysis, &self.search_path, &self.roots).await?;
+        let count = analyzers.default_analysis().stopwords.len();
+        self.swap_dictionary(|| *self.analyzers.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(analyzers));
+        Ok(count)
+    }
+
+    // The synonyms searches expand query terms with
+    pub fn synonyms(&self) -> Arc<Synonyms> {
+        self.synonyms.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    // Rereads the synonym file the engine was built with and returns the number of entries
+    // that expand; searches already running keep the synonyms they started with
+    pub async fn reload_synonyms(&self) -> Result<usize> {
+        let synonyms = load_synonyms(self.synonyms_file.as_deref()).await?;
+        let count = synonyms.len();
+        self.swap_dictionary(|| *self.synonyms.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(synonyms));
+        Ok(count)
+    }
+
+    // The results pinned to the top of some queries
+    pub fn pins(&self) -> Arc<Pins> {
+        self.pins.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    // Rereads the pins file the engine was built with and returns the number of pinned
+    // queries; searches already running keep the pins they started with
+    pub async fn reload_pins(&self) -> Result<usize> {
+        let pins = load_pins(self.pins_file.as_deref()).await?;
+        let count = pins.len();
+        self.swap_dictionary(|| *self.pins.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(pins));
+        Ok(count)
+    }
+
+    // Caps applied to every search
+    pub fn limits(&self) -> SearchLimits {
+        self.limits
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn roots(&self) -> &[SearchRoot] {
+        &self.roots
+    }
+
+    // The search root a document was found in, if it isn't in the search directory
+    pub(crate) fn root_of(&self, file_path: &Path) -> Option<&SearchRoot> {
+        self.roots.iter().find(|root| file_path.starts_with(&root.path))
+    }
+
+    // Documents in a search root belong to whoever keeps that directory, so they are never
+    // deleted, trashed or moved
+    pub(crate) fn ensure_owned(&self, file_path: &Path) -> Result<()> {
+        match self.root_of(file_path) {
+            Some(root) => Err(SearchEngineError::Forbidden(format!(
+                "{:?} is in search root {:?}; exclude it there instead", file_path, root.name()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn loaded_catalog(&self) -> Option<Arc<Catalog>> {
+        self.shared.catalog.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub(crate) fn replace_catalog(&self, catalog: Catalog) -> Arc<Catalog> {
+        let catalog = Arc::new(catalog);
+        *self.shared.catalog.write().unwrap_or_else(PoisonError::into_inner) = Some(catalog.clone());
+        catalog
+    }
+
+    // A snapshot of the catalog, scanning the search directory on first use. The scan is
+    // saved, so it is made holding the write locks, which load or scan the catalog.
+    pub(crate) async fn catalog(&self) -> Result<Arc<Catalog>> {
+        if let Some(catalog) = self.loaded_catalog() {
+            return Ok(catalog);
+        }
+        let _write = self.lock_writes().await?;
+        Ok(self.loaded_catalog().expect("catalog loaded by lock_writes"))
+    }
+
+    pub(crate) async fn scan_documents(&self) -> Result<Catalog> {
+        let mut catalog = self.rescan_catalog().await?;
+        if let Err(e) = self.save_catalog(&catalog).await {
+            warn!(error = %e, "Failed to save index metadata");
+        }
+        self.log_changes(std::mem::take(&mut catalog.pending)).await;
+
+        Ok(catalog)
+    }
+
+    // A catalog of the documents on disk now, keeping the attributes given to them at index
+    // time and recording what changed since the loaded catalog, without saving it
+    pub(crate) async fn rescan_catalog(&self) -> Result<Catalog> {
+        let mut catalog = Catalog::scanned(self.walk_documents().await?, Utc::now());
+        let previous = self.loaded_catalog();
+        if let Some(previous) = &previous {
+            catalog.inherit_attributes(previous);
+        }
+        for doc in catalog.documents.iter_mut().filter(|doc| doc.attributes.document_id.is_none()) {
+            doc.attributes.document_id = self.scanned_document_id(&doc.path);
+        }
+        // Changes found by the scan continue from the previous catalog's generation, or from
+        // the change log when there was none
+        catalog.stamp_rescanned(previous.as_ref().map(|previous| previous.documents.as_slice()).unwrap_or_default());
+        match &previous {
+            Some(previous) => {
+                catalog.pending = previous.pending.clone();
+                catalog.record_rescan(&previous.documents, previous.generation);
+            }
+            None => catalog.record_rescan(&[], self.logged_generation().await),
+        }
+        Ok(catalog)
+    }
+
+    // True if the file has one of the configured document extensions
+    fn is_indexable(&self, path: &Path) -> bool {
+        path.extension()
+            .map(|ext| self.extensions.contains(&ext.to_string_lossy().to_lowercase()))
+            .unwrap_or(false)
+    }
+
+    // True if the file can be indexed as it is or once converted to text
+    fn is_accepted(&self, path: &Path) -> bool {
+        self.is_indexable(path) || is_converted(path)
+    }
+
+    // Lists the documents currently in the search directory and the search roots
+    pub(crate) async fn walk_documents(&self) -> Result<Vec<CatalogEntry>> {
+        let mut objects = self.storage.list(&self.search_path, &[INDEX_DIR, TRASH_DIR]).await?;
+        for root in &self.roots {
+            objects.extend(self.storage.list(&root.path, &[INDEX_DIR, TRASH_DIR]).await?
+                .into_iter()
+                .filter(|object| root.relative_path(&object.path).is_some_and(|relative| root.admits(&relative))));
+        }
+        Ok(objects.into_iter()
+            .filter(|object| self.is_indexable(&object.path))
+            .map(|object| CatalogEntry {
+                path: object.path,
+                size: object.size,
+                modified: object.modified,
+                attributes: Default::default(),
+            })
+            .collect())
+    }
+
+    async fn save_catalog(&self, catalog: &Catalog) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.doc_store.save_meta(&self.index_meta(catalog)).await?;
+        self.bump_catalog_revision().await
+    }
+
+    // The catalog as persisted in `.index/meta.json`
+    pub(crate) fn index_meta(&self, catalog: &Catalog) -> IndexMeta {
+        IndexMeta {
+            format_version: INDEX_FORMAT_VERSION,
+            last_scanned: catalog.last_scanned,
+            counters: catalog.counters.clone(),
+            generation: catalog.generation,
+            documents: catalog.documents.iter()
+                .map(|doc| DocumentMeta {
+                    path: self.relative_path(&doc.path),
+                    size: doc.size,
+                    modified: doc.modified,
+                    attributes: doc.attributes.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    // Applies an in-memory change to the catalog; searches already running keep their snapshot
+    pub(crate) async fn update_catalog<R>(&self, change: impl FnOnce(&mut Catalog) -> R) -> Result<R> {
+        self.catalog().await?;
+        let mut slot = self.shared.catalog.write().unwrap_or_else(PoisonError::into_inner);
+        let catalog = slot.as_mut().expect("catalog loaded above");
+        Ok(change(Arc::make_mut(catalog)))
+    }
+
+    // Persists the catalog after an incremental update, flagging the counters
+    // for re-verification if the metadata could not be written
+    pub(crate) async fn commit_catalog(&self) -> Result<()> {
+        let pending = match self.shared.catalog.write().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            Some(catalog) if !catalog.pending.is_empty() => std::mem::take(&mut Arc::make_mut(catalog).pending),
+            _ => Vec::new(),
+        };
+        let result = match self.loaded_catalog() {
+            Some(catalog) => {
+                let saved = self.save_catalog(&catalog).await;
+                self.term_dictionary(&catalog).await;
+                saved
+            }
+            None => Ok(()),
+        };
+        if result.is_err() {
+            if let Some(catalog) = self.shared.catalog.write().unwrap_or_else(PoisonError::into_inner).as_mut() {
+                Arc::make_mut(catalog).mark_dirty();
+            }
+        }
+        self.log_changes(pending).await;
+        result
+    }
+
+    // Copies a text file, or the text of a PDF, Word, OpenDocument or HTML file, into the
+    // search directory (if it isn't already there) and adds it to the index
+    pub async fn index_document(&self, source: &str) -> Result<IndexedDocument> {
+        self.index_document_with_ttl(source, None).await
+    }
+
+    // Like `index_document`, expiring the document after `ttl` (or the engine's default TTL)
+    #[instrument(skip(self))]
+    pub async fn index_document_with_ttl(&self, source: &str, ttl: Option<Duration>) -> Result<IndexedDocument> {
+        let _write = self.lock_writes().await?;
+        let result = self.add_document(source, ttl, None, None, None).await;
+        self.audit_indexed(source, &result).await;
+        result
+    }
+
+    // Like `index_document_with_ttl`, with the caller's ID for the document; required
+    // when the engine uses `IdStrategy::Provided` and ignored by other strategies
+    #[instrument(skip(self))]
+    pub async fn index_document_with_id(&self, source: &str, id: &str, ttl: Option<Duration>) -> Result<IndexedDocument> {
+        let _write = self.lock_writes().await?;
+        let result = self.add_document(source, ttl, Some(id.to_string()), None, None).await;
+        self.audit_indexed(source, &result).await;
+        result
+    }
+
+    // Like `index_document_with_id`, storing `metadata` with the document in place of
+    // whatever it had before; the ID is optional unless the engine uses `IdStrategy::Provided`
+    #[instrument(skip(self, metadata))]
+    pub async fn index_document_with_metadata(
+        &self,
+        source: &str,
+        id: Option<&str>,
+        ttl: Option<Duration>,
+        metadata: Metadata,
+    ) -> Result<IndexedDocument> {
+        let _write = self.lock_writes().await?;
+        let result = self.add_document(source, ttl, id.map(str::to_string), Some(metadata), None).await;
+        self.audit_indexed(source, &result).await;
+        result
+    }
+
+    // Like `index_document_with_metadata`, letting only the principals in `acl` see the
+    // document in searches made on behalf of principals; the list and any metadata given
+    // replace what the document had, and an empty list makes it visible to everyone
+    #[instrument(skip(self, metadata))]
+    pub async fn index_document_with_acl(
+        &self,
+        source: &str,
+        id: Option<&str>,
+        ttl: Option<Duration>,
+        metadata: Option<Metadata>,
+        acl: Vec<String>,
+    ) -> Result<IndexedDocument> {
+        let _write = self.lock_writes().await?;
+        let result = self.add_document(source, ttl, id.map(str::to_string), metadata, Some(acl)).await;
+        self.audit_indexed(source, &result).await;
+        result
+    }
+
+    // Indexes every document a source yields, one at a time. Documents that fail are
+    // reported and skipped; an error from the source itself stops the run.
+    pub async fn index_source<S: DocumentSource>(&self, source: S) -> Result<IndexReport> {
+        self.index_source_with_progress(source, 1, &NoProgress).await
+    }
+
+    // Indexes every document a stream yields, such as the output of an ETL pipeline or a
+    // `tokio_stream::wrappers::ReceiverStream`, persisting the catalog once per `batch_size`
+    // documents instead of after each one. The stream is only polled once the previous
+    // document is stored, so a bounded channel behind it applies backpressure to its producers.
+    pub async fn index_stream<S>(&self, stream: S, batch_size: usize) -> Result<IndexReport>
+    where
+        S: Stream<Item = DocumentInput> + Send,
+    {
+        self.index_source_with_progress(StreamSource::new(stream), batch_size, &NoProgress).await
+    }
+
+    // Like `index_source`, persisting the catalog once per `batch_size` documents and
+    // reporting each document indexed or failed. The write lock is held per document, not
+    // while waiting for the source.
+    #[instrument(skip(self, source, progress))]
+    pub async fn index_source_with_progress<S: DocumentSource>(
+        &self,
+        mut source: S,
+        batch_size: usize,
+        progress: &dyn ProgressReporter,
+    ) -> Result<IndexReport> {
+        let mut tracker = Tracker::new(progress, "index", None);
+        let batch_size = batch_size.max(1);
+        let mut report = IndexReport::default();
+        let mut pending = 0;
+        let outcome = loop {
+            let input = match source.next_document().await {
+                Ok(Some(input)) => input,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            let path = input.path.to_string_lossy().to_string();
+            let _write = self.lock_writes().await?;
+            let result = self.ingest(input).await;
+            self.audit_indexed(&path, &result).await;
+            match result {
+                Ok(document) => {
+                    report.indexed.push(document);
+                    pending += 1;
+                }
+                Err(e) => report.failed.push(IndexFailure { path: path.clone(), error: e.to_string() }),
+            }
+            tracker.advance(path);
+            if pending >= batch_size {
+                self.commit_catalog().await?;
+                pending = 0;
+            }
+        };
+
+        if pending > 0 {
+            let _write = self.lock_writes().await?;
+            self.commit_catalog().await?;
+        }
+        outcome.map(|_| report)
+    }
+
+    async fn audit_indexed(&self, source: &str, result: &Result<IndexedDocument>) {
+        let documents = match result {
+            Ok(document) => vec![document.path.clone()],
+            Err(_) => vec![source.to_string()],
+        };
+        self.audit("index", documents, result.as_ref().map(|_| ()).map_err(|e| e.to_string())).await;
+        if result.is_ok() {
+            self.record_usage(UsageEvent::DocumentIndexed).await;
+        }
+    }
+
+    fn unsupported_document(&self, path: &Path) -> SearchEngineError {
+        SearchEngineError::UnsupportedDocument(format!(
+            "only {} documents can be indexed: {:?}",
+            self.accepted_extensions().iter().map(|ext| format!(".{}", ext))
+                .collect::<Vec<_>>()
+                .join(", "),
+            path
+        ))
+    }
+
+    async fn add_document(
+        &self,
+        source: &str,
+        ttl: Option<Duration>,
+        id: Option<String>,
+        metadata: Option<Metadata>,
+        acl: Option<Vec<String>>,
+    ) -> Result<IndexedDocument> {
+        self.ensure_writable()?;
+        if let Some(metadata) = &metadata {
+            validate_metadata(metadata)?;
+        }
+        if let Some(acl) = &acl {
+            validate_acl(acl)?;
+        }
+        let source = PathBuf::from(source);
+        if !self.is_accepted(&source) {
+            return Err(self.unsupported_document(&source));
+        }
+
+        let source = match async_fs::canonicalize(&source).await {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SearchEngineError::DocumentNotFound(source.to_string_lossy().to_string()));
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to resolve {:?}", source)),
+        };
+        let search_root = async_fs::canonicalize(&self.search_path).await
+            .context("Failed to resolve search directory")?;
+
+        // Files already in the search directory, or admitted by a search root, are indexed where they are
+        let mut in_place = source.strip_prefix(&search_root).ok().map(|relative| self.search_path.join(relative));
+        for root in &self.roots {
+            let Ok(root_path) = async_fs::canonicalize(&root.path).await else {
+                continue;
+            };
+            let relative = source.strip_prefix(&root_path).ok()
+                .and_then(|relative| root.relative_path(&root.path.join(relative)));
+            if let Some(relative) = relative.filter(|relative| root.admits(relative)) {
+                in_place = Some(root.document_path(&relative));
+            }
+        }
+        // Those in other formats are still converted, and their text stored beside them
+        if let Some(target) = in_place.filter(|_| !is_converted(&source)) {
+            if let Some(stored) = self.storage.stat(&target).await? {
+                let id = self.assign_document_id(&target, &target, id).await?;
+                self.enforce_quota(&target, stored.size, None).await?;
+                let document = self.finish_indexing(stored, ttl, id, metadata, acl, None).await?;
+                self.commit_catalog().await?;
+                return Ok(document);
+            }
+        }
+
+        let file_name = source.file_name()
+            .ok_or_else(|| SearchEngineError::UnsupportedDocument(format!("{:?} has no file name", source)))?;
+        let content = async_fs::File::open(&source).await
+            .context("Failed to open document")?;
+        let mut input = DocumentInput::new(file_name, Box::pin(content)).ttl(ttl);
+        input.id = id;
+        input.metadata = metadata;
+        input.acl = acl;
+        let document = self.ingest(input).await?;
+        self.commit_catalog().await?;
+        Ok(document)
+    }
+
+    // Stores a document's content in the search directory, converted to text if it is in
+    // another format, and indexes it. The content goes to a temporary name first, so a
+    // document rejected by the quota never replaces the one already stored. Leaves the
+    // catalog uncommitted.
+    async fn ingest(&self, mut input: DocumentInput) -> Result<IndexedDocument> {
+        self.ensure_writable()?;
+        let relative = input.path.as_path();
+        let inside = !relative.as_os_str().is_empty() && relative.components().all(|part| matches!(part, Component::Normal(_)));
+        if !inside || relative.starts_with(INDEX_DIR) || relative.starts_with(TRASH_DIR) {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "{:?} must be a relative path inside the search directory", relative
+            )));
+        }
+        if !self.is_accepted(relative) {
+            return Err(self.unsupported_document(relative));
+        }
+        if let Some(metadata) = &input.metadata {
+            validate_metadata(metadata)?;
+        }
+        if let Some(acl) = &input.acl {
+            validate_acl(acl)?;
+        }
+        if is_converted(relative) {
+            let converted = convert(relative, input.content).await?;
+            input.path = text_path(relative);
+            input.content = Box::pin(std::io::Cursor::new(converted.text.into_bytes()));
+            input.title = input.title.or(converted.title);
+        }
+        let relative = input.path.as_path();
+
+        let unpartitioned = self.search_path.join(relative);
+        let temp = temp_path(&unpartitioned);
+        let written = self.storage.write(&temp, input.content).await?;
+        let target = match self.partitioning.filter(|_| partition_of(&relative.to_string_lossy()).is_none()) {
+            Some(partitioning) => {
+                let date = match input.date {
+                    Some(date) => date,
+                    None => self.detect_document_date(&temp, written.size).await,
+                };
+                self.search_path.join(partitioning.key(date)).join(relative)
+            }
+            None => unpartitioned,
+        };
+        let id = match self.assign_document_id(&target, &temp, input.id).await {
+            Ok(id) => id,
+            Err(e) => {
+                self.discard_temp(&temp).await;
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.enforce_quota(&target, written.size, None).await {
+            self.discard_temp(&temp).await;
+            return Err(e);
+        }
+        if let Err(e) = self.storage.rename(&temp, &target).await {
+            self.discard_temp(&temp).await;
+            return Err(e);
+        }
+
+        let stored = StoredObject { path: target, ..written };
+        self.finish_indexing(stored, input.ttl, id, input.metadata, input.acl, input.title).await
+    }
+
+    // A stored document's date as found near its start, or the current time
+    async fn detect_document_date(&self, file_path: &Path, size: u64) -> DateTime<Utc> {
+        match self.storage.read_range(file_path, 0, size.min(DATE_SCAN_BYTES)).await {
+            Ok(head) => detect_date(&String::from_utf8_lossy(&head)).unwrap_or_else(Utc::now),
+            Err(e) => {
+                warn!(path = ?file_path, error = %e, "Failed to read document date");
+                Utc::now()
+            }
+        }
+    }
+
+    async fn discard_temp(&self, temp: &Path) {
+        if let Err(e) = self.storage.remove(temp).await {
+            warn!(path = ?temp, error = %e, "Failed to remove temporary document");
+        }
+    }
+
+    // Builds the line offsets and postings for a stored document and adds it to the catalog.
+    // Without new metadata or access control list, a re-indexed document keeps those it had;
+    // its title is the one given, as it comes with the content.
+    async fn finish_indexing(
+        &self,
+        stored: StoredObject,
+        ttl: Option<Duration>,
+        id: String,
+        metadata: Option<Metadata>,
+        acl: Option<Vec<String>>,
+        title: Option<String>,
+    ) -> Result<IndexedDocument> {
+        let offsets = self.doc_store.offsets(&stored.path).await?;
+        self.embed_document(&stored.path).await?;
+        let text = self.read_document(&stored.path).await?;
+        let postings = self.postings_store.table_from_text(&stored.path, FileStamp::from_object(&stored), &text);
+        self.postings_store.save(&stored.path, &postings).await?;
+        let fingerprint = simhash(&text);
+        let keywords = extract_keywords(&text, MAX_KEYWORDS);
+        let language = detect_language(&text).to_string();
+        let page_breaks = page_breaks(&text);
+        let indexed_at = Utc::now();
+        let expires_at = ttl.or(self.default_ttl).map(|ttl| indexed_at + ttl);
+        // Re-indexing a document keeps the tags it was given
+        let previous = self.loaded_catalog()
+            .and_then(|catalog| catalog.get(&stored.path).map(|doc| doc.attributes.clone()))
+            .unwrap_or_default();
+        let tags = previous.tags;
+        let metadata = metadata.unwrap_or(previous.metadata);
+        let acl = acl.unwrap_or(previous.acl);
+
+        let entry = CatalogEntry {
+            path: stored.path.clone(),
+            size: stored.size,
+            modified: stored.modified,
+            attributes: DocumentAttributes {
+                expires_at,
+                indexed_at: Some(indexed_at),
+                document_id: Some(id.clone()),
+                fingerprint: Some(fingerprint),
+                keywords: keywords.clone(),
+                language: Some(language.clone()),
+                page_breaks,
+                title: title.clone(),
+                tags: tags.clone(),
+                metadata: metadata.clone(),
+                acl: acl.clone(),
+            },
+        };
+        self.update_catalog(|catalog| catalog.insert(entry)).await?;
+        info!(path = ?stored.path, size = stored.size, "Indexed document");
+        self.check_alerts(&stored.path).await;
+
+        Ok(IndexedDocument {
+            path: stored.path.to_string_lossy().to_string(),
+            size: stored.size,
+            line_count: offsets.line_count(),
+            indexed_at,
+            expires_at,
+            document_id: Some(id),
+            keywords,
+            language: Some(language),
+            title,
+            tags,
+            metadata,
+            acl,
+        })
+    }
+
+    // Whole text of a document, one line per line of the file
+    pub(crate) async fn read_document(&self, file_path: &Path) -> Result<String> {
+        let mut lines = BufReader::new(self.storage.open(file_path).await?).lines();
+        let mut text = String::new();
+        while let Some(line) = lines.next_line().await? {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    // Removes a document from the index, moving it to the trash if enabled; returns false if it wasn't indexed
+    #[instrument(skip(self))]
+    pub async fn delete_document(&self, path: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let _write = self.lock_writes().await?;
+        let Some(file_path) = self.resolve_document(path).await? else {
+            return Ok(false);
+        };
+
+        let result = match self.discard_document(&file_path).await {
+            Ok(_) => self.commit_catalog().await,
+            Err(e) => Err(e),
+        };
+        let documents = vec![file_path.to_string_lossy().to_string()];
+        self.audit("delete", documents, result.as_ref().map_err(|e| e.to_string()).cloned()).await;
+        if result.is_ok() {
+            self.record_usage(UsageEvent::DocumentDeleted).await;
+        }
+        result.map(|_| true)
+    }
+
+    // Removes an indexed file and its derived data, leaving the catalog uncommitted
+    pub(crate) async fn remove_document(&self, file_path: &Path) -> Result<()> {
+        self.ensure_owned(file_path)?;
+        if !self.storage.remove(file_path).await? {
+            // Already gone from storage, so the catalog was out of sync
+            self.update_catalog(Catalog::mark_dirty).await?;
+        }
+        self.doc_store.remove_offsets(file_path).await?;
+        self.vector_store.remove(file_path).await?;
+        self.postings_store.remove(file_path).await?;
+
+        self.update_catalog(|catalog| catalog.remove(file_path)).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub(crate) async fn refresh_file_cache(&self) -> Result<()> {
+        let previous = self.loaded_catalog();
+        let catalog = self.scan_documents().await?;
+
+        // Pre-compute line offset and postings tables so searches and single lines don't rescan;
+        // a read-only engine couldn't keep them
+        if !self.read_only {
+            self.warm_documents(&catalog.documents).await;
+        }
+
+        let catalog = self.replace_catalog(catalog);
+        // Files added or changed on disk since the last scan
+        if let Some(previous) = previous {
+            for doc in &catalog.documents {
+                let unchanged = previous.documents.iter()
+                    .any(|old| old.path == doc.path && old.size == doc.size && old.modified == doc.modified);
+                if !unchanged {
+                    self.check_alerts(&doc.path).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Path under the search directory, or the root's name and the path within it for
+    // documents in a search root
+    pub(crate) fn relative_path(&self, file_path: &Path) -> String {
+        relative_path(&self.search_path, &self.roots, file_path)
+    }
+
+    // Inverse of `relative_path`
+    pub(crate) fn absolute_path(&self, relative: &str) -> PathBuf {
+        absolute_path(&self.search_path, &self.roots, relative)
+    }
+
+    // Finds an indexed document by path or document ID
+    pub(crate) async fn resolve_document(&self, path: &str) -> Result<Option<PathBuf>> {
+        let requested = Path::new(path);
+        let absolute = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.absolute_path(path)
+        };
+
+        let catalog = self.catalog().await?;
+        let by_path = catalog.documents.iter()
+            .find(|doc| doc.path == absolute || doc.path.as_path() == requested);
+        Ok(by_path
+            .or_else(|| catalog.documents.iter().find(|doc| doc.attributes.document_id.as_deref() == Some(path)))
+            .map(|doc| doc.path.clone()))
+    }
+
+    // Like `resolve_document`, treating a document `principals` may not see as not indexed
+    async fn resolve_visible(&self, path: &str, principals: Option<&[String]>) -> Result<Option<PathBuf>> {
+        let Some(file_path) = self.resolve_document(path).await? else {
+            return Ok(None);
+        };
+        let catalog = self.catalog().await?;
+        Ok(catalog.documents.iter()
+            .find(|doc| doc.path == file_path)
+            .filter(|doc| permits(&doc.attributes.acl, principals))
+            .map(|doc| doc.path.clone()))
+    }
+
+    // Line `line_number` of a document, found by path or document ID, if `principals` may see it
+    pub async fn get_line(&self, path: &str, line_number: usize, principals: Option<&[String]>) -> Result<Option<LineResult>> {
+        let Some(file_path) = self.resolve_visible(path, principals).await? else {
+            return Ok(None);
+        };
+
+        let _tables = self.shared.index_lock.read().await;
+        let content = self.doc_store.read_line(&file_path, line_number).await?;
+        Ok(content.map(|content| LineResult {
+            path: file_path.to_string_lossy().to_string(),
+            line_number: line_number as i64,
+            content,
+        }))
+    }
+
+    // Lines `start` to `end` of a document, found by path or document ID, with one read of the
+    // bytes its line offsets say they span, e.g. to show more context around a search hit.
+    // A range running past the last line stops there; None if the document has no line `start`
+    // or `principals` may not see it.
+    pub async fn get_lines(&self, path: &str, start: usize, end: usize, principals: Option<&[String]>) -> Result<Option<LineRange>> {
+        if start == 0 || end < start {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "Invalid line range {}-{}: lines are numbered from 1 and the range can't end before it starts", start, end
+            )));
+        }
+        if end - start >= MAX_LINE_RANGE {
+            return Err(SearchEngineError::InvalidInput(format!("At most {} lines can be fetched at once", MAX_LINE_RANGE)));
+        }
+        let Some(file_path) = self.resolve_visible(path, principals).await? else {
+            return Ok(None);
+        };
+
+        let _tables = self.shared.index_lock.read().await;
+        let offsets = self.doc_store.offsets(&file_path).await?;
+        let end = end.min(offsets.line_count());
+        let (Some((from, _)), Some((_, to))) = (offsets.line_range(start), offsets.line_range(end)) else {
+            return Ok(None);
+        };
+        let buffer = self.storage.read_range(&file_path, from, to).await?;
+        let lines = trim_line_ending(&buffer).split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+        Ok(Some(LineRange {
+            path: file_path.to_string_lossy().to_string(),
+            start: start as i64,
+            end: end as i64,
+            total_lines: offsets.line_count(),
+            lines,
+        }))
+    }
+
+    // Searches with every query word also matching words up to `max_distance` edits away,
+    // ranked below exact matches
+    pub async fn search_fuzzy(&self, query: &str, max_distance: usize, options: &SearchOptions) -> Result<SearchResponse> {
+        let options = SearchOptions { fuzzy: Some(max_distance), ..options.clone() };
+        self.search(query, &options).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn search(&self, query: &str, options: &SearchOptions) -> Result<SearchResponse> {
+        let query = normalize_query(query, options.mode)?;
+        let mut parsed = parse_query(&query, options.mode, options.fuzzy, options.case_sensitive)?;
+        options.validate()?;
+        if options.mode != MatchMode::Regex && !options.case_sensitive {
+            parsed.tree = self.synonyms().expand(&parsed.text, parsed.tree.take());
+        }
+        let limits = self.limits.tightest(options.limits);
+        let analyzers = analysis::current(&self.analyzers);
+        // One matcher for each way documents are analyzed, the engine's first
+        let base = Matcher::parsed(&parsed, options.mode, options.fuzzy, options.case_sensitive, &limits)?;
+        let matchers: Vec<Matcher> = analyzers.all().map(|analysis| base.clone().with_analysis(analysis)).collect();
+        let matcher = &matchers[0];
+        let mut filters = options.filters.clone();
+        parsed.fields.apply(&mut filters);
+        let started_at = std::time::Instant::now();
+        let deadline = options.timeout
+            .and_then(|timeout| timeout.to_std().ok())
+            .map(|timeout| started_at + timeout);
+        // The slow query log needs a profile of any search that might be slow
+        let mut profiler = Profiler::new(options.profile || self.slow_query_threshold.is_some());
+        let mut results = Vec::new();
+        let mut timed_out = false;
+        let mut limited = false;
+        let (limit, offset) = (options.limit, options.offset);
+        
+        // Pre-calculate how many results we need to collect for efficient memory usage
+        let target_results = offset + limit;
+        let early_stop_threshold = if options.save_results.is_some() {
+            usize::MAX // A saved result set holds every match
+        } else if target_results > 10000 {
+            target_results + 20000 // For large result sets, collect a reasonable amount extra
+        } else {
+            target_results * 3 // For smaller sets, use 3x multiplier
+        };
+        
+        let _tables = self.shared.index_lock.read().await;
+        let started = profiler.start();
+        let catalog = match &options.reader {
+            Some(reader) => self.reader_catalog(reader).await?,
+            None => self.catalog().await?,
+        };
+        let within = match &options.within {
+            Some(id) => Some(self.result_set_lines(id).await?),
+            None => None,
+        };
+        let mut documents: Vec<&CatalogEntry> = catalog.documents.iter()
+            .filter(|doc| within.as_ref().is_none_or(|lines| lines.contains_key(&doc.path)))
+            .filter(|doc| filters.matches(&self.relative_path(&doc.path), doc.attributes.title.as_deref()) && filters.matches_attributes(&doc.attributes.tags, &doc.attributes.metadata, doc.attributes.language.as_deref(), doc.modified, &doc.attributes.acl))
+            .collect();
+        // A reader's catalog is of documents as they were, which the dictionary may not list
+        if options.reader.is_none() {
+            self.term_dictionary(&catalog).await.narrow(&mut documents, &matchers, &analyzers);
+        }
+        // Pinned documents are searched first, so early stopping never leaves them out
+        let pinned = self.pins().ranks(&query, &catalog, |path| self.relative_path(path));
+        if !pinned.is_empty() {
+            documents.sort_by_key(|doc| pinned.get(&doc.path).copied().unwrap_or(usize::MAX));
+        }
+        if let Some(max) = limits.max_documents.filter(|max| documents.len() > *max) {
+            documents.truncate(max);
+            limited = true;
+        }
+        profiler.record(Phase::CandidateSelection, started);
+        // IDF comes from every document, not just the ones the filters leave
+        let started = profiler.start();
+        let stats = if Scorer::needs_stats(matcher) {
+            self.corpus_stats(&catalog, options.reader.is_none()).await
+        } else {
+            Arc::default()
+        };
+        let scorers: Vec<Scorer> = matchers.iter().map(|matcher| Scorer::new(matcher, self.bm25, &stats)).collect();
+        profiler.record(Phase::Scoring, started);
+        let mut tag_counts: BTreeMap<String, usize> = BTreeMap::new();
+        let mut facets = BTreeMap::new();
+
+        // Documents are searched `parallelism` at a time, and their matches taken in order
+        let documents: Vec<(usize, &CatalogEntry)> = documents.into_iter().enumerate().collect();
+        'documents: for batch in documents.chunks(self.parallelism) {
+            if deadline.map(|deadline| std::time::Instant::now() >= deadline).unwrap_or(false) {
+                timed_out = true;
+                break;
+            }
+            // One more than the matches still allowed, to tell whether the cap cut any off
+            let max_results = limits.max_matches
+                .map(|max| early_stop_threshold.min(max - results.len() + 1))
+                .unwrap_or(early_stop_threshold);
+            let searches = batch.iter().map(|(_, doc)| {
+                let scorer = &scorers[analyzers.index_for(&doc.path)];
+                let mut file_profiler = Profiler::new(profiler.is_enabled());
+                async move {
+                    let searched = self.search_document(&doc.path, scorer, options.highlight, max_results, deadline, &mut file_profiler).await;
+                    (searched, file_profiler)
+                }
+            });
+            for (&(file_idx, doc), (searched, file_profiler)) in batch.iter().zip(join_all(searches).await) {
+                let file_path = doc.path.as_path();
+                profiler.count_file();
+                profiler.merge(file_profiler);
+                match searched {
+                    Ok(FileMatches { results: mut file_results, timed_out: file_timed_out, limited: file_limited }) => {
+                        limited |= file_limited;
+                        if let Some(lines) = within.as_ref().and_then(|lines| lines.get(&doc.path)) {
+                            file_results.retain(|result| lines.contains(&result.line_number));
+                        }
+                        if !file_results.is_empty() {
+                            let boost = options.boost(&self.relative_path(file_path));
+                            for tag in &doc.attributes.tags {
+                                *tag_counts.entry(tag.clone()).or_insert(0) += 1;
+                            }
+                            count_facets(&mut facets, &options.facets, &doc.attributes.metadata);
+                            describe_results(doc, file_idx, &mut file_results);
+                            results.extend(file_results.into_iter().map(|mut result| {
+                                result.score *= boost;
+                                result
+                            }));
+                        }
+                        if file_timed_out {
+                            timed_out = true;
+                            break 'documents;
+                        }
+                    }
+                    Err(e) => {
+                        warn!(path = ?file_path, error = %e, "Failed to search file");
+                    }
+                }
+
+                if let Some(max) = limits.max_matches.filter(|max| results.len() > *max) {
+                    results.truncate(max);
+                    limited = true;
+                    break 'documents;
+                }
+
+                // Early termination if we have enough results for sorting
+                if results.len() >= early_stop_threshold {
+                    break 'documents;
+                }
+            }
+        }
+
+        let started = profiler.start();
+        matching::sort_results(&mut results, options.sort, options.collation.as_deref());
+        profiler.record(Phase::Sorting, started);
+
+        // Keep results from only the best-ranked document of each near-duplicate cluster
+        if options.collapse_duplicates {
+            let representatives = self.duplicate_representatives(&catalog);
+            let mut shown: HashMap<&Path, Arc<str>> = HashMap::new();
+            results.retain(|result| match representatives.get(Path::new(&*result.path)) {
+                Some(representative) => *shown.entry(representative).or_insert_with(|| result.path.clone()) == result.path,
+                None => true,
+            });
+        }
+        
+        if let Some(max) = matching::per_document_limit(options) {
+            matching::limit_per_document(&mut results, max);
+        }
+        if options.diversify {
+            matching::diversify(&mut results, options.sort);
+        }
+        pins::promote(&mut results, &pinned);
+        
+        let result_set = match options.save_results {
+            Some(keep_alive) => Some(self.save_result_set(&query, options.within.as_deref(), keep_alive, &results).await?.id),
+            None => None,
+        };
+        let total = results.len();
+        let mut paginated_results: Vec<SearchResult> = results
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .collect();
+        if options.summarize {
+            let started = profiler.start();
+            for result in &mut paginated_results {
+                match self.read_document(Path::new(&*result.path)).await {
+                    Ok(text) => result.summary = Some(summarize(&text, matcher, SUMMARY_SENTENCES)),
+                    Err(e) => warn!(path = %result.path, error = %e, "Failed to summarize document"),
+                }
+            }
+            profiler.record(Phase::Io, started);
+        }
+        let clusters = cluster_results(
+            paginated_results.iter().map(|result| (result.id.as_str(), result.content.as_str())),
+            &parsed.text,
+            options.clusters,
+        );
+
+        // Measure what serializing the returned page costs
+        if profiler.is_enabled() {
+            let started = profiler.start();
+            serde_json::to_vec(&paginated_results)?;
+            profiler.record(Phase::Serialization, started);
+        }
+
+        debug!(total, returned = paginated_results.len(), timed_out, limited, "Search completed");
+        let latency = started_at.elapsed();
+        self.log_query(&query, total, &paginated_results, latency).await;
+        let profile = profiler.finish();
+        if let Some(profile) = &profile {
+            self.log_slow_query(&query, options, total, latency, profile).await;
+        }
+        self.record_usage(UsageEvent::Query).await;
+
+        Ok(SearchResponse {
+            query,
+            results: paginated_results,
+            total,
+            limit,
+            offset,
+            terms: if options.highlight { matcher.terms().to_vec() } else { Vec::new() },
+            tag_counts,
+            facets,
+            timed_out,
+            profile: profile.filter(|_| options.profile),
+            clusters,
+            query_tree: matcher.tree().cloned(),
+            limited,
+            result_set,
+        })
+    }
+
+
+    // Matching lines of one document from its postings table, or by scanning it when the
+    // table can't narrow the query or fails to load
+    pub(crate) async fn search_document(
+        &self,
+        file_path: &Path,
+        scorer: &Scorer<'_>,
+        highlight: bool,
+        max_results: usize,
+        deadline: Option<std::time::Instant>,
+        profiler: &mut Profiler,
+    ) -> Result<FileMatches> {
+        match self.search_in_file_indexed(file_path, scorer, highlight, max_results, deadline, profiler).await {
+            Ok(Some(searched)) => Ok(searched),
+            Ok(None) => self.search_in_file_progressive(file_path, scorer, highlight, max_results, deadline, profiler).await,
+            Err(e) => {
+                warn!(path = ?file_path, error = %e, "Failed to search postings, scanning instead");
+                self.search_in_file_progressive(file_path, scorer, highlight, max_results, deadline, profiler).await
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip(self, scorer, highlight, max_results, deadline, profiler), fields(path = ?file_path))]
+    async fn search_in_file_progressive(
+        &self,
+        file_path: &Path,
+        scorer: &Scorer<'_>,
+        highlight: bool,
+        max_results: usize,
+        deadline: Option<std::time::Instant>,
+        profiler: &mut Profiler,
+    ) -> Result<FileMatches> {
+        let mut results = Vec::new();
+        let file_path_str: Arc<str> = Arc::from(file_path.to_string_lossy());
+        
+        // Open file and create buffered reader for streaming large files
+        let started = profiler.start();
+        let file = self.storage.open(file_path).await?;
+        profiler.record(Phase::Io, started);
+        
+        // Use larger buffer for massive log files (4MB buffer)
+        let reader = BufReader::with_capacity(4 * 1024 * 1024, file);
+        let mut lines = reader.lines();
+        
+        let filename = file_path.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+        
+        let mut presence = Presence::new(scorer.matcher());
+        let mut line_number = 0;
+        let mut timed_out = false;
+        loop {
+            // Checking the clock on every line would slow down the scan of large files
+            if line_number % 1024 == 0 && deadline.map(|deadline| std::time::Instant::now() >= deadline).unwrap_or(false) {
+                timed_out = true;
+                break;
+            }
+            let started = profiler.start();
+            let next_line = lines.next_line().await?;
+            profiler.record(Phase::Io, started);
+            let Some(line) = next_line else {
+                break;
+            };
+
+            line_number += 1;
+            presence.observe(scorer.matcher(), &line);
+            if let Some(result) = match_line_result(scorer, line, line_number, highlight, &file_path_str, &filename, profiler) {
+                if results.len() < max_results {
+                    results.push(result);
+                }
+            }
+            // Early termination within file if we have enough matches, once the rest of a
+            // boolean query's terms can't change whether the document matches
+            if results.len() >= max_results && presence.is_complete() {
+                break;
+            }
+        }
+        if !presence.satisfies(scorer.matcher()) {
+            results.clear();
+        }
+        
+        Ok(FileMatches { results, timed_out, limited: false })
+    }
+
+    pub(crate) fn query_log_path(&self) -> PathBuf {
+        self.doc_store.index_dir().join(QUERY_LOG_FILE)
+    }
+
+    async fn log_query(&self, query: &str, total: usize, results: &[SearchResult], latency: std::time::Duration) {
+        if !self.query_log {
+            return;
+        }
+        let mut hits: Vec<String> = results.iter()
+            .map(|result| self.relative_path(Path::new(&*result.path)))
+            .collect();
+        hits.sort();
+        hits.dedup();
+        let entry = QueryLogEntry {
+            timestamp: Utc::now(),
+            query: query.to_string(),
+            total,
+            latency_us: latency.as_micros() as u64,
+            hits,
+        };
+        if let Err(e) = async_fs::create_dir_all(self.doc_store.index_dir()).await {
+            warn!(error = %e, "Failed to create index directory for query log");
+        } else if let Err(e) = querylog::append(&self.query_log_path(), &entry).await {
+            warn!(error = %e, "Failed to append to query log");
+        }
+    }
+
+    pub(crate) fn slow_log_path(&self) -> PathBuf {
+        self.doc_store.index_dir().join(SLOW_LOG_FILE)
+    }
+
+    async fn log_slow_query(&self, query: &str, options: &SearchOptions, total: usize, latency: std::time::Duration, profile: &QueryProfile) {
+        let Some(threshold) = self.slow_query_threshold.and_then(|threshold| threshold.to_std().ok()) else {
+            return;
+        };
+        if latency < threshold {
+            return;
+        }
+        let entry = SlowQueryEntry {
+            timestamp: Utc::now(),
+            query: query.to_string(),
+            total,
+            latency_us: latency.as_micros() as u64,
+            options: serde_json::to_value(options).unwrap_or_default(),
+            profile: profile.clone(),
+        };
+        if let Err(e) = async_fs::create_dir_all(self.doc_store.index_dir()).await {
+            warn!(error = %e, "Failed to create index directory for slow query log");
+        } else if let Err(e) = querylog::append(&self.slow_log_path(), &entry).await {
+            warn!(error = %e, "Failed to append to slow query log");
+        }
+    }
+
+    // Searches that took longer than the slow query threshold, oldest first, at most the
+    // `limit` most recent
+    pub async fn slow_queries(&self, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Result<Vec<SlowQueryEntry>> {
+        querylog::read_slow(&self.slow_log_path(), since, limit).await
+    }
+
+    // Aggregates the query log: top and zero-result queries, latencies, and hourly volume
+    pub async fn query_analytics(&self, top: usize, since: Option<DateTime<Utc>>) -> Result<QueryAnalytics> {
+        let entries = querylog::read(&self.query_log_path(), since).await?;
+        Ok(querylog::analyze(&entries, top, since))
+    }
+
+    // Keywords shared by the most documents `principals` may see, most common first
+    pub async fn keyword_counts(&self, top: usize, principals: Option<&[String]>) -> Result<Vec<KeywordCount>> {
+        let catalog = self.catalog().await?;
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for keyword in visible_documents(&catalog, principals).flat_map(|doc| &doc.attributes.keywords) {
+            *counts.entry(keyword).or_insert(0) += 1;
+        }
+        let mut counts: Vec<KeywordCount> = counts.into_iter()
+            .map(|(keyword, documents)| KeywordCount { keyword: keyword.to_string(), documents })
+            .collect();
+        counts.sort_by(|a, b| b.documents.cmp(&a.documents).then_with(|| a.keyword.cmp(&b.keyword)));
+        counts.truncate(top);
+        Ok(counts)
+    }
+
+    // For each metadata field, the number of documents `principals` may see with each of its values
+    pub async fn facet_counts(&self, fields: &[String], principals: Option<&[String]>) -> Result<BTreeMap<String, BTreeMap<String, usize>>> {
+        let catalog = self.catalog().await?;
+        let mut facets = BTreeMap::new();
+        for doc in visible_documents(&catalog, principals) {
+            count_facets(&mut facets, fields, &doc.attributes.metadata);
+        }
+        Ok(facets)
+    }
+
+    // Time partitions holding indexed documents, oldest first
+    pub async fn partitions(&self) -> Result<Vec<PartitionStats>> {
+        let catalog = self.catalog().await?;
+        let mut partitions: BTreeMap<(DateTime<Utc>, String), PartitionStats> = BTreeMap::new();
+        for doc in &catalog.documents {
+            let relative = self.relative_path(&doc.path);
+            let Some(key) = partition_of(&relative) else {
+                continue;
+            };
+            let Some((start, end)) = partition_span(key) else {
+                continue;
+            };
+            let partition = partitions.entry((start, key.to_string())).or_insert_with(|| PartitionStats {
+                key: key.to_string(),
+                start,
+                end,
+                documents: 0,
+                size_bytes: 0,
+            });
+            partition.documents += 1;
+            partition.size_bytes += doc.size;
+        }
+        Ok(partitions.into_values().collect())
+    }
+
+    pub async fn get_stats(&self) -> Result<Stats> {
+        let catalog = self.catalog().await?;
+        
+        let counters = &catalog.counters;
+        
+        Ok(Stats {
+            total_documents: counters.total_documents,
+            index_size_bytes: counters.total_bytes,
+            last_updated: catalog.last_scanned,
+            search_path: self.search_path.to_string_lossy().to_string(),
+            per_extension: counters.per_extension.clone(),
+            dirty: counters.dirty,
+            last_verified: counters.last_verified,
+            documents: Vec::new(),
+        })
+    }
+
+    pub async fn get_status(&self) -> Result<Status> {
+        let healthy = self.search_path.exists() && self.search_path.is_dir();
+        let catalog = self.catalog().await?;
+        let dictionaries = self.dictionary_version();
+
+        Ok(Status {
+            index_exists: healthy,
+            index_healthy: healthy,
+            total_documents: catalog.counters.total_documents,
+            index_size_bytes: catalog.counters.total_bytes,
+            last_updated: catalog.last_scanned,
+            quota: self.quota_status().await?,
+            read_only: self.read_only || self.replica.is_some(),
+            fold_accents: self.analysis.fold_accents,
+            stemming: self.analysis.stemming,
+            stem_language: self.analysis.stemming.then_some(self.analysis.stem_language),
+            ngrams: self.analysis.ngrams,
+            stopwords: dictionaries.stopwords,
+            synonyms: dictionaries.synonyms,
+            pins: dictionaries.pins,
+            dictionary_version: dictionaries.version,
+            analyzer: self.analysis.analyzer.as_ref().map(|analyzer| analyzer.name().to_string()),
+            scheduled_tasks: Vec::new(),
+        })
+    }
+}
+
+// A document's path relative to the search directory, or its root's name and its path within
+// the root
+pub(crate) fn relative_path(search_path: &Path, roots: &[SearchRoot], file_path: &Path) -> String {
+    if let Ok(relative) = file_path.strip_prefix(search_path) {
+        return relative.to_string_lossy().to_string();
+    }
+    roots.iter().find(|root| file_path.starts_with(&root.path))
+        .and_then(|root| root.relative_path(file_path).map(|relative| format!("{}/{}", root.name(), relative)))
+        .unwrap_or_else(|| file_path.to_string_lossy().to_string())
+}
+
+// The documents of `catalog` that `principals` may see
+pub(crate) fn visible_documents<'a>(catalog: &'a Catalog, principals: Option<&'a [String]>) -> impl Iterator<Item = &'a CatalogEntry> {
+    catalog.documents.iter().filter(move |doc| permits(&doc.attributes.acl, principals))
+}
+
+// A catalog as saved in `.index/meta.json`
+pub(crate) fn restore_catalog(search_path: &Path, roots: &[SearchRoot], meta: IndexMeta) -> Catalog {
+    let documents = meta.documents.into_iter()
+        .map(|doc| CatalogEntry {
+            path: absolute_path(search_path, roots, &doc.path),
+            size: doc.size,
+            modified: doc.modified,
+            attributes: doc.attributes,
+        })
+        .collect();
+    Catalog::restored(documents, meta.counters, meta.last_scanned, meta.generation)
+}
+
+// Where the document at `relative`, as `SearchEngine::relative_path` gives it, is stored; a
+// first segment naming a search root refers to that root
+pub(crate) fn absolute_path(search_path: &Path, roots: &[SearchRoot], relative: &str) -> PathBuf {
+    relative.split_once(['/', '\\'])
+        .and_then(|(name, rest)| roots.iter().find(|root| root.name() == name).map(|root| root.document_path(rest)))
+        .unwrap_or_else(|| search_path.join(relative))
+}
+
+// What searching one document found
+pub(crate) struct FileMatches {
+    pub(crate) results: Vec<SearchResult>,
+    // The deadline passed before the end of the document
+    pub(crate) timed_out: bool,
+    // A query term expanded to more indexed terms than allowed, so matches may be missing
+    pub(crate) limited: bool,
+}
+
+// Fills in what results take from their document: IDs numbered by its place among the
+// documents searched, its attributes, and its page and title, if any, on their titles
+pub(crate) fn describe_results(doc: &CatalogEntry, file_idx: usize, results: &mut [SearchResult]) {
+    let document_id: Option<Arc<str>> = doc.attributes.document_id.as_deref().map(Arc::from);
+    // Catalogs from before rescans recorded it only know when the file changed
+    let indexed_at = doc.attributes.indexed_at.unwrap_or(doc.modified);
+    let metadata = (!doc.attributes.metadata.is_empty()).then(|| Arc::new(doc.attributes.metadata.clone()));
+    let breaks = &doc.attributes.page_breaks;
+    let document_title: Option<Arc<str>> = doc.attributes.title.as_deref().map(Arc::from);
+    let label = match &doc.attributes.title {
+        Some(title) => title.clone(),
+        None => doc.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+    };
+    for result in results {
+        result.id = format!("{}-{}", file_idx, result.line_number);
+        result.document_id = document_id.clone();
+        result.metadata = metadata.clone();
+        if !breaks.is_empty() {
+            let page = page_of(breaks, result.line_number as usize);
+            result.page = Some(page);
+            result.title = format!("{} (page {})", label, page);
+        } else if document_title.is_some() {
+            result.title = format!("{} (line {})", label, result.line_number);
+        }
+        result.document_title = document_title.clone();
+        result.indexed_at = indexed_at;
+        result.modified = Some(doc.modified);
+    }
+}
+
+// A result for `line` if it matches, recording the matching and scoring time
+pub(crate) fn match_line_result(
+    scorer: &Scorer,
+    line: String,
+    line_number: usize,
+    highlight: bool,
+    path: &Arc<str>,
+    filename: &str,
+    profiler: &mut Profiler,
+) -> Option<SearchResult> {
+    profiler.count_line();
+    let started = profiler.start();
+    let matcher = scorer.matcher();
+    let line_lower = matcher.fold(&line);
+    let matched = matcher.is_match(&line, &line_lower);
+    profiler.record(Phase::Matching, started);
+    if !matched {
+        return None;
+    }
+
+    let started = profiler.start();
+    let score = scorer.score(&line, &line_lower);
+    profiler.record(Phase::Scoring, started);
+    let (highlights, term_highlights) = if highlight {
+        (matcher.highlights(&line), matcher.term_highlights(&line))
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    Some(SearchResult {
+        id: String::new(), // Will be set later
+        title: format!("{} (line {})", filename, line_number),
+        content: line,
+        score,
+        path: path.clone(),
+        line_number: line_number as i64,
+        page: None, // Will be set later
+        document_title: None, // Will be set later
+        indexed_at: DateTime::UNIX_EPOCH, // Will be set later
+        modified: None,
+        document_id: None, // Will be set later
+        metadata: None,
+        highlights,
+        term_highlights,
+        summary: None,
+        collection: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::ALL_PRINCIPALS;
+    use crate::analysis::AnalysisRule;
+    use crate::metadata::MetadataValue;
+    use crate::testdir::{block_on, index, TestDir};
+
+    fn principals(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    // A public document and one only hr may see, both with a `team` field
+    async fn engine_with_restricted_document(dir: &TestDir) -> SearchEngine {
+        let engine = dir.engine().await;
+        let team = |name: &str| Metadata::from([("team".to_string(), MetadataValue::Text(name.to_string()))]);
+        index(&engine, vec![
+            DocumentInput::from_bytes("public.txt", "quarterly report\n").metadata(team("sales")),
+            DocumentInput::from_bytes("salaries.txt", "quarterly salaries\n").metadata(team("hr")).acl(["hr"]),
+        ]).await;
+        engine
+    }
+
+    #[test]
+    fn restricted_lines_are_hidden_without_a_listed_principal() {
+        let dir = TestDir::new("acl-lines");
+        block_on(async {
+            let engine = engine_with_restricted_document(&dir).await;
+            assert!(engine.get_line("salaries.txt", 1, None).await.unwrap().is_none());
+            assert!(engine.get_lines("salaries.txt", 1, 1, Some(&principals(&["bob"]))).await.unwrap().is_none());
+            assert!(engine.get_line("salaries.txt", 1, Some(&principals(&["hr"]))).await.unwrap().is_some());
+            assert!(engine.get_lines("salaries.txt", 1, 1, Some(&principals(&[ALL_PRINCIPALS]))).await.unwrap().is_some());
+            assert!(engine.get_line("public.txt", 1, None).await.unwrap().is_some());
+        });
+    }
+
+    #[test]
+    fn searches_and_aggregates_only_count_visible_documents() {
+        let dir = TestDir::new("acl-aggregates");
+        block_on(async {
+            let engine = engine_with_restricted_document(&dir).await;
+            let fields = ["team".to_string()];
+            let facets = engine.facet_counts(&fields, None).await.unwrap();
+            assert_eq!(facets["team"].keys().collect::<Vec<_>>(), ["sales"]);
+            let facets = engine.facet_counts(&fields, Some(&principals(&["hr"]))).await.unwrap();
+            assert_eq!(facets["team"].len(), 2);
+
+            assert!(engine.suggest("sal", 10, None).await.unwrap().is_empty());
+            let suggestions = engine.suggest("sal", 10, Some(&principals(&["hr"]))).await.unwrap();
+            assert_eq!(suggestions.iter().map(|suggestion| suggestion.text.as_str()).collect::<Vec<_>>(), ["salaries"]);
+
+            let mut options = SearchOptions::default();
+            assert_eq!(engine.search("quarterly", &options).await.unwrap().total, 1);
+            options.filters.principals = Some(principals(&[ALL_PRINCIPALS]));
+            assert_eq!(engine.search("quarterly", &options).await.unwrap().total, 2);
+        });
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn title_filters_match_a_pages_title() {
+        let dir = TestDir::new("title-filter");
+        block_on(async {
+            let engine = dir.engine().await;
+            index(&engine, vec![
+                DocumentInput::from_bytes("page.html", "<html><head><title>Release Notes</title></head><body><p>fixed a crash</p></body></html>"),
+                DocumentInput::from_bytes("other.txt", "fixed a leak\n"),
+            ]).await;
+            let total = |query: &'static str| {
+                let engine = &engine;
+                async move { engine.search(query, &SearchOptions::default()).await.unwrap().total }
+            };
+            assert_eq!(total("title:\"release notes\" fixed").await, 1);
+            assert_eq!(total("title:page fixed").await, 1);
+            assert_eq!(total("title:other fixed").await, 1);
+            assert_eq!(total("title:changelog fixed").await, 0);
+        });
+    }
+
+    #[test]
+    fn documents_are_stemmed_in_their_rules_language() {
+        let dir = TestDir::new("stem-language");
+        let total = |engine: SearchEngine, query: &'static str| async move {
+            engine.search(query, &SearchOptions::default()).await.unwrap().total
+        };
+        block_on(async {
+            let english = SearchEngine::builder(dir.path()).stemming(true).build().await.unwrap();
+            index(&english, vec![
+                DocumentInput::from_bytes("en.txt", "the runner kept running\n"),
+                DocumentInput::from_bytes("fr/a.txt", "elles chantaient ensemble\n"),
+            ]).await;
+            assert_eq!(total(english.clone(), "runs").await, 1);
+            assert_eq!(total(english, "chanter").await, 0);
+
+            // The French document's table, stemmed in English, is rebuilt
+            let rule = AnalysisRule { pattern: "fr/**".to_string(), stem_language: Some(StemLanguage::French), ..Default::default() };
+            let french = SearchEngine::builder(dir.path()).stemming(true).analysis_rules(vec![rule]).build().await.unwrap();
+            assert_eq!(total(french.clone(), "chanter").await, 1);
+            assert_eq!(total(french, "runs").await, 1);
+        });
+    }
+
+    #[test]
+    fn cjk_phrases_and_near_match_by_ngram_position() {
+        let dir = TestDir::new("cjk-phrases");
+        block_on(async {
+            let engine = SearchEngine::builder(dir.path()).ngrams(Some(2)).build().await.unwrap();
+            index(&engine, vec![
+                DocumentInput::from_bytes("a.txt", "全文搜索引擎很快\n"),
+                DocumentInput::from_bytes("b.txt", "搜索全文\n"),
+            ]).await;
+            let total = |query: &'static str| {
+                let engine = engine.clone();
+                async move { engine.search(query, &SearchOptions::default()).await.unwrap().total }
+            };
+            assert_eq!(total("全文搜索").await, 1);
+            assert_eq!(total("\"全文搜索\"").await, 1);
+            assert_eq!(total("\"搜索全文\"").await, 1);
+            assert_eq!(total("\"全文 搜索\"").await, 1);
+            // Shorter than an n-gram, at the end of a run
+            assert_eq!(total("\"快\"").await, 1);
+            assert_eq!(total("\"全\"").await, 2);
+            // 搜索 lies between them
+            assert_eq!(total("全文 NEAR/3 引擎").await, 1);
+            assert_eq!(total("全文 NEAR/2 引擎").await, 0);
+            assert_eq!(total("全文 NEAR/2 搜索").await, 2);
+
+            let options = SearchOptions { highlight: true, ..Default::default() };
+            let response = engine.search("\"全文搜索\"", &options).await.unwrap();
+            assert_eq!(response.results[0].highlights, [[0, "全文搜索".len()]]);
+        });
+    }
+}
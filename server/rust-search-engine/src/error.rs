@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Every public API returns this, so callers can match on the kind of failure
+#[derive(Debug, Error)]
+pub enum SearchEngineError {
+    #[error("Document not found: {0}")]
+    DocumentNotFound(String),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+    // A query that doesn't fit the query syntax, with where it stopped making sense
+    #[error("Invalid query: {0}")]
+    QuerySyntax(QuerySyntaxError),
+    // Nothing was left to search for once the query was normalized
+    #[error("Empty query: {0}")]
+    EmptyQuery(String),
+    // A malformed argument such as a duration or cron expression
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Unsupported document: {0}")]
+    UnsupportedDocument(String),
+    #[error("Disk quota exceeded: {0}")]
+    QuotaExceeded(String),
+    // The operation would overwrite something that already exists
+    #[error("{0}")]
+    Conflict(String),
+    // Derived index data is unreadable or out of date; a refresh or optimize rebuilds it
+    #[error("Index corrupted: {0}")]
+    IndexCorrupted(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    // The engine was opened read-only, or is a replica that only changes by syncing from its primary
+    #[error("Read-only: {0}")]
+    ReadOnly(String),
+    // A request named a path outside the directories it may touch
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    // A point-in-time reader or saved result set that was closed, expired or never opened
+    #[error("Reader expired: {0}")]
+    ReaderExpired(String),
+    #[error("Failed to initialize logging: {0}")]
+    Logging(String),
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{context}")]
+    Json {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl SearchEngineError {
+    // Stable name for the kind of failure, e.g. "INVALID_QUERY", for callers that can't match on the enum
+    pub fn code(&self) -> &'static str {
+        match self {
+            SearchEngineError::DocumentNotFound(_) => "DOCUMENT_NOT_FOUND",
+            SearchEngineError::InvalidQuery(_) | SearchEngineError::QuerySyntax(_) => "INVALID_QUERY",
+            SearchEngineError::EmptyQuery(_) => "EMPTY_QUERY",
+            SearchEngineError::InvalidInput(_) => "INVALID_INPUT",
+            SearchEngineError::UnsupportedDocument(_) => "UNSUPPORTED_DOCUMENT",
+            SearchEngineError::QuotaExceeded(_) => "QUOTA_EXCEEDED",
+            SearchEngineError::Conflict(_) => "CONFLICT",
+            SearchEngineError::IndexCorrupted(_) => "INDEX_CORRUPTED",
+            SearchEngineError::InvalidConfig(_) => "INVALID_CONFIG",
+            SearchEngineError::ReadOnly(_) => "READ_ONLY",
+            SearchEngineError::Forbidden(_) => "FORBIDDEN",
+            SearchEngineError::ReaderExpired(_) => "READER_EXPIRED",
+            SearchEngineError::Logging(_) => "LOGGING_ERROR",
+            SearchEngineError::Io { .. } => "IO_ERROR",
+            SearchEngineError::Json { .. } => "JSON_ERROR",
+        }
+    }
+}
+
+// Where and why a query failed to parse
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+#[error("query syntax: {message} at character {}", .position + 1)]
+#[non_exhaustive]
+pub struct QuerySyntaxError {
+    pub message: String,
+    // Characters before the offending part of the query as searched, after whitespace is collapsed
+    pub position: usize,
+}
+
+pub type Result<T, E = SearchEngineError> = std::result::Result<T, E>;
+
+impl From<std::io::Error> for SearchEngineError {
+    fn from(source: std::io::Error) -> Self {
+        SearchEngineError::Io {
+            context: source.to_string(),
+            source,
+        }
+    }
+}
+
+impl From<serde_json::Error> for SearchEngineError {
+    fn from(source: serde_json::Error) -> Self {
+        SearchEngineError::Json {
+            context: source.to_string(),
+            source,
+        }
+    }
+}
+
+// Describes which operation an I/O or JSON error came from
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T>;
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T>;
+}
+
+impl<T> Context<T> for std::result::Result<T, std::io::Error> {
+    fn context(self, context: &str) -> Result<T> {
+        self.with_context(|| context.to_string())
+    }
+
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T> {
+        self.map_err(|source| SearchEngineError::Io { context: context(), source })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, serde_json::Error> {
+    fn context(self, context: &str) -> Result<T> {
+        self.with_context(|| context.to_string())
+    }
+
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T> {
+        self.map_err(|source| SearchEngineError::Json { context: context(), source })
+    }
+}
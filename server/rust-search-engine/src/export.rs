@@ -0,0 +1,218 @@
+// Exported indexes, for searching in the browser: the documents of a search directory and a
+// dictionary of the documents each term occurs in, as one JSON file. `search-engine export`
+// writes one and `load_index` reads it back; `LoadedIndex::search` then looks the query's
+// terms up in the dictionary and matches and ranks only the documents holding them, as the
+// engine would. Loading and searching need no tokio, filesystem or clock, so they build for
+// wasm32. Words match as written, without the engine's stemming, stop words or analyzers.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bm25::CorpusStats;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::matching::{index_terms, InMemoryDocument, InMemorySearch, TextDocument};
+use crate::metadata::Metadata;
+use crate::normalize::fold_text;
+use crate::options::SearchOptions;
+use crate::termindex::TermDictionary;
+use crate::SearchResponse;
+
+// Names the file format, so other JSON is told apart from an index of another version
+pub const EXPORT_FORMAT: &str = "search-engine-index";
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedDocument {
+    // Relative to the search directory, as results give it
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub indexed_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedIndex {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub documents: Vec<ExportedDocument>,
+    // Terms of the documents, which are numbered by their place in `documents`
+    pub dictionary: TermDictionary,
+}
+
+impl ExportedIndex {
+    pub fn new(documents: Vec<ExportedDocument>, exported_at: DateTime<Utc>) -> Self {
+        let mut dictionary = TermDictionary::new(false);
+        for (number, document) in documents.iter().enumerate() {
+            for term in index_terms(&fold_text(&document.content, false)) {
+                dictionary.add_term(number, term);
+            }
+        }
+        ExportedIndex { format: EXPORT_FORMAT.to_string(), version: EXPORT_VERSION, exported_at, documents, dictionary }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).context("Failed to serialize the exported index")
+    }
+}
+
+// An exported index read back for searching
+#[derive(Debug, Clone)]
+pub struct LoadedIndex {
+    index: ExportedIndex,
+    // For BM25, from every document
+    stats: CorpusStats,
+}
+
+// Reads an index written by `search-engine export`. Fails with `IndexCorrupted` on anything
+// else, including an index of another format version.
+pub fn load_index(bytes: &[u8]) -> Result<LoadedIndex> {
+    let corrupted = |message: String| SearchEngineError::IndexCorrupted(message);
+    let index: ExportedIndex = serde_json::from_slice(bytes).map_err(|e| corrupted(format!("Invalid exported index: {}", e)))?;
+    if index.format != EXPORT_FORMAT {
+        return Err(corrupted(format!("Not an exported index: {:?}", index.format)));
+    }
+    if index.version != EXPORT_VERSION {
+        return Err(corrupted(format!("Unsupported exported index version {}", index.version)));
+    }
+    if index.dictionary.documents().last().is_some_and(|last| *last >= index.documents.len()) {
+        return Err(corrupted("Exported index dictionary lists documents it doesn't hold".to_string()));
+    }
+    let stats = CorpusStats::from_texts(index.documents.iter().map(|doc| doc.content.as_str()));
+    Ok(LoadedIndex { index, stats })
+}
+
+impl LoadedIndex {
+    pub fn documents(&self) -> &[ExportedDocument] {
+        &self.index.documents
+    }
+
+    pub fn exported_at(&self) -> DateTime<Utc> {
+        self.index.exported_at
+    }
+
+    // Searches the documents holding the query's terms, or all of them for a query the
+    // dictionary can't narrow, such as a regex. Options apply as in `search_documents`, and
+    // filters also see each document's tags, metadata and language.
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<SearchResponse> {
+        let search = InMemorySearch::new(query, options)?;
+        let numbers = self.index.dictionary.documents_matching(search.matcher())
+            .unwrap_or_else(|| (0..self.index.documents.len()).collect());
+        let documents = numbers.into_iter().map(|number| {
+            let document = &self.index.documents[number];
+            let text = TextDocument { path: &document.path, content: &document.content, indexed_at: document.indexed_at };
+            InMemoryDocument {
+                id: document.id.as_deref(),
+                title: document.title.as_deref(),
+                language: document.language.as_deref(),
+                tags: &document.tags,
+                metadata: Some(&document.metadata),
+                ..InMemoryDocument::new(number, text)
+            }
+        });
+        Ok(search.run(documents, &self.stats, options))
+    }
+}
+
+#[cfg(feature = "engine")]
+impl crate::SearchEngine {
+    // Every document with its text and attributes, apart from those restricted by an ACL,
+    // which a browser couldn't keep from anyone, and those past their TTL
+    pub async fn export_index(&self) -> Result<ExportedIndex> {
+        let now = Utc::now();
+        let mut documents = Vec::new();
+        for doc in &self.catalog().await?.documents {
+            let attributes = &doc.attributes;
+            if !attributes.acl.is_empty() || attributes.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                continue;
+            }
+            documents.push(ExportedDocument {
+                path: self.relative_path(&doc.path),
+                id: attributes.document_id.clone(),
+                title: attributes.title.clone(),
+                language: attributes.language.clone(),
+                indexed_at: attributes.indexed_at.unwrap_or(doc.modified),
+                tags: attributes.tags.clone(),
+                metadata: attributes.metadata.clone(),
+                content: self.read_document(&doc.path).await?,
+            });
+        }
+        Ok(ExportedIndex::new(documents, now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::MatchMode;
+
+    fn document(path: &str, content: &str) -> ExportedDocument {
+        ExportedDocument {
+            path: path.to_string(),
+            id: None,
+            title: None,
+            language: None,
+            indexed_at: DateTime::UNIX_EPOCH,
+            tags: Vec::new(),
+            metadata: Metadata::new(),
+            content: content.to_string(),
+        }
+    }
+
+    fn loaded() -> LoadedIndex {
+        let documents = vec![
+            document("notes/salt.txt", "salt and pepper\nsugar"),
+            document("notes/import.txt", "import the report\nexport it"),
+            document("recipes/bread.txt", "flour, water and salt"),
+        ];
+        load_index(&ExportedIndex::new(documents, DateTime::UNIX_EPOCH).to_bytes().unwrap()).unwrap()
+    }
+
+    fn paths(index: &LoadedIndex, query: &str, mode: MatchMode) -> Vec<String> {
+        let options = SearchOptions { mode, ..SearchOptions::default() };
+        let mut paths: Vec<String> = index.search(query, &options).unwrap().results.iter().map(|r| r.path.to_string()).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    #[test]
+    fn dictionary_lists_the_documents_of_each_term() {
+        let index = loaded();
+        let search = InMemorySearch::new("salt", &SearchOptions { mode: MatchMode::Word, ..SearchOptions::default() }).unwrap();
+        assert_eq!(index.index.dictionary.documents_matching(search.matcher()), Some(vec![0, 2]));
+        let search = InMemorySearch::new("port", &SearchOptions { mode: MatchMode::Substring, ..SearchOptions::default() }).unwrap();
+        assert_eq!(index.index.dictionary.documents_matching(search.matcher()), Some(vec![1]));
+    }
+
+    #[test]
+    fn loaded_index_searches_like_the_engine() {
+        let index = loaded();
+        assert_eq!(paths(&index, "salt", MatchMode::Word), ["notes/salt.txt", "recipes/bread.txt"]);
+        assert_eq!(paths(&index, "port", MatchMode::Substring), ["notes/import.txt"]);
+        assert_eq!(paths(&index, "salt AND pepper", MatchMode::Word), ["notes/salt.txt"]);
+        assert_eq!(paths(&index, "\"the report\"", MatchMode::Word), ["notes/import.txt"]);
+        assert_eq!(paths(&index, "flour path:notes", MatchMode::Word), Vec::<String>::new());
+        assert!(paths(&index, "missing", MatchMode::Word).is_empty());
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(matches!(load_index(b"[]"), Err(SearchEngineError::IndexCorrupted(_))));
+        let mut index = ExportedIndex::new(vec![document("a.txt", "a")], DateTime::UNIX_EPOCH);
+        index.version += 1;
+        assert!(matches!(load_index(&index.to_bytes().unwrap()), Err(SearchEngineError::IndexCorrupted(_))));
+        index.version = EXPORT_VERSION;
+        index.documents.clear();
+        assert!(matches!(load_index(&index.to_bytes().unwrap()), Err(SearchEngineError::IndexCorrupted(_))));
+    }
+}
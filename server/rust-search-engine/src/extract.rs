@@ -0,0 +1,351 @@
+// Documents in formats other than plain text, converted to text as they are indexed. The text
+// is stored under the name the document came with plus ".txt", so "report.pdf" is searched as
+// "report.pdf.txt" and never clashes with a "report.txt" beside it. Each page of a PDF is
+// followed by a line holding only a form feed, as `pdftotext` separates pages, and results
+// report the page they are on; plain text documents with form feed lines get pages that way too.
+// Word (.docx) and OpenDocument (.odt) documents are zip archives whose text is in one XML part,
+// taken a paragraph per line. HTML pages (.html, .htm) are indexed by their visible text, without
+// markup, scripts, styles or the head, with a line per block such as a paragraph, list item or
+// table row; their <title> titles their results in place of the file name. PDFs need the `pdf`
+// feature, Word and OpenDocument documents the `office` feature and HTML the `html` feature.
+
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+
+use crate::error::{Context, Result, SearchEngineError};
+use crate::storage::ContentReader;
+
+pub(crate) const PAGE_BREAK: &str = "\x0c";
+
+// Largest XML part read from an office document, so a small archive can't unpack into
+// gigabytes of text
+#[cfg(feature = "office")]
+const MAX_PART_BYTES: u64 = 256 * 1024 * 1024;
+
+// Elements whose content isn't shown: the head holds the title and metadata
+#[cfg(feature = "html")]
+const HIDDEN_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "iframe", "object", "canvas", "svg",
+];
+
+// Elements that start and end a line of text, so their content isn't run into their neighbors'
+#[cfg(feature = "html")]
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "caption", "dd", "details", "div", "dl", "dt",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header",
+    "hr", "li", "main", "nav", "ol", "p", "pre", "section", "summary", "table", "tr", "ul",
+];
+
+// A converted document's text, and the title it declares
+pub(crate) struct Converted {
+    pub(crate) text: String,
+    pub(crate) title: Option<String>,
+}
+
+impl Converted {
+    #[cfg_attr(not(any(feature = "pdf", feature = "office")), allow(dead_code))]
+    fn untitled(text: String) -> Self {
+        Converted { text, title: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    #[cfg(feature = "pdf")]
+    Pdf,
+    #[cfg(feature = "office")]
+    Docx,
+    #[cfg(feature = "office")]
+    Odt,
+    #[cfg(feature = "html")]
+    Html,
+}
+
+// The formats documents are converted from, by extension
+const FORMATS: &[(&str, Format)] = &[
+    #[cfg(feature = "pdf")]
+    ("pdf", Format::Pdf),
+    #[cfg(feature = "office")]
+    ("docx", Format::Docx),
+    #[cfg(feature = "office")]
+    ("odt", Format::Odt),
+    #[cfg(feature = "html")]
+    ("html", Format::Html),
+    #[cfg(feature = "html")]
+    ("htm", Format::Html),
+];
+
+impl Format {
+    fn of(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        FORMATS.iter().find(|(ext, _)| *ext == extension).map(|(_, format)| *format)
+    }
+
+    // Without any of the features there are no formats, and nothing to read the bytes
+    #[cfg_attr(not(any(feature = "pdf", feature = "office", feature = "html")), allow(unused_variables))]
+    fn convert(self, bytes: &[u8]) -> std::result::Result<Converted, String> {
+        match self {
+            #[cfg(feature = "pdf")]
+            Format::Pdf => pdf_extract::extract_text_from_mem_by_pages(bytes)
+                .map(|pages| Converted::untitled(join_pages(&pages)))
+                .map_err(|e| e.to_string()),
+            #[cfg(feature = "office")]
+            Format::Docx => office_text(bytes, "word/document.xml", docx_text).map(Converted::untitled),
+            #[cfg(feature = "office")]
+            Format::Odt => office_text(bytes, "content.xml", odt_text).map(Converted::untitled),
+            #[cfg(feature = "html")]
+            Format::Html => Ok(html_text(&String::from_utf8_lossy(bytes))),
+        }
+    }
+}
+
+// Extensions of the formats documents are converted from
+pub(crate) fn converted_extensions() -> impl Iterator<Item = &'static str> {
+    FORMATS.iter().map(|(ext, _)| *ext)
+}
+
+// True if documents with this path are converted to text before they are indexed
+pub(crate) fn is_converted(path: &Path) -> bool {
+    Format::of(path).is_some()
+}
+
+// Name the text of a converted document is stored under
+pub(crate) fn text_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".txt");
+    PathBuf::from(name)
+}
+
+// Reads a document in one of the converted formats and returns its text
+pub(crate) async fn convert(path: &Path, mut content: ContentReader) -> Result<Converted> {
+    let mut bytes = Vec::new();
+    content.read_to_end(&mut bytes).await
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    let failed = |reason: String| SearchEngineError::UnsupportedDocument(format!("could not extract the text of {:?}: {}", path, reason));
+    let format = Format::of(path).ok_or_else(|| failed("unknown format".to_string()))?;
+    // Parsing is CPU-bound, and malformed files can make a parser panic
+    tokio::task::spawn_blocking(move || format.convert(&bytes)).await
+        .map_err(|e| failed(e.to_string()))?
+        .map_err(failed)
+}
+
+// Reads the XML part of an office document holding its text and extracts it
+#[cfg(feature = "office")]
+fn office_text(
+    bytes: &[u8],
+    part: &str,
+    extract: fn(&str) -> std::result::Result<String, quick_xml::Error>,
+) -> std::result::Result<String, String> {
+    use std::io::Read;
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let file = archive.by_name(part).map_err(|e| format!("{}: {}", part, e))?;
+    let mut xml = String::new();
+    file.take(MAX_PART_BYTES + 1).read_to_string(&mut xml).map_err(|e| format!("{}: {}", part, e))?;
+    if xml.len() as u64 > MAX_PART_BYTES {
+        return Err(format!("{} is larger than {} bytes", part, MAX_PART_BYTES));
+    }
+    extract(&xml).map_err(|e| format!("{}: {}", part, e))
+}
+
+// Text of a Word document body: the text runs of each paragraph, with tabs and line breaks
+#[cfg(feature = "office")]
+fn docx_text(xml: &str) -> std::result::Result<String, quick_xml::Error> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut text = String::new();
+    // Tab stops are `w:tab` elements too, but outside runs
+    let (mut in_run, mut in_text) = (false, false);
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"r" => in_run = true,
+                b"t" => in_text = in_run,
+                _ => {}
+            },
+            Event::Empty(element) => match element.local_name().as_ref() {
+                b"tab" if in_run => text.push('\t'),
+                b"br" | b"cr" if in_run => text.push('\n'),
+                b"p" => text.push('\n'),
+                _ => {}
+            },
+            Event::End(element) => match element.local_name().as_ref() {
+                b"r" => in_run = false,
+                b"t" => in_text = false,
+                b"p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Text(content) if in_text => text.push_str(&content.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+// Text of an OpenDocument body: its paragraphs and headings, with `text:s` runs of spaces,
+// tabs and line breaks
+#[cfg(feature = "office")]
+fn odt_text(xml: &str) -> std::result::Result<String, quick_xml::Error> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut text = String::new();
+    // Paragraphs nest, e.g. in a note or a frame within a paragraph
+    let mut depth = 0usize;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if matches!(element.local_name().as_ref(), b"p" | b"h") => depth += 1,
+            Event::Empty(element) => match element.local_name().as_ref() {
+                b"p" | b"h" => text.push('\n'),
+                b"tab" if depth > 0 => text.push('\t'),
+                b"line-break" if depth > 0 => text.push('\n'),
+                b"s" if depth > 0 => {
+                    let count = element.attributes().flatten()
+                        .find(|attribute| attribute.key.local_name().as_ref() == b"c")
+                        .and_then(|attribute| attribute.unescape_value().ok()?.parse().ok())
+                        .unwrap_or(1);
+                    text.extend(std::iter::repeat_n(' ', count));
+                }
+                _ => {}
+            },
+            Event::End(element) if matches!(element.local_name().as_ref(), b"p" | b"h") => {
+                depth = depth.saturating_sub(1);
+                text.push('\n');
+            }
+            Event::Text(content) if depth > 0 => text.push_str(&content.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+// Visible text of an HTML page, and its title. Whitespace runs become single spaces except
+// within <pre>, whose lines are kept as they are.
+#[cfg(feature = "html")]
+fn html_text(html: &str) -> Converted {
+    use ego_tree::iter::Edge;
+    use scraper::{Html, Node};
+
+    fn end_line(line: &mut String, lines: &mut Vec<String>) {
+        let ended = std::mem::take(line);
+        if !ended.trim().is_empty() {
+            lines.push(ended.trim_end().to_string());
+        }
+    }
+
+    let document = Html::parse_document(html);
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    let mut title: Option<String> = None;
+    // Depth within hidden elements, <pre> and <title>
+    let (mut hidden, mut pre, mut in_title) = (0usize, 0usize, 0usize);
+    for edge in document.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(element) => {
+                    let name = element.name();
+                    if name == "title" {
+                        in_title += 1;
+                    }
+                    if HIDDEN_ELEMENTS.contains(&name) {
+                        hidden += 1;
+                    } else if hidden == 0 {
+                        if BLOCK_ELEMENTS.contains(&name) {
+                            end_line(&mut line, &mut lines);
+                        } else if matches!(name, "td" | "th") && !line.trim().is_empty() {
+                            line.push(' ');
+                        }
+                        if name == "pre" {
+                            pre += 1;
+                        }
+                    }
+                }
+                Node::Text(text) if in_title > 0 && title.is_none() => {
+                    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    title = Some(collapsed).filter(|title| !title.is_empty());
+                }
+                Node::Text(text) if hidden == 0 && pre > 0 => {
+                    let mut parts = text.split('\n');
+                    if let Some(first) = parts.next() {
+                        line.push_str(first);
+                    }
+                    for part in parts {
+                        end_line(&mut line, &mut lines);
+                        line.push_str(part);
+                    }
+                }
+                Node::Text(text) if hidden == 0 => {
+                    if text.starts_with(char::is_whitespace) && !line.is_empty() && !line.ends_with(' ') {
+                        line.push(' ');
+                    }
+                    let words: Vec<&str> = text.split_whitespace().collect();
+                    line.push_str(&words.join(" "));
+                    if text.ends_with(char::is_whitespace) && !words.is_empty() {
+                        line.push(' ');
+                    }
+                }
+                _ => {}
+            },
+            Edge::Close(node) => {
+                if let Node::Element(element) = node.value() {
+                    let name = element.name();
+                    if name == "title" {
+                        in_title -= 1;
+                    }
+                    if HIDDEN_ELEMENTS.contains(&name) {
+                        hidden -= 1;
+                    } else if hidden == 0 {
+                        if name == "pre" {
+                            pre -= 1;
+                        }
+                        if BLOCK_ELEMENTS.contains(&name) {
+                            end_line(&mut line, &mut lines);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    end_line(&mut line, &mut lines);
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Converted { text, title }
+}
+
+// Pages separated by page break lines, with form feeds within a page, which would be taken
+// for breaks, turned to spaces
+#[cfg(feature = "pdf")]
+fn join_pages(pages: &[String]) -> String {
+    let mut text = String::new();
+    for (number, page) in pages.iter().enumerate() {
+        if number > 0 {
+            text.push_str(PAGE_BREAK);
+            text.push('\n');
+        }
+        let page = page.replace(PAGE_BREAK, " ");
+        let page = page.trim();
+        if !page.is_empty() {
+            text.push_str(page);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+// Numbers of the lines holding a page break, ascending
+pub(crate) fn page_breaks(text: &str) -> Vec<usize> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| *line == PAGE_BREAK)
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+// Page a line is on, counting from 1
+pub(crate) fn page_of(breaks: &[usize], line_number: usize) -> usize {
+    breaks.partition_point(|at| *at < line_number) + 1
+}
@@ -0,0 +1,88 @@
+// Searching several collections at once: the query runs against each collection with its
+// own defaults, and the hits are merged into one page. BM25 scores depend on the terms and
+// filters of each search, so before merging each collection's scores are divided by its
+// best one, putting every collection's top hit at 1.0. Every result says which collection it
+// came from; a line in two overlapping collections is returned once for each. Results are
+// diversified once merged rather than within each collection.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::clustering::cluster_results;
+use crate::error::{Result, SearchEngineError};
+use crate::matching::{diversify, sort_results};
+use crate::options::SearchOptions;
+use crate::{SearchEngine, SearchResponse};
+
+impl SearchEngine {
+    // Searches each named collection with the options paired with it, usually its
+    // `Collection::options()` with the caller's own on top. Paging, sorting, collation,
+    // diversifying and clustering follow the first collection's options.
+    pub async fn search_collections(&self, query: &str, searches: &[(String, SearchOptions)]) -> Result<SearchResponse> {
+        let Some((_, first)) = searches.first() else {
+            return Err(SearchEngineError::InvalidInput("at least one collection must be searched".to_string()));
+        };
+        if searches.iter().any(|(_, options)| options.save_results.is_some()) {
+            return Err(SearchEngineError::InvalidInput("results of several collections can't be saved as a result set".to_string()));
+        }
+        let (limit, offset) = (first.limit, first.offset);
+
+        let mut merged: Option<SearchResponse> = None;
+        let mut results = Vec::new();
+        let mut total = 0;
+        let mut tag_counts: BTreeMap<String, usize> = BTreeMap::new();
+        let mut facets: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        let (mut timed_out, mut limited) = (false, false);
+        for (name, options) in searches {
+            // Every collection's first `offset + limit` hits, so the merged page can be cut from them
+            let options = SearchOptions { limit: offset + limit, offset: 0, clusters: 0, diversify: false, ..options.clone() };
+            let mut response = self.search(query, &options).await?;
+            let best = response.results.iter().map(|result| result.score).fold(0.0, f32::max);
+            let collection: Arc<str> = Arc::from(name.as_str());
+            results.extend(std::mem::take(&mut response.results).into_iter().map(|mut result| {
+                if best > 0.0 {
+                    result.score /= best;
+                }
+                result.id = format!("{}:{}", name, result.id);
+                result.collection = Some(collection.clone());
+                result
+            }));
+            total += response.total;
+            for (tag, count) in &response.tag_counts {
+                *tag_counts.entry(tag.clone()).or_insert(0) += count;
+            }
+            for (field, values) in &response.facets {
+                let counts = facets.entry(field.clone()).or_default();
+                for (value, count) in values {
+                    *counts.entry(value.clone()).or_insert(0) += count;
+                }
+            }
+            timed_out |= response.timed_out;
+            limited |= response.limited;
+            merged.get_or_insert(response);
+        }
+
+        sort_results(&mut results, first.sort, first.collation.as_deref());
+        if first.diversify {
+            diversify(&mut results, first.sort);
+        }
+        let results: Vec<_> = results.into_iter().skip(offset).take(limit).collect();
+        let mut response = merged.expect("at least one collection was searched");
+        response.clusters = cluster_results(
+            results.iter().map(|result| (result.id.as_str(), result.content.as_str())),
+            &response.query,
+            first.clusters,
+        );
+        response.results = results;
+        response.total = total;
+        response.limit = limit;
+        response.offset = offset;
+        response.tag_counts = tag_counts;
+        response.facets = facets;
+        response.timed_out = timed_out;
+        response.limited = limited;
+        // Each collection was profiled on its own; one of them would be misleading
+        response.profile = None;
+        Ok(response)
+    }
+}
@@ -0,0 +1,183 @@
+// C ABI for embedding the engine from PHP, Go, C++ and other languages; the matching
+// declarations are in include/search_engine.h (regenerate with `cbindgen --config cbindgen.toml`).
+// Calls block on the engine's own runtime. Strings returned by these functions
+// must be released with `engine_string_free`; on failure they return NULL or -1
+// and `engine_last_error` describes what went wrong.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::blocking;
+use crate::error::{Result, SearchEngineError};
+use crate::metadata::Metadata;
+use crate::options::SearchRequest;
+use crate::schema::Versioned;
+use crate::SearchEngineBuilder;
+
+// Opaque handle returned by `engine_new`
+pub struct Engine {
+    inner: blocking::SearchEngine,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Runs `body`, recording its error (or panic) for `engine_last_error` and returning `failed`
+fn guard<T>(failed: T, body: impl FnOnce() -> Result<T>) -> T {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            failed
+        }
+        Err(_) => {
+            set_last_error("internal error: the engine panicked".to_string());
+            failed
+        }
+    }
+}
+
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str> {
+    if value.is_null() {
+        return Err(SearchEngineError::InvalidInput(format!("{} must not be NULL", name)));
+    }
+    CStr::from_ptr(value).to_str()
+        .map_err(|_| SearchEngineError::InvalidInput(format!("{} is not valid UTF-8", name)))
+}
+
+unsafe fn engine_ref<'a>(engine: *const Engine) -> Result<&'a Engine> {
+    engine.as_ref()
+        .ok_or_else(|| SearchEngineError::InvalidInput("engine must not be NULL".to_string()))
+}
+
+fn into_c_string(value: String) -> Result<*mut c_char> {
+    CString::new(value)
+        .map(CString::into_raw)
+        .map_err(|_| SearchEngineError::InvalidInput("result contains a NUL byte".to_string()))
+}
+
+/// Opens the search directory at `search_path`, creating it if needed.
+/// Returns NULL on failure.
+///
+/// # Safety
+/// `search_path` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn engine_new(search_path: *const c_char) -> *mut Engine {
+    guard(ptr::null_mut(), || {
+        let search_path = read_str(search_path, "search_path")?;
+        let mut inner = blocking::SearchEngine::new(search_path)?;
+        inner.set_actor("ffi");
+        Ok(Box::into_raw(Box::new(Engine { inner })))
+    })
+}
+
+/// Opens the existing search directory at `search_path` without writing anything under it;
+/// indexing through the handle fails. Returns NULL on failure.
+///
+/// # Safety
+/// `search_path` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn engine_open_read_only(search_path: *const c_char) -> *mut Engine {
+    guard(ptr::null_mut(), || {
+        let search_path = read_str(search_path, "search_path")?;
+        let mut inner = blocking::SearchEngine::open(SearchEngineBuilder::new(search_path).read_only(true))?;
+        inner.set_actor("ffi");
+        Ok(Box::into_raw(Box::new(Engine { inner })))
+    })
+}
+
+/// Searches for `query` and returns the response as a JSON string, or NULL on failure.
+/// The response is wrapped as {"schema_version": 1, "data": {...}}.
+/// `options_json` may be NULL or a JSON object with limit, offset, sort, collation,
+/// mode, case_sensitive, filters, highlight, timeout, collapse_duplicates, clusters, summarize, reader,
+/// within, save_results, max_per_file, diversify, boosts, facets, fuzzy and limits.
+///
+/// # Safety
+/// `engine` must come from `engine_new` and not be freed; the strings must be
+/// NULL or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn engine_search_json(engine: *const Engine, query: *const c_char, options_json: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let engine = engine_ref(engine)?;
+        let query = read_str(query, "query")?;
+        let request: SearchRequest = if options_json.is_null() {
+            SearchRequest::default()
+        } else {
+            serde_json::from_str(read_str(options_json, "options_json")?)
+                .map_err(|e| SearchEngineError::InvalidInput(format!("options_json: {}", e)))?
+        };
+        let response = engine.inner.search(query, &request.into_options()?)?;
+        into_c_string(serde_json::to_string(&Versioned::new(response))?)
+    })
+}
+
+/// Indexes the text file at `path`, copying it into the search directory if needed; the
+/// text of a PDF, Word, OpenDocument or HTML file is copied instead.
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+/// `engine` must come from `engine_new` and not be freed; `path` must be NULL
+/// or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn engine_index(engine: *const Engine, path: *const c_char) -> c_int {
+    guard(-1, || {
+        let engine = engine_ref(engine)?;
+        engine.inner.index_document(read_str(path, "path")?)?;
+        Ok(0)
+    })
+}
+
+/// Like `engine_index`, storing `metadata_json`, a JSON object of field names to
+/// strings or numbers, with the document in place of the metadata it had.
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+/// `engine` must come from `engine_new` and not be freed; the strings must be
+/// NULL or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn engine_index_with_metadata(engine: *const Engine, path: *const c_char, metadata_json: *const c_char) -> c_int {
+    guard(-1, || {
+        let engine = engine_ref(engine)?;
+        let metadata: Metadata = serde_json::from_str(read_str(metadata_json, "metadata_json")?)?;
+        engine.inner.index_document_with_metadata(read_str(path, "path")?, None, None, metadata)?;
+        Ok(0)
+    })
+}
+
+/// Closes an engine. Passing NULL does nothing.
+///
+/// # Safety
+/// `engine` must be NULL or come from `engine_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn engine_free(engine: *mut Engine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Releases a string returned by this library. Passing NULL does nothing.
+///
+/// # Safety
+/// `value` must be NULL or a string returned by this library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn engine_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// The error from the last failed call on this thread, or NULL. The string is
+/// owned by the library and valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn engine_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map(|message| message.as_ptr()).unwrap_or(ptr::null()))
+}
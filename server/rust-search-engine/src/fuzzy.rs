@@ -0,0 +1,36 @@
+// Fuzzy term matching: a query word followed by `~`, as in `serach~`, also matches words
+// within a few edits of it (insertions, deletions or substitutions of one character).
+
+// Edits allowed by a bare `~`
+pub const DEFAULT_FUZZY_DISTANCE: usize = 2;
+// Most edits a fuzzy term may allow; beyond this nearly every short word matches
+pub const MAX_FUZZY_DISTANCE: usize = 2;
+
+// A term is never allowed as many edits as it has characters, so `a~` only matches "a"
+pub(crate) fn effective_distance(term: &str, distance: usize) -> usize {
+    distance.min(term.chars().count().saturating_sub(1))
+}
+
+// Levenshtein distance between `a` and `b` if it is at most `max`
+pub(crate) fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // Every path through the rest of the table costs at least the row's minimum
+        if current.iter().min().is_some_and(|least| *least > max) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= max)
+}
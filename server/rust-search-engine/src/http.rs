@@ -0,0 +1,312 @@
+// HTTP client for the endpoints the engine calls, such as embedding APIs, alert webhooks and
+// a replica's primary, over plain HTTP or HTTPS with rustls and the webpki root certificates.
+// Connections are pooled per endpoint, and connecting, each read and each write time out.
+// With the `server` feature, also an HTTP/1.1 server, hyper's, for the replication endpoints.
+// Callers attach context to the returned I/O errors.
+
+#[cfg(feature = "server")]
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Empty, Full, StreamBody};
+#[cfg(feature = "server")]
+use hyper::body::{Body, Bytes, Frame, Incoming};
+#[cfg(feature = "server")]
+use hyper::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+#[cfg(feature = "server")]
+use hyper::server::conn::http1;
+#[cfg(feature = "server")]
+use hyper::service::service_fn;
+#[cfg(feature = "server")]
+use hyper::{Method, Request, Response, StatusCode};
+#[cfg(feature = "server")]
+use hyper_util::rt::{TokioIo, TokioTimer};
+#[cfg(feature = "server")]
+use std::convert::Infallible;
+#[cfg(feature = "server")]
+use std::future::Future;
+use std::io::{self, Read};
+#[cfg(feature = "server")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "server")]
+use tokio::io::AsyncReadExt;
+#[cfg(feature = "server")]
+use tokio::net::TcpListener;
+#[cfg(feature = "server")]
+use tokio::sync::Semaphore;
+#[cfg(feature = "server")]
+use tracing::debug;
+
+use crate::error::{Result, SearchEngineError};
+#[cfg(feature = "server")]
+use crate::storage::ContentReader;
+
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// For each read and write, not the whole exchange, so large downloads aren't cut off
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+// Most of an error response's body kept for its message
+const MAX_ERROR_BODY: u64 = 4096;
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpEndpoint {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpEndpoint {
+    // `url` such as "http://localhost:8080/v1/embeddings" or "https://api.example.com/v1/embeddings"
+    pub(crate) fn parse(url: &str) -> Result<Self> {
+        let invalid = || SearchEngineError::InvalidConfig(format!("URL {:?} must look like http[s]://host[:port]/path", url));
+        let rest = url.strip_prefix("http://").or_else(|| url.strip_prefix("https://")).ok_or_else(invalid)?;
+        if rest.split(['/', ':']).next().unwrap_or_default().is_empty() {
+            return Err(invalid());
+        }
+        let endpoint = HttpEndpoint { url: url.to_string(), agent: agent(DEFAULT_TIMEOUT) };
+        endpoint.agent.get(url).request_url().map_err(|_| invalid())?;
+        Ok(endpoint)
+    }
+
+    // Waits up to `timeout` for each read and write rather than `DEFAULT_TIMEOUT`
+    pub(crate) fn timeout(mut self, timeout: Duration) -> Self {
+        self.agent = agent(timeout);
+        self
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    // POSTs a JSON body and returns the response body
+    pub(crate) async fn post_json(&self, body: &[u8], bearer_token: Option<&str>) -> io::Result<Vec<u8>> {
+        self.send("POST", self.url.clone(), Some(body.to_vec()), bearer_token).await
+    }
+
+    // GETs `path`, which is appended to the endpoint's own path
+    pub(crate) async fn get(&self, path: &str, bearer_token: Option<&str>) -> io::Result<Vec<u8>> {
+        let url = format!("{}{}", self.url.trim_end_matches('/'), path);
+        self.send("GET", url, None, bearer_token).await
+    }
+
+    async fn send(&self, method: &'static str, url: String, body: Option<Vec<u8>>, bearer_token: Option<&str>) -> io::Result<Vec<u8>> {
+        let mut request = self.agent.request(method, &url);
+        if let Some(token) = bearer_token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        tokio::task::spawn_blocking(move || {
+            let response = match body {
+                Some(body) => request.set("Content-Type", "application/json").send_bytes(&body),
+                None => request.call(),
+            };
+            match response {
+                Ok(response) => {
+                    let mut body = Vec::new();
+                    response.into_reader().read_to_end(&mut body)?;
+                    Ok(body)
+                }
+                Err(ureq::Error::Status(status, response)) => {
+                    let mut body = Vec::new();
+                    let _ = response.into_reader().take(MAX_ERROR_BODY).read_to_end(&mut body);
+                    Err(io::Error::other(format!("status {}: {}", status, String::from_utf8_lossy(&body).trim())))
+                }
+                Err(ureq::Error::Transport(e)) => Err(io::Error::other(e.to_string())),
+            }
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(timeout)
+        .timeout_write(timeout)
+        .build()
+}
+
+// Limits on what a client can make the server hold: the head of a request has to arrive
+// within `HEADER_TIMEOUT` and fit in `MAX_REQUEST_HEAD` with at most `MAX_HEADERS` headers,
+// hyper answering 408 or 431 otherwise, bodies are refused unread, and no more than
+// `MAX_CONNECTIONS` connections are served at once, the rest waiting to be accepted
+#[cfg(feature = "server")]
+const HEADER_TIMEOUT: Duration = Duration::from_secs(if cfg!(test) { 1 } else { 30 });
+#[cfg(feature = "server")]
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+#[cfg(feature = "server")]
+const MAX_HEADERS: usize = 32;
+#[cfg(feature = "server")]
+const MAX_CONNECTIONS: usize = 64;
+// Bytes read from a streamed body at a time
+#[cfg(feature = "server")]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[cfg(feature = "server")]
+pub(crate) type HttpResponse = Response<UnsyncBoxBody<Bytes, io::Error>>;
+
+#[cfg(feature = "server")]
+pub(crate) struct HttpRequest {
+    pub(crate) method: Method,
+    // Percent-decoded, without the query string
+    pub(crate) path: String,
+    headers: HeaderMap,
+}
+
+#[cfg(feature = "server")]
+impl HttpRequest {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn response(status: u16, content_type: &str, body: impl Into<Bytes>) -> HttpResponse {
+    let body = Full::new(body.into()).map_err(|never| match never {}).boxed_unsync();
+    with_status(Response::builder().header(CONTENT_TYPE, content_type), status, body)
+}
+
+// A response whose `length` bytes of body are read from `content` as the client takes them
+#[cfg(feature = "server")]
+pub(crate) fn stream_response(content_type: &str, length: u64, content: ContentReader) -> HttpResponse {
+    let chunks = futures_util::stream::unfold(content, |mut content| async move {
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        match content.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((Ok(Frame::data(Bytes::from(chunk))), content))
+            }
+            Err(e) => Some((Err(e), content)),
+        }
+    });
+    let builder = Response::builder().header(CONTENT_TYPE, content_type).header(CONTENT_LENGTH, length);
+    with_status(builder, 200, StreamBody::new(chunks).boxed_unsync())
+}
+
+#[cfg(feature = "server")]
+fn with_status(builder: hyper::http::response::Builder, status: u16, body: UnsyncBoxBody<Bytes, io::Error>) -> HttpResponse {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    builder.status(status).body(body).unwrap_or_else(|_| Response::new(Empty::new().map_err(|never| match never {}).boxed_unsync()))
+}
+
+// Serves HTTP/1.1 on `listener` until accepting fails, answering each request with `handler`
+#[cfg(feature = "server")]
+pub(crate) async fn serve<H, F>(listener: TcpListener, handler: H) -> io::Result<()>
+where
+    H: Fn(HttpRequest) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = HttpResponse> + Send + 'static,
+{
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let permit = connections.clone().acquire_owned().await.map_err(io::Error::other)?;
+        let (stream, peer) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let handler = handler.clone();
+                async move { Ok::<_, Infallible>(answer(request, handler).await) }
+            });
+            let served = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(HEADER_TIMEOUT)
+                .max_buf_size(MAX_REQUEST_HEAD)
+                .max_headers(MAX_HEADERS)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+            if let Err(e) = served {
+                debug!(peer = %peer, error = %e, "HTTP connection ended with an error");
+            }
+            drop(permit);
+        });
+    }
+}
+
+#[cfg(feature = "server")]
+async fn answer<H, F>(request: Request<Incoming>, handler: H) -> HttpResponse
+where
+    H: Fn(HttpRequest) -> F,
+    F: Future<Output = HttpResponse>,
+{
+    if request.body().size_hint().lower() > 0 || request.headers().contains_key(TRANSFER_ENCODING) {
+        return response(413, "text/plain", "request bodies are not accepted");
+    }
+    let (parts, _) = request.into_parts();
+    let Some(path) = percent_decode(parts.uri.path()) else {
+        return response(400, "text/plain", format!("invalid path {:?}", parts.uri.path()));
+    };
+    handler(HttpRequest { method: parts.method, path, headers: parts.headers }).await
+}
+
+// Escapes everything but unreserved characters and slashes, for paths in request lines
+pub(crate) fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'/') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(feature = "server")]
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::testdir::block_on;
+    use std::time::Instant;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    // What the server sends back to `request` before closing the connection
+    async fn exchange(address: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut answer = Vec::new();
+        stream.read_to_end(&mut answer).await.unwrap();
+        String::from_utf8_lossy(&answer).into_owned()
+    }
+
+    #[test]
+    fn requests_are_capped_and_timed() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(serve(listener, |request: HttpRequest| async move { response(200, "text/plain", request.path) }));
+
+            let answer = exchange(address, "GET /a%20b HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").await;
+            assert!(answer.starts_with("HTTP/1.1 200"), "{}", answer);
+            assert!(answer.ends_with("\r\n\r\n/a b"), "{}", answer);
+
+            let answer = exchange(address, "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello").await;
+            assert!(answer.starts_with("HTTP/1.1 413"), "{}", answer);
+
+            let headers: String = (0..MAX_HEADERS).map(|n| format!("X-Header-{}: {}\r\n", n, n)).collect();
+            let answer = exchange(address, &format!("GET / HTTP/1.1\r\nHost: x\r\n{}\r\n", headers)).await;
+            assert!(answer.starts_with("HTTP/1.1 431"), "{}", answer);
+
+            let answer = exchange(address, &format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_REQUEST_HEAD))).await;
+            assert!(answer.starts_with("HTTP/1.1 431"), "{}", answer);
+
+            // A head that never ends is cut off
+            let started = Instant::now();
+            exchange(address, "GET / HTTP/1.1\r\nHost: x\r\n").await;
+            assert!(started.elapsed() < HEADER_TIMEOUT * 5);
+        });
+    }
+}
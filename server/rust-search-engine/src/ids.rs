@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+use crate::error::{Result, SearchEngineError};
+use crate::SearchEngine;
+
+// How documents get the IDs reported in `IndexedDocument` and search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdStrategy {
+    // A random UUIDv4, kept when a document is re-indexed at the same path
+    #[default]
+    RandomUuid,
+    // UUIDv5 of the relative path (forward slashes) in the RFC 4122 URL namespace,
+    // so the same path always gets the same ID
+    PathUuid,
+    // Hex SHA-1 of the content; indexing identical content at a second path is a conflict
+    ContentHash,
+    // Supplied by the caller with `DocumentInput::id`; documents without one are rejected
+    Provided,
+}
+
+pub fn random_uuid() -> String {
+    Uuid::new_v4().to_string()
+}
+
+pub fn path_uuid(relative_path: &str) -> String {
+    Uuid::new_v5(&Uuid::NAMESPACE_URL, relative_path.replace('\\', "/").as_bytes()).to_string()
+}
+
+impl SearchEngine {
+    async fn content_hash(&self, content_path: &Path) -> Result<String> {
+        let mut content = self.storage.open(content_path).await?;
+        let mut sha1 = Sha1::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = content.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            sha1.update(&buffer[..read]);
+        }
+        Ok(sha1.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    // Picks the ID for a document about to be stored at `target` (its content is
+    // currently at `content_path`) and checks no other document already has it
+    pub(crate) async fn assign_document_id(&self, target: &Path, content_path: &Path, provided: Option<String>) -> Result<String> {
+        let catalog = self.catalog().await?;
+        let id = match self.id_strategy {
+            IdStrategy::RandomUuid => catalog.get(target)
+                .and_then(|doc| doc.attributes.document_id.clone())
+                .unwrap_or_else(random_uuid),
+            IdStrategy::PathUuid => path_uuid(&self.relative_path(target)),
+            IdStrategy::ContentHash => self.content_hash(content_path).await?,
+            IdStrategy::Provided => provided
+                .filter(|id| !id.trim().is_empty())
+                .ok_or_else(|| SearchEngineError::InvalidInput(format!(
+                    "{:?} needs a document ID: the engine uses caller-provided IDs", self.relative_path(target)
+                )))?,
+        };
+
+        if let Some(other) = catalog.documents.iter()
+            .find(|doc| doc.path != target && doc.attributes.document_id.as_deref() == Some(id.as_str()))
+        {
+            return Err(SearchEngineError::Conflict(format!(
+                "Document ID {} is already used by {}", id, self.relative_path(&other.path)
+            )));
+        }
+        Ok(id)
+    }
+
+    // ID for a document found by a rescan, if the strategy can make one up
+    pub(crate) fn scanned_document_id(&self, file_path: &Path) -> Option<String> {
+        match self.id_strategy {
+            IdStrategy::RandomUuid => Some(random_uuid()),
+            IdStrategy::PathUuid => Some(path_uuid(&self.relative_path(file_path))),
+            IdStrategy::ContentHash | IdStrategy::Provided => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+
+    #[test]
+    fn path_ids_are_name_based_uuids_of_the_path() {
+        assert_eq!(path_uuid("docs/a.txt"), "853320da-8af1-5dd2-99ca-b18983af6aca");
+        assert_eq!(path_uuid("docs\\a.txt"), path_uuid("docs/a.txt"));
+        let random = random_uuid();
+        assert_eq!(Uuid::parse_str(&random).unwrap().get_version_num(), 4);
+        assert_ne!(random, random_uuid());
+    }
+
+    #[test]
+    fn content_hash_ids_are_the_sha1_of_the_content() {
+        let dir = TestDir::new("ids");
+        block_on(async {
+            let engine = SearchEngine::builder(dir.path()).id_strategy(IdStrategy::ContentHash).build().await.unwrap();
+            index(&engine, vec![DocumentInput::from_bytes("a.txt", "hello\n")]).await;
+            let catalog = engine.catalog().await.unwrap();
+            assert_eq!(catalog.documents[0].attributes.document_id.as_deref(), Some("f572d396fae9206628714fb2ce00f72e94f2258f"));
+        });
+    }
+}
@@ -0,0 +1,174 @@
+// Serializes changes to a search directory's index across processes. Every change to the
+// catalog, the change log or the audit log is made holding the engine's write lock and an
+// exclusive advisory lock on `.index/lock`, which the OS releases if the process dies. The
+// lock file also counts the saves of the catalog, so a process taking the lock reloads a
+// catalog another process saved since and applies its own uncommitted changes on top.
+
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::sync::PoisonError;
+use tokio::fs as async_fs;
+use tokio::sync::MutexGuard;
+use tracing::warn;
+
+use crate::engine::restore_catalog;
+use crate::error::{Context, Result};
+use crate::SearchEngine;
+
+pub const LOCK_FILE: &str = "lock";
+
+// Held while changing the index; dropping it lets other tasks and processes in
+pub(crate) struct WriteGuard<'a> {
+    _write: MutexGuard<'a, ()>,
+    // None on a read-only engine, which changes nothing on disk
+    _file: Option<File>,
+}
+
+async fn lock_exclusive(path: PathBuf) -> std::io::Result<File> {
+    tokio::task::spawn_blocking(move || {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        file.lock_exclusive()?;
+        Ok(file)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+impl SearchEngine {
+    fn lock_path(&self) -> PathBuf {
+        self.doc_store.index_dir().join(LOCK_FILE)
+    }
+
+    // Times the catalog was saved, as the lock file records it; None before the first save
+    pub(crate) async fn catalog_revision(&self) -> Option<u64> {
+        async_fs::read_to_string(self.lock_path()).await.ok()?.trim().parse().ok()
+    }
+
+    // Records a save of the catalog for other processes; called holding the lock
+    pub(crate) async fn bump_catalog_revision(&self) -> Result<()> {
+        let revision = self.catalog_revision().await.unwrap_or(0) + 1;
+        async_fs::write(self.lock_path(), revision.to_string()).await
+            .context("Failed to update index lock")?;
+        *self.shared.revision.lock().unwrap_or_else(PoisonError::into_inner) = Some(revision);
+        Ok(())
+    }
+
+    // Takes the engine's write lock and the lock on the index, then brings the catalog up to
+    // date with the one on disk
+    pub(crate) async fn lock_writes(&self) -> Result<WriteGuard<'_>> {
+        let write = self.shared.write_lock.lock().await;
+        let file = if self.read_only {
+            None
+        } else {
+            async_fs::create_dir_all(self.doc_store.index_dir()).await
+                .context("Failed to create index directory")?;
+            Some(lock_exclusive(self.lock_path()).await.context("Failed to lock the index")?)
+        };
+        self.sync_catalog().await?;
+        Ok(WriteGuard { _write: write, _file: file })
+    }
+
+    // Reloads the catalog if another process saved it since this one loaded or saved it,
+    // keeping the changes made here that aren't committed yet, and scans the search
+    // directory if no catalog was ever saved
+    async fn sync_catalog(&self) -> Result<()> {
+        let revision = self.catalog_revision().await;
+        let known = *self.shared.revision.lock().unwrap_or_else(PoisonError::into_inner);
+        let loaded = self.loaded_catalog();
+        if loaded.is_some() && revision == known {
+            return Ok(());
+        }
+        let saved = match self.doc_store.load_meta().await {
+            Ok(meta) => meta,
+            Err(e) => {
+                warn!(error = %e, "Ignoring unreadable index metadata");
+                None
+            }
+        };
+        match (saved, loaded) {
+            (Some(meta), loaded) => {
+                let mut catalog = restore_catalog(&self.search_path, &self.roots, meta);
+                if let Some(loaded) = loaded {
+                    catalog.rebase(&loaded);
+                }
+                self.replace_catalog(catalog);
+            }
+            (None, None) => {
+                let catalog = self.scan_documents().await?;
+                self.replace_catalog(catalog);
+            }
+            (None, Some(_)) => {}
+        }
+        // A scan above saved the catalog, counting another revision
+        *self.shared.revision.lock().unwrap_or_else(PoisonError::into_inner) = self.catalog_revision().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::process::{Command, Stdio};
+
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, TestDir};
+    use crate::SearchEngine;
+
+    // Set for the child processes the test below starts
+    const CHILD_DIR: &str = "SEARCH_ENGINE_LOCK_TEST_DIR";
+    const CHILD_NAME: &str = "SEARCH_ENGINE_LOCK_TEST_CHILD";
+    const PROCESSES: usize = 8;
+    const DOCUMENTS: usize = 4;
+
+    // What each child process runs: indexes its documents one at a time, visible to hr only.
+    // Does nothing when run as a test of its own.
+    #[test]
+    fn child_indexes_documents() {
+        let (Ok(dir), Ok(child)) = (std::env::var(CHILD_DIR), std::env::var(CHILD_NAME)) else {
+            return;
+        };
+        block_on(async {
+            let engine = SearchEngine::builder(&dir).build().await.unwrap();
+            for n in 0..DOCUMENTS {
+                let input = DocumentInput::from_bytes(format!("{}-{}.txt", child, n), format!("payroll for {}\n", child)).acl(["hr"]);
+                let report = engine.index_source(vec![input].into_iter()).await.unwrap();
+                assert!(report.failed.is_empty(), "{:?}", report.failed);
+            }
+        });
+    }
+
+    #[test]
+    fn processes_indexing_at_once_lose_nothing() {
+        let dir = TestDir::new("indexlock");
+        let exe = std::env::current_exe().unwrap();
+        let children: Vec<_> = (0..PROCESSES)
+            .map(|child| {
+                Command::new(&exe)
+                    .args(["--exact", "indexlock::tests::child_indexes_documents", "--quiet"])
+                    .env(CHILD_DIR, dir.path())
+                    .env(CHILD_NAME, format!("p{}", child))
+                    .stdout(Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+
+        block_on(async {
+            let engine = SearchEngine::builder(dir.path()).build().await.unwrap();
+            let catalog = engine.catalog().await.unwrap();
+            assert_eq!(catalog.documents.len(), PROCESSES * DOCUMENTS);
+            for doc in &catalog.documents {
+                assert_eq!(doc.attributes.acl, ["hr"], "{}", doc.path.display());
+            }
+            assert_eq!(catalog.generation, (PROCESSES * DOCUMENTS) as u64);
+            let feed = engine.changes_since(0, None).await.unwrap();
+            let generations: HashSet<u64> = feed.changes.iter().map(|change| change.generation).collect();
+            assert_eq!(feed.changes.len(), PROCESSES * DOCUMENTS);
+            assert_eq!(generations.len(), feed.changes.len());
+        });
+    }
+}
@@ -0,0 +1,344 @@
+// Terminal UI for `search-engine interactive`: search-as-you-type over the index with a
+// result list, a preview pane of the lines around the selected hit, and Enter to open the
+// hit in $VISUAL / $EDITOR. Drawn with ratatui on a crossterm terminal.
+
+use std::io::{IsTerminal, Stdout};
+use std::path::Path;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::error::{Result, SearchEngineError};
+use crate::options::{MatchMode, SearchOptions};
+use crate::{SearchEngine, SearchResult};
+
+// Results fetched per query; the list scrolls within them
+const MAX_RESULTS: usize = 500;
+// Lines loaded on each side of the selected hit for the preview
+const PREVIEW_CONTEXT: usize = 20;
+// How long typing has to pause before the query is searched
+const DEBOUNCE: Duration = Duration::from_millis(100);
+const PAGE: usize = 10;
+const HELP: &str = "↑/↓ PgUp/PgDn select  Enter open in editor  Tab match mode  Ctrl-U clear  Esc quit";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Key {
+    Char(char),
+    Backspace,
+    Enter,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Tab,
+    ClearLine,
+    Quit,
+}
+
+fn key(event: KeyEvent) -> Option<Key> {
+    // Some platforms report releases as well as presses
+    if event.kind == KeyEventKind::Release {
+        return None;
+    }
+    let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+    let key = match event.code {
+        KeyCode::Esc => Key::Quit,
+        KeyCode::Char('c' | 'd') if ctrl => Key::Quit,
+        KeyCode::Char('u') if ctrl => Key::ClearLine,
+        KeyCode::Char('p') if ctrl => Key::Up,
+        KeyCode::Char('n') if ctrl => Key::Down,
+        KeyCode::Char(_) if ctrl => return None,
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::Tab => Key::Tab,
+        _ => return None,
+    };
+    Some(key)
+}
+
+// Waits up to `DEBOUNCE` for input and takes whatever else is pending; no keys means the
+// user paused typing
+fn read_keys() -> Result<Vec<Key>> {
+    let mut keys = Vec::new();
+    let mut timeout = DEBOUNCE;
+    while event::poll(timeout)? {
+        if let Event::Key(event) = event::read()? {
+            keys.extend(key(event));
+        }
+        timeout = Duration::ZERO;
+    }
+    Ok(keys)
+}
+
+// The terminal in raw mode on the alternate screen, restored on drop
+struct Screen {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            return Err(SearchEngineError::InvalidInput("interactive mode needs a terminal".to_string()));
+        }
+        let mut screen = Screen { terminal: Terminal::new(CrosstermBackend::new(std::io::stdout()))? };
+        screen.resume()?;
+        Ok(screen)
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen)?;
+        self.terminal.clear()?;
+        Ok(())
+    }
+
+    // Restores the terminal for a child process such as the editor
+    fn suspend(&mut self) {
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+        let _ = self.terminal.show_cursor();
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.suspend();
+    }
+}
+
+struct State {
+    query: String,
+    options: SearchOptions,
+    results: Vec<SearchResult>,
+    total: usize,
+    list: ListState,
+    // Numbered lines around the selected hit
+    preview: Vec<(usize, String)>,
+    error: Option<String>,
+    // The query changed since the last search
+    dirty: bool,
+}
+
+impl State {
+    fn selected(&self) -> Option<&SearchResult> {
+        self.list.selected().and_then(|index| self.results.get(index))
+    }
+
+    // Moves the selection by `offset` rows, staying within the results
+    fn select(&mut self, offset: isize) {
+        let Some(last) = self.results.len().checked_sub(1) else {
+            return;
+        };
+        let current = self.list.selected().unwrap_or(0);
+        self.list.select(Some(current.saturating_add_signed(offset).min(last)));
+    }
+}
+
+pub async fn run(engine: &SearchEngine, options: SearchOptions) -> Result<()> {
+    let mut screen = Screen::enter()?;
+    let mut state = State {
+        query: String::new(),
+        options: SearchOptions { limit: MAX_RESULTS, offset: 0, ..options },
+        results: Vec::new(),
+        total: 0,
+        list: ListState::default(),
+        preview: Vec::new(),
+        error: None,
+        dirty: false,
+    };
+
+    loop {
+        screen.terminal.draw(|frame| draw(frame, engine, &mut state))?;
+        let keys = tokio::task::spawn_blocking(read_keys).await
+            .map_err(|e| SearchEngineError::InvalidInput(format!("terminal input failed: {}", e)))??;
+        if keys.is_empty() {
+            // Search once typing pauses rather than on every keystroke
+            if state.dirty {
+                search(engine, &mut state).await;
+                load_preview(engine, &mut state).await;
+            }
+            continue;
+        }
+
+        let previous = state.list.selected();
+        for key in keys {
+            match key {
+                Key::Quit => return Ok(()),
+                Key::Char(c) => {
+                    state.query.push(c);
+                    state.dirty = true;
+                }
+                Key::Backspace => {
+                    state.dirty |= state.query.pop().is_some();
+                }
+                Key::ClearLine => {
+                    state.dirty |= !state.query.is_empty();
+                    state.query.clear();
+                }
+                Key::Tab => {
+                    state.options.mode = match state.options.mode {
+                        MatchMode::Substring => MatchMode::Word,
+                        MatchMode::Word if cfg!(feature = "regex") => MatchMode::Regex,
+                        _ => MatchMode::Substring,
+                    };
+                    state.dirty = true;
+                }
+                Key::Up => state.select(-1),
+                Key::Down => state.select(1),
+                Key::PageUp => state.select(-(PAGE as isize)),
+                Key::PageDown => state.select(PAGE as isize),
+                Key::Home => state.select(isize::MIN),
+                Key::End => state.select(isize::MAX),
+                Key::Enter => {
+                    if let Some(hit) = state.selected() {
+                        let (path, line) = (hit.path.to_string(), hit.line_number);
+                        screen.suspend();
+                        let opened = open_in_editor(Path::new(&path), line);
+                        screen.resume()?;
+                        state.error = opened.err().map(|e| e.to_string());
+                    }
+                }
+            }
+        }
+        if state.list.selected() != previous {
+            load_preview(engine, &mut state).await;
+        }
+    }
+}
+
+async fn search(engine: &SearchEngine, state: &mut State) {
+    state.dirty = false;
+    state.error = None;
+    state.results.clear();
+    state.total = 0;
+    if !state.query.trim().is_empty() {
+        match engine.search(&state.query, &state.options).await {
+            Ok(response) => {
+                state.total = response.total;
+                state.results = response.results;
+            }
+            Err(e) => state.error = Some(e.to_string()),
+        }
+    }
+    state.list.select((!state.results.is_empty()).then_some(0));
+}
+
+async fn load_preview(engine: &SearchEngine, state: &mut State) {
+    state.preview.clear();
+    let Some(hit) = state.selected() else {
+        return;
+    };
+    let path = hit.path.clone();
+    let line = hit.line_number.max(1) as usize;
+    let mut preview = Vec::new();
+    for number in line.saturating_sub(PREVIEW_CONTEXT).max(1)..=line + PREVIEW_CONTEXT {
+        match engine.get_line(&path, number, state.options.filters.principals.as_deref()).await {
+            Ok(Some(result)) => preview.push((number, result.content)),
+            _ => break,
+        }
+    }
+    state.preview = preview;
+}
+
+fn open_in_editor(path: &Path, line: i64) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Editors like vim, nano and emacs accept +LINE; the command may carry its own arguments
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(format!("+{}", line))
+        .arg(path)
+        .status()
+        .map_err(|e| SearchEngineError::InvalidInput(format!("failed to start {}: {}", program, e)))?;
+    if !status.success() {
+        return Err(SearchEngineError::InvalidInput(format!("{} exited with {}", program, status)));
+    }
+    Ok(())
+}
+
+// Tabs would throw the columns off
+fn untab(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+// The query box on top, the results beside the preview of the selected one, and a status line
+fn draw(frame: &mut Frame, engine: &SearchEngine, state: &mut State) {
+    let [input, body, status] =
+        Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let [list, preview] = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(body);
+
+    // Long queries scroll so their end, where the cursor is, stays in view
+    let mode = format!("{:?}", state.options.mode).to_lowercase();
+    let typed = state.query.chars().count() as u16;
+    let overflow = typed.saturating_sub(input.width.saturating_sub(3));
+    frame.render_widget(
+        Paragraph::new(state.query.as_str())
+            .scroll((0, overflow))
+            .block(Block::bordered().title(format!(" Search · {} ", mode))),
+        input,
+    );
+    frame.set_cursor_position(Position::new(input.x + 1 + typed - overflow, input.y + 1));
+
+    let items: Vec<ListItem> = state.results.iter()
+        .map(|hit| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{}:{} ", engine.relative_path(Path::new(&*hit.path)), hit.line_number),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw(untab(hit.content.trim())),
+            ]))
+        })
+        .collect();
+    let results = List::new(items)
+        .block(Block::bordered().title(format!(" {} matches ", state.total)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(results, list, &mut state.list);
+
+    // The hit's line is highlighted and kept in the middle of the pane
+    let hit = state.selected();
+    let hit_line = hit.map(|hit| hit.line_number.max(1) as usize);
+    let title = hit.map(|hit| format!(" {} ", engine.relative_path(Path::new(&*hit.path)))).unwrap_or_default();
+    let lines: Vec<Line> = state.preview.iter()
+        .map(|(number, content)| {
+            let text = format!("{:>6} │ {}", number, untab(content));
+            if Some(*number) == hit_line {
+                Line::styled(text, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            } else {
+                Line::raw(text)
+            }
+        })
+        .collect();
+    let hit_row = state.preview.iter().position(|(number, _)| Some(*number) == hit_line).unwrap_or(0);
+    let scroll = hit_row.saturating_sub(preview.height.saturating_sub(2) as usize / 2);
+    frame.render_widget(
+        Paragraph::new(lines).scroll((scroll as u16, 0)).block(Block::bordered().title(title)),
+        preview,
+    );
+
+    let status_line = match &state.error {
+        Some(error) => Line::styled(error.as_str(), Style::default().fg(Color::Red)),
+        None => Line::styled(HELP, Style::default().add_modifier(Modifier::DIM)),
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+}
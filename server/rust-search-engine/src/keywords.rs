@@ -0,0 +1,92 @@
+// Keyphrase extraction with RAKE (Rapid Automatic Keyword Extraction): candidate phrases
+// are the runs of words between stop words and punctuation, and each word scores by how
+// many other words it appears alongside. Pure computation, so it builds for wasm32 as well.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::stopwords::ENGLISH;
+
+// Keywords stored per document
+pub const MAX_KEYWORDS: usize = 10;
+// Longer runs are rarely meaningful phrases
+const MAX_PHRASE_WORDS: usize = 3;
+// Only the start of very large documents is analyzed
+const MAX_ANALYZED_BYTES: usize = 4 * 1024 * 1024;
+
+// How many documents carry a keyword, for faceting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct KeywordCount {
+    pub keyword: String,
+    pub documents: usize,
+}
+
+// Words too short or common to carry meaning, and bare numbers
+pub(crate) fn is_stop_word(word: &str) -> bool {
+    word.chars().count() < 3 || word.chars().all(|c| c.is_ascii_digit()) || ENGLISH.contains(&word)
+}
+
+fn candidate_phrases(text: &str) -> Vec<Vec<String>> {
+    let mut phrases = Vec::new();
+    // Punctuation and line breaks end a phrase; spaces and tabs don't
+    for fragment in text.split(|c: char| !(c.is_alphanumeric() || c == ' ' || c == '\t')) {
+        let mut phrase = Vec::new();
+        for word in fragment.split_whitespace().map(str::to_lowercase) {
+            if is_stop_word(&word) {
+                if !phrase.is_empty() {
+                    phrases.push(std::mem::take(&mut phrase));
+                }
+            } else {
+                phrase.push(word);
+            }
+        }
+        if !phrase.is_empty() {
+            phrases.push(phrase);
+        }
+    }
+    // A run too long to be a phrase still contributes its words
+    phrases.into_iter()
+        .flat_map(|phrase| {
+            if phrase.len() <= MAX_PHRASE_WORDS {
+                vec![phrase]
+            } else {
+                phrase.into_iter().map(|word| vec![word]).collect()
+            }
+        })
+        .collect()
+}
+
+// The most significant phrases of `text`, best first
+pub fn extract_keywords(text: &str, max: usize) -> Vec<String> {
+    let mut end = text.len().min(MAX_ANALYZED_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let phrases = candidate_phrases(&text[..end]);
+
+    // A word's degree counts the words it co-occurs with in phrases, itself included
+    let mut frequency: HashMap<&str, f32> = HashMap::new();
+    let mut degree: HashMap<&str, f32> = HashMap::new();
+    for phrase in &phrases {
+        for word in phrase {
+            *frequency.entry(word).or_insert(0.0) += 1.0;
+            *degree.entry(word).or_insert(0.0) += phrase.len() as f32;
+        }
+    }
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for phrase in &phrases {
+        *occurrences.entry(phrase.join(" ")).or_insert(0) += 1;
+    }
+
+    // Plain RAKE favors long one-off phrases; weighting by how often a phrase recurs
+    // favors what the document is actually about
+    let mut scored: Vec<(String, f32)> = occurrences.into_iter()
+        .map(|(phrase, count)| {
+            let score: f32 = phrase.split(' ').map(|word| degree[word] / frequency[word]).sum();
+            (phrase, score * (count as f32).sqrt())
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+    scored.into_iter().take(max).map(|(phrase, _)| phrase).collect()
+}
@@ -0,0 +1,103 @@
+// Language detection for indexed documents, so searches can be narrowed with `lang:fr`.
+// Detection is whatlang's, which tells its 69 languages apart by script and trigram statistics.
+// The result is an ISO 639-1 code such as "en" or "fr", or "und" (ISO 639-2 for undetermined)
+// when the text is too short or too mixed to tell.
+
+use whatlang::Lang;
+
+pub const UNDETERMINED: &str = "und";
+
+// Only the start of a document is looked at
+const SAMPLE_BYTES: usize = 64 * 1024;
+// Confidence a detection needs to be trusted. whatlang calls a guess reliable from about 0.9,
+// which a sentence or two of running text seldom reaches; below about 0.2 it is often wrong,
+// e.g. on log lines or a single word.
+const MIN_CONFIDENCE: f64 = 0.25;
+
+// The language of `text` as an ISO 639-1 code, or `UNDETERMINED`
+pub fn detect_language(text: &str) -> &'static str {
+    let mut end = text.len().min(SAMPLE_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match whatlang::detect(&text[..end]) {
+        Some(info) if info.confidence() >= MIN_CONFIDENCE => iso_639_1(info.lang()),
+        _ => UNDETERMINED,
+    }
+}
+
+// whatlang names languages by ISO 639-3 code; documents have carried the shorter codes since
+// before it was used, so those stay
+fn iso_639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Afr => "af",
+        Lang::Aka => "ak",
+        Lang::Amh => "am",
+        Lang::Ara => "ar",
+        Lang::Aze => "az",
+        Lang::Bel => "be",
+        Lang::Ben => "bn",
+        Lang::Bul => "bg",
+        Lang::Cat => "ca",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Est => "et",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Guj => "gu",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hrv => "hr",
+        Lang::Hun => "hu",
+        Lang::Hye => "hy",
+        Lang::Ind => "id",
+        Lang::Ita => "it",
+        Lang::Jav => "jv",
+        Lang::Jpn => "ja",
+        Lang::Kan => "kn",
+        Lang::Kat => "ka",
+        Lang::Khm => "km",
+        Lang::Kor => "ko",
+        Lang::Lat => "la",
+        Lang::Lav => "lv",
+        Lang::Lit => "lt",
+        Lang::Mal => "ml",
+        Lang::Mar => "mr",
+        Lang::Mkd => "mk",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Nld => "nl",
+        Lang::Nob => "nb",
+        Lang::Ori => "or",
+        Lang::Pan => "pa",
+        Lang::Pes => "fa",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Sin => "si",
+        Lang::Slk => "sk",
+        Lang::Slv => "sl",
+        Lang::Sna => "sn",
+        Lang::Spa => "es",
+        Lang::Srp => "sr",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tel => "te",
+        Lang::Tgl => "tl",
+        Lang::Tha => "th",
+        Lang::Tuk => "tk",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        Lang::Urd => "ur",
+        Lang::Uzb => "uz",
+        Lang::Vie => "vi",
+        Lang::Yid => "yi",
+        Lang::Zul => "zu",
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use tracing_appender::rolling::{self, RollingFileAppender};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::{Result, SearchEngineError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Compact,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    // Filter directive such as "info" or "search_engine=debug"; RUST_LOG takes precedence
+    pub level: String,
+    // Write to a rotating file instead of stderr
+    pub file: Option<FileLogConfig>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::default(),
+            level: "warn".to_string(),
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotation {
+    fn appender_rotation(self) -> rolling::Rotation {
+        match self {
+            Rotation::Never => rolling::Rotation::NEVER,
+            Rotation::Hourly => rolling::Rotation::HOURLY,
+            Rotation::Daily => rolling::Rotation::DAILY,
+        }
+    }
+}
+
+// e.g. {"path": "logs/search-engine.log", "rotation": "daily", "max_files": 7}. With rotation
+// each period gets its own file, the path with the period appended such as
+// "logs/search-engine.log.2024-05-01", as tracing-appender names them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileLogConfig {
+    pub path: PathBuf,
+    // No longer supported, files only rotating by time; rejected rather than ignored, so a
+    // config relying on it to bound the log's size doesn't silently stop doing so
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub rotation: Rotation,
+    // Files of earlier periods to keep besides the current one; older ones are deleted
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+// A writer appending to the log file of the current period, deleting the oldest when a new
+// period starts and more than `max_files` earlier ones are left
+fn appender(config: &FileLogConfig) -> Result<RollingFileAppender> {
+    if config.max_bytes.is_some() {
+        return Err(SearchEngineError::InvalidConfig(
+            "logging.file.max_bytes is no longer supported: log files rotate by \"rotation\", and \"max_files\" bounds how many are kept".to_string(),
+        ));
+    }
+    let name = config.path.file_name()
+        .ok_or_else(|| SearchEngineError::InvalidConfig(format!("Log file {:?} has no file name", config.path)))?;
+    let directory = config.path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    RollingFileAppender::builder()
+        .rotation(config.rotation.appender_rotation())
+        .filename_prefix(name.to_string_lossy())
+        .max_log_files(config.max_files + 1)
+        .build(directory)
+        .map_err(|e| SearchEngineError::Io { context: format!("Failed to open log file {:?}", config.path), source: io::Error::other(e) })
+}
+
+// Installs the global tracing subscriber. Diagnostics go to stderr unless a log
+// file is configured, never to stdout, because stdout carries the JSON responses.
+pub fn init(config: &LoggingConfig) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.level))
+        .map_err(|e| SearchEngineError::InvalidConfig(format!("Invalid log level {:?}: {}", config.level, e)))?;
+
+    match &config.file {
+        Some(file) => install(config.format, filter, appender(file)?, false),
+        None => install(config.format, filter, io::stderr, io::stderr().is_terminal()),
+    }
+}
+
+fn install<W>(format: LogFormat, filter: EnvFilter, writer: W, ansi: bool) -> Result<()>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi);
+
+    let result = match format {
+        LogFormat::Pretty => builder.pretty().try_init(),
+        LogFormat::Compact => builder.compact().try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    };
+    result.map_err(|e| SearchEngineError::Logging(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::io::Write;
+
+    #[test]
+    fn log_files_are_named_by_their_period() {
+        let dir = TestDir::new("logging");
+        let daily = FileLogConfig { path: dir.path().join("logs/search-engine.log"), max_bytes: None, rotation: Rotation::Daily, max_files: 2 };
+        appender(&daily).unwrap().make_writer().write_all(b"started\n").unwrap();
+        let today = chrono::Utc::now().format("%Y-%m-%d");
+        let written = std::fs::read_to_string(dir.path().join(format!("logs/search-engine.log.{}", today))).unwrap();
+        assert_eq!(written, "started\n");
+
+        let never = FileLogConfig { path: dir.path().join("plain.log"), rotation: Rotation::Never, ..daily.clone() };
+        appender(&never).unwrap().make_writer().write_all(b"started\n").unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("plain.log")).unwrap(), "started\n");
+
+        let sized = FileLogConfig { max_bytes: Some(1024), ..daily };
+        assert!(matches!(appender(&sized), Err(SearchEngineError::InvalidConfig(_))));
+    }
+}
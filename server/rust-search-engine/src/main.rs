@@ -1,11 +1,60 @@
-use clap::{Parser, Subcommand};
-use search_engine::SearchEngine;
-use log::info;
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use search_engine::audit::AuditFilter;
+use search_engine::config::{Config, DEFAULT_SEARCH_DIRECTORY};
+use search_engine::dedup::DEFAULT_MAX_DISTANCE;
+use search_engine::logging::{self, LogFormat};
+use search_engine::progress::{NoProgress, Progress, ProgressReporter};
+use search_engine::replication::DEFAULT_REPLICATION_ADDRESS;
+use search_engine::metadata::{parse_field, Metadata, MetadataValue};
+use search_engine::options::{parse_boost, MatchMode, SearchFilters, SearchLimits, SearchOptions, SortOrder};
+use search_engine::partition::{parse_date_time, parse_end_date_time};
+use search_engine::records::{JsonMapping, JsonSource, RECORD_BATCH_SIZE};
+use search_engine::schedule::Scheduler;
+use search_engine::schema::{Versioned, SCHEMA_VERSION};
+use search_engine::scope::PathScope;
+use search_engine::scroll::DEFAULT_BATCH_SIZE;
+use search_engine::stress::StressOptions;
+use search_engine::ttl::parse_duration;
+use search_engine::version::version_info;
+use search_engine::{DirectorySource, MaintenanceTask, SearchEngine, SearchEngineError};
+use tracing::{info, warn};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 #[derive(Parser)]
 #[command(name = "search-engine")]
 #[command(about = "A full-text search engine")]
+#[command(after_help = "Exit status: 0 on success, 1 when a search finds no results, \
+2 for an invalid query or arguments, 3 when the engine fails.")]
 struct Cli {
+    /// Path to the JSON config file (defaults to ./search-engine.json if present)
+    #[arg(long, global = true, env = "SEARCH_ENGINE_CONFIG")]
+    config: Option<PathBuf>,
+    /// Named profile from the config file to apply, e.g. dev or prod
+    #[arg(long, env = "SEARCH_ENGINE_PROFILE")]
+    profile: Option<String>,
+    /// Directory of documents to search (overrides SEARCH_DIRECTORY and the config file)
+    #[arg(long, global = true, env = "SEARCH_DIRECTORY")]
+    dir: Option<PathBuf>,
+    /// Identity recorded in the audit log for changes made by this command
+    #[arg(long, global = true, default_value = "cli")]
+    actor: String,
+    /// Diagnostic log format on stderr (overrides the config file)
+    #[arg(long, global = true, value_enum)]
+    log_format: Option<LogFormat>,
+    /// Open the search directory without writing anything under it; commands that would change it fail
+    #[arg(long, global = true, env = "SEARCH_ENGINE_READ_ONLY")]
+    read_only: bool,
+    /// Print only results and errors: no diagnostic logs or progress
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Report failures as a JSON object on stderr: {"error": {"code", "message", "details"}}
+    /// (also prints `version` as JSON)
+    #[arg(long, global = true)]
+    json: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -22,46 +71,1322 @@ enum Commands {
         /// Number of results to skip
         #[arg(short, long, default_value = "0")]
         offset: usize,
+        #[command(flatten)]
+        ordering: Box<SortArgs>,
+        /// How the query is matched against lines [default: substring, or the collection's]
+        #[arg(long, value_enum)]
+        mode: Option<MatchMode>,
+        /// Match the query in the case it is written in, e.g. for identifiers or codes
+        #[arg(short = 's', long)]
+        case_sensitive: bool,
+        /// Start from the defaults of this collection in the config; other options override them
+        #[arg(long)]
+        collection: Option<String>,
+        /// Search several collections at once, e.g. docs,wiki, merging their results with scores
+        /// normalized per collection
+        #[arg(long, value_delimiter = ',', conflicts_with = "collection")]
+        collections: Vec<String>,
+        /// Only search documents whose relative path starts with this prefix
+        #[arg(long)]
+        path_prefix: Option<String>,
+        /// Only search documents with this extension (repeatable)
+        #[arg(long = "extension")]
+        extensions: Vec<String>,
+        /// Only search documents with this tag (repeatable); tag:NAME in the query does the same
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only search documents in this language, e.g. fr (repeatable); lang:CODE in the query does the same
+        #[arg(long = "lang")]
+        languages: Vec<String>,
+        /// Only search documents whose metadata field has this value, e.g. customer=acme (repeatable)
+        #[arg(long = "meta", value_parser = parse_field)]
+        metadata: Vec<(String, MetadataValue)>,
+        /// Search on behalf of this principal, e.g. alice or group:finance (repeatable): only documents
+        /// without an access control list or whose list names one of them are returned
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+        #[command(flatten)]
+        dates: Box<DateArgs>,
+        /// Include the byte ranges of each match in results
+        #[arg(long)]
+        highlight: bool,
+        /// Return partial results after this long, e.g. 500ms or 5s
+        #[arg(long, value_parser = parse_duration)]
+        timeout: Option<chrono::Duration>,
+        /// Include a per-phase timing breakdown in the response
+        #[arg(long)]
+        profile: bool,
+        /// Show only the best-ranked document of each group of near-duplicates
+        #[arg(long)]
+        collapse_duplicates: bool,
+        /// Group the returned results into up to N topics
+        #[arg(long, default_value = "0")]
+        clusters: usize,
+        /// Return one result per document with a summary of its best-matching sentences
+        #[arg(long)]
+        summarize: bool,
+        /// Search the documents pinned by this point-in-time reader (see `reader open`)
+        #[arg(long)]
+        reader: Option<String>,
+        #[command(flatten)]
+        result_sets: Box<ResultSetArgs>,
+        /// Return at most N lines from any one document
+        #[arg(long)]
+        max_per_file: Option<usize>,
+        /// Demote results from a directory already ranked above them, to show more of the corpus
+        #[arg(long)]
+        diversify: bool,
+        /// Multiply the scores of documents under a path prefix, e.g. logs/errors/=2 (repeatable)
+        #[arg(long = "boost", value_parser = parse_boost)]
+        boosts: Vec<(String, f32)>,
+        /// Count the values of this metadata field across matching documents (repeatable)
+        #[arg(long = "facet")]
+        facets: Vec<String>,
+        /// Also match words up to N edits from each query word (at most 2), ranked below exact matches
+        #[arg(long)]
+        fuzzy: Option<usize>,
+        #[command(flatten)]
+        limits: Box<LimitArgs>,
+    },
+    /// Find passages close in meaning to a natural-language query (needs an embedder in the config)
+    Semantic {
+        /// Natural-language query
+        query: String,
+        /// Maximum number of results
+        #[arg(short, long, default_value = "10")]
+        limit: usize,
+        /// Number of results to skip
+        #[arg(short, long, default_value = "0")]
+        offset: usize,
+        /// Only search documents whose relative path starts with this prefix
+        #[arg(long)]
+        path_prefix: Option<String>,
+        /// Only search documents with this extension (repeatable)
+        #[arg(long = "extension")]
+        extensions: Vec<String>,
+        /// Only search documents with this tag (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only search documents in this language, e.g. fr (repeatable)
+        #[arg(long = "lang")]
+        languages: Vec<String>,
+        /// Only search documents whose metadata field has this value, e.g. customer=acme (repeatable)
+        #[arg(long = "meta", value_parser = parse_field)]
+        metadata: Vec<(String, MetadataValue)>,
+        /// Search on behalf of this principal (repeatable); see `search --principal`
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+        /// Only search time partitions ending after this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long, value_parser = parse_date_time)]
+        from: Option<chrono::DateTime<chrono::Utc>>,
+        /// Only search time partitions starting on or before this date
+        #[arg(long, value_parser = parse_date_time)]
+        to: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Search as you type in a terminal UI, opening hits in $EDITOR
+    Interactive {
+        /// Initial match mode; Tab cycles through the modes
+        #[arg(long, value_enum, default_value_t = MatchMode::Substring)]
+        mode: MatchMode,
+        /// Only search documents whose relative path starts with this prefix
+        #[arg(long)]
+        path_prefix: Option<String>,
+        /// Only search documents with this extension (repeatable)
+        #[arg(long = "extension")]
+        extensions: Vec<String>,
+    },
+    /// Index a text document, or the text of a PDF, Word, OpenDocument or HTML file
+    Index {
+        /// Path to the document to index, or a directory to index every document in
+        path: String,
+        /// Remove the document after this long, e.g. 12h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<chrono::Duration>,
+        /// Show progress on stderr when indexing a directory
+        #[arg(long)]
+        progress: bool,
+        /// ID for the document, required when the configured id_strategy is "provided"
+        #[arg(long)]
+        id: Option<String>,
+        /// Store a metadata field with the document, e.g. customer=acme or priority=2 (repeatable);
+        /// replaces the metadata a re-indexed document had
+        #[arg(long = "meta", value_parser = parse_field)]
+        metadata: Vec<(String, MetadataValue)>,
+        /// Only let this principal see the document in searches made on behalf of principals
+        /// (repeatable); replaces the list a re-indexed document had
+        #[arg(long = "acl")]
+        acl: Vec<String>,
+    },
+    /// Index each record of a JSON or NDJSON file as a document: the elements of a top-level
+    /// array, each top-level object or each line
+    IndexJson {
+        /// Path to the .json, .jsonl or .ndjson file
+        path: String,
+        /// Field naming each record's document, and its ID when the id_strategy is "provided";
+        /// nested fields are given as paths such as author.name
+        #[arg(long = "id-field")]
+        id_field: Option<String>,
+        /// Field titling each record's results
+        #[arg(long = "title-field")]
+        title_field: Option<String>,
+        /// Field holding searchable text (repeatable); every field is searched when none are given
+        #[arg(long = "content-field")]
+        content_fields: Vec<String>,
+        /// Field stored as metadata under its path, to filter and facet on (repeatable)
+        #[arg(long = "meta-field")]
+        metadata_fields: Vec<String>,
+        /// Remove the records after this long, e.g. 12h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<chrono::Duration>,
+        /// Only let this principal see the records in searches made on behalf of principals (repeatable)
+        #[arg(long = "acl")]
+        acl: Vec<String>,
+        /// Show progress on stderr
+        #[arg(long)]
+        progress: bool,
+    },
+    /// Remove a document from the index, moving it to the trash
+    Delete {
+        /// Document path, relative to the search directory, or its document ID
+        path: String,
+    },
+    /// List documents in the trash
+    Trash,
+    /// Move a trashed document back into the search directory and index it
+    Restore {
+        /// Original document path, relative to the search directory, or a trash entry id
+        path: String,
+    },
+    /// Fetch a single line from a document
+    Get {
+        /// Document path, relative to the search directory, or its document ID
+        path: String,
+        /// Line number (1-based)
+        line: usize,
+        /// Only show documents this principal may see, as with search (repeatable)
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+    },
+    /// Fetch a range of lines from a document, e.g. for more context around a search hit
+    Lines {
+        /// Document path, relative to the search directory, or its document ID
+        path: String,
+        /// First line (1-based)
+        start: usize,
+        /// Last line, inclusive; a range past the end of the document stops at its last line
+        end: usize,
+        /// Only show documents this principal may see, as with search (repeatable)
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+    },
+    /// Summarize the query log
+    Analytics {
+        /// Number of queries to list in each ranking
+        #[arg(long, default_value = "10")]
+        top: usize,
+        /// Only include queries from this recent window, e.g. 24h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<chrono::Duration>,
+    },
+    /// Complete a partly typed query with indexed terms, or with past queries from the query log
+    Suggest {
+        /// The query typed so far
+        prefix: String,
+        /// Maximum number of suggestions
+        #[arg(long, default_value = "10")]
+        limit: usize,
+        /// Propose whole queries searched before, by how often they were searched and found results
+        #[arg(long)]
+        from_history: bool,
+        /// Only learn from queries in this recent window, e.g. 24h or 7d
+        #[arg(long, value_parser = parse_duration, requires = "from_history")]
+        since: Option<chrono::Duration>,
+        /// Only complete terms from documents this principal may see, as with search (repeatable)
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+    },
+    /// Show searches that took longer than `slow_query_threshold`, with their options and timings
+    SlowQueries {
+        /// Only include queries from this recent window, e.g. 24h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<chrono::Duration>,
+        /// Show at most the N most recent entries
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Show the audit log of index mutations
+    Audit {
+        /// Only include entries from this recent window, e.g. 24h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<chrono::Duration>,
+        /// Only include entries by this actor
+        #[arg(long)]
+        actor: Option<String>,
+        /// Only include this operation, e.g. index, delete, maintenance:clear-all
+        #[arg(long)]
+        operation: Option<String>,
+        /// Show at most the N most recent entries
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// List clusters of near-duplicate documents
+    Duplicates {
+        /// Most fingerprint bits two documents may differ in and still count as duplicates
+        #[arg(long, default_value_t = DEFAULT_MAX_DISTANCE)]
+        max_distance: u32,
+        /// Only cluster documents this principal may see, as with search (repeatable)
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+    },
+    /// List the keywords shared by the most documents
+    Keywords {
+        /// Number of keywords to list
+        #[arg(long, default_value = "20")]
+        top: usize,
+        /// Only count documents this principal may see, as with search (repeatable)
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+    },
+    /// Count the documents with each value of metadata fields
+    Facets {
+        /// Metadata fields to count
+        #[arg(required = true)]
+        fields: Vec<String>,
+        /// Only count documents this principal may see, as with search (repeatable)
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+    },
+    /// List the time partitions holding indexed documents, oldest first
+    Partitions,
+    /// List the collections defined in the config with their search defaults
+    Collections,
+    /// List documents added, updated or deleted after an index generation
+    Changes {
+        /// Generation to list changes after; 0 lists every change still in the log
+        #[arg(long, default_value = "0")]
+        since: u64,
+        /// Maximum number of changes to list
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Pin the current set of documents so paging through results isn't disturbed by indexing
+    Reader {
+        #[command(subcommand)]
+        command: ReaderCommand,
+    },
+    /// Manage result sets saved by `search --save-results` for searching within
+    Results {
+        #[command(subcommand)]
+        command: ResultsCommand,
+    },
+    /// Page through every match of a query in batches, for exporting complete result sets
+    Scroll {
+        #[command(subcommand)]
+        command: ScrollCommand,
+    },
+    /// Label documents with tags that searches can filter on
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+    /// Point alias names at collections, e.g. current -> docs-2024-06, switching them in one step
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommand,
+    },
+    /// Manage saved queries that raise alerts when new or changed documents match
+    Alert {
+        #[command(subcommand)]
+        command: AlertCommand,
     },
     /// Get search statistics
-    Stats,
+    Stats {
+        /// Include per-document term counts, sizes and query hits (reads every document)
+        #[arg(long)]
+        detailed: bool,
+    },
+    /// Export term frequency and document length histograms and the top terms of each directory,
+    /// for analyzing the corpus when tuning relevance (reads every postings table)
+    CorpusStats {
+        /// Number of top terms to list overall and for each directory
+        #[arg(long, default_value = "20")]
+        top: usize,
+        /// Write the statistics as CSV files into this directory instead of printing JSON
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Write the documents and a dictionary of their terms to one file, which the wasm build
+    /// loads and searches in the browser (documents with an ACL are left out)
+    Export {
+        /// File to write the index to
+        output: PathBuf,
+    },
     /// Get system status
     Status,
+    /// Export the opt-in usage statistics collected so far
+    Usage {
+        /// Clear the counters after exporting them
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Print index and query metrics in the Prometheus text format
+    Metrics,
+    /// Diagnose permissions, index consistency, encoding and configuration problems
+    Doctor,
+    /// Search and write concurrently in a scratch directory, checking that no document is lost,
+    /// every write is visible once it returns and the index generation only goes up
+    Stress {
+        /// How long to run, e.g. 30s or 5m
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        duration: chrono::Duration,
+        /// Tasks searching nonstop
+        #[arg(long, default_value = "4")]
+        searchers: usize,
+        /// Tasks indexing, updating and deleting documents
+        #[arg(long, default_value = "2")]
+        writers: usize,
+        /// Documents each writer cycles through
+        #[arg(long, default_value = "20")]
+        documents: usize,
+        /// Seed for the operations each task picks (defaults to the current time)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Keep the scratch directory instead of deleting it afterwards
+        #[arg(long)]
+        keep: bool,
+    },
     /// Run maintenance tasks
     Maintenance {
-        /// Maintenance task to run
-        task: String,
+        /// Report what a destructive task would remove without removing anything
+        #[arg(long, global = true)]
+        dry_run: bool,
+        /// Confirm a destructive task (clear-all, prune, expire, purge-trash, drop-partitions, archive-partitions)
+        #[arg(long, global = true)]
+        yes: bool,
+        /// Show progress on stderr (optimize, verify, backup)
+        #[arg(long, global = true)]
+        progress: bool,
+        #[command(subcommand)]
+        task: MaintenanceCommand,
+    },
+    /// Serve JSON-lines requests on stdin/stdout until EOF
+    Serve,
+    /// Ship indexed documents from a primary to read-only replicas over HTTP
+    Replication {
+        #[command(subcommand)]
+        command: ReplicationCommand,
+    },
+    /// Show version, git commit, index format and enabled features
+    Version,
+}
+
+// Time bounds on the documents one search looks at
+#[derive(Args)]
+struct DateArgs {
+    /// Only search time partitions ending after this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_parser = parse_date_time)]
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only search time partitions starting on or before this date
+    #[arg(long, value_parser = parse_date_time)]
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only search documents modified on or after this date; after:DATE in the query does the same
+    #[arg(long, value_parser = parse_date_time)]
+    modified_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only search documents modified before the end of this date, or before this timestamp;
+    /// before:DATE in the query does the same
+    #[arg(long, value_parser = parse_end_date_time)]
+    modified_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// How search results are ordered
+#[derive(Args)]
+struct SortArgs {
+    /// Result ordering [default: relevance, or the collection's]
+    #[arg(long, value_enum)]
+    sort: Option<SortOrder>,
+    /// Sort paths and titles in this locale's alphabet, e.g. de or sv [default: byte order, or the collection's]
+    #[arg(long)]
+    collation: Option<String>,
+}
+
+// Searching within the matches of an earlier search
+#[derive(Args)]
+struct ResultSetArgs {
+    /// Only search the lines saved in this result set by an earlier --save-results search
+    #[arg(long)]
+    within: Option<String>,
+    /// Save every match as a result set that lives this long unused, e.g. 5m, and report its ID as result_set
+    #[arg(long, value_parser = parse_duration)]
+    save_results: Option<chrono::Duration>,
+}
+
+// Caps on one search, on top of the "limits" in the config
+#[derive(Args)]
+struct LimitArgs {
+    /// Search at most N documents, returning partial results marked "limited"
+    #[arg(long)]
+    max_documents: Option<usize>,
+    /// Collect at most N matching lines, returning partial results marked "limited"
+    #[arg(long)]
+    max_matches: Option<usize>,
+    /// Expand each query term to at most N indexed terms in substring mode
+    #[arg(long)]
+    max_expansions: Option<usize>,
+    /// Reject regex queries that compile to more than N bytes
+    #[arg(long)]
+    max_regex_size: Option<usize>,
+}
+
+#[derive(Subcommand)]
+enum AliasCommand {
+    /// List aliases with the collection each points at
+    List,
+    /// Point an alias at a collection from the config, creating it or moving it
+    Set {
+        alias: String,
+        collection: String,
+    },
+    /// Remove an alias
+    Remove {
+        alias: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlertCommand {
+    /// List saved queries
+    List,
+    /// Save a query; every newly indexed or changed document it matches raises an alert
+    Add {
+        query: String,
+        /// How the query is matched against lines
+        #[arg(long, value_enum, default_value_t = MatchMode::Substring)]
+        mode: MatchMode,
+        /// Only check documents whose relative path starts with this prefix
+        #[arg(long)]
+        path_prefix: Option<String>,
+        /// Only check documents with this extension (repeatable)
+        #[arg(long = "extension")]
+        extensions: Vec<String>,
+        /// Also POST each alert as JSON to this http:// URL
+        #[arg(long)]
+        webhook: Option<String>,
+    },
+    /// Delete a saved query
+    Remove {
+        /// ID printed by `alert add` or `alert list`
+        id: String,
+    },
+    /// List the saved queries a document matches, without indexing it
+    Percolate {
+        /// Document to check, or - for stdin
+        file: PathBuf,
+        /// Path the document would have in the search directory, for saved queries with path filters
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Print alerts raised so far, oldest first
+    Events {
+        /// Only alerts from this recent window, e.g. 24h or 7d
+        #[arg(long, value_parser = parse_duration, conflicts_with = "after")]
+        since: Option<chrono::Duration>,
+        /// Only alerts raised after this RFC 3339 timestamp
+        #[arg(long)]
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        /// Show at most the N most recent alerts
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    /// Add tags to a document
+    Add {
+        /// Document path or ID
+        document: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from a document
+    Remove {
+        /// Document path or ID
+        document: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Print a document's tags
+    Show {
+        /// Document path or ID
+        document: String,
+    },
+    /// Count the documents carrying each tag
+    Counts,
+}
+
+#[derive(Subcommand)]
+enum ReaderCommand {
+    /// Open a reader and print its ID
+    Open {
+        /// How long the reader lives unused; every search with it starts the clock again
+        #[arg(long, value_parser = parse_duration, default_value = "5m")]
+        keep_alive: chrono::Duration,
+    },
+    /// Close a reader before it expires
+    Close {
+        /// ID printed by `reader open`
+        id: String,
+    },
+    /// List open readers, soonest to expire first
+    List,
+}
+
+#[derive(Subcommand)]
+enum ResultsCommand {
+    /// List saved result sets, soonest to expire first
+    List,
+    /// Delete a result set before it expires
+    Close {
+        /// ID reported as result_set by `search --save-results`
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScrollCommand {
+    /// Start a scroll and print its first batch with the ID to fetch the next one by
+    Open {
+        /// Search query
+        query: String,
+        /// Matches per batch, at most 10000
+        #[arg(long, default_value_t = DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+        /// How long the scroll lives between batches
+        #[arg(long, value_parser = parse_duration, default_value = "5m")]
+        keep_alive: chrono::Duration,
+        /// How the query is matched against lines
+        #[arg(long, value_enum, default_value_t = MatchMode::Substring)]
+        mode: MatchMode,
+        /// Match the query in the case it is written in
+        #[arg(short = 's', long)]
+        case_sensitive: bool,
+        /// Also match words up to N edits from each query word (at most 2)
+        #[arg(long)]
+        fuzzy: Option<usize>,
+        /// Only search documents whose relative path starts with this prefix
+        #[arg(long)]
+        path_prefix: Option<String>,
+        /// Only search documents with this extension (repeatable)
+        #[arg(long = "extension")]
+        extensions: Vec<String>,
+        /// Only search documents with this tag (repeatable); tag:NAME in the query does the same
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only search documents whose metadata field has this value, e.g. customer=acme (repeatable)
+        #[arg(long = "meta", value_parser = parse_field)]
+        metadata: Vec<(String, MetadataValue)>,
+        /// Search on behalf of this principal (repeatable); see `search --principal`
+        #[arg(long = "principal")]
+        principals: Vec<String>,
+        /// Include the byte ranges of each match in results
+        #[arg(long)]
+        highlight: bool,
+        /// Scroll through the documents pinned by this point-in-time reader rather than a new one
+        #[arg(long)]
+        reader: Option<String>,
+    },
+    /// Print the next batch of a scroll; the last is marked done
+    Next {
+        /// ID printed as scroll_id by `scroll open`
+        id: String,
+    },
+    /// Close a scroll before it is drained
+    Close {
+        /// ID printed as scroll_id by `scroll open`
+        id: String,
     },
 }
 
+#[derive(Subcommand)]
+enum ReplicationCommand {
+    /// Serve this search directory to replicas until interrupted
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = DEFAULT_REPLICATION_ADDRESS)]
+        listen: String,
+        /// Bearer token replicas must send
+        #[arg(long, env = "SEARCH_ENGINE_REPLICATION_TOKEN")]
+        token: String,
+    },
+    /// Download changes from the primary named by replica_of in the config
+    Sync,
+    /// Print the manifest this search directory would serve to replicas
+    Manifest,
+}
+
+#[derive(Subcommand)]
+enum MaintenanceCommand {
+    /// Rescan the search directory and refresh the file cache
+    #[command(alias = "cleanup", alias = "update-stats")]
+    Refresh,
+    /// Remove every indexed document from the search directory
+    ClearAll,
+    /// Rebuild stale or missing line offset and postings tables
+    Optimize,
+    /// Build a new index from the documents on disk and swap it in, keeping searches running
+    Rebuild,
+    /// Check the index against the documents on disk
+    Verify,
+    /// Copy all documents and index metadata into a timestamped directory
+    Backup {
+        /// Directory to create the backup in
+        destination: PathBuf,
+    },
+    /// Remove documents last modified more than N days ago
+    Prune {
+        /// Age threshold in days
+        #[arg(long)]
+        older_than_days: u64,
+    },
+    /// Delete orphaned index files and rewrite index metadata
+    Compact,
+    /// Remove documents whose TTL has elapsed
+    Expire,
+    /// Permanently delete trashed documents past the retention period
+    PurgeTrash {
+        /// Purge every trashed document regardless of age
+        #[arg(long)]
+        all: bool,
+    },
+    /// Permanently remove time partitions that ended more than N days ago
+    DropPartitions {
+        /// Age threshold in days
+        #[arg(long)]
+        older_than_days: u64,
+    },
+    /// Move time partitions that ended more than N days ago out of the search directory
+    ArchivePartitions {
+        /// Directory to move the partitions into
+        destination: PathBuf,
+        /// Age threshold in days
+        #[arg(long)]
+        older_than_days: u64,
+    },
+    /// Download changes from the primary (replicas only)
+    Replicate,
+}
+
+impl From<MaintenanceCommand> for MaintenanceTask {
+    fn from(command: MaintenanceCommand) -> Self {
+        match command {
+            MaintenanceCommand::Refresh => MaintenanceTask::Refresh,
+            MaintenanceCommand::ClearAll => MaintenanceTask::ClearAll,
+            MaintenanceCommand::Optimize => MaintenanceTask::Optimize,
+            MaintenanceCommand::Verify => MaintenanceTask::Verify,
+            MaintenanceCommand::Backup { destination } => MaintenanceTask::Backup { destination },
+            MaintenanceCommand::Prune { older_than_days } => MaintenanceTask::Prune { older_than_days },
+            MaintenanceCommand::Compact => MaintenanceTask::Compact,
+            MaintenanceCommand::Rebuild => MaintenanceTask::Rebuild,
+            MaintenanceCommand::Expire => MaintenanceTask::Expire,
+            MaintenanceCommand::PurgeTrash { all } => MaintenanceTask::PurgeTrash { all },
+            MaintenanceCommand::DropPartitions { older_than_days } => MaintenanceTask::DropPartitions { older_than_days },
+            MaintenanceCommand::ArchivePartitions { destination, older_than_days } => {
+                MaintenanceTask::ArchivePartitions { older_than_days, destination }
+            }
+            MaintenanceCommand::Replicate => MaintenanceTask::Replicate,
+        }
+    }
+}
+
+// The `--principal` flags as a filter; none only sees documents without an access control list
+fn principals_filter(principals: &[String]) -> Option<&[String]> {
+    (!principals.is_empty()).then_some(principals)
+}
+
+// Serializes straight into a buffered stdout instead of building the whole document as a String first
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    serde_json::to_writer_pretty(&mut stdout, &Versioned::new(value))?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Redraws a single status line on stderr, e.g. "optimize 12/40 notes/a.txt"
+fn progress_reporter(enabled: bool) -> Box<dyn ProgressReporter> {
+    if !enabled {
+        return Box::new(NoProgress);
+    }
+    Box::new(|progress: &Progress| {
+        let total = progress.total.map(|total| format!("/{}", total)).unwrap_or_default();
+        let item = progress.item.as_deref().unwrap_or_default();
+        eprint!("\r\x1b[2K{} {}{} {}", progress.operation, progress.completed, total, item);
+    })
+}
+
+fn finish_progress(enabled: bool) {
+    if enabled {
+        eprintln!();
+    }
+}
+
+async fn open_engine(search_dir: &Path, config: &Config, actor: &str) -> Result<SearchEngine> {
+    let mut builder = SearchEngine::builder(search_dir)
+        .extensions(config.extensions.iter().cloned())
+        .quota(config.quota)
+        .query_log(config.query_log)
+        .slow_query_threshold(config.slow_query_threshold.as_deref().map(parse_duration).transpose()?)
+        .usage_stats(config.usage_stats)
+        .default_ttl(config.document_ttl.as_deref().map(parse_duration).transpose()?)
+        .trash_retention(config.trash_retention.as_deref().map(parse_duration).transpose()?)
+        .actor(actor)
+        .id_strategy(config.id_strategy)
+        .partitioning(config.partitioning)
+        .replica_of(config.replica_of.clone())
+        .read_only(config.read_only)
+        .bm25(config.bm25)
+        .fold_accents(config.fold_accents)
+        .stemming(config.stemming)
+        .stem_language(config.stem_language)
+        .ngrams(config.ngrams)
+        .stopwords(config.stopwords.clone())
+        .analysis_rules(config.analysis.clone())
+        .synonyms(config.synonyms.clone())
+        .pins(config.pins.clone())
+        .limits(config.limits)
+        .collections(config.collections.clone())
+        .roots(config.roots.clone())
+        .parallelism(config.parallelism)
+        .postings_cache(config.postings_cache);
+    if let Some(embedder) = &config.embedder {
+        builder = builder.embedder(embedder.build()?);
+    }
+    Ok(builder.build().await?)
+}
+
+// Exit statuses scripts can branch on; argument errors exit with 2 from clap as well
+const EXIT_NO_RESULTS: u8 = 1;
+const EXIT_QUERY_ERROR: u8 = 2;
+const EXIT_ENGINE_ERROR: u8 = 3;
+
+enum Outcome {
+    Done,
+    NoResults,
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
-    
+async fn main() -> ExitCode {
     let cli = Cli::parse();
-    let search_dir = std::env::var("SEARCH_DIRECTORY").unwrap_or_else(|_| "index".to_string());
-    let mut engine = SearchEngine::new(&search_dir).await?;
+    let json = cli.json;
+    match run(cli).await {
+        Ok(Outcome::Done) => ExitCode::SUCCESS,
+        Ok(Outcome::NoResults) => ExitCode::from(EXIT_NO_RESULTS),
+        Err(e) => {
+            let engine_error = e.downcast_ref::<SearchEngineError>();
+            let query_error = matches!(
+                engine_error,
+                Some(
+                    SearchEngineError::InvalidQuery(_) | SearchEngineError::QuerySyntax(_) | SearchEngineError::EmptyQuery(_)
+                    | SearchEngineError::InvalidInput(_)
+                )
+            );
+            let status = if query_error { EXIT_QUERY_ERROR } else { EXIT_ENGINE_ERROR };
+            if json {
+                let mut error = serde_json::json!({
+                    "schema_version": SCHEMA_VERSION,
+                    "error": {
+                        "code": engine_error.map(SearchEngineError::code).unwrap_or("COMMAND_FAILED"),
+                        "message": e.to_string(),
+                        "details": {
+                            "causes": e.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>(),
+                            "exit_code": status,
+                        },
+                    }
+                });
+                if let Some(SearchEngineError::QuerySyntax(syntax)) = engine_error {
+                    error["error"]["details"]["syntax"] = serde_json::json!(syntax);
+                }
+                eprintln!("{}", error);
+            } else {
+                eprintln!("Error: {:?}", e);
+            }
+            ExitCode::from(status)
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<Outcome> {
+    // Needs no config or search directory
+    if let Commands::Version = cli.command {
+        let info = version_info();
+        if cli.json {
+            print_json(&info)?;
+        } else {
+            println!("search-engine {} ({}, index format {})", info.version, info.git_hash, info.index_format_version);
+        }
+        return Ok(Outcome::Done);
+    }
+    let mut config = Config::discover(cli.config.as_deref(), cli.profile.as_deref()).await?;
+    if let Some(format) = cli.log_format {
+        config.logging.format = format;
+    }
+    config.read_only |= cli.read_only;
+    // Quiet mode silences diagnostics on stderr but still writes a configured log file
+    if !cli.quiet || config.logging.file.is_some() {
+        logging::init(&config.logging)?;
+    }
+    let progress_enabled = |progress: bool| progress && !cli.quiet;
+    // --dir, then SEARCH_DIRECTORY, then the config file, then ./index
+    let search_dir = cli.dir
+        .or_else(|| config.search_directory.clone())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SEARCH_DIRECTORY));
+    let engine = match open_engine(&search_dir, &config, &cli.actor).await {
+        Ok(engine) => engine,
+        // Doctor reports configuration problems instead of failing on them
+        Err(e) if matches!(cli.command, Commands::Doctor) => {
+            warn!(error = %e, "Checking with default settings");
+            SearchEngine::builder(&search_dir).actor(cli.actor.as_str()).build().await?
+        }
+        Err(e) => return Err(e),
+    };
 
+    let mut outcome = Outcome::Done;
     match cli.command {
-        Commands::Search { query, limit, offset } => {
+        Commands::Search {
+            query, limit, offset, ordering, mode, case_sensitive, collection, collections, path_prefix, extensions, tags, languages, dates, highlight, timeout, profile,
+            collapse_duplicates, clusters, summarize, reader, result_sets, max_per_file, diversify, boosts, metadata,
+            principals, facets, fuzzy, limits,
+        } => {
             info!("Searching for: {}", query);
-            let results = engine.search(&query, limit, offset).await?;
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            let LimitArgs { max_documents, max_matches, max_expansions, max_regex_size } = *limits;
+            let DateArgs { from, to, modified_after, modified_before } = *dates;
+            let ResultSetArgs { within, save_results } = *result_sets;
+            let SortArgs { sort, collation } = *ordering;
+            let metadata: Metadata = metadata.into_iter().collect();
+            // The options given here on top of a collection's defaults
+            let apply = |mut options: SearchOptions| {
+                options.limit = limit;
+                options.offset = offset;
+                options.sort = sort.unwrap_or(options.sort);
+                options.collation = collation.clone().or(options.collation);
+                options.mode = mode.unwrap_or(options.mode);
+                options.case_sensitive = case_sensitive;
+                options.filters = SearchFilters {
+                    path_prefix: path_prefix.clone().or(options.filters.path_prefix),
+                    extensions: extensions.clone(),
+                    from,
+                    to,
+                    tags: tags.clone(),
+                    metadata: metadata.clone(),
+                    languages: languages.clone(),
+                    modified_after,
+                    modified_before,
+                    principals: (!principals.is_empty()).then(|| principals.clone()),
+                    ..SearchFilters::default()
+                };
+                options.highlight = highlight;
+                options.timeout = timeout;
+                options.profile = profile;
+                options.collapse_duplicates = collapse_duplicates;
+                options.clusters = clusters;
+                options.summarize = summarize;
+                options.reader = reader.clone();
+                options.within = within.clone();
+                options.save_results = save_results;
+                options.max_per_file = max_per_file.or(options.max_per_file);
+                options.diversify |= diversify;
+                options.boosts.extend(boosts.iter().cloned());
+                options.facets = facets.clone();
+                options.fuzzy = fuzzy;
+                options.limits = SearchLimits { max_documents, max_matches, max_expansions, max_regex_size };
+                options
+            };
+            let results = if collections.is_empty() {
+                let options = match &collection {
+                    Some(name) => engine.resolve_collection(name).await?.options(),
+                    None => SearchOptions::default(),
+                };
+                engine.search(&query, &apply(options)).await?
+            } else {
+                let mut searches = Vec::with_capacity(collections.len());
+                for name in &collections {
+                    searches.push((name.clone(), apply(engine.resolve_collection(name).await?.options())));
+                }
+                engine.search_collections(&query, &searches).await?
+            };
+            print_json(&results)?;
+            if results.total == 0 {
+                outcome = Outcome::NoResults;
+            }
+        }
+        Commands::Semantic { query, limit, offset, path_prefix, extensions, tags, languages, metadata, principals, from, to } => {
+            info!("Semantic search for: {}", query);
+            let options = SearchOptions {
+                limit,
+                offset,
+                filters: SearchFilters {
+                    path_prefix,
+                    extensions,
+                    from,
+                    to,
+                    tags,
+                    metadata: metadata.into_iter().collect(),
+                    languages,
+                    principals: (!principals.is_empty()).then_some(principals),
+                    ..SearchFilters::default()
+                },
+                ..SearchOptions::default()
+            };
+            let results = engine.semantic_search(&query, &options).await?;
+            print_json(&results)?;
+            if results.total == 0 {
+                outcome = Outcome::NoResults;
+            }
+        }
+        Commands::Interactive { mode, path_prefix, extensions } => {
+            let options = SearchOptions {
+                filters: SearchFilters { path_prefix, extensions, ..SearchFilters::default() },
+                mode,
+                ..SearchOptions::default()
+            };
+            search_engine::interactive::run(&engine, options).await?;
+        }
+        Commands::Index { path, ttl, progress, id, metadata, acl } => {
+            let progress = progress_enabled(progress);
+            let metadata: Option<Metadata> = (!metadata.is_empty()).then(|| metadata.into_iter().collect());
+            if Path::new(&path).is_dir() {
+                if id.is_some() {
+                    return Err(SearchEngineError::InvalidInput("--id can only be given when indexing a single document".to_string()).into());
+                }
+                info!("Indexing directory: {}", path);
+                let mut source = DirectorySource::new(&path).extensions(engine.accepted_extensions()).ttl(ttl);
+                if let Some(metadata) = metadata {
+                    source = source.metadata(metadata);
+                }
+                if !acl.is_empty() {
+                    source = source.acl(acl);
+                }
+                let report = engine.index_source_with_progress(source, 1, progress_reporter(progress).as_ref()).await;
+                finish_progress(progress);
+                print_json(&report?)?;
+            } else {
+                info!("Indexing document: {}", path);
+                let document = match (id, metadata) {
+                    (id, metadata) if !acl.is_empty() => engine.index_document_with_acl(&path, id.as_deref(), ttl, metadata, acl).await?,
+                    (id, Some(metadata)) => engine.index_document_with_metadata(&path, id.as_deref(), ttl, metadata).await?,
+                    (Some(id), None) => engine.index_document_with_id(&path, &id, ttl).await?,
+                    (None, None) => engine.index_document_with_ttl(&path, ttl).await?,
+                };
+                print_json(&document)?;
+            }
+        }
+        Commands::IndexJson { path, id_field, title_field, content_fields, metadata_fields, ttl, acl, progress } => {
+            let progress = progress_enabled(progress);
+            let mapping = JsonMapping { id: id_field, title: title_field, content: content_fields, metadata: metadata_fields };
+            info!("Indexing JSON records: {}", path);
+            let mut source = JsonSource::open(&path, mapping).await?.ttl(ttl);
+            if !acl.is_empty() {
+                source = source.acl(acl);
+            }
+            let report = engine.index_source_with_progress(source, RECORD_BATCH_SIZE, progress_reporter(progress).as_ref()).await;
+            finish_progress(progress);
+            print_json(&report?)?;
+        }
+        Commands::Delete { path } => {
+            info!("Deleting document: {}", path);
+            if !engine.delete_document(&path).await? {
+                return Err(SearchEngineError::DocumentNotFound(path).into());
+            }
+            print_json(&serde_json::json!({ "deleted": path }))?;
+        }
+        Commands::Trash => {
+            print_json(&engine.trash_entries().await?)?;
+        }
+        Commands::Restore { path } => {
+            info!("Restoring document: {}", path);
+            let document = engine.restore_document(&path).await?;
+            print_json(&document)?;
+        }
+        Commands::Get { path, line, principals } => {
+            match engine.get_line(&path, line, principals_filter(&principals)).await? {
+                Some(result) => print_json(&result)?,
+                None => return Err(SearchEngineError::DocumentNotFound(format!("line {} of {}", line, path)).into()),
+            }
+        }
+        Commands::Lines { path, start, end, principals } => {
+            match engine.get_lines(&path, start, end, principals_filter(&principals)).await? {
+                Some(range) => print_json(&range)?,
+                None => return Err(SearchEngineError::DocumentNotFound(format!("line {} of {}", start, path)).into()),
+            }
+        }
+        Commands::Analytics { top, since } => {
+            let since = since.map(|window| chrono::Utc::now() - window);
+            let analytics = engine.query_analytics(top, since).await?;
+            print_json(&analytics)?;
         }
-        Commands::Stats => {
-            let stats = engine.get_stats().await?;
-            println!("{}", serde_json::to_string_pretty(&stats)?);
+        Commands::Suggest { prefix, limit, from_history, since, principals } => {
+            let suggestions = if from_history {
+                let since = since.map(|window| chrono::Utc::now() - window);
+                engine.suggest_from_history(&prefix, limit, since).await?
+            } else {
+                engine.suggest(&prefix, limit, principals_filter(&principals)).await?
+            };
+            print_json(&suggestions)?;
+        }
+        Commands::SlowQueries { since, limit } => {
+            let since = since.map(|window| chrono::Utc::now() - window);
+            print_json(&engine.slow_queries(since, limit).await?)?;
+        }
+        Commands::Audit { since, actor, operation, limit } => {
+            let filter = AuditFilter {
+                since: since.map(|window| chrono::Utc::now() - window),
+                actor,
+                operation,
+                limit,
+            };
+            print_json(&engine.audit_log(&filter).await?)?;
+        }
+        Commands::Duplicates { max_distance, principals } => {
+            print_json(&engine.near_duplicates(max_distance, principals_filter(&principals)).await?)?;
+        }
+        Commands::Keywords { top, principals } => {
+            print_json(&engine.keyword_counts(top, principals_filter(&principals)).await?)?;
+        }
+        Commands::Facets { fields, principals } => {
+            print_json(&engine.facet_counts(&fields, principals_filter(&principals)).await?)?;
+        }
+        Commands::Partitions => {
+            print_json(&engine.partitions().await?)?;
+        }
+        Commands::Collections => {
+            print_json(engine.collections())?;
+        }
+        Commands::Changes { since, limit } => {
+            print_json(&engine.changes_since(since, limit).await?)?;
+        }
+        Commands::Tag { command } => match command {
+            TagCommand::Add { document, tags } => print_json(&engine.add_tags(&document, &tags).await?)?,
+            TagCommand::Remove { document, tags } => print_json(&engine.remove_tags(&document, &tags).await?)?,
+            TagCommand::Show { document } => print_json(&engine.document_tags(&document).await?)?,
+            TagCommand::Counts => print_json(&engine.tag_counts().await?)?,
+        },
+        Commands::Reader { command } => match command {
+            ReaderCommand::Open { keep_alive } => print_json(&engine.open_reader(Some(keep_alive)).await?)?,
+            ReaderCommand::Close { id } => {
+                if !engine.close_reader(&id).await? {
+                    bail!("No open reader with ID {}", id);
+                }
+                print_json(&serde_json::json!({ "closed": id }))?;
+            }
+            ReaderCommand::List => print_json(&engine.readers().await?)?,
+        },
+        Commands::Results { command } => match command {
+            ResultsCommand::List => print_json(&engine.result_sets().await?)?,
+            ResultsCommand::Close { id } => {
+                if !engine.close_result_set(&id).await? {
+                    bail!("No saved result set with ID {}", id);
+                }
+                print_json(&serde_json::json!({ "closed": id }))?;
+            }
+        },
+        Commands::Scroll { command } => match command {
+            ScrollCommand::Open {
+                query, batch_size, keep_alive, mode, case_sensitive, fuzzy, path_prefix, extensions, tags, metadata, principals, highlight, reader,
+            } => {
+                let options = SearchOptions {
+                    mode,
+                    case_sensitive,
+                    fuzzy,
+                    filters: SearchFilters {
+                        path_prefix,
+                        extensions,
+                        tags,
+                        metadata: metadata.into_iter().collect(),
+                        principals: (!principals.is_empty()).then_some(principals),
+                        ..SearchFilters::default()
+                    },
+                    highlight,
+                    reader,
+                    ..SearchOptions::default()
+                };
+                let page = engine.open_scroll(&query, &options, batch_size, Some(keep_alive)).await?;
+                print_json(&page)?;
+                if page.returned == 0 {
+                    outcome = Outcome::NoResults;
+                }
+            }
+            ScrollCommand::Next { id } => print_json(&engine.scroll(&id).await?)?,
+            ScrollCommand::Close { id } => {
+                if !engine.close_scroll(&id).await? {
+                    bail!("No open scroll with ID {}", id);
+                }
+                print_json(&serde_json::json!({ "closed": id }))?;
+            }
+        },
+        Commands::Alias { command } => match command {
+            AliasCommand::List => print_json(&engine.aliases().await?)?,
+            AliasCommand::Set { alias, collection } => print_json(&engine.set_alias(&alias, &collection).await?)?,
+            AliasCommand::Remove { alias } => {
+                if !engine.remove_alias(&alias).await? {
+                    bail!("No collection alias named {}", alias);
+                }
+                print_json(&serde_json::json!({ "removed": alias }))?;
+            }
+        },
+        Commands::Alert { command } => match command {
+            AlertCommand::List => print_json(&engine.alerts().await?)?,
+            AlertCommand::Add { query, mode, path_prefix, extensions, webhook } => {
+                let filters = SearchFilters { path_prefix, extensions, ..SearchFilters::default() };
+                print_json(&engine.add_alert(&query, mode, filters, webhook).await?)?;
+            }
+            AlertCommand::Remove { id } => {
+                if !engine.remove_alert(&id).await? {
+                    bail!("No saved alert with ID {}", id);
+                }
+                print_json(&serde_json::json!({ "removed": id }))?;
+            }
+            AlertCommand::Percolate { file, path } => {
+                let text = if file.as_os_str() == "-" {
+                    let mut text = String::new();
+                    tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut text).await?;
+                    text
+                } else {
+                    tokio::fs::read_to_string(&file).await
+                        .with_context(|| format!("Failed to read {}", file.display()))?
+                };
+                let matches = engine.percolate(&text, path.as_deref()).await?;
+                print_json(&matches)?;
+                if matches.is_empty() {
+                    outcome = Outcome::NoResults;
+                }
+            }
+            AlertCommand::Events { since, after, limit } => {
+                let since = after.or(since.map(|window| chrono::Utc::now() - window));
+                print_json(&engine.alert_events(since, limit).await?)?;
+            }
+        },
+        Commands::Stats { detailed } => {
+            let stats = if detailed {
+                engine.get_detailed_stats().await?
+            } else {
+                engine.get_stats().await?
+            };
+            print_json(&stats)?;
+        }
+        Commands::CorpusStats { top, csv } => {
+            let report = engine.corpus_report(top).await?;
+            match csv {
+                Some(directory) => {
+                    std::fs::create_dir_all(&directory).with_context(|| format!("Failed to create {:?}", directory))?;
+                    let mut written = Vec::new();
+                    for (name, table) in report.csv_tables() {
+                        let path = directory.join(name);
+                        std::fs::write(&path, table).with_context(|| format!("Failed to write {:?}", path))?;
+                        written.push(path);
+                    }
+                    print_json(&written)?;
+                }
+                None => print_json(&report)?,
+            }
+        }
+        Commands::Export { output } => {
+            let index = engine.export_index().await?;
+            std::fs::write(&output, index.to_bytes()?).with_context(|| format!("Failed to write {:?}", output))?;
+            print_json(&serde_json::json!({
+                "path": output,
+                "documents": index.documents.len(),
+                "terms": index.dictionary.term_count(),
+            }))?;
         }
         Commands::Status => {
             let status = engine.get_status().await?;
-            println!("{}", serde_json::to_string_pretty(&status)?);
+            print_json(&status)?;
+        }
+        Commands::Usage { reset } => {
+            match engine.export_usage_stats(reset).await? {
+                Some(stats) => print_json(&stats)?,
+                None if config.usage_stats => print_json(&serde_json::json!({ "message": "No usage recorded yet" }))?,
+                None => bail!("Usage statistics are disabled; set \"usage_stats\": true in the config file to opt in"),
+            }
+        }
+        Commands::Metrics => {
+            print!("{}", engine.prometheus_metrics().await?);
+        }
+        Commands::Doctor => {
+            let report = engine.doctor(&config).await?;
+            print_json(&report)?;
+            if !report.healthy {
+                bail!("Doctor found errors; see the findings above");
+            }
         }
-        Commands::Maintenance { task } => {
-            info!("Running maintenance task: {}", task);
-            let result = engine.run_maintenance(&task).await?;
-            println!("{}", serde_json::to_string_pretty(&result)?);
+        Commands::Stress { duration, searchers, writers, documents, seed, keep } => {
+            let scratch = std::env::temp_dir().join(format!("search-engine-stress-{}", std::process::id()));
+            // The config's analysis and limits apply, but nothing that points elsewhere
+            let mut scratch_config = config.clone();
+            scratch_config.roots.clear();
+            scratch_config.collections.clear();
+            scratch_config.replica_of = None;
+            scratch_config.read_only = false;
+            let options = StressOptions {
+                duration: duration.to_std().context("Invalid --duration")?,
+                searchers,
+                writers,
+                documents,
+                seed: seed.unwrap_or_else(|| chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64),
+            };
+            info!("Stress testing in {}", scratch.display());
+            let report: Result<_> = async { Ok(open_engine(&scratch, &scratch_config, &cli.actor).await?.stress(&options).await?) }.await;
+            if !keep {
+                if let Err(e) = tokio::fs::remove_dir_all(&scratch).await {
+                    warn!(error = %e, "Failed to remove {}", scratch.display());
+                }
+            }
+            let report = report?;
+            print_json(&report)?;
+            if !report.passed {
+                bail!("Stress test found violations; see the report above");
+            }
         }
+        Commands::Maintenance { dry_run, yes, progress, task } => {
+            let progress = progress_enabled(progress);
+            let task = MaintenanceTask::from(task);
+            if dry_run {
+                print_json(&engine.preview_maintenance(&task).await?)?;
+                return Ok(Outcome::Done);
+            }
+            if task.is_destructive() && !yes && !config.allow_destructive_maintenance {
+                bail!(
+                    "Refusing to run destructive task '{}' without --yes; use --dry-run to preview it",
+                    task.name()
+                );
+            }
+
+            info!("Running maintenance task: {}", task.name());
+            let result = engine.run_maintenance_with_progress(&task, progress_reporter(progress).as_ref()).await;
+            finish_progress(progress);
+            print_json(&result?)?;
+        }
+        Commands::Version => unreachable!("handled before the engine is opened"),
+        Commands::Serve => {
+            info!("Serving requests on stdin/stdout");
+            let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+            let scheduler = Scheduler::new(&config.schedule)?;
+            let scope = PathScope::new(&search_dir, &config.allowed_roots).await?;
+            let _watcher = config.watch_dictionaries.then(|| engine.watch_dictionaries()).transpose()?;
+            search_engine::server::serve(&engine, scheduler, config.allow_destructive_maintenance, &scope, stdin, tokio::io::stdout()).await?;
+        }
+        Commands::Replication { command } => match command {
+            ReplicationCommand::Serve { listen, token } => engine.serve_replication(&listen, &token).await?,
+            ReplicationCommand::Sync => {
+                let report = engine.sync_replica().await?;
+                print_json(&report)?;
+                if !report.failed.is_empty() {
+                    bail!("{} documents failed to replicate", report.failed.len());
+                }
+            }
+            ReplicationCommand::Manifest => print_json(&engine.replication_manifest().await?)?,
+        },
     }
 
-    Ok(())
+    Ok(outcome)
 }
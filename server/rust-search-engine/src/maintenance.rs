@@ -0,0 +1,580 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+use tracing::{instrument, warn};
+use walkdir::WalkDir;
+
+use crate::catalog::Catalog;
+use crate::checksum;
+use crate::dedup::simhash;
+use crate::error::{Context, Result};
+use crate::keywords::{extract_keywords, MAX_KEYWORDS};
+use crate::language::detect_language;
+use crate::partition::{partition_of, partition_span};
+use crate::progress::{NoProgress, ProgressReporter, Tracker};
+use crate::SearchEngine;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "task", rename_all = "kebab-case")]
+pub enum MaintenanceTask {
+    // Rescan the search directory and rebuild stale line offset and postings tables
+    #[serde(alias = "cleanup", alias = "update-stats")]
+    Refresh,
+    // Remove every indexed document from the search directory
+    ClearAll,
+    // Bring every derived artifact up to date with the documents on disk
+    Optimize,
+    // Build a new index from the documents on disk and swap it in for the current one
+    Rebuild,
+    // Check the catalog and derived artifacts against the documents on disk and their checksums
+    Verify,
+    // Copy all documents and index metadata into a timestamped directory
+    Backup { destination: PathBuf },
+    // Remove documents last modified more than `older_than_days` days ago
+    Prune { older_than_days: u64 },
+    // Delete orphaned offset, vector and postings tables and leftover temp files, and rewrite metadata
+    Compact,
+    // Remove documents whose TTL has elapsed
+    Expire,
+    // Permanently delete trashed documents past the retention period, or all of them
+    PurgeTrash {
+        #[serde(default)]
+        all: bool,
+    },
+    // Permanently remove the time partitions that ended more than `older_than_days` days ago
+    DropPartitions { older_than_days: u64 },
+    // Move those partitions out of the search directory into `destination`
+    ArchivePartitions { older_than_days: u64, destination: PathBuf },
+    // Download changes from the primary; replicas only
+    Replicate,
+}
+
+impl MaintenanceTask {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceTask::Refresh => "refresh",
+            MaintenanceTask::ClearAll => "clear-all",
+            MaintenanceTask::Optimize => "optimize",
+            MaintenanceTask::Rebuild => "rebuild",
+            MaintenanceTask::Verify => "verify",
+            MaintenanceTask::Backup { .. } => "backup",
+            MaintenanceTask::Prune { .. } => "prune",
+            MaintenanceTask::Compact => "compact",
+            MaintenanceTask::Expire => "expire",
+            MaintenanceTask::PurgeTrash { .. } => "purge-trash",
+            MaintenanceTask::DropPartitions { .. } => "drop-partitions",
+            MaintenanceTask::ArchivePartitions { .. } => "archive-partitions",
+            MaintenanceTask::Replicate => "replicate",
+        }
+    }
+
+    // False for the tasks that only read the index: refresh, verify and backup
+    pub fn writes_index(&self) -> bool {
+        !matches!(self, MaintenanceTask::Refresh | MaintenanceTask::Verify | MaintenanceTask::Backup { .. })
+    }
+
+    // The directory the task writes to outside the search directory, for backups and archives
+    pub fn destination(&self) -> Option<&Path> {
+        match self {
+            MaintenanceTask::Backup { destination } | MaintenanceTask::ArchivePartitions { destination, .. } => Some(destination),
+            _ => None,
+        }
+    }
+
+    // Tasks that delete documents, which callers should preview or confirm first
+    pub fn is_destructive(&self) -> bool {
+        matches!(self, MaintenanceTask::ClearAll
+                | MaintenanceTask::Prune { .. }
+                | MaintenanceTask::Expire
+                | MaintenanceTask::PurgeTrash { .. }
+                | MaintenanceTask::DropPartitions { .. }
+                | MaintenanceTask::ArchivePartitions { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MaintenanceResult {
+    pub task: String,
+    pub success: bool,
+    pub message: String,
+    pub executed_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    // Documents removed by the task (or that would be, for a dry run)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub affected: Vec<String>,
+}
+
+impl MaintenanceResult {
+    fn new(task: &MaintenanceTask, success: bool, message: String) -> Self {
+        MaintenanceResult {
+            task: task.name().to_string(),
+            success,
+            message,
+            executed_at: Utc::now(),
+            dry_run: false,
+            affected: Vec::new(),
+        }
+    }
+}
+
+impl SearchEngine {
+    pub async fn run_maintenance(&self, task: &MaintenanceTask) -> Result<MaintenanceResult> {
+        self.run_maintenance_with_progress(task, &NoProgress).await
+    }
+
+    // Like `run_maintenance`, reporting per-document progress for optimize, rebuild, verify and backup
+    #[instrument(skip(self, task, progress), fields(task = task.name()))]
+    pub async fn run_maintenance_with_progress(
+        &self,
+        task: &MaintenanceTask,
+        progress: &dyn ProgressReporter,
+    ) -> Result<MaintenanceResult> {
+        // Replicas mirror their primary, so only tasks that leave the documents alone may run;
+        // a read-only engine runs only those that write nothing under the search directory
+        if task.is_destructive() || (self.read_only && task.writes_index()) {
+            self.ensure_writable()?;
+        }
+        let _write = self.lock_writes().await?;
+        // Documents present before but not after the task are the ones it removed
+        let before: HashSet<PathBuf> = self.catalog().await?.documents.iter()
+            .map(|doc| doc.path.clone())
+            .collect();
+
+        let result = self.run_maintenance_task(task, progress).await;
+
+        let mut removed: Vec<String> = match self.catalog().await {
+            Ok(catalog) => {
+                let after: HashSet<&PathBuf> = catalog.documents.iter().map(|doc| &doc.path).collect();
+                before.iter()
+                    .filter(|path| !after.contains(path))
+                    .map(|path| path.to_string_lossy().to_string())
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        // Tasks that remove files outside the catalog (such as the trash) report them directly
+        if let Ok(result) = &result {
+            removed.extend(result.affected.iter().cloned());
+        }
+        removed.sort();
+
+        let outcome = match &result {
+            Ok(result) if result.success => Ok(()),
+            Ok(result) => Err(result.message.clone()),
+            Err(e) => Err(e.to_string()),
+        };
+        self.audit(&format!("maintenance:{}", task.name()), removed.clone(), outcome).await;
+        self.record_usage(crate::usage::UsageEvent::MaintenanceRun).await;
+        result.map(|result| MaintenanceResult { affected: removed, ..result })
+    }
+
+    // Reports exactly which documents a task would remove, without changing anything
+    pub async fn preview_maintenance(&self, task: &MaintenanceTask) -> Result<MaintenanceResult> {
+        let mut affected: Vec<String> = self.removal_candidates(task).await?
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        affected.sort();
+
+        let message = if task.is_destructive() {
+            format!("Would remove {} documents", affected.len())
+        } else {
+            "Task does not remove documents".to_string()
+        };
+        Ok(MaintenanceResult {
+            dry_run: true,
+            affected,
+            ..MaintenanceResult::new(task, true, message)
+        })
+    }
+
+    async fn removal_candidates(&self, task: &MaintenanceTask) -> Result<Vec<PathBuf>> {
+        let catalog = self.catalog().await?;
+        // Documents in search roots are never removed
+        let owned = || catalog.documents.iter().filter(|doc| self.root_of(&doc.path).is_none());
+        match task {
+            MaintenanceTask::ClearAll => Ok(owned().map(|doc| doc.path.clone()).collect()),
+            MaintenanceTask::Prune { older_than_days } => {
+                let cutoff = Utc::now() - Duration::days(*older_than_days as i64);
+                Ok(owned()
+                    .filter(|doc| doc.modified < cutoff)
+                    .map(|doc| doc.path.clone())
+                    .collect())
+            }
+            MaintenanceTask::Expire => self.expired_documents(Utc::now()).await,
+            MaintenanceTask::PurgeTrash { all } => {
+                let entries = self.purgeable_trash(*all, Utc::now()).await?;
+                Ok(self.trash_paths(&entries))
+            }
+            MaintenanceTask::DropPartitions { older_than_days }
+            | MaintenanceTask::ArchivePartitions { older_than_days, .. } => {
+                let cutoff = Utc::now() - Duration::days(*older_than_days as i64);
+                Ok(owned()
+                    .filter(|doc| {
+                        partition_of(&self.relative_path(&doc.path))
+                            .and_then(partition_span)
+                            .map(|(_, end)| end <= cutoff)
+                            .unwrap_or(false)
+                    })
+                    .map(|doc| doc.path.clone())
+                    .collect())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    async fn run_maintenance_task(&self, task: &MaintenanceTask, progress: &dyn ProgressReporter) -> Result<MaintenanceResult> {
+        match task {
+            MaintenanceTask::Refresh => {
+                self.refresh_file_cache().await?;
+                Ok(MaintenanceResult::new(task, true, "File cache refreshed successfully".to_string()))
+            }
+            MaintenanceTask::ClearAll => {
+                // Remove all indexed files from search directory, into the trash if enabled
+                let mut files_removed = 0;
+                let files_to_remove = self.removal_candidates(task).await?;
+
+                for file_path in &files_to_remove {
+                    match self.discard_document(file_path).await {
+                        Ok(_) => files_removed += 1,
+                        Err(e) => warn!(path = ?file_path, error = %e, "Failed to remove file"),
+                    }
+                }
+
+                self.refresh_file_cache().await?;
+
+                let message = if self.trash_retention.is_some() {
+                    format!("Moved {} files to trash", files_removed)
+                } else {
+                    format!("Removed {} files from search directory", files_removed)
+                };
+                Ok(MaintenanceResult::new(task, true, message))
+            }
+            MaintenanceTask::Optimize => {
+                let mut rebuilt = 0;
+                let mut rebuilt_postings = 0;
+                let mut failed = 0;
+                let mut analyzed = HashMap::new();
+                let catalog = self.catalog().await?;
+                let mut tracker = Tracker::new(progress, "optimize", Some(catalog.documents.len()));
+                for doc in &catalog.documents {
+                    match self.doc_store.ensure_offsets(&doc.path).await {
+                        Ok((_, true)) => rebuilt += 1,
+                        Ok((_, false)) => {}
+                        Err(e) => {
+                            warn!(path = ?doc.path, error = %e, "Failed to build line offsets");
+                            failed += 1;
+                        }
+                    }
+                    match self.postings_store.ensure_postings(&doc.path).await {
+                        Ok((_, true)) => rebuilt_postings += 1,
+                        Ok((_, false)) => {}
+                        Err(e) => {
+                            warn!(path = ?doc.path, error = %e, "Failed to build postings");
+                            failed += 1;
+                        }
+                    }
+                    // Documents picked up by a rescan or indexed by older versions lack these
+                    if doc.attributes.fingerprint.is_none() || doc.attributes.keywords.is_empty() || doc.attributes.language.is_none() {
+                        match self.read_document(&doc.path).await {
+                            Ok(text) => {
+                                let analysis = (simhash(&text), extract_keywords(&text, MAX_KEYWORDS), detect_language(&text));
+                                analyzed.insert(doc.path.clone(), analysis);
+                            }
+                            Err(e) => {
+                                warn!(path = ?doc.path, error = %e, "Failed to analyze document");
+                                failed += 1;
+                            }
+                        }
+                    }
+                    tracker.advance(self.relative_path(&doc.path));
+                }
+
+                let analyzed_count = analyzed.len();
+                if analyzed_count > 0 {
+                    self.update_catalog(|catalog| {
+                        for doc in catalog.documents.iter_mut() {
+                            if let Some((fingerprint, keywords, language)) = analyzed.remove(&doc.path) {
+                                doc.attributes.fingerprint = Some(fingerprint);
+                                doc.attributes.keywords = keywords;
+                                doc.attributes.language = Some(language.to_string());
+                            }
+                        }
+                    }).await?;
+                    self.commit_catalog().await?;
+                }
+
+                Ok(MaintenanceResult::new(
+                    task,
+                    failed == 0,
+                    format!(
+                        "Rebuilt {} line offset tables and {} postings tables, analyzed {} documents ({} failed)",
+                        rebuilt, rebuilt_postings, analyzed_count, failed
+                    ),
+                ))
+            }
+            MaintenanceTask::Rebuild => {
+                let report = self.rebuild_index(progress).await?;
+                Ok(MaintenanceResult::new(
+                    task,
+                    report.failed == 0,
+                    format!("Rebuilt the index from {} documents ({} failed)", report.documents, report.failed),
+                ))
+            }
+            MaintenanceTask::Verify => {
+                let mut problems = Vec::new();
+                if let Err(e) = self.doc_store.load_meta().await {
+                    problems.push(format!("index metadata: {}", e));
+                }
+                if let Err(e) = self.postings_store.verify_stats().await {
+                    problems.push(format!("term statistics: {}", e));
+                }
+                if let Err(e) = self.verify_dictionary().await {
+                    problems.push(format!("term dictionary: {}", e));
+                }
+                for (dir, what) in [(self.readers_dir(), "point-in-time reader"), (self.result_sets_dir(), "result set"), (self.scrolls_dir(), "scroll")] {
+                    problems.extend(verify_json_files(&dir, what).await?);
+                }
+                let catalog = self.catalog().await?;
+                let mut tracker = Tracker::new(progress, "verify", Some(catalog.documents.len()));
+                for doc in &catalog.documents {
+                    match self.storage.stat(&doc.path).await {
+                        Ok(Some(stored)) if stored.size != doc.size => {
+                            problems.push(format!("{}: size changed since indexing", doc.path.display()));
+                        }
+                        Ok(Some(_)) => {
+                            if let Err(e) = self.doc_store.verify_offsets(&doc.path).await {
+                                problems.push(format!("{}: {}", doc.path.display(), e));
+                            }
+                            if let Err(e) = self.postings_store.verify(&doc.path).await {
+                                problems.push(format!("{}: {}", doc.path.display(), e));
+                            }
+                            if let Err(e) = self.vector_store.verify(&doc.path).await {
+                                problems.push(format!("{}: {}", doc.path.display(), e));
+                            }
+                        }
+                        _ => problems.push(format!("{}: missing from disk", doc.path.display())),
+                    }
+                    tracker.advance(self.relative_path(&doc.path));
+                }
+
+                if problems.is_empty() {
+                    return Ok(MaintenanceResult::new(task, true, "Index is consistent".to_string()));
+                }
+
+                self.update_catalog(Catalog::mark_dirty).await?;
+                self.commit_catalog().await?;
+                Ok(MaintenanceResult::new(
+                    task,
+                    false,
+                    format!("Found {} problems: {}", problems.len(), problems.join("; ")),
+                ))
+            }
+            MaintenanceTask::Backup { destination } => {
+                let backup_dir = destination.join(format!("backup-{}", Utc::now().format("%Y%m%dT%H%M%SZ")));
+                let documents: Vec<PathBuf> = self.catalog().await?.documents.iter()
+                    .map(|doc| doc.path.clone())
+                    .collect();
+
+                let mut tracker = Tracker::new(progress, "backup", Some(documents.len()));
+                for file_path in &documents {
+                    let relative = self.relative_path(file_path);
+                    let target = backup_dir.join(&relative);
+                    if let Some(parent) = target.parent() {
+                        async_fs::create_dir_all(parent).await
+                            .context("Failed to create backup directory")?;
+                    }
+                    let mut content = self.storage.open(file_path).await?;
+                    let mut file = async_fs::File::create(&target).await
+                        .context("Failed to create backup file")?;
+                    tokio::io::copy(&mut content, &mut file).await
+                        .with_context(|| format!("Failed to back up {:?}", file_path))?;
+                    tracker.advance(relative);
+                }
+
+                let meta_dir = backup_dir.join(crate::docstore::INDEX_DIR);
+                async_fs::create_dir_all(&meta_dir).await
+                    .context("Failed to create backup directory")?;
+                let meta_path = self.doc_store.meta_path();
+                if let Some(file_name) = meta_path.file_name().filter(|_| meta_path.exists()) {
+                    async_fs::copy(&meta_path, meta_dir.join(file_name)).await
+                        .context("Failed to back up index metadata")?;
+                }
+
+                Ok(MaintenanceResult::new(
+                    task,
+                    true,
+                    format!("Backed up {} documents to {}", documents.len(), backup_dir.display()),
+                ))
+            }
+            MaintenanceTask::Prune { older_than_days } => {
+                let expired = self.removal_candidates(task).await?;
+
+                let mut removed = 0;
+                for file_path in &expired {
+                    match self.remove_document(file_path).await {
+                        Ok(()) => removed += 1,
+                        Err(e) => warn!(path = ?file_path, error = %e, "Failed to prune document"),
+                    }
+                }
+                self.commit_catalog().await?;
+
+                Ok(MaintenanceResult::new(
+                    task,
+                    removed == expired.len(),
+                    format!("Pruned {} of {} documents older than {} days", removed, expired.len(), older_than_days),
+                ))
+            }
+            MaintenanceTask::Compact => {
+                let live: Vec<PathBuf> = self.catalog().await?.documents.iter()
+                    .map(|doc| doc.path.clone())
+                    .collect();
+                let removed = self.doc_store.remove_orphans(&live).await?;
+                self.commit_catalog().await?;
+
+                Ok(MaintenanceResult::new(task, true, format!("Removed {} orphaned index files", removed)))
+            }
+            MaintenanceTask::Expire => {
+                let (expired, removed) = self.expire_documents(Utc::now()).await?;
+                Ok(MaintenanceResult::new(
+                    task,
+                    removed == expired,
+                    format!("Removed {} of {} expired documents", removed, expired),
+                ))
+            }
+            MaintenanceTask::DropPartitions { older_than_days } => {
+                let documents = self.removal_candidates(task).await?;
+                let partitions = self.partition_dirs(&documents);
+
+                let mut removed = 0;
+                for file_path in &documents {
+                    match self.remove_document(file_path).await {
+                        Ok(()) => removed += 1,
+                        Err(e) => warn!(path = ?file_path, error = %e, "Failed to drop document"),
+                    }
+                }
+                self.commit_catalog().await?;
+                for dir in &partitions {
+                    remove_empty_dirs(dir).await;
+                }
+
+                Ok(MaintenanceResult::new(
+                    task,
+                    removed == documents.len(),
+                    format!(
+                        "Dropped {} partitions older than {} days ({} of {} documents removed)",
+                        partitions.len(), older_than_days, removed, documents.len()
+                    ),
+                ))
+            }
+            MaintenanceTask::ArchivePartitions { older_than_days, destination } => {
+                let documents = self.removal_candidates(task).await?;
+                let partitions = self.partition_dirs(&documents);
+
+                let mut archived = 0;
+                for file_path in &documents {
+                    match self.archive_document(file_path, destination).await {
+                        Ok(()) => archived += 1,
+                        Err(e) => warn!(path = ?file_path, error = %e, "Failed to archive document"),
+                    }
+                }
+                self.commit_catalog().await?;
+                for dir in &partitions {
+                    remove_empty_dirs(dir).await;
+                }
+
+                Ok(MaintenanceResult::new(
+                    task,
+                    archived == documents.len(),
+                    format!(
+                        "Archived {} partitions older than {} days to {} ({} of {} documents moved)",
+                        partitions.len(), older_than_days, destination.display(), archived, documents.len()
+                    ),
+                ))
+            }
+            MaintenanceTask::Replicate => {
+                let report = self.replicate().await?;
+                Ok(MaintenanceResult::new(
+                    task,
+                    report.failed.is_empty(),
+                    format!(
+                        "Downloaded {} and removed {} documents from {}, {} unchanged ({} failed)",
+                        report.downloaded.len(), report.removed.len(), report.primary, report.unchanged, report.failed.len()
+                    ),
+                ))
+            }
+            MaintenanceTask::PurgeTrash { all } => {
+                let (candidates, purged) = self.purge_trash(*all, Utc::now()).await?;
+                Ok(MaintenanceResult {
+                    affected: purged.iter().map(|path| path.to_string_lossy().to_string()).collect(),
+                    ..MaintenanceResult::new(
+                        task,
+                        purged.len() == candidates,
+                        format!("Purged {} of {} trashed documents", purged.len(), candidates),
+                    )
+                })
+            }
+        }
+    }
+
+    // The partition directories the documents are in
+    fn partition_dirs(&self, documents: &[PathBuf]) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = documents.iter()
+            .filter_map(|path| partition_of(&self.relative_path(path)).map(|key| self.search_path.join(key)))
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    // Moves a document to the same relative path under `destination` and drops it from
+    // the index, leaving the catalog uncommitted
+    async fn archive_document(&self, file_path: &Path, destination: &Path) -> Result<()> {
+        self.ensure_owned(file_path)?;
+        self.storage.rename(file_path, &destination.join(self.relative_path(file_path))).await?;
+        self.doc_store.remove_offsets(file_path).await?;
+        self.vector_store.remove(file_path).await?;
+        self.postings_store.remove(file_path).await?;
+        self.update_catalog(|catalog| catalog.remove(file_path)).await?;
+        Ok(())
+    }
+}
+
+// Removes `root` and the directories below it that are left empty, deepest first
+async fn remove_empty_dirs(root: &Path) {
+    let dirs: Vec<PathBuf> = WalkDir::new(root).contents_first(true).into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+        .map(|entry| entry.into_path())
+        .collect();
+    for dir in dirs {
+        // Fails harmlessly on directories that still hold files
+        let _ = async_fs::remove_dir(&dir).await;
+    }
+}
+
+// Problems with the JSON files in `dir`, each of which must parse and match its checksum
+async fn verify_json_files(dir: &Path, what: &str) -> Result<Vec<String>> {
+    let mut entries = match async_fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to list index files"),
+    };
+    let mut problems = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+            continue;
+        }
+        let checked = async_fs::read(&path).await.map_err(Into::into)
+            .and_then(|bytes| checksum::from_json::<serde_json::Value>(&bytes, what));
+        if let Err(e) = checked {
+            problems.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    Ok(problems)
+}
@@ -0,0 +1,1154 @@
+// Line matching, scoring and ranking shared by the engine and `search_documents`.
+// Nothing here touches tokio, the filesystem or the clock, so it builds for wasm32.
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "regex")]
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::analyzer::Analyzer;
+use crate::bm25::{Bm25, CorpusStats, Scorer};
+use crate::boolean::QueryNode;
+use crate::clustering::cluster_results;
+use crate::error::{Result, SearchEngineError};
+use crate::fuzzy::edit_distance;
+use crate::language::detect_language;
+use crate::metadata::Metadata;
+use crate::normalize::{decomposed, is_mark, normalize_query, Folding};
+use crate::summary::{summarize, SUMMARY_SENTENCES};
+use crate::proximity::{near_occurrences, nearest_frequency, proximity_frequency};
+use crate::stem::{stem, StemLanguage};
+use crate::stopwords::StopWords;
+use crate::wildcard::wildcard_matches;
+use crate::collation::{compare_names, compare_paths, file_name, Collation};
+use crate::options::{MatchMode, SearchFilters, SearchLimits, SearchOptions, SortOrder};
+use crate::query::{parse_query, ParsedQuery};
+use crate::{SearchResponse, SearchResult};
+
+// Combining marks count as part of the word they are on, as in text not in form C
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || (!c.is_ascii() && is_mark(c))
+}
+
+// The terms of lowercased text: runs of letters, digits and underscores
+pub(crate) fn index_terms(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !is_word_char(c)).filter(|term| !term.is_empty())
+}
+
+// The terms `analyzer` splits `text` into, lowercased and folded as lines are
+pub(crate) fn analyzed_terms(analyzer: &dyn Analyzer, text: &str, folding: Folding) -> Vec<String> {
+    analyzer.analyze(text).iter().map(|term| folding.fold(term)).collect()
+}
+
+// Chinese, Japanese and Korean characters, written without spaces between words
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0xAC00..=0xD7AF)
+}
+
+// Whether `term` has to be matched as part of indexed terms even as a whole word, as a run
+// of Chinese or Japanese text is one term with no word boundaries to find it by
+pub(crate) fn has_cjk(term: &str) -> bool {
+    !term.is_ascii() && term.chars().any(is_cjk)
+}
+
+// True if `[start, end)` of `text` is not part of a longer word. Where the characters on
+// either side of an edge are CJK, the edge counts as a word boundary.
+fn is_word_bounded(text: &str, start: usize, end: usize) -> bool {
+    let matched = &text[start..end];
+    let joined = |outside: Option<char>, inside: Option<char>| {
+        outside.is_some_and(|c| is_word_char(c) && !is_cjk(c)) && !inside.is_some_and(is_cjk)
+    };
+    !joined(text[..start].chars().next_back(), matched.chars().next())
+        && !joined(text[end..].chars().next(), matched.chars().next_back())
+}
+
+// True if `word` occurs in `text` as a whole word
+pub(crate) fn contains_word(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(start, found)| is_word_bounded(text, start, start + found.len()))
+}
+
+// End of the match if `query`, folded and decomposed, matches `text` folded at byte `start`.
+// Combining marks right after the match belong to its last character: with accent folding
+// the match takes them in, otherwise that character isn't the query's and there is no match.
+fn match_at(text: &str, start: usize, query: &[char], folding: Folding) -> Option<usize> {
+    let mut expected = query.iter().peekable();
+    let mut end = None;
+    for (offset, c) in text[start..].char_indices() {
+        if end.is_some() {
+            if !is_mark(c) {
+                break;
+            }
+            if !folding.accents {
+                return None;
+            }
+            end = Some(start + offset + c.len_utf8());
+            continue;
+        }
+        let mut matched = true;
+        folding.fold_char(c, |folded| matched = matched && expected.next() == Some(&folded));
+        if !matched {
+            return None;
+        }
+        if expected.peek().is_none() {
+            end = Some(start + offset + c.len_utf8());
+        }
+    }
+    end
+}
+
+// Byte ranges of the non-overlapping occurrences of folded `query` in `text`, folded as well
+fn find_folded(text: &str, query: &str, folding: Folding) -> Vec<[usize; 2]> {
+    let mut ranges = Vec::new();
+    if query.is_empty() {
+        return ranges;
+    }
+    let query = decomposed(query);
+    let mut from = 0;
+    while let Some(c) = text[from..].chars().next() {
+        match match_at(text, from, &query, folding) {
+            Some(end) => {
+                ranges.push([from, end]);
+                from = end;
+            }
+            None => from += c.len_utf8(),
+        }
+    }
+    ranges
+}
+
+// Byte ranges of the words in `text`
+fn word_spans(text: &str) -> Vec<[usize; 2]> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (at, c) in text.char_indices() {
+        match (is_word_char(c), start) {
+            (true, None) => start = Some(at),
+            (false, Some(from)) => {
+                spans.push([from, at]);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        spans.push([from, text.len()]);
+    }
+    spans
+}
+
+// `word` split into runs of Chinese, Japanese or Korean characters and runs of others, each
+// with its byte offset and whether it is CJK
+fn cjk_runs(word: &str) -> Vec<(usize, &str, bool)> {
+    let mut runs: Vec<(usize, &str, bool)> = Vec::new();
+    for (at, c) in word.char_indices() {
+        let cjk = is_cjk(c);
+        match runs.last_mut() {
+            Some((start, run, last)) if *last == cjk => *run = &word[*start..at + c.len_utf8()],
+            _ => runs.push((at, &word[at..at + c.len_utf8()], cjk)),
+        }
+    }
+    runs
+}
+
+// A position phrases and NEAR count in a line: a word, or a character of a run of Chinese,
+// Japanese or Korean text, which has no word boundaries, holding the n-gram of the postings
+// tables' size starting there, cut short at the end of the run
+struct Position {
+    // Folded
+    text: String,
+    cjk: bool,
+    // Of the word or character
+    range: [usize; 2],
+}
+
+fn positions(text: &str, gram_size: usize, folding: Folding) -> Vec<Position> {
+    let mut positions = Vec::new();
+    for [start, end] in word_spans(text) {
+        for (offset, run, cjk) in cjk_runs(&text[start..end]) {
+            let start = start + offset;
+            if !cjk {
+                positions.push(Position { text: folding.fold(run), cjk, range: [start, start + run.len()] });
+                continue;
+            }
+            let chars: Vec<(usize, char)> = run.char_indices().collect();
+            for (at, (offset, c)) in chars.iter().enumerate() {
+                let gram_end = chars.get(at + gram_size).map_or(run.len(), |(end, _)| *end);
+                let range = [start + offset, start + offset + c.len_utf8()];
+                positions.push(Position { text: folding.fold(&run[*offset..gram_end]), cjk, range });
+            }
+        }
+    }
+    positions
+}
+
+// A folded word, or a run of Chinese, Japanese or Korean text in one, as it is found among a
+// line's positions: a word at one, a run of n characters at n from its n-grams
+struct PositionedTerm {
+    grams: Vec<String>,
+    cjk: bool,
+    width: usize,
+}
+
+// The terms of folded `words` separated by single spaces, in order
+fn positioned_terms(words: &str, gram_size: usize) -> Vec<PositionedTerm> {
+    let mut terms = Vec::new();
+    for (_, run, cjk) in words.split(' ').flat_map(cjk_runs) {
+        if !cjk {
+            terms.push(PositionedTerm { grams: vec![run.to_string()], cjk, width: 1 });
+            continue;
+        }
+        // A run shorter than an n-gram is the start of the one at its position
+        let chars: Vec<char> = run.chars().collect();
+        let grams = (0..=chars.len().saturating_sub(gram_size))
+            .map(|at| chars[at..(at + gram_size).min(chars.len())].iter().collect())
+            .collect();
+        terms.push(PositionedTerm { grams, cjk, width: chars.len() });
+    }
+    terms
+}
+
+// The first and last of the positions where `terms` follow each other, overlapping or not
+fn occurrences(positions: &[Position], terms: &[PositionedTerm]) -> Vec<[usize; 2]> {
+    let found_at = |at: usize, term: &PositionedTerm| {
+        at + term.width <= positions.len() && term.grams.iter().zip(&positions[at..]).all(|(gram, position)| {
+            position.cjk == term.cjk && if term.cjk { position.text.starts_with(gram.as_str()) } else { position.text == *gram }
+        })
+    };
+    let mut found = Vec::new();
+    if terms.is_empty() {
+        return found;
+    }
+    for first in 0..positions.len() {
+        let mut at = first;
+        if terms.iter().all(|term| found_at(at, term) && { at += term.width; true }) {
+            found.push([first, at - 1]);
+        }
+    }
+    found
+}
+
+// Byte ranges of the non-overlapping places where the words of `phrase`, folded and
+// separated by single spaces, follow each other in `text` as whole words, whatever
+// punctuation or spacing is between them. Chinese, Japanese and Korean text is matched by
+// its n-grams of `gram_size` characters, one position apart.
+fn find_phrase(text: &str, phrase: &str, gram_size: usize, folding: Folding) -> Vec<[usize; 2]> {
+    let positions = positions(text, gram_size, folding);
+    let mut ranges = Vec::new();
+    let mut next = 0;
+    for [first, last] in occurrences(&positions, &positioned_terms(phrase, gram_size)) {
+        if first >= next {
+            ranges.push([positions[first].range[0], positions[last].range[1]]);
+            next = last + 1;
+        }
+    }
+    ranges
+}
+
+// Byte ranges of the non-overlapping places where words with the stems `stems`, in order,
+// follow each other in `text`, stemmed in `language`
+fn find_stemmed(text: &str, stems: &[String], language: StemLanguage, folding: Folding) -> Vec<[usize; 2]> {
+    let spans = word_spans(text);
+    let mut ranges = Vec::new();
+    let mut at = 0;
+    while at + stems.len() <= spans.len() {
+        let matched = stems.iter().zip(&spans[at..])
+            .all(|(expected, [start, end])| stem(&folding.fold(&text[*start..*end]), language) == expected.as_str());
+        if matched {
+            ranges.push([spans[at][0], spans[at + stems.len() - 1][1]]);
+            at += stems.len();
+        } else {
+            at += 1;
+        }
+    }
+    ranges
+}
+
+// Sorted ranges without those overlapping an earlier or longer one
+fn without_overlaps(mut ranges: Vec<[usize; 2]>) -> Vec<[usize; 2]> {
+    ranges.sort_by_key(|[start, end]| (*start, std::cmp::Reverse(*end)));
+    let mut covered = 0;
+    ranges.retain(|[start, end]| {
+        let keep = *start >= covered;
+        if keep {
+            covered = *end;
+        }
+        keep
+    });
+    ranges
+}
+
+// Byte ranges of the words in `text` within `distance` edits of folded `term`
+fn find_fuzzy(text: &str, term: &str, distance: usize, folding: Folding) -> Vec<[usize; 2]> {
+    word_spans(text).into_iter()
+        .filter(|[start, end]| edit_distance(&folding.fold(&text[*start..*end]), term, distance).is_some())
+        .collect()
+}
+
+// Byte ranges of the words in `text` that fit the folded wildcard `pattern`
+fn find_wildcard(text: &str, pattern: &str, folding: Folding) -> Vec<[usize; 2]> {
+    word_spans(text).into_iter()
+        .filter(|[start, end]| wildcard_matches(pattern, &folding.fold(&text[*start..*end])))
+        .collect()
+}
+
+// The occurrences among `positions` of each of the folded words
+fn occurrences_of<const N: usize>(positions: &[Position], words: [&str; N], gram_size: usize) -> [Vec<[usize; 2]>; N] {
+    words.map(|word| occurrences(positions, &positioned_terms(word, gram_size)))
+}
+
+// Byte ranges of the occurrences of the folded words `terms`, separated by a space, that
+// are within `distance` positions of each other in `text`
+fn find_near(text: &str, terms: &str, distance: usize, gram_size: usize, folding: Folding) -> Vec<[usize; 2]> {
+    let positions = positions(text, gram_size, folding);
+    let (left, right) = terms.split_once(' ').unwrap_or((terms, terms));
+    let [left, right] = occurrences_of(&positions, [left, right], gram_size);
+    let mut ranges: Vec<[usize; 2]> = near_occurrences(&left, &right, distance).into_iter()
+        .flat_map(|(at, other, _)| [left[at], right[other]])
+        .map(|[first, last]| [positions[first].range[0], positions[last].range[1]])
+        .collect();
+    ranges.sort_unstable();
+    ranges.dedup();
+    ranges
+}
+
+// Compiled form of a query under a match mode
+#[derive(Clone)]
+pub(crate) struct Matcher {
+    mode: MatchMode,
+    // Lowercased query, used for substring and word matching and scoring; for a parsed
+    // query, its terms and phrases that aren't negated, leaving out fuzzy, wildcard and NEAR
+    // terms and synonyms
+    query: String,
+    // Distinct words of the query when it has several, for per-term highlights; empty in regex
+    // mode. For a parsed query, the text of every term and phrase that isn't negated.
+    terms: Vec<String>,
+    // Query with operators or quoted phrases, as parsed
+    tree: Option<QueryNode>,
+    // Its terms and phrases: those in `terms`, in the same order, then those only under NOT
+    leaves: Vec<QueryNode>,
+    // Caps on the search the query is for
+    limits: SearchLimits,
+    // With stemming, the stems of the query, its terms and the terms of a parsed query, by
+    // their text; words with the same stems match them too
+    stems: HashMap<String, Vec<String>>,
+    // Language of `stems`
+    stem_language: StemLanguage,
+    // Words that add nothing to scores
+    stopwords: Arc<StopWords>,
+    // Whether terms and lines are folded without accents
+    fold_accents: bool,
+    // Whether terms and lines keep their case
+    case_sensitive: bool,
+    // Splits lines and query terms in place of `index_terms`
+    analyzer: Option<Arc<dyn Analyzer>>,
+    // Size of the n-grams postings tables list, by which phrases and NEAR find Chinese,
+    // Japanese and Korean text; single characters without them
+    ngrams: Option<usize>,
+    // Only set in regex mode
+    #[cfg(feature = "regex")]
+    pattern: Option<Regex>,
+}
+
+impl Matcher {
+    // For saved alert and percolator queries, whose field terms are ordinary words. With
+    // `fuzzy`, every word of the query matches words within that many edits. The regex size
+    // cap of `limits` applies here; the others to the search the matcher is used for.
+    #[cfg(feature = "engine")]
+    pub(crate) fn new(query: &str, mode: MatchMode, fuzzy: Option<usize>, limits: &SearchLimits) -> Result<Self> {
+        let tree = match mode {
+            MatchMode::Regex => None,
+            MatchMode::Substring | MatchMode::Word => crate::query::parse_tree(query, fuzzy)?,
+        };
+        Self::build(query, tree, mode, fuzzy, false, limits)
+    }
+
+    // Matches the text of a query split by `parse_query` with the fuzzy distance it was parsed
+    // with, and with the case it was parsed with if `case_sensitive`
+    pub(crate) fn parsed(
+        parsed: &ParsedQuery,
+        mode: MatchMode,
+        fuzzy: Option<usize>,
+        case_sensitive: bool,
+        limits: &SearchLimits,
+    ) -> Result<Self> {
+        Self::build(&parsed.text, parsed.tree.clone(), mode, fuzzy, case_sensitive, limits)
+    }
+
+    fn build(
+        query: &str,
+        tree: Option<QueryNode>,
+        mode: MatchMode,
+        fuzzy: Option<usize>,
+        case_sensitive: bool,
+        limits: &SearchLimits,
+    ) -> Result<Self> {
+        #[cfg(feature = "regex")]
+        let pattern = match mode {
+            MatchMode::Regex => {
+                let mut builder = RegexBuilder::new(query);
+                builder.case_insensitive(!case_sensitive);
+                if let Some(max) = limits.max_regex_size {
+                    builder.size_limit(max);
+                }
+                Some(builder.build().map_err(|e| match e {
+                    regex::Error::CompiledTooBig(max) => {
+                        SearchEngineError::InvalidQuery(format!("regex is too complex: it compiles to more than {} bytes", max))
+                    }
+                    e => SearchEngineError::InvalidQuery(e.to_string()),
+                })?)
+            }
+            MatchMode::Substring | MatchMode::Word => None,
+        };
+        #[cfg(not(feature = "regex"))]
+        if mode == MatchMode::Regex {
+            return Err(SearchEngineError::InvalidQuery("regex mode requires the `regex` feature".to_string()));
+        }
+        if mode == MatchMode::Regex && fuzzy.is_some() {
+            return Err(SearchEngineError::InvalidQuery("fuzzy matching doesn't apply to regex queries".to_string()));
+        }
+        if let Some(tree) = tree {
+            let mut leaves: Vec<QueryNode> = tree.terms(false).into_iter().cloned().collect();
+            let terms: Vec<String> = leaves.iter().filter_map(QueryNode::text).map(str::to_string).collect();
+            for leaf in tree.terms(true) {
+                if !leaves.contains(leaf) {
+                    leaves.push(leaf.clone());
+                }
+            }
+            let exact: Vec<&str> = leaves[..terms.len()].iter()
+                .filter(|leaf| {
+                    !matches!(leaf, QueryNode::Fuzzy { .. } | QueryNode::Wildcard(_) | QueryNode::Near { .. } | QueryNode::Synonym { .. })
+                })
+                .filter_map(QueryNode::text)
+                .collect();
+            return Ok(Matcher {
+                mode,
+                query: exact.join(" "),
+                terms,
+                tree: Some(tree),
+                leaves,
+                limits: *limits,
+                stems: HashMap::new(),
+                stem_language: StemLanguage::default(),
+                stopwords: Arc::default(),
+                fold_accents: false,
+                case_sensitive,
+                analyzer: None,
+                ngrams: None,
+                #[cfg(feature = "regex")]
+                pattern,
+            });
+        }
+        let query = if case_sensitive { query.to_string() } else { query.to_lowercase() };
+        let mut terms: Vec<String> = Vec::new();
+        if mode != MatchMode::Regex {
+            for term in query.split_whitespace() {
+                if !terms.iter().any(|existing| existing == term) {
+                    terms.push(term.to_string());
+                }
+            }
+        }
+        if terms.len() < 2 {
+            terms.clear();
+        }
+        Ok(Matcher {
+            mode,
+            query,
+            terms,
+            tree: None,
+            leaves: Vec::new(),
+            limits: *limits,
+            stems: HashMap::new(),
+            stem_language: StemLanguage::default(),
+            stopwords: Arc::default(),
+            fold_accents: false,
+            case_sensitive,
+            analyzer: None,
+            ngrams: None,
+            #[cfg(feature = "regex")]
+            pattern,
+        })
+    }
+
+    // Also matches the query's terms by their stems in `language` if one is given, so
+    // "running" finds "runs" and "ran". Phrases, fuzzy, wildcard and NEAR terms and regex queries still match as written.
+    #[cfg(feature = "engine")]
+    pub(crate) fn with_stemming(mut self, language: Option<StemLanguage>) -> Self {
+        self.stems.clear();
+        let Some(language) = language else {
+            return self;
+        };
+        self.stem_language = language;
+        if self.case_sensitive || self.mode == MatchMode::Regex {
+            return self;
+        }
+        let mut texts = vec![self.query.clone()];
+        texts.extend(self.terms.iter().cloned());
+        texts.extend(self.leaves.iter().filter_map(|leaf| match leaf {
+            QueryNode::Term(term) | QueryNode::Synonym { term, .. } => Some(term.clone()),
+            _ => None,
+        }));
+        for text in texts {
+            let stems: Vec<String> = index_terms(&text).map(|word| stem(word, language).into_owned()).collect();
+            if !stems.is_empty() {
+                self.stems.insert(text, stems);
+            }
+        }
+        self
+    }
+
+    // Stop words of the engine the query is run by
+    #[cfg(feature = "engine")]
+    pub(crate) fn with_stopwords(mut self, stopwords: Arc<StopWords>) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+
+    // Matches "cafe" and "Café" alike, folding the query's terms and the lines it is matched
+    // against to their letters without accents. Regex queries still match as written.
+    #[cfg(feature = "engine")]
+    pub(crate) fn with_fold_accents(mut self, fold_accents: bool) -> Self {
+        if !fold_accents || self.fold_accents || self.mode == MatchMode::Regex {
+            return self;
+        }
+        let folding = Folding { accents: true, keep_case: self.case_sensitive };
+        let fold = |text: &str| folding.fold(text);
+        self.fold_accents = true;
+        self.query = fold(&self.query);
+        self.terms.iter_mut().for_each(|term| *term = fold(term));
+        self.leaves.iter_mut().for_each(|leaf| leaf.map_text(&fold));
+        if let Some(tree) = &mut self.tree {
+            tree.map_text(&fold);
+        }
+        self
+    }
+
+    // This query matching in any case, as postings tables list lowercased terms
+    pub(crate) fn ignoring_case(&self) -> Self {
+        let mut matcher = self.clone();
+        let lower = |text: &str| text.to_lowercase();
+        matcher.case_sensitive = false;
+        matcher.query = lower(&matcher.query);
+        matcher.terms.iter_mut().for_each(|term| *term = lower(term));
+        matcher.leaves.iter_mut().for_each(|leaf| leaf.map_text(&lower));
+        if let Some(tree) = &mut matcher.tree {
+            tree.map_text(&lower);
+        }
+        matcher
+    }
+
+    // Splits lines and query terms with `analyzer` rather than into runs of letters, digits
+    // and underscores. Regex queries still match as written.
+    #[cfg(feature = "engine")]
+    pub(crate) fn with_analyzer(mut self, analyzer: Option<Arc<dyn Analyzer>>) -> Self {
+        if self.mode != MatchMode::Regex {
+            self.analyzer = analyzer;
+        }
+        self
+    }
+
+    // Accent folding, stemming, stop words, analyzer and n-grams of the documents the query
+    // is run against; folding comes first, so the folded terms are the ones stemmed
+    #[cfg(feature = "engine")]
+    pub(crate) fn with_analysis(mut self, analysis: &crate::analysis::Analysis) -> Self {
+        self.ngrams = analysis.ngrams;
+        self.with_fold_accents(analysis.fold_accents)
+            .with_stemming(analysis.stemming.then_some(analysis.stem_language))
+            .with_stopwords(analysis.stopwords.clone())
+            .with_analyzer(analysis.analyzer.clone())
+    }
+
+    fn gram_size(&self) -> usize {
+        self.ngrams.unwrap_or(1)
+    }
+
+    #[cfg(feature = "engine")]
+    pub(crate) fn folds_accents(&self) -> bool {
+        self.fold_accents
+    }
+
+    // How lines are folded before this query's terms are looked for in them
+    fn folding(&self) -> Folding {
+        Folding { accents: self.fold_accents, keep_case: self.case_sensitive }
+    }
+
+    pub(crate) fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+
+    // `text` folded the way lines are before this query's terms are looked for in them
+    pub(crate) fn fold(&self, text: &str) -> String {
+        self.folding().fold(text)
+    }
+
+    pub(crate) fn stopwords(&self) -> &StopWords {
+        &self.stopwords
+    }
+
+    pub(crate) fn analyzer(&self) -> Option<&dyn Analyzer> {
+        self.analyzer.as_deref()
+    }
+
+    // Whether `term` is matched as a whole term rather than as part of one. In word mode it
+    // is unless, without an analyzer, it is Chinese or Japanese text.
+    pub(crate) fn is_exact(&self, term: &str) -> bool {
+        self.mode == MatchMode::Word && (self.analyzer.is_some() || !has_cjk(term))
+    }
+
+    // The terms of folded query text, split the way lines are
+    pub(crate) fn terms_of<'t>(&self, text: &'t str) -> Vec<Cow<'t, str>> {
+        match &self.analyzer {
+            Some(analyzer) => analyzed_terms(analyzer.as_ref(), text, self.folding()).into_iter().map(Cow::Owned).collect(),
+            None => index_terms(text).map(Cow::Borrowed).collect(),
+        }
+    }
+
+    // The terms of `line`, whose folded form is `line_lower`
+    pub(crate) fn line_terms<'t>(&self, line: &str, line_lower: &'t str) -> Vec<Cow<'t, str>> {
+        match &self.analyzer {
+            Some(analyzer) => analyzed_terms(analyzer.as_ref(), line, self.folding()).into_iter().map(Cow::Owned).collect(),
+            None => index_terms(line_lower).map(Cow::Borrowed).collect(),
+        }
+    }
+
+    // Whether `term` occurs in `line` as whole words: with an analyzer, its terms follow each
+    // other among the line's, otherwise it is found in `line_lower` between word boundaries
+    fn contains_word(&self, line: &str, line_lower: &str, term: &str) -> bool {
+        if self.analyzer.is_none() {
+            return contains_word(line_lower, term);
+        }
+        let wanted = self.terms_of(term);
+        !wanted.is_empty() && self.line_terms(line, line_lower).windows(wanted.len()).any(|words| words == wanted.as_slice())
+    }
+
+    // Byte ranges of folded `term` in `line` as this query's mode matches it: anywhere in
+    // substring mode and as whole words in word mode. Terms an analyzer splits lines into
+    // needn't end at word boundaries, so with one `term` is found wherever it is, or else
+    // the terms it splits into are.
+    fn find_term(&self, line: &str, term: &str) -> Vec<[usize; 2]> {
+        match (self.mode, &self.analyzer) {
+            (MatchMode::Word, Some(_)) => {
+                let ranges = find_folded(line, term, self.folding());
+                if !ranges.is_empty() {
+                    return ranges;
+                }
+                without_overlaps(self.terms_of(term).iter().flat_map(|term| find_folded(line, term, self.folding())).collect())
+            }
+            (MatchMode::Word, None) => find_folded(line, term, self.folding()).into_iter()
+                .filter(|[start, end]| is_word_bounded(line, *start, *end))
+                .collect(),
+            _ => find_folded(line, term, self.folding()),
+        }
+    }
+
+    pub(crate) fn stemming(&self) -> bool {
+        !self.stems.is_empty()
+    }
+
+    pub(crate) fn stem_language(&self) -> StemLanguage {
+        self.stem_language
+    }
+
+    // Stems of the query, one of its terms or a term of a parsed query, if it is stemmed
+    pub(crate) fn stems(&self, text: &str) -> Option<&[String]> {
+        self.stems.get(text).map(Vec::as_slice)
+    }
+
+    // Whether lowercased `text` has words with the stems of `term`
+    fn contains_stemmed(&self, text: &str, term: &str) -> bool {
+        self.stems(term).is_some_and(|stems| !find_stemmed(text, stems, self.stem_language, self.folding()).is_empty())
+    }
+
+    // `ranges` of `term` in `line` together with where words with its stems are
+    fn with_stemmed(&self, line: &str, term: &str, ranges: Vec<[usize; 2]>) -> Vec<[usize; 2]> {
+        match self.stems(term) {
+            Some(stems) => without_overlaps(ranges.into_iter().chain(find_stemmed(line, stems, self.stem_language, self.folding())).collect()),
+            None => ranges,
+        }
+    }
+
+    pub(crate) fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub(crate) fn mode(&self) -> MatchMode {
+        self.mode
+    }
+
+    pub(crate) fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    pub(crate) fn tree(&self) -> Option<&QueryNode> {
+        self.tree.as_ref()
+    }
+
+    // Terms and phrases of a parsed query, those not negated first
+    pub(crate) fn leaves(&self) -> &[QueryNode] {
+        &self.leaves
+    }
+
+    pub(crate) fn limits(&self) -> &SearchLimits {
+        &self.limits
+    }
+
+    // Fuzzy terms that aren't negated, with the edits each allows
+    pub(crate) fn fuzzy_terms(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.leaves.iter().take(self.terms.len()).filter_map(|leaf| match leaf {
+            QueryNode::Fuzzy { term, distance } => Some((term.as_str(), *distance)),
+            _ => None,
+        })
+    }
+
+    // Wildcard patterns that aren't negated
+    pub(crate) fn wildcard_terms(&self) -> impl Iterator<Item = &str> {
+        self.leaves.iter().take(self.terms.len()).filter_map(|leaf| match leaf {
+            QueryNode::Wildcard(pattern) => Some(pattern.as_str()),
+            _ => None,
+        })
+    }
+
+    // Two words that must be close, with the distance allowed, for NEAR terms that aren't negated
+    pub(crate) fn near_terms(&self) -> impl Iterator<Item = (&str, &str, usize)> {
+        self.leaves.iter().take(self.terms.len()).filter_map(|leaf| match leaf {
+            QueryNode::Near { terms, distance } => {
+                terms.split_once(' ').map(|(left, right)| (left, right, *distance))
+            }
+            _ => None,
+        })
+    }
+
+    // How close `left` and `right` are in `line`, folded to `line_lower`, from `nearest_frequency`;
+    // among the analyzer's terms if there is one
+    pub(crate) fn near_frequency(&self, line: &str, line_lower: &str, left: &str, right: &str, distance: usize) -> f32 {
+        if self.analyzer.is_some() {
+            return proximity_frequency(&self.line_terms(line, line_lower), left, right, distance);
+        }
+        let positions = positions(line_lower, self.gram_size(), self.folding());
+        let [left, right] = occurrences_of(&positions, [left, right], self.gram_size());
+        nearest_frequency(&near_occurrences(&left, &right, distance))
+    }
+
+    // Synonyms of terms that aren't negated, each with the text it stands in for
+    pub(crate) fn synonym_terms(&self) -> impl Iterator<Item = (&QueryNode, &str)> {
+        self.leaves.iter().take(self.terms.len()).filter_map(|leaf| match leaf {
+            QueryNode::Synonym { of, .. } => Some((leaf, of.as_str())),
+            _ => None,
+        })
+    }
+
+    // Whether `line`, folded to `text`, contains a term as this query's mode matches it,
+    // a phrase, a word close enough to a fuzzy term, one fitting a wildcard pattern or two
+    // words NEAR each other
+    fn contains_leaf(&self, line: &str, text: &str, leaf: &QueryNode) -> bool {
+        match (leaf, self.mode) {
+            (QueryNode::Phrase(phrase), _) => !find_phrase(text, phrase, self.gram_size(), self.folding()).is_empty(),
+            (QueryNode::Fuzzy { term, distance }, _) => {
+                self.line_terms(line, text).iter().any(|word| edit_distance(word, term, *distance).is_some())
+            }
+            (QueryNode::Wildcard(pattern), _) => self.line_terms(line, text).iter().any(|word| wildcard_matches(pattern, word)),
+            (QueryNode::Near { terms, distance }, _) => {
+                !find_near(text, terms, *distance, self.gram_size(), self.folding()).is_empty()
+            }
+            (QueryNode::Term(term) | QueryNode::Synonym { term, .. }, MatchMode::Word) => {
+                self.contains_word(line, text, term) || self.contains_stemmed(text, term)
+            }
+            (QueryNode::Term(term) | QueryNode::Synonym { term, .. }, _) => {
+                text.contains(term.as_str()) || self.contains_stemmed(text, term)
+            }
+            _ => false,
+        }
+    }
+
+    // Byte ranges of a term, phrase, fuzzy term, wildcard pattern or NEAR words in `line`
+    pub(crate) fn leaf_ranges(&self, line: &str, leaf: &QueryNode) -> Vec<[usize; 2]> {
+        match leaf {
+            QueryNode::Phrase(phrase) => find_phrase(line, phrase, self.gram_size(), self.folding()),
+            QueryNode::Fuzzy { term, distance } => find_fuzzy(line, term, *distance, self.folding()),
+            QueryNode::Wildcard(pattern) => find_wildcard(line, pattern, self.folding()),
+            QueryNode::Near { terms, distance } => find_near(line, terms, *distance, self.gram_size(), self.folding()),
+            _ => {
+                let term = leaf.text().unwrap_or_default();
+                self.with_stemmed(line, term, self.find_term(line, term))
+            }
+        }
+    }
+
+    // `line_lower` is `line` as `fold` leaves it; substring and word mode only need that. A parsed
+    // query matches lines with any term or phrase that isn't negated; whether the document
+    // as a whole satisfies it is up to `Presence`.
+    pub(crate) fn is_match(&self, line: &str, line_lower: &str) -> bool {
+        if self.tree.is_some() {
+            return self.leaves[..self.terms.len()].iter().any(|leaf| self.contains_leaf(line, line_lower, leaf));
+        }
+        match self.mode {
+            MatchMode::Substring => line_lower.contains(&self.query) || self.contains_stemmed(line_lower, &self.query),
+            MatchMode::Word => {
+                self.contains_word(line, line_lower, &self.query) || self.contains_stemmed(line_lower, &self.query)
+            }
+            MatchMode::Regex => self.regex_count(line).map(|count| count > 0).unwrap_or(false),
+        }
+    }
+
+    pub(crate) fn highlights(&self, line: &str) -> Vec<[usize; 2]> {
+        if self.tree.is_some() {
+            return self.term_highlights(line).into_iter().map(|[start, end, _]| [start, end]).collect();
+        }
+        match self.mode {
+            MatchMode::Substring | MatchMode::Word => self.with_stemmed(line, &self.query, self.find_term(line, &self.query)),
+            #[cfg(feature = "regex")]
+            MatchMode::Regex => self.pattern.iter()
+                .flat_map(|pattern| pattern.find_iter(line))
+                .filter(|m| !m.is_empty())
+                .map(|m| [m.start(), m.end()])
+                .collect(),
+            #[cfg(not(feature = "regex"))]
+            MatchMode::Regex => Vec::new(),
+        }
+    }
+
+    // [start, end, term] for each occurrence of a query term, `term` indexing `terms()`.
+    // Where occurrences overlap, the earliest and then longest one wins.
+    pub(crate) fn term_highlights(&self, line: &str) -> Vec<[usize; 3]> {
+        let mut spans: Vec<[usize; 3]> = if self.tree.is_some() {
+            self.leaves[..self.terms.len()].iter().enumerate()
+                .flat_map(|(term, leaf)| self.leaf_ranges(line, leaf).into_iter().map(move |[start, end]| [start, end, term]))
+                .collect()
+        } else {
+            self.terms.iter().enumerate()
+                .flat_map(|(term, text)| {
+                    self.with_stemmed(line, text, self.find_term(line, text)).into_iter().map(move |[start, end]| [start, end, term])
+                })
+                .collect()
+        };
+        spans.sort_by_key(|[start, end, _]| (*start, std::cmp::Reverse(*end)));
+        let mut covered = 0;
+        spans.retain(|[start, end, _]| {
+            let keep = *start >= covered;
+            if keep {
+                covered = *end;
+            }
+            keep
+        });
+        spans
+    }
+
+    // Number of matches in regex mode, which is scored by match count alone
+    #[cfg(feature = "regex")]
+    pub(crate) fn regex_count(&self, line: &str) -> Option<usize> {
+        self.pattern.as_ref().map(|pattern| pattern.find_iter(line).count())
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub(crate) fn regex_count(&self, _line: &str) -> Option<usize> {
+        None
+    }
+}
+
+// Which terms and phrases of a parsed query a document contains, collected line by line.
+// Other queries have nothing to track and every document satisfies them.
+pub(crate) struct Presence {
+    // One flag for each of the matcher's leaves
+    found: Vec<bool>,
+}
+
+impl Presence {
+    pub(crate) fn new(matcher: &Matcher) -> Self {
+        Presence { found: vec![false; matcher.leaves().len()] }
+    }
+
+    pub(crate) fn observe(&mut self, matcher: &Matcher, line: &str) {
+        if self.is_complete() {
+            return;
+        }
+        let line_lower = matcher.fold(line);
+        for (found, leaf) in self.found.iter_mut().zip(matcher.leaves()) {
+            if !*found {
+                *found = matcher.contains_leaf(line, &line_lower, leaf);
+            }
+        }
+    }
+
+    // Every leaf has been seen, so the rest of the document can't change the outcome
+    pub(crate) fn is_complete(&self) -> bool {
+        self.found.iter().all(|found| *found)
+    }
+
+    pub(crate) fn satisfies(&self, matcher: &Matcher) -> bool {
+        let Some(tree) = &matcher.tree else {
+            return true;
+        };
+        tree.evaluate(&|leaf| {
+            matcher.leaves().iter().position(|candidate| candidate == leaf).map(|i| self.found[i]).unwrap_or(false)
+        })
+    }
+}
+
+// Score for one line, or None if it doesn't match
+fn match_line(scorer: &Scorer, line: &str, line_lower: &str) -> Option<f32> {
+    if !scorer.matcher().is_match(line, line_lower) {
+        return None;
+    }
+    Some(scorer.score(line, line_lower))
+}
+
+// Paths and file names are compared in the alphabet of the `collation` locale if there is
+// one, which `SearchOptions::validate` has checked
+pub(crate) fn sort_results(results: &mut [SearchResult], sort: SortOrder, collation: Option<&str>) {
+    let collation = collation.and_then(|locale| Collation::new(locale).ok());
+    let collation = collation.as_ref();
+    match sort {
+        // Higher score is better
+        SortOrder::Relevance => {
+            results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        }
+        SortOrder::Path => {
+            results.sort_by(|a, b| compare_paths(&a.path, &b.path, collation).then(a.line_number.cmp(&b.line_number)));
+        }
+        SortOrder::Title => {
+            results.sort_by(|a, b| {
+                compare_names(title_name(a), title_name(b), collation)
+                    .then_with(|| compare_paths(&a.path, &b.path, collation))
+                    .then(a.line_number.cmp(&b.line_number))
+            });
+        }
+    }
+}
+
+// What a result's title starts with: the title its document declares, or its file name
+fn title_name(result: &SearchResult) -> &str {
+    result.document_title.as_deref().unwrap_or_else(|| file_name(&result.path))
+}
+
+// Keeps the `max` best-ranked results of each document; one per document when summarizing
+pub(crate) fn limit_per_document(results: &mut Vec<SearchResult>, max: usize) {
+    let mut seen: HashMap<Arc<str>, usize> = HashMap::new();
+    results.retain(|result| {
+        let count = seen.entry(result.path.clone()).or_insert(0);
+        *count += 1;
+        *count <= max
+    });
+}
+
+// What a result's score is multiplied by for each better-ranked one from its directory when
+// results are diversified
+const DIVERSITY_DECAY: f32 = 0.7;
+
+// Directory part of a result's path, empty for one at the top
+fn directory(path: &str) -> &str {
+    path.rfind(['/', '\\']).map(|at| &path[..at]).unwrap_or_default()
+}
+
+// Demotes results ranked by relevance below others from their directory: the nth result from
+// a directory has its score multiplied by DIVERSITY_DECAY n - 1 times before they are ranked
+// again, so a directory's best result keeps its place and the rest make way for other
+// directories unless they score well above them
+pub(crate) fn diversify(results: &mut [SearchResult], sort: SortOrder) {
+    if sort != SortOrder::Relevance {
+        return;
+    }
+    let mut shown: HashMap<&str, i32> = HashMap::new();
+    let factors: Vec<f32> = results.iter()
+        .map(|result| {
+            let earlier = shown.entry(directory(&result.path)).or_insert(0);
+            *earlier += 1;
+            DIVERSITY_DECAY.powi(*earlier - 1)
+        })
+        .collect();
+    for (result, factor) in results.iter_mut().zip(factors) {
+        result.score *= factor;
+    }
+    sort_results(results, sort, None);
+}
+
+// How many results each document may contribute, if limited
+pub(crate) fn per_document_limit(options: &SearchOptions) -> Option<usize> {
+    if options.summarize {
+        Some(1)
+    } else {
+        options.max_per_file
+    }
+}
+
+// A document already in memory, such as one loaded from an exported index in the browser
+#[derive(Debug, Clone, Copy)]
+pub struct TextDocument<'a> {
+    // Relative path, matched against `SearchFilters`
+    pub path: &'a str,
+    pub content: &'a str,
+    // Also what `after:` and `before:` compare, as there is no file modification time
+    pub indexed_at: DateTime<Utc>,
+}
+
+// Searches in-memory documents with the same matching and ranking as the engine, taking
+// BM25 statistics from the documents given. `timeout` and `profile` need a clock and are
+// ignored here, as are `reader`, `within` and `save_results`, which need an index; nothing
+// is logged, and words match as written, stemming being an engine setting.
+pub fn search_documents(documents: &[TextDocument], query: &str, options: &SearchOptions) -> Result<SearchResponse> {
+    let search = InMemorySearch::new(query, options)?;
+    let stats = if Scorer::needs_stats(&search.matcher) {
+        CorpusStats::from_texts(documents.iter().map(|doc| doc.content))
+    } else {
+        CorpusStats::default()
+    };
+    // In-memory documents have no tags or metadata, so filters on them leave none of them
+    let documents = documents.iter().enumerate().map(|(number, doc)| InMemoryDocument::new(number, *doc));
+    Ok(search.run(documents, &stats, options))
+}
+
+// A document searched in memory, with the attributes filters look at and results take
+pub(crate) struct InMemoryDocument<'a> {
+    // Its place among every document, which numbers its result IDs
+    pub(crate) number: usize,
+    pub(crate) text: TextDocument<'a>,
+    pub(crate) id: Option<&'a str>,
+    pub(crate) title: Option<&'a str>,
+    pub(crate) language: Option<&'a str>,
+    pub(crate) tags: &'a [String],
+    pub(crate) metadata: Option<&'a Metadata>,
+}
+
+impl<'a> InMemoryDocument<'a> {
+    pub(crate) fn new(number: usize, text: TextDocument<'a>) -> Self {
+        InMemoryDocument { number, text, id: None, title: None, language: None, tags: &[], metadata: None }
+    }
+}
+
+// A query parsed and checked for searching documents in memory, before they are picked
+pub(crate) struct InMemorySearch {
+    query: String,
+    // The query without its field terms, which are in `filters`
+    text: String,
+    matcher: Matcher,
+    filters: SearchFilters,
+}
+
+impl InMemorySearch {
+    pub(crate) fn new(query: &str, options: &SearchOptions) -> Result<Self> {
+        let query = normalize_query(query, options.mode)?;
+        let parsed = parse_query(&query, options.mode, options.fuzzy, options.case_sensitive)?;
+        options.validate()?;
+        let matcher = Matcher::parsed(&parsed, options.mode, options.fuzzy, options.case_sensitive, &options.limits)?;
+        let mut filters = options.filters.clone();
+        parsed.fields.apply(&mut filters);
+        Ok(InMemorySearch { query, text: parsed.text, matcher, filters })
+    }
+
+    pub(crate) fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    pub(crate) fn run<'a>(
+        &self,
+        documents: impl IntoIterator<Item = InMemoryDocument<'a>>,
+        stats: &CorpusStats,
+        options: &SearchOptions,
+    ) -> SearchResponse {
+        let InMemorySearch { query, text, matcher, filters } = self;
+        let scorer = Scorer::new(matcher, Bm25::default(), stats);
+        let no_metadata = Metadata::new();
+
+        let mut results = Vec::new();
+        let mut searched_documents = Vec::new();
+        let mut limited = false;
+        let candidates = documents.into_iter().filter(|doc| {
+            // Languages are only worked out when the filters ask for one and aren't known
+            let language = doc.language.or_else(|| (!filters.languages.is_empty()).then(|| detect_language(doc.text.content)));
+            let metadata = doc.metadata.unwrap_or(&no_metadata);
+            filters.matches(doc.text.path, None)
+                && filters.matches_attributes(doc.tags, metadata, language, doc.text.indexed_at, &[])
+        });
+        for (searched, doc) in candidates.enumerate() {
+            if matcher.limits().max_documents.is_some_and(|max| searched >= max) {
+                limited = true;
+                break;
+            }
+            let path: Arc<str> = Arc::from(doc.text.path);
+            let filename = doc.text.path.rsplit(['/', '\\']).next().unwrap_or(doc.text.path);
+            let label = doc.title.unwrap_or(filename);
+            let document_id: Option<Arc<str>> = doc.id.map(Arc::from);
+            let document_title: Option<Arc<str>> = doc.title.map(Arc::from);
+            let metadata = doc.metadata.filter(|metadata| !metadata.is_empty()).map(|metadata| Arc::new(metadata.clone()));
+            let boost = options.boost(doc.text.path);
+            let mut presence = Presence::new(matcher);
+            let mut doc_results = Vec::new();
+            for (idx, line) in doc.text.content.lines().enumerate() {
+                presence.observe(matcher, line);
+                let line_lower = matcher.fold(line);
+                let Some(score) = match_line(&scorer, line, &line_lower) else {
+                    continue;
+                };
+                let (highlights, term_highlights) = if options.highlight {
+                    (matcher.highlights(line), matcher.term_highlights(line))
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                let line_number = idx + 1;
+                doc_results.push(SearchResult {
+                    id: format!("{}-{}", doc.number, line_number),
+                    title: format!("{} (line {})", label, line_number),
+                    content: line.to_string(),
+                    score: score * boost,
+                    path: path.clone(),
+                    line_number: line_number as i64,
+                    page: None,
+                    document_title: document_title.clone(),
+                    indexed_at: doc.text.indexed_at,
+                    modified: None,
+                    document_id: document_id.clone(),
+                    metadata: metadata.clone(),
+                    highlights,
+                    term_highlights,
+                    summary: None,
+                    collection: None,
+                });
+            }
+            if presence.satisfies(matcher) {
+                results.extend(doc_results);
+            }
+            if options.summarize {
+                searched_documents.push(doc.text);
+            }
+            if let Some(max) = matcher.limits().max_matches.filter(|max| results.len() > *max) {
+                results.truncate(max);
+                limited = true;
+                break;
+            }
+        }
+
+        sort_results(&mut results, options.sort, options.collation.as_deref());
+        if let Some(max) = per_document_limit(options) {
+            limit_per_document(&mut results, max);
+        }
+        if options.diversify {
+            diversify(&mut results, options.sort);
+        }
+        let total = results.len();
+        let mut results: Vec<SearchResult> = results.into_iter().skip(options.offset).take(options.limit).collect();
+        if options.summarize {
+            for result in &mut results {
+                if let Some(doc) = searched_documents.iter().find(|doc| *doc.path == *result.path) {
+                    result.summary = Some(summarize(doc.content, matcher, SUMMARY_SENTENCES));
+                }
+            }
+        }
+        let clusters = cluster_results(results.iter().map(|result| (result.id.as_str(), result.content.as_str())), text, options.clusters);
+        SearchResponse {
+            query: query.clone(),
+            results,
+            total,
+            limit: options.limit,
+            offset: options.offset,
+            terms: if options.highlight { matcher.terms().to_vec() } else { Vec::new() },
+            tag_counts: BTreeMap::new(),
+            facets: BTreeMap::new(),
+            timed_out: false,
+            profile: None,
+            clusters,
+            query_tree: matcher.tree().cloned(),
+            limited,
+            result_set: None,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Application-defined fields given with a document at index time, such as a customer ID or
+// a category. Values are strings or numbers; they are stored with the document, returned
+// with its results, and can be filtered and faceted on. Pure data, so it builds for wasm32 as well.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::{Result, SearchEngineError};
+
+pub const MAX_METADATA_FIELDS: usize = 64;
+pub const MAX_METADATA_KEY_LENGTH: usize = 64;
+pub const MAX_METADATA_VALUE_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Number(serde_json::Number),
+    Text(String),
+}
+
+impl MetadataValue {
+    // Numbers compare by value and text case-insensitively; a number never equals text
+    pub fn matches(&self, other: &MetadataValue) -> bool {
+        match (self, other) {
+            (MetadataValue::Number(a), MetadataValue::Number(b)) => a.as_f64() == b.as_f64(),
+            (MetadataValue::Text(a), MetadataValue::Text(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        }
+    }
+}
+
+// Whole numbers display without a fraction, so 2 and 2.0 share a facet as they match the same filter
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataValue::Number(number) => match number.as_f64() {
+                Some(value) if value.fract() == 0.0 && value.abs() < 1e15 => write!(f, "{}", value as i64),
+                _ => write!(f, "{}", number),
+            },
+            MetadataValue::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+// Parses a "key=value" field; values that read as numbers become numbers
+pub fn parse_field(text: &str) -> Result<(String, MetadataValue)> {
+    let (key, value) = text.split_once('=').ok_or_else(|| {
+        SearchEngineError::InvalidInput(format!("Invalid metadata field {:?}: expected KEY=VALUE", text))
+    })?;
+    let value = match value.trim().parse::<serde_json::Number>() {
+        Ok(number) => MetadataValue::Number(number),
+        Err(_) => MetadataValue::Text(value.to_string()),
+    };
+    Ok((key.trim().to_string(), value))
+}
+
+// Checks the field count, key names and value sizes of metadata given at index time
+pub fn validate_metadata(metadata: &Metadata) -> Result<()> {
+    let invalid = |message: String| Err(SearchEngineError::InvalidInput(message));
+    if metadata.len() > MAX_METADATA_FIELDS {
+        return invalid(format!("At most {} metadata fields are allowed", MAX_METADATA_FIELDS));
+    }
+    for (key, value) in metadata {
+        let valid_key = !key.is_empty()
+            && key.len() <= MAX_METADATA_KEY_LENGTH
+            && key.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_key {
+            return invalid(format!(
+                "Invalid metadata key {:?}: use up to {} letters, digits, '-', '_' or '.'", key, MAX_METADATA_KEY_LENGTH
+            ));
+        }
+        if let MetadataValue::Text(text) = value {
+            if text.len() > MAX_METADATA_VALUE_LENGTH {
+                return invalid(format!("Metadata field {:?} is longer than {} bytes", key, MAX_METADATA_VALUE_LENGTH));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Adds a document's values of the requested fields to facet counts keyed by field, then value
+pub fn count_facets(counts: &mut BTreeMap<String, BTreeMap<String, usize>>, fields: &[String], metadata: &Metadata) {
+    for field in fields {
+        if let Some(value) = metadata.get(field) {
+            *counts.entry(field.clone()).or_default().entry(value.to_string()).or_insert(0) += 1;
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+
+use crate::error::Result;
+use crate::querylog;
+use crate::SearchEngine;
+
+// Appends one metric family in the Prometheus text exposition format. Each sample is
+// written as the name followed by its suffix, e.g. `_sum` or `{extension="txt"}`.
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (suffix, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, suffix, value);
+    }
+}
+
+fn single(value: f64) -> Vec<(String, f64)> {
+    vec![(String::new(), value)]
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl SearchEngine {
+    // Index, trash, quota and query log figures in the Prometheus text format
+    pub async fn prometheus_metrics(&self) -> Result<String> {
+        let mut out = String::new();
+
+        let catalog = self.catalog().await?;
+        let counters = &catalog.counters;
+        family(&mut out, "search_engine_documents", "gauge", "Number of indexed documents.", &single(counters.total_documents as f64));
+        let by_extension: Vec<(String, f64)> = counters.per_extension.iter()
+            .map(|(extension, count)| (format!("{{extension=\"{}\"}}", escape_label(extension)), *count as f64))
+            .collect();
+        family(&mut out, "search_engine_documents_by_extension", "gauge", "Number of indexed documents per file extension.", &by_extension);
+        family(&mut out, "search_engine_index_bytes", "gauge", "Total size of indexed documents in bytes.", &single(counters.total_bytes as f64));
+        family(&mut out, "search_engine_index_dirty", "gauge", "1 if the index counters need re-verification.", &single(if counters.dirty { 1.0 } else { 0.0 }));
+        family(
+            &mut out,
+            "search_engine_last_scan_timestamp_seconds",
+            "gauge",
+            "Unix time of the last full scan of the search directory.",
+            &single(catalog.last_scanned.timestamp() as f64),
+        );
+
+        if let Some(quota) = self.quota {
+            family(&mut out, "search_engine_quota_bytes", "gauge", "Configured disk quota in bytes.", &single(quota.max_bytes as f64));
+        }
+
+        let trash = self.trash_entries().await?;
+        family(&mut out, "search_engine_trash_documents", "gauge", "Number of documents in the trash.", &single(trash.len() as f64));
+        family(
+            &mut out,
+            "search_engine_trash_bytes",
+            "gauge",
+            "Total size of documents in the trash in bytes.",
+            &single(trash.iter().map(|entry| entry.size).sum::<u64>() as f64),
+        );
+
+        let queries = querylog::read(&self.query_log_path(), None).await?;
+        family(&mut out, "search_engine_queries_total", "counter", "Searches recorded in the query log.", &single(queries.len() as f64));
+        family(
+            &mut out,
+            "search_engine_zero_result_queries_total",
+            "counter",
+            "Searches recorded in the query log that returned no results.",
+            &single(queries.iter().filter(|entry| entry.total == 0).count() as f64),
+        );
+        family(
+            &mut out,
+            "search_engine_query_latency_seconds",
+            "summary",
+            "Search latency recorded in the query log.",
+            &[
+                ("_sum".to_string(), queries.iter().map(|entry| entry.latency_us as f64).sum::<f64>() / 1_000_000.0),
+                ("_count".to_string(), queries.len() as f64),
+            ],
+        );
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,126 @@
+// Cleans up a query before it is parsed, so the CLI, the JSON-lines server, the FFI and
+// in-memory search all see the same text: control characters are dropped, whitespace is
+// trimmed and collapsed, the text is put in Unicode normalization form C, and empty or
+// overlong queries are refused.
+//
+// Indexed text and lines being matched are folded the same way by `fold_text`: lowercased and
+// in form C, so "café" written with a precomposed "é" and with "e" plus a combining accent
+// are the same word. With accent folding on, text is also put in compatibility form with its
+// combining marks dropped, so "Café" matches "cafe", "naïve" matches "naive" and the "ﬁ"
+// ligature matches "fi". Case-sensitive searches fold lines the same way but keep their case.
+
+use unicode_normalization::char::{decompose_canonical, decompose_compatible, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::{Result, SearchEngineError};
+use crate::options::MatchMode;
+
+// Longest query accepted, in characters after normalization
+pub const MAX_QUERY_CHARS: usize = 1024;
+
+// Returns the query as it will be searched. Runs of whitespace become one space except in
+// regex mode, where spacing is part of the pattern and only the ends are trimmed.
+pub fn normalize_query(query: &str, mode: MatchMode) -> Result<String> {
+    let mut normalized = String::with_capacity(query.len());
+    let mut pending_space = false;
+    for c in query.chars() {
+        if c.is_whitespace() {
+            if mode == MatchMode::Regex {
+                normalized.push(c);
+            } else {
+                pending_space = !normalized.is_empty();
+            }
+            continue;
+        }
+        if c.is_control() {
+            continue;
+        }
+        if pending_space {
+            normalized.push(' ');
+            pending_space = false;
+        }
+        normalized.push(c);
+    }
+    let normalized: String = match mode {
+        MatchMode::Regex => normalized.trim().nfc().collect(),
+        MatchMode::Substring | MatchMode::Word => normalized.nfc().collect(),
+    };
+
+    if normalized.is_empty() {
+        return Err(SearchEngineError::EmptyQuery("query must contain something besides whitespace and control characters".to_string()));
+    }
+    let length = normalized.chars().count();
+    if length > MAX_QUERY_CHARS {
+        return Err(SearchEngineError::InvalidQuery(format!("query is {} characters long; the limit is {}", length, MAX_QUERY_CHARS)));
+    }
+    Ok(normalized)
+}
+
+// How text is folded for matching
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Folding {
+    pub(crate) accents: bool,
+    pub(crate) keep_case: bool,
+}
+
+impl Folding {
+    // `text` normalized for matching, as described above
+    pub(crate) fn fold(self, text: &str) -> String {
+        if text.is_ascii() {
+            return if self.keep_case { text.to_string() } else { text.to_ascii_lowercase() };
+        }
+        match (self.accents, self.keep_case) {
+            (false, false) => text.to_lowercase().nfc().collect(),
+            (false, true) => text.nfc().collect(),
+            (true, false) => {
+                // Lowercasing can bring back marks, such as the dot of "İ"
+                let lower = text.nfkd().filter(|c| !is_combining_mark(*c)).collect::<String>().to_lowercase();
+                lower.chars().filter(|c| !is_combining_mark(*c)).nfc().collect()
+            }
+            (true, true) => text.nfkd().filter(|c| !is_combining_mark(*c)).nfc().collect(),
+        }
+    }
+
+    // Calls `f` with each character `c` folds to, decomposed rather than in form C so text can
+    // be folded one character at a time; a combining mark folds to nothing with accent folding
+    pub(crate) fn fold_char(self, c: char, mut f: impl FnMut(char)) {
+        if c.is_ascii() {
+            f(if self.keep_case { c } else { c.to_ascii_lowercase() });
+            return;
+        }
+        let mut emit = |c: char| {
+            let mut emit_one = |c: char| {
+                if !(self.accents && is_combining_mark(c)) {
+                    f(c);
+                }
+            };
+            if self.keep_case {
+                emit_one(c);
+            } else {
+                c.to_lowercase().for_each(emit_one);
+            }
+        };
+        if self.accents {
+            decompose_compatible(c, &mut emit);
+        } else {
+            decompose_canonical(c, &mut emit);
+        }
+    }
+}
+
+// `text` lowercased and normalized for matching, as described above
+pub fn fold_text(text: &str, fold_accents: bool) -> String {
+    Folding { accents: fold_accents, keep_case: false }.fold(text)
+}
+
+// Folded text, such as a query term, decomposed the way `fold_char` leaves each character
+pub(crate) fn decomposed(folded: &str) -> Vec<char> {
+    if folded.is_ascii() {
+        return folded.chars().collect();
+    }
+    folded.nfd().collect()
+}
+
+pub(crate) fn is_mark(c: char) -> bool {
+    is_combining_mark(c)
+}
@@ -0,0 +1,466 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::acl::permits;
+use crate::collation::Collation;
+use crate::error::{Result, SearchEngineError};
+use crate::fuzzy::MAX_FUZZY_DISTANCE;
+use crate::metadata::Metadata;
+use crate::partition::{partition_of, partition_span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    // Highest score first
+    #[default]
+    Relevance,
+    // By document path, then line number
+    Path,
+    // By file name, then document path and line number
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    // Case-insensitive substring match
+    #[default]
+    Substring,
+    // The query must match whole words
+    Word,
+    // The query is a case-insensitive regular expression
+    Regex,
+}
+
+// Restricts which documents are searched; empty filters match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchFilters {
+    // Relative path prefix such as "logs/"
+    pub path_prefix: Option<String>,
+    // Document extensions without the dot
+    pub extensions: Vec<String>,
+    // Only partitions overlapping this time range, inclusive; setting either bound leaves
+    // out documents outside partition directories
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // Only documents carrying every one of these tags; `tag:` terms in the query add to them
+    pub tags: Vec<String>,
+    // Only documents whose metadata has each of these values
+    pub metadata: Metadata,
+    // Only documents whose relative path contains each of these, ignoring case; `path:` terms
+    // in the query add to them
+    pub paths: Vec<String>,
+    // Only documents whose declared title, such as an HTML page's <title>, or file name
+    // contains each of these, ignoring case; `title:` terms in the query add to them
+    pub titles: Vec<String>,
+    // Only documents last modified at or after this time; `after:` in the query sets it
+    pub modified_after: Option<DateTime<Utc>>,
+    // Only documents last modified before this time; `before:` in the query sets it
+    pub modified_before: Option<DateTime<Utc>>,
+    // Only documents in one of these languages, ISO 639-1 codes such as "fr" or "und" for
+    // those whose language couldn't be told; `lang:` terms in the query add to them
+    pub languages: Vec<String>,
+    // Whom the search is made for: only documents without an access control list and those
+    // whose list names one of these, or every document for `acl::ALL_PRINCIPALS`. None
+    // only sees documents without a list.
+    pub principals: Option<Vec<String>>,
+}
+
+impl SearchFilters {
+    // `title` is the one the document declares, if it's known
+    pub(crate) fn matches(&self, relative_path: &str, title: Option<&str>) -> bool {
+        let prefix_ok = self.path_prefix.as_deref()
+            .map(|prefix| relative_path.starts_with(prefix.trim_start_matches("./")))
+            .unwrap_or(true);
+        let extension_ok = self.extensions.is_empty() || std::path::Path::new(relative_path).extension()
+            .map(|ext| {
+                let ext = ext.to_string_lossy();
+                self.extensions.iter().any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+            })
+            .unwrap_or(false);
+        prefix_ok && extension_ok && self.fields_match(relative_path, title) && self.time_range_matches(relative_path)
+    }
+
+    fn fields_match(&self, relative_path: &str, title: Option<&str>) -> bool {
+        if self.paths.is_empty() && self.titles.is_empty() {
+            return true;
+        }
+        let path = relative_path.to_lowercase();
+        let file_name = path.rsplit(['/', '\\']).next().unwrap_or(&path);
+        let title = title.map(str::to_lowercase);
+        self.paths.iter().all(|part| path.contains(&part.to_lowercase()))
+            && self.titles.iter().all(|part| {
+                let part = part.to_lowercase();
+                file_name.contains(&part) || title.as_deref().is_some_and(|title| title.contains(&part))
+            })
+    }
+
+    // Checked separately from `matches`, as tags, metadata, the language, the modification
+    // time and the access control list are attributes rather than part of the path
+    pub(crate) fn matches_attributes(
+        &self,
+        document_tags: &[String],
+        document_metadata: &Metadata,
+        language: Option<&str>,
+        modified: DateTime<Utc>,
+        acl: &[String],
+    ) -> bool {
+        if !permits(acl, self.principals.as_deref()) {
+            return false;
+        }
+        let modified_ok = self.modified_after.is_none_or(|after| modified >= after)
+            && self.modified_before.is_none_or(|before| modified < before);
+        let language_ok = self.languages.is_empty()
+            || language.is_some_and(|language| self.languages.iter().any(|wanted| wanted.eq_ignore_ascii_case(language)));
+        let tags_ok = self.tags.iter().all(|tag| {
+            let tag = tag.trim_start_matches("tag:");
+            document_tags.iter().any(|document_tag| document_tag.eq_ignore_ascii_case(tag))
+        });
+        modified_ok && language_ok && tags_ok && self.metadata.iter().all(|(key, value)| {
+            document_metadata.get(key).map(|document_value| document_value.matches(value)).unwrap_or(false)
+        })
+    }
+
+    fn time_range_matches(&self, relative_path: &str) -> bool {
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        let Some((start, end)) = partition_of(relative_path).and_then(partition_span) else {
+            return false;
+        };
+        self.from.map(|from| end > from).unwrap_or(true) && self.to.map(|to| start <= to).unwrap_or(true)
+    }
+}
+
+// Caps on the work one search may do; unset caps are unlimited. A search that reaches a cap
+// returns what it found so far with `SearchResponse::limited` set, except that a regex over
+// `max_regex_size` is rejected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchLimits {
+    // Documents searched, in index order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_documents: Option<usize>,
+    // Matching lines collected before ranking and pagination
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_matches: Option<usize>,
+    // Indexed terms one query term may expand to in substring mode or as a fuzzy term, per document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_expansions: Option<usize>,
+    // Compiled size of a regex query in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_regex_size: Option<usize>,
+}
+
+impl SearchLimits {
+    // The lower of each pair of caps, so a request can tighten the engine's limits but not lift them
+    pub fn tightest(self, other: SearchLimits) -> SearchLimits {
+        let lower = |a: Option<usize>, b: Option<usize>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        SearchLimits {
+            max_documents: lower(self.max_documents, other.max_documents),
+            max_matches: lower(self.max_matches, other.max_matches),
+            max_expansions: lower(self.max_expansions, other.max_expansions),
+            max_regex_size: lower(self.max_regex_size, other.max_regex_size),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let caps = [
+            ("max_documents", self.max_documents),
+            ("max_matches", self.max_matches),
+            ("max_expansions", self.max_expansions),
+            ("max_regex_size", self.max_regex_size),
+        ];
+        if let Some((name, _)) = caps.iter().find(|(_, cap)| *cap == Some(0)) {
+            return Err(SearchEngineError::InvalidInput(format!("{} must be greater than zero", name)));
+        }
+        Ok(())
+    }
+}
+
+// Everything `search` accepts besides the query; start from `Default` and override fields.
+// Serialized for the slow query log, with durations in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchOptions {
+    pub limit: usize,
+    pub offset: usize,
+    pub sort: SortOrder,
+    // Locale such as "de" or "sv" whose alphabet orders paths and file names when sorting by
+    // path or title, rather than comparing their bytes
+    pub collation: Option<String>,
+    pub filters: SearchFilters,
+    // Attach the byte ranges of each match to results
+    pub highlight: bool,
+    // Stop scanning after this long and return what was found so far
+    #[serde(rename = "timeout_ms", serialize_with = "serialize_millis")]
+    pub timeout: Option<Duration>,
+    pub mode: MatchMode,
+    // Match query terms in the case they are written in, without stemming or synonyms; regex
+    // patterns match case-sensitively too
+    pub case_sensitive: bool,
+    // Attach a per-phase timing breakdown to the response
+    pub profile: bool,
+    // Drop results from documents that are near-duplicates of a better-ranked one
+    pub collapse_duplicates: bool,
+    // Group the returned results into up to this many topics; 0 leaves them ungrouped.
+    // To cluster the top N results, ask for a limit of N.
+    pub clusters: usize,
+    // Return one result per document, carrying a summary of its best-matching sentences
+    pub summarize: bool,
+    // Search the documents pinned by this point-in-time reader instead of the live index
+    pub reader: Option<String>,
+    // Return at most this many lines from any one document
+    pub max_per_file: Option<usize>,
+    // Demote each result below others from its directory, so the first page spans more of the
+    // corpus; only when sorting by relevance
+    pub diversify: bool,
+    // Score multipliers for documents under relative path prefixes; the longest matching prefix applies
+    pub boosts: BTreeMap<String, f32>,
+    // Metadata fields to count the values of across matching documents
+    pub facets: Vec<String>,
+    // Caps on this search, on top of any the engine was built with
+    pub limits: SearchLimits,
+    // Match every query word within this many edits, as if each had a `~N` suffix; not for regex mode
+    pub fuzzy: Option<usize>,
+    // Only search the lines saved in this result set by an earlier search
+    pub within: Option<String>,
+    // Save every line this search matches as a result set kept this long after each use, so
+    // later searches can narrow it with `within`
+    #[serde(rename = "save_results_ms", serialize_with = "serialize_millis")]
+    pub save_results: Option<Duration>,
+}
+
+fn serialize_millis<S: serde::Serializer>(duration: &Option<Duration>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    duration.map(|duration| duration.num_milliseconds()).serialize(serializer)
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            limit: 10,
+            offset: 0,
+            sort: SortOrder::default(),
+            collation: None,
+            filters: SearchFilters::default(),
+            highlight: false,
+            timeout: None,
+            mode: MatchMode::default(),
+            case_sensitive: false,
+            profile: false,
+            collapse_duplicates: false,
+            clusters: 0,
+            summarize: false,
+            reader: None,
+            max_per_file: None,
+            diversify: false,
+            boosts: BTreeMap::new(),
+            facets: Vec::new(),
+            limits: SearchLimits::default(),
+            fuzzy: None,
+            within: None,
+            save_results: None,
+        }
+    }
+}
+
+impl SearchOptions {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.max_per_file == Some(0) {
+            return Err(SearchEngineError::InvalidInput("max_per_file must be greater than zero".to_string()));
+        }
+        if let Some((prefix, _)) = self.boosts.iter().find(|(_, boost)| !boost.is_finite() || **boost <= 0.0) {
+            return Err(SearchEngineError::InvalidInput(format!("boost for {:?} must be a positive number", prefix)));
+        }
+        if let Some(distance) = self.fuzzy.filter(|distance| *distance > MAX_FUZZY_DISTANCE) {
+            return Err(SearchEngineError::InvalidInput(format!("fuzzy distance {} is over the limit of {}", distance, MAX_FUZZY_DISTANCE)));
+        }
+        if let Some(locale) = &self.collation {
+            Collation::new(locale)?;
+        }
+        self.limits.validate()
+    }
+
+    pub(crate) fn boost(&self, relative_path: &str) -> f32 {
+        self.boosts.iter()
+            .filter(|(prefix, _)| relative_path.starts_with(prefix.trim_start_matches("./")))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, boost)| *boost)
+            .unwrap_or(1.0)
+    }
+}
+
+// Field filters given in a query rather than with its options, as `parse_query` reads them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryFields {
+    pub tags: Vec<String>,
+    pub paths: Vec<String>,
+    pub titles: Vec<String>,
+    pub languages: Vec<String>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl QueryFields {
+    // Narrows `filters` to the documents these fields allow as well
+    pub fn apply(self, filters: &mut SearchFilters) {
+        filters.tags.extend(self.tags);
+        filters.paths.extend(self.paths);
+        filters.titles.extend(self.titles);
+        filters.languages.extend(self.languages);
+        // The later of two lower bounds and the earlier of two upper bounds
+        filters.modified_after = filters.modified_after.max(self.after);
+        filters.modified_before = match (filters.modified_before, self.before) {
+            (Some(given), Some(query)) => Some(given.min(query)),
+            (given, query) => given.or(query),
+        };
+    }
+}
+
+// Parses a "PREFIX=FACTOR" boost such as "logs/errors/=2"
+pub fn parse_boost(text: &str) -> Result<(String, f32)> {
+    let invalid = || SearchEngineError::InvalidInput(format!("Invalid boost {:?}: expected PREFIX=FACTOR with a positive factor", text));
+    let (prefix, factor) = text.rsplit_once('=').ok_or_else(invalid)?;
+    let factor: f32 = factor.trim().parse().map_err(|_| invalid())?;
+    if !factor.is_finite() || factor <= 0.0 {
+        return Err(invalid());
+    }
+    Ok((prefix.to_string(), factor))
+}
+
+// A named set of search defaults from the config, so callers don't have to repeat them with
+// every query. Searches of a collection start from `options()` rather than
+// `SearchOptions::default()`, and anything given with the query still wins.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Collection {
+    // Documents under this relative path prefix
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    // How queries are matched: "substring", "word" or "regex"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MatchMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_per_file: Option<usize>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub diversify: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub boosts: BTreeMap<String, f32>,
+}
+
+impl Collection {
+    pub fn options(&self) -> SearchOptions {
+        let defaults = SearchOptions::default();
+        SearchOptions {
+            sort: self.sort.unwrap_or(defaults.sort),
+            collation: self.collation.clone(),
+            mode: self.mode.unwrap_or(defaults.mode),
+            filters: SearchFilters { path_prefix: self.path_prefix.clone(), ..SearchFilters::default() },
+            max_per_file: self.max_per_file,
+            diversify: self.diversify,
+            boosts: self.boosts.clone(),
+            ..defaults
+        }
+    }
+}
+
+// Search settings as the C ABI and the Python module take them, a JSON object such as
+// {"limit": 20, "mode": "word", "highlight": true} with durations written out
+#[cfg(any(feature = "ffi", feature = "python"))]
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct SearchRequest {
+    limit: usize,
+    offset: usize,
+    sort: SortOrder,
+    collation: Option<String>,
+    mode: MatchMode,
+    case_sensitive: bool,
+    filters: SearchFilters,
+    highlight: bool,
+    // Duration such as "500ms"
+    timeout: Option<String>,
+    collapse_duplicates: bool,
+    clusters: usize,
+    summarize: bool,
+    reader: Option<String>,
+    within: Option<String>,
+    // Keep-alive such as "5m"
+    save_results: Option<String>,
+    max_per_file: Option<usize>,
+    diversify: bool,
+    boosts: BTreeMap<String, f32>,
+    facets: Vec<String>,
+    fuzzy: Option<usize>,
+    limits: SearchLimits,
+}
+
+#[cfg(any(feature = "ffi", feature = "python"))]
+impl Default for SearchRequest {
+    fn default() -> Self {
+        let options = SearchOptions::default();
+        SearchRequest {
+            limit: options.limit,
+            offset: options.offset,
+            sort: options.sort,
+            collation: options.collation,
+            mode: options.mode,
+            case_sensitive: options.case_sensitive,
+            filters: options.filters,
+            highlight: options.highlight,
+            timeout: None,
+            collapse_duplicates: options.collapse_duplicates,
+            clusters: options.clusters,
+            summarize: options.summarize,
+            reader: options.reader,
+            within: options.within,
+            save_results: None,
+            max_per_file: options.max_per_file,
+            diversify: options.diversify,
+            boosts: options.boosts,
+            facets: options.facets,
+            fuzzy: options.fuzzy,
+            limits: options.limits,
+        }
+    }
+}
+
+#[cfg(any(feature = "ffi", feature = "python"))]
+impl SearchRequest {
+    pub(crate) fn into_options(self) -> Result<SearchOptions> {
+        Ok(SearchOptions {
+            limit: self.limit,
+            offset: self.offset,
+            sort: self.sort,
+            collation: self.collation,
+            filters: self.filters,
+            highlight: self.highlight,
+            timeout: self.timeout.as_deref().map(crate::ttl::parse_duration).transpose()?,
+            mode: self.mode,
+            case_sensitive: self.case_sensitive,
+            profile: false,
+            collapse_duplicates: self.collapse_duplicates,
+            clusters: self.clusters,
+            summarize: self.summarize,
+            reader: self.reader,
+            max_per_file: self.max_per_file,
+            diversify: self.diversify,
+            boosts: self.boosts,
+            facets: self.facets,
+            fuzzy: self.fuzzy,
+            limits: self.limits,
+            within: self.within,
+            save_results: self.save_results.as_deref().map(crate::ttl::parse_duration).transpose()?,
+        })
+    }
+}
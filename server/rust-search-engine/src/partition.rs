@@ -0,0 +1,119 @@
+// Time partitions for log-style data: documents of a partitioned search directory live
+// under a top-level directory named for the day or week of their date, such as
+// "2026-10-16" or "2026-W42". A time range then maps to whole directories, which searches
+// skip and maintenance drops or archives as a unit. Pure computation, so it builds for
+// wasm32 as well.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SearchEngineError};
+
+// Lines searched for a timestamp when a document is indexed without a date
+const DATE_SCAN_LINES: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum Partitioning {
+    // One directory per UTC day, "2026-10-16"
+    Daily,
+    // One directory per ISO week, "2026-W42"
+    Weekly,
+}
+
+impl Partitioning {
+    // Name of the partition directory holding documents dated `date`
+    pub fn key(self, date: DateTime<Utc>) -> String {
+        match self {
+            Partitioning::Daily => date.format("%Y-%m-%d").to_string(),
+            Partitioning::Weekly => {
+                let week = date.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+        }
+    }
+}
+
+// The half-open time span [start, end) a partition directory covers, or None if `key`
+// isn't a partition name
+pub fn partition_span(key: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (start, days) = if key.len() == 10 {
+        (NaiveDate::parse_from_str(key, "%Y-%m-%d").ok()?, 1)
+    } else {
+        let (year, week) = key.split_once("-W")?;
+        if year.len() != 4 || week.len() != 2 {
+            return None;
+        }
+        (NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?, 7)
+    };
+    let start = start.and_hms_opt(0, 0, 0)?.and_utc();
+    Some((start, start + Duration::days(days)))
+}
+
+// The partition a document is in: the first component of its relative path, when that
+// names a partition and isn't the file itself
+pub fn partition_of(relative_path: &str) -> Option<&str> {
+    let (first, _) = relative_path.split_once(['/', '\\'])?;
+    partition_span(first).map(|_| first)
+}
+
+// Accepts RFC 3339 timestamps and plain dates, which mean midnight UTC
+pub fn parse_date_time(text: &str) -> Result<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(date_time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date_time| date_time.and_utc())
+        .ok_or_else(|| SearchEngineError::InvalidInput(format!(
+            "Invalid date {:?}: expected YYYY-MM-DD or an RFC 3339 timestamp", text
+        )))
+}
+
+// Like `parse_date_time`, but a plain date means the midnight after it, so an exclusive
+// upper bound given as a date includes that whole day
+pub fn parse_end_date_time(text: &str) -> Result<DateTime<Utc>> {
+    let bound = parse_date_time(text)?;
+    match NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d") {
+        Ok(_) => Ok(bound + chrono::Duration::days(1)),
+        Err(_) => Ok(bound),
+    }
+}
+
+// The first timestamp near the start of a document, as log lines usually begin with one.
+// Timestamps without an offset are taken as UTC.
+pub fn detect_date(text: &str) -> Option<DateTime<Utc>> {
+    text.lines().take(DATE_SCAN_LINES).find_map(|line| {
+        let bytes = line.as_bytes();
+        (0..bytes.len().saturating_sub(9)).find_map(|i| {
+            let shape = bytes[i..i + 10].iter().enumerate()
+                .all(|(j, byte)| if j == 4 || j == 7 { *byte == b'-' } else { byte.is_ascii_digit() });
+            if !shape || (i > 0 && bytes[i - 1].is_ascii_digit()) {
+                return None;
+            }
+            let token = line[i..].split_whitespace().next().unwrap_or_default();
+            if let Ok(date_time) = DateTime::parse_from_rfc3339(token) {
+                return Some(date_time.with_timezone(&Utc));
+            }
+            if let Some(with_time) = line.get(i..i + 19) {
+                if let Ok(date_time) = NaiveDateTime::parse_from_str(&with_time.replacen('T', " ", 1), "%Y-%m-%d %H:%M:%S") {
+                    return Some(date_time.and_utc());
+                }
+            }
+            parse_date_time(&line[i..i + 10]).ok()
+        })
+    })
+}
+
+// Size of one partition of the search directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PartitionStats {
+    pub key: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub documents: usize,
+    pub size_bytes: u64,
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+use crate::alerts::{AlertMatch, SavedQuery};
+use crate::error::Result;
+use crate::matching::{Matcher, Presence};
+use crate::normalize::fold_text;
+use crate::options::{MatchMode, SearchLimits};
+use crate::SearchEngine;
+
+// Matching lines included per query; `total_matches` counts all of them
+const MAX_MATCHED_LINES: usize = 5;
+
+// A saved query that matched a percolated document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PercolateMatch {
+    pub alert_id: String,
+    pub query: String,
+    pub total_matches: usize,
+    pub matches: Vec<AlertMatch>,
+}
+
+// Three lowercase characters packed into one key; a char needs at most 21 bits
+fn trigram(chars: &[char]) -> u64 {
+    (chars[0] as u64) << 42 | (chars[1] as u64) << 21 | chars[2] as u64
+}
+
+fn trigrams(text: &str) -> impl Iterator<Item = u64> {
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len().saturating_sub(2)).map(move |i| trigram(&chars[i..i + 3]))
+}
+
+// Reverse index over saved queries. A substring or word query can only match text
+// containing every trigram of the query, so each query is filed under one of them and
+// a document is only checked against the queries filed under its own trigrams.
+pub(crate) struct Percolator<'a> {
+    queries: Vec<(&'a SavedQuery, Matcher)>,
+    by_trigram: HashMap<u64, Vec<usize>>,
+    // Regex, boolean and one- or two-character queries, checked against every document
+    unindexed: Vec<usize>,
+}
+
+impl<'a> Percolator<'a> {
+    pub(crate) fn new(saved: &'a [SavedQuery]) -> Self {
+        let mut percolator = Percolator {
+            queries: Vec::with_capacity(saved.len()),
+            by_trigram: HashMap::new(),
+            unindexed: Vec::new(),
+        };
+        for query in saved {
+            let matcher = match Matcher::new(&query.query, query.mode, None, &SearchLimits::default()) {
+                Ok(matcher) => matcher,
+                Err(e) => {
+                    warn!(alert = %query.id, error = %e, "Skipping invalid saved query");
+                    continue;
+                }
+            };
+            let index = percolator.queries.len();
+            // File under the trigram shared with the fewest queries so far, keeping buckets small
+            let key = match query.mode {
+                MatchMode::Regex => None,
+                _ if matcher.tree().is_some() => None,
+                _ => trigrams(matcher.query())
+                    .min_by_key(|key| percolator.by_trigram.get(key).map(Vec::len).unwrap_or(0)),
+            };
+            match key {
+                Some(key) => percolator.by_trigram.entry(key).or_default().push(index),
+                None => percolator.unindexed.push(index),
+            }
+            percolator.queries.push((query, matcher));
+        }
+        percolator
+    }
+
+    // Saved queries matching `text`, in the order they were saved. Path filters only
+    // apply when the document's relative path is known, and title filters also match the
+    // title it declares, if any.
+    pub(crate) fn matches(&self, text: &str, relative_path: Option<&str>, title: Option<&str>) -> Vec<PercolateMatch> {
+        let lower = fold_text(text, false);
+        let mut candidates: Vec<usize> = self.unindexed.clone();
+        if !self.by_trigram.is_empty() {
+            let present: HashSet<u64> = trigrams(&lower).collect();
+            for (key, queries) in &self.by_trigram {
+                if present.contains(key) {
+                    candidates.extend(queries);
+                }
+            }
+        }
+        candidates.sort_unstable();
+
+        let lines: Vec<(&str, String)> = text.lines().map(|line| (line, fold_text(line, false))).collect();
+        candidates.into_iter()
+            .filter_map(|index| {
+                let (query, matcher) = &self.queries[index];
+                if let Some(path) = relative_path {
+                    if !query.filters.matches(path, title) {
+                        return None;
+                    }
+                }
+                let mut total_matches = 0;
+                let mut matches = Vec::new();
+                let mut presence = Presence::new(matcher);
+                for (idx, (line, line_lower)) in lines.iter().enumerate() {
+                    presence.observe(matcher, line);
+                    if matcher.is_match(line, line_lower) {
+                        total_matches += 1;
+                        if matches.len() < MAX_MATCHED_LINES {
+                            matches.push(AlertMatch { line_number: idx + 1, content: line.to_string() });
+                        }
+                    }
+                }
+                (total_matches > 0 && presence.satisfies(matcher)).then(|| PercolateMatch {
+                    alert_id: query.id.clone(),
+                    query: query.query.clone(),
+                    total_matches,
+                    matches,
+                })
+            })
+            .collect()
+    }
+}
+
+impl SearchEngine {
+    // Which saved queries a document would match, without indexing it. `path`, relative
+    // to the search directory, lets queries with path filters match too.
+    pub async fn percolate(&self, text: &str, path: Option<&str>) -> Result<Vec<PercolateMatch>> {
+        let alerts = self.alerts().await?;
+        Ok(Percolator::new(&alerts).matches(text, path, None))
+    }
+}
@@ -0,0 +1,148 @@
+// Pinned results, read from a JSON file named by `"pins"` in `search-engine.json` that maps
+// queries to the documents, by path relative to the search directory or by document ID, whose
+// matches rank first for them:
+//
+//   { "annual report": ["reports/2024.txt", "reports/2023.txt"], "pricing": ["doc-17"] }
+//
+// A query is pinned when it is the same text ignoring case and runs of whitespace. Matches in
+// pinned documents come before every other result, in the order the documents are listed, and
+// keep their own order within a document. Pins only reorder: a pinned document still has to
+// match the query and pass the search's filters and access control list to appear.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::Catalog;
+use crate::error::{Result, SearchEngineError};
+use crate::SearchResult;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Pins {
+    queries: HashMap<String, Vec<String>>,
+}
+
+// Lowercased with runs of whitespace made single spaces, so "Annual  Report" is "annual report"
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+impl Pins {
+    pub fn parse(text: &str) -> Result<Self> {
+        let parsed: Pins = serde_json::from_str(text)
+            .map_err(|e| SearchEngineError::InvalidConfig(format!("Invalid pins file: {}", e)))?;
+        let mut queries = HashMap::new();
+        for (query, documents) in parsed.queries {
+            let key = normalize(&query);
+            if key.is_empty() {
+                return Err(SearchEngineError::InvalidConfig("Invalid pins file: a pinned query is empty".to_string()));
+            }
+            if queries.insert(key, documents).is_some() {
+                return Err(SearchEngineError::InvalidConfig(format!("Invalid pins file: {:?} is pinned twice", query)));
+            }
+        }
+        Ok(Pins { queries })
+    }
+
+    // Number of pinned queries
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    // The place of each document of `catalog` pinned for `query`, by absolute path;
+    // `relative_path` gives a document's path as the pins file names it
+    pub(crate) fn ranks(&self, query: &str, catalog: &Catalog, relative_path: impl Fn(&Path) -> String) -> HashMap<PathBuf, usize> {
+        let Some(pinned) = self.queries.get(&normalize(query)) else {
+            return HashMap::new();
+        };
+        let mut ranks = HashMap::new();
+        for doc in &catalog.documents {
+            let relative = relative_path(&doc.path);
+            let rank = pinned.iter().position(|document| {
+                document.trim_start_matches("./") == relative || doc.attributes.document_id.as_deref() == Some(document.as_str())
+            });
+            if let Some(rank) = rank {
+                ranks.insert(doc.path.clone(), rank);
+            }
+        }
+        ranks
+    }
+}
+
+// Moves the results of pinned documents to the front in the order they are pinned, leaving
+// the order of the rest and of each document's results as it was
+pub(crate) fn promote(results: &mut [SearchResult], ranks: &HashMap<PathBuf, usize>) {
+    if ranks.is_empty() {
+        return;
+    }
+    results.sort_by_key(|result| ranks.get(Path::new(&*result.path)).copied().unwrap_or(usize::MAX));
+}
+
+// Reads a pins file
+pub async fn load_pins(path: Option<&Path>) -> Result<Pins> {
+    let Some(path) = path else {
+        return Ok(Pins::default());
+    };
+    let text = tokio::fs::read_to_string(path).await.map_err(|e| {
+        SearchEngineError::InvalidConfig(format!("Failed to read pins from {:?}: {}", path, e))
+    })?;
+    Pins::parse(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::SearchOptions;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+    use crate::SearchEngine;
+
+    #[test]
+    fn queries_match_ignoring_case_and_spacing() {
+        let pins = Pins::parse(r#"{"Annual  Report": ["a.txt"]}"#).unwrap();
+        assert_eq!(pins.len(), 1);
+        assert!(pins.queries.contains_key("annual report"));
+        assert!(Pins::parse(r#"{"a b": ["x.txt"], "A  B": ["y.txt"]}"#).is_err());
+        assert!(Pins::parse(r#"{" ": ["x.txt"]}"#).is_err());
+        assert!(Pins::parse(r#"["x.txt"]"#).is_err());
+    }
+
+    async fn paths(engine: &SearchEngine, query: &str) -> Vec<String> {
+        let response = engine.search(query, &SearchOptions::default()).await.unwrap();
+        response.results.iter().map(|result| engine.relative_path(Path::new(&*result.path))).collect()
+    }
+
+    #[test]
+    fn pinned_documents_rank_first_and_reload_with_their_file() {
+        let dir = TestDir::new("pins");
+        let pins_file = dir.path().join("pins.json");
+        std::fs::write(&pins_file, r#"{"report": ["weak.txt"]}"#).unwrap();
+        block_on(async {
+            let engine = SearchEngine::builder(dir.path()).pins(Some(pins_file.clone())).build().await.unwrap();
+            index(&engine, vec![
+                DocumentInput::from_bytes("strong.txt", "report report report\n"),
+                DocumentInput::from_bytes("weak.txt", "a long line that mentions the report only once among many words\n"),
+                DocumentInput::from_bytes("other.txt", "nothing relevant\n"),
+            ]).await;
+            assert_eq!(paths(&engine, "report").await, ["weak.txt", "strong.txt"]);
+            assert_eq!(paths(&engine, "Report").await, ["weak.txt", "strong.txt"]);
+
+            // A pinned document that doesn't match isn't added
+            std::fs::write(&pins_file, r#"{"report": ["other.txt"]}"#).unwrap();
+            let version = engine.dictionary_version().version;
+            assert_eq!(engine.reload_pins().await.unwrap(), 1);
+            assert_eq!(engine.dictionary_version().version, version + 1);
+            assert_eq!(paths(&engine, "report").await, ["strong.txt", "weak.txt"]);
+
+            // A broken file leaves the pins in use
+            std::fs::write(&pins_file, "{").unwrap();
+            assert!(engine.reload_pins().await.is_err());
+            assert_eq!(engine.pins().len(), 1);
+        });
+    }
+}
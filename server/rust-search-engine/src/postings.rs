@@ -0,0 +1,989 @@
+// Postings tables: for each document, the lines every term, stem and n-gram occurs on, stored
+// under `.index/postings` and rebuilt when the document or its analysis settings change. A
+// search opens the tables of the documents the corpus dictionary lists for its terms and reads
+// only the candidate lines; regex queries still scan.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::warn;
+
+use crate::bm25::{CorpusStats, Scorer};
+use crate::catalog::Catalog;
+use crate::checksum;
+use crate::docstore::{fnv1a64, temp_path, trim_line_ending, FileStamp, POSTINGS_DIR};
+use crate::engine::{match_line_result, FileMatches};
+use crate::error::{Context, Result, SearchEngineError};
+use crate::matching::{analyzed_terms, index_terms, Matcher, Presence};
+use crate::normalize::{fold_text, Folding};
+use crate::profile::{Phase, Profiler};
+use crate::stem::{stem, StemLanguage};
+use crate::analysis::{self, analyzer_fingerprint, Analysis, SharedAnalyzers};
+use crate::storage::StorageBackend;
+use crate::termindex::{self, can_narrow, intersect, TermLookup};
+use crate::wildcard::{literal_prefix, wildcard_matches};
+use crate::SearchEngine;
+
+const POSTINGS_MAGIC: &[u8; 4] = b"POST";
+const POSTINGS_VERSION: u32 = 8;
+const HEADER_LEN: usize = 4 + 4 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8;
+// Candidate lines this close together are fetched with one read, up to `MAX_READ_BYTES`
+const MAX_GAP_LINES: usize = 32;
+const MAX_READ_BYTES: u64 = 4 * 1024 * 1024;
+// Entries between the seek points a lookup binary searches
+const SEEK_INTERVAL: usize = 32;
+// Corpus statistics aggregated from every document's table, for BM25
+pub const TERMS_FILE: &str = "terms.bin";
+const TERMS_MAGIC: &[u8; 4] = b"TERM";
+const TERMS_VERSION: u32 = 3;
+
+// Every run of `size` characters in `term`, none if it is shorter
+fn ngrams(term: &str, size: usize) -> impl Iterator<Item = &str> {
+    let bounds: Vec<usize> = term.char_indices().map(|(at, _)| at).chain([term.len()]).collect();
+    (0..bounds.len().saturating_sub(size)).map(move |i| &term[bounds[i]..bounds[i + size]])
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], at: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*at)?;
+        *at += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// Walks the entries of an encoded table: each is a term and the delta-encoded line numbers
+// it occurs on, both length-prefixed
+struct Entries<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let term_len = read_varint(self.bytes, &mut self.at)? as usize;
+        let term = self.bytes.get(self.at..self.at + term_len)?;
+        self.at += term_len;
+        let postings_len = read_varint(self.bytes, &mut self.at)? as usize;
+        let postings = self.bytes.get(self.at..self.at + postings_len)?;
+        self.at += postings_len;
+        Some((term, postings))
+    }
+}
+
+// Byte offsets of every `SEEK_INTERVAL`th entry, starting with the first
+fn seek_points(entries: &[u8]) -> Vec<usize> {
+    let mut walker = Entries { bytes: entries, at: 0 };
+    let mut points = Vec::new();
+    for index in 0.. {
+        let at = walker.at;
+        if walker.next().is_none() {
+            break;
+        }
+        if index % SEEK_INTERVAL == 0 {
+            points.push(at);
+        }
+    }
+    points
+}
+
+fn decode_lines(postings: &[u8], lines: &mut Vec<usize>) {
+    let (mut at, mut line) = (0, 0u64);
+    while let Some(delta) = read_varint(postings, &mut at) {
+        line += delta;
+        lines.push(line as usize);
+    }
+}
+
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.len() <= haystack.len() && haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+// Entries of `entries` from the last seek point before `term`, so every entry not less than it follows
+fn entries_from<'a>(entries: &'a [u8], seek: &[usize], term: &[u8]) -> Entries<'a> {
+    let term_at = |at: usize| Entries { bytes: entries, at }.next().map(|(entry, _)| entry);
+    let after = seek.partition_point(|&at| term_at(at).is_some_and(|entry| entry < term));
+    let at = after.checked_sub(1).map(|point| seek[point]).unwrap_or(0);
+    Entries { bytes: entries, at }
+}
+
+// Sorted lines of the entry for exactly `term`
+fn exact_lines(entries: Entries<'_>, term: &[u8]) -> Vec<usize> {
+    let mut lines = Vec::new();
+    for (entry, postings) in entries {
+        match entry.cmp(term) {
+            std::cmp::Ordering::Less => continue,
+            std::cmp::Ordering::Equal => decode_lines(postings, &mut lines),
+            std::cmp::Ordering::Greater => {}
+        }
+        break;
+    }
+    lines
+}
+
+fn encode_entries(terms: &BTreeMap<String, Vec<usize>>) -> Vec<u8> {
+    let mut entries = Vec::new();
+    let mut postings = Vec::new();
+    for (term, lines) in terms {
+        postings.clear();
+        let mut previous = 0;
+        for line in lines {
+            write_varint(&mut postings, (line - previous) as u64);
+            previous = *line;
+        }
+        write_varint(&mut entries, term.len() as u64);
+        entries.extend_from_slice(term.as_bytes());
+        write_varint(&mut entries, postings.len() as u64);
+        entries.extend_from_slice(&postings);
+    }
+    entries
+}
+
+// Every term of a document with the 1-based lines it occurs on, tied to the file version it
+// was built from, plus the line and term occurrence counts BM25 needs. Kept encoded;
+// lookups walk the terms without allocating.
+#[derive(Debug, Clone)]
+pub struct PostingsTable {
+    stamp: FileStamp,
+    term_count: usize,
+    line_count: u64,
+    token_count: u64,
+    // Entries sorted by term
+    entries: Vec<u8>,
+    // From `seek_points`; rebuilt when a table is loaded rather than stored
+    seek: Vec<usize>,
+    // Fingerprint of the stop words left out of it
+    stopwords: u64,
+    // Whether terms were folded without accents
+    folded: bool,
+    // Whether the table was built with stemming, listing the lines of each stem in `stems`
+    stemmed: bool,
+    // Language of the stems, English when not stemmed
+    stem_language: StemLanguage,
+    stem_count: usize,
+    stems: Vec<u8>,
+    stem_seek: Vec<usize>,
+    // Characters in each n-gram listed in `grams`, 0 without n-grams
+    gram_size: usize,
+    gram_count: usize,
+    grams: Vec<u8>,
+    gram_seek: Vec<usize>,
+    // Fingerprint of the analyzer that split lines into terms, 0 for none
+    analyzer: u64,
+}
+
+// A document's terms, and its stems and n-grams when the analysis asks for them, each with
+// the lines they occur on, as collected line by line
+#[derive(Default)]
+struct Collected {
+    terms: BTreeMap<String, Vec<usize>>,
+    stems: Option<BTreeMap<String, Vec<usize>>>,
+    grams: Option<BTreeMap<String, Vec<usize>>>,
+    line_count: u64,
+    // Term occurrences, stop words included
+    token_count: u64,
+}
+
+fn add_posting(map: &mut BTreeMap<String, Vec<usize>>, key: &str, line_number: usize) {
+    let lines = match map.get_mut(key) {
+        Some(lines) => lines,
+        None => map.entry(key.to_string()).or_default(),
+    };
+    if lines.last() != Some(&line_number) {
+        lines.push(line_number);
+    }
+}
+
+impl Collected {
+    fn new(analysis: &Analysis) -> Self {
+        Collected {
+            stems: analysis.stemming.then(BTreeMap::new),
+            grams: analysis.ngrams.map(|_| BTreeMap::new()),
+            ..Default::default()
+        }
+    }
+
+    fn add_line(&mut self, analysis: &Analysis, line: &str, line_number: usize) {
+        self.line_count += 1;
+        let folded = fold_text(line, analysis.fold_accents);
+        let Some(analyzer) = &analysis.analyzer else {
+            for term in index_terms(&folded) {
+                self.add_term(analysis, term, line_number);
+                if let Some(stems) = self.stems.as_mut().filter(|_| !analysis.stopwords.contains(term)) {
+                    add_posting(stems, &stem(term, analysis.stem_language), line_number);
+                }
+            }
+            return;
+        };
+        for term in analyzed_terms(analyzer.as_ref(), line, Folding { accents: analysis.fold_accents, keep_case: false }) {
+            self.add_term(analysis, &term, line_number);
+        }
+        if let Some(stems) = self.stems.as_mut() {
+            for word in index_terms(&folded).filter(|word| !analysis.stopwords.contains(word)) {
+                add_posting(stems, &stem(word, analysis.stem_language), line_number);
+            }
+        }
+    }
+
+    fn add_term(&mut self, analysis: &Analysis, term: &str, line_number: usize) {
+        self.token_count += 1;
+        if analysis.stopwords.contains(term) {
+            return;
+        }
+        add_posting(&mut self.terms, term, line_number);
+        if let (Some(grams), Some(size)) = (self.grams.as_mut(), analysis.ngrams) {
+            for gram in ngrams(term, size) {
+                add_posting(grams, gram, line_number);
+            }
+        }
+    }
+}
+
+impl PostingsTable {
+    fn from_collected(stamp: FileStamp, collected: Collected, analysis: &Analysis) -> Self {
+        let entries = encode_entries(&collected.terms);
+        let seek = seek_points(&entries);
+        let stemmed = collected.stems.is_some();
+        let stems = collected.stems.unwrap_or_default();
+        let stem_entries = encode_entries(&stems);
+        let grams = collected.grams.unwrap_or_default();
+        let gram_entries = encode_entries(&grams);
+        PostingsTable {
+            stamp,
+            term_count: collected.terms.len(),
+            line_count: collected.line_count,
+            token_count: collected.token_count,
+            entries,
+            seek,
+            stopwords: analysis.stopwords.fingerprint(),
+            folded: analysis.fold_accents,
+            stemmed,
+            stem_language: analysis.stem_language,
+            stem_count: stems.len(),
+            stem_seek: seek_points(&stem_entries),
+            stems: stem_entries,
+            gram_size: analysis.ngrams.unwrap_or(0),
+            gram_count: grams.len(),
+            gram_seek: seek_points(&gram_entries),
+            grams: gram_entries,
+            analyzer: analyzer_fingerprint(analysis.analyzer.as_deref()),
+        }
+    }
+
+    // From text already read, one line per line of the file
+    pub fn from_text(stamp: FileStamp, text: &str, analysis: &Analysis) -> Self {
+        let mut collected = Collected::new(analysis);
+        for (idx, line) in text.lines().enumerate() {
+            collected.add_line(analysis, line, idx + 1);
+        }
+        Self::from_collected(stamp, collected, analysis)
+    }
+
+    async fn build(storage: &dyn StorageBackend, file_path: &Path, stamp: FileStamp, analysis: &Analysis) -> Result<Self> {
+        let mut lines = BufReader::with_capacity(4 * 1024 * 1024, storage.open(file_path).await?).lines();
+        let mut collected = Collected::new(analysis);
+        let mut line_number = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_number += 1;
+            collected.add_line(analysis, &line, line_number);
+        }
+        Ok(Self::from_collected(stamp, collected, analysis))
+    }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp
+    }
+
+    pub fn term_count(&self) -> usize {
+        self.term_count
+    }
+
+    pub fn line_count(&self) -> u64 {
+        self.line_count
+    }
+
+    // Term occurrences across all lines
+    pub fn token_count(&self) -> u64 {
+        self.token_count
+    }
+
+    // Whether the table lists the lines of each stem
+    pub fn is_stemmed(&self) -> bool {
+        self.stemmed
+    }
+
+    // The distinct terms, sorted
+    pub fn terms(&self) -> impl Iterator<Item = &str> {
+        self.entries().filter_map(|(term, _)| std::str::from_utf8(term).ok())
+    }
+
+    // The distinct stems, sorted; none unless the table is stemmed
+    pub fn stems(&self) -> impl Iterator<Item = &str> {
+        Entries { bytes: &self.stems, at: 0 }.filter_map(|(stem, _)| std::str::from_utf8(stem).ok())
+    }
+
+    // The distinct terms, sorted, with the number of lines each occurs on
+    pub fn term_lines(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.entries().filter_map(|(term, postings)| {
+            // Each line is one varint, whose last byte has the high bit clear
+            let lines = postings.iter().filter(|byte| *byte & 0x80 == 0).count() as u64;
+            std::str::from_utf8(term).ok().map(|term| (term, lines))
+        })
+    }
+
+    fn entries(&self) -> Entries<'_> {
+        Entries { bytes: &self.entries, at: 0 }
+    }
+
+    fn entries_from(&self, term: &[u8]) -> Entries<'_> {
+        entries_from(&self.entries, &self.seek, term)
+    }
+
+    // Sorted lines on which `term` occurs, or a term containing it unless `exact`. Without
+    // n-grams long enough to look it up by, only the first `max_expansions` terms containing
+    // it count; `limited` is set if there were more.
+    fn lines_with(&self, term: &str, exact: bool, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize> {
+        if !exact {
+            if let Some(lines) = self.lines_with_grams(term) {
+                return lines;
+            }
+            let term = term.as_bytes();
+            return self.lines_with_any(|entry| contains_bytes(entry, term), max_expansions, limited);
+        }
+        let term = term.as_bytes();
+        exact_lines(self.entries_from(term), term)
+    }
+
+    // Sorted lines with every n-gram of `term`, which include all lines where a term contains
+    // it; None if the table has no n-grams or `term` is shorter than one
+    fn lines_with_grams(&self, term: &str) -> Option<Vec<usize>> {
+        if self.gram_size == 0 || term.chars().count() < self.gram_size {
+            return None;
+        }
+        let mut candidates: Option<Vec<usize>> = None;
+        for gram in ngrams(term, self.gram_size) {
+            let lines = exact_lines(entries_from(&self.grams, &self.gram_seek, gram.as_bytes()), gram.as_bytes());
+            let narrowed = match candidates {
+                Some(candidates) => intersect(&candidates, &lines),
+                None => lines,
+            };
+            if narrowed.is_empty() {
+                return Some(narrowed);
+            }
+            candidates = Some(narrowed);
+        }
+        candidates
+    }
+
+    // Sorted lines on which a word with the stem `stem` occurs; none unless the table is stemmed
+    fn lines_with_stem(&self, stem: &str) -> Vec<usize> {
+        let stem = stem.as_bytes();
+        exact_lines(entries_from(&self.stems, &self.stem_seek, stem), stem)
+    }
+
+    // Sorted lines on which any term `accept`s occurs, counting only the first `max_expansions`
+    // such terms as `lines_with` does
+    fn lines_with_any(&self, accept: impl Fn(&[u8]) -> bool, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize> {
+        let mut lines = Vec::new();
+        let mut matched = 0;
+        for (entry, postings) in self.entries() {
+            if accept(entry) {
+                if max_expansions.is_some_and(|max| matched >= max) {
+                    *limited = true;
+                    break;
+                }
+                decode_lines(postings, &mut lines);
+                matched += 1;
+            }
+        }
+        if matched > 1 {
+            lines.sort_unstable();
+            lines.dedup();
+        }
+        lines
+    }
+
+    // Sorted lines on which a term fitting the wildcard `pattern` occurs, counting only the
+    // first `max_expansions` such terms as `lines_with` does. Only the terms starting with the
+    // pattern's literal prefix are checked.
+    fn lines_with_pattern(&self, pattern: &str, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize> {
+        let prefix = literal_prefix(pattern).as_bytes();
+        let mut lines = Vec::new();
+        let mut matched = 0;
+        for (entry, postings) in self.entries_from(prefix) {
+            if entry < prefix {
+                continue;
+            }
+            if !entry.starts_with(prefix) {
+                break;
+            }
+            if std::str::from_utf8(entry).is_ok_and(|entry| wildcard_matches(pattern, entry)) {
+                if max_expansions.is_some_and(|max| matched >= max) {
+                    *limited = true;
+                    break;
+                }
+                decode_lines(postings, &mut lines);
+                matched += 1;
+            }
+        }
+        if matched > 1 {
+            lines.sort_unstable();
+            lines.dedup();
+        }
+        lines
+    }
+
+    // Lines the query can match on, as `termindex::candidates` finds them, or None when the
+    // table can't narrow them down, which includes a table built with other accent folding,
+    // stop words, stemming language or analyzer than the query is matched with
+    pub(crate) fn candidate_lines(&self, matcher: &Matcher) -> Option<(Vec<usize>, bool)> {
+        if matcher.folds_accents() != self.folded || matcher.stopwords().fingerprint() != self.stopwords
+            || analyzer_fingerprint(matcher.analyzer()) != self.analyzer
+            || (matcher.stemming() && matcher.stem_language() != self.stem_language)
+        {
+            return None;
+        }
+        termindex::candidates(self, matcher)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            HEADER_LEN + self.entries.len() + self.stems.len() + self.grams.len() + checksum::CHECKSUM_LEN,
+        );
+        bytes.extend_from_slice(POSTINGS_MAGIC);
+        bytes.extend_from_slice(&POSTINGS_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.size.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.modified_secs.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.modified_nanos.to_le_bytes());
+        bytes.extend_from_slice(&(self.term_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.line_count.to_le_bytes());
+        bytes.extend_from_slice(&self.token_count.to_le_bytes());
+        bytes.extend_from_slice(&self.stopwords.to_le_bytes());
+        let flags = self.stemmed as u64 | (self.folded as u64) << 1 | self.stem_language.code() << 8;
+        bytes.extend_from_slice(&flags.to_le_bytes());
+        bytes.extend_from_slice(&(self.stem_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.stems.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.gram_size as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.gram_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.analyzer.to_le_bytes());
+        bytes.extend_from_slice(&self.entries);
+        bytes.extend_from_slice(&self.stems);
+        bytes.extend_from_slice(&self.grams);
+        checksum::seal(&mut bytes);
+        bytes
+    }
+
+    fn decode(mut bytes: Vec<u8>) -> Result<Self> {
+        let payload_len = checksum::unseal(&bytes, "postings table")?.len();
+        bytes.truncate(payload_len);
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != POSTINGS_MAGIC {
+            return Err(SearchEngineError::IndexCorrupted("Invalid postings table".to_string()));
+        }
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != POSTINGS_VERSION {
+            return Err(SearchEngineError::IndexCorrupted(format!("Unsupported postings table version {}", version)));
+        }
+        let stamp = FileStamp {
+            size: read_u64(8),
+            modified_secs: read_u64(16) as i64,
+            modified_nanos: u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+        };
+        let term_count = read_u64(28) as usize;
+        let line_count = read_u64(36);
+        let token_count = read_u64(44);
+        let stopwords = read_u64(52);
+        let flags = read_u64(60);
+        let (stemmed, folded) = (flags & 1 != 0, flags & 2 != 0);
+        let stem_language = StemLanguage::from_code(flags >> 8)
+            .ok_or_else(|| SearchEngineError::IndexCorrupted("Unknown stemming language in postings table".to_string()))?;
+        let stem_count = read_u64(68) as usize;
+        let entries_len = read_u64(76) as usize;
+        let stems_len = read_u64(84) as usize;
+        let gram_size = read_u64(92) as usize;
+        let gram_count = read_u64(100) as usize;
+        let analyzer = read_u64(108);
+
+        let truncated = || SearchEngineError::IndexCorrupted("Truncated postings table".to_string());
+        let mut entries = bytes.split_off(HEADER_LEN);
+        if entries_len > entries.len() {
+            return Err(truncated());
+        }
+        let mut stems = entries.split_off(entries_len);
+        if stems_len > stems.len() {
+            return Err(truncated());
+        }
+        let grams = stems.split_off(stems_len);
+        for (section, count) in [(&entries, term_count), (&stems, stem_count), (&grams, gram_count)] {
+            let mut walker = Entries { bytes: section, at: 0 };
+            let walked = walker.by_ref().count();
+            if walked != count || walker.at != section.len() {
+                return Err(truncated());
+            }
+        }
+        let seek = seek_points(&entries);
+        let stem_seek = seek_points(&stems);
+        let gram_seek = seek_points(&grams);
+        Ok(PostingsTable {
+            stamp,
+            term_count,
+            line_count,
+            token_count,
+            entries,
+            seek,
+            stopwords,
+            folded,
+            stemmed,
+            stem_language,
+            stem_count,
+            stems,
+            stem_seek,
+            gram_size,
+            gram_count,
+            grams,
+            gram_seek,
+            analyzer,
+        })
+    }
+}
+
+impl TermLookup for PostingsTable {
+    fn with_term(&self, term: &str, exact: bool, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize> {
+        self.lines_with(term, exact, max_expansions, limited)
+    }
+
+    fn with_any(&self, accept: &dyn Fn(&str) -> bool, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize> {
+        self.lines_with_any(|entry| std::str::from_utf8(entry).is_ok_and(accept), max_expansions, limited)
+    }
+
+    fn with_pattern(&self, pattern: &str, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize> {
+        self.lines_with_pattern(pattern, max_expansions, limited)
+    }
+
+    fn with_stem(&self, stem: &str) -> Vec<usize> {
+        self.lines_with_stem(stem)
+    }
+
+    fn lists_stems(&self) -> bool {
+        self.stemmed
+    }
+}
+
+// Identifies the document versions in a catalog, whatever order they are listed in
+pub(crate) fn catalog_fingerprint(catalog: &Catalog) -> u64 {
+    catalog.documents.iter().fold(catalog.documents.len() as u64, |fingerprint, doc| {
+        let version = format!("{}\0{}\0{}", doc.path.to_string_lossy(), doc.size, doc.modified.timestamp_nanos_opt().unwrap_or_default());
+        fingerprint.wrapping_add(fnv1a64(version.as_bytes()))
+    })
+}
+
+fn encode_stats(fingerprint: u64, stats: &CorpusStats) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(TERMS_MAGIC);
+    bytes.extend_from_slice(&TERMS_VERSION.to_le_bytes());
+    for value in [fingerprint, stats.documents, stats.lines, stats.tokens] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    write_varint(&mut bytes, stats.document_frequency.len() as u64);
+    for (term, count) in &stats.document_frequency {
+        write_varint(&mut bytes, term.len() as u64);
+        bytes.extend_from_slice(term.as_bytes());
+        write_varint(&mut bytes, *count);
+    }
+    checksum::seal(&mut bytes);
+    bytes
+}
+
+// None if the file is unreadable, damaged or from another version
+fn decode_stats(bytes: &[u8]) -> Option<(u64, CorpusStats)> {
+    let bytes = checksum::unseal(bytes, "term statistics").ok()?;
+    if bytes.get(0..4)? != TERMS_MAGIC || u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?) != TERMS_VERSION {
+        return None;
+    }
+    let read_u64 = |at: usize| Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?));
+    let fingerprint = read_u64(8)?;
+    let mut stats = CorpusStats { documents: read_u64(16)?, lines: read_u64(24)?, tokens: read_u64(32)?, ..Default::default() };
+    let mut at = 40;
+    let terms = read_varint(bytes, &mut at)?;
+    for _ in 0..terms {
+        let len = read_varint(bytes, &mut at)? as usize;
+        let term = std::str::from_utf8(bytes.get(at..at + len)?).ok()?;
+        at += len;
+        let count = read_varint(bytes, &mut at)?;
+        stats.document_frequency.insert(term.to_string(), count);
+    }
+    (at == bytes.len()).then_some((fingerprint, stats))
+}
+
+// The tables used last, up to `capacity` of them, so searches repeating terms don't read and
+// decode them again; entries are checked against the document and analysis like stored tables
+#[derive(Debug, Default)]
+struct TableCache {
+    capacity: usize,
+    uses: u64,
+    tables: HashMap<PathBuf, (Arc<PostingsTable>, u64)>,
+}
+
+impl TableCache {
+    fn get(&mut self, file_path: &Path) -> Option<Arc<PostingsTable>> {
+        self.uses += 1;
+        let (table, used) = self.tables.get_mut(file_path)?;
+        *used = self.uses;
+        Some(table.clone())
+    }
+
+    fn insert(&mut self, file_path: &Path, table: Arc<PostingsTable>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.uses += 1;
+        self.tables.insert(file_path.to_path_buf(), (table, self.uses));
+        if self.tables.len() > self.capacity {
+            let oldest = self.tables.iter().min_by_key(|(_, (_, used))| *used).map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                self.tables.remove(&oldest);
+            }
+        }
+    }
+}
+
+// Persists per-document postings tables under `<search_path>/.index/postings`
+#[derive(Debug, Clone)]
+pub struct PostingsStore {
+    root: PathBuf,
+    // Rebuilt tables are kept in memory only
+    read_only: bool,
+    // Shared by clones of the store
+    cache: Arc<Mutex<TableCache>>,
+    // Whether each document's table lists the lines of each stem and which stop words it
+    // leaves out; tables built otherwise are rebuilt
+    analyzers: SharedAnalyzers,
+    dir: PathBuf,
+    terms_path: PathBuf,
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl PostingsStore {
+    pub fn new(root: &Path, index_dir: &Path, storage: Arc<dyn StorageBackend>) -> Self {
+        PostingsStore {
+            root: root.to_path_buf(),
+            read_only: false,
+            cache: Arc::default(),
+            analyzers: SharedAnalyzers::default(),
+            dir: index_dir.join(POSTINGS_DIR),
+            terms_path: index_dir.join(TERMS_FILE),
+            storage,
+        }
+    }
+
+    pub(crate) fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub(crate) fn analyzers(mut self, analyzers: SharedAnalyzers) -> Self {
+        self.analyzers = analyzers;
+        self
+    }
+
+    // Keeps up to `tables` tables in memory; none by default
+    pub(crate) fn cache_size(self, tables: usize) -> Self {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).capacity = tables;
+        self
+    }
+
+    fn cached(&self, file_path: &Path, stamp: FileStamp) -> Option<Arc<PostingsTable>> {
+        let table = self.cache.lock().unwrap_or_else(PoisonError::into_inner).get(file_path)?;
+        self.is_current(file_path, &table, stamp).then_some(table)
+    }
+
+    fn cache(&self, file_path: &Path, table: PostingsTable) -> Arc<PostingsTable> {
+        let table = Arc::new(table);
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).insert(file_path, table.clone());
+        table
+    }
+
+    // Builds the table of a document's text already read with the document's stemming
+    // setting and stop words
+    pub(crate) fn table_from_text(&self, file_path: &Path, stamp: FileStamp, text: &str) -> PostingsTable {
+        let analyzers = analysis::current(&self.analyzers);
+        let analysis = analyzers.for_file(file_path);
+        PostingsTable::from_text(stamp, text, analysis)
+    }
+
+    // A stored table is current if it was built from this file version with the document's
+    // accent folding, stemming and n-gram settings and stop words
+    fn is_current(&self, file_path: &Path, table: &PostingsTable, stamp: FileStamp) -> bool {
+        let analyzers = analysis::current(&self.analyzers);
+        let analysis = analyzers.for_file(file_path);
+        table.stamp == stamp && table.folded == analysis.fold_accents && table.stemmed == analysis.stemming
+            && (!table.stemmed || table.stem_language == analysis.stem_language)
+            && table.gram_size == analysis.ngrams.unwrap_or(0) && table.stopwords == analysis.stopwords.fingerprint()
+            && table.analyzer == analyzer_fingerprint(analysis.analyzer.as_deref())
+    }
+
+    pub fn postings_path(&self, file_path: &Path) -> PathBuf {
+        let relative = file_path.strip_prefix(&self.root).unwrap_or(file_path);
+        let key = fnv1a64(relative.to_string_lossy().as_bytes());
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+
+    // The stored table if it is current, without rebuilding it
+    pub async fn stored_postings(&self, file_path: &Path) -> Result<Option<Arc<PostingsTable>>> {
+        let object = self.storage.stat(file_path).await?
+            .ok_or_else(|| SearchEngineError::DocumentNotFound(file_path.to_string_lossy().to_string()))?;
+        let stamp = FileStamp::from_object(&object);
+        if let Some(cached) = self.cached(file_path, stamp) {
+            return Ok(Some(cached));
+        }
+        Ok(self.load(file_path).await.ok().flatten()
+            .filter(|stored| self.is_current(file_path, stored, stamp))
+            .map(|stored| self.cache(file_path, stored)))
+    }
+
+    async fn load(&self, file_path: &Path) -> Result<Option<PostingsTable>> {
+        match async_fs::read(self.postings_path(file_path)).await {
+            Ok(bytes) => Ok(Some(PostingsTable::decode(bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read postings table"),
+        }
+    }
+
+    pub async fn save(&self, file_path: &Path, table: &PostingsTable) -> Result<()> {
+        async_fs::create_dir_all(&self.dir).await
+            .context("Failed to create postings directory")?;
+        let target = self.postings_path(file_path);
+        let temp = temp_path(&target);
+        let mut file = async_fs::File::create(&temp).await?;
+        file.write_all(&table.encode()).await?;
+        file.flush().await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist postings table")?;
+        Ok(())
+    }
+
+    async fn load_stats(&self, fingerprint: u64) -> Option<CorpusStats> {
+        let bytes = async_fs::read(&self.terms_path).await.ok()?;
+        decode_stats(&bytes).filter(|(stored, _)| *stored == fingerprint).map(|(_, stats)| stats)
+    }
+
+    async fn save_stats(&self, fingerprint: u64, stats: &CorpusStats) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        if let Some(parent) = self.terms_path.parent() {
+            async_fs::create_dir_all(parent).await
+                .context("Failed to create index directory")?;
+        }
+        let temp = temp_path(&self.terms_path);
+        async_fs::write(&temp, encode_stats(fingerprint, stats)).await?;
+        async_fs::rename(&temp, &self.terms_path).await
+            .context("Failed to persist term statistics")?;
+        Ok(())
+    }
+
+    // Returns the postings of a document, rebuilding the table if it is missing,
+    // unreadable, or was built from a different file version, stemming setting or stop words
+    pub async fn postings(&self, file_path: &Path) -> Result<Arc<PostingsTable>> {
+        Ok(self.ensure_postings(file_path).await?.0)
+    }
+
+    // Like `postings`, also reporting whether the table had to be rebuilt
+    pub async fn ensure_postings(&self, file_path: &Path) -> Result<(Arc<PostingsTable>, bool)> {
+        let object = self.storage.stat(file_path).await?
+            .ok_or_else(|| SearchEngineError::DocumentNotFound(file_path.to_string_lossy().to_string()))?;
+        let stamp = FileStamp::from_object(&object);
+        if let Some(cached) = self.cached(file_path, stamp) {
+            return Ok((cached, false));
+        }
+
+        match self.load(file_path).await {
+            Ok(Some(stored)) if self.is_current(file_path, &stored, stamp) => return Ok((self.cache(file_path, stored), false)),
+            Err(e) => warn!(path = ?file_path, error = %e, "Rebuilding unreadable postings table"),
+            _ => {}
+        }
+
+        let analysis = analysis::current(&self.analyzers).for_file(file_path).clone();
+        let table = PostingsTable::build(self.storage.as_ref(), file_path, stamp, &analysis).await?;
+        if !self.read_only {
+            self.save(file_path, &table).await?;
+        }
+        Ok((self.cache(file_path, table), true))
+    }
+
+    // Checks that a stored table exists, decodes cleanly, and matches the file on disk, the
+    // stemming setting and the stop words
+    pub async fn verify(&self, file_path: &Path) -> Result<()> {
+        let object = self.storage.stat(file_path).await?
+            .ok_or_else(|| SearchEngineError::DocumentNotFound(file_path.to_string_lossy().to_string()))?;
+        match self.load(file_path).await? {
+            Some(stored) if self.is_current(file_path, &stored, FileStamp::from_object(&object)) => Ok(()),
+            Some(_) => Err(SearchEngineError::IndexCorrupted("Postings table is stale".to_string())),
+            None => Err(SearchEngineError::IndexCorrupted("Postings table is missing".to_string())),
+        }
+    }
+
+    // Checks that the saved term statistics, if any, are intact
+    pub(crate) async fn verify_stats(&self) -> Result<()> {
+        match async_fs::read(&self.terms_path).await {
+            Ok(bytes) => checksum::unseal(&bytes, "term statistics").map(|_| ()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to read term statistics"),
+        }
+    }
+
+    pub async fn remove(&self, file_path: &Path) -> Result<()> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).tables.remove(file_path);
+        match async_fs::remove_file(self.postings_path(file_path)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to remove postings table"),
+        }
+    }
+}
+
+impl SearchEngine {
+    // BM25 statistics of the documents in `catalog`, aggregated from their postings tables and
+    // kept in memory and in `.index/terms.bin` until the documents change. Statistics for a
+    // point-in-time reader's catalog are neither cached nor saved unless `persist`. Tables
+    // leave out stop words, so other stop words or analysis rules give other statistics.
+    pub(crate) async fn corpus_stats(&self, catalog: &Catalog, persist: bool) -> Arc<CorpusStats> {
+        let fingerprint = catalog_fingerprint(catalog).wrapping_add(analysis::current(&self.analyzers).fingerprint());
+        let cached = self.shared.corpus_stats.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some((_, stats)) = cached.filter(|(cached, _)| *cached == fingerprint) {
+            return stats;
+        }
+
+        let stats = match self.postings_store.load_stats(fingerprint).await {
+            Some(stats) => stats,
+            None => {
+                let mut stats = CorpusStats::default();
+                for doc in &catalog.documents {
+                    match self.postings_store.postings(&doc.path).await {
+                        Ok(table) => stats.add_document(table.line_count(), table.token_count(), table.terms()),
+                        Err(e) => warn!(path = ?doc.path, error = %e, "Failed to read postings for term statistics"),
+                    }
+                }
+                if persist {
+                    if let Err(e) = self.postings_store.save_stats(fingerprint, &stats).await {
+                        warn!(error = %e, "Failed to save term statistics");
+                    }
+                }
+                stats
+            }
+        };
+        let stats = Arc::new(stats);
+        if persist {
+            *self.shared.corpus_stats.lock().unwrap_or_else(PoisonError::into_inner) = Some((fingerprint, stats.clone()));
+        }
+        stats
+    }
+
+    // Searches a document through its postings, reading only the lines that can match.
+    // None if the index can't narrow down the query, in which case the file is scanned.
+    pub(crate) async fn search_in_file_indexed(
+        &self,
+        file_path: &Path,
+        scorer: &Scorer<'_>,
+        highlight: bool,
+        max_results: usize,
+        deadline: Option<std::time::Instant>,
+        profiler: &mut Profiler,
+    ) -> Result<Option<FileMatches>> {
+        let matcher = scorer.matcher();
+        if !can_narrow(matcher) {
+            return Ok(None);
+        }
+        let started = profiler.start();
+        // A read-only engine can't keep a rebuilt table, so building one costs more than scanning
+        let table = if self.read_only {
+            self.postings_store.stored_postings(file_path).await?
+        } else {
+            Some(self.postings_store.postings(file_path).await?)
+        };
+        let candidates = table.and_then(|table| table.candidate_lines(matcher));
+        profiler.record(Phase::CandidateSelection, started);
+        let Some((candidates, limited)) = candidates else {
+            return Ok(None);
+        };
+        profiler.count_indexed_file();
+
+        let mut results = Vec::new();
+        if candidates.is_empty() {
+            return Ok(Some(FileMatches { results, timed_out: false, limited }));
+        }
+        let mut presence = Presence::new(matcher);
+        let mut timed_out = false;
+        let file_path_str: Arc<str> = Arc::from(file_path.to_string_lossy());
+        let filename = file_path.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+
+        let started = profiler.start();
+        let offsets = self.doc_store.offsets(file_path).await?;
+        profiler.record(Phase::Io, started);
+        let mut next = 0;
+        'blocks: while next < candidates.len() {
+            if deadline.map(|deadline| std::time::Instant::now() >= deadline).unwrap_or(false) {
+                timed_out = true;
+                break;
+            }
+            // Nearby candidates share one read
+            let Some((from, mut to)) = offsets.line_range(candidates[next]) else {
+                break;
+            };
+            let mut last = next;
+            while let Some(&line_number) = candidates.get(last + 1) {
+                match offsets.line_range(line_number) {
+                    Some((_, end)) if line_number - candidates[last] <= MAX_GAP_LINES && end - from <= MAX_READ_BYTES => {
+                        to = end;
+                        last += 1;
+                    }
+                    _ => break,
+                }
+            }
+            let started = profiler.start();
+            let block = self.storage.read_range(file_path, from, to).await?;
+            profiler.record(Phase::Io, started);
+
+            for &line_number in &candidates[next..=last] {
+                let Some((start, end)) = offsets.line_range(line_number) else {
+                    continue;
+                };
+                let line = trim_line_ending(&block[(start - from) as usize..(end - from) as usize]);
+                presence.observe(matcher, &line);
+                if let Some(result) = match_line_result(scorer, line, line_number, highlight, &file_path_str, &filename, profiler) {
+                    if results.len() < max_results {
+                        results.push(result);
+                    }
+                }
+                if results.len() >= max_results && presence.is_complete() {
+                    break 'blocks;
+                }
+            }
+            next = last + 1;
+        }
+        if !presence.satisfies(matcher) {
+            results.clear();
+        }
+        Ok(Some(FileMatches { results, timed_out, limited }))
+    }
+}
@@ -0,0 +1,120 @@
+// Query timings. The profiler reads the clock, which wasm32 lacks, so only the engine has it.
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "engine")]
+use std::time::{Duration, Instant};
+
+// Per-phase timing breakdown for a single query, in microseconds
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QueryProfile {
+    pub candidate_selection_us: u64,
+    pub io_us: u64,
+    pub matching_us: u64,
+    pub scoring_us: u64,
+    pub sorting_us: u64,
+    pub serialization_us: u64,
+    pub total_us: u64,
+    pub files_scanned: usize,
+    // Files searched through the inverted index rather than read in full
+    pub files_from_index: usize,
+    pub lines_scanned: u64,
+}
+
+// Accumulates phase timings when profiling is enabled and costs nothing otherwise
+#[cfg(feature = "engine")]
+pub struct Profiler {
+    profile: Option<QueryProfile>,
+    started: Instant,
+}
+
+#[cfg(feature = "engine")]
+#[derive(Clone, Copy)]
+pub enum Phase {
+    CandidateSelection,
+    Io,
+    Matching,
+    Scoring,
+    Sorting,
+    Serialization,
+}
+
+#[cfg(feature = "engine")]
+impl Profiler {
+    pub fn new(enabled: bool) -> Self {
+        Profiler {
+            profile: enabled.then(QueryProfile::default),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    pub fn start(&self) -> Option<Instant> {
+        self.profile.as_ref().map(|_| Instant::now())
+    }
+
+    pub fn record(&mut self, phase: Phase, started: Option<Instant>) {
+        if let (Some(profile), Some(started)) = (self.profile.as_mut(), started) {
+            let elapsed = micros(started.elapsed());
+            let slot = match phase {
+                Phase::CandidateSelection => &mut profile.candidate_selection_us,
+                Phase::Io => &mut profile.io_us,
+                Phase::Matching => &mut profile.matching_us,
+                Phase::Scoring => &mut profile.scoring_us,
+                Phase::Sorting => &mut profile.sorting_us,
+                Phase::Serialization => &mut profile.serialization_us,
+            };
+            *slot += elapsed;
+        }
+    }
+
+    pub fn count_file(&mut self) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.files_scanned += 1;
+        }
+    }
+
+    pub fn count_indexed_file(&mut self) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.files_from_index += 1;
+        }
+    }
+
+    pub fn count_line(&mut self) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.lines_scanned += 1;
+        }
+    }
+
+    // Adds the timings and counts of a profiler that ran alongside this one, such as one
+    // searching another document at the same time; phases may then add up to more than the total
+    pub fn merge(&mut self, other: Profiler) {
+        if let (Some(profile), Some(other)) = (self.profile.as_mut(), other.profile) {
+            profile.candidate_selection_us += other.candidate_selection_us;
+            profile.io_us += other.io_us;
+            profile.matching_us += other.matching_us;
+            profile.scoring_us += other.scoring_us;
+            profile.sorting_us += other.sorting_us;
+            profile.serialization_us += other.serialization_us;
+            profile.files_scanned += other.files_scanned;
+            profile.files_from_index += other.files_from_index;
+            profile.lines_scanned += other.lines_scanned;
+        }
+    }
+
+    pub fn finish(self) -> Option<QueryProfile> {
+        let started = self.started;
+        self.profile.map(|mut profile| {
+            profile.total_us = micros(started.elapsed());
+            profile
+        })
+    }
+}
+
+#[cfg(feature = "engine")]
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros().min(u64::MAX as u128) as u64
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+// A snapshot of how far a long operation has got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Progress {
+    // Operation name such as "index" or "optimize"
+    pub operation: String,
+    pub completed: u64,
+    // None when the size of the job isn't known up front, as for streams
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    // The document just processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+}
+
+// Receives progress from bulk indexing, optimize, verify and backup, once per
+// document. Called on the task running the operation, so it should return quickly;
+// closures can forward to a channel, e.g. `move |p: &Progress| { tx.send(p.clone()).ok(); }`.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, progress: &Progress);
+}
+
+impl<F> ProgressReporter for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn report(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+// Discards progress, for callers that don't ask for it
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn report(&self, _progress: &Progress) {}
+}
+
+// Counts the documents of one operation and forwards each step to a reporter
+pub(crate) struct Tracker<'a> {
+    reporter: &'a dyn ProgressReporter,
+    operation: &'static str,
+    total: Option<u64>,
+    completed: u64,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(reporter: &'a dyn ProgressReporter, operation: &'static str, total: Option<usize>) -> Self {
+        Tracker {
+            reporter,
+            operation,
+            total: total.map(|total| total as u64),
+            completed: 0,
+        }
+    }
+
+    pub(crate) fn advance(&mut self, item: impl Into<String>) {
+        self.completed += 1;
+        self.reporter.report(&Progress {
+            operation: self.operation.to_string(),
+            completed: self.completed,
+            total: self.total,
+            item: Some(item.into()),
+        });
+    }
+}
@@ -0,0 +1,52 @@
+// Proximity queries: `tokio NEAR/5 runtime` matches lines where the two words occur, in
+// either order, at most 5 words apart, counting adjacent words as 1 apart. Bare `NEAR`
+// allows `DEFAULT_NEAR_DISTANCE`. Positions are word positions within a line, except that a
+// run of Chinese, Japanese or Korean text takes one position per character.
+
+// Distance allowed by `NEAR` without `/N`
+pub const DEFAULT_NEAR_DISTANCE: usize = 10;
+// Largest N accepted in `NEAR/N`; further apart than this the words hardly relate
+pub const MAX_NEAR_DISTANCE: usize = 100;
+
+// (left, right, gap) for the occurrences in `left` and `right`, each its first and last
+// position, that are at most `distance` positions apart in either order, by their indexes.
+// An occurrence right after another is 1 apart; overlapping ones aren't near each other.
+pub(crate) fn near_occurrences(left: &[[usize; 2]], right: &[[usize; 2]], distance: usize) -> Vec<(usize, usize, usize)> {
+    let mut pairs = Vec::new();
+    for (at, [first, last]) in left.iter().enumerate() {
+        for (other, [other_first, other_last]) in right.iter().enumerate() {
+            let gap = if other_first > last {
+                other_first - last
+            } else if first > other_last {
+                first - other_last
+            } else {
+                continue;
+            };
+            if gap <= distance {
+                pairs.push((at, other, gap));
+            }
+        }
+    }
+    pairs
+}
+
+// Occurrences of a left word with a right one close by, from `near_occurrences`, each
+// counting 1/n for the nearest right one n positions away, so closer pairs weigh more
+pub(crate) fn nearest_frequency(pairs: &[(usize, usize, usize)]) -> f32 {
+    let mut nearest: Vec<(usize, usize)> = Vec::new();
+    for (at, _, gap) in pairs {
+        match nearest.last_mut() {
+            Some((last, best)) if last == at => *best = (*best).min(*gap),
+            _ => nearest.push((*at, *gap)),
+        }
+    }
+    nearest.iter().map(|(_, gap)| 1.0 / *gap as f32).sum()
+}
+
+// `nearest_frequency` of `left` and `right` among `words`
+pub(crate) fn proximity_frequency<S: AsRef<str>>(words: &[S], left: &str, right: &str, distance: usize) -> f32 {
+    let at = |wanted: &str| -> Vec<[usize; 2]> {
+        words.iter().enumerate().filter(|(_, word)| word.as_ref() == wanted).map(|(at, _)| [at, at]).collect()
+    };
+    nearest_frequency(&near_occurrences(&at(left), &at(right), distance))
+}
@@ -0,0 +1,105 @@
+// Python module `search_engine`, for feeding corpora and querying from notebooks and asyncio
+// applications:
+//
+//     from search_engine import SearchEngine
+//     engine = SearchEngine("corpus")
+//     await engine.index("notes/meeting.txt", metadata={"team": "sales"})
+//     response = await engine.search("quarterly report", limit=20, highlight=True)
+//
+// `search` and `index` return awaitables that run on a tokio runtime shared by the module,
+// so the event loop stays free while they do. Search options are the keyword arguments the
+// C ABI takes as JSON; responses come back as dicts. Failures raise `SearchEngineError`.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::metadata::Metadata;
+use crate::options::SearchRequest;
+use crate::SearchEngineBuilder;
+
+create_exception!(search_engine, SearchEngineError, PyException);
+
+fn raise(error: crate::error::SearchEngineError) -> PyErr {
+    SearchEngineError::new_err(error.to_string())
+}
+
+// A value serialized to JSON and read back by Python's json module, so it arrives as dicts,
+// lists and plain values
+fn to_python<T: serde::Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(|e| raise(e.into()))?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+fn from_python<T: serde::de::DeserializeOwned>(py: Python<'_>, value: &Bound<'_, PyAny>, name: &str) -> PyResult<T> {
+    let json: String = py.import("json")?.call_method1("dumps", (value,))?.extract()?;
+    serde_json::from_str(&json).map_err(|e| SearchEngineError::new_err(format!("{}: {}", name, e)))
+}
+
+// An engine over a search directory; safe to share between threads and event loops
+#[pyclass(name = "SearchEngine", module = "search_engine", frozen)]
+struct PySearchEngine {
+    inner: Arc<crate::SearchEngine>,
+}
+
+#[pymethods]
+impl PySearchEngine {
+    // Opens the search directory, creating it if needed; with read_only nothing is written
+    // under it and index() fails
+    #[new]
+    #[pyo3(signature = (search_path, read_only = false))]
+    fn new(py: Python<'_>, search_path: &str, read_only: bool) -> PyResult<Self> {
+        let builder = SearchEngineBuilder::new(search_path).read_only(read_only);
+        let runtime = pyo3_async_runtimes::tokio::get_runtime();
+        let mut engine = py.allow_threads(|| runtime.block_on(builder.build())).map_err(raise)?;
+        engine.set_actor("python");
+        Ok(PySearchEngine { inner: Arc::new(engine) })
+    }
+
+    // Awaits the search response as a dict. The keyword arguments are limit, offset, sort,
+    // collation, mode, case_sensitive, filters, highlight, timeout, collapse_duplicates,
+    // clusters, summarize, reader, within, save_results, max_per_file, diversify, boosts,
+    // facets, fuzzy and limits, as `engine_search_json` takes them.
+    #[pyo3(signature = (query, **options))]
+    fn search<'py>(&self, py: Python<'py>, query: String, options: Option<&Bound<'py, PyDict>>) -> PyResult<Bound<'py, PyAny>> {
+        let request: SearchRequest = match options {
+            Some(options) => from_python(py, options.as_any(), "options")?,
+            None => SearchRequest::default(),
+        };
+        let options = request.into_options().map_err(raise)?;
+        let engine = Arc::clone(&self.inner);
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let response = engine.search(&query, &options).await.map_err(raise)?;
+            Python::with_gil(|py| to_python(py, &response))
+        })
+    }
+
+    // Awaits indexing a file, copying it into the search directory if needed, and returns
+    // the indexed document as a dict. The text of a PDF, Word, OpenDocument or HTML file is
+    // copied instead. metadata, a dict of field names to strings or numbers, replaces the
+    // document's metadata when given.
+    #[pyo3(signature = (path, metadata = None))]
+    fn index<'py>(&self, py: Python<'py>, path: String, metadata: Option<&Bound<'py, PyDict>>) -> PyResult<Bound<'py, PyAny>> {
+        let metadata: Option<Metadata> = metadata.map(|metadata| from_python(py, metadata.as_any(), "metadata")).transpose()?;
+        let engine = Arc::clone(&self.inner);
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let indexed: Result<_> = match metadata {
+                Some(metadata) => engine.index_document_with_metadata(&path, None, None, metadata).await,
+                None => engine.index_document(&path).await,
+            };
+            let indexed = indexed.map_err(raise)?;
+            Python::with_gil(|py| to_python(py, &indexed))
+        })
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "search_engine")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PySearchEngine>()?;
+    module.add("SearchEngineError", module.py().get_type::<SearchEngineError>())?;
+    Ok(())
+}
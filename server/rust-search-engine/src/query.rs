@@ -0,0 +1,700 @@
+// The query language. Field terms narrow the documents searched: `tag:urgent`, `path:docs/`,
+// `title:readme` or `title:"release notes"`, `lang:fr`, `after:2024-01-01` and `before:2024-02-01`;
+// `content:error` is just `error`, as terms match line content anyway. They apply to the
+// whole query, so they can't be in parentheses, negated or joined with OR. The rest is
+// matched against lines, with AND, OR and NOT combining terms and parentheses grouping them,
+// as in `rust AND (tokio OR async) NOT blocking`. Operators are only recognized in upper
+// case, so "salt and pepper" is still one phrase; words next to each other without an
+// operator form a phrase matched like a plain query. Double quotes make an exact phrase, as
+// in `"full text search"`, whose words must follow each other in order. A word ending in `~`
+// or `~N`, as in `serach~`, matches whole words within N edits of it (2 by default), and one
+// with `*` or `?`, as in `index*` or `te?t`, whole words fitting that pattern.
+// `tokio NEAR/5 runtime` matches lines with the two words at most 5 words apart.
+// A query that doesn't parse fails with a `QuerySyntaxError` saying where. Queries are read
+// with nom: a lexer splits the text into tokens and a grammar over them builds the `QueryNode`.
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till1, take_until, take_while};
+use nom::character::complete::{alpha1, char};
+use nom::combinator::{consumed, cut, map, map_opt, not};
+use nom::error::{ErrorKind, ParseError};
+use nom::multi::{many0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::{Finish, IResult, Offset};
+
+use crate::boolean::QueryNode;
+use crate::error::{QuerySyntaxError, Result, SearchEngineError};
+use crate::fuzzy::{effective_distance, DEFAULT_FUZZY_DISTANCE, MAX_FUZZY_DISTANCE};
+use crate::matching::index_terms;
+use crate::options::{MatchMode, QueryFields};
+use crate::partition::{parse_date_time, parse_end_date_time};
+use crate::proximity::{DEFAULT_NEAR_DISTANCE, MAX_NEAR_DISTANCE};
+use crate::wildcard::is_pattern;
+
+// A query split into what is matched against lines and what narrows the documents searched
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ParsedQuery {
+    // The query without its field terms
+    pub text: String,
+    pub fields: QueryFields,
+    // How the operators, phrases, fuzzy terms and wildcards of `text` combine; None when
+    // `text` is matched as written
+    pub tree: Option<QueryNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Tag,
+    Path,
+    Title,
+    Lang,
+    After,
+    Before,
+}
+
+impl Field {
+    fn named(name: &str) -> Option<Field> {
+        match name {
+            "tag" => Some(Field::Tag),
+            "path" => Some(Field::Path),
+            "title" => Some(Field::Title),
+            "lang" => Some(Field::Lang),
+            "after" => Some(Field::After),
+            "before" => Some(Field::Before),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+    // The text between a pair of double quotes
+    Quoted(String),
+    // A word with a `~` suffix and the distance after it, if any
+    Fuzzy(String, Option<usize>),
+    // A word with `*` or `?` wildcards
+    Wildcard(String),
+    // `NEAR` or `NEAR/N`
+    Near(Option<usize>),
+    // A field term with its value as written
+    Field(Field, String),
+}
+
+// A token and the bytes of the query it was read from
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    start: usize,
+    end: usize,
+}
+
+fn cased(text: &str, keep_case: bool) -> String {
+    if keep_case { text.to_string() } else { text.to_lowercase() }
+}
+
+// A stretch of the query the lexer reads as one
+enum Lexeme<'a> {
+    Token(Token),
+    // Everything up to the next space, `)`s at its end included
+    Word(&'a str),
+}
+
+// lexeme := ( | "quoted" | field:"quoted" | word
+fn lexeme<'a>(keep_case: bool) -> impl FnMut(&'a str) -> IResult<&'a str, Lexeme<'a>> {
+    alt((
+        map(char('('), |_| Lexeme::Token(Token::Open)),
+        map(delimited(char('"'), take_until("\""), char('"')), move |quoted: &str| {
+            Lexeme::Token(Token::Quoted(cased(quoted, keep_case)))
+        }),
+        map(quoted_field, |(field, value)| Lexeme::Token(Token::Field(field, value.to_string()))),
+        map(take_till1(char::is_whitespace), Lexeme::Word),
+    ))
+}
+
+// `title:"release notes"`
+fn quoted_field(input: &str) -> IResult<&str, (Field, &str)> {
+    pair(map_opt(alpha1, Field::named), delimited(tag(":\""), take_until("\""), char('"')))(input)
+}
+
+// Lowercases the text of terms unless `keep_case`. A double quote without a closing one is
+// an ordinary character.
+fn tokenize(query: &str, keep_case: bool) -> Vec<Spanned> {
+    // many0 stops at the first thing that isn't a lexeme, which is only trailing whitespace
+    let lexemes = many0(preceded(take_while(char::is_whitespace), consumed(lexeme(keep_case))))(query)
+        .map(|(_, lexemes)| lexemes)
+        .unwrap_or_default();
+    let mut tokens = Vec::new();
+    for (text, lexeme) in lexemes {
+        let start = query.offset(text);
+        match lexeme {
+            Lexeme::Token(token) => tokens.push(Spanned { token, start, end: start + text.len() }),
+            Lexeme::Word(text) => {
+                let word = text.trim_end_matches(')');
+                if !word.is_empty() {
+                    tokens.push(Spanned { token: word_token(word, keep_case), start, end: start + word.len() });
+                }
+                tokens.extend((start + word.len()..start + text.len()).map(|close| Spanned {
+                    token: Token::Close,
+                    start: close,
+                    end: close + 1,
+                }));
+            }
+        }
+    }
+    tokens
+}
+
+fn word_token(word: &str, keep_case: bool) -> Token {
+    if let Some((field, value)) = word.split_once(':').filter(|(_, value)| !value.is_empty()) {
+        if let Some(field) = Field::named(field) {
+            return Token::Field(field, value.to_string());
+        }
+    }
+    let word = word.strip_prefix("content:").filter(|value| !value.is_empty()).unwrap_or(word);
+    match word {
+        "AND" => Token::And,
+        "OR" => Token::Or,
+        "NOT" => Token::Not,
+        "NEAR" => Token::Near(None),
+        _ if near_distance(word).is_some() => Token::Near(near_distance(word)),
+        _ if is_pattern(word) => Token::Wildcard(cased(word, keep_case)),
+        _ => fuzzy_token(word, keep_case).unwrap_or_else(|| Token::Word(cased(word, keep_case))),
+    }
+}
+
+// "word~" or "word~N"; anything else after the `~` leaves it an ordinary word
+fn fuzzy_token(word: &str, keep_case: bool) -> Option<Token> {
+    let (term, distance) = word.rsplit_once('~')?;
+    if term.is_empty() {
+        return None;
+    }
+    let distance = match distance {
+        "" => None,
+        digits => Some(digits.parse().ok()?),
+    };
+    Some(Token::Fuzzy(cased(term, keep_case), distance))
+}
+
+// N in "NEAR/N"; anything else after the `/` leaves it an ordinary word
+fn near_distance(word: &str) -> Option<usize> {
+    word.strip_prefix("NEAR/")?.parse().ok()
+}
+
+// True if the tokens use an operator, a group, a quoted phrase, a fuzzy term, a wildcard or a
+// field term the grammar has to place, and so should be parsed rather than matched as
+// written. A `)` alone doesn't count, so "foo()" is still a plain query.
+fn needs_parsing(tokens: &[Spanned]) -> bool {
+    tokens.iter().any(|spanned| {
+        matches!(
+            spanned.token,
+            Token::Open | Token::And | Token::Or | Token::Not | Token::Quoted(_) | Token::Fuzzy(..) | Token::Wildcard(_) | Token::Near(_) | Token::Field(..)
+        )
+    })
+}
+
+fn syntax_error(query: &str, byte: usize, message: &str) -> SearchEngineError {
+    SearchEngineError::QuerySyntax(QuerySyntaxError {
+        message: message.to_string(),
+        position: query[..byte].chars().count(),
+    })
+}
+
+const FIELD_SCOPE: &str = "field terms apply to the whole query, so they can't be in parentheses, negated or joined with OR";
+
+type Tokens<'a> = &'a [Spanned];
+type Parsed<'a, T> = IResult<Tokens<'a>, T, GrammarError>;
+
+// Why the tokens stop fitting the grammar, and where
+#[derive(Debug)]
+struct GrammarError {
+    // The byte the offending token starts at, or None at the end of the query
+    at: Option<usize>,
+    message: String,
+}
+
+impl GrammarError {
+    fn at(input: Tokens, message: &str) -> Self {
+        GrammarError { at: input.first().map(|spanned| spanned.start), message: message.to_string() }
+    }
+}
+
+impl ParseError<Tokens<'_>> for GrammarError {
+    fn from_error_kind(input: Tokens, _: ErrorKind) -> Self {
+        GrammarError::at(input, "unexpected term")
+    }
+
+    fn append(_: Tokens, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+// Lets an alternative be tried instead
+fn backtrack<'a, T>(input: Tokens<'a>, message: &str) -> Parsed<'a, T> {
+    Err(nom::Err::Error(GrammarError::at(input, message)))
+}
+
+// Fails the whole query
+fn fail<'a, T>(input: Tokens<'a>, message: &str) -> Parsed<'a, T> {
+    Err(nom::Err::Failure(GrammarError::at(input, message)))
+}
+
+// The next token, if `accept` takes it
+fn token<'a, T>(accept: impl Fn(&Token) -> Option<T>) -> impl Fn(Tokens<'a>) -> Parsed<'a, T> {
+    move |input| match input.first().and_then(|spanned| accept(&spanned.token)) {
+        Some(value) => Ok((&input[1..], value)),
+        None => backtrack(input, "unexpected term"),
+    }
+}
+
+fn is<'a>(expected: Token) -> impl Fn(Tokens<'a>) -> Parsed<'a, ()> {
+    token(move |token| (*token == expected).then_some(()))
+}
+
+fn word(input: Tokens) -> Parsed<String> {
+    token(|token| match token {
+        Token::Word(word) => Some(word.clone()),
+        _ => None,
+    })(input)
+}
+
+fn near_operator(input: Tokens) -> Parsed<usize> {
+    token(|token| match token {
+        Token::Near(distance) => Some(distance.unwrap_or(DEFAULT_NEAR_DISTANCE)),
+        _ => None,
+    })(input)
+}
+
+// "quoted", whose index terms make the phrase
+fn quoted(input: Tokens) -> Parsed<QueryNode> {
+    let (rest, quoted) = token(|token| match token {
+        Token::Quoted(quoted) => Some(quoted.clone()),
+        _ => None,
+    })(input)?;
+    let words: Vec<&str> = index_terms(&quoted).collect();
+    if words.is_empty() {
+        return fail(input, "a quoted phrase needs at least one word");
+    }
+    Ok((rest, QueryNode::Phrase(words.join(" "))))
+}
+
+fn wildcard(input: Tokens) -> Parsed<QueryNode> {
+    token(|token| match token {
+        Token::Wildcard(pattern) => Some(QueryNode::Wildcard(pattern.clone())),
+        _ => None,
+    })(input)
+}
+
+// Where no operand starts: a `)` or OR ends a run of them, so is only an error where one is
+// required, while anything else can't be one at all
+fn missing_operand(input: Tokens) -> Parsed<QueryNode> {
+    match input.first().map(|spanned| &spanned.token) {
+        Some(Token::Field(..)) => fail(input, FIELD_SCOPE),
+        Some(Token::Close) => backtrack(input, "unexpected ')'"),
+        Some(Token::Or) | None => backtrack(input, "an operator is missing a term"),
+        Some(_) => fail(input, "an operator is missing a term"),
+    }
+}
+
+struct Grammar {
+    // Distance every plain word is matched within, when the whole query is fuzzy
+    fuzzy: Option<usize>,
+}
+
+impl Grammar {
+    // or := and (OR and)*
+    fn or<'a>(&self, input: Tokens<'a>) -> Parsed<'a, QueryNode> {
+        map(separated_list1(is(Token::Or), cut(|input| self.and(input))), |operands| combine(operands, false))(input)
+    }
+
+    // and := unary ((AND)? unary)*, where "a NOT b" means "a AND NOT b"
+    fn and<'a>(&self, input: Tokens<'a>) -> Parsed<'a, QueryNode> {
+        let unary = |input| self.unary(input);
+        map(pair(unary, many0(alt((preceded(is(Token::And), cut(unary)), unary)))), |(first, mut rest)| {
+            rest.insert(0, first);
+            combine(rest, true)
+        })(input)
+    }
+
+    // unary := NOT unary | ( or ) | word NEAR word | word+ | "quoted" | word~ | wild*card
+    fn unary<'a>(&self, input: Tokens<'a>) -> Parsed<'a, QueryNode> {
+        alt((
+            map(preceded(is(Token::Not), cut(|input| self.unary(input))), |node| QueryNode::Not(Box::new(node))),
+            |input| self.group(input),
+            near,
+            |input| self.words(input),
+            quoted,
+            |input| self.fuzzy_term(input),
+            wildcard,
+            missing_operand,
+        ))(input)
+    }
+
+    // ( or ), failing at the `(` if the `)` is missing
+    fn group<'a>(&self, input: Tokens<'a>) -> Parsed<'a, QueryNode> {
+        let (rest, node) = preceded(is(Token::Open), |input| self.or(input))(input)?;
+        match is(Token::Close)(rest) {
+            Ok((rest, ())) => Ok((rest, node)),
+            Err(_) => fail(input, "'(' is missing its ')'"),
+        }
+    }
+
+    // word+, a phrase. In a fuzzy query each word stands alone, as it is matched on its own.
+    fn words<'a>(&self, input: Tokens<'a>) -> Parsed<'a, QueryNode> {
+        if let Some(distance) = self.fuzzy {
+            return map(word, |word| match single_term(&word) {
+                Some(term) => fuzzy_node(term, distance),
+                None => QueryNode::Term(word),
+            })(input);
+        }
+        // The word before a NEAR is its left operand, not part of the phrase
+        map(pair(word, many0(terminated(word, not(near_operator)))), |(mut phrase, rest)| {
+            for word in rest {
+                phrase.push(' ');
+                phrase.push_str(&word);
+            }
+            QueryNode::Term(phrase)
+        })(input)
+    }
+
+    // word~ or word~N
+    fn fuzzy_term<'a>(&self, input: Tokens<'a>) -> Parsed<'a, QueryNode> {
+        let (rest, (word, distance)) = token(|token| match token {
+            Token::Fuzzy(word, distance) => Some((word.clone(), *distance)),
+            _ => None,
+        })(input)?;
+        let Some(term) = single_term(&word) else {
+            return fail(input, "a fuzzy term must be a single word");
+        };
+        let distance = distance.or(self.fuzzy).unwrap_or(DEFAULT_FUZZY_DISTANCE);
+        if distance > MAX_FUZZY_DISTANCE {
+            return fail(input, &format!("a fuzzy term allows at most {} edits", MAX_FUZZY_DISTANCE));
+        }
+        Ok((rest, fuzzy_node(term, distance)))
+    }
+
+    fn parse(self, query: &str, tokens: &[Spanned]) -> Result<QueryNode> {
+        let error = |error: GrammarError| syntax_error(query, error.at.unwrap_or(query.len()), &error.message);
+        let (rest, tree) = self.or(tokens).finish().map_err(error)?;
+        if !rest.is_empty() {
+            return Err(error(GrammarError::at(rest, "unexpected ')'")));
+        }
+        if tree.terms(false).is_empty() {
+            return Err(syntax_error(query, 0, "at least one term must be outside NOT"));
+        }
+        Ok(tree)
+    }
+}
+
+// word NEAR/N word, failing at the NEAR
+fn near(input: Tokens) -> Parsed<QueryNode> {
+    let (operator, left) = word(input)?;
+    let (rest, distance) = near_operator(operator)?;
+    if distance == 0 || distance > MAX_NEAR_DISTANCE {
+        return fail(operator, &format!("NEAR allows 1 to {} words apart", MAX_NEAR_DISTANCE));
+    }
+    let Ok((rest, right)) = word(rest) else {
+        return fail(operator, "NEAR needs a word on each side");
+    };
+    match (single_term(&left), single_term(&right)) {
+        (Some(left), Some(right)) => Ok((rest, QueryNode::Near { terms: format!("{} {}", left, right), distance })),
+        _ => fail(operator, "NEAR joins two single words"),
+    }
+}
+
+// The word's only index term, if it has exactly one
+fn single_term(word: &str) -> Option<&str> {
+    let mut terms = index_terms(word);
+    terms.next().filter(|_| terms.next().is_none())
+}
+
+fn fuzzy_node(term: &str, distance: usize) -> QueryNode {
+    QueryNode::Fuzzy { term: term.to_string(), distance: effective_distance(term, distance) }
+}
+
+// One operand stays as it is; nested groups of the same operator are flattened
+fn combine(operands: Vec<QueryNode>, and: bool) -> QueryNode {
+    if operands.len() == 1 {
+        return operands.into_iter().next().unwrap();
+    }
+    let mut flat = Vec::with_capacity(operands.len());
+    for operand in operands {
+        match operand {
+            QueryNode::And(inner) if and => flat.extend(inner),
+            QueryNode::Or(inner) if !and => flat.extend(inner),
+            operand => flat.push(operand),
+        }
+    }
+    if and {
+        QueryNode::And(flat)
+    } else {
+        QueryNode::Or(flat)
+    }
+}
+
+// Adds the value of a field term to `fields`
+fn apply_field(fields: &mut QueryFields, field: Field, value: &str) -> std::result::Result<(), String> {
+    let date = |name: &str| format!("{}: expects YYYY-MM-DD or an RFC 3339 timestamp, not {:?}", name, value);
+    match field {
+        Field::Tag => fields.tags.push(value.to_lowercase()),
+        Field::Path => fields.paths.push(value.to_string()),
+        Field::Title => fields.titles.push(value.to_string()),
+        Field::Lang => fields.languages.push(value.to_lowercase()),
+        Field::After => fields.after = Some(parse_date_time(value).map_err(|_| date("after"))?),
+        Field::Before => fields.before = Some(parse_end_date_time(value).map_err(|_| date("before"))?),
+    }
+    Ok(())
+}
+
+// Parses a query as normalized by `normalize_query`. In regex mode field terms are still
+// taken out, but the rest is a pattern rather than the grammar above. With `fuzzy`, every
+// word is matched within that many edits unless it gives its own. Terms are lowercased unless
+// `case_sensitive`.
+pub fn parse_query(query: &str, mode: MatchMode, fuzzy: Option<usize>, case_sensitive: bool) -> Result<ParsedQuery> {
+    let mut tokens = tokenize(query, case_sensitive);
+    let mut fields = QueryFields::default();
+    // Bytes of the query that aren't part of the text: field terms, the AND joining each to
+    // the rest, and `content:` prefixes
+    let mut removed: Vec<(usize, usize)> = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < tokens.len() {
+        let before = i.checked_sub(1).map(|j| tokens[j].token.clone());
+        let after = tokens.get(i + 1).map(|spanned| spanned.token.clone());
+        match tokens[i].token.clone() {
+            Token::Open => depth += 1,
+            Token::Close => depth = depth.saturating_sub(1),
+            Token::Word(_) | Token::Fuzzy(..) | Token::Wildcard(_) if query[tokens[i].start..].starts_with("content:") => {
+                removed.push((tokens[i].start, tokens[i].start + "content:".len()));
+            }
+            Token::Field(field, value) => {
+                // The grammar reports a field term it can't take out
+                let placed = mode != MatchMode::Regex
+                    && (depth > 0 || matches!(before, Some(Token::Not | Token::Or)) || after == Some(Token::Or));
+                if !placed {
+                    apply_field(&mut fields, field, &value).map_err(|message| syntax_error(query, tokens[i].start, &message))?;
+                    removed.push((tokens[i].start, tokens[i].end));
+                    tokens.remove(i);
+                    if mode != MatchMode::Regex {
+                        if before == Some(Token::And) {
+                            removed.push((tokens[i - 1].start, tokens[i - 1].end));
+                            tokens.remove(i - 1);
+                            i -= 1;
+                        } else if after == Some(Token::And) {
+                            removed.push((tokens[i].start, tokens[i].end));
+                            tokens.remove(i);
+                        }
+                    }
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let text = if removed.is_empty() {
+        query.to_string()
+    } else {
+        removed.sort_unstable();
+        let mut text = String::with_capacity(query.len());
+        let mut at = 0;
+        for (start, end) in removed {
+            text.push_str(&query[at..start]);
+            text.push(' ');
+            at = end;
+        }
+        text.push_str(&query[at..]);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    if text.is_empty() {
+        return Err(SearchEngineError::EmptyQuery("query must not be empty apart from tag:, path: and title: filters".to_string()));
+    }
+    let tree = if mode != MatchMode::Regex && (fuzzy.is_some() || needs_parsing(&tokens)) {
+        Some(Grammar { fuzzy }.parse(query, &tokens)?)
+    } else {
+        None
+    };
+    Ok(ParsedQuery { text, fields, tree })
+}
+
+// The tree of a query matched without its field terms taken out, as saved alerts and
+// percolator queries are, where a field term is an ordinary word; None if the query is
+// matched as written
+pub fn parse_tree(query: &str, fuzzy: Option<usize>) -> Result<Option<QueryNode>> {
+    let mut tokens = tokenize(query, false);
+    for spanned in &mut tokens {
+        if let Token::Field(..) = spanned.token {
+            spanned.token = Token::Word(query[spanned.start..spanned.end].to_lowercase());
+        }
+    }
+    if fuzzy.is_none() && !needs_parsing(&tokens) {
+        return Ok(None);
+    }
+    Grammar { fuzzy }.parse(query, &tokens).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use QueryNode::{And, Not, Or, Phrase, Term};
+
+    fn tree(query: &str) -> QueryNode {
+        parse_query(query, MatchMode::Substring, None, false).unwrap().tree.unwrap()
+    }
+
+    fn term(text: &str) -> QueryNode {
+        Term(text.to_string())
+    }
+
+    // The message and position of the error a query fails with
+    fn error(query: &str) -> (String, usize) {
+        match parse_query(query, MatchMode::Substring, None, false) {
+            Err(SearchEngineError::QuerySyntax(error)) => (error.message, error.position),
+            other => panic!("{:?} parsed as {:?}", query, other),
+        }
+    }
+
+    #[test]
+    fn plain_queries_are_matched_as_written() {
+        let parsed = parse_query("salt and pepper", MatchMode::Substring, None, false).unwrap();
+        assert_eq!(parsed.text, "salt and pepper");
+        assert_eq!(parsed.tree, None);
+        assert_eq!(parse_query("foo()", MatchMode::Substring, None, false).unwrap().tree, None);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(tree("a OR b AND c"), Or(vec![term("a"), And(vec![term("b"), term("c")])]));
+        assert_eq!(tree("a AND b OR c"), Or(vec![And(vec![term("a"), term("b")]), term("c")]));
+        assert_eq!(tree("(a OR b) AND c"), And(vec![Or(vec![term("a"), term("b")]), term("c")]));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_operand() {
+        assert_eq!(tree("a NOT b OR c"), Or(vec![And(vec![term("a"), Not(Box::new(term("b")))]), term("c")]));
+        assert_eq!(
+            tree("a NOT (b OR c)"),
+            And(vec![term("a"), Not(Box::new(Or(vec![term("b"), term("c")])))])
+        );
+    }
+
+    #[test]
+    fn nested_groups_of_one_operator_are_flattened() {
+        assert_eq!(tree("a AND (b AND c)"), And(vec![term("a"), term("b"), term("c")]));
+        assert_eq!(tree("(a OR b) OR c"), Or(vec![term("a"), term("b"), term("c")]));
+    }
+
+    #[test]
+    fn adjacent_words_form_a_phrase() {
+        assert_eq!(tree("Rust Tokio OR async"), Or(vec![term("rust tokio"), term("async")]));
+        assert_eq!(tree("salt and pepper NOT x"), And(vec![term("salt and pepper"), Not(Box::new(term("x")))]));
+    }
+
+    #[test]
+    fn quotes_make_exact_phrases() {
+        assert_eq!(tree("\"Full-Text search\" OR x"), Or(vec![Phrase("full text search".to_string()), term("x")]));
+        assert_eq!(error("a OR \"--\""), ("a quoted phrase needs at least one word".to_string(), 5));
+    }
+
+    #[test]
+    fn an_unclosed_quote_is_an_ordinary_character() {
+        assert_eq!(tree("\"foo OR bar"), Or(vec![term("\"foo"), term("bar")]));
+    }
+
+    #[test]
+    fn case_is_kept_when_asked() {
+        let parsed = parse_query("Rust OR Go", MatchMode::Substring, None, true).unwrap();
+        assert_eq!(parsed.tree, Some(Or(vec![term("Rust"), term("Go")])));
+    }
+
+    #[test]
+    fn fuzzy_wildcard_and_near_terms() {
+        assert_eq!(tree("serach~"), QueryNode::Fuzzy { term: "serach".to_string(), distance: 2 });
+        assert_eq!(tree("ab~1"), QueryNode::Fuzzy { term: "ab".to_string(), distance: 1 });
+        assert_eq!(tree("a~"), QueryNode::Fuzzy { term: "a".to_string(), distance: 0 });
+        assert_eq!(tree("Index*"), QueryNode::Wildcard("index*".to_string()));
+        assert_eq!(
+            tree("x tokio NEAR/5 runtime"),
+            And(vec![term("x"), QueryNode::Near { terms: "tokio runtime".to_string(), distance: 5 }])
+        );
+        assert_eq!(tree("a NEAR b"), QueryNode::Near { terms: "a b".to_string(), distance: DEFAULT_NEAR_DISTANCE });
+    }
+
+    #[test]
+    fn a_fuzzy_query_makes_every_word_fuzzy() {
+        let parsed = parse_query("tokio runtime", MatchMode::Substring, Some(1), false).unwrap();
+        assert_eq!(
+            parsed.tree,
+            Some(And(vec![
+                QueryNode::Fuzzy { term: "tokio".to_string(), distance: 1 },
+                QueryNode::Fuzzy { term: "runtime".to_string(), distance: 1 },
+            ]))
+        );
+    }
+
+    #[test]
+    fn field_terms_at_the_top_level_narrow_the_search() {
+        let parsed = parse_query("tag:Urgent AND error lang:FR title:\"Release notes\" content:panic", MatchMode::Substring, None, false).unwrap();
+        assert_eq!(parsed.text, "error panic");
+        assert_eq!(parsed.tree, None);
+        assert_eq!(parsed.fields.tags, vec!["urgent".to_string()]);
+        assert_eq!(parsed.fields.languages, vec!["fr".to_string()]);
+        assert_eq!(parsed.fields.titles, vec!["Release notes".to_string()]);
+
+        let parsed = parse_query("a OR b path:docs/", MatchMode::Substring, None, false).unwrap();
+        assert_eq!(parsed.text, "a OR b");
+        assert_eq!(parsed.fields.paths, vec!["docs/".to_string()]);
+        assert_eq!(parsed.tree, Some(Or(vec![term("a"), term("b")])));
+    }
+
+    #[test]
+    fn field_terms_in_regex_mode_are_taken_out() {
+        let parsed = parse_query("tag:x fo+ AND", MatchMode::Regex, None, false).unwrap();
+        assert_eq!(parsed.text, "fo+ AND");
+        assert_eq!(parsed.tree, None);
+    }
+
+    #[test]
+    fn field_terms_must_apply_to_the_whole_query() {
+        let scoped = "field terms apply to the whole query, so they can't be in parentheses, negated or joined with OR".to_string();
+        assert_eq!(error("a AND (tag:x OR b)"), (scoped.clone(), 7));
+        assert_eq!(error("a NOT tag:x"), (scoped.clone(), 6));
+        assert_eq!(error("a OR tag:x"), (scoped.clone(), 5));
+        assert_eq!(error("tag:x OR a"), (scoped, 0));
+        assert_eq!(error("a after:yesterday").1, 2);
+        assert!(matches!(
+            parse_query("tag:x", MatchMode::Substring, None, false),
+            Err(SearchEngineError::EmptyQuery(_))
+        ));
+    }
+
+    #[test]
+    fn field_terms_are_words_in_saved_queries() {
+        assert_eq!(parse_tree("tag:x", None).unwrap(), None);
+        assert_eq!(parse_tree("tag:x OR b", None).unwrap(), Some(Or(vec![term("tag:x"), term("b")])));
+    }
+
+    #[test]
+    fn errors_say_where() {
+        assert_eq!(error("(a OR b"), ("'(' is missing its ')'".to_string(), 0));
+        assert_eq!(error("a OR b)"), ("unexpected ')'".to_string(), 6));
+        assert_eq!(error("a AND )"), ("unexpected ')'".to_string(), 6));
+        assert_eq!(error("a AND"), ("an operator is missing a term".to_string(), 5));
+        assert_eq!(error("OR a"), ("an operator is missing a term".to_string(), 0));
+        assert_eq!(error("a OR OR b"), ("an operator is missing a term".to_string(), 5));
+        assert_eq!(error("NOT a"), ("at least one term must be outside NOT".to_string(), 0));
+        assert_eq!(error("é AND (b"), ("'(' is missing its ')'".to_string(), 6));
+        assert_eq!(error("a NEAR/0 b"), (format!("NEAR allows 1 to {} words apart", MAX_NEAR_DISTANCE), 2));
+        assert_eq!(error("a NEAR"), ("NEAR needs a word on each side".to_string(), 2));
+        assert_eq!(error("a-b NEAR c"), ("NEAR joins two single words".to_string(), 4));
+        assert_eq!(error("x a-b~"), ("a fuzzy term must be a single word".to_string(), 2));
+        assert_eq!(error("word~3"), (format!("a fuzzy term allows at most {} edits", MAX_FUZZY_DISTANCE), 0));
+    }
+}
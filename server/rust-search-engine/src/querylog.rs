@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::error::{Context, Result};
+use crate::profile::QueryProfile;
+
+pub const QUERY_LOG_FILE: &str = "queries.log";
+pub const SLOW_LOG_FILE: &str = "slow.log";
+
+// One line of the append-only query log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QueryLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub query: String,
+    pub total: usize,
+    pub latency_us: u64,
+    // Documents (relative to the search directory) with a line in the returned page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hits: Vec<String>,
+}
+
+// One line of the slow query log: a search that took longer than the configured threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SlowQueryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub query: String,
+    pub total: usize,
+    pub latency_us: u64,
+    // The search options as given, with durations in milliseconds
+    pub options: Value,
+    pub profile: QueryProfile,
+}
+
+// Appends an entry to the query log or the slow query log
+pub async fn append(path: &Path, entry: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let mut file = async_fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .context("Failed to open query log")?;
+    file.write_all(&line).await?;
+    Ok(())
+}
+
+// Reads every entry at or after `since`, skipping lines that fail to parse
+pub async fn read(path: &Path, since: Option<DateTime<Utc>>) -> Result<Vec<QueryLogEntry>> {
+    read_lines(path, |entry: &QueryLogEntry| since.map(|since| entry.timestamp >= since).unwrap_or(true)).await
+}
+
+// Reads the slow query log like `read`, keeping the `limit` most recent entries
+pub async fn read_slow(path: &Path, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Result<Vec<SlowQueryEntry>> {
+    let mut entries = read_lines(path, |entry: &SlowQueryEntry| since.map(|since| entry.timestamp >= since).unwrap_or(true)).await?;
+    if let Some(limit) = limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+    Ok(entries)
+}
+
+async fn read_lines<T: DeserializeOwned>(path: &Path, keep: impl Fn(&T) -> bool) -> Result<Vec<T>> {
+    let file = match async_fs::File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to open query log"),
+    };
+
+    let mut entries = Vec::new();
+    let mut lines = BufReader::new(file).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Ok(entry) = serde_json::from_str::<T>(&line) {
+            if keep(&entry) {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QueryStats {
+    pub query: String,
+    pub count: usize,
+    pub zero_result_count: usize,
+    pub average_latency_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QueryAnalytics {
+    pub since: Option<DateTime<Utc>>,
+    pub total_queries: usize,
+    pub zero_result_queries: usize,
+    pub average_latency_us: u64,
+    pub top_queries: Vec<QueryStats>,
+    pub top_zero_result_queries: Vec<QueryStats>,
+    // Keyed by the start of each hour, e.g. "2024-06-01T13:00:00Z"
+    pub queries_per_hour: BTreeMap<String, usize>,
+}
+
+pub fn analyze(entries: &[QueryLogEntry], top: usize, since: Option<DateTime<Utc>>) -> QueryAnalytics {
+    #[derive(Default)]
+    struct Totals {
+        display: String,
+        count: usize,
+        zero: usize,
+        latency: u64,
+    }
+
+    // Queries differing only in case or surrounding whitespace are the same query
+    let mut per_query: HashMap<String, Totals> = HashMap::new();
+    let mut queries_per_hour = BTreeMap::new();
+    let mut total_latency = 0u64;
+    let mut zero_result_queries = 0;
+
+    for entry in entries {
+        let key = entry.query.trim().to_lowercase();
+        let totals = per_query.entry(key).or_insert_with(|| Totals {
+            display: entry.query.trim().to_string(),
+            ..Default::default()
+        });
+        totals.count += 1;
+        totals.latency += entry.latency_us;
+        if entry.total == 0 {
+            totals.zero += 1;
+            zero_result_queries += 1;
+        }
+        total_latency += entry.latency_us;
+
+        let hour = entry.timestamp.format("%Y-%m-%dT%H:00:00Z").to_string();
+        *queries_per_hour.entry(hour).or_insert(0) += 1;
+    }
+
+    let mut stats: Vec<QueryStats> = per_query.into_values()
+        .map(|totals| QueryStats {
+            query: totals.display,
+            count: totals.count,
+            zero_result_count: totals.zero,
+            average_latency_us: totals.latency / totals.count as u64,
+        })
+        .collect();
+    stats.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.query.cmp(&b.query)));
+
+    let top_zero_result_queries = stats.iter()
+        .filter(|stat| stat.zero_result_count > 0)
+        .take(top)
+        .cloned()
+        .collect();
+    stats.truncate(top);
+
+    QueryAnalytics {
+        since,
+        total_queries: entries.len(),
+        zero_result_queries,
+        average_latency_us: if entries.is_empty() { 0 } else { total_latency / entries.len() as u64 },
+        top_queries: stats,
+        top_zero_result_queries,
+        queries_per_hour,
+    }
+}
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+use walkdir::WalkDir;
+
+use crate::catalog::Catalog;
+use crate::error::{Result, SearchEngineError};
+use crate::trash::TrashEntry;
+use crate::SearchEngine;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuotaPolicy {
+    // Refuse to index documents that would exceed the quota
+    #[default]
+    Reject,
+    // Empty the trash, oldest first, then move the least recently modified documents to it
+    // until the new one fits
+    EvictOldest,
+}
+
+// Upper bound on the bytes kept in the search directory: its indexed documents, `.index`
+// and the trash
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DiskQuota {
+    pub max_bytes: u64,
+    #[serde(default)]
+    pub policy: QuotaPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QuotaStatus {
+    pub max_bytes: u64,
+    // Documents, index and trash together
+    pub used_bytes: u64,
+    pub index_bytes: u64,
+    pub trash_bytes: u64,
+    pub policy: QuotaPolicy,
+}
+
+// Bytes of the files under `dir`
+async fn directory_bytes(dir: PathBuf) -> u64 {
+    tokio::task::spawn_blocking(move || {
+        WalkDir::new(dir).into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    })
+    .await
+    .unwrap_or(0)
+}
+
+impl SearchEngine {
+    // Bytes of the documents in the search directory; those in search roots don't count
+    pub(crate) fn stored_bytes(&self, catalog: &Catalog) -> u64 {
+        let in_roots: u64 = catalog.documents.iter()
+            .filter(|doc| self.root_of(&doc.path).is_some())
+            .map(|doc| doc.size)
+            .sum();
+        catalog.counters.total_bytes.saturating_sub(in_roots)
+    }
+
+    // Bytes under `.index`: tables, metadata and logs
+    async fn index_bytes(&self) -> u64 {
+        directory_bytes(self.doc_store.index_dir().to_path_buf()).await
+    }
+
+    // Bytes of the line offset, postings and vector tables kept for one document
+    async fn document_index_bytes(&self, file_path: &Path) -> u64 {
+        let tables = [
+            self.doc_store.offsets_path(file_path),
+            self.postings_store.postings_path(file_path),
+            self.vector_store.vectors_path(file_path),
+        ];
+        let mut bytes = 0;
+        for table in tables {
+            bytes += async_fs::metadata(&table).await.map(|metadata| metadata.len()).unwrap_or(0);
+        }
+        bytes
+    }
+
+    pub(crate) async fn quota_status(&self) -> Result<Option<QuotaStatus>> {
+        let Some(quota) = self.quota else {
+            return Ok(None);
+        };
+        let catalog = self.catalog().await?;
+        let index_bytes = self.index_bytes().await;
+        let trash_bytes = self.trash_entries().await?.iter().map(|entry| entry.size).sum();
+        Ok(Some(QuotaStatus {
+            max_bytes: quota.max_bytes,
+            used_bytes: self.stored_bytes(&catalog) + index_bytes + trash_bytes,
+            index_bytes,
+            trash_bytes,
+            policy: quota.policy,
+        }))
+    }
+
+    // Makes room for `incoming` bytes at `target`, which may replace an existing document or,
+    // with `restoring`, come back from the trash, evicting or refusing according to the
+    // configured policy
+    pub(crate) async fn enforce_quota(&self, target: &Path, incoming: u64, restoring: Option<&TrashEntry>) -> Result<()> {
+        let Some(quota) = self.quota.filter(|_| self.root_of(target).is_none()) else {
+            return Ok(());
+        };
+        if incoming > quota.max_bytes {
+            return Err(SearchEngineError::QuotaExceeded(format!(
+                "document of {} bytes is larger than the quota of {} bytes", incoming, quota.max_bytes
+            )));
+        }
+
+        let catalog = self.catalog().await?;
+        let replaced = catalog.get(target).map(|doc| doc.size).unwrap_or(0);
+        // A restored document leaves the trash as it comes back
+        let mut trash: VecDeque<TrashEntry> = self.trash_entries().await?.into_iter()
+            .filter(|entry| restoring.is_none_or(|restoring| entry.id != restoring.id))
+            .collect();
+        let trash_bytes: u64 = trash.iter().map(|entry| entry.size).sum();
+        let mut used = (self.stored_bytes(&catalog) + self.index_bytes().await + trash_bytes).saturating_sub(replaced);
+        if used + incoming <= quota.max_bytes {
+            return Ok(());
+        }
+
+        if quota.policy == QuotaPolicy::Reject {
+            return Err(SearchEngineError::QuotaExceeded(format!(
+                "{} of {} bytes used, document needs {}",
+                used, quota.max_bytes, incoming
+            )));
+        }
+
+        let mut candidates: Vec<(chrono::DateTime<chrono::Utc>, PathBuf, u64)> = catalog.documents.iter()
+            .filter(|doc| doc.path != target && self.root_of(&doc.path).is_none())
+            .map(|doc| (doc.modified, doc.path.clone(), doc.size))
+            .collect();
+        candidates.sort();
+        let mut candidates = candidates.into_iter();
+
+        // Documents already deleted go first. An evicted document frees its tables and goes
+        // to the trash like a deleted one, where it is purged in turn if that isn't enough.
+        let mut evicted = Vec::new();
+        while used + incoming > quota.max_bytes {
+            if let Some(entry) = trash.pop_front() {
+                self.purge_entry(&entry).await?;
+                used = used.saturating_sub(entry.size);
+                continue;
+            }
+            let Some((_, path, size)) = candidates.next() else {
+                break;
+            };
+            let tables = self.document_index_bytes(&path).await;
+            match self.discard_document(&path).await? {
+                Some(entry) => trash.push_back(entry),
+                None => used = used.saturating_sub(size),
+            }
+            used = used.saturating_sub(tables);
+            evicted.push(path.to_string_lossy().to_string());
+        }
+        self.commit_catalog().await?;
+        self.audit("evict", evicted, Ok(())).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::SearchOptions;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+
+    async fn with_quota(dir: &TestDir, max_bytes: u64, policy: QuotaPolicy) -> SearchEngine {
+        SearchEngine::builder(dir.path()).quota(Some(DiskQuota { max_bytes, policy })).build().await.unwrap()
+    }
+
+    async fn total(engine: &SearchEngine, query: &str) -> usize {
+        engine.search(query, &SearchOptions::default()).await.unwrap().total
+    }
+
+    // `lines` lines of `word`, 100 bytes each
+    fn document(word: &str, lines: usize) -> String {
+        format!("{:<99}\n", word).repeat(lines)
+    }
+
+    #[test]
+    fn eviction_empties_the_trash_before_moving_documents_to_it() {
+        let dir = TestDir::new("quota");
+        block_on(async {
+            index(&dir.engine().await, vec![
+                DocumentInput::from_bytes("a.txt", document("alpha", 20)),
+                DocumentInput::from_bytes("b.txt", document("beta", 20)),
+            ]).await;
+            let status = with_quota(&dir, u64::MAX, QuotaPolicy::Reject).await.quota_status().await.unwrap().unwrap();
+            assert!(status.index_bytes > 0);
+            assert_eq!(status.trash_bytes, 0);
+            assert_eq!(status.used_bytes, 4000 + status.index_bytes);
+
+            // Removing the oldest document's tables makes room, so it stays in the trash
+            let engine = with_quota(&dir, status.used_bytes + 40, QuotaPolicy::EvictOldest).await;
+            index(&engine, vec![DocumentInput::from_bytes("c.txt", document("gamma", 1))]).await;
+            let trashed: Vec<String> = engine.trash_entries().await.unwrap().into_iter().map(|entry| entry.path).collect();
+            assert_eq!(trashed, ["a.txt"]);
+            assert_eq!(engine.quota_status().await.unwrap().unwrap().trash_bytes, 2000);
+            assert_eq!(total(&engine, "alpha").await, 0);
+            assert_eq!(total(&engine, "beta").await, 20);
+            assert_eq!(total(&engine, "gamma").await, 1);
+
+            // Then the trash is purged rather than another document evicted
+            index(&engine, vec![DocumentInput::from_bytes("d.txt", document("delta", 1))]).await;
+            assert!(engine.trash_entries().await.unwrap().is_empty());
+            assert_eq!(total(&engine, "beta").await, 20);
+            assert_eq!(total(&engine, "delta").await, 1);
+
+            let used = engine.quota_status().await.unwrap().unwrap().used_bytes;
+            let engine = with_quota(&dir, used, QuotaPolicy::Reject).await;
+            let report = engine.index_source(vec![DocumentInput::from_bytes("e.txt", "epsilon\n")].into_iter()).await.unwrap();
+            assert_eq!(report.failed.len(), 1);
+            assert!(report.failed[0].error.contains("bytes used"), "{}", report.failed[0].error);
+            assert_eq!(total(&engine, "beta").await, 20);
+        });
+    }
+}
@@ -0,0 +1,241 @@
+// Point-in-time readers: a pinned copy of the document list that searches can page
+// through while indexing carries on. Readers are kept as files under the index
+// directory, so one opened by a CLI call is usable by the next, and each use pushes
+// back its expiry by its keep-alive.
+//
+// Only the set of documents is pinned, not their content, so a document deleted or changed
+// after the reader was opened is skipped: a reader's searches find what they would have
+// found when it was opened, less what is gone since, and never anything newer.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs as async_fs;
+use tracing::warn;
+
+use crate::catalog::{Catalog, CatalogEntry};
+use crate::checksum;
+use crate::docstore::{generation, temp_path, DocumentMeta};
+use crate::error::{Context, Result, SearchEngineError};
+use crate::ids::random_uuid;
+use crate::SearchEngine;
+
+pub const READERS_DIR: &str = "readers";
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::minutes(5);
+// Longest a reader may go unused, so forgotten readers don't pile up
+pub const MAX_KEEP_ALIVE: Duration = Duration::hours(24);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PointInTime {
+    pub id: String,
+    // Same as the replication manifest generation of the pinned documents
+    pub generation: String,
+    pub opened_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub total_documents: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReaderState {
+    #[serde(flatten)]
+    info: PointInTime,
+    keep_alive_ms: i64,
+    documents: Vec<DocumentMeta>,
+}
+
+impl SearchEngine {
+    pub(crate) fn readers_dir(&self) -> PathBuf {
+        self.doc_store.index_dir().join(READERS_DIR)
+    }
+
+    fn reader_path(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Err(SearchEngineError::InvalidInput(format!("Invalid reader ID {:?}", id)));
+        }
+        Ok(self.readers_dir().join(format!("{}.json", id)))
+    }
+
+    async fn save_reader(&self, state: &ReaderState) -> Result<()> {
+        async_fs::create_dir_all(self.readers_dir()).await
+            .context("Failed to create readers directory")?;
+        let target = self.reader_path(&state.info.id)?;
+        let temp = temp_path(&target);
+        async_fs::write(&temp, checksum::to_json(state)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist point-in-time reader")?;
+        Ok(())
+    }
+
+    async fn load_reader(&self, id: &str) -> Result<Option<ReaderState>> {
+        match async_fs::read(self.reader_path(id)?).await {
+            Ok(bytes) => Ok(Some(checksum::from_json(&bytes, "point-in-time reader")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read point-in-time reader"),
+        }
+    }
+
+    // Pins the current document list for `keep_alive` (5 minutes by default), renewed by every search using it
+    pub async fn open_reader(&self, keep_alive: Option<Duration>) -> Result<PointInTime> {
+        let keep_alive = keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE);
+        if keep_alive <= Duration::zero() || keep_alive > MAX_KEEP_ALIVE {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "Reader keep-alive must be positive and at most {} hours", MAX_KEEP_ALIVE.num_hours()
+            )));
+        }
+        self.ensure_writable()?;
+        let now = Utc::now();
+        self.expire_readers(now).await?;
+
+        let documents: Vec<DocumentMeta> = self.catalog().await?.documents.iter()
+            .map(|doc| DocumentMeta {
+                path: self.relative_path(&doc.path),
+                size: doc.size,
+                modified: doc.modified,
+                attributes: doc.attributes.clone(),
+            })
+            .collect();
+        let state = ReaderState {
+            info: PointInTime {
+                id: random_uuid(),
+                generation: generation(&documents)?,
+                opened_at: now,
+                expires_at: now + keep_alive,
+                total_documents: documents.len(),
+            },
+            keep_alive_ms: keep_alive.num_milliseconds(),
+            documents,
+        };
+        self.save_reader(&state).await?;
+        Ok(state.info)
+    }
+
+    // Returns false if no reader has the ID or it had already expired
+    pub async fn close_reader(&self, id: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let expired = match self.load_reader(id).await? {
+            Some(state) => state.info.expires_at <= Utc::now(),
+            None => return Ok(false),
+        };
+        match async_fs::remove_file(self.reader_path(id)?).await {
+            Ok(()) => Ok(!expired),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).context("Failed to remove point-in-time reader"),
+        }
+    }
+
+    // Open readers, soonest to expire first
+    pub async fn readers(&self) -> Result<Vec<PointInTime>> {
+        let now = Utc::now();
+        if !self.read_only {
+            self.expire_readers(now).await?;
+        }
+        let mut readers = Vec::new();
+        let mut entries = match async_fs::read_dir(self.readers_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(readers),
+            Err(e) => return Err(e).context("Failed to list point-in-time readers"),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(id) = name.strip_suffix(".json") {
+                if let Some(state) = self.load_reader(id).await?.filter(|state| state.info.expires_at > now) {
+                    readers.push(state.info);
+                }
+            }
+        }
+        readers.sort_by_key(|reader| reader.expires_at);
+        Ok(readers)
+    }
+
+    // Deletes readers whose keep-alive ran out, returning how many
+    pub(crate) async fn expire_readers(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut entries = match async_fs::read_dir(self.readers_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).context("Failed to list point-in-time readers"),
+        };
+        let mut expired = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+                continue;
+            }
+            let stale = match async_fs::read(&path).await {
+                Ok(bytes) => checksum::from_json::<ReaderState>(&bytes, "point-in-time reader")
+                    .map(|state| state.info.expires_at <= now)
+                    .unwrap_or(true),
+                Err(_) => continue,
+            };
+            if stale {
+                match async_fs::remove_file(&path).await {
+                    Ok(()) => expired += 1,
+                    Err(e) => warn!(path = ?path, error = %e, "Failed to remove expired reader"),
+                }
+            }
+        }
+        Ok(expired)
+    }
+
+    // The catalog pinned by a reader, less the documents deleted or changed since, renewing
+    // its keep-alive unless the engine is read-only
+    pub(crate) async fn reader_catalog(&self, id: &str) -> Result<Arc<Catalog>> {
+        let now = Utc::now();
+        let mut state = match self.load_reader(id).await? {
+            Some(state) if state.info.expires_at > now => state,
+            _ => return Err(SearchEngineError::ReaderExpired(format!(
+                "point-in-time reader {} does not exist or has expired", id
+            ))),
+        };
+        if !self.read_only {
+            state.info.expires_at = now + Duration::milliseconds(state.keep_alive_ms);
+            self.save_reader(&state).await?;
+        }
+
+        let live = self.catalog().await?;
+        let stamps: HashMap<&PathBuf, (u64, DateTime<Utc>)> = live.documents.iter()
+            .map(|doc| (&doc.path, (doc.size, doc.modified)))
+            .collect();
+        let documents = state.documents.into_iter()
+            .map(|doc| CatalogEntry {
+                path: self.absolute_path(&doc.path),
+                size: doc.size,
+                modified: doc.modified,
+                attributes: doc.attributes,
+            })
+            .filter(|doc| stamps.get(&doc.path) == Some(&(doc.size, doc.modified)))
+            .collect();
+        Ok(Arc::new(Catalog::scanned(documents, state.info.opened_at)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::SearchOptions;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+
+    #[test]
+    fn a_reader_never_sees_documents_changed_after_it_opened() {
+        let dir = TestDir::new("reader");
+        block_on(async {
+            let engine = dir.engine().await;
+            index(&engine, vec![
+                DocumentInput::from_bytes("a.txt", "apple\n"),
+                DocumentInput::from_bytes("b.txt", "apple pie\n"),
+            ]).await;
+            let reader = engine.open_reader(None).await.unwrap();
+            let options = SearchOptions { reader: Some(reader.id.clone()), ..SearchOptions::default() };
+            assert_eq!(engine.search("apple", &options).await.unwrap().total, 2);
+
+            index(&engine, vec![DocumentInput::from_bytes("a.txt", "apple\napple\napple\napple\n")]).await;
+            index(&engine, vec![DocumentInput::from_bytes("c.txt", "apple\n")]).await;
+            assert_eq!(engine.search("apple", &SearchOptions::default()).await.unwrap().total, 6);
+            let response = engine.search("apple", &options).await.unwrap();
+            assert_eq!(response.total, 1);
+            assert!(response.results[0].path.ends_with("b.txt"));
+        });
+    }
+}
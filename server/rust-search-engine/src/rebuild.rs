@@ -0,0 +1,165 @@
+// Full index rebuild: every derived table and the index metadata are built from the
+// documents on disk into `.index/rebuild.tmp`, then swapped in for the live ones while
+// searches are held off, so a corrupted or misconfigured index is regenerated without a
+// clear-all and without any search seeing half of each. Logs, saved alerts, readers and
+// the other state kept in `.index` are left where they are.
+
+use std::path::Path;
+use std::sync::PoisonError;
+use tokio::fs as async_fs;
+use tracing::{info, warn};
+
+use crate::catalog::Catalog;
+use crate::dedup::simhash;
+use crate::docstore::{DocStore, FileStamp, LINES_DIR, META_FILE, POSTINGS_DIR, VECTORS_DIR};
+use crate::error::{Context, Result, SearchEngineError};
+use crate::extract::page_breaks;
+use crate::keywords::{extract_keywords, MAX_KEYWORDS};
+use crate::language::detect_language;
+use crate::postings::{PostingsStore, TERMS_FILE};
+use crate::progress::{ProgressReporter, Tracker};
+use crate::vector::VectorStore;
+use crate::SearchEngine;
+
+// Where the new index is built, next to the one it replaces so the swap is a rename
+const STAGING_DIR: &str = "rebuild.tmp";
+// Where the replaced tables go until they are deleted after the swap
+const RETIRED_DIR: &str = "rebuild.old";
+// Everything under `.index` a rebuild replaces, in the order it is swapped in
+const DERIVED: [&str; 5] = [LINES_DIR, POSTINGS_DIR, VECTORS_DIR, TERMS_FILE, META_FILE];
+
+// What a rebuild produced
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RebuildReport {
+    pub(crate) documents: usize,
+    pub(crate) failed: usize,
+}
+
+async fn exists(path: &Path) -> bool {
+    async_fs::try_exists(path).await.unwrap_or(false)
+}
+
+async fn remove_dir(dir: &Path) -> Result<()> {
+    match async_fs::remove_dir_all(dir).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {:?}", dir)),
+    }
+}
+
+impl SearchEngine {
+    // Rebuilds the index from scratch and swaps it in. Documents that fail to index are left
+    // out of the new tables and rebuilt on first use, as they would be after a refresh.
+    pub(crate) async fn rebuild_index(&self, progress: &dyn ProgressReporter) -> Result<RebuildReport> {
+        self.ensure_writable()?;
+        let index_dir = self.doc_store.index_dir().to_path_buf();
+        let staging = index_dir.join(STAGING_DIR);
+        // Left over from a rebuild that was interrupted
+        remove_dir(&staging).await?;
+        remove_dir(&index_dir.join(RETIRED_DIR)).await?;
+        async_fs::create_dir_all(&staging).await
+            .context("Failed to create rebuild directory")?;
+
+        let result = match self.build_index(&staging, progress).await {
+            Ok((catalog, report)) => self.swap_index(&index_dir, &staging, catalog).await.map(|()| report),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = remove_dir(&staging).await {
+            warn!(error = %e, "Failed to clean up rebuild directory");
+        }
+        result
+    }
+
+    // Builds the catalog and every table into `staging`, leaving the live index alone
+    async fn build_index(&self, staging: &Path, progress: &dyn ProgressReporter) -> Result<(Catalog, RebuildReport)> {
+        let mut catalog = self.rescan_catalog().await?;
+        let doc_store = DocStore::new(&self.search_path, self.storage.clone()).with_index_dir(staging);
+        let postings_store = PostingsStore::new(&self.search_path, staging, self.storage.clone())
+            .analyzers(self.analyzers.clone());
+        let vector_store = VectorStore::new(&self.search_path, staging, self.storage.clone());
+
+        let mut report = RebuildReport { documents: catalog.documents.len(), failed: 0 };
+        let mut tracker = Tracker::new(progress, "rebuild", Some(catalog.documents.len()));
+        for doc in &mut catalog.documents {
+            let built: Result<()> = async {
+                let object = self.storage.stat(&doc.path).await?
+                    .ok_or_else(|| SearchEngineError::DocumentNotFound(doc.path.to_string_lossy().to_string()))?;
+                let text = self.read_document(&doc.path).await?;
+                doc_store.offsets(&doc.path).await?;
+                postings_store.save(&doc.path, &postings_store.table_from_text(&doc.path, FileStamp::from_object(&object), &text)).await?;
+                if let Some(embedder) = self.embedder.as_deref() {
+                    vector_store.vectors(&doc.path, embedder).await?;
+                }
+                doc.attributes.fingerprint = Some(simhash(&text));
+                doc.attributes.keywords = extract_keywords(&text, MAX_KEYWORDS);
+                doc.attributes.language = Some(detect_language(&text).to_string());
+                doc.attributes.page_breaks = page_breaks(&text);
+                Ok(())
+            }.await;
+            if let Err(e) = built {
+                warn!(path = ?doc.path, error = %e, "Failed to rebuild document");
+                report.failed += 1;
+            }
+            tracker.advance(self.relative_path(&doc.path));
+        }
+        doc_store.save_meta(&self.index_meta(&catalog)).await?;
+        Ok((catalog, report))
+    }
+
+    // Moves the live tables aside and the new ones into place while no search is reading
+    // them, putting the old ones back if any move fails. The catalog's changes are logged
+    // once it is live.
+    async fn swap_index(&self, index_dir: &Path, staging: &Path, mut catalog: Catalog) -> Result<()> {
+        let retired = index_dir.join(RETIRED_DIR);
+        async_fs::create_dir_all(&retired).await
+            .context("Failed to create rebuild directory")?;
+        let pending = std::mem::take(&mut catalog.pending);
+
+        let tables = self.shared.index_lock.write().await;
+        let mut swapped: Vec<&str> = Vec::new();
+        let mut failure = None;
+        for name in DERIVED {
+            let (live, built) = (index_dir.join(name), staging.join(name));
+            let moved = async {
+                if exists(&live).await {
+                    async_fs::rename(&live, retired.join(name)).await?;
+                }
+                if exists(&built).await {
+                    async_fs::rename(&built, &live).await?;
+                }
+                Ok::<(), std::io::Error>(())
+            }.await;
+            swapped.push(name);
+            if let Err(e) = moved {
+                failure = Some(e);
+                break;
+            }
+        }
+        if let Some(e) = failure {
+            for name in swapped {
+                let (live, old) = (index_dir.join(name), retired.join(name));
+                if exists(&old).await {
+                    let _ = remove_dir(&live).await;
+                    let _ = async_fs::remove_file(&live).await;
+                    if let Err(e) = async_fs::rename(&old, &live).await {
+                        warn!(name, error = %e, "Failed to restore index after a failed rebuild");
+                    }
+                }
+            }
+            return Err(e).context("Failed to swap in the rebuilt index");
+        }
+        self.replace_catalog(catalog);
+        if let Err(e) = self.bump_catalog_revision().await {
+            warn!(error = %e, "Failed to record the rebuilt catalog");
+        }
+        *self.shared.corpus_stats.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        drop(tables);
+
+        info!("Swapped in the rebuilt index");
+        self.log_changes(pending).await;
+        if let Err(e) = remove_dir(&retired).await {
+            warn!(error = %e, "Failed to remove the replaced index");
+        }
+        Ok(())
+    }
+}
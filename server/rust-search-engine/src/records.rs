@@ -0,0 +1,271 @@
+// JSON records indexed as documents of their own: each element of a top-level array, each
+// top-level object and each line of an NDJSON file. A mapping names the fields, given as paths
+// such as "author.name", that identify and title a record, hold its searchable text, and become
+// its metadata. Records are stored as text under the file's name plus ".records", named by their
+// ID field or numbered from 1, so "products.json" is searched as "products.json.records/sku-1.txt"
+// and re-indexing the file replaces its records in place. Records it no longer holds are left
+// in the index until deleted.
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+
+use crate::error::{Context, Result, SearchEngineError};
+use crate::metadata::{Metadata, MetadataValue};
+use crate::progress::NoProgress;
+use crate::source::{DocumentInput, DocumentSource, IndexReport};
+use crate::storage::BoxFuture;
+use crate::SearchEngine;
+
+// Extensions of the files `index-json` reads
+pub const JSON_EXTENSIONS: &[&str] = &["json", "jsonl", "ndjson"];
+// Records indexed between catalog commits
+pub const RECORD_BATCH_SIZE: usize = 100;
+// Longest ID field value used to name a record's document; longer ones are numbered
+const MAX_RECORD_NAME_LENGTH: usize = 128;
+
+// Which fields of a record make up its document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonMapping {
+    // Names the record's document, and is its document ID with the "provided" id_strategy
+    pub id: Option<String>,
+    // Titles the record's results in place of its file name
+    pub title: Option<String>,
+    // Searched as the record's text, a line per value; every field, as "field: value" lines,
+    // when none are given
+    pub content: Vec<String>,
+    // Stored as metadata under their paths, if they hold a string, number or boolean
+    pub metadata: Vec<String>,
+}
+
+impl JsonMapping {
+    // The document of the `number`th record of a file, stored under `directory`
+    fn document(&self, record: &Value, number: usize, directory: &Path) -> DocumentInput {
+        let id = self.id.as_deref().and_then(|path| field(record, path)).and_then(scalar_text);
+        let name = id.as_deref()
+            .filter(|id| is_record_name(id))
+            .map(str::to_string)
+            .unwrap_or_else(|| number.to_string());
+
+        let mut text = String::new();
+        if self.content.is_empty() {
+            flatten(record, "", &mut text);
+        } else {
+            for path in &self.content {
+                if let Some(value) = field(record, path) {
+                    append_text(value, &mut text);
+                }
+            }
+        }
+
+        let mut input = DocumentInput::from_bytes(directory.join(format!("{}.txt", name)), text);
+        input.id = id;
+        input.title = self.title.as_deref()
+            .and_then(|path| field(record, path))
+            .and_then(scalar_text)
+            .and_then(|title| title.lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string));
+        let metadata: Metadata = self.metadata.iter()
+            .filter_map(|path| Some((path.clone(), metadata_value(field(record, path)?)?)))
+            .collect();
+        if !self.metadata.is_empty() {
+            input.metadata = Some(metadata);
+        }
+        input
+    }
+}
+
+// A record's field by path: a key holding dots as it is, or the keys between them in nested objects
+fn field<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    record.get(path).or_else(|| path.split('.').try_fold(record, |value, key| value.get(key)))
+}
+
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+fn metadata_value(value: &Value) -> Option<MetadataValue> {
+    match value {
+        Value::Number(number) => Some(MetadataValue::Number(number.clone())),
+        Value::String(text) => Some(MetadataValue::Text(text.clone())),
+        Value::Bool(flag) => Some(MetadataValue::Text(flag.to_string())),
+        _ => None,
+    }
+}
+
+// Letters, digits, '-', '_' and '.', and not only dots, so it names one file in the records directory
+fn is_record_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_RECORD_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.chars().all(|c| c == '.')
+}
+
+// The text of a content field: a string or number on its own line, each element of an array,
+// and each field of an object as "field: value"
+fn append_text(value: &Value, text: &mut String) {
+    match value {
+        Value::Array(values) => values.iter().for_each(|value| append_text(value, text)),
+        Value::Object(_) => flatten(value, "", text),
+        value => {
+            if let Some(line) = scalar_text(value) {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+    }
+}
+
+// Every value under `value` as a "path: value" line, arrays listing theirs under the array's path
+fn flatten(value: &Value, path: &str, text: &mut String) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                flatten(value, &path, text);
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| flatten(value, path, text)),
+        value => {
+            if let Some(line) = scalar_text(value) {
+                if !path.is_empty() {
+                    text.push_str(path);
+                    text.push_str(": ");
+                }
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+    }
+}
+
+// Reads the records of a JSON file: the values it holds one after another, as in NDJSON, with
+// each top-level array standing for its elements
+fn parse_records(bytes: &[u8]) -> std::result::Result<Vec<Value>, serde_json::Error> {
+    let mut records = Vec::new();
+    for value in serde_json::Deserializer::from_slice(bytes).into_iter::<Value>() {
+        match value? {
+            Value::Array(values) => records.extend(values),
+            value => records.push(value),
+        }
+    }
+    Ok(records)
+}
+
+// Yields a document for each record of a JSON or NDJSON file
+pub struct JsonSource {
+    directory: PathBuf,
+    mapping: JsonMapping,
+    ttl: Option<Duration>,
+    acl: Option<Vec<String>>,
+    records: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+}
+
+impl JsonSource {
+    // Reads and parses the whole file; a file that isn't JSON fails before anything is indexed
+    pub async fn open(path: impl AsRef<Path>, mapping: JsonMapping) -> Result<Self> {
+        let path = path.as_ref();
+        let is_json = path.extension()
+            .map(|ext| JSON_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+            .unwrap_or(false);
+        if !is_json {
+            return Err(SearchEngineError::UnsupportedDocument(format!(
+                "only .json, .jsonl and .ndjson files hold records: {:?}", path
+            )));
+        }
+        let bytes = match async_fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SearchEngineError::DocumentNotFound(path.to_string_lossy().to_string()));
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        let file_name = path.file_name()
+            .ok_or_else(|| SearchEngineError::UnsupportedDocument(format!("{:?} has no file name", path)))?;
+        let records = tokio::task::spawn_blocking(move || parse_records(&bytes)).await
+            .map_err(|e| SearchEngineError::InvalidInput(e.to_string()))?
+            .map_err(|e| SearchEngineError::InvalidInput(format!("{:?} is not valid JSON: {}", path, e)))?;
+        let mut directory = file_name.to_os_string();
+        directory.push(".records");
+        Ok(JsonSource {
+            directory: PathBuf::from(directory),
+            mapping,
+            ttl: None,
+            acl: None,
+            records: records.into_iter().enumerate(),
+        })
+    }
+
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    // Given to every record
+    pub fn acl(mut self, principals: Vec<String>) -> Self {
+        self.acl = Some(principals);
+        self
+    }
+}
+
+impl DocumentSource for JsonSource {
+    fn next_document(&mut self) -> BoxFuture<'_, Result<Option<DocumentInput>>> {
+        let next = self.records.next().map(|(index, record)| {
+            let mut input = self.mapping.document(&record, index + 1, &self.directory).ttl(self.ttl);
+            input.acl = self.acl.clone();
+            input
+        });
+        Box::pin(async move { Ok(next) })
+    }
+}
+
+impl SearchEngine {
+    // Indexes every record of a JSON or NDJSON file as a document, as `mapping` lays it out.
+    // Records that fail, e.g. on a metadata value that is too long, are reported and skipped.
+    pub async fn index_json(
+        &self,
+        path: &str,
+        mapping: JsonMapping,
+        ttl: Option<Duration>,
+        acl: Option<Vec<String>>,
+    ) -> Result<IndexReport> {
+        self.ensure_writable()?;
+        let mut source = JsonSource::open(path, mapping).await?.ttl(ttl);
+        if let Some(acl) = acl {
+            source = source.acl(acl);
+        }
+        self.index_source_with_progress(source, RECORD_BATCH_SIZE, &NoProgress).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::SearchOptions;
+    use crate::testdir::{block_on, TestDir};
+
+    #[test]
+    fn title_filters_match_a_records_title_field() {
+        let dir = TestDir::new("records-title");
+        let files = TestDir::new("records-title-files");
+        let file = files.path().join("posts.json");
+        std::fs::write(&file, r#"[{"id": "1", "title": "First", "body": "hello"}, {"id": "2", "title": "Second", "body": "hello"}]"#).unwrap();
+        block_on(async {
+            let engine = dir.engine().await;
+            let mapping = JsonMapping { id: Some("id".to_string()), title: Some("title".to_string()), content: vec!["body".to_string()], ..Default::default() };
+            let report = engine.index_json(&file.to_string_lossy(), mapping, None, None).await.unwrap();
+            assert_eq!(report.indexed.len(), 2);
+
+            let response = engine.search("title:second hello", &SearchOptions::default()).await.unwrap();
+            assert_eq!(response.total, 1);
+            assert_eq!(engine.relative_path(Path::new(&*response.results[0].path)), "posts.json.records/2.txt");
+        });
+    }
+}
@@ -0,0 +1,210 @@
+// Search within results: a search can save every line it matched as a result set, and later
+// searches given its ID only look at those lines, so drilling down narrows the set without
+// running the query that found it again. Refining a set can save a smaller one in turn.
+// Result sets are kept as files under the index directory like point-in-time readers and
+// expire the same way, each use pushing back its expiry by its keep-alive.
+//
+// A set holds line numbers, so a document edited after the set was saved is narrowed to
+// whatever is on those lines now; a deleted one is skipped.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+use tracing::warn;
+
+use crate::checksum;
+use crate::docstore::temp_path;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::ids::random_uuid;
+use crate::reader::MAX_KEEP_ALIVE;
+use crate::{SearchEngine, SearchResult};
+
+pub const RESULT_SETS_DIR: &str = "results";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ResultSet {
+    pub id: String,
+    // The query whose matches were saved
+    pub query: String,
+    // The set that query was run within, if it refined one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub within: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub total_lines: usize,
+    pub total_documents: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResultSetState {
+    #[serde(flatten)]
+    info: ResultSet,
+    keep_alive_ms: i64,
+    // Matched line numbers of each document, by relative path
+    lines: BTreeMap<String, Vec<i64>>,
+}
+
+// The lines a search within a result set may return, by absolute document path
+pub(crate) type ResultLines = HashMap<PathBuf, HashSet<i64>>;
+
+impl SearchEngine {
+    pub(crate) fn result_sets_dir(&self) -> PathBuf {
+        self.doc_store.index_dir().join(RESULT_SETS_DIR)
+    }
+
+    fn result_set_path(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Err(SearchEngineError::InvalidInput(format!("Invalid result set ID {:?}", id)));
+        }
+        Ok(self.result_sets_dir().join(format!("{}.json", id)))
+    }
+
+    async fn write_result_set(&self, state: &ResultSetState) -> Result<()> {
+        async_fs::create_dir_all(self.result_sets_dir()).await
+            .context("Failed to create result sets directory")?;
+        let target = self.result_set_path(&state.info.id)?;
+        let temp = temp_path(&target);
+        async_fs::write(&temp, checksum::to_json(state)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist result set")?;
+        Ok(())
+    }
+
+    async fn load_result_set(&self, id: &str) -> Result<Option<ResultSetState>> {
+        match async_fs::read(self.result_set_path(id)?).await {
+            Ok(bytes) => Ok(Some(checksum::from_json(&bytes, "result set")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read result set"),
+        }
+    }
+
+    // Saves the lines of `results`, every match of `query` before paging, for `keep_alive`
+    pub(crate) async fn save_result_set(
+        &self,
+        query: &str,
+        within: Option<&str>,
+        keep_alive: Duration,
+        results: &[SearchResult],
+    ) -> Result<ResultSet> {
+        if keep_alive <= Duration::zero() || keep_alive > MAX_KEEP_ALIVE {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "Result set keep-alive must be positive and at most {} hours", MAX_KEEP_ALIVE.num_hours()
+            )));
+        }
+        self.ensure_writable()?;
+        let now = Utc::now();
+        self.expire_result_sets(now).await?;
+
+        let mut lines: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for result in results {
+            lines.entry(self.relative_path(Path::new(&*result.path))).or_default().push(result.line_number);
+        }
+        for numbers in lines.values_mut() {
+            numbers.sort_unstable();
+            numbers.dedup();
+        }
+        let state = ResultSetState {
+            info: ResultSet {
+                id: random_uuid(),
+                query: query.to_string(),
+                within: within.map(str::to_string),
+                created_at: now,
+                expires_at: now + keep_alive,
+                total_lines: lines.values().map(Vec::len).sum(),
+                total_documents: lines.len(),
+            },
+            keep_alive_ms: keep_alive.num_milliseconds(),
+            lines,
+        };
+        self.write_result_set(&state).await?;
+        Ok(state.info)
+    }
+
+    // The lines saved in a result set, renewing its keep-alive unless the engine is read-only
+    pub(crate) async fn result_set_lines(&self, id: &str) -> Result<ResultLines> {
+        let now = Utc::now();
+        let mut state = match self.load_result_set(id).await? {
+            Some(state) if state.info.expires_at > now => state,
+            _ => return Err(SearchEngineError::ReaderExpired(format!(
+                "result set {} does not exist or has expired", id
+            ))),
+        };
+        if !self.read_only {
+            state.info.expires_at = now + Duration::milliseconds(state.keep_alive_ms);
+            self.write_result_set(&state).await?;
+        }
+        Ok(state.lines.into_iter()
+            .map(|(path, numbers)| (self.absolute_path(&path), numbers.into_iter().collect()))
+            .collect())
+    }
+
+    // Returns false if no result set has the ID or it had already expired
+    pub async fn close_result_set(&self, id: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let expired = match self.load_result_set(id).await? {
+            Some(state) => state.info.expires_at <= Utc::now(),
+            None => return Ok(false),
+        };
+        match async_fs::remove_file(self.result_set_path(id)?).await {
+            Ok(()) => Ok(!expired),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).context("Failed to remove result set"),
+        }
+    }
+
+    // Saved result sets, soonest to expire first
+    pub async fn result_sets(&self) -> Result<Vec<ResultSet>> {
+        let now = Utc::now();
+        if !self.read_only {
+            self.expire_result_sets(now).await?;
+        }
+        let mut sets = Vec::new();
+        let mut entries = match async_fs::read_dir(self.result_sets_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(sets),
+            Err(e) => return Err(e).context("Failed to list result sets"),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(id) = name.strip_suffix(".json") {
+                if let Some(state) = self.load_result_set(id).await?.filter(|state| state.info.expires_at > now) {
+                    sets.push(state.info);
+                }
+            }
+        }
+        sets.sort_by_key(|set| set.expires_at);
+        Ok(sets)
+    }
+
+    // Deletes result sets whose keep-alive ran out, returning how many
+    async fn expire_result_sets(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut entries = match async_fs::read_dir(self.result_sets_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).context("Failed to list result sets"),
+        };
+        let mut expired = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+                continue;
+            }
+            let stale = match async_fs::read(&path).await {
+                Ok(bytes) => checksum::from_json::<ResultSetState>(&bytes, "result set")
+                    .map(|state| state.info.expires_at <= now)
+                    .unwrap_or(true),
+                Err(_) => continue,
+            };
+            if stale {
+                match async_fs::remove_file(&path).await {
+                    Ok(()) => expired += 1,
+                    Err(e) => warn!(path = ?path, error = %e, "Failed to remove expired result set"),
+                }
+            }
+        }
+        Ok(expired)
+    }
+}
@@ -0,0 +1,545 @@
+// Primary/replica replication over HTTP, so indexing stays on the primary while any number
+// of read-only replicas serve searches. The primary ships its change log: a replica asks for
+// the changes after the generation it last applied and gets the documents added or updated
+// since, with the attributes computed when they were indexed, and the paths of those deleted.
+// It downloads the content of just those documents and removes the deleted ones. A replica
+// that never synced, or whose generation the change log no longer covers, syncs from the
+// manifest of every indexed document instead. Every request carries the bearer token the
+// primary was started with.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Component, Path};
+use std::time::Duration;
+use tokio::fs as async_fs;
+#[cfg(feature = "server")]
+use std::sync::Arc;
+#[cfg(feature = "server")]
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::catalog::{Catalog, CatalogEntry};
+use crate::docstore::{generation, temp_path, DocumentMeta, INDEX_DIR};
+use crate::error::{Context, Result, SearchEngineError};
+#[cfg(feature = "server")]
+use crate::http;
+use crate::http::{percent_encode_path, HttpEndpoint};
+use crate::source::IndexFailure;
+use crate::trash::TRASH_DIR;
+use crate::SearchEngine;
+
+pub const REPLICA_STATE_FILE: &str = "replica.json";
+pub const DEFAULT_REPLICATION_ADDRESS: &str = "127.0.0.1:7700";
+const MANIFEST_PATH: &str = "/replication/manifest";
+// Followed by the generation to list the changes after
+const CHANGES_PATH: &str = "/replication/changes/";
+const DOCUMENTS_PATH: &str = "/replication/documents/";
+// Changes listed per request; a replica asks again for the rest
+const CHANGES_PER_REQUEST: usize = 1000;
+// Per request, so a primary that stops responding can't stall a sync forever
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Where a replica syncs from, e.g. {"primary": "http://10.0.0.5:7700", "token": "..."}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicaSource {
+    pub primary: String,
+    // Sent as a bearer token; must match the primary's `replication serve --token`
+    pub token: String,
+}
+
+// Every document the primary has indexed. `generation` changes whenever any of them does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ReplicationManifest {
+    pub generation: String,
+    // The change log's generation the documents are at; a replica asks for the changes after it
+    #[serde(default)]
+    pub change_generation: u64,
+    pub documents: Vec<DocumentMeta>,
+}
+
+// The documents added, updated or deleted after a generation, as they are now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ReplicationChanges {
+    // The generation a replica is at once it applies these
+    pub generation: u64,
+    // Added or updated
+    pub documents: Vec<DocumentMeta>,
+    pub removed: Vec<String>,
+    // More changes follow; ask again from `generation`
+    pub has_more: bool,
+    // The change log no longer covers the generation asked from, so the replica syncs from
+    // the manifest instead
+    pub resync: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SyncReport {
+    pub primary: String,
+    // The primary's change log generation the replica is now at
+    pub generation: u64,
+    // Synced from the manifest rather than the change log
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub full: bool,
+    pub downloaded: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<IndexFailure>,
+}
+
+// The version of each document a replica last downloaded, as the primary reported it
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReplicaState {
+    // The primary's change log generation last applied; None until a sync from the manifest
+    #[serde(default)]
+    applied: Option<u64>,
+    documents: BTreeMap<String, ReplicatedVersion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ReplicatedVersion {
+    size: u64,
+    modified: DateTime<Utc>,
+}
+
+impl SearchEngine {
+    // Fails for read-only engines and for replicas, which only change by syncing from their primary
+    pub(crate) fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(SearchEngineError::ReadOnly("the search directory was opened read-only".to_string()));
+        }
+        match &self.replica {
+            Some(source) => Err(SearchEngineError::ReadOnly(format!(
+                "documents can only be changed on the primary at {}", source.primary
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // Built from the index metadata on disk, so it includes documents indexed by other
+    // processes sharing the search directory
+    pub async fn replication_manifest(&self) -> Result<ReplicationManifest> {
+        let (change_generation, documents) = match self.doc_store.load_meta().await? {
+            Some(meta) => (meta.generation, meta.documents),
+            None => {
+                let catalog = self.catalog().await?;
+                (catalog.generation, catalog.documents.iter().map(|doc| self.document_meta(doc)).collect())
+            }
+        };
+        Ok(ReplicationManifest { generation: generation(&documents)?, change_generation, documents })
+    }
+
+    fn document_meta(&self, doc: &CatalogEntry) -> DocumentMeta {
+        DocumentMeta {
+            path: self.relative_path(&doc.path),
+            size: doc.size,
+            modified: doc.modified,
+            attributes: doc.attributes.clone(),
+        }
+    }
+
+    // The changes after generation `since` from the change log, at most `CHANGES_PER_REQUEST`
+    // of them, each document's collapsed into what it is now. Takes the index lock to pick up
+    // changes other processes sharing the search directory made.
+    pub async fn replication_changes(&self, since: u64) -> Result<ReplicationChanges> {
+        drop(self.lock_writes().await?);
+        let catalog = self.catalog().await?;
+        if since > catalog.generation {
+            return Ok(ReplicationChanges { generation: catalog.generation, documents: Vec::new(), removed: Vec::new(), has_more: false, resync: true });
+        }
+        let feed = self.changes_since(since, Some(CHANGES_PER_REQUEST)).await?;
+        let generation = match feed.changes.last() {
+            Some(last) if feed.has_more => last.generation,
+            _ => feed.generation,
+        };
+        let mut documents = Vec::new();
+        let mut removed = Vec::new();
+        let paths: BTreeSet<&str> = feed.changes.iter().map(|change| change.path.as_str()).collect();
+        for path in paths {
+            match catalog.get(&self.absolute_path(path)) {
+                Some(doc) => documents.push(self.document_meta(doc)),
+                None => removed.push(path.to_string()),
+            }
+        }
+        Ok(ReplicationChanges { generation, documents, removed, has_more: feed.has_more, resync: feed.resync })
+    }
+
+    // Serves the change log, manifest and document content to replicas until the process
+    // exits. Requests must carry `token` as "Authorization: Bearer <token>".
+    #[cfg(feature = "server")]
+    pub async fn serve_replication(&self, address: &str, token: &str) -> Result<()> {
+        let listener = TcpListener::bind(address).await
+            .with_context(|| format!("Failed to listen on {}", address))?;
+        info!(address = %address, "Serving replication");
+        self.serve_replication_on(listener, token).await
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) async fn serve_replication_on(&self, listener: TcpListener, token: &str) -> Result<()> {
+        if token.trim().is_empty() {
+            return Err(SearchEngineError::InvalidConfig("replication needs a token".to_string()));
+        }
+        let engine = self.clone();
+        let authorization: Arc<str> = Arc::from(format!("Bearer {}", token));
+        http::serve(listener, move |request| {
+            let engine = engine.clone();
+            let authorization = authorization.clone();
+            async move { engine.answer_replication_request(request, &authorization).await }
+        })
+        .await
+        .context("Failed to accept replication connection")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "server")]
+    async fn answer_replication_request(&self, request: http::HttpRequest, authorization: &str) -> http::HttpResponse {
+        if request.header("Authorization") != Some(authorization) {
+            return http::response(401, "text/plain", "missing or wrong replication token");
+        }
+        if request.method != hyper::Method::GET {
+            return http::response(405, "text/plain", "only GET is supported");
+        }
+        match self.replication_response(&request.path).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(path = %request.path, error = %e, "Replication request failed");
+                http::response(500, "text/plain", e.to_string())
+            }
+        }
+    }
+
+    #[cfg(feature = "server")]
+    async fn replication_response(&self, path: &str) -> Result<http::HttpResponse> {
+        if let Some(since) = path.strip_prefix(CHANGES_PATH) {
+            let Ok(since) = since.parse() else {
+                return Ok(http::response(400, "text/plain", "expected a generation after /replication/changes/"));
+            };
+            return Ok(http::response(200, "application/json", serde_json::to_vec(&self.replication_changes(since).await?)?));
+        }
+        let manifest = self.replication_manifest().await?;
+        if path == MANIFEST_PATH {
+            return Ok(http::response(200, "application/json", serde_json::to_vec(&manifest)?));
+        }
+
+        // Only documents in the manifest are served, which also rules out paths outside the search
+        // directory and the search roots
+        let requested = path.strip_prefix(DOCUMENTS_PATH)
+            .filter(|path| manifest.documents.iter().any(|doc| doc.path == *path));
+        let Some(relative) = requested else {
+            return Ok(http::response(404, "text/plain", "not found"));
+        };
+        let file_path = self.absolute_path(relative);
+        let Some(stored) = self.storage.stat(&file_path).await? else {
+            return Ok(http::response(404, "text/plain", "not found"));
+        };
+        let content = self.storage.open(&file_path).await?;
+        Ok(http::stream_response("application/octet-stream", stored.size, content))
+    }
+
+    fn replica_state_path(&self) -> std::path::PathBuf {
+        self.doc_store.index_dir().join(REPLICA_STATE_FILE)
+    }
+
+    async fn load_replica_state(&self) -> Result<ReplicaState> {
+        match async_fs::read(self.replica_state_path()).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).context("Failed to parse replica state")?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ReplicaState::default()),
+            Err(e) => Err(e).context("Failed to read replica state"),
+        }
+    }
+
+    async fn save_replica_state(&self, state: &ReplicaState) -> Result<()> {
+        async_fs::create_dir_all(self.doc_store.index_dir()).await
+            .context("Failed to create index directory")?;
+        let target = self.replica_state_path();
+        let temp = temp_path(&target);
+        async_fs::write(&temp, serde_json::to_vec_pretty(state)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist replica state")?;
+        Ok(())
+    }
+
+    // Brings a replica up to date with its primary
+    pub async fn sync_replica(&self) -> Result<SyncReport> {
+        if self.read_only {
+            self.ensure_writable()?;
+        }
+        let _write = self.lock_writes().await?;
+        let report = self.replicate().await;
+        let documents = match &report {
+            Ok(report) => report.downloaded.iter().chain(&report.removed).cloned().collect(),
+            Err(_) => Vec::new(),
+        };
+        self.audit("replicate", documents, report.as_ref().map(|_| ()).map_err(|e| e.to_string())).await;
+        report
+    }
+
+    // `sync_replica` for callers already holding the write lock
+    pub(crate) async fn replicate(&self) -> Result<SyncReport> {
+        let source = self.replica.as_ref().ok_or_else(|| SearchEngineError::InvalidConfig(
+            "this engine is not a replica; set replica_of in the config".to_string(),
+        ))?;
+        let primary = HttpEndpoint::parse(&source.primary)?;
+        let token = source.token.as_str();
+
+        let mut state = self.load_replica_state().await?;
+        let mut report = SyncReport {
+            primary: primary.url().to_string(),
+            generation: 0,
+            full: false,
+            downloaded: Vec::new(),
+            removed: Vec::new(),
+            unchanged: 0,
+            failed: Vec::new(),
+        };
+        let applied = match state.applied {
+            Some(since) => self.replicate_changes(&primary, token, since, &mut state, &mut report).await?,
+            None => false,
+        };
+        if !applied {
+            report.full = true;
+            self.replicate_manifest(&primary, token, &mut state, &mut report).await?;
+        }
+
+        self.commit_catalog().await?;
+        // The change log moves past what failed, so the next sync compares the whole manifest
+        state.applied = report.failed.is_empty().then_some(report.generation);
+        self.save_replica_state(&state).await?;
+        info!(
+            full = report.full,
+            downloaded = report.downloaded.len(),
+            removed = report.removed.len(),
+            failed = report.failed.len(),
+            "Synced replica"
+        );
+        Ok(report)
+    }
+
+    // Applies the primary's changes after generation `since`; false if the change log no
+    // longer covers it, leaving the replica to sync from the manifest
+    async fn replicate_changes(&self, primary: &HttpEndpoint, token: &str, mut since: u64, state: &mut ReplicaState, report: &mut SyncReport) -> Result<bool> {
+        loop {
+            let body = fetch(primary, &format!("{}{}", CHANGES_PATH, since), token).await
+                .context("Failed to fetch replication changes")?;
+            let changes: ReplicationChanges = serde_json::from_slice(&body)
+                .context("Failed to parse replication changes")?;
+            if changes.resync {
+                return Ok(false);
+            }
+            let catalog = self.catalog().await?;
+            for doc in &changes.documents {
+                self.replicate_document(primary, token, doc, &catalog, state, report).await;
+            }
+            for path in &changes.removed {
+                let file_path = self.search_path.join(path);
+                if catalog.get(&file_path).is_some() {
+                    self.replicate_removal(&file_path, state, report).await;
+                }
+            }
+            since = changes.generation;
+            report.generation = since;
+            if !changes.has_more {
+                return Ok(true);
+            }
+        }
+    }
+
+    // Downloads what differs from the primary's manifest and removes what it doesn't list
+    async fn replicate_manifest(&self, primary: &HttpEndpoint, token: &str, state: &mut ReplicaState, report: &mut SyncReport) -> Result<()> {
+        let body = fetch(primary, MANIFEST_PATH, token).await
+            .context("Failed to fetch replication manifest")?;
+        let manifest: ReplicationManifest = serde_json::from_slice(&body)
+            .context("Failed to parse replication manifest")?;
+
+        let catalog = self.catalog().await?;
+        for doc in &manifest.documents {
+            self.replicate_document(primary, token, doc, &catalog, state, report).await;
+        }
+
+        let live: HashSet<&str> = manifest.documents.iter().map(|doc| doc.path.as_str()).collect();
+        for doc in &catalog.documents {
+            if !live.contains(self.relative_path(&doc.path).as_str()) {
+                self.replicate_removal(&doc.path, state, report).await;
+            }
+        }
+        report.generation = manifest.change_generation;
+        Ok(())
+    }
+
+    // Downloads a document unless this version of it is already here
+    async fn replicate_document(&self, primary: &HttpEndpoint, token: &str, doc: &DocumentMeta, catalog: &Catalog, state: &mut ReplicaState, report: &mut SyncReport) {
+        let version = ReplicatedVersion { size: doc.size, modified: doc.modified };
+        if state.documents.get(&doc.path) == Some(&version) && catalog.get(&self.search_path.join(&doc.path)).is_some() {
+            report.unchanged += 1;
+            return;
+        }
+        match self.download_document(primary, token, doc).await {
+            Ok(()) => {
+                state.documents.insert(doc.path.clone(), version);
+                report.downloaded.push(doc.path.clone());
+            }
+            Err(e) => {
+                warn!(path = %doc.path, error = %e, "Failed to replicate document");
+                report.failed.push(IndexFailure { path: doc.path.clone(), error: e.to_string() });
+            }
+        }
+    }
+
+    async fn replicate_removal(&self, file_path: &Path, state: &mut ReplicaState, report: &mut SyncReport) {
+        let relative = self.relative_path(file_path);
+        match self.remove_document(file_path).await {
+            Ok(()) => {
+                state.documents.remove(&relative);
+                report.removed.push(relative);
+            }
+            Err(e) => {
+                warn!(path = %relative, error = %e, "Failed to remove document deleted on the primary");
+                report.failed.push(IndexFailure { path: relative, error: e.to_string() });
+            }
+        }
+    }
+
+    // Stores the primary's copy of a document with the attributes the primary computed,
+    // leaving the catalog uncommitted
+    async fn download_document(&self, primary: &HttpEndpoint, token: &str, doc: &DocumentMeta) -> Result<()> {
+        let relative = Path::new(&doc.path);
+        let inside = relative.components().all(|part| matches!(part, Component::Normal(_)));
+        if !inside || relative.starts_with(INDEX_DIR) || relative.starts_with(TRASH_DIR) {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "{:?} must be a relative path inside the search directory", doc.path
+            )));
+        }
+
+        let content = fetch(primary, &format!("{}{}", DOCUMENTS_PATH, percent_encode_path(&doc.path)), token).await
+            .context("Failed to download document")?;
+        let target = self.search_path.join(relative);
+        let temp = temp_path(&target);
+        let written = self.storage.write(&temp, Box::pin(std::io::Cursor::new(content))).await?;
+        if let Err(e) = self.storage.rename(&temp, &target).await {
+            let _ = self.storage.remove(&temp).await;
+            return Err(e);
+        }
+
+        self.doc_store.offsets(&target).await?;
+        self.postings_store.postings(&target).await?;
+        if let Err(e) = self.embed_document(&target).await {
+            warn!(path = ?target, error = %e, "Failed to embed document");
+        }
+        let entry = CatalogEntry {
+            path: target,
+            size: written.size,
+            modified: written.modified,
+            attributes: doc.attributes.clone(),
+        };
+        self.update_catalog(|catalog| catalog.insert(entry)).await?;
+        Ok(())
+    }
+}
+
+async fn fetch(primary: &HttpEndpoint, path: &str, token: &str) -> std::io::Result<Vec<u8>> {
+    match tokio::time::timeout(REQUEST_TIMEOUT, primary.get(path, Some(token))).await {
+        Ok(response) => response,
+        Err(_) => Err(std::io::ErrorKind::TimedOut.into()),
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::options::SearchOptions;
+    use crate::source::DocumentInput;
+    use crate::testdir::{block_on, index, TestDir};
+
+    const TOKEN: &str = "secret";
+
+    // Serves `primary` on a free port, returning its URL
+    async fn serve(primary: &SearchEngine) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let primary = primary.clone();
+        tokio::spawn(async move { primary.serve_replication_on(listener, TOKEN).await });
+        url
+    }
+
+    async fn replica(dir: &TestDir, primary: &str, token: &str) -> Result<SearchEngine> {
+        let source = ReplicaSource { primary: primary.to_string(), token: token.to_string() };
+        SearchEngine::builder(dir.path()).replica_of(Some(source)).build().await
+    }
+
+    async fn total(engine: &SearchEngine, query: &str) -> usize {
+        engine.search(query, &SearchOptions::default()).await.unwrap().total
+    }
+
+    #[test]
+    fn replicas_apply_the_change_log() {
+        let primary_dir = TestDir::new("replication-primary");
+        let replica_dir = TestDir::new("replication-replica");
+        block_on(async {
+            let primary = primary_dir.engine().await;
+            index(&primary, vec![
+                DocumentInput::from_bytes("a.txt", "alpha\n"),
+                DocumentInput::from_bytes("b.txt", "beta\n"),
+            ]).await;
+            let url = serve(&primary).await;
+            let replica = replica(&replica_dir, &url, TOKEN).await.unwrap();
+
+            let report = replica.sync_replica().await.unwrap();
+            assert!(report.full);
+            assert_eq!(report.downloaded, ["a.txt", "b.txt"]);
+            assert_eq!(report.generation, 2);
+            assert_eq!(total(&replica, "beta").await, 1);
+
+            // Only what changed since is shipped
+            index(&primary, vec![
+                DocumentInput::from_bytes("b.txt", "beta gamma\n"),
+                DocumentInput::from_bytes("c.txt", "delta\n"),
+            ]).await;
+            assert!(primary.delete_document("a.txt").await.unwrap());
+            let report = replica.sync_replica().await.unwrap();
+            assert!(!report.full);
+            assert_eq!(report.downloaded, ["b.txt", "c.txt"]);
+            assert_eq!(report.removed, ["a.txt"]);
+            assert_eq!(report.unchanged, 0);
+            assert_eq!(report.generation, primary.generation().await.unwrap());
+            assert_eq!(total(&replica, "alpha").await, 0);
+            assert_eq!(total(&replica, "gamma").await, 1);
+            assert_eq!(total(&replica, "delta").await, 1);
+
+            let report = replica.sync_replica().await.unwrap();
+            assert!(!report.full);
+            assert!(report.downloaded.is_empty() && report.removed.is_empty());
+
+            // With the change log gone the replica compares the whole manifest
+            index(&primary, vec![DocumentInput::from_bytes("d.txt", "epsilon\n")]).await;
+            std::fs::remove_file(primary_dir.path().join(INDEX_DIR).join(crate::changes::CHANGES_FILE)).unwrap();
+            let report = replica.sync_replica().await.unwrap();
+            assert!(report.full);
+            assert_eq!(report.downloaded, ["d.txt"]);
+            assert_eq!(report.unchanged, 2);
+            assert_eq!(total(&replica, "epsilon").await, 1);
+        });
+    }
+
+    #[test]
+    fn replication_requires_the_token() {
+        let primary_dir = TestDir::new("replication-token-primary");
+        let replica_dir = TestDir::new("replication-token-replica");
+        block_on(async {
+            let primary = primary_dir.engine().await;
+            index(&primary, vec![DocumentInput::from_bytes("a.txt", "alpha\n")]).await;
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            assert!(matches!(primary.serve_replication_on(listener, " ").await, Err(SearchEngineError::InvalidConfig(_))));
+            let url = serve(&primary).await;
+            assert!(matches!(replica(&replica_dir, &url, "").await, Err(SearchEngineError::InvalidConfig(_))));
+
+            let replica = replica(&replica_dir, &url, "wrong").await.unwrap();
+            assert!(replica.sync_replica().await.is_err());
+            assert_eq!(total(&replica, "alpha").await, 0);
+        });
+    }
+}
@@ -0,0 +1,122 @@
+// Extra directories searched in place alongside the search directory, such as `~/docs` or
+// `/var/log/app`. Their documents are never copied, moved or deleted; each shows up under
+// its root's name, so `manuals/setup.txt` is `setup.txt` in the root named "manuals".
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{Result, SearchEngineError};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchRoot {
+    pub path: PathBuf,
+    // Prefix of the root's documents in results and filters; defaults to the directory's name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Glob patterns such as "*.md" or "guides/**"; when set, only matching files are searched.
+    // A pattern without a '/' is matched against the file name, otherwise against the whole
+    // path within the root. Files still need one of the configured extensions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // Glob patterns of files to leave out, even if an include pattern matches them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl SearchRoot {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        SearchRoot { path: path.as_ref().to_path_buf(), ..SearchRoot::default() }
+    }
+
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        }
+    }
+
+    // Whether the file at `relative`, a '/'-separated path within the root, is searched
+    pub(crate) fn admits(&self, relative: &str) -> bool {
+        let matches = |pattern: &String| pattern_matches(pattern, relative);
+        (self.include.is_empty() || self.include.iter().any(matches)) && !self.exclude.iter().any(matches)
+    }
+
+    // Where the document at `relative` within the root is stored
+    pub(crate) fn document_path(&self, relative: &str) -> PathBuf {
+        self.path.join(relative)
+    }
+
+    // The path within the root, '/'-separated, if `file_path` is under it
+    pub(crate) fn relative_path(&self, file_path: &Path) -> Option<String> {
+        let relative = file_path.strip_prefix(&self.path).ok()?;
+        let parts: Vec<String> = relative.components()
+            .map(|part| match part {
+                Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Some(parts.join("/"))
+    }
+}
+
+// Checks root names and patterns; `search_path` may not overlap any root
+pub(crate) fn validate_roots(roots: &[SearchRoot], search_path: &Path) -> Result<()> {
+    let invalid = |message: String| Err(SearchEngineError::InvalidConfig(message));
+    let mut names = Vec::new();
+    for root in roots {
+        let name = root.name();
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return invalid(format!("root {:?} needs a name without slashes or a leading dot", root.path));
+        }
+        if names.contains(&name) {
+            return invalid(format!("two roots are named {:?}", name));
+        }
+        if root.path.starts_with(search_path) || search_path.starts_with(&root.path) {
+            return invalid(format!("root {:?} overlaps the search directory", root.path));
+        }
+        if let Some(pattern) = root.include.iter().chain(&root.exclude).find(|pattern| pattern.is_empty()) {
+            return invalid(format!("root {:?} has an empty pattern {:?}", name, pattern));
+        }
+        names.push(name);
+    }
+    Ok(())
+}
+
+// A pattern without a '/' is matched against the file name of `relative`, a '/'-separated
+// path, otherwise against the whole of it
+pub(crate) fn pattern_matches(pattern: &str, relative: &str) -> bool {
+    if pattern.contains('/') {
+        glob_matches(pattern, relative)
+    } else {
+        glob_matches(pattern, relative.rsplit('/').next().unwrap_or(relative))
+    }
+}
+
+// `*` matches within one path segment, `?` one character and `**` any number of segments
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    segments_match(&pattern, &path)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| segments_match(rest, &path[skip..])),
+        Some((segment, rest)) => path.split_first().is_some_and(|(name, path)| {
+            let segment: Vec<char> = segment.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            segment_matches(&segment, &name) && segments_match(rest, path)
+        }),
+    }
+}
+
+fn segment_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| segment_matches(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && segment_matches(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && segment_matches(rest, &name[1..]),
+    }
+}
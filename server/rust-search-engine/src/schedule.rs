@@ -0,0 +1,206 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Result, SearchEngineError};
+use crate::{MaintenanceResult, MaintenanceTask, SearchEngine};
+
+// A maintenance task run on a cron schedule, e.g. {"cron": "0 * * * *", "task": "refresh"}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleEntry {
+    pub cron: String,
+    #[serde(flatten)]
+    pub task: MaintenanceTask,
+}
+
+// Standard five-field cron expression (minute hour day-of-month month day-of-week),
+// evaluated in UTC. Fields accept `*`, numbers, ranges (`1-5`), lists (`1,15`) and steps (`*/10`).
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days_of_month: Vec<bool>,
+    months: Vec<bool>,
+    days_of_week: Vec<bool>,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(SearchEngineError::InvalidInput(format!("Cron expression must have 5 fields: {:?}", expression)));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 mean Sunday
+        if days_of_week[7] {
+            days_of_week[0] = true;
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            day_of_month_restricted: fields[2] != "*",
+            day_of_week_restricted: fields[4] != "*",
+        })
+    }
+
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let dom = self.days_of_month[time.day() as usize];
+        let dow = self.days_of_week[time.weekday().num_days_from_sunday() as usize];
+        // Like cron, a restricted day-of-month and day-of-week match if either does
+        match (self.day_of_month_restricted, self.day_of_week_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+
+    // First matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // Bounded so impossible expressions (e.g. 31 February) terminate
+        for _ in 0..100_000 {
+            if !self.months[time.month() as usize] {
+                let (year, month) = if time.month() == 12 { (time.year() + 1, 1) } else { (time.year(), time.month() + 1) };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.day_matches(&time) {
+                time = (time + Duration::days(1)).with_hour(0)?.with_minute(0)?;
+            } else if !self.hours[time.hour() as usize] {
+                time = (time + Duration::hours(1)).with_minute(0)?;
+            } else if !self.minutes[time.minute() as usize] {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+fn parse_number(text: &str, part: &str) -> Result<u32> {
+    text.parse()
+        .map_err(|_| SearchEngineError::InvalidInput(format!("Invalid cron field {:?}", part)))
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max as usize + 1];
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_number(step, part)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(SearchEngineError::InvalidInput(format!("Cron step must be positive: {:?}", part)));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_number(start, part)?, parse_number(end, part)?)
+        } else {
+            let value = parse_number(range, part)?;
+            // `5/15` means "from 5 to the end, every 15"
+            (value, if part.contains('/') { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(SearchEngineError::InvalidInput(format!("Cron field {:?} out of range {}-{}", part, min, max)));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+
+    Ok(allowed)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ScheduleStatus {
+    pub cron: String,
+    pub task: String,
+    pub next_run: Option<DateTime<Utc>>,
+    pub last_run: Option<MaintenanceResult>,
+}
+
+struct ScheduledJob {
+    entry: ScheduleEntry,
+    schedule: CronSchedule,
+    next_run: Option<DateTime<Utc>>,
+    last_run: Option<MaintenanceResult>,
+}
+
+// Runs configured maintenance tasks when they fall due while the server is running
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+}
+
+impl Scheduler {
+    pub fn new(entries: &[ScheduleEntry]) -> Result<Self> {
+        let now = Utc::now();
+        let jobs = entries.iter()
+            .map(|entry| {
+                let schedule = CronSchedule::parse(&entry.cron)?;
+                Ok(ScheduledJob {
+                    next_run: schedule.next_after(now),
+                    schedule,
+                    entry: entry.clone(),
+                    last_run: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Scheduler { jobs })
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.jobs.iter().filter_map(|job| job.next_run).min()
+    }
+
+    // Runs every job whose time has come and schedules its next occurrence
+    pub async fn run_due(&mut self, engine: &SearchEngine) {
+        let now = Utc::now();
+        for job in &mut self.jobs {
+            if job.next_run.map(|next| next > now).unwrap_or(true) {
+                continue;
+            }
+
+            let result = match engine.run_maintenance(&job.entry.task).await {
+                Ok(result) => result,
+                Err(e) => MaintenanceResult {
+                    task: job.entry.task.name().to_string(),
+                    success: false,
+                    message: e.to_string(),
+                    executed_at: Utc::now(),
+                    dry_run: false,
+                    affected: Vec::new(),
+                },
+            };
+            if !result.success {
+                warn!(task = %result.task, message = %result.message, "Scheduled maintenance failed");
+            }
+
+            job.last_run = Some(result);
+            job.next_run = job.schedule.next_after(now);
+        }
+    }
+
+    pub fn status(&self) -> Vec<ScheduleStatus> {
+        self.jobs.iter()
+            .map(|job| ScheduleStatus {
+                cron: job.entry.cron.clone(),
+                task: job.entry.task.name().to_string(),
+                next_run: job.next_run,
+                last_run: job.last_run.clone(),
+            })
+            .collect()
+    }
+}
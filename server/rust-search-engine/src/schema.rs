@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+// Version of the JSON the CLI, server mode and C API emit. Within a version:
+// - fields may be added to any object, so consumers must ignore fields they don't know
+// - optional fields may be left out when unset, so consumers must treat missing as unset
+// - enums such as error codes or match modes may gain values
+// Removing or renaming a field, changing its type or meaning, or dropping an enum value
+// bumps the version. Response structs are #[non_exhaustive] so Rust callers can't
+// depend on the exact field set either.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Every response written as JSON, e.g. {"schema_version": 1, "data": {"query": ...}}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Versioned<T> {
+    pub schema_version: u32,
+    pub data: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(data: T) -> Self {
+        Versioned {
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+}
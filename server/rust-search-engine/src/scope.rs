@@ -0,0 +1,126 @@
+// Keeps paths from untrusted requests inside the directories they may touch. The JSON-lines
+// server checks every path it is asked to index, delete or restore, and every directory
+// maintenance writes to, before the engine sees it.
+
+use std::path::{Component, Path, PathBuf};
+use tokio::fs as async_fs;
+
+use crate::error::{Context, Result, SearchEngineError};
+
+#[derive(Debug, Clone)]
+pub struct PathScope {
+    // Canonical search directory; documents are only ever deleted or restored inside it
+    search_root: PathBuf,
+    // Canonical directories documents may be indexed from, the search directory first
+    roots: Vec<PathBuf>,
+}
+
+fn forbidden(path: &str, reason: &str) -> SearchEngineError {
+    SearchEngineError::Forbidden(format!("{:?} {}", path, reason))
+}
+
+fn has_parent_components(path: &str) -> bool {
+    Path::new(path).components().any(|component| component == Component::ParentDir)
+}
+
+async fn canonical_root(root: &Path) -> Result<PathBuf> {
+    async_fs::canonicalize(root).await
+        .map_err(|e| SearchEngineError::InvalidConfig(format!("allowed root {:?} is not accessible: {}", root, e)))
+}
+
+impl PathScope {
+    // Allows the search directory and each of `allowed_roots`, which must exist
+    pub async fn new(search_path: &Path, allowed_roots: &[PathBuf]) -> Result<Self> {
+        let search_root = async_fs::canonicalize(search_path).await
+            .context("Failed to resolve search directory")?;
+        let mut roots = vec![search_root.clone()];
+        for root in allowed_roots {
+            let root = canonical_root(root).await?;
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        Ok(PathScope { search_root, roots })
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    // A file to index must resolve, after following symlinks, to somewhere under an allowed root
+    pub async fn check_source(&self, path: &str) -> Result<()> {
+        if has_parent_components(path) {
+            return Err(forbidden(path, "must not contain '..'"));
+        }
+        let canonical = match async_fs::canonicalize(path).await {
+            Ok(canonical) => canonical,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SearchEngineError::DocumentNotFound(path.to_string()));
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to resolve {:?}", path)),
+        };
+        if self.roots.iter().any(|root| canonical.starts_with(root)) {
+            Ok(())
+        } else {
+            Err(forbidden(path, "is outside the directories documents may be indexed from"))
+        }
+    }
+
+    // A directory maintenance writes to, such as a backup or archive destination, which may not
+    // exist yet: what exists of it must resolve under one of the allowed roots, and not into the
+    // search directory, where copies of documents would be picked up as documents themselves
+    pub async fn check_destination(&self, path: &Path) -> Result<()> {
+        let shown = path.to_string_lossy();
+        if has_parent_components(&shown) {
+            return Err(forbidden(&shown, "must not contain '..'"));
+        }
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            std::env::current_dir().context("Failed to resolve working directory")?.join(path)
+        };
+        // Resolve the deepest directory that exists, and put the missing rest back on
+        let mut existing = absolute.as_path();
+        let mut missing = Vec::new();
+        let resolved = loop {
+            match async_fs::canonicalize(existing).await {
+                Ok(canonical) => break canonical,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                        return Err(forbidden(&shown, "does not resolve to a directory"));
+                    };
+                    missing.push(name);
+                    existing = parent;
+                }
+                Err(e) => return Err(e).with_context(|| format!("Failed to resolve {:?}", path)),
+            }
+        };
+        let resolved = missing.iter().rev().fold(resolved, |resolved, name| resolved.join(name));
+        if resolved.starts_with(&self.search_root) {
+            return Err(forbidden(&shown, "is inside the search directory"));
+        }
+        if self.roots.iter().any(|root| resolved.starts_with(root)) {
+            Ok(())
+        } else {
+            Err(forbidden(&shown, "is outside the allowed roots"))
+        }
+    }
+
+    // A document to delete or restore is named by a path relative to the search directory, an
+    // absolute path inside it, or a document ID
+    pub async fn check_document(&self, path: &str) -> Result<()> {
+        if has_parent_components(path) {
+            return Err(forbidden(path, "must not contain '..'"));
+        }
+        let requested = Path::new(path);
+        if !requested.is_absolute() {
+            return Ok(());
+        }
+        let resolved = async_fs::canonicalize(requested).await.unwrap_or_else(|_| requested.to_path_buf());
+        if resolved.starts_with(&self.search_root) {
+            Ok(())
+        } else {
+            Err(forbidden(path, "is outside the search directory"))
+        }
+    }
+}
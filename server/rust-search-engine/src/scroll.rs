@@ -0,0 +1,292 @@
+// Scrolling through every match of a query, for exports and other jobs that need the whole
+// result set rather than its best page. A scroll pins the documents with a point-in-time
+// reader and returns matches in batches, document by document in index order and by line
+// within each, so no match is returned twice or left out however many batches it takes.
+// Batches aren't ranked or capped by `max_matches`, which bounds one search's memory rather
+// than a scroll's, as each batch only holds its own matches.
+//
+// Scrolls are kept as files under the index directory like readers and result sets, each
+// batch pushing back the expiry by the keep-alive, and are deleted once drained. A document
+// changed in place mid-scroll is searched as it is now, so lines may shift past the cursor.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs as async_fs;
+use tracing::{instrument, warn};
+
+use crate::analysis;
+use crate::bm25::Scorer;
+use crate::checksum;
+use crate::docstore::temp_path;
+use crate::engine::describe_results;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::ids::random_uuid;
+use crate::matching::Matcher;
+use crate::normalize::normalize_query;
+use crate::options::{MatchMode, SearchFilters, SearchLimits, SearchOptions};
+use crate::profile::Profiler;
+use crate::query::parse_query;
+use crate::reader::{DEFAULT_KEEP_ALIVE, MAX_KEEP_ALIVE};
+use crate::{SearchEngine, SearchResult};
+
+pub const SCROLLS_DIR: &str = "scrolls";
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+pub const MAX_BATCH_SIZE: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ScrollPage {
+    pub scroll_id: String,
+    pub results: Vec<SearchResult>,
+    // Matches returned by this scroll so far, this batch included
+    pub returned: usize,
+    // Every match has been returned and the scroll is closed
+    pub done: bool,
+    // A query term expanded to more indexed terms than allowed, so matches may be missing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub limited: bool,
+}
+
+// Where the next batch starts: a document of the reader, and how many of its matches
+// earlier batches returned
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Cursor {
+    document: usize,
+    skip: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScrollState {
+    id: String,
+    query: String,
+    mode: MatchMode,
+    case_sensitive: bool,
+    fuzzy: Option<usize>,
+    highlight: bool,
+    filters: SearchFilters,
+    limits: SearchLimits,
+    reader: String,
+    // The reader was opened for this scroll, so it closes with it
+    owns_reader: bool,
+    batch_size: usize,
+    keep_alive_ms: i64,
+    expires_at: DateTime<Utc>,
+    returned: usize,
+    cursor: Cursor,
+}
+
+impl SearchEngine {
+    pub(crate) fn scrolls_dir(&self) -> PathBuf {
+        self.doc_store.index_dir().join(SCROLLS_DIR)
+    }
+
+    fn scroll_path(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Err(SearchEngineError::InvalidInput(format!("Invalid scroll ID {:?}", id)));
+        }
+        Ok(self.scrolls_dir().join(format!("{}.json", id)))
+    }
+
+    async fn save_scroll(&self, state: &ScrollState) -> Result<()> {
+        async_fs::create_dir_all(self.scrolls_dir()).await
+            .context("Failed to create scrolls directory")?;
+        let target = self.scroll_path(&state.id)?;
+        let temp = temp_path(&target);
+        async_fs::write(&temp, checksum::to_json(state)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist scroll")?;
+        Ok(())
+    }
+
+    async fn load_scroll(&self, id: &str) -> Result<Option<ScrollState>> {
+        match async_fs::read(self.scroll_path(id)?).await {
+            Ok(bytes) => Ok(Some(checksum::from_json(&bytes, "scroll")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read scroll"),
+        }
+    }
+
+    // Starts scrolling through every match of `query` and returns the first batch of up to
+    // `batch_size`. Of the options, the mode, case sensitivity, fuzziness, filters, highlighting
+    // and limits on query expansion apply; without a reader, one is opened for the scroll and
+    // lives as long as it does.
+    #[instrument(skip(self, options))]
+    pub async fn open_scroll(&self, query: &str, options: &SearchOptions, batch_size: usize, keep_alive: Option<Duration>) -> Result<ScrollPage> {
+        let keep_alive = keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE);
+        if keep_alive <= Duration::zero() || keep_alive > MAX_KEEP_ALIVE {
+            return Err(SearchEngineError::InvalidInput(format!(
+                "Scroll keep-alive must be positive and at most {} hours", MAX_KEEP_ALIVE.num_hours()
+            )));
+        }
+        if batch_size == 0 || batch_size > MAX_BATCH_SIZE {
+            return Err(SearchEngineError::InvalidInput(format!("Scroll batch size must be between 1 and {}", MAX_BATCH_SIZE)));
+        }
+        self.ensure_writable()?;
+        let query = normalize_query(query, options.mode)?;
+        // Fail on a bad query before pinning anything for it
+        parse_query(&query, options.mode, options.fuzzy, options.case_sensitive)?;
+        options.validate()?;
+        self.expire_scrolls(Utc::now()).await?;
+
+        let (reader, owns_reader) = match &options.reader {
+            Some(reader) => (reader.clone(), false),
+            None => (self.open_reader(Some(keep_alive)).await?.id, true),
+        };
+        let mut state = ScrollState {
+            id: random_uuid(),
+            query,
+            mode: options.mode,
+            case_sensitive: options.case_sensitive,
+            fuzzy: options.fuzzy,
+            highlight: options.highlight,
+            filters: options.filters.clone(),
+            limits: options.limits,
+            reader,
+            owns_reader,
+            batch_size,
+            keep_alive_ms: keep_alive.num_milliseconds(),
+            expires_at: Utc::now() + keep_alive,
+            returned: 0,
+            cursor: Cursor::default(),
+        };
+        self.next_batch(&mut state).await
+    }
+
+    // The next batch of a scroll; the last one is marked done and closes the scroll
+    #[instrument(skip(self))]
+    pub async fn scroll(&self, id: &str) -> Result<ScrollPage> {
+        self.ensure_writable()?;
+        let mut state = match self.load_scroll(id).await? {
+            Some(state) if state.expires_at > Utc::now() => state,
+            _ => return Err(SearchEngineError::ReaderExpired(format!(
+                "scroll {} does not exist or has expired", id
+            ))),
+        };
+        self.next_batch(&mut state).await
+    }
+
+    // Returns false if no scroll has the ID or it had already expired
+    pub async fn close_scroll(&self, id: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let Some(state) = self.load_scroll(id).await? else {
+            return Ok(false);
+        };
+        self.remove_scroll(&state).await?;
+        Ok(state.expires_at > Utc::now())
+    }
+
+    async fn remove_scroll(&self, state: &ScrollState) -> Result<()> {
+        match async_fs::remove_file(self.scroll_path(&state.id)?).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to remove scroll"),
+        }
+        if state.owns_reader {
+            self.close_reader(&state.reader).await?;
+        }
+        Ok(())
+    }
+
+    async fn next_batch(&self, state: &mut ScrollState) -> Result<ScrollPage> {
+        let mut parsed = parse_query(&state.query, state.mode, state.fuzzy, state.case_sensitive)?;
+        if state.mode != MatchMode::Regex && !state.case_sensitive {
+            parsed.tree = self.synonyms().expand(&parsed.text, parsed.tree.take());
+        }
+        let limits = self.limits.tightest(state.limits);
+        let analyzers = analysis::current(&self.analyzers);
+        let base = Matcher::parsed(&parsed, state.mode, state.fuzzy, state.case_sensitive, &limits)?;
+        let matchers: Vec<Matcher> = analyzers.all().map(|analysis| base.clone().with_analysis(analysis)).collect();
+        let mut filters = state.filters.clone();
+        parsed.fields.apply(&mut filters);
+
+        let tables = self.shared.index_lock.read().await;
+        let catalog = self.reader_catalog(&state.reader).await?;
+        let stats = if Scorer::needs_stats(&matchers[0]) {
+            self.corpus_stats(&catalog, false).await
+        } else {
+            Default::default()
+        };
+        let scorers: Vec<Scorer> = matchers.iter().map(|matcher| Scorer::new(matcher, self.bm25, &stats)).collect();
+        let mut profiler = Profiler::new(false);
+        let mut results = Vec::new();
+        let mut limited = false;
+        let mut cursor = state.cursor;
+        while results.len() < state.batch_size {
+            let Some(doc) = catalog.documents.get(cursor.document) else {
+                break;
+            };
+            let (document, skip) = (cursor.document, cursor.skip);
+            cursor = Cursor { document: document + 1, skip: 0 };
+            let wanted = state.batch_size - results.len();
+            if !filters.matches(&self.relative_path(&doc.path), doc.attributes.title.as_deref()) || !filters.matches_attributes(&doc.attributes.tags, &doc.attributes.metadata, doc.attributes.language.as_deref(), doc.modified, &doc.attributes.acl) {
+                continue;
+            }
+            // One more than wanted, to tell whether the document has matches left
+            let max_results = skip + wanted + 1;
+            let scorer = &scorers[analyzers.index_for(&doc.path)];
+            let found = match self.search_document(&doc.path, scorer, state.highlight, max_results, None, &mut profiler).await {
+                Ok(found) => found,
+                Err(e) => {
+                    warn!(path = ?doc.path, error = %e, "Failed to search file");
+                    continue;
+                }
+            };
+            limited |= found.limited;
+            let mut found: Vec<SearchResult> = found.results.into_iter().skip(skip).collect();
+            if found.len() > wanted {
+                found.truncate(wanted);
+                cursor = Cursor { document, skip: skip + wanted };
+            }
+            describe_results(doc, document, &mut found);
+            results.extend(found);
+        }
+        drop(tables);
+
+        state.cursor = cursor;
+        state.returned += results.len();
+        let done = cursor.document >= catalog.documents.len();
+        if done {
+            self.remove_scroll(state).await?;
+        } else {
+            state.expires_at = Utc::now() + Duration::milliseconds(state.keep_alive_ms);
+            self.save_scroll(state).await?;
+        }
+        Ok(ScrollPage {
+            scroll_id: state.id.clone(),
+            results,
+            returned: state.returned,
+            done,
+            limited,
+        })
+    }
+
+    // Deletes scrolls whose keep-alive ran out, returning how many; their readers expire alongside
+    async fn expire_scrolls(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut entries = match async_fs::read_dir(self.scrolls_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).context("Failed to list scrolls"),
+        };
+        let mut expired = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+                continue;
+            }
+            let stale = match async_fs::read(&path).await {
+                Ok(bytes) => checksum::from_json::<ScrollState>(&bytes, "scroll")
+                    .map(|state| state.expires_at <= now)
+                    .unwrap_or(true),
+                Err(_) => continue,
+            };
+            if stale {
+                match async_fs::remove_file(&path).await {
+                    Ok(()) => expired += 1,
+                    Err(e) => warn!(path = ?path, error = %e, "Failed to remove expired scroll"),
+                }
+            }
+        }
+        Ok(expired)
+    }
+}
@@ -0,0 +1,1007 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinSet;
+use tracing::{info_span, warn, Instrument};
+
+use crate::alerts::AlertEvent;
+use crate::dedup::DEFAULT_MAX_DISTANCE;
+use crate::error::{Result, SearchEngineError};
+use crate::metadata::Metadata;
+use crate::audit::AuditFilter;
+use crate::options::{MatchMode, SearchFilters, SearchLimits, SearchOptions, SortOrder};
+use crate::records::JsonMapping;
+use crate::schedule::{ScheduleStatus, Scheduler};
+use crate::schema::SCHEMA_VERSION;
+use crate::scope::PathScope;
+use crate::scroll::{ScrollPage, DEFAULT_BATCH_SIZE};
+use crate::ttl::parse_duration;
+use crate::{MaintenanceTask, SearchEngine};
+
+// One request per input line, e.g. {"id": 1, "command": "search", "query": "error"}
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    id: Value,
+    // Identity recorded in the audit log, e.g. the API key owner
+    #[serde(default)]
+    actor: Option<String>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Request {
+    Search {
+        query: String,
+        #[serde(default = "default_limit")]
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+        // Unset sort, collation, mode, max_per_file and diversify fall back to the collection's defaults
+        #[serde(default)]
+        sort: Option<SortOrder>,
+        // Locale such as "de" whose alphabet orders paths and titles
+        #[serde(default)]
+        collation: Option<String>,
+        #[serde(default)]
+        mode: Option<MatchMode>,
+        #[serde(default)]
+        case_sensitive: bool,
+        #[serde(default)]
+        collection: Option<String>,
+        // Several collections searched at once, with their results merged; instead of `collection`
+        #[serde(default)]
+        collections: Vec<String>,
+        // Boxed, like `limits`, to keep this variant from dwarfing the others
+        #[serde(default)]
+        filters: Box<SearchFilters>,
+        #[serde(default)]
+        highlight: bool,
+        // Duration such as "500ms"
+        #[serde(default)]
+        timeout: Option<String>,
+        #[serde(default)]
+        profile: bool,
+        #[serde(default)]
+        collapse_duplicates: bool,
+        #[serde(default)]
+        clusters: usize,
+        #[serde(default)]
+        summarize: bool,
+        // ID from "open-reader", to page through a fixed set of documents
+        #[serde(default)]
+        reader: Option<String>,
+        // ID of a result set saved by an earlier search, to search only its lines
+        #[serde(default)]
+        within: Option<String>,
+        // Keep-alive such as "5m" to save every match as a result set
+        #[serde(default)]
+        save_results: Option<String>,
+        #[serde(default)]
+        max_per_file: Option<usize>,
+        #[serde(default)]
+        diversify: Option<bool>,
+        #[serde(default)]
+        boosts: BTreeMap<String, f32>,
+        // Metadata fields to count values of across matching documents
+        #[serde(default)]
+        facets: Vec<String>,
+        // Edits each query word may be from the words it matches
+        #[serde(default)]
+        fuzzy: Option<usize>,
+        // Caps on this search; they can only tighten the engine's configured limits. Boxed to
+        // keep this variant from dwarfing the others.
+        #[serde(default)]
+        limits: Box<SearchLimits>,
+    },
+    Semantic {
+        query: String,
+        #[serde(default = "default_limit")]
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+        #[serde(default)]
+        filters: SearchFilters,
+    },
+    Index {
+        path: String,
+        // Lifetime such as "7d"
+        #[serde(default)]
+        ttl: Option<String>,
+        // Replaces the document's metadata when given
+        #[serde(default)]
+        metadata: Option<Metadata>,
+        // Replaces the principals that may see the document when given
+        #[serde(default)]
+        acl: Option<Vec<String>>,
+    },
+    IndexJson {
+        path: String,
+        // Fields making up each record's document; every field is searched by default
+        #[serde(default)]
+        mapping: JsonMapping,
+        #[serde(default)]
+        ttl: Option<String>,
+        #[serde(default)]
+        acl: Option<Vec<String>>,
+    },
+    Delete {
+        path: String,
+    },
+    Trash,
+    Restore {
+        path: String,
+    },
+    Get {
+        path: String,
+        line: usize,
+        // Whom the request is made for, as in search filters; none only sees documents without
+        // an access control list
+        #[serde(default)]
+        principals: Option<Vec<String>>,
+    },
+    Lines {
+        path: String,
+        start: usize,
+        end: usize,
+        #[serde(default)]
+        principals: Option<Vec<String>>,
+    },
+    Analytics {
+        #[serde(default = "default_limit")]
+        top: usize,
+        // Window such as "24h"
+        #[serde(default)]
+        since: Option<String>,
+    },
+    SlowQueries {
+        // Window such as "24h"
+        #[serde(default)]
+        since: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    Audit {
+        #[serde(default)]
+        since: Option<String>,
+        #[serde(default)]
+        actor: Option<String>,
+        #[serde(default)]
+        operation: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    Stats {
+        #[serde(default)]
+        detailed: bool,
+    },
+    Duplicates {
+        #[serde(default = "default_max_distance")]
+        max_distance: u32,
+        #[serde(default)]
+        principals: Option<Vec<String>>,
+    },
+    Keywords {
+        #[serde(default = "default_top_keywords")]
+        top: usize,
+        #[serde(default)]
+        principals: Option<Vec<String>>,
+    },
+    CorpusStats {
+        #[serde(default = "default_top_keywords")]
+        top: usize,
+    },
+    Suggest {
+        prefix: String,
+        #[serde(default = "default_limit")]
+        limit: usize,
+        #[serde(default)]
+        from_history: bool,
+        // Window such as "24h", for suggestions from history
+        #[serde(default)]
+        since: Option<String>,
+        #[serde(default)]
+        principals: Option<Vec<String>>,
+    },
+    Partitions,
+    Changes {
+        #[serde(default)]
+        since: u64,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    Facets {
+        fields: Vec<String>,
+        #[serde(default)]
+        principals: Option<Vec<String>>,
+    },
+    AddTags {
+        // Path or document ID
+        document: String,
+        tags: Vec<String>,
+    },
+    RemoveTags {
+        document: String,
+        tags: Vec<String>,
+    },
+    Tags {
+        document: String,
+    },
+    TagCounts,
+    OpenReader {
+        // Duration such as "5m"
+        #[serde(default)]
+        keep_alive: Option<String>,
+    },
+    CloseReader {
+        id: String,
+    },
+    Readers,
+    ResultSets,
+    CloseResultSet {
+        result_set: String,
+    },
+    OpenScroll {
+        query: String,
+        #[serde(default = "default_batch_size")]
+        batch_size: usize,
+        // Duration such as "5m"
+        #[serde(default)]
+        keep_alive: Option<String>,
+        #[serde(default)]
+        mode: MatchMode,
+        #[serde(default)]
+        case_sensitive: bool,
+        #[serde(default)]
+        fuzzy: Option<usize>,
+        #[serde(default)]
+        filters: SearchFilters,
+        #[serde(default)]
+        highlight: bool,
+        // ID from "open-reader" to scroll through instead of a reader opened for the scroll
+        #[serde(default)]
+        reader: Option<String>,
+    },
+    Scroll {
+        scroll_id: String,
+    },
+    CloseScroll {
+        scroll_id: String,
+    },
+    Alerts,
+    AddAlert {
+        query: String,
+        #[serde(default)]
+        mode: MatchMode,
+        #[serde(default)]
+        filters: SearchFilters,
+        #[serde(default)]
+        webhook: Option<String>,
+    },
+    RemoveAlert {
+        id: String,
+    },
+    Aliases,
+    SetAlias {
+        alias: String,
+        collection: String,
+    },
+    RemoveAlias {
+        alias: String,
+    },
+    AlertEvents {
+        // Window such as "24h"
+        #[serde(default)]
+        since: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    // Pushes an "alert" event line to this client whenever a saved query matches
+    SubscribeAlerts,
+    // Saved queries matching `text`, which is not indexed
+    Percolate {
+        text: String,
+        #[serde(default)]
+        path: Option<String>,
+    },
+    Status,
+    // Rereads the configured stop word file
+    ReloadStopwords,
+    // Rereads the configured synonym file
+    ReloadSynonyms,
+    // Rereads the configured pins file
+    ReloadPins,
+    // Replies with the Prometheus text as a string
+    Metrics,
+    Version,
+    Maintenance {
+        #[serde(flatten)]
+        task: MaintenanceTask,
+        #[serde(default)]
+        dry_run: bool,
+        // Confirms a destructive task
+        #[serde(default)]
+        yes: bool,
+    },
+}
+
+impl Request {
+    fn name(&self) -> &'static str {
+        match self {
+            Request::Search { .. } => "search",
+            Request::Semantic { .. } => "semantic",
+            Request::Index { .. } => "index",
+            Request::IndexJson { .. } => "index-json",
+            Request::Delete { .. } => "delete",
+            Request::Trash => "trash",
+            Request::Restore { .. } => "restore",
+            Request::Get { .. } => "get",
+            Request::Lines { .. } => "lines",
+            Request::Analytics { .. } => "analytics",
+            Request::SlowQueries { .. } => "slow-queries",
+            Request::Audit { .. } => "audit",
+            Request::Stats { .. } => "stats",
+            Request::Duplicates { .. } => "duplicates",
+            Request::Keywords { .. } => "keywords",
+            Request::CorpusStats { .. } => "corpus-stats",
+            Request::Suggest { .. } => "suggest",
+            Request::Partitions => "partitions",
+            Request::Changes { .. } => "changes",
+            Request::Facets { .. } => "facets",
+            Request::AddTags { .. } => "add-tags",
+            Request::RemoveTags { .. } => "remove-tags",
+            Request::Tags { .. } => "tags",
+            Request::TagCounts => "tag-counts",
+            Request::OpenReader { .. } => "open-reader",
+            Request::CloseReader { .. } => "close-reader",
+            Request::Readers => "readers",
+            Request::ResultSets => "result-sets",
+            Request::CloseResultSet { .. } => "close-result-set",
+            Request::OpenScroll { .. } => "open-scroll",
+            Request::Scroll { .. } => "scroll",
+            Request::CloseScroll { .. } => "close-scroll",
+            Request::Alerts => "alerts",
+            Request::AddAlert { .. } => "add-alert",
+            Request::RemoveAlert { .. } => "remove-alert",
+            Request::AlertEvents { .. } => "alert-events",
+            Request::Aliases => "aliases",
+            Request::SetAlias { .. } => "set-alias",
+            Request::RemoveAlias { .. } => "remove-alias",
+            Request::SubscribeAlerts => "subscribe-alerts",
+            Request::Percolate { .. } => "percolate",
+            Request::Status => "status",
+            Request::ReloadStopwords => "reload-stopwords",
+            Request::ReloadSynonyms => "reload-synonyms",
+            Request::ReloadPins => "reload-pins",
+            Request::Metrics => "metrics",
+            Request::Version => "version",
+            Request::Maintenance { .. } => "maintenance",
+        }
+    }
+
+    // Requests that change documents, tags, saved alerts, aliases, readers, result sets or scrolls run one at a time in input
+    // order, so a pipelined client sees the effect of each change in every request it sends afterwards
+    fn is_mutating(&self) -> bool {
+        matches!(
+            self,
+            Request::Index { .. } | Request::IndexJson { .. } | Request::Delete { .. } | Request::Restore { .. } | Request::Maintenance { dry_run: false, .. }
+                | Request::AddAlert { .. } | Request::RemoveAlert { .. } | Request::OpenReader { .. } | Request::CloseReader { .. }
+                | Request::AddTags { .. } | Request::RemoveTags { .. } | Request::SetAlias { .. } | Request::RemoveAlias { .. }
+                | Request::Search { save_results: Some(_), .. } | Request::CloseResultSet { .. } | Request::ReloadStopwords
+                | Request::ReloadSynonyms | Request::ReloadPins
+                | Request::OpenScroll { .. } | Request::Scroll { .. } | Request::CloseScroll { .. }
+        )
+    }
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+fn default_batch_size() -> usize {
+    DEFAULT_BATCH_SIZE
+}
+
+fn default_max_distance() -> u32 {
+    DEFAULT_MAX_DISTANCE
+}
+
+fn default_top_keywords() -> usize {
+    20
+}
+
+// Replies carry `schema_version` themselves rather than nesting the result in `Versioned`
+#[derive(Serialize)]
+struct Reply<'a, T: Serialize> {
+    schema_version: u32,
+    id: &'a Value,
+    ok: bool,
+    result: &'a T,
+}
+
+#[derive(Serialize)]
+struct ErrorReply<'a> {
+    schema_version: u32,
+    id: &'a Value,
+    ok: bool,
+    error: String,
+}
+
+// Pushed to subscribed clients between replies; tell it apart from replies by the missing id
+#[derive(Serialize)]
+struct EventLine<'a, T: Serialize> {
+    schema_version: u32,
+    event: &'a str,
+    data: &'a T,
+}
+
+// Serializes replies straight into a reused buffer that is flushed once per line,
+// so no intermediate String is built for the response or any of its results.
+// Each request has its own writer; the output is locked only to write a finished line.
+struct ReplyWriter<W> {
+    output: Arc<Mutex<W>>,
+    buffer: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> ReplyWriter<W> {
+    fn new(output: Arc<Mutex<W>>) -> Self {
+        ReplyWriter {
+            output,
+            buffer: Vec::with_capacity(64 * 1024),
+        }
+    }
+
+    async fn ok<T: Serialize>(&mut self, id: &Value, result: &T) -> Result<()> {
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, &Reply { schema_version: SCHEMA_VERSION, id, ok: true, result })?;
+        self.flush().await
+    }
+
+    async fn error(&mut self, id: &Value, error: impl ToString) -> Result<()> {
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, &ErrorReply { schema_version: SCHEMA_VERSION, id, ok: false, error: error.to_string() })?;
+        self.flush().await
+    }
+
+    async fn event<T: Serialize>(&mut self, event: &str, data: &T) -> Result<()> {
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, &EventLine { schema_version: SCHEMA_VERSION, event, data })?;
+        self.flush().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.buffer.push(b'\n');
+        let mut output = self.output.lock().await;
+        output.write_all(&self.buffer).await?;
+        output.flush().await?;
+        Ok(())
+    }
+}
+
+// Long-running JSON-lines server: reads requests from `input` until EOF and
+// writes one reply line per request to `output`, running scheduled maintenance in between.
+// Read-only requests run concurrently, so replies may arrive out of order; match them by id.
+// Destructive maintenance requests need "yes": true unless `allow_destructive` is set.
+// Paths to index, delete or restore must stay within `scope`.
+pub async fn serve<R, W>(
+    engine: &SearchEngine,
+    mut scheduler: Scheduler,
+    allow_destructive: bool,
+    scope: &PathScope,
+    input: R,
+    output: W,
+) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    engine.warm().await?;
+
+    let mut lines = input.lines();
+    let output = Arc::new(Mutex::new(output));
+    let mut requests = JoinSet::new();
+    let mut alert_events = None;
+
+    loop {
+        let next_due = scheduler.next_due();
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = sleep_until_due(next_due) => {
+                scheduler.run_due(engine).await;
+                continue;
+            }
+            event = next_alert_event(&mut alert_events), if alert_events.is_some() => {
+                match event {
+                    Ok(event) => ReplyWriter::new(output.clone()).event("alert", &event).await?,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!(missed, "Alert subscriber fell behind; events were dropped");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => alert_events = None,
+                }
+                continue;
+            }
+            // Surfaces write failures from finished requests without waiting for EOF
+            Some(finished) = requests.join_next(), if !requests.is_empty() => {
+                finished.map_err(std::io::Error::other)??;
+                continue;
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let envelope: Envelope = match serde_json::from_str(&line) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                ReplyWriter::new(output.clone()).error(&Value::Null, format!("Invalid request: {}", e)).await?;
+                continue;
+            }
+        };
+        let scheduled = match envelope.request {
+            Request::Status => scheduler.status(),
+            _ => Vec::new(),
+        };
+        if let Request::SubscribeAlerts = envelope.request {
+            alert_events.get_or_insert_with(|| engine.subscribe_alerts());
+        }
+        let mut engine = engine.clone();
+        engine.set_actor(envelope.actor.as_deref().unwrap_or("server"));
+        let mut writer = ReplyWriter::new(output.clone());
+        let span = info_span!("request", id = %envelope.id, command = envelope.request.name());
+        let mutating = envelope.request.is_mutating();
+        let scope = scope.clone();
+        let request = async move { handle(&engine, &scope, &scheduled, allow_destructive, &mut writer, &envelope.id, envelope.request).await }
+            .instrument(span);
+        if mutating {
+            request.await?;
+        } else {
+            requests.spawn(request);
+        }
+    }
+
+    while let Some(finished) = requests.join_next().await {
+        finished.map_err(std::io::Error::other)??;
+    }
+    Ok(())
+}
+
+async fn handle<W: AsyncWrite + Unpin>(
+    engine: &SearchEngine,
+    scope: &PathScope,
+    scheduled: &[ScheduleStatus],
+    allow_destructive: bool,
+    writer: &mut ReplyWriter<W>,
+    id: &Value,
+    request: Request,
+) -> Result<()> {
+    match request {
+        Request::Search {
+            query, limit, offset, sort, collation, mode, case_sensitive, collection, collections, filters, highlight, timeout, profile, collapse_duplicates, clusters,
+            summarize, reader, within, save_results, max_per_file, diversify, boosts, facets, fuzzy, limits,
+        } => {
+            let save_results = match save_results.as_deref().map(parse_duration).transpose() {
+                Ok(keep_alive) => keep_alive,
+                Err(e) => return writer.error(id, e).await,
+            };
+            // The request's options on top of a collection's defaults
+            let apply = |mut options: SearchOptions| {
+                options.limit = limit;
+                options.offset = offset;
+                options.sort = sort.unwrap_or(options.sort);
+                options.collation = collation.clone().or(options.collation);
+                options.mode = mode.unwrap_or(options.mode);
+                options.case_sensitive = case_sensitive;
+                options.filters = SearchFilters {
+                    path_prefix: filters.path_prefix.clone().or(options.filters.path_prefix),
+                    ..(*filters).clone()
+                };
+                options.highlight = highlight;
+                options.profile = profile;
+                options.collapse_duplicates = collapse_duplicates;
+                options.clusters = clusters;
+                options.summarize = summarize;
+                options.reader = reader.clone();
+                options.within = within.clone();
+                options.save_results = save_results;
+                options.max_per_file = max_per_file.or(options.max_per_file);
+                options.diversify = diversify.unwrap_or(options.diversify);
+                options.boosts.extend(boosts.iter().map(|(prefix, boost)| (prefix.clone(), *boost)));
+                options.facets = facets.clone();
+                options.fuzzy = fuzzy;
+                options.limits = *limits;
+                options
+            };
+            let response = if collections.is_empty() {
+                match base_options(engine, collection.as_deref(), timeout.as_deref()).await {
+                    Ok(options) => engine.search(&query, &apply(options)).await,
+                    Err(e) => Err(e),
+                }
+            } else if collection.is_some() {
+                Err(SearchEngineError::InvalidInput("give either collection or collections, not both".to_string()))
+            } else {
+                match collection_searches(engine, &collections, timeout.as_deref(), &apply).await {
+                    Ok(searches) => engine.search_collections(&query, &searches).await,
+                    Err(e) => Err(e),
+                }
+            };
+            match response {
+                Ok(response) => writer.ok(id, &response).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::Semantic { query, limit, offset, filters } => {
+            let options = SearchOptions { limit, offset, filters, ..SearchOptions::default() };
+            match engine.semantic_search(&query, &options).await {
+                Ok(response) => writer.ok(id, &response).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::Index { path, ttl, metadata, acl } => match index(engine, scope, &path, ttl.as_deref(), metadata, acl).await {
+            Ok(document) => writer.ok(id, &document).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::IndexJson { path, mapping, ttl, acl } => match index_json(engine, scope, &path, mapping, ttl.as_deref(), acl).await {
+            Ok(report) => writer.ok(id, &report).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Delete { path } => match delete(engine, scope, &path).await {
+            Ok(true) => writer.ok(id, &serde_json::json!({ "deleted": path })).await?,
+            Ok(false) => writer.error(id, format!("Document not found: {}", path)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Trash => match engine.trash_entries().await {
+            Ok(entries) => writer.ok(id, &entries).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Restore { path } => match restore(engine, scope, &path).await {
+            Ok(document) => writer.ok(id, &document).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Get { path, line, principals } => match engine.get_line(&path, line, principals.as_deref()).await {
+            Ok(Some(result)) => writer.ok(id, &result).await?,
+            Ok(None) => writer.error(id, format!("Line {} not found in {}", line, path)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Lines { path, start, end, principals } => match engine.get_lines(&path, start, end, principals.as_deref()).await {
+            Ok(Some(range)) => writer.ok(id, &range).await?,
+            Ok(None) => writer.error(id, format!("Line {} not found in {}", start, path)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Analytics { top, since } => match analytics(engine, top, since.as_deref()).await {
+            Ok(analytics) => writer.ok(id, &analytics).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::SlowQueries { since, limit } => {
+            let entries = match since.as_deref().map(parse_duration).transpose() {
+                Ok(since) => engine.slow_queries(since.map(|window| chrono::Utc::now() - window), limit).await,
+                Err(e) => Err(e),
+            };
+            match entries {
+                Ok(entries) => writer.ok(id, &entries).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::Audit { since, actor, operation, limit } => {
+            let filter = since.as_deref().map(parse_duration).transpose().map(|since| AuditFilter {
+                since: since.map(|window| chrono::Utc::now() - window),
+                actor,
+                operation,
+                limit,
+            });
+            let entries = match filter {
+                Ok(filter) => engine.audit_log(&filter).await,
+                Err(e) => Err(e),
+            };
+            match entries {
+                Ok(entries) => writer.ok(id, &entries).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::Duplicates { max_distance, principals } => match engine.near_duplicates(max_distance, principals.as_deref()).await {
+            Ok(clusters) => writer.ok(id, &clusters).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Keywords { top, principals } => match engine.keyword_counts(top, principals.as_deref()).await {
+            Ok(counts) => writer.ok(id, &counts).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::CorpusStats { top } => match engine.corpus_report(top).await {
+            Ok(report) => writer.ok(id, &report).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Suggest { prefix, limit, from_history, since, principals } => {
+            match suggest(engine, &prefix, limit, from_history, since.as_deref(), principals.as_deref()).await {
+                Ok(suggestions) => writer.ok(id, &suggestions).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::Partitions => match engine.partitions().await {
+            Ok(partitions) => writer.ok(id, &partitions).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Changes { since, limit } => match engine.changes_since(since, limit).await {
+            Ok(feed) => writer.ok(id, &feed).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::AddTags { document, tags } => match engine.add_tags(&document, &tags).await {
+            Ok(tags) => writer.ok(id, &tags).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::RemoveTags { document, tags } => match engine.remove_tags(&document, &tags).await {
+            Ok(tags) => writer.ok(id, &tags).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Tags { document } => match engine.document_tags(&document).await {
+            Ok(tags) => writer.ok(id, &tags).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Facets { fields, principals } => match engine.facet_counts(&fields, principals.as_deref()).await {
+            Ok(facets) => writer.ok(id, &facets).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::TagCounts => match engine.tag_counts().await {
+            Ok(counts) => writer.ok(id, &counts).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::OpenReader { keep_alive } => match open_reader(engine, keep_alive.as_deref()).await {
+            Ok(reader) => writer.ok(id, &reader).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::CloseReader { id: reader_id } => match engine.close_reader(&reader_id).await {
+            Ok(true) => writer.ok(id, &serde_json::json!({ "closed": reader_id })).await?,
+            Ok(false) => writer.error(id, format!("No open reader with ID {}", reader_id)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Readers => match engine.readers().await {
+            Ok(readers) => writer.ok(id, &readers).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::ResultSets => match engine.result_sets().await {
+            Ok(sets) => writer.ok(id, &sets).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::CloseResultSet { result_set } => match engine.close_result_set(&result_set).await {
+            Ok(true) => writer.ok(id, &serde_json::json!({ "closed": result_set })).await?,
+            Ok(false) => writer.error(id, format!("No saved result set with ID {}", result_set)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::OpenScroll { query, batch_size, keep_alive, mode, case_sensitive, fuzzy, filters, highlight, reader } => {
+            let options = SearchOptions { mode, case_sensitive, fuzzy, filters, highlight, reader, ..SearchOptions::default() };
+            match open_scroll(engine, &query, &options, batch_size, keep_alive.as_deref()).await {
+                Ok(page) => writer.ok(id, &page).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::Scroll { scroll_id } => match engine.scroll(&scroll_id).await {
+            Ok(page) => writer.ok(id, &page).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::CloseScroll { scroll_id } => match engine.close_scroll(&scroll_id).await {
+            Ok(true) => writer.ok(id, &serde_json::json!({ "closed": scroll_id })).await?,
+            Ok(false) => writer.error(id, format!("No open scroll with ID {}", scroll_id)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Alerts => match engine.alerts().await {
+            Ok(alerts) => writer.ok(id, &alerts).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::AddAlert { query, mode, filters, webhook } => match engine.add_alert(&query, mode, filters, webhook).await {
+            Ok(alert) => writer.ok(id, &alert).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Aliases => match engine.aliases().await {
+            Ok(aliases) => writer.ok(id, &aliases).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::SetAlias { alias, collection } => match engine.set_alias(&alias, &collection).await {
+            Ok(switch) => writer.ok(id, &switch).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::RemoveAlias { alias } => match engine.remove_alias(&alias).await {
+            Ok(true) => writer.ok(id, &serde_json::json!({ "removed": alias })).await?,
+            Ok(false) => writer.error(id, format!("No collection alias named {}", alias)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::RemoveAlert { id: alert_id } => match engine.remove_alert(&alert_id).await {
+            Ok(true) => writer.ok(id, &serde_json::json!({ "removed": alert_id })).await?,
+            Ok(false) => writer.error(id, format!("No saved alert with ID {}", alert_id)).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::AlertEvents { since, limit } => {
+            let events = match since.as_deref().map(parse_duration).transpose() {
+                Ok(since) => engine.alert_events(since.map(|window| chrono::Utc::now() - window), limit).await,
+                Err(e) => Err(e),
+            };
+            match events {
+                Ok(events) => writer.ok(id, &events).await?,
+                Err(e) => writer.error(id, e).await?,
+            }
+        }
+        Request::SubscribeAlerts => writer.ok(id, &serde_json::json!({ "subscribed": true })).await?,
+        Request::Percolate { text, path } => match engine.percolate(&text, path.as_deref()).await {
+            Ok(matches) => writer.ok(id, &matches).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Stats { detailed: true } => match engine.get_detailed_stats().await {
+            Ok(stats) => writer.ok(id, &stats).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Stats { detailed: false } => match engine.get_stats().await {
+            Ok(stats) => writer.ok(id, &stats).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Status => match engine.get_status().await {
+            Ok(mut status) => {
+                status.scheduled_tasks = scheduled.to_vec();
+                writer.ok(id, &status).await?
+            }
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::ReloadStopwords => match engine.reload_stopwords().await {
+            Ok(count) => writer.ok(id, &serde_json::json!({ "stopwords": count })).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::ReloadSynonyms => match engine.reload_synonyms().await {
+            Ok(count) => writer.ok(id, &serde_json::json!({ "synonyms": count })).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::ReloadPins => match engine.reload_pins().await {
+            Ok(count) => writer.ok(id, &serde_json::json!({ "pins": count })).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Metrics => match engine.prometheus_metrics().await {
+            Ok(metrics) => writer.ok(id, &metrics).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+        Request::Version => writer.ok(id, &crate::version::version_info()).await?,
+        Request::Maintenance { task, dry_run, yes } => match maintenance(engine, scope, &task, dry_run, yes || allow_destructive).await {
+            Ok(result) => writer.ok(id, &result).await?,
+            Err(e) => writer.error(id, e).await?,
+        },
+    }
+    Ok(())
+}
+
+// Backups and archives may only be written under the allowed roots, as a client could
+// otherwise copy the index, or move documents, anywhere the server can write
+async fn maintenance(
+    engine: &SearchEngine,
+    scope: &PathScope,
+    task: &MaintenanceTask,
+    dry_run: bool,
+    confirmed: bool,
+) -> Result<crate::MaintenanceResult> {
+    if let Some(destination) = task.destination() {
+        scope.check_destination(destination).await?;
+    }
+    if dry_run {
+        return engine.preview_maintenance(task).await;
+    }
+    if task.is_destructive() && !confirmed {
+        return Err(SearchEngineError::InvalidInput(format!(
+            "Destructive task '{}' requires \"yes\": true; use \"dry_run\" to preview it", task.name()
+        )));
+    }
+    engine.run_maintenance(task).await
+}
+
+async fn index(
+    engine: &SearchEngine,
+    scope: &PathScope,
+    path: &str,
+    ttl: Option<&str>,
+    metadata: Option<Metadata>,
+    acl: Option<Vec<String>>,
+) -> Result<crate::IndexedDocument> {
+    scope.check_source(path).await?;
+    let ttl = ttl.map(parse_duration).transpose()?;
+    match (metadata, acl) {
+        (metadata, Some(acl)) => engine.index_document_with_acl(path, None, ttl, metadata, acl).await,
+        (Some(metadata), None) => engine.index_document_with_metadata(path, None, ttl, metadata).await,
+        (None, None) => engine.index_document_with_ttl(path, ttl).await,
+    }
+}
+
+async fn index_json(
+    engine: &SearchEngine,
+    scope: &PathScope,
+    path: &str,
+    mapping: JsonMapping,
+    ttl: Option<&str>,
+    acl: Option<Vec<String>>,
+) -> Result<crate::source::IndexReport> {
+    scope.check_source(path).await?;
+    let ttl = ttl.map(parse_duration).transpose()?;
+    engine.index_json(path, mapping, ttl, acl).await
+}
+
+async fn delete(engine: &SearchEngine, scope: &PathScope, path: &str) -> Result<bool> {
+    scope.check_document(path).await?;
+    engine.delete_document(path).await
+}
+
+async fn restore(engine: &SearchEngine, scope: &PathScope, path: &str) -> Result<crate::IndexedDocument> {
+    scope.check_document(path).await?;
+    engine.restore_document(path).await
+}
+
+async fn analytics(engine: &SearchEngine, top: usize, since: Option<&str>) -> Result<crate::querylog::QueryAnalytics> {
+    let since = since.map(parse_duration).transpose()?.map(|window| chrono::Utc::now() - window);
+    engine.query_analytics(top, since).await
+}
+
+async fn suggest(
+    engine: &SearchEngine,
+    prefix: &str,
+    limit: usize,
+    from_history: bool,
+    since: Option<&str>,
+    principals: Option<&[String]>,
+) -> Result<Vec<crate::suggest::Suggestion>> {
+    if !from_history {
+        return engine.suggest(prefix, limit, principals).await;
+    }
+    let since = since.map(parse_duration).transpose()?.map(|window| chrono::Utc::now() - window);
+    engine.suggest_from_history(prefix, limit, since).await
+}
+
+// The defaults of the collection or the one an alias points at, or the usual ones, with the
+// timeout parsed
+async fn base_options(engine: &SearchEngine, collection: Option<&str>, timeout: Option<&str>) -> Result<SearchOptions> {
+    let mut options = match collection {
+        Some(name) => engine.resolve_collection(name).await?.options(),
+        None => SearchOptions::default(),
+    };
+    options.timeout = timeout.map(parse_duration).transpose()?;
+    Ok(options)
+}
+
+// Each named collection's options with the request's applied on top
+async fn collection_searches(
+    engine: &SearchEngine,
+    names: &[String],
+    timeout: Option<&str>,
+    apply: &(dyn Fn(SearchOptions) -> SearchOptions + Sync),
+) -> Result<Vec<(String, SearchOptions)>> {
+    let mut searches = Vec::with_capacity(names.len());
+    for name in names {
+        searches.push((name.clone(), apply(base_options(engine, Some(name), timeout).await?)));
+    }
+    Ok(searches)
+}
+
+async fn open_reader(engine: &SearchEngine, keep_alive: Option<&str>) -> Result<crate::reader::PointInTime> {
+    let keep_alive = keep_alive.map(parse_duration).transpose()?;
+    engine.open_reader(keep_alive).await
+}
+
+async fn open_scroll(engine: &SearchEngine, query: &str, options: &SearchOptions, batch_size: usize, keep_alive: Option<&str>) -> Result<ScrollPage> {
+    let keep_alive = keep_alive.map(parse_duration).transpose()?;
+    engine.open_scroll(query, options, batch_size, keep_alive).await
+}
+
+async fn next_alert_event(events: &mut Option<broadcast::Receiver<AlertEvent>>) -> std::result::Result<AlertEvent, broadcast::error::RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn sleep_until_due(due: Option<chrono::DateTime<chrono::Utc>>) {
+    match due {
+        Some(due) => {
+            let wait = (due - chrono::Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+        }
+        None => std::future::pending().await,
+    }
+}
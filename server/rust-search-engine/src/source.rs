@@ -0,0 +1,234 @@
+use chrono::{DateTime, Duration, Utc};
+use futures_core::Stream;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use tokio::fs as async_fs;
+use walkdir::WalkDir;
+
+use crate::builder::DEFAULT_EXTENSIONS;
+use crate::docstore::INDEX_DIR;
+use crate::error::{Context, Result};
+use crate::metadata::Metadata;
+use crate::storage::{BoxFuture, ContentReader};
+use crate::trash::TRASH_DIR;
+use crate::IndexedDocument;
+
+// A document to add to the index
+pub struct DocumentInput {
+    // Where to store it, relative to the search directory
+    pub path: PathBuf,
+    pub content: ContentReader,
+    // Falls back to the engine's default TTL
+    pub ttl: Option<Duration>,
+    // Required when the engine uses `IdStrategy::Provided`, ignored otherwise
+    pub id: Option<String>,
+    // Picks the partition when the engine partitions by time; otherwise found in the content
+    pub date: Option<DateTime<Utc>>,
+    // Replaces the document's metadata; None keeps what a re-indexed document had
+    pub metadata: Option<Metadata>,
+    // Titles its results in place of the file name; an HTML page's <title> if not given
+    pub title: Option<String>,
+    // Replaces the principals that may see the document; None keeps what a re-indexed
+    // document had
+    pub acl: Option<Vec<String>>,
+}
+
+impl DocumentInput {
+    pub fn new(path: impl Into<PathBuf>, content: ContentReader) -> Self {
+        DocumentInput {
+            path: path.into(),
+            content,
+            ttl: None,
+            id: None,
+            date: None,
+            metadata: None,
+            title: None,
+            acl: None,
+        }
+    }
+
+    // A document whose content is already in memory
+    pub fn from_bytes(path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        Self::new(path, Box::pin(std::io::Cursor::new(content.into())))
+    }
+
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn date(mut self, date: Option<DateTime<Utc>>) -> Self {
+        self.date = date;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn acl<I, S>(mut self, principals: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.acl = Some(principals.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+// Yields documents for `SearchEngine::index_source`: a directory walker, a database cursor, a
+// message queue consumer, a channel, a list of inputs or, through `StreamSource`, any stream
+pub trait DocumentSource: Send {
+    // The next document, or None once the source is exhausted
+    fn next_document(&mut self) -> BoxFuture<'_, Result<Option<DocumentInput>>>;
+}
+
+impl DocumentSource for std::vec::IntoIter<DocumentInput> {
+    fn next_document(&mut self) -> BoxFuture<'_, Result<Option<DocumentInput>>> {
+        let next = self.next();
+        Box::pin(async move { Ok(next) })
+    }
+}
+
+// Receives documents from producer tasks; a bounded channel makes producers
+// wait while the engine catches up
+impl DocumentSource for tokio::sync::mpsc::Receiver<DocumentInput> {
+    fn next_document(&mut self) -> BoxFuture<'_, Result<Option<DocumentInput>>> {
+        Box::pin(async move { Ok(self.recv().await) })
+    }
+}
+
+// Any stream of documents as a source, such as an ETL pipeline's output; a stream can't fail,
+// so it runs until it ends
+pub struct StreamSource<'a> {
+    stream: Pin<Box<dyn Stream<Item = DocumentInput> + Send + 'a>>,
+}
+
+impl<'a> StreamSource<'a> {
+    pub fn new(stream: impl Stream<Item = DocumentInput> + Send + 'a) -> Self {
+        StreamSource { stream: Box::pin(stream) }
+    }
+}
+
+impl DocumentSource for StreamSource<'_> {
+    fn next_document(&mut self) -> BoxFuture<'_, Result<Option<DocumentInput>>> {
+        Box::pin(async move { Ok(std::future::poll_fn(|cx| self.stream.as_mut().poll_next(cx)).await) })
+    }
+}
+
+// Walks a local directory, yielding every file with one of the given extensions
+// under its path relative to that directory
+pub struct DirectorySource {
+    root: PathBuf,
+    extensions: Vec<String>,
+    ttl: Option<Duration>,
+    metadata: Option<Metadata>,
+    acl: Option<Vec<String>>,
+    entries: Option<walkdir::IntoIter>,
+}
+
+impl DirectorySource {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        DirectorySource {
+            root: root.as_ref().to_path_buf(),
+            extensions: DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            ttl: None,
+            metadata: None,
+            acl: None,
+            entries: None,
+        }
+    }
+
+    // Defaults to the engine's default extensions
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(|ext| ext.into().to_lowercase()).collect();
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    // Given to every document found
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    // Given to every document found
+    pub fn acl(mut self, principals: Vec<String>) -> Self {
+        self.acl = Some(principals);
+        self
+    }
+
+    fn next_file(&mut self) -> Option<PathBuf> {
+        let entries = self.entries.get_or_insert_with(|| WalkDir::new(&self.root).sort_by_file_name().into_iter());
+        loop {
+            let entry = match entries.next()? {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let name = entry.file_name();
+            if entry.file_type().is_dir() && (name == INDEX_DIR || name == TRASH_DIR) {
+                entries.skip_current_dir();
+                continue;
+            }
+            let indexable = entry.path().extension()
+                .map(|ext| self.extensions.contains(&ext.to_string_lossy().to_lowercase()))
+                .unwrap_or(false);
+            if entry.file_type().is_file() && indexable {
+                return Some(entry.into_path());
+            }
+        }
+    }
+}
+
+impl DocumentSource for DirectorySource {
+    fn next_document(&mut self) -> BoxFuture<'_, Result<Option<DocumentInput>>> {
+        Box::pin(async move {
+            let Some(file_path) = self.next_file() else {
+                return Ok(None);
+            };
+            let file = async_fs::File::open(&file_path).await
+                .with_context(|| format!("Failed to open {:?}", file_path))?;
+            let relative = file_path.strip_prefix(&self.root).unwrap_or(&file_path).to_path_buf();
+            let mut input = DocumentInput::new(relative, Box::pin(file)).ttl(self.ttl);
+            input.metadata = self.metadata.clone();
+            input.acl = self.acl.clone();
+            Ok(Some(input))
+        })
+    }
+}
+
+// A document a source yielded that could not be indexed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct IndexFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct IndexReport {
+    pub indexed: Vec<IndexedDocument>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<IndexFailure>,
+}
@@ -0,0 +1,127 @@
+// Stemming, reducing word forms such as "running" and "runs" to a common stem, with the Snowball
+// stemmers of rust-stemmers for the language stemming is set to, English unless set otherwise.
+// English irregular forms the suffix rules can't reach, such as "ran" or "went", are looked up
+// first. Words with digits are returned unchanged.
+
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// Languages words can be stemmed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StemLanguage {
+    // First, as postings tables built before there was a choice were stemmed in English
+    #[default]
+    English,
+    Arabic,
+    Danish,
+    Dutch,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+#[cfg(feature = "engine")]
+const LANGUAGES: [StemLanguage; 18] = [
+    StemLanguage::English, StemLanguage::Arabic, StemLanguage::Danish, StemLanguage::Dutch,
+    StemLanguage::Finnish, StemLanguage::French, StemLanguage::German, StemLanguage::Greek,
+    StemLanguage::Hungarian, StemLanguage::Italian, StemLanguage::Norwegian, StemLanguage::Portuguese,
+    StemLanguage::Romanian, StemLanguage::Russian, StemLanguage::Spanish, StemLanguage::Swedish,
+    StemLanguage::Tamil, StemLanguage::Turkish,
+];
+
+impl StemLanguage {
+    // The number postings tables record the language by
+    #[cfg(feature = "engine")]
+    pub(crate) fn code(self) -> u64 {
+        self as u64
+    }
+
+    #[cfg(feature = "engine")]
+    pub(crate) fn from_code(code: u64) -> Option<Self> {
+        LANGUAGES.get(code as usize).copied()
+    }
+
+    fn algorithm(self) -> Algorithm {
+        match self {
+            StemLanguage::English => Algorithm::English,
+            StemLanguage::Arabic => Algorithm::Arabic,
+            StemLanguage::Danish => Algorithm::Danish,
+            StemLanguage::Dutch => Algorithm::Dutch,
+            StemLanguage::Finnish => Algorithm::Finnish,
+            StemLanguage::French => Algorithm::French,
+            StemLanguage::German => Algorithm::German,
+            StemLanguage::Greek => Algorithm::Greek,
+            StemLanguage::Hungarian => Algorithm::Hungarian,
+            StemLanguage::Italian => Algorithm::Italian,
+            StemLanguage::Norwegian => Algorithm::Norwegian,
+            StemLanguage::Portuguese => Algorithm::Portuguese,
+            StemLanguage::Romanian => Algorithm::Romanian,
+            StemLanguage::Russian => Algorithm::Russian,
+            StemLanguage::Spanish => Algorithm::Spanish,
+            StemLanguage::Swedish => Algorithm::Swedish,
+            StemLanguage::Tamil => Algorithm::Tamil,
+            StemLanguage::Turkish => Algorithm::Turkish,
+        }
+    }
+}
+
+// Irregular verb and noun forms with their base form, which is then stemmed like any word.
+// Forms that are also common words of their own, such as "saw" or "found", are left out.
+const IRREGULAR: &[(&str, &str)] = &[
+    ("ate", "eat"), ("began", "begin"), ("begun", "begin"), ("bought", "buy"), ("brought", "bring"),
+    ("caught", "catch"), ("children", "child"), ("chose", "choose"), ("chosen", "choose"),
+    ("did", "do"), ("done", "do"), ("drove", "drive"), ("driven", "drive"), ("eaten", "eat"),
+    ("feet", "foot"), ("flew", "fly"), ("flown", "fly"), ("gave", "give"), ("given", "give"),
+    ("gone", "go"), ("grew", "grow"), ("grown", "grow"), ("knew", "know"), ("known", "know"),
+    ("made", "make"), ("men", "man"), ("mice", "mouse"), ("ran", "run"), ("sang", "sing"),
+    ("sought", "seek"), ("spoke", "speak"), ("spoken", "speak"), ("stole", "steal"),
+    ("stolen", "steal"), ("sung", "sing"), ("swam", "swim"), ("swum", "swim"), ("taken", "take"),
+    ("taught", "teach"), ("teeth", "tooth"), ("thought", "think"), ("threw", "throw"),
+    ("thrown", "throw"), ("took", "take"), ("went", "go"), ("women", "woman"), ("wrote", "write"),
+    ("written", "write"),
+];
+
+// The stem of a lowercase word in `language`
+pub fn stem(word: &str, language: StemLanguage) -> Cow<'_, str> {
+    let base = match language {
+        StemLanguage::English => IRREGULAR.iter().find(|(form, _)| *form == word).map(|(_, base)| *base).unwrap_or(word),
+        _ => word,
+    };
+    if base.chars().count() <= 2 || base.chars().any(|c| c.is_numeric()) {
+        return if base == word { Cow::Borrowed(word) } else { Cow::Owned(base.to_string()) };
+    }
+    match Stemmer::create(language.algorithm()).stem(base) {
+        Cow::Borrowed(stemmed) if stemmed == word => Cow::Borrowed(word),
+        stemmed => Cow::Owned(stemmed.into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_stemmed_in_the_language_given() {
+        for word in ["running", "runs", "ran"] {
+            assert_eq!(stem(word, StemLanguage::English), "run");
+        }
+        assert_eq!(stem("connected", StemLanguage::English), stem("connection", StemLanguage::English));
+        assert_eq!(stem("continuation", StemLanguage::French), stem("continuer", StemLanguage::French));
+        assert_eq!(stem("häuser", StemLanguage::German), stem("häusern", StemLanguage::German));
+        assert_eq!(stem("ran", StemLanguage::French), "ran");
+        assert_eq!(stem("mp3s", StemLanguage::English), "mp3s");
+    }
+}
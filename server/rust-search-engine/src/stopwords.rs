@@ -0,0 +1,152 @@
+// Stop words: words so common they say little about a line, such as "the" and "and". They
+// add nothing to relevance scores unless a query has nothing else, and postings tables leave
+// them out, so the longest lists in them are never stored. Lines still have to contain them
+// to match, so "to be or not to be" finds only lines with those words.
+//
+// The engine uses English stop words by default; `search-engine.json` can name a file instead,
+// one word per line with `#` starting a comment, or turn them off.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::stem::{stem, StemLanguage};
+
+pub const ENGLISH: &[&str] = &[
+    "a", "about", "after", "all", "also", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "before", "being", "but", "by", "can", "could", "did", "do", "does", "for",
+    "from", "had", "has", "have", "he", "her", "his", "how", "i", "if", "in", "into", "is", "it",
+    "its", "just", "me", "more", "most", "my", "no", "not", "now", "of", "off", "on", "only", "or",
+    "other", "our", "out", "over", "she", "should", "so", "some", "such", "than", "that", "the",
+    "their", "them", "then", "there", "these", "they", "this", "those", "through", "to", "too",
+    "under", "up", "us", "very", "was", "we", "were", "what", "when", "where", "which", "while",
+    "who", "why", "will", "with", "would", "you", "your",
+];
+
+// Where the engine's stop words come from: "english", "none", or the path of a word list
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum StopWordList {
+    #[default]
+    English,
+    None,
+    File(PathBuf),
+}
+
+impl From<String> for StopWordList {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "english" => StopWordList::English,
+            "none" => StopWordList::None,
+            _ => StopWordList::File(PathBuf::from(value)),
+        }
+    }
+}
+
+impl From<StopWordList> for String {
+    fn from(list: StopWordList) -> Self {
+        match list {
+            StopWordList::English => "english".to_string(),
+            StopWordList::None => "none".to_string(),
+            StopWordList::File(path) => path.to_string_lossy().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StopWords {
+    words: HashSet<String>,
+    // Their stems, English unless `stemmed_in` says otherwise, as a stemmed lookup can't tell
+    // apart words sharing a stem with one
+    stems: HashSet<String>,
+    // Identifies the list, so postings tables built without it are rebuilt
+    fingerprint: u64,
+}
+
+impl StopWords {
+    pub fn new<I, S>(words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let words: HashSet<String> = words.into_iter()
+            .map(|word| word.as_ref().trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let stems = words.iter().map(|word| stem(word, StemLanguage::English).into_owned()).collect();
+        let mut sorted: Vec<&str> = words.iter().map(String::as_str).collect();
+        sorted.sort_unstable();
+        // FNV-1a over the sorted words, as the index's other fingerprints are
+        let fingerprint = if sorted.is_empty() {
+            0
+        } else {
+            sorted.join("\n").bytes()
+                .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+        };
+        StopWords { words, stems, fingerprint }
+    }
+
+    // The same words with their stems in `language`
+    #[cfg(feature = "engine")]
+    pub(crate) fn stemmed_in(mut self, language: StemLanguage) -> Self {
+        self.stems = self.words.iter().map(|word| stem(word, language).into_owned()).collect();
+        self
+    }
+
+    pub fn english() -> Self {
+        Self::new(ENGLISH)
+    }
+
+    // A word list with one word per line; blank lines and anything after `#` are ignored
+    pub fn parse(text: &str) -> Self {
+        Self::new(text.lines().map(|line| line.split('#').next().unwrap_or_default()))
+    }
+
+    // `word` lowercase
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        self.words.iter().map(String::as_str)
+    }
+
+    pub(crate) fn contains_stem(&self, stem: &str) -> bool {
+        self.stems.contains(stem)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    // 0 for an empty list
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    // The words, sorted
+    pub fn words(&self) -> Vec<&str> {
+        let mut words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        words.sort_unstable();
+        words
+    }
+}
+
+// Reads the stop words a list names
+#[cfg(feature = "engine")]
+pub async fn load_stopwords(list: &StopWordList) -> crate::error::Result<StopWords> {
+    Ok(match list {
+        StopWordList::English => StopWords::english(),
+        StopWordList::None => StopWords::default(),
+        StopWordList::File(path) => {
+            let text = tokio::fs::read_to_string(path).await.map_err(|e| {
+                crate::error::SearchEngineError::InvalidConfig(format!("Failed to read stop words from {:?}: {}", path, e))
+            })?;
+            StopWords::parse(&text)
+        }
+    })
+}
@@ -0,0 +1,267 @@
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::fs as async_fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use walkdir::WalkDir;
+
+use crate::error::{Context, Result, SearchEngineError};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+// Streams a document's content
+pub type ContentReader = Pin<Box<dyn AsyncRead + Send>>;
+
+// A stored document as reported by a backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+// Where document content lives. Documents are addressed by their path under the
+// search directory; a backend for object storage maps those paths to its own keys.
+// The index itself (`.index`, trash entries, logs) always stays on local disk.
+pub trait StorageBackend: fmt::Debug + Send + Sync {
+    // Every document under `root`, not descending into directories named in `skip`
+    fn list<'a>(&'a self, root: &'a Path, skip: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<StoredObject>>>;
+
+    // None if nothing is stored at `path`
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Option<StoredObject>>>;
+
+    fn open<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<ContentReader>>;
+
+    // The bytes in `start..end`
+    fn read_range<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> BoxFuture<'a, Result<Vec<u8>>>;
+
+    // Stores everything read from `content` at `path`, replacing any previous content
+    fn write<'a>(&'a self, path: &'a Path, content: ContentReader) -> BoxFuture<'a, Result<StoredObject>>;
+
+    // Returns false if nothing was stored at `path`
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool>>;
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<()>>;
+}
+
+// Documents are plain files in the search directory
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalStorage;
+
+fn stored_object(path: &Path, metadata: &std::fs::Metadata) -> StoredObject {
+    StoredObject {
+        path: path.to_path_buf(),
+        size: metadata.len(),
+        modified: metadata.modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now()),
+    }
+}
+
+fn not_found(path: &Path, e: std::io::Error, context: &str) -> SearchEngineError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        SearchEngineError::DocumentNotFound(path.to_string_lossy().to_string())
+    } else {
+        SearchEngineError::Io { context: context.to_string(), source: e }
+    }
+}
+
+impl StorageBackend for LocalStorage {
+    fn list<'a>(&'a self, root: &'a Path, skip: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<StoredObject>>> {
+        Box::pin(async move {
+            let mut objects = Vec::new();
+            if !root.is_dir() {
+                return Ok(objects);
+            }
+            for entry in WalkDir::new(root)
+                .into_iter()
+                .filter_entry(|e| !skip.iter().any(|name| e.file_name() == *name))
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file()) {
+                // Sizes come from the walk itself so stats never need to stat files again
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                objects.push(stored_object(entry.path(), &metadata));
+            }
+            Ok(objects)
+        })
+    }
+
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Option<StoredObject>>> {
+        Box::pin(async move {
+            match async_fs::metadata(path).await {
+                Ok(metadata) if metadata.is_file() => Ok(Some(stored_object(path, &metadata))),
+                Ok(_) => Ok(None),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).context("Failed to read document metadata"),
+            }
+        })
+    }
+
+    fn open<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<ContentReader>> {
+        Box::pin(async move {
+            let file = async_fs::File::open(path).await
+                .map_err(|e| not_found(path, e, "Failed to open file"))?;
+            Ok(Box::pin(file) as ContentReader)
+        })
+    }
+
+    fn read_range<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let mut file = async_fs::File::open(path).await
+                .map_err(|e| not_found(path, e, "Failed to open file"))?;
+            file.seek(SeekFrom::Start(start)).await?;
+            let mut buffer = vec![0u8; end.saturating_sub(start) as usize];
+            file.read_exact(&mut buffer).await?;
+            Ok(buffer)
+        })
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, mut content: ContentReader) -> BoxFuture<'a, Result<StoredObject>> {
+        Box::pin(async move {
+            if let Some(parent) = path.parent() {
+                async_fs::create_dir_all(parent).await
+                    .context("Failed to create document directory")?;
+            }
+            let mut file = async_fs::File::create(path).await
+                .context("Failed to create document")?;
+            tokio::io::copy(&mut content, &mut file).await
+                .context("Failed to write document")?;
+            let metadata = file.metadata().await
+                .context("Failed to read document metadata")?;
+            Ok(stored_object(path, &metadata))
+        })
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            match async_fs::remove_file(path).await {
+                Ok(()) => Ok(true),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e).context("Failed to remove document"),
+            }
+        })
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if let Some(parent) = to.parent() {
+                async_fs::create_dir_all(parent).await
+                    .context("Failed to create document directory")?;
+            }
+            async_fs::rename(from, to).await
+                .map_err(|e| not_found(from, e, "Failed to move document"))
+        })
+    }
+}
+
+struct MemoryObject {
+    content: Arc<[u8]>,
+    modified: DateTime<Utc>,
+}
+
+// Documents are kept in memory, for tests and short-lived engines; the index under `.index`
+// is still written to the search directory. Clones share the same documents.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    objects: Arc<RwLock<BTreeMap<PathBuf, MemoryObject>>>,
+}
+
+impl fmt::Debug for MemoryStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStorage")
+            .field("documents", &self.read().len())
+            .finish()
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<PathBuf, MemoryObject>> {
+        self.objects.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_objects(&self) -> RwLockWriteGuard<'_, BTreeMap<PathBuf, MemoryObject>> {
+        self.objects.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn content(&self, path: &Path) -> Result<Arc<[u8]>> {
+        self.read().get(path)
+            .map(|object| object.content.clone())
+            .ok_or_else(|| SearchEngineError::DocumentNotFound(path.to_string_lossy().to_string()))
+    }
+}
+
+fn memory_object(path: &Path, object: &MemoryObject) -> StoredObject {
+    StoredObject { path: path.to_path_buf(), size: object.content.len() as u64, modified: object.modified }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn list<'a>(&'a self, root: &'a Path, skip: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<StoredObject>>> {
+        Box::pin(async move {
+            Ok(self.read().range(root.to_path_buf()..)
+                .take_while(|(path, _)| path.starts_with(root))
+                .filter(|(path, _)| {
+                    let relative = path.strip_prefix(root).unwrap_or(path);
+                    !relative.components().any(|component| skip.iter().any(|name| component.as_os_str() == *name))
+                })
+                .map(|(path, object)| memory_object(path, object))
+                .collect())
+        })
+    }
+
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Option<StoredObject>>> {
+        Box::pin(async move { Ok(self.read().get(path).map(|object| memory_object(path, object))) })
+    }
+
+    fn open<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<ContentReader>> {
+        Box::pin(async move { Ok(Box::pin(std::io::Cursor::new(self.content(path)?)) as ContentReader) })
+    }
+
+    fn read_range<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let content = self.content(path)?;
+            content.get(start as usize..end as usize)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| SearchEngineError::Io {
+                    context: format!("Failed to read {:?}", path),
+                    source: std::io::ErrorKind::UnexpectedEof.into(),
+                })
+        })
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, mut content: ContentReader) -> BoxFuture<'a, Result<StoredObject>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            content.read_to_end(&mut bytes).await
+                .context("Failed to write document")?;
+            let object = MemoryObject { content: bytes.into(), modified: Utc::now() };
+            let stored = memory_object(path, &object);
+            self.write_objects().insert(path.to_path_buf(), object);
+            Ok(stored)
+        })
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move { Ok(self.write_objects().remove(path).is_some()) })
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut objects = self.write_objects();
+            let object = objects.remove(from)
+                .ok_or_else(|| SearchEngineError::DocumentNotFound(from.to_string_lossy().to_string()))?;
+            objects.insert(to.to_path_buf(), object);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,342 @@
+// Stress test for sharing one engine between concurrent tasks: searchers query nonstop while
+// writers index, update and delete documents, and everything they see is checked against
+// what must hold whatever the interleaving:
+//
+// - no task panics and no search or write fails
+// - documents indexed before the run and never touched are found by every search for them
+// - a writer finds each document it indexed or updated as soon as the call returns, and no
+//   longer finds it or its previous version once deleted or replaced
+// - the index generation never goes down as seen by any task, goes up with every write, and
+//   ends up exactly as many writes past where it started
+// - the document count at the end is what the writes add up to
+//
+// Each writer owns its own documents, so what a writer expects of them isn't raced by the
+// others. Documents are named `stress-*.txt` and left in place, so run it on a scratch
+// directory; `search-engine stress` makes one.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::error::{Result, SearchEngineError};
+use crate::options::SearchOptions;
+use crate::source::DocumentInput;
+use crate::SearchEngine;
+
+// Documents indexed before the run that no writer touches
+const PINNED_DOCUMENTS: usize = 10;
+// Violations kept in the report; later ones are only counted
+const MAX_VIOLATIONS: usize = 50;
+// Filler words, so searches do some scoring besides looking up markers
+const VOCABULARY: &[&str] = &[
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliet",
+    "kilo", "lima", "mike", "november", "oscar", "papa", "quebec", "romeo", "sierra", "tango",
+];
+
+#[derive(Debug, Clone)]
+pub struct StressOptions {
+    pub duration: Duration,
+    pub searchers: usize,
+    pub writers: usize,
+    // Documents each writer cycles through
+    pub documents: usize,
+    // Same seed, same sequence of operations per task; the interleaving still varies
+    pub seed: u64,
+}
+
+impl Default for StressOptions {
+    fn default() -> Self {
+        StressOptions {
+            duration: Duration::from_secs(10),
+            searchers: 4,
+            writers: 2,
+            documents: 20,
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StressReport {
+    // No invariant was violated
+    pub passed: bool,
+    pub elapsed_ms: u64,
+    pub seed: u64,
+    pub searches: u64,
+    pub indexed: u64,
+    pub updated: u64,
+    pub deleted: u64,
+    pub start_generation: u64,
+    pub end_generation: u64,
+    // Indexed documents at the end
+    pub documents: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<String>,
+    // Violations beyond those listed
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub more_violations: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+// xorshift64*: enough to vary operations without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64, stream: u64) -> Self {
+        Rng((seed ^ stream.wrapping_mul(0x9e3779b97f4a7c15)).max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    searches: AtomicU64,
+    indexed: AtomicU64,
+    updated: AtomicU64,
+    deleted: AtomicU64,
+    violations: Mutex<Vec<String>>,
+}
+
+impl Tally {
+    fn violation(&self, message: String) {
+        self.violations.lock().unwrap_or_else(PoisonError::into_inner).push(message);
+    }
+
+    fn writes(&self) -> u64 {
+        self.indexed.load(Ordering::Relaxed) + self.updated.load(Ordering::Relaxed) + self.deleted.load(Ordering::Relaxed)
+    }
+}
+
+// Appears in exactly one line of one document, at a fixed width so no marker contains another
+fn marker(owner: &str, slot: usize, version: u64) -> String {
+    format!("stressmark{}{:04}v{:08}", owner, slot, version)
+}
+
+fn document_text(rng: &mut Rng, marker: &str) -> String {
+    let mut text = String::new();
+    for _ in 0..1 + rng.below(8) {
+        let words: Vec<&str> = (0..3 + rng.below(6)).map(|_| VOCABULARY[rng.below(VOCABULARY.len())]).collect();
+        text.push_str(&words.join(" "));
+        text.push('\n');
+    }
+    text.push_str(&format!("{} {}\n", VOCABULARY[rng.below(VOCABULARY.len())], marker));
+    text
+}
+
+impl SearchEngine {
+    // Runs searches and writes against this engine concurrently for `options.duration`,
+    // checking the invariants above; see the module comment for what it leaves behind
+    pub async fn stress(&self, options: &StressOptions) -> Result<StressReport> {
+        self.ensure_writable()?;
+        let started = Instant::now();
+        let start_documents = self.get_stats().await?.total_documents;
+        let mut rng = Rng::new(options.seed, 0);
+        let mut pinned = Vec::with_capacity(PINNED_DOCUMENTS);
+        for slot in 0..PINNED_DOCUMENTS {
+            let marker = marker("p", slot, 0);
+            let input = DocumentInput::from_bytes(format!("stress-pinned-{}.txt", slot), document_text(&mut rng, &marker));
+            let report = self.index_source(vec![input].into_iter()).await?;
+            if let Some(failure) = report.failed.first() {
+                return Err(SearchEngineError::InvalidInput(format!("Failed to index {}: {}", failure.path, failure.error)));
+            }
+            pinned.push(marker);
+        }
+        let pinned = Arc::new(pinned);
+        let start_generation = self.generation().await?;
+        let deadline = started + options.duration;
+        let tally = Arc::new(Tally::default());
+
+        let mut tasks = Vec::new();
+        for searcher in 0..options.searchers {
+            let (engine, tally, pinned) = (self.clone(), tally.clone(), pinned.clone());
+            let rng = Rng::new(options.seed, 1 + searcher as u64);
+            tasks.push(tokio::spawn(async move { engine.stress_searcher(rng, &pinned, deadline, &tally).await }));
+        }
+        let mut writers = Vec::new();
+        for writer in 0..options.writers {
+            let (engine, tally) = (self.clone(), tally.clone());
+            let rng = Rng::new(options.seed, 1 + (options.searchers + writer) as u64);
+            let documents = options.documents.max(1);
+            writers.push(tokio::spawn(async move { engine.stress_writer(writer, documents, rng, deadline, &tally).await }));
+        }
+
+        for task in tasks {
+            if let Err(e) = task.await {
+                tally.violation(format!("Searcher task failed: {}", e));
+            }
+        }
+        let mut present = 0;
+        for task in writers {
+            match task.await {
+                Ok(documents) => present += documents,
+                Err(e) => tally.violation(format!("Writer task failed: {}", e)),
+            }
+        }
+
+        let end_generation = self.generation().await?;
+        let writes = tally.writes();
+        if end_generation != start_generation + writes {
+            tally.violation(format!(
+                "Generation went from {} to {} over {} writes",
+                start_generation, end_generation, writes
+            ));
+        }
+        let documents = self.get_stats().await?.total_documents;
+        let expected = start_documents + PINNED_DOCUMENTS + present;
+        if documents != expected {
+            tally.violation(format!("{} documents indexed at the end, expected {}", documents, expected));
+        }
+
+        let mut violations = std::mem::take(&mut *tally.violations.lock().unwrap_or_else(PoisonError::into_inner));
+        let more_violations = violations.len().saturating_sub(MAX_VIOLATIONS);
+        violations.truncate(MAX_VIOLATIONS);
+        Ok(StressReport {
+            passed: violations.is_empty(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            seed: options.seed,
+            searches: tally.searches.load(Ordering::Relaxed),
+            indexed: tally.indexed.load(Ordering::Relaxed),
+            updated: tally.updated.load(Ordering::Relaxed),
+            deleted: tally.deleted.load(Ordering::Relaxed),
+            start_generation,
+            end_generation,
+            documents,
+            violations,
+            more_violations,
+        })
+    }
+
+    // Lines matching `query`, recording a failed search as a violation
+    async fn stress_count(&self, query: &str, tally: &Tally) -> Option<usize> {
+        tally.searches.fetch_add(1, Ordering::Relaxed);
+        match self.search(query, &SearchOptions::default()).await {
+            Ok(response) => Some(response.total),
+            Err(e) => {
+                tally.violation(format!("Search for {:?} failed: {}", query, e));
+                None
+            }
+        }
+    }
+
+    // Checks the generation hasn't gone down since `last`, returning it
+    async fn stress_generation(&self, last: u64, who: &str, tally: &Tally) -> u64 {
+        match self.generation().await {
+            Ok(generation) if generation < last => {
+                tally.violation(format!("{} saw the generation go back from {} to {}", who, last, generation));
+                generation
+            }
+            Ok(generation) => generation,
+            Err(e) => {
+                tally.violation(format!("{} couldn't read the generation: {}", who, e));
+                last
+            }
+        }
+    }
+
+    async fn stress_searcher(&self, mut rng: Rng, pinned: &[String], deadline: Instant, tally: &Tally) {
+        let who = "A searcher";
+        let mut generation = 0;
+        while Instant::now() < deadline {
+            if rng.below(2) == 0 {
+                let marker = &pinned[rng.below(pinned.len())];
+                if let Some(total) = self.stress_count(marker, tally).await {
+                    if total != 1 {
+                        tally.violation(format!("{} found {} lines with {}, expected 1", who, total, marker));
+                    }
+                }
+            } else {
+                let query: Vec<&str> = (0..1 + rng.below(2)).map(|_| VOCABULARY[rng.below(VOCABULARY.len())]).collect();
+                self.stress_count(&query.join(" "), tally).await;
+            }
+            generation = self.stress_generation(generation, who, tally).await;
+        }
+    }
+
+    // Returns how many of its documents are indexed at the end
+    async fn stress_writer(&self, writer: usize, documents: usize, mut rng: Rng, deadline: Instant, tally: &Tally) -> usize {
+        let who = format!("Writer {}", writer);
+        let owner = format!("w{:03}", writer);
+        // Path and version of each document while it is indexed
+        let mut slots: Vec<Option<(String, u64)>> = vec![None; documents];
+        let mut version = 0;
+        let mut generation = self.stress_generation(0, &who, tally).await;
+        while Instant::now() < deadline {
+            let slot = rng.below(documents);
+            version += 1;
+            let before = generation;
+            let (written, gone) = match slots[slot].take() {
+                Some((path, old)) if rng.below(3) == 0 => match self.delete_document(&path).await {
+                    Ok(true) => {
+                        tally.deleted.fetch_add(1, Ordering::Relaxed);
+                        (None, Some(old))
+                    }
+                    Ok(false) => {
+                        tally.violation(format!("{} deleted {} but it wasn't indexed", who, path));
+                        (None, Some(old))
+                    }
+                    Err(e) => {
+                        tally.violation(format!("{} couldn't delete {}: {}", who, path, e));
+                        slots[slot] = Some((path, old));
+                        continue;
+                    }
+                },
+                previous => {
+                    let marker = marker(&owner, slot, version);
+                    let input = DocumentInput::from_bytes(format!("stress-{}-{}.txt", owner, slot), document_text(&mut rng, &marker));
+                    match self.index_source(vec![input].into_iter()).await {
+                        Ok(report) if report.failed.is_empty() && report.indexed.len() == 1 => {
+                            let counter = if previous.is_some() { &tally.updated } else { &tally.indexed };
+                            counter.fetch_add(1, Ordering::Relaxed);
+                            slots[slot] = Some((report.indexed[0].path.clone(), version));
+                            (Some(version), previous.map(|(_, old)| old))
+                        }
+                        Ok(report) => {
+                            let error = report.failed.first().map(|failure| failure.error.as_str()).unwrap_or("nothing indexed");
+                            tally.violation(format!("{} couldn't index slot {}: {}", who, slot, error));
+                            slots[slot] = previous;
+                            continue;
+                        }
+                        Err(e) => {
+                            tally.violation(format!("{} couldn't index slot {}: {}", who, slot, e));
+                            slots[slot] = previous;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            generation = self.stress_generation(generation, &who, tally).await;
+            if generation <= before {
+                tally.violation(format!("{} wrote slot {} but the generation stayed at {}", who, slot, generation));
+            }
+            if let Some(version) = written {
+                let marker = marker(&owner, slot, version);
+                if let Some(total) = self.stress_count(&marker, tally).await.filter(|total| *total != 1) {
+                    tally.violation(format!("{} found {} lines with {} right after writing it", who, total, marker));
+                }
+            }
+            if let Some(old) = gone {
+                let marker = marker(&owner, slot, old);
+                if let Some(total) = self.stress_count(&marker, tally).await.filter(|total| *total != 0) {
+                    tally.violation(format!("{} still found {} lines with {} after replacing it", who, total, marker));
+                }
+            }
+        }
+        slots.iter().filter(|slot| slot.is_some()).count()
+    }
+}
@@ -0,0 +1,143 @@
+// Completions for a partly typed query. From the index, the last word of the prefix is completed
+// with indexed terms starting with it, those in the most documents first. From the query log,
+// whole queries users searched before that start with the prefix are proposed, weighted by how
+// often they were searched and whether they found anything: a search with results counts once
+// and one without counts a quarter, and queries that never found anything aren't proposed.
+// Queries differing only in case or spacing are one query, shown as most recently typed.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::instrument;
+
+use crate::analysis;
+use crate::catalog::Catalog;
+use crate::engine::visible_documents;
+use crate::error::Result;
+use crate::matching::is_word_char;
+use crate::normalize::fold_text;
+use crate::querylog::{self, QueryLogEntry};
+use crate::SearchEngine;
+
+// How much a logged search that found nothing adds to its query's weight
+const ZERO_RESULT_WEIGHT: f64 = 0.25;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Suggestion {
+    // The prefix completed
+    pub text: String,
+    // Higher ranks first: documents containing the completed term, or the weight of a logged query
+    pub score: f64,
+    // Times a logged query was searched, and how many of those found something
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub searches: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub successful: Option<usize>,
+}
+
+// Lowercased with runs of whitespace made single spaces, so "Rust  Async" is "rust async"
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// Ranks the logged queries starting with `prefix`
+pub fn suggest_from_log(entries: &[QueryLogEntry], prefix: &str, limit: usize) -> Vec<Suggestion> {
+    #[derive(Default)]
+    struct Totals {
+        display: String,
+        searches: usize,
+        successful: usize,
+    }
+
+    let prefix = normalize(prefix);
+    let mut per_query: HashMap<String, Totals> = HashMap::new();
+    for entry in entries {
+        let key = normalize(&entry.query);
+        if key.is_empty() || !key.starts_with(&prefix) {
+            continue;
+        }
+        let totals = per_query.entry(key).or_default();
+        // Entries are oldest first, so the last spelling seen is the latest
+        totals.display = entry.query.split_whitespace().collect::<Vec<_>>().join(" ");
+        totals.searches += 1;
+        if entry.total > 0 {
+            totals.successful += 1;
+        }
+    }
+
+    let mut suggestions: Vec<Suggestion> = per_query.into_values()
+        .filter(|totals| totals.successful > 0)
+        .map(|totals| Suggestion {
+            text: totals.display,
+            score: totals.successful as f64 + (totals.searches - totals.successful) as f64 * ZERO_RESULT_WEIGHT,
+            searches: Some(totals.searches),
+            successful: Some(totals.successful),
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+impl SearchEngine {
+    // Completes the last word of `prefix` with the terms starting with it in documents
+    // `principals` may see, those in the most documents first; nothing if the prefix ends
+    // between words
+    #[instrument(skip(self))]
+    pub async fn suggest(&self, prefix: &str, limit: usize, principals: Option<&[String]>) -> Result<Vec<Suggestion>> {
+        let start = prefix.char_indices()
+            .rev()
+            .take_while(|(_, c)| is_word_char(*c))
+            .last()
+            .map(|(at, _)| at)
+            .unwrap_or(prefix.len());
+        let (head, word) = prefix.split_at(start);
+        if word.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fold_accents = analysis::current(&self.analyzers).default_analysis().fold_accents;
+        let word = fold_text(word, fold_accents);
+
+        let catalog = self.catalog().await?;
+        let hidden = catalog.documents.len() - visible_documents(&catalog, principals).count();
+        let stats = {
+            let _tables = self.shared.index_lock.read().await;
+            if hidden == 0 {
+                self.corpus_stats(&catalog, true).await
+            } else {
+                // Statistics of only some documents aren't worth keeping
+                let visible = Catalog {
+                    documents: visible_documents(&catalog, principals).cloned().collect(),
+                    counters: catalog.counters.clone(),
+                    last_scanned: catalog.last_scanned,
+                    generation: catalog.generation,
+                    pending: Vec::new(),
+                };
+                self.corpus_stats(&visible, false).await
+            }
+        };
+        let mut terms: Vec<(&str, u64)> = stats.document_frequency.iter()
+            .filter(|(term, _)| term.starts_with(&word))
+            .map(|(term, documents)| (term.as_str(), *documents))
+            .collect();
+        terms.sort_by(|(a, a_documents), (b, b_documents)| b_documents.cmp(a_documents).then_with(|| a.cmp(b)));
+        Ok(terms.into_iter()
+            .take(limit)
+            .map(|(term, documents)| Suggestion {
+                text: format!("{}{}", head, term),
+                score: documents as f64,
+                searches: None,
+                successful: None,
+            })
+            .collect())
+    }
+
+    // Previously searched queries starting with `prefix`, from the query log entries at or
+    // after `since`, by how often they were searched and found something
+    #[instrument(skip(self))]
+    pub async fn suggest_from_history(&self, prefix: &str, limit: usize, since: Option<DateTime<Utc>>) -> Result<Vec<Suggestion>> {
+        let entries = querylog::read(&self.query_log_path(), since).await?;
+        Ok(suggest_from_log(&entries, prefix, limit))
+    }
+}
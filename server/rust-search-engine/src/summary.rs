@@ -0,0 +1,93 @@
+// Extractive summaries: the sentences of a document that best match the query, in
+// document order. Pure computation, so it builds for wasm32 as well.
+
+use crate::keywords::is_stop_word;
+use crate::matching::{contains_word, Matcher};
+use crate::options::MatchMode;
+
+// Sentences picked for a summary
+pub const SUMMARY_SENTENCES: usize = 3;
+// Longer sentences are cut, and so is the whole summary past MAX_SUMMARY_CHARS
+const MAX_SENTENCE_CHARS: usize = 400;
+const MAX_SUMMARY_CHARS: usize = 800;
+// Placed between sentences that aren't adjacent in the document
+const GAP: &str = " … ";
+
+// Splits at sentence-ending punctuation and blank lines. Text without punctuation, such
+// as logs, would otherwise form one huge sentence, so overlong ones are split at line breaks.
+fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut start = 0;
+        let mut chars = paragraph.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let ends_sentence = matches!(c, '.' | '!' | '?')
+                && chars.peek().map(|(_, next)| next.is_whitespace()).unwrap_or(true);
+            if ends_sentence {
+                sentences.push(&paragraph[start..i + c.len_utf8()]);
+                start = i + c.len_utf8();
+            }
+        }
+        sentences.push(&paragraph[start..]);
+    }
+
+    sentences.into_iter()
+        .flat_map(|sentence| {
+            if sentence.len() > MAX_SENTENCE_CHARS {
+                sentence.lines().collect()
+            } else {
+                vec![sentence]
+            }
+        })
+        .map(|sentence| sentence.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|sentence| !sentence.is_empty())
+        .collect()
+}
+
+fn truncate(text: &mut String, max_chars: usize) {
+    if let Some((end, _)) = text.char_indices().nth(max_chars) {
+        text.truncate(end);
+        text.push('…');
+    }
+}
+
+// Up to `max_sentences` sentences of `text` ranked by matches of the whole query, then by
+// how many distinct query words they contain; ties go to the earlier sentence
+pub(crate) fn summarize(text: &str, matcher: &Matcher, max_sentences: usize) -> String {
+    let words: Vec<&str> = match matcher.mode() {
+        MatchMode::Regex => Vec::new(),
+        _ => matcher.query().split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !is_stop_word(word))
+            .collect(),
+    };
+    let sentences = sentences(text);
+    let mut ranked: Vec<(usize, usize)> = sentences.iter().enumerate()
+        .map(|(i, sentence)| {
+            let lower = matcher.fold(sentence);
+            let matched_words = words.iter().filter(|word| contains_word(&lower, word)).count();
+            (i, matcher.highlights(sentence).len() * 10 + matched_words * 2)
+        })
+        .filter(|(_, score)| *score > 0)
+        .collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut picked: Vec<usize> = ranked.into_iter().take(max_sentences).map(|(i, _)| i).collect();
+    // Nothing matched at sentence level, e.g. a match spanning a sentence break
+    if picked.is_empty() {
+        picked = (0..sentences.len().min(max_sentences)).collect();
+    }
+    picked.sort_unstable();
+
+    let mut summary = String::new();
+    let mut previous = None;
+    for i in picked {
+        if let Some(previous) = previous {
+            summary.push_str(if i == previous + 1 { " " } else { GAP });
+        }
+        let mut sentence = sentences[i].clone();
+        truncate(&mut sentence, MAX_SENTENCE_CHARS);
+        summary.push_str(&sentence);
+        previous = Some(i);
+    }
+    truncate(&mut summary, MAX_SUMMARY_CHARS);
+    summary
+}
@@ -0,0 +1,170 @@
+// Synonyms expanding query terms, read from a file named by `"synonyms"` in
+// `search-engine.json`. Each line either maps words one way or lists words that all mean the
+// same, and `#` starts a comment:
+//
+//   error => failure, fault
+//   car, automobile, auto
+//
+// A search for "error" then also matches lines with "failure" or "fault", but not the other
+// way round, while any of "car", "automobile" and "auto" matches all three. Entries may be
+// several words long. Synonym matches count for a little less than the words they stand in
+// for, so lines with the query's own words rank first. Only terms that aren't negated are
+// expanded; phrases, fuzzy terms and wildcards match as written.
+
+use std::collections::HashMap;
+
+use crate::boolean::QueryNode;
+use crate::error::{Result, SearchEngineError};
+
+// What an occurrence of a synonym counts for next to one of the text it stands in for
+pub const SYNONYM_WEIGHT: f32 = 0.8;
+// Most variants one term expands to
+const MAX_VARIANTS: usize = 32;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Synonyms {
+    // Lowercased words separated by single spaces, and what they expand to
+    expansions: HashMap<String, Vec<String>>,
+    // Most words in an entry that expands
+    longest: usize,
+}
+
+fn normalize(words: &str) -> String {
+    words.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+impl Synonyms {
+    // A synonym file's text; fails on a line with an empty side or a single word
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut synonyms = Synonyms::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |problem: &str| SearchEngineError::InvalidConfig(format!("synonyms line {}: {}", number + 1, problem));
+            let list = |side: &str| -> Vec<String> {
+                side.split(',').map(normalize).filter(|entry| !entry.is_empty()).collect()
+            };
+            match line.split_once("=>") {
+                Some((from, to)) => {
+                    let (from, to) = (list(from), list(to));
+                    if from.is_empty() || to.is_empty() {
+                        return Err(invalid("both sides of => need words"));
+                    }
+                    for entry in &from {
+                        synonyms.add(entry, &to);
+                    }
+                }
+                None => {
+                    let group = list(line);
+                    if group.len() < 2 {
+                        return Err(invalid("list at least two words separated by commas, or map them with =>"));
+                    }
+                    for entry in &group {
+                        synonyms.add(entry, &group);
+                    }
+                }
+            }
+        }
+        Ok(synonyms)
+    }
+
+    fn add(&mut self, entry: &str, synonyms: &[String]) {
+        let expansions = self.expansions.entry(entry.to_string()).or_default();
+        for synonym in synonyms {
+            if synonym != entry && !expansions.contains(synonym) {
+                expansions.push(synonym.clone());
+            }
+        }
+        self.longest = self.longest.max(entry.split(' ').count());
+    }
+
+    // Entries that expand to something
+    pub fn len(&self) -> usize {
+        self.expansions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expansions.is_empty()
+    }
+
+    // What `text` expands to, lowercased: the text with one occurrence of an entry, as
+    // whole words, replaced by each of the entry's synonyms
+    pub fn variants(&self, text: &str) -> Vec<String> {
+        let mut variants = Vec::new();
+        if self.is_empty() {
+            return variants;
+        }
+        let text = normalize(text);
+        let words: Vec<&str> = text.split(' ').collect();
+        for start in 0..words.len() {
+            for length in 1..=self.longest.min(words.len() - start) {
+                let Some(synonyms) = self.expansions.get(&words[start..start + length].join(" ")) else {
+                    continue;
+                };
+                for synonym in synonyms {
+                    let variant = words[..start].iter()
+                        .chain(std::iter::once(&synonym.as_str()))
+                        .chain(&words[start + length..])
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    if variant != text && !variants.contains(&variant) {
+                        variants.push(variant);
+                    }
+                    if variants.len() == MAX_VARIANTS {
+                        return variants;
+                    }
+                }
+            }
+        }
+        variants
+    }
+
+    // The tree of a query whose text is `text`, with every term that isn't negated OR-ed with
+    // its synonyms. A query matched as written becomes a tree if its text has synonyms.
+    pub fn expand(&self, text: &str, tree: Option<QueryNode>) -> Option<QueryNode> {
+        match tree {
+            Some(tree) => Some(self.expand_node(tree)),
+            None => {
+                let text = text.to_lowercase();
+                let variants = self.variants(&text);
+                (!variants.is_empty()).then(|| with_synonyms(text, variants))
+            }
+        }
+    }
+
+    fn expand_node(&self, node: QueryNode) -> QueryNode {
+        match node {
+            QueryNode::Term(term) => {
+                let variants = self.variants(&term);
+                if variants.is_empty() {
+                    QueryNode::Term(term)
+                } else {
+                    with_synonyms(term, variants)
+                }
+            }
+            QueryNode::And(nodes) => QueryNode::And(nodes.into_iter().map(|node| self.expand_node(node)).collect()),
+            QueryNode::Or(nodes) => QueryNode::Or(nodes.into_iter().map(|node| self.expand_node(node)).collect()),
+            node => node,
+        }
+    }
+}
+
+fn with_synonyms(term: String, variants: Vec<String>) -> QueryNode {
+    let synonyms = variants.into_iter().map(|variant| QueryNode::Synonym { term: variant, of: term.clone() });
+    QueryNode::Or(std::iter::once(QueryNode::Term(term.clone())).chain(synonyms).collect())
+}
+
+// Reads a synonym file
+#[cfg(feature = "engine")]
+pub async fn load_synonyms(path: Option<&std::path::Path>) -> Result<Synonyms> {
+    let Some(path) = path else {
+        return Ok(Synonyms::default());
+    };
+    let text = tokio::fs::read_to_string(path).await.map_err(|e| {
+        SearchEngineError::InvalidConfig(format!("Failed to read synonyms from {:?}: {}", path, e))
+    })?;
+    Synonyms::parse(&text)
+}
@@ -0,0 +1,88 @@
+// User-assigned tags: short keywords kept with a document's attributes, so they survive
+// rescans and re-indexing, and can be changed without touching the file. Searches filter on
+// them with `tag:` terms or `SearchFilters::tags`.
+
+use std::collections::BTreeMap;
+
+use crate::catalog::ChangeKind;
+use crate::error::{Result, SearchEngineError};
+use crate::SearchEngine;
+
+pub const MAX_TAG_LENGTH: usize = 64;
+
+// Lowercases a tag and checks it is a single word of letters, digits, '-', '_', '.' or '/'
+// starting with a letter or digit
+pub fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim().trim_start_matches("tag:").to_lowercase();
+    let valid = tag.starts_with(char::is_alphanumeric)
+        && tag.len() <= MAX_TAG_LENGTH
+        && tag.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'));
+    if !valid {
+        return Err(SearchEngineError::InvalidInput(format!(
+            "Invalid tag {:?}: use up to {} letters, digits, '-', '_', '.' or '/', starting with a letter or digit", tag, MAX_TAG_LENGTH
+        )));
+    }
+    Ok(tag)
+}
+
+impl SearchEngine {
+    // Adds tags to a document found by path or ID, returning all of its tags
+    pub async fn add_tags(&self, document: &str, tags: &[String]) -> Result<Vec<String>> {
+        let tags = tags.iter().map(|tag| normalize_tag(tag)).collect::<Result<Vec<_>>>()?;
+        self.change_tags("tag", document, |current| {
+            for tag in tags {
+                if !current.contains(&tag) {
+                    current.push(tag);
+                }
+            }
+            current.sort();
+        }).await
+    }
+
+    // Removes tags from a document found by path or ID, returning the tags it has left
+    pub async fn remove_tags(&self, document: &str, tags: &[String]) -> Result<Vec<String>> {
+        let tags = tags.iter().map(|tag| normalize_tag(tag)).collect::<Result<Vec<_>>>()?;
+        self.change_tags("untag", document, |current| current.retain(|tag| !tags.contains(tag))).await
+    }
+
+    async fn change_tags(&self, operation: &str, document: &str, change: impl FnOnce(&mut Vec<String>)) -> Result<Vec<String>> {
+        self.ensure_writable()?;
+        let _write = self.lock_writes().await?;
+        let Some(file_path) = self.resolve_document(document).await? else {
+            return Err(SearchEngineError::DocumentNotFound(document.to_string()));
+        };
+
+        let tags = self.update_catalog(|catalog| {
+            let doc = catalog.documents.iter_mut().find(|doc| doc.path == file_path)?;
+            change(&mut doc.attributes.tags);
+            let (tags, document_id) = (doc.attributes.tags.clone(), doc.attributes.document_id.clone());
+            catalog.record(ChangeKind::Updated, &file_path, document_id);
+            Some(tags)
+        }).await?.unwrap_or_default();
+        let result = self.commit_catalog().await;
+        let documents = vec![file_path.to_string_lossy().to_string()];
+        self.audit(operation, documents, result.as_ref().map_err(|e| e.to_string()).cloned()).await;
+        result.map(|_| tags)
+    }
+
+    // Tags of a document found by path or ID
+    pub async fn document_tags(&self, document: &str) -> Result<Vec<String>> {
+        let Some(file_path) = self.resolve_document(document).await? else {
+            return Err(SearchEngineError::DocumentNotFound(document.to_string()));
+        };
+        Ok(self.catalog().await?.get(&file_path)
+            .map(|doc| doc.attributes.tags.clone())
+            .unwrap_or_default())
+    }
+
+    // Number of documents carrying each tag
+    pub async fn tag_counts(&self) -> Result<BTreeMap<String, usize>> {
+        let mut counts = BTreeMap::new();
+        for doc in &self.catalog().await?.documents {
+            for tag in &doc.attributes.tags {
+                *counts.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(counts)
+    }
+}
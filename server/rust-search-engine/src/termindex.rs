@@ -0,0 +1,297 @@
+// Where the terms of a query occur. A postings table lists the lines of one document that
+// each term is on, and a term dictionary the documents of a corpus; `candidates` looks a
+// query's terms up in either, narrowing what has to be read to where it could match.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Bound;
+
+use crate::boolean::QueryNode;
+use crate::fuzzy::edit_distance;
+use crate::matching::{has_cjk, Matcher};
+use crate::options::MatchMode;
+use crate::wildcard::{literal_prefix, wildcard_matches};
+
+// Sorted positions, lines or documents, that terms occur at
+pub(crate) trait TermLookup {
+    // Where `term` occurs, or a term containing it unless `exact`. Only the first
+    // `max_expansions` terms containing it may count; `limited` is set if there were more.
+    fn with_term(&self, term: &str, exact: bool, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize>;
+    // Where any term `accept`s occurs, counting the terms as `with_term` does
+    fn with_any(&self, accept: &dyn Fn(&str) -> bool, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize>;
+    // Where a term fitting the wildcard `pattern` occurs, counting the terms as `with_term` does
+    fn with_pattern(&self, pattern: &str, max_expansions: Option<usize>, limited: &mut bool) -> Vec<usize>;
+    // Where a word with the stem `stem` occurs; nowhere unless stems are listed
+    fn with_stem(&self, stem: &str) -> Vec<usize>;
+    fn lists_stems(&self) -> bool;
+}
+
+// False for regex queries and queries with no letters or digits, which terms can't narrow
+// down. Every term of a parsed query needs some, as positions with any of them must be read.
+// With an analyzer, only word mode terms are looked up by the terms it lists.
+pub(crate) fn can_narrow(matcher: &Matcher) -> bool {
+    if matcher.mode() == MatchMode::Regex {
+        return false;
+    }
+    if matcher.analyzer().is_some() {
+        let terms_only = matcher.leaves().iter().all(|leaf| matches!(leaf, QueryNode::Term(_) | QueryNode::Synonym { .. }));
+        if matcher.mode() != MatchMode::Word || !terms_only {
+            return false;
+        }
+    }
+    if matcher.tree().is_some() {
+        return matcher.leaves().iter().all(|leaf| !matcher.terms_of(leaf.text().unwrap_or_default()).is_empty());
+    }
+    !matcher.terms_of(matcher.query()).is_empty()
+}
+
+// Sorted positions present in both
+pub(crate) fn intersect(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let (mut i, mut j, mut both) = (0, 0, Vec::new());
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                both.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    both
+}
+
+// Sorted positions present in either
+pub(crate) fn union(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut either: Vec<usize> = a.iter().chain(b).copied().collect();
+    either.sort_unstable();
+    either.dedup();
+    either
+}
+
+// Positions the query can match at, or None when `lookup` can't narrow them down. A match
+// contains every term of the query, whole in word mode and as part of one of the listed
+// terms otherwise. For a parsed query, these are the positions with any of its terms or
+// phrases, negated or not, since all of them decide whether the document matches; a
+// phrase's or NEAR's words must all be there, whole, a fuzzy term matches the positions of
+// every listed term within its distance, and a wildcard those of every listed term fitting
+// it. With stemming, a term or the query also matches where words with the same stems are,
+// which only a lookup listing stems can tell. Stop words are skipped where every term must
+// be there; where a stop word could be one of the listed terms a term matches, the lookup
+// can't tell where those are, as stop words aren't listed.
+// The flag is set when a term expanded to more listed terms than the matcher's limits
+// allow, so some positions that match were left out.
+pub(crate) fn candidates(lookup: &impl TermLookup, matcher: &Matcher) -> Option<(Vec<usize>, bool)> {
+    // Positions with the query's terms in any case hold those matching in its own
+    if matcher.is_case_sensitive() {
+        return candidates(lookup, &matcher.ignoring_case());
+    }
+    if !can_narrow(matcher) || (matcher.stemming() && !lookup.lists_stems()) {
+        return None;
+    }
+    let stopwords = matcher.stopwords();
+    let exact = matcher.mode() == MatchMode::Word;
+    let max_expansions = matcher.limits().max_expansions;
+    let mut limited = false;
+    let candidates = if matcher.tree().is_some() {
+        let mut candidates = Vec::new();
+        for leaf in matcher.leaves() {
+            let positions = match leaf {
+                QueryNode::Phrase(phrase) | QueryNode::Near { terms: phrase, .. } => {
+                    with_all(lookup, matcher, phrase, true, max_expansions, &mut limited)?
+                }
+                QueryNode::Fuzzy { term, distance } => {
+                    if stopwords.iter().any(|word| edit_distance(word, term, *distance).is_some()) {
+                        return None;
+                    }
+                    lookup.with_any(&|entry| edit_distance(entry, term, *distance).is_some(), max_expansions, &mut limited)
+                }
+                QueryNode::Wildcard(pattern) => {
+                    if stopwords.iter().any(|word| wildcard_matches(pattern, word)) {
+                        return None;
+                    }
+                    lookup.with_pattern(pattern, max_expansions, &mut limited)
+                }
+                _ => {
+                    let text = leaf.text().unwrap_or_default();
+                    let positions = with_all(lookup, matcher, text, exact, max_expansions, &mut limited)?;
+                    with_stems(lookup, matcher, text, positions)?
+                }
+            };
+            candidates = union(&candidates, &positions);
+        }
+        candidates
+    } else {
+        let positions = with_all(lookup, matcher, matcher.query(), exact, max_expansions, &mut limited)?;
+        with_stems(lookup, matcher, matcher.query(), positions)?
+    };
+    Some((candidates, limited))
+}
+
+// Positions with every term of `text` besides stop words, or None if it has no other terms
+// or is matched as part of listed terms and could be part of a stop word
+fn with_all(
+    lookup: &impl TermLookup,
+    matcher: &Matcher,
+    text: &str,
+    exact: bool,
+    max_expansions: Option<usize>,
+    limited: &mut bool,
+) -> Option<Vec<usize>> {
+    let stopwords = matcher.stopwords();
+    let mut candidates: Option<Vec<usize>> = None;
+    for term in matcher.terms_of(text) {
+        let term = term.as_ref();
+        let exact = exact && (matcher.analyzer().is_some() || !has_cjk(term));
+        if exact && stopwords.contains(term) {
+            continue;
+        }
+        if !exact && stopwords.iter().any(|word| word.contains(term)) {
+            return None;
+        }
+        let positions = lookup.with_term(term, exact, max_expansions, limited);
+        let narrowed = match candidates {
+            Some(candidates) => intersect(&candidates, &positions),
+            None => positions,
+        };
+        if narrowed.is_empty() {
+            return Some(narrowed);
+        }
+        candidates = Some(narrowed);
+    }
+    candidates
+}
+
+// `positions` plus those with every stem of `text`, if the matcher stems it. None if a stem
+// is shared with a stop word, which isn't listed.
+fn with_stems(lookup: &impl TermLookup, matcher: &Matcher, text: &str, positions: Vec<usize>) -> Option<Vec<usize>> {
+    let Some(stems) = matcher.stems(text) else {
+        return Some(positions);
+    };
+    if stems.iter().any(|stem| matcher.stopwords().contains_stem(stem)) {
+        return None;
+    }
+    let stemmed = stems.iter()
+        .map(|stem| lookup.with_stem(stem))
+        .reduce(|candidates, positions| intersect(&candidates, &positions))
+        .unwrap_or_default();
+    Some(union(&positions, &stemmed))
+}
+
+// Adds `document` to the sorted documents of `key`
+fn add_document(map: &mut BTreeMap<String, Vec<usize>>, key: &str, document: usize) {
+    let documents = match map.get_mut(key) {
+        Some(documents) => documents,
+        None => map.entry(key.to_string()).or_default(),
+    };
+    if let Err(at) = documents.binary_search(&document) {
+        documents.insert(at, document);
+    }
+}
+
+// Sorted documents of the entries `accept`ed
+fn documents_with<'a>(entries: impl Iterator<Item = (&'a String, &'a Vec<usize>)>, accept: &dyn Fn(&str) -> bool) -> Vec<usize> {
+    let mut documents = Vec::new();
+    let mut matched = 0;
+    for (_, listed) in entries.filter(|(entry, _)| accept(entry)) {
+        documents.extend_from_slice(listed);
+        matched += 1;
+    }
+    if matched > 1 {
+        documents.sort_unstable();
+        documents.dedup();
+    }
+    documents
+}
+
+// The documents of a corpus each term occurs in, numbered by the corpus, and with stemming
+// the documents of each stem. Terms are those of postings tables, so stop words are left
+// out in the same way.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TermDictionary {
+    terms: BTreeMap<String, Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stems: Option<BTreeMap<String, Vec<usize>>>,
+}
+
+impl TermDictionary {
+    pub fn new(stemmed: bool) -> Self {
+        TermDictionary { terms: BTreeMap::new(), stems: stemmed.then(BTreeMap::new) }
+    }
+
+    pub fn add_term(&mut self, document: usize, term: &str) {
+        add_document(&mut self.terms, term, document);
+    }
+
+    // Ignored unless the dictionary lists stems
+    pub fn add_stem(&mut self, document: usize, stem: &str) {
+        if let Some(stems) = self.stems.as_mut() {
+            add_document(stems, stem, document);
+        }
+    }
+
+    // Drops `documents` from every term and stem, and the entries left without any
+    pub fn remove_documents(&mut self, documents: &HashSet<usize>) {
+        if documents.is_empty() {
+            return;
+        }
+        for map in std::iter::once(&mut self.terms).chain(self.stems.as_mut()) {
+            map.retain(|_, listed| {
+                listed.retain(|document| !documents.contains(document));
+                !listed.is_empty()
+            });
+        }
+    }
+
+    pub fn term_count(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_stemmed(&self) -> bool {
+        self.stems.is_some()
+    }
+
+    // Every document number listed, sorted
+    pub fn documents(&self) -> Vec<usize> {
+        let lists = self.terms.values().chain(self.stems.iter().flat_map(|stems| stems.values()));
+        let mut documents: Vec<usize> = lists.flatten().copied().collect();
+        documents.sort_unstable();
+        documents.dedup();
+        documents
+    }
+
+    // The documents `matcher` can match in, or None when the dictionary can't tell
+    pub(crate) fn documents_matching(&self, matcher: &Matcher) -> Option<Vec<usize>> {
+        candidates(self, matcher).map(|(documents, _)| documents)
+    }
+}
+
+// Unlike a table's lookups, every listed term a query term expands to counts, whatever the
+// matcher's limits, so no document whose table would find lines is left out
+impl TermLookup for TermDictionary {
+    fn with_term(&self, term: &str, exact: bool, _max_expansions: Option<usize>, _limited: &mut bool) -> Vec<usize> {
+        if exact {
+            return self.terms.get(term).cloned().unwrap_or_default();
+        }
+        documents_with(self.terms.iter(), &|entry| entry.contains(term))
+    }
+
+    fn with_any(&self, accept: &dyn Fn(&str) -> bool, _max_expansions: Option<usize>, _limited: &mut bool) -> Vec<usize> {
+        documents_with(self.terms.iter(), accept)
+    }
+
+    fn with_pattern(&self, pattern: &str, _max_expansions: Option<usize>, _limited: &mut bool) -> Vec<usize> {
+        let prefix = literal_prefix(pattern);
+        let entries = self.terms.range::<str, _>((Bound::Included(prefix), Bound::Unbounded)).take_while(|(entry, _)| entry.starts_with(prefix));
+        documents_with(entries, &|entry| wildcard_matches(pattern, entry))
+    }
+
+    fn with_stem(&self, stem: &str) -> Vec<usize> {
+        self.stems.as_ref().and_then(|stems| stems.get(stem)).cloned().unwrap_or_default()
+    }
+
+    fn lists_stems(&self) -> bool {
+        self.stems.is_some()
+    }
+}
@@ -0,0 +1,45 @@
+// Scratch search directories for tests, removed when dropped
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::source::DocumentInput;
+use crate::SearchEngine;
+
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("search-engine-test-{}-{}-{}", name, std::process::id(), n));
+        std::fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    // An engine with the default settings on the directory
+    pub(crate) async fn engine(&self) -> SearchEngine {
+        SearchEngine::builder(&self.0).build().await.unwrap()
+    }
+}
+
+// Indexes documents, failing the test if any can't be
+pub(crate) async fn index(engine: &SearchEngine, documents: Vec<DocumentInput>) {
+    let report = engine.index_source(documents.into_iter()).await.unwrap();
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Runs an engine call to completion on a runtime of its own
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+}
@@ -0,0 +1,220 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+use tracing::{info, instrument, warn};
+
+use crate::error::{Context, Result, SearchEngineError};
+use crate::catalog::{CatalogEntry, DocumentAttributes};
+use crate::docstore::fnv1a64;
+use crate::{IndexedDocument, SearchEngine};
+
+pub const TRASH_DIR: &str = ".trash";
+pub const DEFAULT_TRASH_RETENTION: &str = "7d";
+const ENTRY_FILE: &str = "entry.json";
+
+// A deleted document kept in `.trash/<id>/` alongside an entry.json describing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TrashEntry {
+    pub id: String,
+    // Original location, relative to the search directory
+    pub path: String,
+    pub size: u64,
+    pub deleted_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub attributes: DocumentAttributes,
+}
+
+impl SearchEngine {
+    fn trash_dir(&self) -> PathBuf {
+        self.search_path.join(TRASH_DIR)
+    }
+
+    // Removes a document from the index, moving it to the trash when enabled, and returns
+    // its trash entry if it was. Leaves the catalog uncommitted, like `remove_document`.
+    pub(crate) async fn discard_document(&self, file_path: &Path) -> Result<Option<TrashEntry>> {
+        self.ensure_owned(file_path)?;
+        if self.trash_retention.is_none() {
+            self.remove_document(file_path).await?;
+            return Ok(None);
+        }
+
+        let deleted_at = Utc::now();
+        let relative = self.relative_path(file_path);
+        let id = format!("{}-{:016x}", deleted_at.format("%Y%m%dT%H%M%S%6fZ"), fnv1a64(relative.as_bytes()));
+        let entry_dir = self.trash_dir().join(&id);
+        let file_name = file_path.file_name()
+            .ok_or_else(|| SearchEngineError::UnsupportedDocument(format!("{:?} has no file name", file_path)))?;
+
+        async_fs::create_dir_all(&entry_dir).await
+            .context("Failed to create trash directory")?;
+        self.storage.rename(file_path, &entry_dir.join(file_name)).await?;
+
+        let catalog = self.catalog().await?;
+        let (size, attributes) = match catalog.get(file_path) {
+            Some(doc) => (doc.size, doc.attributes.clone()),
+            None => (0, DocumentAttributes::default()),
+        };
+        let entry = TrashEntry {
+            id,
+            path: relative,
+            size,
+            deleted_at,
+            attributes,
+        };
+        async_fs::write(entry_dir.join(ENTRY_FILE), serde_json::to_vec_pretty(&entry)?).await
+            .context("Failed to write trash entry")?;
+
+        self.doc_store.remove_offsets(file_path).await?;
+        self.vector_store.remove(file_path).await?;
+        self.postings_store.remove(file_path).await?;
+        self.update_catalog(|catalog| catalog.remove(file_path)).await?;
+        Ok(Some(entry))
+    }
+
+    // Trashed documents, oldest first
+    pub async fn trash_entries(&self) -> Result<Vec<TrashEntry>> {
+        let mut entries = Vec::new();
+        let mut dir = match async_fs::read_dir(self.trash_dir()).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e).context("Failed to read trash directory"),
+        };
+
+        while let Some(item) = dir.next_entry().await? {
+            let entry_path = item.path().join(ENTRY_FILE);
+            let bytes = match async_fs::read(&entry_path).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    warn!(path = ?entry_path, error = %e, "Skipping unreadable trash entry");
+                    continue;
+                }
+            };
+            match serde_json::from_slice::<TrashEntry>(&bytes) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!(path = ?entry_path, error = %e, "Skipping unreadable trash entry"),
+            }
+        }
+
+        entries.sort_by_key(|entry| entry.deleted_at);
+        Ok(entries)
+    }
+
+    fn trashed_file(&self, entry: &TrashEntry) -> PathBuf {
+        let file_name = Path::new(&entry.path).file_name().unwrap_or_default();
+        self.trash_dir().join(&entry.id).join(file_name)
+    }
+
+    // Trash entries `purge-trash` would remove: those past the retention period, or all of them
+    pub(crate) async fn purgeable_trash(&self, all: bool, now: DateTime<Utc>) -> Result<Vec<TrashEntry>> {
+        let retention = self.trash_retention;
+        Ok(self.trash_entries().await?.into_iter()
+            .filter(|entry| all || retention.map(|retention| entry.deleted_at + retention <= now).unwrap_or(true))
+            .collect())
+    }
+
+    pub(crate) fn trash_paths(&self, entries: &[TrashEntry]) -> Vec<PathBuf> {
+        entries.iter().map(|entry| self.trashed_file(entry)).collect()
+    }
+
+    // Permanently deletes trashed documents, returning how many were due and the files purged
+    pub(crate) async fn purge_trash(&self, all: bool, now: DateTime<Utc>) -> Result<(usize, Vec<PathBuf>)> {
+        let entries = self.purgeable_trash(all, now).await?;
+        let mut purged = Vec::new();
+        for entry in &entries {
+            match self.purge_entry(entry).await {
+                Ok(()) => purged.push(self.trashed_file(entry)),
+                Err(e) => warn!(id = %entry.id, error = %e, "Failed to purge trashed document"),
+            }
+        }
+        Ok((entries.len(), purged))
+    }
+
+    // Permanently deletes one trashed document
+    pub(crate) async fn purge_entry(&self, entry: &TrashEntry) -> Result<()> {
+        self.storage.remove(&self.trashed_file(entry)).await?;
+        async_fs::remove_dir_all(self.trash_dir().join(&entry.id)).await
+            .context("Failed to purge trash entry")?;
+        Ok(())
+    }
+
+    // Moves the most recently trashed copy of a document (or the entry with this id)
+    // back to its original location and indexes it again
+    #[instrument(skip(self))]
+    pub async fn restore_document(&self, path: &str) -> Result<IndexedDocument> {
+        self.ensure_writable()?;
+        let _write = self.lock_writes().await?;
+        let result = self.restore_from_trash(path).await;
+        let documents = match &result {
+            Ok(document) => vec![document.path.clone()],
+            Err(_) => vec![path.to_string()],
+        };
+        self.audit("restore", documents, result.as_ref().map(|_| ()).map_err(|e| e.to_string())).await;
+        result
+    }
+
+    async fn restore_from_trash(&self, path: &str) -> Result<IndexedDocument> {
+        let Some(entry) = self.trash_entries().await?.into_iter()
+            .rev()
+            .find(|entry| entry.id == path || entry.path == path)
+        else {
+            return Err(SearchEngineError::DocumentNotFound(format!("no trashed document matches {:?}", path)));
+        };
+
+        let target = self.search_path.join(&entry.path);
+        if self.storage.stat(&target).await?.is_some() {
+            return Err(SearchEngineError::Conflict(format!(
+                "Cannot restore {:?}: a file already exists at that path", entry.path
+            )));
+        }
+        if let Some(id) = &entry.attributes.document_id {
+            if let Some(other) = self.catalog().await?.documents.iter()
+                .find(|doc| doc.attributes.document_id.as_deref() == Some(id.as_str()))
+            {
+                return Err(SearchEngineError::Conflict(format!(
+                    "Cannot restore {:?}: its document ID {} is now used by {}", entry.path, id, self.relative_path(&other.path)
+                )));
+            }
+        }
+        self.enforce_quota(&target, entry.size, Some(&entry)).await?;
+
+        self.storage.rename(&self.trashed_file(&entry), &target).await?;
+        if let Err(e) = async_fs::remove_dir_all(self.trash_dir().join(&entry.id)).await {
+            warn!(id = %entry.id, error = %e, "Failed to remove trash entry");
+        }
+
+        let stored = self.storage.stat(&target).await?
+            .ok_or_else(|| SearchEngineError::DocumentNotFound(entry.path.clone()))?;
+        let offsets = self.doc_store.offsets(&target).await?;
+        self.postings_store.postings(&target).await?;
+        let indexed_at = Utc::now();
+        let restored = CatalogEntry {
+            path: target.clone(),
+            size: stored.size,
+            modified: stored.modified,
+            attributes: DocumentAttributes {
+                indexed_at: Some(indexed_at),
+                ..entry.attributes.clone()
+            },
+        };
+        self.update_catalog(|catalog| catalog.insert(restored)).await?;
+        self.commit_catalog().await?;
+        info!(path = ?target, "Restored document from trash");
+
+        Ok(IndexedDocument {
+            path: target.to_string_lossy().to_string(),
+            size: stored.size,
+            line_count: offsets.line_count(),
+            indexed_at,
+            expires_at: entry.attributes.expires_at,
+            document_id: entry.attributes.document_id.clone(),
+            keywords: entry.attributes.keywords.clone(),
+            language: entry.attributes.language.clone(),
+            title: entry.attributes.title.clone(),
+            tags: entry.attributes.tags.clone(),
+            metadata: entry.attributes.metadata.clone(),
+            acl: entry.attributes.acl.clone(),
+        })
+    }
+}
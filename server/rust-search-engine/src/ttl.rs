@@ -0,0 +1,62 @@
+use chrono::{DateTime, Duration, Utc};
+use std::path::PathBuf;
+use tracing::warn;
+
+use crate::error::{Result, SearchEngineError};
+use crate::SearchEngine;
+
+// Parses durations like `500ms`, `90s`, `30m`, `12h`, `7d` or `2w`
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let amount: i64 = amount.parse()
+        .map_err(|_| SearchEngineError::InvalidInput(format!("Invalid duration: {:?}", text)))?;
+
+    let duration = match unit {
+        "ms" => Duration::milliseconds(amount),
+        "s" | "" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => {
+            return Err(SearchEngineError::InvalidInput(format!("Unknown duration unit {:?} in {:?}", unit, text)));
+        }
+    };
+    if duration <= Duration::zero() {
+        return Err(SearchEngineError::InvalidInput(format!("Duration must be positive: {:?}", text)));
+    }
+    Ok(duration)
+}
+
+impl SearchEngine {
+    pub(crate) async fn expired_documents(&self, now: DateTime<Utc>) -> Result<Vec<PathBuf>> {
+        let default_ttl = self.default_ttl;
+        Ok(self.catalog().await?.documents.iter()
+            .filter(|doc| self.root_of(&doc.path).is_none())
+            .filter(|doc| {
+                doc.attributes.expires_at
+                    .or_else(|| default_ttl.map(|ttl| doc.modified + ttl))
+                    .map(|expires_at| expires_at <= now)
+                    .unwrap_or(false)
+            })
+            .map(|doc| doc.path.clone())
+            .collect())
+    }
+
+    // Removes every document whose expiry has passed, returning (expired, removed)
+    pub(crate) async fn expire_documents(&self, now: DateTime<Utc>) -> Result<(usize, usize)> {
+        let expired = self.expired_documents(now).await?;
+
+        let mut removed = 0;
+        for file_path in &expired {
+            match self.remove_document(file_path).await {
+                Ok(()) => removed += 1,
+                Err(e) => warn!(path = ?file_path, error = %e, "Failed to expire document"),
+            }
+        }
+        self.commit_catalog().await?;
+        Ok((expired.len(), removed))
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs as async_fs;
+use tracing::warn;
+
+use crate::docstore::temp_path;
+use crate::error::{Context, Result};
+use crate::SearchEngine;
+
+pub const USAGE_FILE: &str = "usage.json";
+
+// Aggregate counters only: no queries, paths or identities are recorded.
+// Nothing is collected unless enabled, and nothing leaves the machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct UsageStats {
+    pub since: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub queries: u64,
+    pub documents_indexed: u64,
+    pub documents_deleted: u64,
+    pub maintenance_runs: u64,
+}
+
+impl UsageStats {
+    fn new(now: DateTime<Utc>) -> Self {
+        UsageStats {
+            since: now,
+            updated_at: now,
+            queries: 0,
+            documents_indexed: 0,
+            documents_deleted: 0,
+            maintenance_runs: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum UsageEvent {
+    Query,
+    DocumentIndexed,
+    DocumentDeleted,
+    MaintenanceRun,
+}
+
+impl SearchEngine {
+    fn usage_path(&self) -> std::path::PathBuf {
+        self.doc_store.index_dir().join(USAGE_FILE)
+    }
+
+    pub(crate) async fn record_usage(&self, event: UsageEvent) {
+        if !self.usage_stats {
+            return;
+        }
+        if let Err(e) = self.increment_usage(event).await {
+            warn!(error = %e, "Failed to record usage statistics");
+        }
+    }
+
+    async fn increment_usage(&self, event: UsageEvent) -> Result<()> {
+        let _usage = self.shared.usage_lock.lock().await;
+        let now = Utc::now();
+        let mut stats = self.usage().await?.unwrap_or_else(|| UsageStats::new(now));
+        match event {
+            UsageEvent::Query => stats.queries += 1,
+            UsageEvent::DocumentIndexed => stats.documents_indexed += 1,
+            UsageEvent::DocumentDeleted => stats.documents_deleted += 1,
+            UsageEvent::MaintenanceRun => stats.maintenance_runs += 1,
+        }
+        stats.updated_at = now;
+        self.save_usage(&stats).await
+    }
+
+    async fn usage(&self) -> Result<Option<UsageStats>> {
+        match async_fs::read(self.usage_path()).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).context("Failed to parse usage statistics")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read usage statistics"),
+        }
+    }
+
+    async fn save_usage(&self, stats: &UsageStats) -> Result<()> {
+        async_fs::create_dir_all(self.doc_store.index_dir()).await
+            .context("Failed to create index directory")?;
+        let target = self.usage_path();
+        let temp = temp_path(&target);
+        async_fs::write(&temp, serde_json::to_vec(stats)?).await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist usage statistics")?;
+        Ok(())
+    }
+
+    // Exports the counters collected so far, optionally starting a new collection period
+    pub async fn export_usage_stats(&self, reset: bool) -> Result<Option<UsageStats>> {
+        if reset {
+            self.ensure_writable()?;
+        }
+        let _usage = self.shared.usage_lock.lock().await;
+        let stats = self.usage().await?;
+        if reset && stats.is_some() {
+            async_fs::remove_file(self.usage_path()).await
+                .context("Failed to reset usage statistics")?;
+        }
+        Ok(stats)
+    }
+}
@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs as async_fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, warn};
+
+use crate::checksum;
+use crate::docstore::{fnv1a64, temp_path, FileStamp, VECTORS_DIR};
+use crate::embedder::Embedder;
+use crate::error::{Context, Result, SearchEngineError};
+use crate::normalize::normalize_query;
+use crate::options::{MatchMode, SearchOptions};
+use crate::storage::StorageBackend;
+use crate::usage::UsageEvent;
+use crate::SearchEngine;
+
+const VECTORS_MAGIC: &[u8; 4] = b"VECS";
+const VECTORS_VERSION: u32 = 3;
+// Paragraphs are split into chunks of up to this many characters (or `CHUNK_LINES` lines)
+const CHUNK_CHARS: usize = 1000;
+const CHUNK_LINES: usize = 20;
+
+async fn embed(embedder: &dyn Embedder, text: &str) -> Result<Vec<f32>> {
+    let mut vector = embedder.embed(text).await?;
+    if vector.len() != embedder.dimensions() {
+        return Err(SearchEngineError::InvalidConfig(format!(
+            "Embedder {} returned {} dimensions instead of {}", embedder.model(), vector.len(), embedder.dimensions()
+        )));
+    }
+    normalize(&mut vector);
+    Ok(vector)
+}
+
+// Scales a vector to unit length, so the dot product of two vectors is their cosine similarity
+pub fn normalize(vector: &mut [f32]) {
+    let norm = dot(vector, vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= norm);
+    }
+}
+
+// Accumulates in eight independent lanes so the compiler can vectorize the loop
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let (a_chunks, b_chunks) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: f32 = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(x, y)| x * y).sum();
+    let mut lanes = [0.0f32; 8];
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for ((lane, x), y) in lanes.iter_mut().zip(x).zip(y) {
+            *lane += x * y;
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+// The vector of one run of consecutive lines
+#[derive(Debug, Clone)]
+pub struct ChunkVector {
+    pub start_line: usize,
+    pub end_line: usize,
+    // SHA-1 of the chunk text, the key its vector is reused under when the document changes
+    pub content_hash: [u8; 20],
+    pub vector: Vec<f32>,
+}
+
+fn chunk_hash(text: &str) -> [u8; 20] {
+    Sha1::digest(text.as_bytes()).into()
+}
+
+// Every chunk vector of a document, tied to the file version and model it was built from
+#[derive(Debug, Clone)]
+pub struct VectorTable {
+    stamp: FileStamp,
+    model: String,
+    dimensions: usize,
+    chunks: Vec<ChunkVector>,
+}
+
+impl VectorTable {
+    // Only chunks whose text isn't in `cached` (vectors from the same model) are embedded
+    async fn build(
+        storage: &dyn StorageBackend,
+        embedder: &dyn Embedder,
+        file_path: &Path,
+        stamp: FileStamp,
+        cached: &HashMap<[u8; 20], &[f32]>,
+    ) -> Result<Self> {
+        let mut lines = BufReader::new(storage.open(file_path).await?).lines();
+        let mut chunks = Vec::new();
+        let mut reused = 0;
+        let mut text = String::new();
+        let (mut start_line, mut line_number) = (1, 0);
+        loop {
+            let line = lines.next_line().await?;
+            let paragraph_end = line.as_deref().map(|line| line.trim().is_empty()).unwrap_or(false);
+            let full = paragraph_end || text.len() >= CHUNK_CHARS || line_number + 1 - start_line >= CHUNK_LINES;
+            if (line.is_none() || full) && line_number >= start_line {
+                if !text.trim().is_empty() {
+                    let content_hash = chunk_hash(&text);
+                    let vector = match cached.get(&content_hash) {
+                        Some(vector) => {
+                            reused += 1;
+                            vector.to_vec()
+                        }
+                        None => embed(embedder, &text).await?,
+                    };
+                    chunks.push(ChunkVector { start_line, end_line: line_number, content_hash, vector });
+                }
+                text.clear();
+                start_line = line_number + 1;
+            }
+            let Some(line) = line else {
+                break;
+            };
+            line_number += 1;
+            if text.is_empty() && line.trim().is_empty() {
+                start_line = line_number + 1;
+                continue;
+            }
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        debug!(path = ?file_path, chunks = chunks.len(), reused, "Embedded document");
+        Ok(VectorTable {
+            stamp,
+            model: embedder.model().to_string(),
+            dimensions: embedder.dimensions(),
+            chunks,
+        })
+    }
+
+    pub fn chunks(&self) -> &[ChunkVector] {
+        &self.chunks
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + self.chunks.len() * (36 + self.dimensions * 4));
+        bytes.extend_from_slice(VECTORS_MAGIC);
+        bytes.extend_from_slice(&VECTORS_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.size.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.modified_secs.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.modified_nanos.to_le_bytes());
+        bytes.extend_from_slice(&(self.model.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.model.as_bytes());
+        bytes.extend_from_slice(&(self.dimensions as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.chunks.len() as u64).to_le_bytes());
+        for chunk in &self.chunks {
+            bytes.extend_from_slice(&(chunk.start_line as u64).to_le_bytes());
+            bytes.extend_from_slice(&(chunk.end_line as u64).to_le_bytes());
+            bytes.extend_from_slice(&chunk.content_hash);
+            for value in &chunk.vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        checksum::seal(&mut bytes);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let bytes = checksum::unseal(bytes, "vector table")?;
+        let corrupted = |message: &str| SearchEngineError::IndexCorrupted(message.to_string());
+        let mut at = 0;
+        let mut take = |len: usize| -> Result<&[u8]> {
+            let slice = bytes.get(at..at + len).ok_or_else(|| corrupted("Truncated vector table"))?;
+            at += len;
+            Ok(slice)
+        };
+        if take(4)? != VECTORS_MAGIC {
+            return Err(corrupted("Invalid vector table"));
+        }
+        let version = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        if version != VECTORS_VERSION {
+            return Err(SearchEngineError::IndexCorrupted(format!("Unsupported vector table version {}", version)));
+        }
+        let stamp = FileStamp {
+            size: u64::from_le_bytes(take(8)?.try_into().unwrap()),
+            modified_secs: i64::from_le_bytes(take(8)?.try_into().unwrap()),
+            modified_nanos: u32::from_le_bytes(take(4)?.try_into().unwrap()),
+        };
+        let model_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let model = String::from_utf8_lossy(take(model_len)?).into_owned();
+        let dimensions = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+
+        let mut chunks = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            let start_line = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+            let end_line = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+            let content_hash = take(20)?.try_into().unwrap();
+            let vector = take(dimensions * 4)?
+                .chunks_exact(4)
+                .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+                .collect();
+            chunks.push(ChunkVector { start_line, end_line, content_hash, vector });
+        }
+        if at != bytes.len() {
+            return Err(corrupted("Vector table has trailing bytes"));
+        }
+        Ok(VectorTable { stamp, model, dimensions, chunks })
+    }
+}
+
+// Persists per-document vector tables under `<search_path>/.index/vectors`
+#[derive(Debug, Clone)]
+pub struct VectorStore {
+    root: PathBuf,
+    // Rebuilt tables are kept in memory only
+    read_only: bool,
+    dir: PathBuf,
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl VectorStore {
+    pub fn new(root: &Path, index_dir: &Path, storage: Arc<dyn StorageBackend>) -> Self {
+        VectorStore {
+            root: root.to_path_buf(),
+            read_only: false,
+            dir: index_dir.join(VECTORS_DIR),
+            storage,
+        }
+    }
+
+    pub(crate) fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn vectors_path(&self, file_path: &Path) -> PathBuf {
+        let relative = file_path.strip_prefix(&self.root).unwrap_or(file_path);
+        let key = fnv1a64(relative.to_string_lossy().as_bytes());
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+
+    async fn load(&self, file_path: &Path) -> Result<Option<VectorTable>> {
+        match async_fs::read(self.vectors_path(file_path)).await {
+            Ok(bytes) => Ok(Some(VectorTable::decode(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read vector table"),
+        }
+    }
+
+    async fn save(&self, file_path: &Path, table: &VectorTable) -> Result<()> {
+        async_fs::create_dir_all(&self.dir).await
+            .context("Failed to create vector directory")?;
+        let target = self.vectors_path(file_path);
+        let temp = temp_path(&target);
+        let mut file = async_fs::File::create(&temp).await?;
+        file.write_all(&table.encode()).await?;
+        file.flush().await?;
+        async_fs::rename(&temp, &target).await
+            .context("Failed to persist vector table")?;
+        Ok(())
+    }
+
+    // Returns the chunk vectors of a document, rebuilding the table if it is missing,
+    // unreadable, or was built from another file version or model. A rebuild for the
+    // same model only embeds chunks whose text changed.
+    pub async fn vectors(&self, file_path: &Path, embedder: &dyn Embedder) -> Result<VectorTable> {
+        let object = self.storage.stat(file_path).await?
+            .ok_or_else(|| SearchEngineError::DocumentNotFound(file_path.to_string_lossy().to_string()))?;
+        let stamp = FileStamp::from_object(&object);
+
+        let stored = self.load(file_path).await
+            .inspect_err(|e| warn!(path = ?file_path, error = %e, "Rebuilding unreadable vector table"))
+            .ok()
+            .flatten()
+            .filter(|stored| stored.model == embedder.model() && stored.dimensions == embedder.dimensions());
+        let stored = match stored {
+            Some(stored) if stored.stamp == stamp => return Ok(stored),
+            stored => stored,
+        };
+
+        let cached = stored.iter()
+            .flat_map(|stored| &stored.chunks)
+            .map(|chunk| (chunk.content_hash, chunk.vector.as_slice()))
+            .collect();
+        let table = VectorTable::build(self.storage.as_ref(), embedder, file_path, stamp, &cached).await?;
+        if !self.read_only {
+            self.save(file_path, &table).await?;
+        }
+        Ok(table)
+    }
+
+    // Checks that a stored table, if the document has one, decodes and matches its checksum;
+    // stale tables are re-embedded when next used
+    pub async fn verify(&self, file_path: &Path) -> Result<()> {
+        self.load(file_path).await.map(|_| ())
+    }
+
+    pub async fn remove(&self, file_path: &Path) -> Result<()> {
+        match async_fs::remove_file(self.vectors_path(file_path)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to remove vector table"),
+        }
+    }
+}
+
+// A run of lines close in meaning to the query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SemanticResult {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+    // Cosine similarity between the query and the chunk, from -1 to 1
+    pub score: f32,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SemanticResponse {
+    pub query: String,
+    pub results: Vec<SemanticResult>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl SearchEngine {
+    fn embedder(&self) -> Result<&dyn Embedder> {
+        self.embedder.as_deref()
+            .ok_or_else(|| SearchEngineError::InvalidConfig("semantic search needs an embedder to be configured".to_string()))
+    }
+
+    // Chunks and embeds a document now rather than on the next semantic search
+    pub(crate) async fn embed_document(&self, file_path: &Path) -> Result<()> {
+        if let Some(embedder) = self.embedder.as_deref() {
+            self.vector_store.vectors(file_path, embedder).await?;
+        }
+        Ok(())
+    }
+
+    // Finds the chunks of text nearest in meaning to a natural-language query by
+    // comparing it against every chunk vector. Uses `limit`, `offset` and `filters`
+    // from the options; match mode, sort order and highlighting don't apply.
+    pub async fn semantic_search(&self, query: &str, options: &SearchOptions) -> Result<SemanticResponse> {
+        let query = normalize_query(query, MatchMode::Substring)?;
+        let embedder = self.embedder()?;
+        let query_vector = embed(embedder, &query).await?;
+
+        let _tables = self.shared.index_lock.read().await;
+        let catalog = self.catalog().await?;
+        let mut scored = Vec::new();
+        for doc in catalog.documents.iter()
+            .filter(|doc| options.filters.matches(&self.relative_path(&doc.path), doc.attributes.title.as_deref()) && options.filters.matches_attributes(&doc.attributes.tags, &doc.attributes.metadata, doc.attributes.language.as_deref(), doc.modified, &doc.attributes.acl))
+        {
+            let table = match self.vector_store.vectors(&doc.path, embedder).await {
+                Ok(table) => table,
+                Err(e) => {
+                    warn!(path = ?doc.path, error = %e, "Failed to embed document");
+                    continue;
+                }
+            };
+            for chunk in table.chunks() {
+                scored.push((dot(&query_vector, &chunk.vector), doc, chunk.start_line, chunk.end_line));
+            }
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let total = scored.len();
+        let mut results = Vec::new();
+        for (score, doc, start_line, end_line) in scored.into_iter().skip(options.offset).take(options.limit) {
+            let content = self.doc_store.read_lines(&doc.path, start_line, end_line).await?.unwrap_or_default();
+            results.push(SemanticResult {
+                path: doc.path.to_string_lossy().to_string(),
+                document_id: doc.attributes.document_id.clone(),
+                start_line,
+                end_line,
+                score,
+                content,
+            });
+        }
+        self.record_usage(UsageEvent::Query).await;
+
+        Ok(SemanticResponse {
+            query,
+            results,
+            total,
+            limit: options.limit,
+            offset: options.offset,
+        })
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+use crate::docstore::INDEX_FORMAT_VERSION;
+
+// Identifies exactly which build is running, for deployments and bug reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct VersionInfo {
+    pub version: String,
+    pub git_hash: String,
+    pub index_format_version: u32,
+    pub features: Vec<String>,
+}
+
+pub fn version_info() -> VersionInfo {
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: env!("SEARCH_ENGINE_GIT_HASH").to_string(),
+        index_format_version: INDEX_FORMAT_VERSION,
+        features: env!("SEARCH_ENGINE_FEATURES")
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .map(str::to_string)
+            .collect(),
+    }
+}
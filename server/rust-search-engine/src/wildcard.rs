@@ -0,0 +1,58 @@
+// Wildcard terms: a query word with `*` (any run of characters, including none) or `?`
+// (exactly one character), as in `index*`, `*ing` or `te?t`, matches whole words that fit
+// the pattern. Indexed terms are sorted, so the literal prefix before the first wildcard
+// narrows the terms a pattern is checked against.
+
+use crate::matching::is_word_char;
+
+pub(crate) fn is_wildcard(c: char) -> bool {
+    c == '*' || c == '?'
+}
+
+// The text of a pattern before its first wildcard, which every term it fits starts with
+pub(crate) fn literal_prefix(pattern: &str) -> &str {
+    &pattern[..pattern.find(is_wildcard).unwrap_or(pattern.len())]
+}
+
+// True if `word` is made of word characters and wildcards with at least one of each
+pub(crate) fn is_pattern(word: &str) -> bool {
+    word.chars().any(is_wildcard)
+        && word.chars().any(is_word_char)
+        && word.chars().all(|c| is_wildcard(c) || is_word_char(c))
+}
+
+// Whether all of lowercase `word` fits `pattern`. Backtracks only to the last `*`, so
+// it runs in time proportional to the lengths multiplied, not exponential in the stars.
+pub(crate) fn wildcard_matches(pattern: &str, word: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let word: Vec<char> = word.chars().collect();
+    let (mut p, mut w) = (0, 0);
+    // Position after the last `*` seen and the word position it was tried against
+    let mut star: Option<(usize, usize)> = None;
+    while w < word.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, w));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                w += 1;
+            }
+            Some(c) if *c == word[w] => {
+                p += 1;
+                w += 1;
+            }
+            _ => match star {
+                // Let the last `*` take one more character and try again
+                Some((after, from)) => {
+                    p = after;
+                    w = from + 1;
+                    star = Some((after, from + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
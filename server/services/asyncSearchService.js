@@ -154,7 +154,7 @@ class AsyncSearchService {
     }
   }
 
-  async executeMaintenance(task, taskId = null) {
+  async executeMaintenance(task, taskId = null, actor) {
     try {
       if (taskId) {
         await taskManager.updateTaskStatus(taskId, 'running', {
@@ -192,7 +192,7 @@ class AsyncSearchService {
       }
 
       // Execute maintenance
-      const result = await rustEngine.runMaintenance(task);
+      const result = await rustEngine.runMaintenance(task, actor);
 
       if (taskId) {
         await taskManager.updateTaskStatus(taskId, 'completed', {
@@ -249,7 +249,7 @@ class AsyncSearchService {
         }
         
         // Get next batch of results
-        const batchResults = await rustEngine.search(query, batchSize, currentOffset);
+        const batchResults = await rustEngine.search(query, { limit: batchSize, offset: currentOffset });
         
         if (!batchResults.results || batchResults.results.length === 0) {
           // No more results found
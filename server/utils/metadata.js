@@ -0,0 +1,18 @@
+// Checks for document metadata sent by clients, mirroring the engine's limits so bad
+// requests are rejected before spawning it
+
+const FIELD_NAME = /^[\w.-]{1,64}$/;
+
+// An object mapping field names to strings or finite numbers
+function isMetadata(value) {
+  return typeof value === 'object' && value !== null && !Array.isArray(value)
+    && Object.entries(value).every(([field, fieldValue]) => FIELD_NAME.test(field)
+      && (typeof fieldValue === 'string' || (typeof fieldValue === 'number' && isFinite(fieldValue))));
+}
+
+// An array of field names
+function isFieldList(value) {
+  return Array.isArray(value) && value.every(field => typeof field === 'string' && FIELD_NAME.test(field));
+}
+
+module.exports = { isMetadata, isFieldList };
@@ -8,6 +8,58 @@ const logger = winston.createLogger({
   transports: [new winston.transports.Console()]
 });
 
+const DESTRUCTIVE_TASKS = new Set(['clear-all', 'prune', 'expire']);
+
+// Exit statuses of the search-engine binary besides 0 (success) and 3 (engine error)
+const EXIT_NO_RESULTS = 1;
+const EXIT_QUERY_ERROR = 2;
+
+// Newest response schema this server understands; responses look like
+// {"schema_version": 1, "data": {...}} and may gain fields within a version
+const SUPPORTED_SCHEMA_VERSION = 1;
+
+// HTTP status for the error codes the binary reports with --json; others are 500s
+const ERROR_STATUS = {
+  INVALID_QUERY: 400,
+  EMPTY_QUERY: 400,
+  INVALID_INPUT: 400,
+  FORBIDDEN: 403,
+  DOCUMENT_NOT_FOUND: 404,
+  CONFLICT: 409,
+  READ_ONLY: 409,
+  READER_EXPIRED: 410,
+  UNSUPPORTED_DOCUMENT: 415,
+  QUOTA_EXCEEDED: 507
+};
+
+// Finds the {"schema_version": 1, "error": {...}} object the binary writes to stderr among any log lines
+function parseEngineError(stderr) {
+  const lines = stderr.split('\n').reverse();
+  for (const line of lines) {
+    if (!line.startsWith('{') || !line.includes('"error"')) continue;
+    try {
+      const parsed = JSON.parse(line);
+      if (parsed.error) return parsed.error;
+    } catch (e) {
+      continue;
+    }
+  }
+  return null;
+}
+
+// Flags restricting a request to documents the principals may see; without any only documents
+// without an access control list are seen, and ['*'] sees every document. Values are attached
+// with `=` so a principal starting with a dash isn't taken for a flag.
+function principalArgs(principals) {
+  return (principals || []).map(principal => `--principal=${principal}`);
+}
+
+// Flag naming who a change is made by in the engine's audit log; without one the engine
+// records the change as made from the command line
+function actorArgs(actor) {
+  return actor ? [`--actor=${actor}`] : [];
+}
+
 class RustEngine {
   constructor() {
     this.binaryPath = process.env.RUST_SEARCH_BINARY || './rust-search-engine/target/release/search-engine.exe';
@@ -131,7 +183,7 @@ class RustEngine {
     let isResolved = false;
     const maxRetries = 3;
 
-    const rustProcess = spawn(this.binaryPath, [command, ...args], {
+    const rustProcess = spawn(this.binaryPath, ['--json', command, ...args], {
       stdio: ['pipe', 'pipe', 'pipe'],
       env: { ...process.env, SEARCH_DIRECTORY: 'searches' }
     });
@@ -161,10 +213,24 @@ class RustEngine {
       isResolved = true;
       cleanup();
 
-      if (code !== 0) {
+      const engineError = code !== 0 && code !== EXIT_NO_RESULTS ? parseEngineError(errorOutput) : null;
+
+      // Invalid queries and client errors fail the same way on every attempt
+      if (code === EXIT_QUERY_ERROR || (engineError && ERROR_STATUS[engineError.code])) {
+        reject(new SearchEngineError(
+          engineError ? engineError.message : 'Invalid query',
+          engineError ? engineError.code : 'INVALID_QUERY',
+          engineError ? ERROR_STATUS[engineError.code] || 400 : 400,
+          engineError ? engineError.details : undefined
+        ));
+        return;
+      }
+
+      // A search without results still prints its (empty) response
+      if (code !== 0 && code !== EXIT_NO_RESULTS) {
         logger.error(`Rust engine command failed: ${command}`, {
           code,
-          error: errorOutput,
+          error: engineError || errorOutput,
           args,
           retryCount
         });
@@ -180,16 +246,17 @@ class RustEngine {
         }
         
         reject(new SearchEngineError(
-          'Search engine operation failed',
-          'SEARCH_ENGINE_ERROR',
-          500
+          engineError ? engineError.message : 'Search engine operation failed',
+          engineError ? engineError.code : 'SEARCH_ENGINE_ERROR',
+          500,
+          engineError ? engineError.details : undefined
         ));
         return;
       }
 
+      let response;
       try {
-        const result = JSON.parse(output);
-        resolve(result);
+        response = JSON.parse(output);
       } catch (parseError) {
         logger.error('Failed to parse Rust engine output:', parseError);
         reject(new SearchEngineError(
@@ -197,7 +264,18 @@ class RustEngine {
           'PARSE_ERROR',
           500
         ));
+        return;
       }
+
+      if (response.schema_version > SUPPORTED_SCHEMA_VERSION) {
+        reject(new SearchEngineError(
+          `Search engine responded with schema version ${response.schema_version}; this server supports up to ${SUPPORTED_SCHEMA_VERSION}`,
+          'UNSUPPORTED_SCHEMA',
+          500
+        ));
+        return;
+      }
+      resolve(response.data);
     });
 
     rustProcess.on('error', (error) => {
@@ -304,23 +382,302 @@ class RustEngine {
     };
   }
 
-  async search(query, limit = 10, offset = 0) {
+  // options: { limit, offset, sort, collation, mode, caseSensitive, collection, collections, filters: { pathPrefix, extensions, tags, languages, metadata: { field: value }, from, to,
+  //   modifiedAfter, modifiedBefore },
+  //   highlight, timeout, collapseDuplicates, clusters, summarize, reader, within, saveResults, maxPerFile, diversify, boosts: { pathPrefix: factor },
+  //   facets, fuzzy, limits: { maxDocuments, maxMatches, maxExpansions, maxRegexSize } }
+  // Options left unset fall back to the collection's defaults from the engine config; with
+  // collections, each one's own, and the results are merged and tagged with their collection
+  async search(query, options = {}) {
+    const {
+      limit = 10, offset = 0, sort, collation, mode, caseSensitive, collection, collections = [], filters = {}, highlight, timeout, collapseDuplicates, clusters, summarize,
+      reader, within, saveResults, maxPerFile, diversify, boosts = {}, facets = [], fuzzy, limits = {}, principals
+    } = options;
+    const args = [query, '--limit', limit.toString(), '--offset', offset.toString()];
+    if (sort) args.push('--sort', sort);
+    if (collation) args.push('--collation', collation);
+    if (mode) args.push('--mode', mode);
+    if (caseSensitive) args.push('--case-sensitive');
+    if (collection) args.push('--collection', collection);
+    if (collections.length > 0) args.push('--collections', collections.join(','));
+    if (filters.pathPrefix) args.push('--path-prefix', filters.pathPrefix);
+    for (const extension of filters.extensions || []) {
+      args.push('--extension', extension);
+    }
+    for (const tag of filters.tags || []) {
+      args.push('--tag', tag);
+    }
+    for (const language of filters.languages || []) {
+      args.push('--lang', language);
+    }
+    for (const [field, value] of Object.entries(filters.metadata || {})) {
+      args.push('--meta', `${field}=${value}`);
+    }
+    if (filters.from) args.push('--from', filters.from);
+    if (filters.to) args.push('--to', filters.to);
+    if (filters.modifiedAfter) args.push('--modified-after', filters.modifiedAfter);
+    if (filters.modifiedBefore) args.push('--modified-before', filters.modifiedBefore);
+    if (highlight) args.push('--highlight');
+    if (timeout) args.push('--timeout', timeout);
+    if (collapseDuplicates) args.push('--collapse-duplicates');
+    if (clusters) args.push('--clusters', clusters.toString());
+    if (summarize) args.push('--summarize');
+    if (reader) args.push('--reader', reader);
+    if (within) args.push('--within', within);
+    if (saveResults) args.push('--save-results', saveResults);
+    if (maxPerFile) args.push('--max-per-file', maxPerFile.toString());
+    if (diversify) args.push('--diversify');
+    for (const [prefix, factor] of Object.entries(boosts)) {
+      args.push('--boost', `${prefix}=${factor}`);
+    }
+    for (const field of facets) {
+      args.push('--facet', field);
+    }
+    if (fuzzy !== undefined) args.push('--fuzzy', fuzzy.toString());
+    if (limits.maxDocuments) args.push('--max-documents', limits.maxDocuments.toString());
+    if (limits.maxMatches) args.push('--max-matches', limits.maxMatches.toString());
+    if (limits.maxExpansions) args.push('--max-expansions', limits.maxExpansions.toString());
+    if (limits.maxRegexSize) args.push('--max-regex-size', limits.maxRegexSize.toString());
+    args.push(...principalArgs(principals));
     // Use very long timeout for large file searches (1 hour for massive log files)
-    return this.executeCommand('search', [query, '--limit', limit.toString(), '--offset', offset.toString()], 3600000);
+    return this.executeCommand('search', args, 3600000);
+  }
+
+  // options: { limit, offset, filters: { pathPrefix, extensions, from, to }, principals }
+  async semanticSearch(query, options = {}) {
+    const { limit = 10, offset = 0, filters = {}, principals } = options;
+    const args = [query, '--limit', limit.toString(), '--offset', offset.toString()];
+    if (filters.pathPrefix) args.push('--path-prefix', filters.pathPrefix);
+    for (const extension of filters.extensions || []) {
+      args.push('--extension', extension);
+    }
+    if (filters.from) args.push('--from', filters.from);
+    if (filters.to) args.push('--to', filters.to);
+    args.push(...principalArgs(principals));
+    return this.executeCommand('semantic', args, 3600000);
+  }
+
+  async getStats(detailed = false) {
+    return this.executeCommand('stats', detailed ? ['--detailed'] : []);
+  }
+
+  async getDuplicates(maxDistance, principals) {
+    const args = maxDistance !== undefined ? ['--max-distance', maxDistance.toString()] : [];
+    return this.executeCommand('duplicates', [...args, ...principalArgs(principals)], 3600000);
+  }
+
+  async getKeywords(top = 20, principals) {
+    return this.executeCommand('keywords', ['--top', top.toString(), ...principalArgs(principals)]);
+  }
+
+  // Completions of a partly typed query: indexed terms, or with fromHistory past queries
+  // from the query log, optionally only those within `since`, e.g. "7d"; indexed terms only
+  // come from documents the principals may see
+  async getSuggestions(prefix, { limit, fromHistory = false, since, principals } = {}) {
+    const args = principalArgs(principals);
+    if (limit !== undefined) args.push('--limit', limit.toString());
+    if (fromHistory) args.push('--from-history');
+    if (since) args.push('--since', since);
+    return this.executeCommand('suggest', [...args, '--', prefix]);
+  }
+
+  async getCorpusStats(top = 20) {
+    return this.executeCommand('corpus-stats', ['--top', top.toString()], 3600000);
+  }
+
+  // Indexes a document already in the search directory, replacing its metadata: { field: value },
+  // and restricting it to the principals in `acl` if any are given
+  async indexDocument(filePath, metadata = {}, acl = [], actor) {
+    const args = actorArgs(actor);
+    for (const [field, value] of Object.entries(metadata)) {
+      args.push('--meta', `${field}=${value}`);
+    }
+    for (const principal of acl) {
+      args.push(`--acl=${principal}`);
+    }
+    return this.executeCommand('index', [...args, '--', filePath]);
+  }
+
+  // Indexes each record of a JSON or NDJSON file as a document; mapping: { id, title, content: [...], metadata: [...] }
+  // names the fields making up each one, and every record is restricted to the principals in `acl` if any are given
+  async indexJson(filePath, mapping = {}, acl = [], actor) {
+    const args = actorArgs(actor);
+    if (mapping.id) args.push('--id-field', mapping.id);
+    if (mapping.title) args.push('--title-field', mapping.title);
+    for (const field of mapping.content || []) {
+      args.push('--content-field', field);
+    }
+    for (const field of mapping.metadata || []) {
+      args.push('--meta-field', field);
+    }
+    for (const principal of acl) {
+      args.push(`--acl=${principal}`);
+    }
+    return this.executeCommand('index-json', [...args, '--', filePath], 3600000);
+  }
+
+  async getFacets(fields, principals) {
+    return this.executeCommand('facets', [...principalArgs(principals), '--', ...fields]);
   }
 
-  async getStats() {
-    return this.executeCommand('stats');
+  async getPartitions() {
+    return this.executeCommand('partitions');
+  }
+
+  // Documents added, updated or deleted after index generation `since`
+  async getChanges(since = 0, limit) {
+    const args = ['--since', String(since)];
+    if (limit !== undefined) args.push('--limit', String(limit));
+    return this.executeCommand('changes', args);
+  }
+
+  async openReader(keepAlive) {
+    return this.executeCommand('reader', keepAlive ? ['open', '--keep-alive', keepAlive] : ['open']);
+  }
+
+  async closeReader(id) {
+    return this.executeCommand('reader', ['close', id]);
+  }
+
+  async getReaders() {
+    return this.executeCommand('reader', ['list']);
+  }
+
+  // Lines start..end of a document, by path or document ID
+  async getLines(document, start, end, principals) {
+    return this.executeCommand('lines', [...principalArgs(principals), '--', document, String(start), String(end)]);
+  }
+
+  async getResultSets() {
+    return this.executeCommand('results', ['list']);
+  }
+
+  async closeResultSet(id) {
+    return this.executeCommand('results', ['close', id]);
+  }
+
+  // First batch of a scroll through every match of a query, carrying the scroll_id to fetch
+  // the rest by; options: { batchSize, keepAlive, mode, caseSensitive, fuzzy, filters: { pathPrefix, extensions, tags, metadata }, highlight, reader, principals }
+  async openScroll(query, options = {}) {
+    const { batchSize, keepAlive, mode, caseSensitive, fuzzy, filters = {}, highlight, reader, principals } = options;
+    const args = ['open'];
+    if (batchSize) args.push('--batch-size', batchSize.toString());
+    if (keepAlive) args.push('--keep-alive', keepAlive);
+    if (mode) args.push('--mode', mode);
+    if (caseSensitive) args.push('--case-sensitive');
+    if (fuzzy !== undefined) args.push('--fuzzy', fuzzy.toString());
+    if (filters.pathPrefix) args.push('--path-prefix', filters.pathPrefix);
+    for (const extension of filters.extensions || []) {
+      args.push('--extension', extension);
+    }
+    for (const tag of filters.tags || []) {
+      args.push('--tag', tag);
+    }
+    for (const [field, value] of Object.entries(filters.metadata || {})) {
+      args.push('--meta', `${field}=${value}`);
+    }
+    if (highlight) args.push('--highlight');
+    if (reader) args.push('--reader', reader);
+    args.push(...principalArgs(principals));
+    args.push('--', query);
+    return this.executeCommand('scroll', args, 3600000);
+  }
+
+  async nextScrollBatch(id) {
+    return this.executeCommand('scroll', ['next', id], 3600000);
+  }
+
+  async closeScroll(id) {
+    return this.executeCommand('scroll', ['close', id]);
+  }
+
+  async addTags(document, tags, actor) {
+    return this.executeCommand('tag', ['add', ...actorArgs(actor), '--', document, ...tags]);
+  }
+
+  async removeTags(document, tags, actor) {
+    return this.executeCommand('tag', ['remove', ...actorArgs(actor), '--', document, ...tags]);
+  }
+
+  async getDocumentTags(document) {
+    return this.executeCommand('tag', ['show', '--', document]);
+  }
+
+  async getTagCounts() {
+    return this.executeCommand('tag', ['counts']);
+  }
+
+  async getCollections() {
+    return this.executeCommand('collections');
+  }
+
+  async getAliases() {
+    return this.executeCommand('alias', ['list']);
+  }
+
+  // Creates the alias or moves it to another collection in one step
+  async setAlias(alias, collection, actor) {
+    return this.executeCommand('alias', ['set', ...actorArgs(actor), '--', alias, collection]);
+  }
+
+  async removeAlias(alias, actor) {
+    return this.executeCommand('alias', ['remove', ...actorArgs(actor), '--', alias]);
+  }
+
+  async getAlerts() {
+    return this.executeCommand('alert', ['list']);
+  }
+
+  // options: { mode, filters: { pathPrefix, extensions }, webhook, actor }
+  async addAlert(query, options = {}) {
+    const { mode, filters = {}, webhook, actor } = options;
+    const args = ['add', ...actorArgs(actor), query];
+    if (mode) args.push('--mode', mode);
+    if (filters.pathPrefix) args.push('--path-prefix', filters.pathPrefix);
+    for (const extension of filters.extensions || []) {
+      args.push('--extension', extension);
+    }
+    if (webhook) args.push('--webhook', webhook);
+    return this.executeCommand('alert', args);
+  }
+
+  async removeAlert(id, actor) {
+    return this.executeCommand('alert', ['remove', ...actorArgs(actor), id]);
+  }
+
+  // Saved queries the document at filePath would match; path is where it would live
+  // in the search directory, for saved queries with path filters
+  async percolate(filePath, path) {
+    const args = ['percolate', filePath];
+    if (path) args.push('--path', path);
+    return this.executeCommand('alert', args);
+  }
+
+  // options: { after: RFC 3339 timestamp, limit }
+  async getAlertEvents(options = {}) {
+    const { after, limit } = options;
+    const args = ['events'];
+    if (after) args.push('--after', after);
+    if (limit) args.push('--limit', limit.toString());
+    return this.executeCommand('alert', args);
   }
 
   async getStatus() {
     return this.executeCommand('status');
   }
 
+  async getVersion() {
+    // Every command runs with --json, which also prints the version as JSON
+    return this.executeCommand('version');
+  }
+
 
 
-  async runMaintenance(task) {
-    return this.executeCommand('maintenance', [task]);
+  async runMaintenance(task, actor) {
+    // The engine refuses destructive tasks without explicit confirmation;
+    // callers here are admin actions that have already been confirmed
+    const args = DESTRUCTIVE_TASKS.has(task) ? ['--yes', task] : [task];
+    return this.executeCommand('maintenance', [...actorArgs(actor), ...args]);
   }
 }
 
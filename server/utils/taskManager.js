@@ -191,6 +191,7 @@ class TaskManager {
         return params.filesCount ? Math.min(params.filesCount * 100, 15000) : 5000;
       
       case 'maintenance':
+        if (params.task === 'rebuild') return 15000;
         return params.task === 'clear-all' ? 8000 : 3000;
       
       default: